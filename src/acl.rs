@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use teloxide::types::{ChatId, UserId};
+use tokio::sync::RwLock;
+
+/// Записи, добавленные администраторами через `/allow`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AllowlistData {
+    #[serde(default)]
+    users: BTreeSet<u64>,
+    #[serde(default)]
+    chats: BTreeSet<i64>,
+    /// Администратор уже менял список: бот остается закрытым, даже если список опустел
+    #[serde(default)]
+    restricted: bool,
+}
+
+/// Кому разрешено пользоваться ботом.
+/// Пока ни одного правила нет (ни в конфигурации, ни в файле) и администраторы не вызывали
+/// `/allow` или `/deny`, бот открыт для всех.
+pub struct AccessControl {
+    path: PathBuf,
+    /// Пользователи и чаты из `ALLOWED_USER_IDS` / `ALLOWED_CHAT_IDS` (не удаляются через `/deny`)
+    static_users: BTreeSet<u64>,
+    static_chats: BTreeSet<i64>,
+    admins: BTreeSet<u64>,
+    allowlist: RwLock<AllowlistData>,
+}
+
+/// Что добавляет или удаляет `/allow <id>`: положительный id - пользователь, отрицательный - группа
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclEntry {
+    User(u64),
+    Chat(i64),
+}
+
+impl AclEntry {
+    pub fn parse(value: &str) -> Option<Self> {
+        let id: i64 = value.trim().parse().ok()?;
+        if id > 0 {
+            Some(Self::User(id as u64))
+        } else if id < 0 {
+            Some(Self::Chat(id))
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for AclEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::User(id) => write!(f, "пользователь {}", id),
+            Self::Chat(id) => write!(f, "чат {}", id),
+        }
+    }
+}
+
+impl AccessControl {
+    /// Загружает сохраненный список доступа, создавая пустой, если файла еще нет
+    pub fn open(
+        path: impl Into<PathBuf>,
+        static_users: &[u64],
+        static_chats: &[i64],
+        admins: &[u64],
+    ) -> Result<Self> {
+        let path = path.into();
+        let allowlist = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read allowlist file {}", path.display()))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse allowlist file {}", path.display()))?
+        } else {
+            AllowlistData::default()
+        };
+
+        Ok(Self {
+            path,
+            static_users: static_users.iter().copied().collect(),
+            static_chats: static_chats.iter().copied().collect(),
+            admins: admins.iter().copied().collect(),
+            allowlist: RwLock::new(allowlist),
+        })
+    }
+
+    pub fn is_admin(&self, user_id: UserId) -> bool {
+        self.admins.contains(&user_id.0)
+    }
+
+    /// Разрешен ли доступ отправителю в данном чате
+    pub async fn is_allowed(&self, user_id: Option<UserId>, chat_id: Option<ChatId>) -> bool {
+        let allowlist = self.allowlist.read().await;
+        let restricted = !self.static_users.is_empty()
+            || !self.static_chats.is_empty()
+            || !allowlist.users.is_empty()
+            || !allowlist.chats.is_empty()
+            || allowlist.restricted;
+        if !restricted {
+            return true;
+        }
+
+        let user_allowed = user_id.is_some_and(|id| {
+            self.admins.contains(&id.0) || self.static_users.contains(&id.0) || allowlist.users.contains(&id.0)
+        });
+        let chat_allowed = chat_id.is_some_and(|id| {
+            self.static_chats.contains(&id.0) || allowlist.chats.contains(&id.0)
+        });
+        user_allowed || chat_allowed
+    }
+
+    /// Добавляет запись; возвращает `false`, если она уже была
+    pub async fn allow(&self, entry: AclEntry) -> Result<bool> {
+        let mut allowlist = self.allowlist.write().await;
+        let added = match entry {
+            AclEntry::User(id) => allowlist.users.insert(id),
+            AclEntry::Chat(id) => allowlist.chats.insert(id),
+        };
+        if added || !allowlist.restricted {
+            allowlist.restricted = true;
+            self.save(&allowlist).await?;
+        }
+        Ok(added)
+    }
+
+    /// Удаляет запись из сохраненного списка; возвращает `false`, если ее там не было.
+    /// Бот остается закрытым и тогда, когда удалена последняя запись.
+    pub async fn deny(&self, entry: AclEntry) -> Result<bool> {
+        let mut allowlist = self.allowlist.write().await;
+        let removed = match entry {
+            AclEntry::User(id) => allowlist.users.remove(&id),
+            AclEntry::Chat(id) => allowlist.chats.remove(&id),
+        };
+        if removed || !allowlist.restricted {
+            allowlist.restricted = true;
+            self.save(&allowlist).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Запись задана в конфигурации и не может быть удалена командой
+    pub fn is_static(&self, entry: AclEntry) -> bool {
+        match entry {
+            AclEntry::User(id) => self.static_users.contains(&id),
+            AclEntry::Chat(id) => self.static_chats.contains(&id),
+        }
+    }
+
+    async fn save(&self, allowlist: &AllowlistData) -> Result<()> {
+        let content = serde_json::to_string_pretty(allowlist).context("Failed to serialize allowlist")?;
+        // Пишем во временный файл и переименовываем, чтобы не оставить битый файл при сбое
+        let temp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&temp_path, content)
+            .await
+            .with_context(|| format!("Failed to write allowlist file {}", temp_path.display()))?;
+        tokio::fs::rename(&temp_path, &self.path)
+            .await
+            .with_context(|| format!("Failed to replace allowlist file {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist_path(name: &str) -> PathBuf {
+        crate::utils::test_dir(&format!("acl_{}", name)).join("allowlist.json")
+    }
+
+    #[test]
+    fn entries_are_parsed_by_sign() {
+        assert_eq!(AclEntry::parse(" 42 "), Some(AclEntry::User(42)));
+        assert_eq!(AclEntry::parse("-1001"), Some(AclEntry::Chat(-1001)));
+        assert_eq!(AclEntry::parse("0"), None);
+        assert_eq!(AclEntry::parse("@user"), None);
+    }
+
+    #[tokio::test]
+    async fn bot_is_open_until_first_rule() {
+        let acl = AccessControl::open(allowlist_path("open"), &[], &[], &[1]).unwrap();
+        assert!(acl.is_allowed(Some(UserId(42)), Some(ChatId(42))).await);
+
+        assert!(acl.allow(AclEntry::User(7)).await.unwrap());
+        assert!(!acl.allow(AclEntry::User(7)).await.unwrap());
+        assert!(!acl.is_allowed(Some(UserId(42)), Some(ChatId(42))).await);
+        assert!(acl.is_allowed(Some(UserId(7)), Some(ChatId(7))).await);
+        // Администратор проходит всегда
+        assert!(acl.is_allowed(Some(UserId(1)), None).await);
+    }
+
+    #[tokio::test]
+    async fn allowed_chat_admits_any_member() {
+        let acl = AccessControl::open(allowlist_path("chat"), &[], &[-100], &[]).unwrap();
+        assert!(acl.is_allowed(Some(UserId(42)), Some(ChatId(-100))).await);
+        assert!(!acl.is_allowed(Some(UserId(42)), Some(ChatId(-200))).await);
+        assert!(!acl.is_allowed(None, None).await);
+        assert!(acl.is_static(AclEntry::Chat(-100)));
+        // Запись из конфигурации не удаляется командой
+        assert!(!acl.deny(AclEntry::Chat(-100)).await.unwrap());
+        assert!(acl.is_allowed(Some(UserId(42)), Some(ChatId(-100))).await);
+    }
+
+    #[tokio::test]
+    async fn allowlist_survives_restart() {
+        let path = allowlist_path("restart");
+        let acl = AccessControl::open(&path, &[], &[], &[]).unwrap();
+        acl.allow(AclEntry::User(7)).await.unwrap();
+        acl.allow(AclEntry::Chat(-100)).await.unwrap();
+        assert!(acl.deny(AclEntry::Chat(-100)).await.unwrap());
+
+        let reopened = AccessControl::open(&path, &[], &[], &[]).unwrap();
+        assert!(reopened.is_allowed(Some(UserId(7)), None).await);
+        assert!(!reopened.is_allowed(Some(UserId(42)), Some(ChatId(-100))).await);
+    }
+
+    #[tokio::test]
+    async fn denying_last_entry_keeps_bot_closed() {
+        let path = allowlist_path("deny_last");
+        let acl = AccessControl::open(&path, &[], &[], &[1]).unwrap();
+        acl.allow(AclEntry::User(7)).await.unwrap();
+        assert!(acl.deny(AclEntry::User(7)).await.unwrap());
+        assert!(!acl.is_allowed(Some(UserId(7)), Some(ChatId(7))).await);
+        assert!(!acl.is_allowed(Some(UserId(42)), Some(ChatId(42))).await);
+        assert!(acl.is_allowed(Some(UserId(1)), None).await);
+
+        let reopened = AccessControl::open(&path, &[], &[], &[1]).unwrap();
+        assert!(!reopened.is_allowed(Some(UserId(7)), Some(ChatId(7))).await);
+
+        // /deny на открытом боте тоже закрывает его
+        let acl = AccessControl::open(allowlist_path("deny_open"), &[], &[], &[1]).unwrap();
+        assert!(!acl.deny(AclEntry::User(42)).await.unwrap());
+        assert!(!acl.is_allowed(Some(UserId(42)), Some(ChatId(42))).await);
+    }
+}
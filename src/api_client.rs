@@ -1,8 +1,24 @@
+use crate::api_error::ApiError;
+use crate::auth::Credentials;
+use crate::endpoints::{ApiVersion, BackendEndpoint, EndpointConfig, Endpoints};
+use crate::metrics::{Endpoint, METRICS};
+use crate::response_cache::ResponseCache;
+use crate::transliterate::Transliterator;
 use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex, Semaphore};
 
-#[derive(Debug, Serialize, Clone)]
+/// Время на установку соединения с бэкендом (общий лимит задает `BACKEND_TIMEOUT_SECS`)
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
 pub enum OutputType {
     #[serde(rename = "table")]
     Table,
@@ -11,16 +27,42 @@ pub enum OutputType {
     #[serde(rename = "json")]
     Json,
     #[serde(rename = "auto")]
+    #[default]
     Auto,
 }
 
-impl Default for OutputType {
-    fn default() -> Self {
-        OutputType::Auto
+impl OutputType {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "auto" | "авто" => Some(Self::Auto),
+            "table" | "таблица" => Some(Self::Table),
+            "chart" | "диаграмма" | "график" => Some(Self::Chart),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// Значение для `/settings output` и кнопок настроек
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Table => "table",
+            Self::Chart => "chart",
+            Self::Json => "json",
+            Self::Auto => "auto",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Table => "таблица",
+            Self::Chart => "диаграмма",
+            Self::Json => "JSON",
+            Self::Auto => "на выбор бэкенда",
+        }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct QueryRequest {
     pub question: String,
     #[serde(default)]
@@ -33,11 +75,26 @@ pub struct QueryRequest {
     pub user_id: Option<String>,
     #[serde(default)]
     pub output_type: OutputType,
+    /// Желаемый язык ответа (анализ, выводы), ISO 639-1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Период из вопроса («за вчера», «с 1 по 15 марта»), распознанный ботом; границы включительно
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_from: Option<chrono::NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_to: Option<chrono::NaiveDate>,
+    /// Часовой пояс пользователя (`Asia/Almaty` или `+05:00`): в нем бэкенд считает границы дней
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
 pub struct QueryResponse {
     pub question: String,
+    /// Идентификатор запроса на бэкенде (для перехода в веб-интерфейс)
+    #[serde(default)]
+    pub query_id: Option<String>,
     #[serde(default)]
     pub sql: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -55,16 +112,20 @@ pub struct QueryResponse {
     pub cached: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChartData {
     pub chart_type: String,
     pub labels: Vec<String>,
     pub datasets: Vec<ChartDataset>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
+    /// Логарифмическая шкала значений; бэкенд ее не присылает, включается кнопкой под диаграммой
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub log_scale: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
 pub struct ChartDataset {
     pub label: String,
     pub data: Vec<f64>,
@@ -72,7 +133,8 @@ pub struct ChartDataset {
     pub background_color: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
 pub struct AnalysisResult {
     pub headline: String,
     pub insights: Vec<Insight>,
@@ -82,13 +144,25 @@ pub struct AnalysisResult {
     pub chart_type: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Insight {
     pub title: String,
     pub description: String,
     pub significance: String,
 }
 
+/// Файл пользователя (CSV/XLSX) для сравнения с данными транзакций
+#[derive(Debug)]
+pub struct UploadRequest {
+    pub file_name: String,
+    pub content: Vec<u8>,
+    /// Что сравнить или посчитать (подпись к файлу); пустая - бэкенд выбирает анализ сам
+    pub question: String,
+    pub user_id: Option<String>,
+    /// Желаемый язык ответа, ISO 639-1
+    pub language: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ChatRequest {
     pub message: String,
@@ -96,9 +170,12 @@ pub struct ChatRequest {
     pub session_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 pub struct ChatResponse {
     pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -106,57 +183,366 @@ pub struct ChatResponse {
     pub response_time_ms: u64,
 }
 
+#[derive(Debug, Serialize)]
+pub struct LinkRequest {
+    pub nonce: String,
+    pub user_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub telegram_username: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkResponse {
+    /// Имя аккаунта бэкенда, к которому привязан пользователь
+    pub account: String,
+    /// Персональный токен, если бэкенд выдает его при привязке
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EstimateResponse {
+    pub estimated_rows: u64,
+    #[serde(default)]
+    pub estimated_time_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SuggestResponse {
+    #[serde(default)]
+    suggestions: Vec<String>,
+}
+
+/// Таблица из описания схемы данных (`GET /api/schema`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct SchemaTable {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub columns: Vec<SchemaColumn>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SchemaColumn {
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub data_type: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SchemaResponse {
+    tables: Vec<SchemaTable>,
+}
+
+/// Сколько хранится полученное описание схемы: она меняется редко, а `/schema` листают кнопками
+const SCHEMA_TTL: Duration = Duration::from_secs(600);
+
+/// Бэкенд аналитики глазами обработчиков. Настоящая реализация - `ApiClient` (HTTP через reqwest);
+/// обработчики получают `Arc<dyn BackendClient>`, так что в тестах вместо нее подставляются
+/// заготовленные ответы (`testing::FakeBackend`).
+#[async_trait]
+pub trait BackendClient: Send + Sync {
+    /// Версия API, с которой сейчас работает бот
+    fn api_version(&self) -> ApiVersion;
+
+    fn cache(&self) -> &ResponseCache;
+
+    /// Бэкенд присылает ответы на вопросы по частям (см. `query_stream`)
+    fn is_streaming(&self) -> bool;
+
+    async fn query(&self, request: QueryRequest) -> Result<QueryResponse>;
+
+    /// Как `query`, но через `/api/query/stream` (server-sent events): части текста ответа
+    /// отправляются в `tokens` по мере того, как бэкенд их формирует.
+    /// Если потоковые ответы выключены или бэкенд их не поддерживает, выполняется обычный `query`.
+    async fn query_stream(&self, request: QueryRequest, tokens: mpsc::UnboundedSender<String>) -> Result<QueryResponse>;
+
+    async fn chat(&self, request: ChatRequest) -> Result<ChatResponse>;
+
+    /// Отправляет файл в `/api/upload` (multipart) и получает ответ в формате `/api/query`
+    async fn upload(&self, request: UploadRequest) -> Result<QueryResponse>;
+
+    async fn clear_context(&self, user_id: &str) -> Result<()>;
+
+    /// Оценивает объем запроса (сканируемые строки, время) без его выполнения
+    async fn estimate(&self, question: &str, user_id: &str) -> Result<EstimateResponse>;
+
+    /// Исправленные варианты вопроса, запрос по которому не вернул ни одной строки
+    /// (опечатки в названиях городов, банков и т.п.)
+    async fn suggest(&self, question: &str, user_id: &str) -> Result<Vec<String>>;
+
+    /// Таблицы и колонки, о которых можно спрашивать; ответ бэкенда хранится `SCHEMA_TTL`
+    async fn schema(&self, user_id: &str) -> Result<Arc<Vec<SchemaTable>>>;
+
+    /// Удаляет все данные пользователя на бэкенде (контекст, историю, профиль)
+    async fn delete_user_data(&self, user_id: &str) -> Result<()>;
+
+    /// Обменивает одноразовый nonce из веб-интерфейса на привязку аккаунта
+    async fn link_account(&self, request: LinkRequest) -> Result<LinkResponse>;
+
+    async fn health_check(&self) -> Result<bool>;
+}
+
 pub struct ApiClient {
     base_url: String,
+    /// Пути эндпоинтов с учетом версии API бэкенда
+    endpoints: Endpoints,
     client: reqwest::Client,
+    credentials: Option<Arc<Credentials>>,
+    cache: ResponseCache,
+    /// Ограничение одновременных запросов к бэкенду (`/api/query`, `/api/chat`, `/api/estimate`)
+    permits: Semaphore,
+    /// Запросы отправляются в потоковый `/api/query/stream`; выключается, если бэкенд его не знает
+    streaming: AtomicBool,
+    /// Последнее описание схемы и когда оно получено
+    schema: Mutex<Option<(Instant, Arc<Vec<SchemaTable>>)>>,
+    /// Перевод названий в вопросах на латиницу (`None` - вопросы отправляются как есть)
+    transliterator: Option<Arc<Transliterator>>,
+}
+
+/// Настройки HTTP-соединения с бэкендом
+#[derive(Clone)]
+pub struct HttpOptions {
+    pub timeout: Duration,
+    /// Дополнительные корневые сертификаты (`BACKEND_CA_BUNDLE`), например частного CA
+    pub root_certs: Vec<reqwest::Certificate>,
+    /// Не проверять сертификат бэкенда (`BACKEND_DANGER_ACCEPT_INVALID_CERTS`)
+    pub accept_invalid_certs: bool,
+}
+
+impl HttpOptions {
+    /// Читает сертификаты из PEM-файла `ca_bundle`; файл без сертификатов - ошибка конфигурации
+    pub fn load(timeout: Duration, ca_bundle: Option<&str>, accept_invalid_certs: bool) -> Result<Self> {
+        let root_certs = match ca_bundle {
+            Some(path) => {
+                let pem = std::fs::read(path).with_context(|| format!("Failed to read BACKEND_CA_BUNDLE {}", path))?;
+                let certs = reqwest::Certificate::from_pem_bundle(&pem)
+                    .with_context(|| format!("BACKEND_CA_BUNDLE {} is not a valid PEM bundle", path))?;
+                if certs.is_empty() {
+                    anyhow::bail!("BACKEND_CA_BUNDLE {} contains no certificates", path);
+                }
+                tracing::info!("Trusting {} backend CA certificate(s) from {}", certs.len(), path);
+                certs
+            }
+            None => Vec::new(),
+        };
+        if accept_invalid_certs {
+            tracing::warn!("BACKEND_DANGER_ACCEPT_INVALID_CERTS is set: backend TLS certificates are NOT verified");
+        }
+        Ok(Self { timeout, root_certs, accept_invalid_certs })
+    }
 }
 
 impl ApiClient {
-    pub fn new(base_url: String) -> Self {
-        Self {
-            base_url,
-            client: reqwest::Client::new(),
+    /// `api_key` отправляется с каждым запросом; персональный токен из /login его заменяет.
+    /// Больше `max_concurrent` тяжелых запросов одновременно не отправляется, остальные ждут очереди.
+    /// `streaming` включает потоковые ответы (`query_stream`).
+    pub fn new(
+        base_url: String,
+        api_key: Option<&str>,
+        http: &HttpOptions,
+        credentials: Option<Arc<Credentials>>,
+        cache: ResponseCache,
+        max_concurrent: usize,
+        streaming: bool,
+    ) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        if let Some(api_key) = api_key {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", api_key))
+                .context("BACKEND_API_KEY contains characters not allowed in an HTTP header")?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let mut builder = reqwest::Client::builder()
+            .default_headers(headers)
+            .connect_timeout(CONNECT_TIMEOUT.min(http.timeout))
+            .timeout(http.timeout)
+            .danger_accept_invalid_certs(http.accept_invalid_certs);
+        for cert in &http.root_certs {
+            builder = builder.add_root_certificate(cert.clone());
+        }
+        let client = builder.build().context("Failed to build HTTP client")?;
+
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            endpoints: Endpoints::new(EndpointConfig::default()),
+            client,
+            credentials,
+            cache,
+            permits: Semaphore::new(max_concurrent.max(1)),
+            streaming: AtomicBool::new(streaming),
+            schema: Mutex::new(None),
+            transliterator: None,
+        })
+    }
+
+    /// Пути эндпоинтов и версия API (`BACKEND_API_VERSION`, `BACKEND_API_PREFIX`, `BACKEND_ENDPOINTS`);
+    /// по умолчанию версия определяется по `/api/health`
+    pub fn with_endpoints(mut self, config: EndpointConfig) -> Self {
+        self.endpoints = Endpoints::new(config);
+        self
+    }
+
+    /// Названия городов и банков в вопросах к данным переводятся на латиницу до отправки (и до поиска в кэше);
+    /// `None` - вопросы отправляются как есть
+    pub fn with_transliterator(mut self, transliterator: Option<Arc<Transliterator>>) -> Self {
+        self.transliterator = transliterator;
+        self
+    }
+
+    /// Вопрос в том виде, в котором он уйдет бэкенду
+    fn prepare_question(&self, question: &str) -> String {
+        match &self.transliterator {
+            Some(transliterator) => transliterator.apply(question),
+            None => question.to_string(),
+        }
+    }
+
+    fn url(&self, endpoint: BackendEndpoint) -> String {
+        format!("{}{}", self.base_url, self.endpoints.path(endpoint))
+    }
+
+    /// Пользователь с персональным токеном получает собственные записи в кэше
+    async fn cache_scope(&self, user_id: Option<&str>) -> Option<String> {
+        let (Some(credentials), Some(user_id)) = (&self.credentials, user_id) else {
+            return None;
+        };
+        credentials.token_for(user_id).await.map(|_| user_id.to_string())
+    }
+
+    /// Добавляет id корреляции и трассу (`traceparent`) текущего обновления и персональный токен пользователя,
+    /// если он привязан через /login
+    async fn prepare(
+        &self,
+        builder: reqwest::RequestBuilder,
+        user_id: Option<&str>,
+    ) -> reqwest::RequestBuilder {
+        let builder = match crate::correlation::current() {
+            Some(id) => builder.header(crate::correlation::HEADER, id),
+            None => builder,
+        };
+        let builder = match crate::correlation::traceparent() {
+            Some(traceparent) => builder.header(crate::correlation::TRACEPARENT, traceparent),
+            None => builder,
+        };
+        let (Some(credentials), Some(user_id)) = (&self.credentials, user_id) else {
+            return builder;
+        };
+        match credentials.token_for(user_id).await {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
+
+    /// Ключ кэша для запроса и ответ из кэша, если он там есть
+    async fn cached(&self, request: &QueryRequest) -> (Option<String>, Option<QueryResponse>) {
+        if !request.use_cache || !self.cache.is_enabled() {
+            return (None, None);
+        }
+        let scope = self.cache_scope(request.user_id.as_deref()).await;
+        let key = ResponseCache::key(request, scope.as_deref());
+        match self.cache.get(&key).await {
+            Some(mut cached) => {
+                tracing::debug!("Serving query from local cache: {}", request.question);
+                cached.cached = true;
+                METRICS.record_cache(true);
+                (Some(key), Some(cached))
+            }
+            None => {
+                METRICS.record_cache(false);
+                (Some(key), None)
+            }
         }
     }
 
-    pub async fn query(&self, request: QueryRequest) -> Result<QueryResponse> {
-        let url = format!("{}/api/query", self.base_url);
+    async fn send_query(&self, request: &QueryRequest) -> Result<QueryResponse> {
+        let url = self.url(BackendEndpoint::Query);
         let response = self
-            .client
-            .post(&url)
-            .json(&request)
+            .prepare(self.client.post(&url), request.user_id.as_deref())
+            .await
+            .json(request)
             .send()
             .await
             .context("Failed to send request to backend")?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            anyhow::bail!("Backend error ({}): {}", status, text);
+            return Err(ApiError::from_response(response).await.into());
         }
 
-        let query_response: QueryResponse = response
+        response
             .json()
             .await
-            .context("Failed to parse backend response")?;
+            .context("Failed to parse backend response")
+    }
 
-        Ok(query_response)
+    /// События потока: `token` (`{"text": "..."}` - очередная часть ответа),
+    /// `result` (ответ в формате `/api/query`) и `error` (`{"error": "..."}`)
+    async fn send_query_stream(
+        &self,
+        request: &QueryRequest,
+        tokens: &mpsc::UnboundedSender<String>,
+    ) -> Result<QueryResponse> {
+        let url = self.url(BackendEndpoint::QueryStream);
+        let mut response = self
+            .prepare(self.client.post(&url), request.user_id.as_deref())
+            .await
+            .header(ACCEPT, "text/event-stream")
+            .json(request)
+            .send()
+            .await
+            .context("Failed to send request to backend")?;
+
+        let status = response.status();
+        if matches!(status, StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED) {
+            return Err(StreamingUnsupported(status).into());
+        }
+        if !status.is_success() {
+            return Err(ApiError::from_response(response).await.into());
+        }
+        // Готовый ответ (например, из кэша бэкенда) может прийти сразу целиком
+        let is_event_stream = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+        if !is_event_stream {
+            return response.json().await.context("Failed to parse backend response");
+        }
+
+        let mut events = EventStream::default();
+        while let Some(chunk) = response.chunk().await.context("Failed to read backend stream")? {
+            for event in events.push(&chunk) {
+                match event.name.as_str() {
+                    "token" => {
+                        // Получатель мог перестать слушать - ответ все равно нужен целиком
+                        let _ = tokens.send(stream_text(&event.data, "text"));
+                    }
+                    "result" => {
+                        return serde_json::from_str(&event.data).context("Failed to parse backend response");
+                    }
+                    "error" => return Err(ApiError::from_stream_event(&event.data).into()),
+                    _ => {}
+                }
+            }
+        }
+        anyhow::bail!("Backend stream ended without a result")
     }
 
-    pub async fn chat(&self, request: ChatRequest) -> Result<ChatResponse> {
-        let url = format!("{}/api/chat", self.base_url);
+    async fn send_chat(&self, request: &ChatRequest) -> Result<ChatResponse> {
+        let url = self.url(BackendEndpoint::Chat);
         let response = self
-            .client
-            .post(&url)
-            .json(&request)
+            .prepare(self.client.post(&url), request.user_id.as_deref())
+            .await
+            .json(request)
             .send()
             .await
             .context("Failed to send request to backend")?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            anyhow::bail!("Backend error ({}): {}", status, text);
+            return Err(ApiError::from_response(response).await.into());
         }
 
         let chat_response: ChatResponse = response
@@ -167,27 +553,263 @@ impl ApiClient {
         Ok(chat_response)
     }
 
-    pub async fn clear_context(&self, user_id: &str) -> Result<()> {
-        let url = format!("{}/api/context/clear", self.base_url);
+    async fn send_upload(&self, request: UploadRequest) -> Result<QueryResponse> {
+        let url = self.url(BackendEndpoint::Upload);
+        let mime = mime_for(&request.file_name);
+        let file = reqwest::multipart::Part::bytes(request.content)
+            .file_name(request.file_name)
+            .mime_str(mime)
+            .context("Invalid upload MIME type")?;
+        let mut form = reqwest::multipart::Form::new()
+            .part("file", file)
+            .text("question", request.question);
+        if let Some(user_id) = &request.user_id {
+            form = form.text("user_id", user_id.clone());
+        }
+        if let Some(language) = request.language {
+            form = form.text("language", language);
+        }
+
         let response = self
-            .client
-            .post(&url)
-            .json(&serde_json::json!({ "user_id": user_id }))
+            .prepare(self.client.post(&url), request.user_id.as_deref())
+            .await
+            .multipart(form)
             .send()
+            .await
+            .context("Failed to send upload to backend")?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(response).await.into());
+        }
+
+        response
+            .json()
+            .await
+            .context("Failed to parse backend response")
+    }
+}
+
+#[async_trait]
+impl BackendClient for ApiClient {
+    fn api_version(&self) -> ApiVersion {
+        self.endpoints.version()
+    }
+
+    fn cache(&self) -> &ResponseCache {
+        &self.cache
+    }
+
+    fn is_streaming(&self) -> bool {
+        self.streaming.load(Ordering::Relaxed)
+    }
+
+    async fn query(&self, mut request: QueryRequest) -> Result<QueryResponse> {
+        request.question = self.prepare_question(&request.question);
+        let (cache_key, cached) = self.cached(&request).await;
+        if let Some(cached) = cached {
+            return Ok(cached);
+        }
+
+        let _permit = self.permits.acquire().await?;
+        let started = Instant::now();
+        let result = with_retry(|| self.send_query(&request)).await;
+        METRICS.record_backend(Endpoint::Query, started.elapsed(), result.is_ok());
+        crate::correlation::record_backend(started.elapsed());
+        let query_response = result?;
+
+        if let Some(key) = cache_key {
+            self.cache.insert(key, &query_response).await;
+        }
+
+        Ok(query_response)
+    }
+
+    async fn query_stream(
+        &self,
+        request: QueryRequest,
+        tokens: mpsc::UnboundedSender<String>,
+    ) -> Result<QueryResponse> {
+        if !self.is_streaming() {
+            return self.query(request).await;
+        }
+        let mut request = request;
+        request.question = self.prepare_question(&request.question);
+        let (cache_key, cached) = self.cached(&request).await;
+        if let Some(cached) = cached {
+            return Ok(cached);
+        }
+
+        let _permit = self.permits.acquire().await?;
+        let started = Instant::now();
+        let result = match self.send_query_stream(&request, &tokens).await {
+            Err(e) if e.downcast_ref::<StreamingUnsupported>().is_some() => {
+                tracing::warn!("{}, falling back to /api/query", e);
+                self.streaming.store(false, Ordering::Relaxed);
+                self.send_query(&request).await
+            }
+            result => result,
+        };
+        METRICS.record_backend(Endpoint::Query, started.elapsed(), result.is_ok());
+        crate::correlation::record_backend(started.elapsed());
+        let query_response = result?;
+
+        if let Some(key) = cache_key {
+            self.cache.insert(key, &query_response).await;
+        }
+
+        Ok(query_response)
+    }
+
+    async fn chat(&self, request: ChatRequest) -> Result<ChatResponse> {
+        let _permit = self.permits.acquire().await?;
+        let started = Instant::now();
+        let result = with_retry(|| self.send_chat(&request)).await;
+        METRICS.record_backend(Endpoint::Chat, started.elapsed(), result.is_ok());
+        crate::correlation::record_backend(started.elapsed());
+        result
+    }
+
+    async fn upload(&self, request: UploadRequest) -> Result<QueryResponse> {
+        let _permit = self.permits.acquire().await?;
+        let started = Instant::now();
+        let result = self.send_upload(request).await;
+        METRICS.record_backend(Endpoint::Upload, started.elapsed(), result.is_ok());
+        crate::correlation::record_backend(started.elapsed());
+        result
+    }
+
+    async fn clear_context(&self, user_id: &str) -> Result<()> {
+        let url = self.url(BackendEndpoint::ContextClear);
+        let request = self
+            .prepare(self.client.post(&url), Some(user_id))
+            .await
+            .json(&serde_json::json!({ "user_id": user_id }));
+        let response = crate::correlation::backend(request.send())
+            .await
+            .context("Failed to send request to backend")?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(response).await.into());
+        }
+
+        Ok(())
+    }
+
+    async fn estimate(&self, question: &str, user_id: &str) -> Result<EstimateResponse> {
+        let _permit = self.permits.acquire().await?;
+        let url = self.url(BackendEndpoint::Estimate);
+        let question = self.prepare_question(question);
+        let request = self
+            .prepare(self.client.post(&url), Some(user_id))
+            .await
+            .json(&serde_json::json!({ "question": question, "user_id": user_id }));
+        let response = crate::correlation::backend(request.send())
+            .await
+            .context("Failed to send request to backend")?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(response).await.into());
+        }
+
+        let estimate: EstimateResponse = response
+            .json()
+            .await
+            .context("Failed to parse backend response")?;
+
+        Ok(estimate)
+    }
+
+    async fn suggest(&self, question: &str, user_id: &str) -> Result<Vec<String>> {
+        let url = self.url(BackendEndpoint::Suggest);
+        let request = self
+            .prepare(self.client.post(&url), Some(user_id))
+            .await
+            .json(&serde_json::json!({ "question": question, "user_id": user_id }));
+        let response = crate::correlation::backend(request.send())
+            .await
+            .context("Failed to send request to backend")?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(response).await.into());
+        }
+
+        let suggest: SuggestResponse = response
+            .json()
+            .await
+            .context("Failed to parse backend response")?;
+        Ok(suggest.suggestions)
+    }
+
+    async fn schema(&self, user_id: &str) -> Result<Arc<Vec<SchemaTable>>> {
+        let mut cached = self.schema.lock().await;
+        if let Some((fetched_at, tables)) = cached.as_ref() {
+            if fetched_at.elapsed() < SCHEMA_TTL {
+                return Ok(tables.clone());
+            }
+        }
+
+        let url = self.url(BackendEndpoint::Schema);
+        let request = self
+            .prepare(self.client.get(&url), Some(user_id))
+            .await;
+        let response = crate::correlation::backend(request.send())
             .await
             .context("Failed to send request to backend")?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            anyhow::bail!("Backend error ({}): {}", status, text);
+            return Err(ApiError::from_response(response).await.into());
+        }
+
+        let schema: SchemaResponse = response
+            .json()
+            .await
+            .context("Failed to parse backend response")?;
+        let tables = Arc::new(schema.tables);
+        *cached = Some((Instant::now(), tables.clone()));
+        Ok(tables)
+    }
+
+    async fn delete_user_data(&self, user_id: &str) -> Result<()> {
+        let url = format!("{}/{}", self.url(BackendEndpoint::Users), user_id);
+        let request = self
+            .prepare(self.client.delete(&url), Some(user_id))
+            .await;
+        let response = crate::correlation::backend(request.send())
+            .await
+            .context("Failed to send request to backend")?;
+
+        // 404 - у бэкенда нет данных об этом пользователе
+        if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_FOUND {
+            return Err(ApiError::from_response(response).await.into());
         }
 
         Ok(())
     }
 
-    pub async fn health_check(&self) -> Result<bool> {
-        let url = format!("{}/api/health", self.base_url);
+    async fn link_account(&self, request: LinkRequest) -> Result<LinkResponse> {
+        let url = self.url(BackendEndpoint::TelegramLink);
+        let request = self
+            .client
+            .post(&url)
+            .json(&request);
+        let response = crate::correlation::backend(request.send())
+            .await
+            .context("Failed to send request to backend")?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(response).await.into());
+        }
+
+        let link_response: LinkResponse = response
+            .json()
+            .await
+            .context("Failed to parse backend response")?;
+
+        Ok(link_response)
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        let url = self.url(BackendEndpoint::Health);
         let response = self
             .client
             .get(&url)
@@ -195,7 +817,226 @@ impl ApiClient {
             .await
             .context("Failed to send request to backend")?;
 
-        Ok(response.status().is_success())
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            return Err(Unauthorized(status).into());
+        }
+        if !status.is_success() {
+            return Ok(false);
+        }
+        // Бэкенд может сообщить версию API; старый бэкенд отвечает без тела или без версии
+        if let Ok(health) = response.json::<Value>().await {
+            if let Some(version) = self.endpoints.negotiate(&health) {
+                tracing::info!("Backend reports API {}, using {}", version.as_str(), self.endpoints.path(BackendEndpoint::Query));
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Повторяет запрос один раз, если бэкенд ответил временной ошибкой (перегружен, 503, 429)
+async fn with_retry<T, F, Fut>(mut send: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let result = send().await;
+    let Some(delay) = result.as_ref().err().and_then(crate::api_error::retry_delay) else {
+        return result;
+    };
+    tracing::warn!("Backend is temporarily unavailable, retrying in {:?}", delay);
+    tokio::time::sleep(delay).await;
+    send().await
+}
+
+fn mime_for(file_name: &str) -> &'static str {
+    if file_name.to_lowercase().ends_with(".xlsx") {
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+    } else {
+        "text/csv"
+    }
+}
+
+/// Бэкенд отклонил запрос: ключ не передан или неверен
+#[derive(Debug)]
+pub struct Unauthorized(pub StatusCode);
+
+impl std::fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "backend rejected the request ({})", self.0)
+    }
+}
+
+impl std::error::Error for Unauthorized {}
+
+/// У бэкенда нет потокового `/api/query/stream`
+#[derive(Debug)]
+struct StreamingUnsupported(StatusCode);
+
+impl std::fmt::Display for StreamingUnsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "backend does not support streaming queries ({})", self.0)
+    }
+}
+
+impl std::error::Error for StreamingUnsupported {}
+
+/// Событие server-sent events
+struct StreamEvent {
+    /// Поле `event:`; без него событие называется `message`
+    name: String,
+    /// Строки `data:`, соединенные переводом строки
+    data: String,
+}
+
+/// Разбирает поток server-sent events, приходящий произвольными кусками
+#[derive(Default)]
+struct EventStream {
+    buffer: Vec<u8>,
+}
+
+impl EventStream {
+    /// Добавляет очередной кусок и возвращает события, которые в нем завершились
+    fn push(&mut self, chunk: &[u8]) -> Vec<StreamEvent> {
+        self.buffer.extend(chunk.iter().filter(|byte| **byte != b'\r'));
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|pair| pair == b"\n\n") {
+            let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            if let Some(event) = parse_event(&String::from_utf8_lossy(&block)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+}
+
+fn parse_event(block: &str) -> Option<StreamEvent> {
+    let mut name = None;
+    let mut data: Option<String> = None;
+    for line in block.lines() {
+        // Строки, начинающиеся с `:`, - комментарии (например, keep-alive)
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => name = Some(value.to_string()),
+            "data" => match &mut data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => data = Some(value.to_string()),
+            },
+            _ => {}
+        }
     }
+    Some(StreamEvent {
+        name: name.unwrap_or_else(|| "message".to_string()),
+        data: data?,
+    })
 }
 
+/// Строковое поле `field` из JSON в данных события; данные не в JSON используются как есть
+fn stream_text(data: &str, field: &str) -> String {
+    match serde_json::from_str::<Value>(data) {
+        Ok(Value::Object(object)) => object.get(field).and_then(Value::as_str).unwrap_or_default().to_string(),
+        Ok(Value::String(text)) => text,
+        _ => data.to_string(),
+    }
+}
+
+/// Ошибка вызвана тем, что бэкенд не ответил за отведенное время
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_timeout())
+}
+
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Бэкенд с заготовленными ответами: `query` отдает их по очереди и запоминает вопросы.
+    /// Подсказок у него нет, остальные запросы завершаются ошибкой.
+    pub struct FakeBackend {
+        answers: std::sync::Mutex<VecDeque<QueryResponse>>,
+        questions: std::sync::Mutex<Vec<String>>,
+        cache: ResponseCache,
+    }
+
+    impl FakeBackend {
+        pub fn new(answers: impl IntoIterator<Item = QueryResponse>) -> Self {
+            Self {
+                answers: std::sync::Mutex::new(answers.into_iter().collect()),
+                questions: Default::default(),
+                cache: ResponseCache::open(None, 0).unwrap(),
+            }
+        }
+
+        /// Вопросы, полученные `query`, по порядку
+        pub fn questions(&self) -> Vec<String> {
+            self.questions.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl BackendClient for FakeBackend {
+        fn api_version(&self) -> ApiVersion {
+            ApiVersion::V1
+        }
+
+        fn cache(&self) -> &ResponseCache {
+            &self.cache
+        }
+
+        fn is_streaming(&self) -> bool {
+            false
+        }
+
+        async fn query(&self, request: QueryRequest) -> Result<QueryResponse> {
+            self.questions.lock().unwrap().push(request.question);
+            self.answers.lock().unwrap().pop_front().context("FakeBackend has no more answers")
+        }
+
+        async fn query_stream(&self, request: QueryRequest, _: mpsc::UnboundedSender<String>) -> Result<QueryResponse> {
+            self.query(request).await
+        }
+
+        async fn chat(&self, _: ChatRequest) -> Result<ChatResponse> {
+            anyhow::bail!("FakeBackend does not support chat")
+        }
+
+        async fn upload(&self, _: UploadRequest) -> Result<QueryResponse> {
+            anyhow::bail!("FakeBackend does not support uploads")
+        }
+
+        async fn clear_context(&self, _: &str) -> Result<()> {
+            Ok(())
+        }
+
+        async fn estimate(&self, _: &str, _: &str) -> Result<EstimateResponse> {
+            anyhow::bail!("FakeBackend does not support estimates")
+        }
+
+        async fn suggest(&self, _: &str, _: &str) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        async fn schema(&self, _: &str) -> Result<Arc<Vec<SchemaTable>>> {
+            Ok(Arc::new(Vec::new()))
+        }
+
+        async fn delete_user_data(&self, _: &str) -> Result<()> {
+            Ok(())
+        }
+
+        async fn link_account(&self, _: LinkRequest) -> Result<LinkResponse> {
+            anyhow::bail!("FakeBackend does not support account linking")
+        }
+
+        async fn health_check(&self) -> Result<bool> {
+            Ok(true)
+        }
+    }
+}
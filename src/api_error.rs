@@ -0,0 +1,173 @@
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Дольше этого повтор запроса не откладывается, даже если бэкенд просит подождать больше
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Ошибка, которую вернул бэкенд. Новый бэкенд присылает ее в JSON:
+/// `{"error": {"code": "...", "message": "...", "category": "..."}}` (или те же поля без `error`);
+/// ответы прежнего бэкенда без структуры разбираются по коду статуса и тексту.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    /// Сгенерированный по вопросу SQL не выполнился - вопрос, скорее всего, не про данные
+    Sql { code: Option<String>, message: String },
+    /// Бэкенд считает, что вопрос не относится к данным
+    OffTopic { code: Option<String>, message: String },
+    /// Вопрос или параметры некорректны; сообщение бэкенда показывается пользователю как подсказка
+    Invalid { code: Option<String>, message: String },
+    /// Бэкенд перегружен или временно недоступен, запрос можно повторить
+    Transient { status: StatusCode, message: String, retry_after: Option<Duration> },
+    Other { status: StatusCode, code: Option<String>, message: String },
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    category: Option<String>,
+}
+
+impl ApiError {
+    /// Ошибка из неуспешного HTTP-ответа бэкенда
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let retry_after = response.headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        let text = response.text().await.unwrap_or_default();
+        Self::parse(status, &text, retry_after)
+    }
+
+    /// Ошибка из события `error` потокового ответа (`{"error": ...}` или просто текст)
+    pub fn from_stream_event(data: &str) -> Self {
+        Self::parse(StatusCode::INTERNAL_SERVER_ERROR, data, None)
+    }
+
+    fn parse(status: StatusCode, text: &str, retry_after: Option<Duration>) -> Self {
+        let body = match serde_json::from_str::<Value>(text) {
+            Ok(Value::Object(mut object)) => match object.remove("error") {
+                Some(error @ Value::Object(_)) => serde_json::from_value(error).unwrap_or_default(),
+                // `{"error": "текст", "category": ...}`: текст ошибки - в `error`, остальное рядом
+                Some(Value::String(message)) => {
+                    let body: ErrorBody = serde_json::from_value(Value::Object(object)).unwrap_or_default();
+                    ErrorBody { message: body.message.or(Some(message)), ..body }
+                }
+                _ => serde_json::from_value(Value::Object(object)).unwrap_or_default(),
+            },
+            Ok(Value::String(message)) => ErrorBody { message: Some(message), ..Default::default() },
+            _ => ErrorBody { message: Some(text.trim().to_string()), ..Default::default() },
+        };
+        let ErrorBody { code, message, category } = body;
+        let message = message.unwrap_or_default();
+
+        match category.as_deref().map(str::to_lowercase).as_deref() {
+            Some("sql" | "database" | "query_execution") => Self::Sql { code, message },
+            Some("off_topic" | "not_data_question" | "unsupported_question") => Self::OffTopic { code, message },
+            Some("validation" | "invalid_request" | "bad_request") => Self::Invalid { code, message },
+            Some("rate_limited" | "rate_limit" | "overloaded" | "unavailable" | "timeout") => {
+                Self::Transient { status, message, retry_after }
+            }
+            Some(_) => Self::Other { status, code, message },
+            // Прежний бэкенд: категории нет, ошибки выполнения SQL узнаются только по тексту
+            None => match status {
+                StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT => Self::Transient { status, message, retry_after },
+                _ if ["syntax error", "SQL", "database"].iter().any(|marker| message.contains(marker)) => {
+                    Self::Sql { code, message }
+                }
+                StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Self::Invalid { code, message },
+                _ => Self::Other { status, code, message },
+            },
+        }
+    }
+
+    /// На вопрос стоит ответить через чат-ассистента (`/api/chat`), а не показывать ошибку
+    pub fn suggests_chat(&self) -> bool {
+        matches!(self, Self::Sql { .. } | Self::OffTopic { .. })
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Sql { message, .. }
+            | Self::OffTopic { message, .. }
+            | Self::Invalid { message, .. }
+            | Self::Transient { message, .. }
+            | Self::Other { message, .. } => message,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (kind, code) = match self {
+            Self::Sql { code, .. } => ("SQL error", code.as_deref()),
+            Self::OffTopic { code, .. } => ("question is not about the data", code.as_deref()),
+            Self::Invalid { code, .. } => ("invalid request", code.as_deref()),
+            Self::Transient { status, .. } => (status.canonical_reason().unwrap_or("temporarily unavailable"), None),
+            Self::Other { code, .. } => ("backend error", code.as_deref()),
+        };
+        match code {
+            Some(code) => write!(f, "Backend error ({}, {}): {}", kind, code, self.message()),
+            None => write!(f, "Backend error ({}): {}", kind, self.message()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Через сколько повторить запрос, если он завершился временной ошибкой бэкенда
+pub fn retry_delay(error: &anyhow::Error) -> Option<Duration> {
+    match error.downcast_ref::<ApiError>()? {
+        ApiError::Transient { retry_after, .. } => Some(retry_after.unwrap_or(Duration::from_secs(1)).min(MAX_RETRY_DELAY)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn structured_errors_are_classified_by_category() {
+        let error = ApiError::parse(
+            StatusCode::BAD_REQUEST,
+            r#"{"error": {"code": "E_COLUMN", "message": "column \"foo\" does not exist", "category": "sql"}}"#,
+            None,
+        );
+        assert!(error.suggests_chat());
+        assert_eq!(error.message(), "column \"foo\" does not exist");
+
+        let error = ApiError::parse(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            r#"{"code": "E_PERIOD", "message": "Период больше года", "category": "validation"}"#,
+            None,
+        );
+        assert_eq!(error, ApiError::Invalid { code: Some("E_PERIOD".into()), message: "Период больше года".into() });
+
+        let error = ApiError::parse(StatusCode::OK, r#"{"error": "overloaded", "category": "overloaded"}"#, None);
+        assert!(matches!(error, ApiError::Transient { .. }));
+    }
+
+    #[test]
+    fn unstructured_errors_fall_back_to_status_and_text() {
+        let error = ApiError::parse(StatusCode::INTERNAL_SERVER_ERROR, "ERROR: syntax error at or near \"FROM\"", None);
+        assert!(error.suggests_chat());
+
+        let error = ApiError::parse(StatusCode::SERVICE_UNAVAILABLE, "", Some(Duration::from_secs(30)));
+        assert_eq!(retry_delay(&error.into()), Some(MAX_RETRY_DELAY));
+
+        let error = ApiError::parse(StatusCode::INTERNAL_SERVER_ERROR, r#"{"error": "boom"}"#, None);
+        assert!(matches!(error, ApiError::Other { .. }));
+        assert!(!error.suggests_chat());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::api_client::QueryResponse;
+use crate::storage::Storage;
+use crate::timezone::UserZone;
+use crate::utils::escape_html;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Сколько последних взаимодействий хранится на пользователя
+pub const MAX_AUDIT_ENTRIES: usize = 200;
+
+/// Запись журнала взаимодействий пользователя с ботом
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub question: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headline: Option<String>,
+    /// Ключевые числа ответа в виде «название: значение»
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_numbers: Vec<String>,
+    pub row_count: usize,
+    pub execution_time_ms: u64,
+}
+
+impl AuditEntry {
+    pub fn from_response(response: &QueryResponse) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            question: response.question.clone(),
+            headline: response.analysis.as_ref().map(|a| a.headline.clone()),
+            key_numbers: key_numbers(response),
+            row_count: response.row_count,
+            execution_time_ms: response.execution_time_ms,
+        }
+    }
+}
+
+/// Для одиночных агрегатов берем числовые поля первой строки, иначе - количество строк
+pub fn key_numbers(response: &QueryResponse) -> Vec<String> {
+    if response.data.len() == 1 {
+        if let Some(obj) = response.data[0].as_object() {
+            let numbers: Vec<String> = obj
+                .iter()
+                .filter(|(_, v)| v.is_number())
+                .map(|(k, v)| format!("{}: {}", k, v))
+                .collect();
+            if !numbers.is_empty() {
+                return numbers;
+            }
+        }
+    }
+
+    if response.row_count > 0 {
+        vec![format!("строк: {}", response.row_count)]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Записывает ответ в журнал пользователя (ошибки только логируются)
+pub async fn record(storage: &Storage, user_id: &str, response: &QueryResponse) {
+    let entry = AuditEntry::from_response(response);
+    let result = storage.add_history(user_id, entry, MAX_AUDIT_ENTRIES).await;
+
+    if let Err(e) = result {
+        tracing::error!("Failed to record audit entry for user {}: {}", user_id, e);
+    }
+}
+
+/// Формирует HTML-документ со стенограммой последних запросов; время - в поясе `zone`
+pub fn render_transcript_html(entries: &[AuditEntry], zone: UserZone) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html lang=\"ru\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Payment Analytics — история запросов</title>\n\
+         <style>\n\
+         body { font-family: sans-serif; margin: 2em; color: #222; }\n\
+         .entry { border-bottom: 1px solid #ddd; padding: 0.8em 0; }\n\
+         .time { color: #888; font-size: 0.85em; }\n\
+         .question { font-weight: bold; margin: 0.3em 0; }\n\
+         .headline { color: #1a5fb4; }\n\
+         ul { margin: 0.3em 0; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    html.push_str(&format!(
+        "<h1>История запросов</h1>\n<p>Сформировано {}, записей: {}</p>\n",
+        zone.stamp(Utc::now(), "%Y-%m-%d %H:%M"),
+        entries.len()
+    ));
+
+    for entry in entries {
+        html.push_str("<div class=\"entry\">\n");
+        html.push_str(&format!(
+            "<div class=\"time\">{} · {} мс</div>\n",
+            zone.local(entry.timestamp).format("%Y-%m-%d %H:%M:%S"),
+            entry.execution_time_ms
+        ));
+        html.push_str(&format!("<div class=\"question\">{}</div>\n", escape_html(&entry.question)));
+        if let Some(headline) = &entry.headline {
+            html.push_str(&format!("<div class=\"headline\">{}</div>\n", escape_html(headline)));
+        }
+        if !entry.key_numbers.is_empty() {
+            html.push_str("<ul>\n");
+            for number in &entry.key_numbers {
+                html.push_str(&format!("<li>{}</li>\n", escape_html(number)));
+            }
+            html.push_str("</ul>\n");
+        }
+        html.push_str("</div>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
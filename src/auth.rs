@@ -0,0 +1,118 @@
+use crate::storage::Storage;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+const NONCE_LEN: usize = 12;
+
+/// Ключ, под которым хранится токен отправителя: его Telegram id независимо от `CONTEXT_SCOPE`
+pub fn personal_key(msg: &teloxide::types::Message) -> Option<String> {
+    msg.from().map(|user| user.id.to_string())
+}
+
+/// Ключ контекста - Telegram id пользователя (личный чат или `CONTEXT_SCOPE=user`)
+fn is_personal_key(key: &str) -> bool {
+    key.parse::<u64>().is_ok()
+}
+
+/// Персональные токены пользователей для бэкенда.
+/// Токены хранятся в `Storage` в зашифрованном виде (AES-256-GCM).
+pub struct Credentials {
+    storage: Arc<Storage>,
+    cipher: Aes256Gcm,
+}
+
+impl Credentials {
+    /// Ключ шифрования выводится из секрета `TOKEN_ENCRYPTION_KEY`
+    pub fn new(storage: Arc<Storage>, secret: &str) -> Self {
+        let key = Sha256::digest(secret.as_bytes());
+        Self {
+            storage,
+            cipher: Aes256Gcm::new(&key),
+        }
+    }
+
+    /// Токен пользователя по ключу контекста. Токен принадлежит человеку, поэтому ключи
+    /// групп (`-100…`) и участников групп (`чат:пользователь`) его не дают: иначе запросы
+    /// всех участников выполнялись бы с правами того, кто привязал токен.
+    pub async fn token_for(&self, user_id: &str) -> Option<String> {
+        if !is_personal_key(user_id) {
+            return None;
+        }
+        let encrypted = self.storage.user(user_id).await?.api_token?;
+        match self.decrypt(&encrypted) {
+            Ok(token) => Some(token),
+            Err(e) => {
+                tracing::warn!("Failed to decrypt token for user {}: {}", user_id, e);
+                None
+            }
+        }
+    }
+
+    /// Сохраняет токен под Telegram id пользователя (см. [`personal_key`])
+    pub async fn store_token(&self, user_id: &str, token: &str) -> Result<()> {
+        anyhow::ensure!(is_personal_key(user_id), "Token key {} is not a Telegram user id", user_id);
+        let encrypted = self.encrypt(token)?;
+        self.storage
+            .update_user(user_id, |user| user.api_token = Some(encrypted))
+            .await
+    }
+
+    pub async fn remove_token(&self, user_id: &str) -> Result<()> {
+        self.storage
+            .update_user(user_id, |user| user.api_token = None)
+            .await
+    }
+
+    fn encrypt(&self, plain: &str) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plain.as_bytes())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt token"))?;
+
+        // Храним nonce вместе с шифротекстом
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(BASE64.encode(payload))
+    }
+
+    fn decrypt(&self, encoded: &str) -> Result<String> {
+        let payload = BASE64.decode(encoded).context("Invalid token encoding")?;
+        if payload.len() <= NONCE_LEN {
+            anyhow::bail!("Encrypted token is too short");
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let plain = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt token (wrong key?)"))?;
+        String::from_utf8(plain).context("Decrypted token is not valid UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn group_keys_never_get_a_token() {
+        let dir = crate::utils::test_dir("auth");
+        let storage = Arc::new(Storage::open(dir.join("bot_data.db")).unwrap());
+        let credentials = Credentials::new(storage.clone(), "secret");
+
+        credentials.store_token("42", "personal").await.unwrap();
+        assert_eq!(credentials.token_for("42").await.as_deref(), Some("personal"));
+        assert!(credentials.store_token("-1001", "shared").await.is_err());
+        assert!(credentials.store_token("-1001:42", "shared").await.is_err());
+
+        // Токен, сохраненный под ключом группы до исправления, не применяется
+        let legacy = credentials.encrypt("shared").unwrap();
+        storage.update_user("-1001", |user| user.api_token = Some(legacy)).await.unwrap();
+        assert_eq!(credentials.token_for("-1001").await, None);
+    }
+}
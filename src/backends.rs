@@ -0,0 +1,47 @@
+use crate::api_client::BackendClient;
+use std::sync::Arc;
+
+/// Именованные бэкенды аналитики (например, `prod` и `sandbox`). Первый - основной:
+/// через него идут запросы пользователей, не выбравших другой через `/env`, и фоновые проверки.
+pub struct Backends {
+    clients: Vec<(String, Arc<dyn BackendClient>)>,
+}
+
+impl Backends {
+    pub fn new(default_name: String, default_client: Arc<dyn BackendClient>) -> Self {
+        Self { clients: vec![(default_name, default_client)] }
+    }
+
+    pub fn add(&mut self, name: String, client: Arc<dyn BackendClient>) {
+        self.clients.push((name, client));
+    }
+
+    pub fn default_name(&self) -> &str {
+        &self.clients[0].0
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn BackendClient>> {
+        self.clients.iter().find(|(known, _)| known == name).map(|(_, client)| client)
+    }
+
+    /// Бэкенд с именем `name`, а если такого нет (или имя не задано) - основной
+    pub fn resolve(&self, name: Option<&str>) -> (&str, &Arc<dyn BackendClient>) {
+        let (name, client) = name
+            .and_then(|name| self.clients.iter().find(|(known, _)| known == name))
+            .unwrap_or(&self.clients[0]);
+        (name, client)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.clients.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = &Arc<dyn BackendClient>> {
+        self.clients.iter().map(|(_, client)| client)
+    }
+
+    /// Настроено больше одного бэкенда, и `/env` имеет смысл
+    pub fn is_multiple(&self) -> bool {
+        self.clients.len() > 1
+    }
+}
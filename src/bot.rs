@@ -1,16 +1,99 @@
-use crate::config::Config;
-use crate::api_client::ApiClient;
+use crate::config::{BotMode, Config};
+use crate::acl::AccessControl;
+use crate::api_client::{ApiClient, BackendClient, HttpOptions};
+use crate::auth::Credentials;
+use crate::backends::Backends;
+use crate::charts::ChartRenderer;
+use crate::commands::Command;
+use crate::correlation;
 use crate::handlers;
+use crate::handoff::HandoffSigner;
+use crate::i18n::{tr, Msg};
+use crate::metrics::{UpdateKind, METRICS};
+use crate::inline::{self, HeadlineCache};
+use crate::monitor::BackendMonitor;
+use crate::progress::Progress;
+use crate::rate_limit::RateLimiter;
+use crate::response_cache::ResponseCache;
+use crate::sender::ResponseSender;
+use crate::sessions::ChatSessions;
+use crate::state::BotState;
+use crate::storage::Storage;
+use crate::transliterate::Transliterator;
 use teloxide::prelude::*;
 use teloxide::types::Message;
-use anyhow::Result;
+use teloxide::utils::command::{BotCommands, ParseError};
+use teloxide::update_listeners::webhooks;
+use anyhow::{Context, Result};
 use tracing::info;
 use std::sync::Arc;
 
 pub async fn start_bot(bot: Bot, config: Config) -> Result<()> {
     info!("Bot is starting...");
 
-    let api_client = Arc::new(ApiClient::new(config.backend_url.clone()));
+    let storage = Arc::new(Storage::open(&config.storage_path)?);
+    let acl = AccessControl::open(
+        &config.allowlist_path,
+        &config.allowed_user_ids,
+        &config.allowed_chat_ids,
+        &config.admin_user_ids,
+    )?;
+    let credentials = match &config.token_encryption_key {
+        Some(secret) => Some(Arc::new(Credentials::new(storage.clone(), secret))),
+        None => {
+            tracing::warn!("TOKEN_ENCRYPTION_KEY is not set, /login is disabled");
+            None
+        }
+    };
+    let response_cache = ResponseCache::open(
+        config.response_cache_path.as_ref().map(Into::into),
+        config.response_cache_ttl_secs,
+    )?;
+    let http = HttpOptions::load(
+        std::time::Duration::from_secs(config.backend_timeout_secs),
+        config.backend_ca_bundle.as_deref(),
+        config.backend_accept_invalid_certs,
+    )?;
+    // Один словарь на все бэкенды: записи в базах одинаковые
+    let transliterator = config
+        .transliteration
+        .then(|| Arc::new(Transliterator::new(&config.translit_dictionary)));
+    let api_client: Arc<dyn BackendClient> = Arc::new(ApiClient::new(
+        config.backend_url.clone(),
+        config.backend_api_key.as_deref(),
+        &http,
+        credentials.clone(),
+        response_cache,
+        config.max_concurrent_backend_requests,
+        config.backend_streaming,
+    )?
+        .with_endpoints(config.backend_endpoints.clone())
+        .with_transliterator(transliterator.clone()));
+
+    let mut backends = Backends::new(config.backend_name.clone(), api_client.clone());
+    for backend in &config.extra_backends {
+        // Отдельный кэш, чтобы ответы разных бэкендов не смешивались; персональные токены
+        // из /login выданы основным бэкендом и сюда не передаются
+        let cache = ResponseCache::open(
+            config.response_cache_path.as_ref().map(|path| format!("{}.{}", path, backend.name).into()),
+            config.response_cache_ttl_secs,
+        )?;
+        let client = ApiClient::new(
+            backend.url.clone(),
+            backend.api_key.as_deref(),
+            &http,
+            None,
+            cache,
+            config.max_concurrent_backend_requests,
+            config.backend_streaming,
+        )
+            .with_context(|| format!("Failed to configure backend {}", backend.name))?
+            .with_endpoints(config.backend_endpoints.clone())
+            .with_transliterator(transliterator.clone());
+        info!("Backend {} is available via /env at {}", backend.name, backend.url);
+        backends.add(backend.name.clone(), Arc::new(client));
+    }
+    crate::retention::spawn_purge_task(storage.clone(), backends.clients().cloned().collect(), config.retention_days);
 
     // Проверяем подключение к бэкенду
     match api_client.health_check().await {
@@ -18,15 +101,127 @@ pub async fn start_bot(bot: Bot, config: Config) -> Result<()> {
         Ok(false) => {
             tracing::warn!("Backend is not available, but continuing anyway");
         }
+        // Без верного ключа бот не сможет выполнить ни одного запроса
+        Err(e) if e.downcast_ref::<crate::api_client::Unauthorized>().is_some() => {
+            if config.backend_api_key.is_some() {
+                anyhow::bail!("Backend rejected BACKEND_API_KEY: {}", e);
+            }
+            anyhow::bail!("Backend requires authentication ({}): set BACKEND_API_KEY", e);
+        }
         Err(e) => {
             tracing::warn!("Failed to check backend: {} (continuing anyway)", e);
         }
     }
 
-    let api_client_clone1 = api_client.clone();
-    let api_client_clone2 = api_client.clone();
-    let api_client_clone3 = api_client.clone();
+    let handoff = match (&config.web_dashboard_url, &config.handoff_secret) {
+        (Some(url), Some(secret)) => Some(HandoffSigner::new(url.clone(), secret.clone())),
+        _ => None,
+    };
+
+    if let Err(e) = bot.set_my_commands(Command::bot_commands()).await {
+        tracing::warn!("Failed to register bot commands: {}", e);
+    }
+
+    let me = bot.get_me().await?;
+    let bot_username = me.username().to_string();
+
+    let monitor = Arc::new(BackendMonitor::default());
+    monitor.spawn(
+        bot.clone(),
+        api_client.clone(),
+        config.admin_chat_id.map(ChatId),
+        std::time::Duration::from_secs(config.health_check_interval_secs),
+    );
+
+    let headlines = Arc::new(HeadlineCache::default());
+    headlines.spawn_refresh(api_client.clone());
+
+    let dialogues = crate::dialogue::open_storage(config.redis_url.as_deref()).await?;
+
+    let pdf_font = match std::fs::read(&config.pdf_font_path) {
+        Ok(font) => Some(Arc::new(font)),
+        Err(e) => {
+            tracing::warn!("Failed to read PDF_FONT_PATH {}: {}, PDF reports are disabled", config.pdf_font_path, e);
+            None
+        }
+    };
+
+    let currency = crate::currency::CurrencyConverter::new(&config.currency_rates, config.fx_api_url.clone())?;
+    let object_storage = config.object_storage.clone()
+        .map(crate::object_storage::ObjectStorage::new)
+        .transpose()?;
+
+    let chat_sessions = ChatSessions::new(storage.clone(), config.chat_session_ttl_mins);
+    let state = Arc::new(BotState {
+        acl,
+        chat_sessions,
+        api_client,
+        backends,
+        storage,
+        credentials,
+        handoff,
+        headlines,
+        monitor,
+        context_scope: config.context_scope,
+        pending_queries: Default::default(),
+        rate_limiter: RateLimiter::new(config.rate_limit_per_minute, config.rate_limit_burst),
+        last_results: Default::default(),
+        charts: Default::default(),
+        chart_renderer: ChartRenderer::new(config.chart_render_concurrency, config.chart_theme, &bot_username),
+        pdf_font,
+        result_pages: Default::default(),
+        answer_versions: Default::default(),
+        suggestions: Default::default(),
+        sql_queries: Default::default(),
+        answered_questions: Default::default(),
+        answer_messages: Default::default(),
+        estimate_confirm_rows: config.estimate_confirm_rows,
+        max_message_chunks: config.max_message_chunks,
+        // Bot API не отдает ботам файлы больше 20 МБ
+        max_upload_bytes: config.max_upload_mb.min(20) * 1_048_576,
+        text_format: config.text_format,
+        in_flight: Default::default(),
+        dialogues,
+        running_queries: Default::default(),
+        chat_queues: Default::default(),
+        bot_username,
+        // Неверное смещение уже отключает расписание (см. scheduler::spawn), даты в вопросах тогда считаются по UTC
+        time_zone: chrono::FixedOffset::east_opt(config.schedule_utc_offset_hours * 3600)
+            .unwrap_or_else(|| chrono::Offset::fix(&chrono::Utc)),
+        object_storage,
+        drilldown_template: config.drilldown_template.clone(),
+        currency,
+    });
+
+    if let Some(port) = config.metrics_port {
+        crate::metrics::spawn_server(port);
+    }
+    crate::scheduler::spawn(bot.clone(), state.clone(), config.schedule_utc_offset_hours);
+    crate::watcher::spawn(bot.clone(), state.clone());
+    if let (Some(port), Some(secret)) = (config.notify_port, config.notify_secret.clone()) {
+        crate::notify::spawn_server(port, secret, bot.clone(), state.clone());
+    }
+
+    let state_clone1 = state.clone();
+    let state_clone2 = state.clone();
+    let state_clone3 = state.clone();
+    let state_clone4 = state.clone();
+    let state_clone5 = state.clone();
+    let state_clone6 = state.clone();
+    let bot_username = state.bot_username.clone();
     let handler = dptree::entry()
+        .branch(
+            // Обновления от пользователей и чатов без доступа дальше не обрабатываются
+            dptree::filter_async(move |update: Update| {
+                let state = state_clone5.clone();
+                async move {
+                    let user_id = update.user().map(|user| user.id);
+                    let chat_id = update.chat().map(|chat| chat.id);
+                    !state.acl.is_allowed(user_id, chat_id).await
+                }
+            })
+            .endpoint(move |bot: Bot, update: Update| reject_unauthorized(bot, update, bot_username.clone()))
+        )
         .branch(
             Update::filter_message()
                 .filter(|msg: Message| {
@@ -36,96 +231,431 @@ pub async fn start_bot(bot: Bot, config: Config) -> Result<()> {
                         false
                     }
                 })
-                .endpoint(move |bot: Bot, msg: Message| {
-                    let api_client = api_client_clone1.clone();
-                    async move {
-                        handle_commands(bot, msg, api_client).await
-                    }
+                .endpoint(move |bot: Bot, msg: Message, update: Update| {
+                    let state = state_clone1.clone();
+                    METRICS.record_update(UpdateKind::Command);
+                    correlation::scope(correlation::for_update(&update), async move {
+                        handle_commands(bot, msg, state).await
+                    })
                 })
         )
         .branch(
             Update::filter_callback_query()
-                .endpoint(move |bot: Bot, q: teloxide::types::CallbackQuery| {
-                    let api_client = api_client_clone2.clone();
-                    async move {
-                        handle_callback(bot, q, api_client).await
-                    }
+                .endpoint(move |bot: Bot, q: teloxide::types::CallbackQuery, update: Update| {
+                    let state = state_clone2.clone();
+                    METRICS.record_update(UpdateKind::Callback);
+                    correlation::scope(correlation::for_update(&update), async move {
+                        handle_callback(bot, q, state).await
+                    })
+                })
+        )
+        .branch(
+            Update::filter_inline_query()
+                .endpoint(move |bot: Bot, q: teloxide::types::InlineQuery, update: Update| {
+                    let state = state_clone4.clone();
+                    METRICS.record_update(UpdateKind::Inline);
+                    correlation::scope(correlation::for_update(&update), async move {
+                        inline::handle_inline_query(bot, q, state).await
+                    })
+                })
+        )
+        .branch(
+            Update::filter_edited_message()
+                .endpoint(move |bot: Bot, msg: Message, update: Update| {
+                    let state = state_clone6.clone();
+                    METRICS.record_update(UpdateKind::EditedMessage);
+                    correlation::scope(correlation::for_update(&update), async move {
+                        handle_edited_message(bot, msg, state).await
+                    })
                 })
         )
         .branch(
             Update::filter_message()
-                .endpoint(move |bot: Bot, msg: Message| {
-                    let api_client = api_client_clone3.clone();
-                    async move {
-                        handle_messages(bot, msg, api_client).await
-                    }
+                .endpoint(move |bot: Bot, msg: Message, update: Update| {
+                    let state = state_clone3.clone();
+                    METRICS.record_update(UpdateKind::Message);
+                    correlation::scope(correlation::for_update(&update), async move {
+                        handle_messages(bot, msg, state).await
+                    })
                 })
         );
 
-    Dispatcher::builder(bot, handler)
-        .enable_ctrlc_handler()
-        .build()
-        .dispatch()
-        .await;
+    let mut dispatcher = Dispatcher::builder(bot.clone(), handler).build();
+    crate::shutdown::spawn_signal_handler(
+        bot.clone(),
+        dispatcher.shutdown_token(),
+        state.in_flight.clone(),
+        std::time::Duration::from_secs(config.shutdown_timeout_secs),
+    );
+
+    match config.bot_mode {
+        BotMode::Polling => {
+            info!("Receiving updates via long polling");
+            dispatcher.dispatch().await;
+        }
+        BotMode::Webhook => {
+            // URL проверен при загрузке конфигурации
+            let url = config.webhook_url.as_deref().unwrap_or_default();
+            let url = url.parse().context("WEBHOOK_URL is not a valid URL")?;
+            let address = std::net::SocketAddr::from(([0, 0, 0, 0], config.webhook_port));
+            info!("Receiving updates via webhook {} (listening on {})", url, address);
+
+            let listener = webhooks::axum(bot.clone(), webhooks::Options::new(address, url))
+                .await
+                .context("Failed to set up webhook")?;
+            dispatcher
+                .dispatch_with_listener(
+                    listener,
+                    LoggingErrorHandler::with_custom_text("An error from the webhook listener"),
+                )
+                .await;
+        }
+    }
+
+
+    crate::shutdown::finish(&bot, &state.in_flight).await;
+    Ok(())
+}
+
+/// Вежливо отказывает пользователю без доступа. В группе без доступа бот отвечает только
+/// на адресованные ему команды, остальные сообщения участников пропускаются молча.
+async fn reject_unauthorized(bot: Bot, update: Update, bot_username: String) -> ResponseResult<()> {
+    use teloxide::types::UpdateKind;
+
+    let user_id = update.user().map(|user| user.id);
+    if let UpdateKind::Message(msg) = &update.kind {
+        let addressed = msg.chat.is_private() || msg.text().is_some_and(|text| is_command_for(text, &bot_username));
+        if !addressed {
+            tracing::info!("Ignored message from unauthorized user {:?} in chat {}", user_id, msg.chat.id);
+            return Ok(());
+        }
+    }
+    tracing::info!("Rejected update from unauthorized user {:?}", user_id);
+
+    let text = match user_id {
+        Some(id) => format!(
+            "⛔ У вас нет доступа к этому боту.\n\nЧтобы получить доступ, передайте администратору ваш id: {}",
+            id
+        ),
+        None => "⛔ У этого чата нет доступа к боту.".to_string(),
+    };
+
+    match update.kind {
+        UpdateKind::Message(msg) => {
+            bot.send_message(msg.chat.id, text)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+        UpdateKind::CallbackQuery(q) => {
+            bot.answer_callback_query(q.id)
+                .text(text)
+                .show_alert(true)
+                .await?;
+        }
+        UpdateKind::InlineQuery(q) => {
+            bot.answer_inline_query(q.id, Vec::<teloxide::types::InlineQueryResult>::new())
+                .is_personal(true)
+                .cache_time(0)
+                .await?;
+        }
+        _ => {}
+    }
 
     Ok(())
 }
 
+/// Команда адресована этому боту: `/cmd` или `/cmd@bot_username`
+fn is_command_for(text: &str, bot_username: &str) -> bool {
+    let Some(command) = text.strip_prefix('/').and_then(|text| text.split_whitespace().next()) else {
+        return false;
+    };
+    match command.split_once('@') {
+        Some((_, username)) => username.eq_ignore_ascii_case(bot_username),
+        None => true,
+    }
+}
+
 async fn handle_commands(
     bot: Bot,
     msg: Message,
-    api_client: Arc<ApiClient>,
+    state: Arc<BotState>,
 ) -> ResponseResult<()> {
     let text = msg.text().unwrap_or_default();
-    let command = text.split_whitespace().next().unwrap_or("");
+
+    let command = match Command::parse(text, &state.bot_username) {
+        Ok(command) => command,
+        // Команда адресована другому боту в группе
+        Err(ParseError::WrongBotName(_)) => return Ok(()),
+        Err(ParseError::UnknownCommand(name)) => {
+            return handle_unknown_command(bot, msg, &name).await;
+        }
+        Err(e) => {
+            tracing::debug!("Failed to parse command {:?}: {}", text, e);
+            bot.send_message(msg.chat.id, "⚠️ Эта команда не принимает аргументов. Список команд — /help")
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    };
 
     match command {
-        "/start" => {
-            handlers::handle_start(bot, msg).await?;
+        Command::Start(payload) => {
+            handlers::handle_start(bot, msg, state, &payload).await?;
         }
-        "/help" => {
-            handlers::handle_help(bot, msg).await?;
+        Command::Help => {
+            handlers::handle_help(bot, msg, state).await?;
         }
-        "/clear" => {
-            handlers::handle_clear(bot, msg, api_client).await?;
+        Command::Clear => {
+            handlers::handle_clear(bot, msg, state).await?;
         }
-        "/status" => {
-            handlers::handle_status(bot, msg, api_client).await?;
+        Command::Status => {
+            handlers::handle_status(bot, msg, state).await?;
         }
-        "/menu" => {
-            use crate::menu::create_main_menu;
-            bot.send_message(msg.chat.id, "📋 Главное меню")
-                .reply_markup(create_main_menu())
-                .reply_to_message_id(msg.id)
-                .await?;
+        Command::Ping => {
+            handlers::handle_ping(bot, msg, state).await?;
+        }
+        Command::Sql(question) => {
+            handlers::handle_sql_command(bot, msg, state, &question).await?;
+        }
+        Command::Chat(message) => {
+            handlers::handle_chat_command(bot, msg, state, &message).await?;
+        }
+        Command::Mode(arg) => {
+            handlers::handle_mode(bot, msg, state, &arg).await?;
+        }
+        Command::Settings(arg) => {
+            handlers::handle_settings(bot, msg, state, &arg).await?;
+        }
+        Command::Schedule(arg) => {
+            handlers::handle_schedule(bot, msg, state, &arg).await?;
+        }
+        Command::Language => {
+            handlers::handle_language(bot, msg, state).await?;
+        }
+        Command::Answerlang(arg) => {
+            handlers::handle_answer_language(bot, msg, state, &arg).await?;
+        }
+        Command::Currency(arg) => {
+            handlers::handle_currency(bot, msg, state, &arg).await?;
+        }
+        Command::Timezone(arg) => {
+            handlers::handle_timezone(bot, msg, state, &arg).await?;
+        }
+        Command::Login(token) => {
+            handlers::handle_login(bot, msg, state, &token).await?;
+        }
+        Command::Logout => {
+            handlers::handle_logout(bot, msg, state).await?;
+        }
+        Command::History(arg) => {
+            handlers::handle_history(bot, msg, state, &arg).await?;
+        }
+        Command::Transcript(arg) => {
+            handlers::handle_transcript(bot, msg, state, &arg).await?;
         }
-        _ => {
-            // Неизвестная команда, игнорируем
+        Command::Alert(arg) => {
+            handlers::handle_alert(bot, msg, state, &arg).await?;
+        }
+        Command::Alerts(arg) => {
+            handlers::handle_alerts(bot, msg, state, &arg).await?;
+        }
+        Command::Subscribe(arg) => {
+            handlers::handle_subscribe(bot, msg, state, &arg, true).await?;
+        }
+        Command::Unsubscribe(arg) => {
+            handlers::handle_subscribe(bot, msg, state, &arg, false).await?;
+        }
+        Command::Save(arg) => {
+            handlers::handle_save(bot, msg, state, &arg).await?;
+        }
+        Command::Saved => {
+            handlers::handle_saved(bot, msg, state).await?;
+        }
+        Command::Template(arg) => {
+            crate::templates::handle_template(bot, msg, state, &arg).await?;
+        }
+        Command::T(arg) => {
+            crate::templates::handle_run(bot, msg, state, &arg).await?;
+        }
+        Command::Export(arg) => {
+            handlers::handle_export(bot, msg, state, &arg).await?;
+        }
+        Command::Forgetme => {
+            handlers::handle_forgetme(bot, msg).await?;
+        }
+        Command::Allow(arg) => {
+            handlers::handle_allow(bot, msg, state, &arg, true).await?;
+        }
+        Command::Deny(arg) => {
+            handlers::handle_allow(bot, msg, state, &arg, false).await?;
+        }
+        Command::Cache(arg) => {
+            handlers::handle_cache(bot, msg, state, &arg).await?;
+        }
+        Command::Stats => {
+            handlers::handle_stats(bot, msg, state).await?;
+        }
+        Command::Env(arg) => {
+            handlers::handle_env(bot, msg, state, &arg).await?;
+        }
+        Command::Debug(arg) => {
+            handlers::handle_debug(bot, msg, state, &arg).await?;
+        }
+        Command::MenuAdd(arg) => {
+            handlers::handle_menu_add(bot, msg, state, &arg).await?;
+        }
+        Command::MenuRemove(arg) => {
+            handlers::handle_menu_remove(bot, msg, state, &arg).await?;
+        }
+        Command::MenuList => {
+            handlers::handle_menu_list(bot, msg, state).await?;
+        }
+        Command::Menu => {
+            handlers::send_menu(&bot, &msg, &state).await?;
+        }
+        Command::Examples => {
+            crate::examples::send(&bot, &msg, &state).await?;
+        }
+        Command::Schema(arg) => {
+            crate::schema::handle_schema(bot, msg, state, &arg).await?;
+        }
+        Command::Compare(arg) => {
+            crate::compare::handle_compare(bot, msg, state, &arg).await?;
         }
     }
 
     Ok(())
 }
 
+/// Сообщает о неизвестной команде и подсказывает похожую
+async fn handle_unknown_command(bot: Bot, msg: Message, name: &str) -> ResponseResult<()> {
+    let text = match crate::commands::suggest_command(name) {
+        Some(suggestion) => format!(
+            "❓ Неизвестная команда /{}. Возможно, вы имели в виду {}?\n\nСписок команд — /help",
+            name, suggestion
+        ),
+        None => format!("❓ Неизвестная команда /{}. Список команд — /help", name),
+    };
+    bot.send_message(msg.chat.id, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
 async fn handle_callback(
     bot: Bot,
     q: teloxide::types::CallbackQuery,
-    api_client: Arc<ApiClient>,
+    state: Arc<BotState>,
 ) -> ResponseResult<()> {
     if let Some(data) = q.data {
         // Отвечаем на callback сразу
         bot.answer_callback_query(q.id).await?;
         
         if let Some(msg) = q.message {
-            // Отправляем сообщение "обрабатывается"
-            let processing_msg = bot.send_message(msg.chat.id, "⏳ <b>Обрабатываю запрос...</b>")
-                .parse_mode(teloxide::types::ParseMode::Html)
-                .reply_to_message_id(msg.id)
-                .await?;
-            
-            // Отправляем индикатор печати
-            let _ = bot.send_chat_action(msg.chat.id, teloxide::types::ChatAction::Typing).await;
+            // Служебные кнопки, не связанные с запросами к данным
+            if let Some(action) = data.strip_prefix("forget:") {
+                // Удаляются только данные нажавшего: его личные и его контекст в этом чате.
+                // Общий контекст группы (`CONTEXT_SCOPE=chat`) принадлежит всем участникам.
+                let mut user_ids = vec![q.from.id.to_string()];
+                let context_key = state.context_scope.key(msg.chat.id, Some(q.from.id));
+                if context_key != msg.chat.id.to_string() && !user_ids.contains(&context_key) {
+                    user_ids.push(context_key);
+                }
+                return handlers::handle_forget_callback(bot, msg, user_ids, action, state).await;
+            }
+            if let Some(format) = data.strip_prefix("export:") {
+                let user_id = state.context_scope.key(msg.chat.id, Some(q.from.id));
+                return handlers::handle_export_callback(bot, msg, user_id, format, state).await;
+            }
+            if let Some(page) = data.strip_prefix("page:") {
+                let user_id = state.context_scope.key(msg.chat.id, Some(q.from.id));
+                let lang = state.ui_language(&user_id, Some(&q.from)).await;
+                return handlers::handle_page_callback(bot, msg, page, lang, state).await;
+            }
+            if let Some(action) = data.strip_prefix("view:") {
+                let user_id = state.context_scope.key(msg.chat.id, Some(q.from.id));
+                let lang = state.ui_language(&user_id, Some(&q.from)).await;
+                return handlers::handle_view_callback(bot, msg, action, lang, state).await;
+            }
+            if let Some(action) = data.strip_prefix("frame:") {
+                let user_id = state.context_scope.key(msg.chat.id, Some(q.from.id));
+                let lang = state.ui_language(&user_id, Some(&q.from)).await;
+                return handlers::handle_frame_callback(bot, msg, action, lang, state).await;
+            }
+            if let Some(row) = data.strip_prefix("row:") {
+                return handlers::handle_row_callback(bot, msg, row, state).await;
+            }
+            if let Some(option) = data.strip_prefix("chart:") {
+                let user_id = state.context_scope.key(msg.chat.id, Some(q.from.id));
+                let lang = state.ui_language(&user_id, Some(&q.from)).await;
+                let settings = state.storage.settings(&user_id).await;
+                return handlers::handle_chart_options_callback(bot, msg, option, lang, &settings, state).await;
+            }
+            if let Some(code) = data.strip_prefix("lang:") {
+                let user_id = state.context_scope.key(msg.chat.id, Some(q.from.id));
+                return handlers::handle_language_callback(bot, msg, user_id, code, state).await;
+            }
+            if let Some(hash) = data.strip_prefix("hist:edit:") {
+                return handlers::handle_history_edit_callback(bot, msg, hash, state).await;
+            }
+            if data == "stats:chart" {
+                return handlers::handle_stats_chart_callback(bot, msg, q.from.id, state).await;
+            }
+            if let Some(action) = data.strip_prefix("saved:") {
+                let user_id = state.context_scope.key(msg.chat.id, Some(q.from.id));
+                return handlers::handle_saved_callback(bot, msg, user_id, action, state).await;
+            }
+            if let Some(action) = data.strip_prefix("tpl:") {
+                return crate::templates::handle_callback(bot, msg, q.from.id, action, state).await;
+            }
+            if let Some(action) = data.strip_prefix("schema:") {
+                return crate::schema::handle_callback(bot, msg, q.from.id, action, state).await;
+            }
+            if let Some(action) = data.strip_prefix("ex:") {
+                return crate::examples::handle_callback(bot, msg, q.from.id, action, state).await;
+            }
+            if let Some(action) = data.strip_prefix("onb:") {
+                return crate::onboarding::handle_callback(bot, msg, q.from.id, action, state).await;
+            }
+            if let Some(action) = data.strip_prefix("menu:") {
+                return handlers::handle_menu_callback(bot, msg, q.from.id, action, state).await;
+            }
+            if let Some(action) = data.strip_prefix("param:") {
+                return crate::prompts::handle_callback(bot, msg, q.from.id, action, state).await;
+            }
+            if let Some(action) = data.strip_prefix("settings:") {
+                let user_id = state.context_scope.key(msg.chat.id, Some(q.from.id));
+                return handlers::handle_settings_callback(bot, msg, user_id, action, state).await;
+            }
+            if data == "insights:all" {
+                return crate::brief::handle_expand_callback(bot, msg, state).await;
+            }
+            if let Some(action) = data.strip_prefix("brief:") {
+                return crate::brief::handle_callback(bot, msg, action, state).await;
+            }
+            if let Some(hash) = data.strip_prefix("showsql:") {
+                return handlers::handle_show_sql_callback(bot, msg, hash, state).await;
+            }
+            if let Some(id) = data.strip_prefix("alert:del:") {
+                let zone = state.user_zone(&state.context_scope.key(msg.chat.id, Some(q.from.id))).await;
+                return handlers::handle_alert_delete_callback(bot, msg, id, zone, state).await;
+            }
+            if let Some(id) = data.strip_prefix("sched:del:") {
+                return handlers::handle_schedule_delete_callback(bot, msg, id, state).await;
+            }
+            if let Some(action) = data.strip_prefix("heavy:") {
+                let user_id = state.context_scope.key(msg.chat.id, Some(q.from.id));
+                return handlers::handle_heavy_query_callback(bot, msg, user_id, action, state).await;
+            }
             
+            let user_id = state.context_scope.key(msg.chat.id, Some(q.from.id));
+            let lang = state.ui_language(&user_id, Some(&q.from)).await;
+            if handlers::reject_if_backend_down(&bot, &msg, &state, lang).await?
+                || handlers::reject_if_rate_limited(&bot, &msg, &state, lang).await?
+            {
+                return Ok(());
+            }
+
             let question = if data.starts_with("query:") {
                 let q = data.strip_prefix("query:").unwrap_or("").to_string();
                        // Suggested questions всегда SQL запросы, добавляем префикс если его нет
@@ -134,12 +664,18 @@ async fn handle_callback(
                        } else {
                            q
                        }
-            } else if data.starts_with("q:") {
-                // Это хеш, нужно получить оригинальный вопрос
-                // Пока что просто возвращаем пустую строку - это не должно происходить
-                // В будущем можно добавить кеш вопросов по хешам
-                tracing::warn!("Received hash-based callback, but no mapping available: {}", data);
-                return Ok(());
+            } else if let Some(hash) = data.strip_prefix("q:") {
+                // Длинная подсказка: восстанавливаем полный вопрос по хешу
+                match state.suggestions.get(hash) {
+                    Some(q) if q.to_lowercase().starts_with("sql:") => q,
+                    Some(q) => format!("sql: {}", q),
+                    None => {
+                        tracing::warn!("Unknown suggestion hash in callback: {}", data);
+                        bot.send_message(msg.chat.id, "⌛ Эта подсказка устарела. Задайте вопрос заново.")
+                            .await?;
+                        return Ok(());
+                    }
+                }
             } else {
                 return Ok(());
             };
@@ -147,97 +683,41 @@ async fn handle_callback(
             if question.is_empty() {
                 return Ok(());
             }
-                
+            // Двойное нажатие на подсказку не должно запускать второй такой же запрос
+            let Some(_running) = handlers::start_unless_running(&bot, &msg, &state, lang, &question).await? else {
+                return Ok(());
+            };
+
+            // Сообщение "обрабатывается" редактируется по ходу запроса и становится ответом
+            let progress = Progress::start(&bot, &msg, &state, lang).await?;
+            
             // Обрабатываем запрос напрямую
-            let user_id = msg.chat.id.to_string();
+            let zone = state.user_zone(&user_id).await;
+            let period = zone.question_period(&question);
             let query_request = crate::api_client::QueryRequest {
                 question: question.clone(),
                 include_analysis: true,
                 use_cache: true,
-                include_sql: false,
+                include_sql: true,
                 user_id: Some(user_id.clone()),
-                output_type: crate::api_client::OutputType::Auto,
+                output_type: handlers::preferred_output_type(&state, &user_id, crate::api_client::OutputType::Auto).await,
+                language: handlers::answer_language(&state, &user_id, None).await,
+                date_from: period.map(|period| period.from),
+                date_to: period.map(|period| period.to),
+                timezone: Some(zone.name()),
             };
             
-            match api_client.query(query_request).await {
+            let output_type = query_request.output_type;
+            match progress.query(&state, query_request).await {
                 Ok(response) => {
-                    // Удаляем сообщение "обрабатывается"
-                    let _ = bot.delete_message(msg.chat.id, processing_msg.id).await;
-                    
-                    // Отправляем CSV, если есть
-                    if !response.data.is_empty() {
-                        use crate::utils::format_as_csv;
-                        let csv_content = format_as_csv(&response.data);
-                        if !csv_content.is_empty() {
-                            let filename = format!("data_{}.csv", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
-                            let temp_path = std::env::temp_dir().join(&filename);
-                            if let Ok(_) = std::fs::write(&temp_path, csv_content.as_bytes()) {
-                                let _ = bot.send_document(msg.chat.id, teloxide::types::InputFile::file(&temp_path))
-                                    .caption("📊 Данные в формате CSV")
-                                    .await;
-                                let _ = std::fs::remove_file(&temp_path);
-                            }
-                        }
-                    }
-                    
-                    // Отправляем диаграмму, если есть
-                    if let Some(chart_data) = &response.chart_data {
-                        use crate::utils::generate_chart_image;
-                        // Генерируем изображение синхронно перед await
-                        let image_result = generate_chart_image(chart_data, 1000, 700);
-                        match image_result {
-                            Ok(image_bytes) => {
-                                let temp_path = std::env::temp_dir().join(format!("chart_{}.png", std::process::id()));
-                                if let Ok(_) = std::fs::write(&temp_path, &image_bytes) {
-                                    if let Err(e) = bot.send_photo(msg.chat.id, teloxide::types::InputFile::file(&temp_path))
-                                        .caption("📈 Визуализация данных")
-                                        .await {
-                                        tracing::error!("Failed to send chart image: {}", e);
-                                    }
-                                    let _ = std::fs::remove_file(&temp_path);
-                                }
-                            }
-                            Err(e) => {
-                                tracing::error!("Failed to generate chart image: {}", e);
-                            }
-                        }
-                    }
-                    
-                    // Отправляем текстовый ответ
-                    if let Some(text_response) = &response.text_response {
-                        bot.send_message(msg.chat.id, text_response)
-                            .parse_mode(teloxide::types::ParseMode::Html)
-                            .await?;
-                    } else {
-                        let formatted = crate::utils::format_query_response(&response);
-                        let keyboard = if let Some(analysis) = &response.analysis {
-                            if !analysis.suggested_questions.is_empty() {
-                                Some(crate::utils::create_suggestions_keyboard(&analysis.suggested_questions))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        };
-                        
-                        let mut message = bot.send_message(msg.chat.id, &formatted)
-                            .parse_mode(teloxide::types::ParseMode::Html);
-                        
-                        if let Some(kb) = keyboard {
-                            message = message.reply_markup(kb);
-                        }
-                        
-                        message.await?;
-                    }
+                    ResponseSender::new(&bot, &state, msg.chat.id, &user_id)
+                        .with_output_type(output_type)
+                        .send(progress, &response)
+                        .await?;
                 }
                 Err(e) => {
-                    // Удаляем сообщение "обрабатывается" даже при ошибке
-                    let _ = bot.delete_message(msg.chat.id, processing_msg.id).await;
-                    
                     tracing::error!("Error processing callback query: {}", e);
-                    bot.send_message(msg.chat.id, &format!("❌ Ошибка: {}", e))
-                        .parse_mode(teloxide::types::ParseMode::Html)
-                        .await?;
+                    progress.fail(&crate::utils::format_backend_error(lang, &e, tr(lang, Msg::QueryFailed))).await?;
                 }
             }
         }
@@ -248,9 +728,41 @@ async fn handle_callback(
 async fn handle_messages(
     bot: Bot,
     msg: Message,
-    api_client: Arc<ApiClient>,
+    state: Arc<BotState>,
 ) -> ResponseResult<()> {
-    handlers::handle_message(bot, msg, api_client).await?;
+    handlers::handle_message(bot, msg, state).await?;
     Ok(())
 }
 
+/// Пользователь исправил вопрос (например, опечатку): прежний ответ удаляется, а исправленный
+/// вопрос выполняется заново, и новый ответ помечается «обновлено». Сообщения, на которые бот
+/// не отвечал, и команды не перезапускаются.
+async fn handle_edited_message(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    if msg.text().is_none_or(|text| text.starts_with('/')) {
+        return Ok(());
+    }
+    let Some(previous) = state.answer_messages.take(msg.chat.id, msg.id).await else {
+        return Ok(());
+    };
+
+    info!("Question {} in chat {} was edited, re-running it", msg.id, msg.chat.id);
+    for message_id in previous {
+        // Сообщение могли уже удалить (сам пользователь или бот, заменив ход запроса подписью)
+        let _ = bot.delete_message(msg.chat.id, message_id).await;
+    }
+    handlers::handle_message(bot, msg, state).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_own_commands_are_answered_in_groups() {
+        assert!(is_command_for("/start", "data_bot"));
+        assert!(is_command_for("/ask@Data_Bot оборот", "data_bot"));
+        assert!(!is_command_for("/start@other_bot", "data_bot"));
+        assert!(!is_command_for("привет всем", "data_bot"));
+        assert!(!is_command_for("/", "data_bot"));
+    }
+}
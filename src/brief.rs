@@ -0,0 +1,214 @@
+use crate::api_client::QueryResponse;
+use crate::language::Language;
+use crate::numbers::localize_text;
+use crate::state::BotState;
+use crate::utils::{escape_html, fit_caption, fits_in_caption, format_insight, visible_insights, InsightLevel};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId, ParseMode};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Сколько ответов помним для переключения «Кратко / Подробно» и «Показать все выводы»
+const MAX_CACHED_ANSWERS: usize = 200;
+
+/// Префикс кнопки переключения: `brief:short` - показать кратко, `brief:full` - подробно
+const CALLBACK_PREFIX: &str = "brief:";
+
+/// Кнопка «📋 Показать все выводы»
+const EXPAND_CALLBACK: &str = "insights:all";
+
+/// Краткая версия ответа с анализом: заголовок и самый важный вывод (HTML).
+/// `None`, если анализа нет - сокращать нечего.
+pub fn brief_answer(response: &QueryResponse, lang: Language) -> Option<String> {
+    let analysis = response.analysis.as_ref()?;
+    let mut text = format!("📊 <b>{}</b>", escape_html(&localize_text(&analysis.headline, lang)));
+    if let Some(insight) = visible_insights(&analysis.insights, InsightLevel::Low).first() {
+        text.push_str("\n\n");
+        text.push_str(&format_insight(insight, lang));
+    }
+    Some(text)
+}
+
+/// Кнопка переключения: под полным ответом - «Кратко», под кратким - «Подробно»
+pub fn toggle_button(brief_shown: bool) -> InlineKeyboardButton {
+    if brief_shown {
+        InlineKeyboardButton::callback("📜 Подробно", format!("{}full", CALLBACK_PREFIX))
+    } else {
+        InlineKeyboardButton::callback("📝 Кратко", format!("{}short", CALLBACK_PREFIX))
+    }
+}
+
+/// Кнопка «Показать все выводы» под ответом со свернутыми выводами
+pub fn expand_button() -> InlineKeyboardButton {
+    InlineKeyboardButton::callback("📋 Показать все выводы", EXPAND_CALLBACK)
+}
+
+/// Версии текста ответа с анализом (HTML)
+#[derive(Debug, Clone)]
+pub struct Versions {
+    /// Полный ответ, как он отправлен
+    pub full: String,
+    /// Заголовок и главный вывод (кнопки «Кратко / Подробно»)
+    pub brief: Option<String>,
+    /// Полный ответ со всеми выводами, если в `full` часть выводов свернута
+    pub all_insights: Option<String>,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    answers: HashMap<(ChatId, MessageId), Arc<Versions>>,
+    order: VecDeque<(ChatId, MessageId)>,
+}
+
+/// Тексты отправленных ответов с анализом по сообщению, чтобы переключать их без запроса к бэкенду
+#[derive(Default)]
+pub struct AnswerVersions {
+    inner: Mutex<CacheInner>,
+}
+
+impl AnswerVersions {
+    pub async fn insert(&self, chat_id: ChatId, message_id: MessageId, versions: Versions) {
+        let mut inner = self.inner.lock().await;
+        if inner.answers.insert((chat_id, message_id), Arc::new(versions)).is_none() {
+            inner.order.push_back((chat_id, message_id));
+        }
+        while inner.order.len() > MAX_CACHED_ANSWERS {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.answers.remove(&oldest);
+            }
+        }
+    }
+
+    async fn get(&self, chat_id: ChatId, message_id: MessageId) -> Option<Arc<Versions>> {
+        self.inner.lock().await.answers.get(&(chat_id, message_id)).cloned()
+    }
+}
+
+/// Кнопки сообщения с кнопкой переключения в состоянии `brief_shown`; кнопка «Показать все выводы»
+/// остается, только если `keep_expand`. Остальные кнопки остаются прежними.
+fn switch_keyboard(markup: Option<&InlineKeyboardMarkup>, brief_shown: bool, keep_expand: bool) -> InlineKeyboardMarkup {
+    use teloxide::types::InlineKeyboardButtonKind;
+
+    let rows = markup.map(|markup| markup.inline_keyboard.clone()).unwrap_or_default();
+    let rows = rows.into_iter().map(|row| {
+        row.into_iter()
+            .filter_map(|button| match &button.kind {
+                InlineKeyboardButtonKind::CallbackData(data) if data.starts_with(CALLBACK_PREFIX) => Some(toggle_button(brief_shown)),
+                InlineKeyboardButtonKind::CallbackData(data) if data == EXPAND_CALLBACK => keep_expand.then_some(button),
+                _ => Some(button),
+            })
+            .collect::<Vec<_>>()
+    });
+    InlineKeyboardMarkup::new(rows.filter(|row| !row.is_empty()).collect::<Vec<_>>())
+}
+
+/// Заменяет текст ответа (или подпись, если ответ пришел подписью к диаграмме или файлу)
+async fn edit_answer(bot: &Bot, msg: &Message, text: &str, keyboard: InlineKeyboardMarkup) {
+    let edited = if msg.caption().is_some() {
+        bot.edit_message_caption(msg.chat.id, msg.id)
+            .caption(fit_caption(text))
+            .parse_mode(ParseMode::Html)
+            .reply_markup(keyboard)
+            .await
+            .map(drop)
+    } else {
+        bot.edit_message_text(msg.chat.id, msg.id, text)
+            .parse_mode(ParseMode::Html)
+            .reply_markup(keyboard)
+            .await
+            .map(drop)
+    };
+    // Повторное нажатие (текст не изменился) Telegram отклоняет - это не ошибка
+    if let Err(e) = edited {
+        warn!("Failed to switch answer version: {}", e);
+    }
+}
+
+/// Версии ответа на сообщение `msg`; если они уже не сохранены, сообщает об этом в чат
+async fn versions(bot: &Bot, msg: &Message, state: &BotState) -> ResponseResult<Option<Arc<Versions>>> {
+    let versions = state.answer_versions.get(msg.chat.id, msg.id).await;
+    if versions.is_none() {
+        bot.send_message(msg.chat.id, "⌛ Текст этого ответа уже не сохранен, повторите запрос")
+            .await?;
+    }
+    Ok(versions)
+}
+
+/// Кнопки «📝 Кратко» (`brief:short`) и «📜 Подробно» (`brief:full`): заменяют текст ответа сохраненной версией
+pub async fn handle_callback(bot: Bot, msg: Message, action: &str, state: Arc<BotState>) -> ResponseResult<()> {
+    let Some(versions) = versions(&bot, &msg, &state).await? else {
+        return Ok(());
+    };
+
+    let brief_shown = action == "short";
+    let text = match (&versions.brief, brief_shown) {
+        (Some(brief), true) => brief,
+        _ => &versions.full,
+    };
+    // В краткой версии выводов нет - разворачивать нечего
+    let keyboard = switch_keyboard(msg.reply_markup(), brief_shown, !brief_shown && versions.all_insights.is_some());
+    edit_answer(&bot, &msg, text, keyboard).await;
+    Ok(())
+}
+
+/// Кнопка «📋 Показать все выводы» (`insights:all`): полный ответ со всеми выводами. Подпись,
+/// в которую все выводы не помещаются, остается прежней, а ответ со всеми выводами приходит
+/// отдельным сообщением.
+pub async fn handle_expand_callback(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    let Some(versions) = versions(&bot, &msg, &state).await? else {
+        return Ok(());
+    };
+    let Some(all_insights) = versions.all_insights.clone() else {
+        return Ok(());
+    };
+
+    let keyboard = switch_keyboard(msg.reply_markup(), false, false);
+    if msg.caption().is_some() && !fits_in_caption(&all_insights) {
+        let _ = bot.edit_message_reply_markup(msg.chat.id, msg.id).reply_markup(keyboard).await;
+        bot.send_message(msg.chat.id, all_insights)
+            .parse_mode(ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    edit_answer(&bot, &msg, &all_insights, keyboard).await;
+    // «Подробно» теперь показывает ответ со всеми выводами
+    let expanded = Versions { full: all_insights, all_insights: None, ..(*versions).clone() };
+    state.answer_versions.insert(msg.chat.id, msg.id, expanded).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn brief_answer_keeps_headline_and_most_important_insight() {
+        let response: QueryResponse = serde_json::from_value(json!({
+            "question": "Динамика оборота",
+            "data": [],
+            "execution_time_ms": 5,
+            "row_count": 0,
+            "analysis": {
+                "headline": "Оборот вырос на 12%",
+                "insights": [
+                    {"title": "Выходные", "description": "По субботам меньше", "significance": "Low"},
+                    {"title": "Almaty", "description": "Половина роста", "significance": "High"},
+                    {"title": "Astana", "description": "Четверть роста", "significance": "High"},
+                ],
+                "explanation": "Подробное объяснение",
+                "suggested_questions": [],
+            },
+        }))
+        .unwrap();
+
+        let brief = brief_answer(&response, Language::Ru).unwrap();
+        assert!(brief.starts_with("📊 <b>Оборот вырос на 12%</b>"));
+        assert!(brief.contains("🔴 <b>Almaty</b>\nПоловина роста"));
+        assert!(!brief.contains("Astana") && !brief.contains("Выходные") && !brief.contains("объяснение"));
+    }
+}
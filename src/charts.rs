@@ -0,0 +1,160 @@
+use crate::api_client::ChartData;
+use crate::language::Language;
+use crate::timezone::UserZone;
+use crate::utils::ChartTheme;
+use std::collections::{HashMap, VecDeque};
+use teloxide::types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup, MessageId};
+use std::time::Instant;
+use tokio::sync::{Mutex, Semaphore};
+
+/// Сколько отправленных диаграмм помним для перерисовки
+const MAX_CACHED_CHARTS: usize = 500;
+
+/// DejaVu Sans, встроенный в бинарник: в минимальных образах Docker системных шрифтов
+/// с кириллицей может не быть, и подписи превращаются в квадраты
+const FONT_REGULAR: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+const FONT_BOLD: &[u8] = include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf");
+
+/// Регистрирует встроенный шрифт в plotters под всеми именами, которые используют диаграммы
+/// и картинки таблиц. Системные шрифты plotters не ищет.
+fn register_fonts() {
+    use plotters::style::{register_font, FontStyle};
+
+    static REGISTER: std::sync::Once = std::sync::Once::new();
+    REGISTER.call_once(|| {
+        for name in [crate::utils::CHART_FONT, "sans-serif"] {
+            for (style, bytes) in [(FontStyle::Normal, FONT_REGULAR), (FontStyle::Bold, FONT_BOLD)] {
+                if register_font(name, style, bytes).is_err() {
+                    tracing::error!("Embedded chart font {} ({}) is invalid", name, style.as_str());
+                }
+            }
+        }
+    });
+}
+
+/// Типы диаграмм, на которые можно переключиться кнопками
+pub const CHART_TYPES: &[(&str, &str)] = &[("bar", "📊 Bar"), ("line", "📈 Line"), ("pie", "🥧 Pie")];
+
+/// Кнопка логарифмической шкалы (`chart:log`)
+pub const LOG_SCALE_OPTION: &str = "log";
+
+/// Кнопки вида диаграммы: тип (текущий отмечен галочкой) и логарифмическая шкала,
+/// которая есть у всех типов, кроме круговых
+pub fn chart_options_keyboard(chart: &ChartData) -> InlineKeyboardMarkup {
+    let checked = |label: &str, selected: bool| if selected { format!("✅ {}", label) } else { label.to_string() };
+    let current = chart.chart_type.to_lowercase();
+    let mut row: Vec<InlineKeyboardButton> = CHART_TYPES
+        .iter()
+        .map(|(chart_type, label)| {
+            InlineKeyboardButton::callback(checked(label, current == *chart_type), format!("chart:{}", chart_type))
+        })
+        .collect();
+    if !matches!(current.as_str(), "pie" | "donut") {
+        row.push(InlineKeyboardButton::callback(
+            checked("🔢 Log scale", chart.log_scale),
+            format!("chart:{}", LOG_SCALE_OPTION),
+        ));
+    }
+    InlineKeyboardMarkup::new(vec![row])
+}
+
+#[derive(Default)]
+struct CacheInner {
+    charts: HashMap<(ChatId, MessageId), ChartData>,
+    order: VecDeque<(ChatId, MessageId)>,
+}
+
+/// Данные отправленных диаграмм по сообщению, чтобы перерисовать их без запроса к бэкенду
+#[derive(Default)]
+pub struct ChartCache {
+    inner: Mutex<CacheInner>,
+}
+
+impl ChartCache {
+    pub async fn insert(&self, chat_id: ChatId, message_id: MessageId, chart: ChartData) {
+        let mut inner = self.inner.lock().await;
+        if inner.charts.insert((chat_id, message_id), chart).is_none() {
+            inner.order.push_back((chat_id, message_id));
+        }
+        while inner.order.len() > MAX_CACHED_CHARTS {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.charts.remove(&oldest);
+            }
+        }
+    }
+
+    pub async fn get(&self, chat_id: ChatId, message_id: MessageId) -> Option<ChartData> {
+        self.inner.lock().await.charts.get(&(chat_id, message_id)).cloned()
+    }
+}
+
+/// Рисует диаграммы в пуле блокирующих потоков, не занимая потоки tokio.
+/// Семафор ограничивает число одновременных отрисовок, чтобы большие диаграммы
+/// не съели все ядра.
+pub struct ChartRenderer {
+    permits: Semaphore,
+    /// Тема, если пользователь не выбрал свою (`CHART_THEME`)
+    default_theme: ChartTheme,
+    /// Имя бота для подписи в углу диаграммы
+    bot_username: String,
+}
+
+impl ChartRenderer {
+    pub fn new(max_concurrent: usize, default_theme: ChartTheme, bot_username: &str) -> Self {
+        register_fonts();
+        Self {
+            permits: Semaphore::new(max_concurrent.max(1)),
+            default_theme,
+            bot_username: bot_username.to_string(),
+        }
+    }
+
+    /// PNG диаграммы размером `width`×`height` с подписями на языке `lang`
+    /// в теме `theme` (`None` - тема по умолчанию); время в подписи - в поясе `zone`
+    pub async fn render(
+        &self,
+        chart_data: &ChartData,
+        width: u32,
+        height: u32,
+        lang: Language,
+        theme: Option<ChartTheme>,
+        zone: UserZone,
+    ) -> anyhow::Result<Vec<u8>> {
+        let _permit = self.permits.acquire().await?;
+        let chart_data = chart_data.clone();
+        let theme = theme.unwrap_or(self.default_theme);
+        let footer = self.footer(zone);
+        let started = Instant::now();
+        let result = tokio::task::spawn_blocking(move || {
+            crate::utils::generate_chart_image(&chart_data, width, height, lang, theme, &footer)
+                .map_err(|e| anyhow::anyhow!(e))
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+        crate::metrics::METRICS.record_chart_render(started.elapsed(), result.is_ok());
+        result
+    }
+
+    pub fn default_theme(&self) -> ChartTheme {
+        self.default_theme
+    }
+
+    /// Подпись в углу диаграммы: `@bot · 16.10.2026 17:30 Asia/Almaty` (время в поясе `zone`)
+    fn footer(&self, zone: UserZone) -> String {
+        let time = zone.stamp(chrono::Utc::now(), "%d.%m.%Y %H:%M");
+        if self.bot_username.is_empty() {
+            time
+        } else {
+            format!("@{} · {}", self.bot_username, time)
+        }
+    }
+
+    /// PNG таблицы результата (см. `table_image`); рисуется в том же пуле, что и диаграммы
+    pub async fn render_table(&self, data: &[serde_json::Value], lang: Language) -> anyhow::Result<Vec<u8>> {
+        let _permit = self.permits.acquire().await?;
+        let data = data.to_vec();
+        tokio::task::spawn_blocking(move || crate::table_image::render(&data, lang).map_err(|e| anyhow::anyhow!(e)))
+            .await?
+    }
+}
@@ -0,0 +1,103 @@
+use crate::utils::edit_distance;
+use teloxide::utils::command::BotCommands;
+
+/// Команды бота. Описания попадают в меню команд Telegram (`set_my_commands`);
+/// служебные команды администраторов скрыты (`description = "off"`).
+#[derive(BotCommands, Clone, Debug, PartialEq)]
+#[command(rename_rule = "lowercase")]
+pub enum Command {
+    #[command(description = "Начать работу с ботом")]
+    Start(String),
+    #[command(description = "Справка и примеры запросов")]
+    Help,
+    #[command(description = "Главное меню с популярными запросами")]
+    Menu,
+    #[command(description = "Примеры вопросов по темам")]
+    Examples,
+    #[command(description = "Какие данные есть: таблицы и поля")]
+    Schema(String),
+    #[command(description = "Очистить контекст запросов")]
+    Clear,
+    #[command(description = "Состояние бэкенда")]
+    Status,
+    #[command(description = "Проверить время ответа бэкенда")]
+    Ping,
+    #[command(description = "Запрос к данным (без перехода в чат)")]
+    Sql(String),
+    #[command(description = "Сравнить два периода: /compare вопрос | период 1 | период 2")]
+    Compare(String),
+    #[command(description = "Свободный вопрос ассистенту")]
+    Chat(String),
+    #[command(description = "Режим по умолчанию: auto, sql или chat")]
+    Mode(String),
+    #[command(description = "Настройки: вложения CSV и другие")]
+    Settings(String),
+    #[command(description = "Регулярные отчеты по расписанию")]
+    Schedule(String),
+    #[command(description = "Оповещение о пороге: /alert \"вопрос\" > 1000000 every 15m")]
+    Alert(String),
+    #[command(description = "Оповещения чата")]
+    Alerts(String),
+    #[command(description = "Подписать чат на уведомления: /subscribe anomalies")]
+    Subscribe(String),
+    #[command(description = "Отписать чат от уведомлений")]
+    Unsubscribe(String),
+    #[command(description = "Язык интерфейса / Interface language / Интерфейс тілі")]
+    Language,
+    #[command(description = "Язык ответов: ru, en, kk или auto")]
+    Answerlang(String),
+    #[command(description = "Валюта сумм в ответах: KZT, USD или EUR")]
+    Currency(String),
+    #[command(description = "Часовой пояс: /timezone Asia/Almaty или auto")]
+    Timezone(String),
+    #[command(description = "Привязать персональный токен бэкенда")]
+    Login(String),
+    #[command(description = "Удалить персональный токен")]
+    Logout,
+    #[command(description = "Последние запросы с повтором")]
+    History(String),
+    #[command(description = "История запросов файлом")]
+    Transcript(String),
+    #[command(description = "Сохранить запрос: /save название | вопрос")]
+    Save(String),
+    #[command(description = "Сохраненные запросы")]
+    Saved,
+    #[command(description = "Шаблоны запросов: /template top_cities топ {n} городов за {period}")]
+    Template(String),
+    #[command(description = "Выполнить шаблон: /t top_cities n=5 period=неделя")]
+    T(String),
+    #[command(description = "Выгрузить последний результат: /export csv, xlsx, json или parquet")]
+    Export(String),
+    #[command(description = "Удалить все мои данные")]
+    Forgetme,
+    #[command(description = "off")]
+    Allow(String),
+    #[command(description = "off")]
+    Deny(String),
+    #[command(description = "off")]
+    Cache(String),
+    #[command(description = "off")]
+    Stats,
+    #[command(description = "off")]
+    Env(String),
+    #[command(description = "off")]
+    Debug(String),
+    #[command(rename = "menu_add", description = "off")]
+    MenuAdd(String),
+    #[command(rename = "menu_remove", description = "off")]
+    MenuRemove(String),
+    #[command(rename = "menu_list", description = "off")]
+    MenuList,
+}
+
+/// Ближайшая по написанию известная команда (для подсказки при опечатке)
+pub fn suggest_command(unknown: &str) -> Option<String> {
+    let unknown = unknown.trim_start_matches('/').to_lowercase();
+    Command::bot_commands()
+        .into_iter()
+        .map(|command| command.command.trim_start_matches('/').to_string())
+        .map(|command| (edit_distance(&unknown, &command), command))
+        .filter(|(distance, command)| *distance <= 2 || command.starts_with(&unknown))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, command)| format!("/{}", command))
+}
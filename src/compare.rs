@@ -0,0 +1,379 @@
+use crate::api_client::{ChartData, ChartDataset, OutputType, QueryRequest};
+use crate::handlers::{answer_language, reject_if_backend_down, reject_if_rate_limited, send_chart, start_unless_running};
+use crate::i18n::{tr, Msg};
+use crate::language::Language;
+use crate::numbers::{column_header, format_change, format_number, format_percent_change};
+use crate::progress::{Progress, Stage};
+use crate::query_parser::{detect_date_range, DateRange};
+use crate::state::BotState;
+use crate::utils::{collect_columns, escape_html, format_backend_error, format_table};
+use chrono::NaiveDate;
+use serde_json::Value;
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+use tracing::error;
+
+/// Больше строк в таблице сравнения не показывается
+const MAX_TABLE_ROWS: usize = 30;
+
+/// Больше категорий на диаграмме сравнения не рисуется
+const MAX_CHART_GROUPS: usize = 15;
+
+const USAGE: &str = "⚠️ Использование: <code>/compare вопрос | период 1 | период 2</code>\n\nНапример: <code>/compare оборот по городам | прошлый месяц | этот месяц</code>";
+
+/// Разобранная команда `/compare`: вопрос и два периода с подписями, как их написал пользователь
+#[derive(Debug, PartialEq)]
+struct CompareRequest {
+    question: String,
+    periods: [(String, DateRange); 2],
+}
+
+/// `вопрос | период 1 | период 2`; ошибка - текст для пользователя (HTML)
+fn parse_request(arg: &str, today: NaiveDate) -> Result<CompareRequest, String> {
+    let parts: Vec<&str> = arg.split('|').map(str::trim).collect();
+    let [question, first, second] = parts[..] else {
+        return Err(USAGE.to_string());
+    };
+    if question.is_empty() || first.is_empty() || second.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let period = |text: &str| {
+        detect_date_range(text, today).map(|range| (text.to_string(), range)).ok_or_else(|| {
+            format!(
+                "⚠️ Не понял период «{}». Примеры: <code>вчера</code>, <code>прошлая неделя</code>, <code>март 2024</code>, <code>01.03.2024-15.03.2024</code>",
+                escape_html(text)
+            )
+        })
+    };
+    Ok(CompareRequest { question: question.to_string(), periods: [period(first)?, period(second)?] })
+}
+
+/// Строка сравнения: значения показателя за оба периода (`None` - строки нет в периоде)
+#[derive(Debug, PartialEq)]
+struct ComparedRow {
+    key: String,
+    metric: String,
+    values: [Option<f64>; 2],
+}
+
+impl ComparedRow {
+    /// Изменение второго периода относительно первого; отсутствующая строка считается нулем
+    fn delta(&self) -> f64 {
+        self.values[1].unwrap_or(0.0) - self.values[0].unwrap_or(0.0)
+    }
+
+    /// Изменение в процентах; `None`, если в первом периоде значения нет или оно нулевое
+    fn percent(&self) -> Option<f64> {
+        self.values[0].filter(|first| *first != 0.0).map(|first| self.delta() / first.abs() * 100.0)
+    }
+}
+
+/// Результат сравнения: строки по ключу (значениям нечисловых колонок) и показателю
+#[derive(Debug)]
+struct Comparison {
+    /// Нечисловые колонки, по которым сопоставляются строки периодов
+    key_columns: Vec<String>,
+    /// Числовые колонки
+    metrics: Vec<String>,
+    rows: Vec<ComparedRow>,
+}
+
+/// Сопоставляет строки двух периодов по значениям нечисловых колонок («город», «мерчант») и
+/// считает изменение каждой числовой колонки. Порядок строк - как в первом периоде, затем
+/// строки, которые есть только во втором.
+fn compare(first: &[Value], second: &[Value]) -> Comparison {
+    let both: Vec<Value> = first.iter().chain(second).cloned().collect();
+    let columns = collect_columns(&both);
+    let (metrics, key_columns): (Vec<String>, Vec<String>) = columns.into_iter().partition(|column| {
+        let mut values = both.iter().filter_map(|row| row.get(column)).filter(|value| !value.is_null()).peekable();
+        values.peek().is_some() && values.all(Value::is_number)
+    });
+
+    let key = |row: &Value| {
+        key_columns
+            .iter()
+            .map(|column| match row.get(column) {
+                Some(Value::String(text)) => text.clone(),
+                None | Some(Value::Null) => "—".to_string(),
+                Some(value) => value.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" · ")
+    };
+    let mut keys: Vec<String> = Vec::new();
+    for row in first.iter().chain(second) {
+        let key = key(row);
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    // Первая строка с таким ключом; дубликаты ключа (если колонки не различают строки) пропускаются
+    let value = |rows: &[Value], key_value: &str, metric: &str| {
+        rows.iter().find(|row| key(row) == key_value).and_then(|row| row.get(metric)).and_then(Value::as_f64)
+    };
+
+    let rows = keys
+        .iter()
+        .flat_map(|key| {
+            metrics.iter().map(move |metric| ComparedRow {
+                key: key.clone(),
+                metric: metric.clone(),
+                values: [value(first, key, metric), value(second, key, metric)],
+            })
+        })
+        .collect();
+    Comparison { key_columns, metrics, rows }
+}
+
+/// Период для заголовка: `01.03.2024` или `01.03.2024 – 31.03.2024`
+fn describe_range(range: DateRange) -> String {
+    if range.from == range.to {
+        return range.from.format("%d.%m.%Y").to_string();
+    }
+    format!("{} – {}", range.from.format("%d.%m.%Y"), range.to.format("%d.%m.%Y"))
+}
+
+/// Текст ответа: периоды и таблица «ключ | период 1 | период 2 | Δ | Δ %» (HTML)
+fn format_comparison(request: &CompareRequest, comparison: &Comparison, lang: Language) -> String {
+    let [(first_label, first), (second_label, second)] = &request.periods;
+    let mut text = format!(
+        "⚖️ <b>Сравнение периодов</b>\n<i>{}</i>\n\n1️⃣ {}: {}\n2️⃣ {}: {}\n\n",
+        escape_html(&request.question),
+        escape_html(first_label),
+        describe_range(*first),
+        escape_html(second_label),
+        describe_range(*second),
+    );
+
+    // Колонка ключа не нужна для одной итоговой строки, колонка показателя - для одного показателя
+    let with_key = !comparison.key_columns.is_empty();
+    let with_metric = comparison.metrics.len() > 1 || !with_key;
+    let mut headers = Vec::new();
+    if with_key {
+        headers.push(comparison.key_columns.join(" / "));
+    }
+    if with_metric {
+        headers.push("показатель".to_string());
+    }
+    headers.extend([first_label.clone(), second_label.clone(), "Δ".to_string(), "Δ %".to_string()]);
+    let numeric: Vec<bool> = headers.iter().enumerate().map(|(i, _)| i >= headers.len() - 4).collect();
+
+    let value = |value: Option<f64>| value.map_or_else(|| "—".to_string(), |value| format_number(value, lang));
+    let rows: Vec<Vec<String>> = comparison
+        .rows
+        .iter()
+        .take(MAX_TABLE_ROWS)
+        .map(|row| {
+            let mut cells = Vec::new();
+            if with_key {
+                cells.push(row.key.clone());
+            }
+            if with_metric {
+                cells.push(column_header(&row.metric, true));
+            }
+            cells.extend([
+                value(row.values[0]),
+                value(row.values[1]),
+                format_change(row.delta(), lang),
+                row.percent().map_or_else(|| "—".to_string(), |percent| format_percent_change(percent, lang)),
+            ]);
+            cells
+        })
+        .collect();
+    text.push_str(&format_table(&headers, &rows, &numeric));
+    if comparison.rows.len() > MAX_TABLE_ROWS {
+        text.push_str(&format!("<i>… и еще строк: {}</i>\n", comparison.rows.len() - MAX_TABLE_ROWS));
+    }
+    text
+}
+
+/// Столбцы обоих периодов рядом по первому показателю; без ключевых колонок - по всем показателям
+fn comparison_chart(request: &CompareRequest, comparison: &Comparison) -> Option<ChartData> {
+    let first_metric = comparison.metrics.first()?;
+    let rows: Vec<&ComparedRow> = comparison
+        .rows
+        .iter()
+        .filter(|row| comparison.key_columns.is_empty() || row.metric == *first_metric)
+        .take(MAX_CHART_GROUPS)
+        .collect();
+    let labels = rows
+        .iter()
+        .map(|row| if comparison.key_columns.is_empty() { row.metric.clone() } else { row.key.clone() })
+        .collect();
+    let datasets = request
+        .periods
+        .iter()
+        .enumerate()
+        .map(|(i, (label, _))| ChartDataset {
+            label: label.clone(),
+            data: rows.iter().map(|row| row.values[i].unwrap_or(0.0)).collect(),
+            background_color: None,
+        })
+        .collect();
+    let title = match comparison.key_columns.is_empty() {
+        true => request.question.clone(),
+        false => column_header(first_metric, true),
+    };
+    Some(ChartData { chart_type: "bar".to_string(), labels, datasets, title: Some(title), log_scale: false })
+}
+
+/// `/compare вопрос | период 1 | период 2` - один вопрос за два периода с изменениями и диаграммой
+pub async fn handle_compare(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    let lang = state.ui_language(&user_id, msg.from()).await;
+    let request = match parse_request(arg, state.user_zone(&user_id).await.today()) {
+        Ok(request) => request,
+        Err(text) => {
+            bot.send_message(msg.chat.id, text)
+                .parse_mode(ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if reject_if_backend_down(&bot, &msg, &state, lang).await? || reject_if_rate_limited(&bot, &msg, &state, lang).await? {
+        return Ok(());
+    }
+    run_compare(&bot, &msg, &state, &user_id, &request, lang).await
+}
+
+/// Выполняет вопрос за оба периода одновременно и отправляет сравнение
+async fn run_compare<S: crate::messenger::MessageSender>(
+    bot: &S,
+    msg: &Message,
+    state: &BotState,
+    user_id: &str,
+    request: &CompareRequest,
+    lang: Language,
+) -> ResponseResult<()> {
+    let dedup_key = format!("compare: {} | {} | {}", request.question, request.periods[0].0, request.periods[1].0);
+    let Some(_running) = start_unless_running(bot, msg, state, lang, &dedup_key).await? else {
+        return Ok(());
+    };
+    let progress = Progress::start(bot, msg, state, lang).await?;
+
+    let language = answer_language(state, user_id, None).await;
+    let zone = state.user_zone(user_id).await;
+    let query = |range: DateRange| QueryRequest {
+        question: request.question.clone(),
+        include_analysis: false,
+        use_cache: true,
+        include_sql: false,
+        user_id: Some(user_id.to_string()),
+        output_type: OutputType::Table,
+        language: language.clone(),
+        date_from: Some(range.from),
+        date_to: Some(range.to),
+        timezone: Some(zone.name()),
+    };
+    let api = state.api(user_id).await;
+    let [(_, first), (_, second)] = &request.periods;
+    let (first, second) = progress
+        .run(async { tokio::join!(api.query(query(*first)), api.query(query(*second))) })
+        .await;
+    let (first, second) = match (first, second) {
+        (Ok(first), Ok(second)) => (first, second),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Error comparing periods for user {}: {}", user_id, e);
+            return progress.fail(&format_backend_error(lang, &e, tr(lang, Msg::QueryFailed))).await;
+        }
+    };
+
+    let comparison = compare(&first.data, &second.data);
+    if comparison.metrics.is_empty() {
+        return progress
+            .fail("📭 В ответе нет чисел, которые можно сравнить. Спросите о сумме, количестве или среднем.")
+            .await;
+    }
+
+    let chart = comparison_chart(request, &comparison);
+    if chart.is_some() {
+        progress.stage(Stage::DrawingChart).await;
+    }
+    let question = progress.question();
+    progress.finish(state, &format_comparison(request, &comparison, lang), None).await?;
+    if let Some(chart) = chart {
+        let settings = state.storage.settings(user_id).await;
+        if let Some(sent) = send_chart(bot, msg.chat.id, state, &chart, lang, &settings, None).await {
+            state.answer_messages.add(msg.chat.id, question, sent).await;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::testing::FakeBackend;
+    use crate::api_client::QueryResponse;
+    use crate::messenger::testing::RecordingSender;
+    use serde_json::json;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 4, 10).unwrap()
+    }
+
+    #[test]
+    fn request_needs_question_and_two_periods() {
+        let request = parse_request("оборот по городам | март 2024 | прошлая неделя", today()).unwrap();
+        assert_eq!(request.question, "оборот по городам");
+        assert_eq!(request.periods[0].1.from, NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+        assert_eq!(request.periods[0].1.to, NaiveDate::from_ymd_opt(2024, 3, 31).unwrap());
+        assert_eq!(request.periods[1].0, "прошлая неделя");
+
+        assert_eq!(parse_request("оборот | март 2024", today()).unwrap_err(), USAGE);
+        assert!(parse_request("оборот | когда-нибудь | март 2024", today()).unwrap_err().contains("«когда-нибудь»"));
+    }
+
+    #[test]
+    fn rows_are_matched_by_key_columns() {
+        let first = [json!({"city": "Almaty", "amount": 100.0}), json!({"city": "Astana", "amount": 50.0})];
+        let second = [json!({"city": "Astana", "amount": 75.0}), json!({"city": "Shymkent", "amount": 20.0})];
+
+        let comparison = compare(&first, &second);
+        assert_eq!(comparison.key_columns, ["city"]);
+        assert_eq!(comparison.metrics, ["amount"]);
+        let rows: Vec<_> = comparison.rows.iter().map(|row| (row.key.as_str(), row.values, row.delta(), row.percent())).collect();
+        assert_eq!(rows, [
+            ("Almaty", [Some(100.0), None], -100.0, Some(-100.0)),
+            ("Astana", [Some(50.0), Some(75.0)], 25.0, Some(50.0)),
+            ("Shymkent", [None, Some(20.0)], 20.0, None),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn comparison_is_sent_with_grouped_chart() {
+        let response = |amount: f64| -> QueryResponse {
+            serde_json::from_value(json!({
+                "question": "оборот",
+                "data": [{"total_amount": amount, "count": 10}],
+                "execution_time_ms": 5,
+                "row_count": 1,
+            }))
+            .unwrap()
+        };
+        let backend = Arc::new(FakeBackend::new([response(200.0), response(200.0)]));
+        let state = BotState::with_backend("compare", backend.clone()).await;
+        let msg: Message = serde_json::from_value(json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": {"id": 42, "type": "private", "first_name": "Test"},
+            "from": {"id": 42, "is_bot": false, "first_name": "Test"},
+            "text": "/compare оборот | март 2024 | апрель 2024",
+        }))
+        .unwrap();
+        let request = parse_request("оборот | март 2024 | апрель 2024", today()).unwrap();
+        let sender = RecordingSender::default();
+
+        run_compare(&sender, &msg, &state, "42", &request, Language::Ru).await.unwrap();
+
+        assert_eq!(backend.questions(), ["оборот", "оборот"]);
+        let sent = sender.take();
+        let answer = sent.iter().find(|sent| sent.method == "edit_message" && sent.text.contains("Сравнение")).unwrap();
+        assert!(answer.text.contains("01.03.2024 – 31.03.2024"));
+        assert!(answer.text.contains("total_amount, ₸"));
+        assert_eq!(sent.last().unwrap().method, "send_photo");
+    }
+}
@@ -1,20 +1,682 @@
 use anyhow::{Context, Result};
+use std::collections::HashMap;
 use std::env;
+use std::str::FromStr;
+use teloxide::types::{ChatId, UserId};
+
+/// Как разделяется контекст запросов на бэкенде
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextScope {
+    /// Один контекст на чат (в группах общий для всех участников)
+    Chat,
+    /// Отдельный контекст для каждого пользователя, во всех чатах общий
+    User,
+    /// Отдельный контекст для каждого пользователя в каждом чате
+    ChatUser,
+}
+
+impl ContextScope {
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "chat" => Ok(Self::Chat),
+            "user" => Ok(Self::User),
+            "chat_user" => Ok(Self::ChatUser),
+            other => anyhow::bail!("CONTEXT_SCOPE must be one of chat, user, chat_user (got {:?})", other),
+        }
+    }
+
+    /// Ключ контекста, передаваемый бэкенду как `user_id`
+    pub fn key(&self, chat_id: ChatId, user_id: Option<UserId>) -> String {
+        match (self, user_id) {
+            (Self::User, Some(user_id)) => user_id.to_string(),
+            (Self::ChatUser, Some(user_id)) if chat_id.0 != user_id.0 as i64 => {
+                format!("{}:{}", chat_id, user_id)
+            }
+            // Личный чат или отправитель неизвестен (например, сообщение канала)
+            _ => chat_id.to_string(),
+        }
+    }
+}
+
+/// Способ получения обновлений от Telegram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotMode {
+    /// Long polling (по умолчанию, удобно для локального запуска)
+    Polling,
+    /// Webhook: Telegram сам присылает обновления на `WEBHOOK_URL`
+    Webhook,
+}
+
+impl BotMode {
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "polling" => Ok(Self::Polling),
+            "webhook" => Ok(Self::Webhook),
+            other => anyhow::bail!("BOT_MODE must be one of polling, webhook (got {:?})", other),
+        }
+    }
+}
+
+/// Разметка текстовых ответов бэкенда
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextFormat {
+    /// HTML-ответы отправляются как есть, Markdown - через MarkdownV2, остальное - экранированным HTML
+    Auto,
+    /// Markdown из ответа переводится в HTML
+    Html,
+    /// Ответ отправляется в MarkdownV2 (кроме ответов, уже размеченных HTML)
+    MarkdownV2,
+}
+
+impl TextFormat {
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "html" => Ok(Self::Html),
+            "markdown" | "markdownv2" => Ok(Self::MarkdownV2),
+            other => anyhow::bail!("TEXT_FORMAT must be one of auto, html, markdown (got {:?})", other),
+        }
+    }
+}
+
+/// Дополнительный бэкенд из `BACKENDS`, на который администратор может переключиться через `/env`
+#[derive(Debug, Clone)]
+pub struct NamedBackend {
+    pub name: String,
+    pub url: String,
+    /// `BACKEND_API_KEY_<NAME>`, иначе общий `BACKEND_API_KEY`
+    pub api_key: Option<String>,
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub telegram_token: String,
     pub backend_url: String,
+    /// Сервисный ключ бэкенда (`Authorization: Bearer ...`)
+    pub backend_api_key: Option<String>,
+    /// Имя основного бэкенда (`BACKEND_URL`) в `/env`
+    pub backend_name: String,
+    /// Дополнительные бэкенды, например с тестовыми данными
+    pub extra_backends: Vec<NamedBackend>,
+    pub storage_path: String,
+    pub token_encryption_key: Option<String>,
+    pub web_dashboard_url: Option<String>,
+    pub handoff_secret: Option<String>,
+    /// Срок хранения истории и выгрузок в днях (0 - хранить бессрочно)
+    pub retention_days: u32,
+    /// Чат для служебных уведомлений (падение/восстановление бэкенда)
+    pub admin_chat_id: Option<i64>,
+    pub health_check_interval_secs: u64,
+    pub context_scope: ContextScope,
+    /// Порог оценки строк, выше которого запрос требует подтверждения
+    pub estimate_confirm_rows: u64,
+    /// Максимум сообщений на ответ, дальше ответ отправляется файлом
+    pub max_message_chunks: usize,
+    pub bot_mode: BotMode,
+    /// Публичный адрес, на который Telegram отправляет обновления (режим webhook)
+    pub webhook_url: Option<String>,
+    /// Локальный порт, который слушает webhook-сервер
+    pub webhook_port: u16,
+    /// Кому разрешен доступ помимо сохраненного списка (пусто - без ограничений)
+    pub allowed_user_ids: Vec<u64>,
+    pub allowed_chat_ids: Vec<i64>,
+    /// Пользователи, которым доступны `/allow` и `/deny`
+    pub admin_user_ids: Vec<u64>,
+    pub allowlist_path: String,
+    /// Сколько запросов в минуту разрешено одному чату (0 - без ограничения)
+    pub rate_limit_per_minute: u32,
+    /// Сколько запросов можно отправить подряд, прежде чем сработает ограничение
+    pub rate_limit_burst: u32,
+    /// Сколько секунд повторный вопрос обслуживается из кэша бота (0 - кэш выключен)
+    pub response_cache_ttl_secs: u64,
+    /// Файл кэша ответов (`None` - только в памяти)
+    pub response_cache_path: Option<String>,
+    /// Через сколько минут без сообщений диалог с `/api/chat` начинается заново
+    pub chat_session_ttl_mins: u32,
+    /// Сколько секунд ждать ответа бэкенда, прежде чем сообщить о превышении времени
+    pub backend_timeout_secs: u64,
+    /// Сколько запросов к бэкенду может выполняться одновременно
+    pub max_concurrent_backend_requests: usize,
+    /// Получать ответы на вопросы по частям через `/api/query/stream`
+    pub backend_streaming: bool,
+    /// Максимальный размер файла CSV/XLSX для сравнения, МБ
+    pub max_upload_mb: u32,
+    /// Порт HTTP-сервера с `/metrics` для Prometheus (`None` - метрики не публикуются)
+    pub metrics_port: Option<u16>,
+    /// Порт HTTP-сервера для событий бэкенда (`POST /api/anomalies`; `None` - сервер не запускается)
+    pub notify_port: Option<u16>,
+    /// Токен, которым бэкенд подписывает события (`Authorization: Bearer ...`)
+    pub notify_secret: Option<String>,
+    /// Сколько секунд при остановке ждать завершения начатых запросов
+    pub shutdown_timeout_secs: u64,
+    /// Сколько диаграмм может рисоваться одновременно
+    pub chart_render_concurrency: usize,
+    /// Часовой пояс расписания `/schedule` (смещение от UTC в часах)
+    pub schedule_utc_offset_hours: i32,
+    /// Разметка текстовых ответов бэкенда
+    pub text_format: TextFormat,
+    /// Тема диаграмм для пользователей, не выбравших свою
+    pub chart_theme: crate::utils::ChartTheme,
+    /// TTF-шрифт с кириллицей для PDF-отчетов
+    pub pdf_font_path: String,
+    /// Redis для состояний диалогов (`None` - в памяти процесса)
+    pub redis_url: Option<String>,
+    /// PEM-файл с сертификатами частного CA бэкенда
+    pub backend_ca_bundle: Option<String>,
+    /// Не проверять TLS-сертификат бэкенда (только для отладки)
+    pub backend_accept_invalid_certs: bool,
+    /// Версия API бэкенда и пути эндпоинтов
+    pub backend_endpoints: crate::endpoints::EndpointConfig,
+    /// S3-совместимое хранилище для выгрузок больше лимита Telegram (`None`, если не задан `S3_BUCKET`)
+    pub object_storage: Option<crate::object_storage::ObjectStorageConfig>,
+    /// Переводить названия городов и банков в вопросах на латиницу до отправки бэкенду
+    pub transliteration: bool,
+    /// Дополнительные названия для транслитерации: кириллица → запись в базе
+    pub translit_dictionary: Vec<(String, String)>,
+    /// Шаблон вопроса для кнопок «🔎» под строками агрегата (`None` - кнопок нет)
+    pub drilldown_template: Option<String>,
+    /// Курсы валют для `/currency`: тенге за единицу валюты
+    pub currency_rates: Vec<(crate::currency::Currency, f64)>,
+    /// FX API с курсами к тенге (`None` - только `currency_rates`)
+    pub fx_api_url: Option<String>,
+}
+
+/// Источник настроек: переменные окружения поверх необязательного TOML-файла.
+/// Ключи файла - имена тех же переменных в любом регистре (`rate_limit_per_minute = 20`);
+/// списки записываются массивами, `backends` - таблицей.
+struct Settings {
+    env: HashMap<String, String>,
+    file: HashMap<String, String>,
+}
+
+impl Settings {
+    /// Файл из `CONFIG_FILE`, иначе `config.toml`, если он есть в рабочей директории
+    fn load() -> Result<Self> {
+        // Переменные с не-UTF-8 значениями считаются незаданными, как и при `env::var`
+        let env: HashMap<String, String> = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        let (path, required) = match env.get("CONFIG_FILE").filter(|path| !path.is_empty()) {
+            Some(path) => (path.clone(), true),
+            None => ("config.toml".to_string(), false),
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self { env, file: HashMap::new() });
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read config file {}", path)),
+        };
+        let file = Self::parse_file(&text, &path)?;
+        tracing::info!("Loaded {} settings from {}", file.len(), path);
+        Ok(Self { env, file })
+    }
+
+    /// Ключи TOML-файла `path` приводятся к именам переменных окружения
+    fn parse_file(text: &str, path: &str) -> Result<HashMap<String, String>> {
+        let table: toml::Table = text.parse().with_context(|| format!("Config file {} is not valid TOML", path))?;
+
+        let mut file = HashMap::new();
+        for (key, value) in table {
+            let name = key.to_uppercase();
+            let value = toml_to_env(&value)
+                .with_context(|| format!("{} in {}: expected a string, number, boolean, list or table of strings", key, path))?;
+            file.insert(name, value);
+        }
+        Ok(file)
+    }
+
+    /// Значение переменной: из окружения, иначе из файла
+    fn get(&self, name: &str) -> Option<String> {
+        self.env.get(name).or_else(|| self.file.get(name)).cloned()
+    }
+}
+
+/// Значение из TOML в виде, в котором оно задается переменной окружения:
+/// массив - через запятую, таблица - `имя=значение` через запятую
+fn toml_to_env(value: &toml::Value) -> Option<String> {
+    use toml::Value;
+
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Integer(number) => Some(number.to_string()),
+        Value::Float(number) => Some(number.to_string()),
+        Value::Boolean(flag) => Some(flag.to_string()),
+        Value::Datetime(datetime) => Some(datetime.to_string()),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::Array(_) | Value::Table(_) => None,
+                item => toml_to_env(item),
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        Value::Table(table) => table
+            .iter()
+            .map(|(key, value)| match value {
+                Value::Array(_) | Value::Table(_) => None,
+                value => toml_to_env(value).map(|value| format!("{}={}", key, value)),
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|pairs| pairs.join(",")),
+    }
 }
 
 impl Config {
-    pub fn from_env() -> Result<Self> {
+    /// Настройки из переменных окружения и файла `CONFIG_FILE`/`config.toml` (окружение важнее).
+    /// Ошибки называют переменную, значение которой не подошло.
+    pub fn load() -> Result<Self> {
+        let vars = Settings::load()?;
+        let config = Self::from_settings(&vars)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_settings(vars: &Settings) -> Result<Self> {
+        let bot_mode = vars.get("BOT_MODE")
+            .map(|mode| BotMode::parse(&mode))
+            .transpose()?
+            .unwrap_or(BotMode::Polling);
+        let webhook_url = vars.get("WEBHOOK_URL")
+            .filter(|url| !url.is_empty());
+        let notify_port: Option<u16> = vars.get("NOTIFY_PORT")
+            .filter(|port| !port.is_empty())
+            .map(|port| port.parse().context("NOTIFY_PORT must be a port number"))
+            .transpose()?;
+        let notify_secret = vars.get("NOTIFY_SECRET")
+            .filter(|secret| !secret.is_empty());
+        if notify_port.is_some() && notify_secret.is_none() {
+            anyhow::bail!("NOTIFY_SECRET is required when NOTIFY_PORT is set");
+        }
+        if bot_mode == BotMode::Webhook && webhook_url.is_none() {
+            anyhow::bail!("WEBHOOK_URL is required when BOT_MODE=webhook");
+        }
+
+        let backend_api_key = vars.get("BACKEND_API_KEY")
+            .filter(|key| !key.is_empty());
+        let backend_name = vars.get("BACKEND_NAME")
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "prod".to_string());
+        let extra_backends = parse_backends(vars, &backend_name, backend_api_key.as_deref())?;
+
         Ok(Self {
-            telegram_token: env::var("TELEGRAM_BOT_TOKEN")
+            telegram_token: vars.get("TELEGRAM_BOT_TOKEN")
                 .context("TELEGRAM_BOT_TOKEN environment variable is required")?,
-            backend_url: env::var("BACKEND_URL")
-                .unwrap_or_else(|_| "http://localhost:3000".to_string()),
+            backend_url: vars.get("BACKEND_URL")
+                .unwrap_or_else(|| "http://localhost:3000".to_string()),
+            backend_api_key,
+            backend_name,
+            extra_backends,
+            storage_path: vars.get("STORAGE_PATH")
+                .unwrap_or_else(|| "bot_data.db".to_string()),
+            token_encryption_key: vars.get("TOKEN_ENCRYPTION_KEY")
+                .filter(|key| !key.is_empty()),
+            web_dashboard_url: vars.get("WEB_DASHBOARD_URL")
+                .filter(|url| !url.is_empty()),
+            handoff_secret: vars.get("HANDOFF_SECRET")
+                .filter(|secret| !secret.is_empty()),
+            retention_days: vars.get("RETENTION_DAYS")
+                .map(|days| days.parse().context("RETENTION_DAYS must be a number of days"))
+                .transpose()?
+                .unwrap_or(90),
+            admin_chat_id: vars.get("ADMIN_CHAT_ID")
+                .map(|id| id.parse().context("ADMIN_CHAT_ID must be a numeric chat id"))
+                .transpose()?,
+            health_check_interval_secs: vars.get("HEALTH_CHECK_INTERVAL_SECS")
+                .map(|secs| secs.parse().context("HEALTH_CHECK_INTERVAL_SECS must be a number of seconds"))
+                .transpose()?
+                .unwrap_or(30),
+            context_scope: vars.get("CONTEXT_SCOPE")
+                .map(|scope| ContextScope::parse(&scope))
+                .transpose()?
+                .unwrap_or(ContextScope::Chat),
+            estimate_confirm_rows: vars.get("ESTIMATE_CONFIRM_ROWS")
+                .map(|rows| rows.parse().context("ESTIMATE_CONFIRM_ROWS must be a number"))
+                .transpose()?
+                .unwrap_or(1_000_000),
+            max_message_chunks: vars.get("MAX_MESSAGE_CHUNKS")
+                .map(|chunks| chunks.parse().context("MAX_MESSAGE_CHUNKS must be a number"))
+                .transpose()?
+                .unwrap_or(3),
+            bot_mode,
+            webhook_url,
+            webhook_port: vars.get("WEBHOOK_PORT")
+                .map(|port| port.parse().context("WEBHOOK_PORT must be a port number"))
+                .transpose()?
+                .unwrap_or(8443),
+            allowed_user_ids: parse_id_list(vars, "ALLOWED_USER_IDS")?,
+            allowed_chat_ids: parse_id_list(vars, "ALLOWED_CHAT_IDS")?,
+            admin_user_ids: parse_id_list(vars, "ADMIN_USER_IDS")?,
+            allowlist_path: vars.get("ALLOWLIST_PATH")
+                .unwrap_or_else(|| "allowlist.json".to_string()),
+            rate_limit_per_minute: vars.get("RATE_LIMIT_PER_MINUTE")
+                .map(|limit| limit.parse().context("RATE_LIMIT_PER_MINUTE must be a number"))
+                .transpose()?
+                .unwrap_or(10),
+            rate_limit_burst: vars.get("RATE_LIMIT_BURST")
+                .map(|burst| burst.parse().context("RATE_LIMIT_BURST must be a number"))
+                .transpose()?
+                .unwrap_or(5),
+            response_cache_ttl_secs: vars.get("RESPONSE_CACHE_TTL_SECS")
+                .map(|secs| secs.parse().context("RESPONSE_CACHE_TTL_SECS must be a number of seconds"))
+                .transpose()?
+                .unwrap_or(600),
+            response_cache_path: match vars.get("RESPONSE_CACHE_PATH") {
+                Some(path) => Some(path).filter(|path| !path.is_empty()),
+                None => Some("response_cache.json".to_string()),
+            },
+            chat_session_ttl_mins: vars.get("CHAT_SESSION_TTL_MINS")
+                .map(|mins| mins.parse().context("CHAT_SESSION_TTL_MINS must be a number of minutes"))
+                .transpose()?
+                .unwrap_or(30),
+            backend_timeout_secs: vars.get("BACKEND_TIMEOUT_SECS")
+                .map(|secs| secs.parse().context("BACKEND_TIMEOUT_SECS must be a number of seconds"))
+                .transpose()?
+                .unwrap_or(120),
+            max_concurrent_backend_requests: vars.get("MAX_CONCURRENT_BACKEND_REQUESTS")
+                .map(|count| count.parse().context("MAX_CONCURRENT_BACKEND_REQUESTS must be a number"))
+                .transpose()?
+                .unwrap_or(8),
+            backend_streaming: vars.get("BACKEND_STREAMING")
+                .filter(|value| !value.is_empty())
+                .map(|value| parse_flag(&value).context("BACKEND_STREAMING must be true or false"))
+                .transpose()?
+                .unwrap_or(false),
+            max_upload_mb: vars.get("MAX_UPLOAD_MB")
+                .map(|mb| mb.parse().context("MAX_UPLOAD_MB must be a number of megabytes"))
+                .transpose()?
+                .unwrap_or(10),
+            metrics_port: vars.get("METRICS_PORT")
+                .filter(|port| !port.is_empty())
+                .map(|port| port.parse().context("METRICS_PORT must be a port number"))
+                .transpose()?,
+            notify_port,
+            notify_secret,
+            shutdown_timeout_secs: vars.get("SHUTDOWN_TIMEOUT_SECS")
+                .map(|secs| secs.parse().context("SHUTDOWN_TIMEOUT_SECS must be a number of seconds"))
+                .transpose()?
+                .unwrap_or(30),
+            chart_render_concurrency: vars.get("CHART_RENDER_CONCURRENCY")
+                .map(|count| count.parse().context("CHART_RENDER_CONCURRENCY must be a number"))
+                .transpose()?
+                .unwrap_or(2),
+            schedule_utc_offset_hours: vars.get("SCHEDULE_UTC_OFFSET_HOURS")
+                .map(|hours| hours.parse().context("SCHEDULE_UTC_OFFSET_HOURS must be a number of hours"))
+                .transpose()?
+                .unwrap_or(5),
+            text_format: vars.get("TEXT_FORMAT")
+                .map(|format| TextFormat::parse(&format))
+                .transpose()?
+                .unwrap_or(TextFormat::Auto),
+            chart_theme: vars.get("CHART_THEME")
+                .map(|theme| {
+                    crate::utils::ChartTheme::parse(&theme)
+                        .with_context(|| format!("CHART_THEME must be light or dark (got {:?})", theme))
+                })
+                .transpose()?
+                .unwrap_or_default(),
+            pdf_font_path: vars.get("PDF_FONT_PATH")
+                .unwrap_or_else(|| "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf".to_string()),
+            redis_url: vars.get("REDIS_URL").filter(|url| !url.is_empty()),
+            backend_ca_bundle: vars.get("BACKEND_CA_BUNDLE").filter(|path| !path.is_empty()),
+            backend_accept_invalid_certs: vars.get("BACKEND_DANGER_ACCEPT_INVALID_CERTS")
+                .filter(|value| !value.is_empty())
+                .map(|value| parse_flag(&value).context("BACKEND_DANGER_ACCEPT_INVALID_CERTS must be true or false"))
+                .transpose()?
+                .unwrap_or(false),
+            backend_endpoints: crate::endpoints::EndpointConfig {
+                version: vars.get("BACKEND_API_VERSION")
+                    .filter(|version| !version.is_empty())
+                    .map(|version| crate::endpoints::VersionMode::parse(&version))
+                    .transpose()?
+                    .unwrap_or(crate::endpoints::VersionMode::Auto),
+                prefix: vars.get("BACKEND_API_PREFIX").filter(|prefix| !prefix.is_empty()),
+                overrides: vars.get("BACKEND_ENDPOINTS")
+                    .map(|value| crate::endpoints::EndpointConfig::parse_overrides(&value))
+                    .transpose()?
+                    .unwrap_or_default(),
+            },
+            object_storage: object_storage_from_env(vars)?,
+            transliteration: vars.get("TRANSLITERATION")
+                .filter(|value| !value.is_empty())
+                .map(|value| parse_flag(&value).context("TRANSLITERATION must be true or false"))
+                .transpose()?
+                .unwrap_or(true),
+            translit_dictionary: parse_dictionary(vars, "TRANSLIT_DICTIONARY")?,
+            drilldown_template: match vars.get("DRILLDOWN_TEMPLATE") {
+                Some(template) => Some(template).filter(|template| !template.trim().is_empty()),
+                None => Some(crate::drilldown::DEFAULT_TEMPLATE.to_string()),
+            },
+            currency_rates: parse_currency_rates(vars)?,
+            fx_api_url: vars.get("FX_API_URL").filter(|url| !url.is_empty()),
         })
     }
 }
 
+impl Config {
+    /// Проверки, которые не сводятся к разбору одной переменной
+    fn validate(&self) -> Result<()> {
+        if self.telegram_token.trim().is_empty() {
+            anyhow::bail!("TELEGRAM_BOT_TOKEN must not be empty");
+        }
+        validate_http_url("BACKEND_URL", &self.backend_url)?;
+        for backend in &self.extra_backends {
+            validate_http_url(&format!("BACKENDS ({})", backend.name), &backend.url)?;
+        }
+        if let Some(url) = &self.webhook_url {
+            if !url.starts_with("https://") {
+                anyhow::bail!("WEBHOOK_URL must be an https:// URL (got {:?})", url);
+            }
+        }
+
+        // Нулевые значения здесь не отключают функцию, а ломают ее
+        let positive = [
+            ("HEALTH_CHECK_INTERVAL_SECS", self.health_check_interval_secs),
+            ("BACKEND_TIMEOUT_SECS", self.backend_timeout_secs),
+            ("MAX_CONCURRENT_BACKEND_REQUESTS", self.max_concurrent_backend_requests as u64),
+            ("CHART_RENDER_CONCURRENCY", self.chart_render_concurrency as u64),
+            ("MAX_MESSAGE_CHUNKS", self.max_message_chunks as u64),
+            ("MAX_UPLOAD_MB", u64::from(self.max_upload_mb)),
+        ];
+        if let Some((name, _)) = positive.iter().find(|(_, value)| *value == 0) {
+            anyhow::bail!("{} must be greater than 0", name);
+        }
+        if self.rate_limit_per_minute > 0 && self.rate_limit_burst == 0 {
+            anyhow::bail!("RATE_LIMIT_BURST must be greater than 0 when RATE_LIMIT_PER_MINUTE is set");
+        }
+        if let Some(url) = &self.fx_api_url {
+            validate_http_url("FX_API_URL", url)?;
+        }
+        if let Some(template) = &self.drilldown_template {
+            if !template.contains("{value}") {
+                anyhow::bail!("DRILLDOWN_TEMPLATE must contain {{value}} (got {:?})", template);
+            }
+            let unknown = crate::drilldown::unknown_variables(template);
+            if !unknown.is_empty() {
+                anyhow::bail!(
+                    "DRILLDOWN_TEMPLATE may only use {{question}}, {{column}} and {{value}} (unknown: {})",
+                    unknown.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_http_url(name: &str, value: &str) -> Result<()> {
+    let url = reqwest::Url::parse(value).with_context(|| format!("{} must be a URL (got {:?})", name, value))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("{} must be an http:// or https:// URL (got {:?})", name, value);
+    }
+    Ok(())
+}
+
+/// `BACKENDS`: `sandbox=http://localhost:3001,staging=https://staging.example.com`
+fn parse_backends(vars: &Settings, default_name: &str, default_api_key: Option<&str>) -> Result<Vec<NamedBackend>> {
+    let Some(value) = vars.get("BACKENDS") else {
+        return Ok(Vec::new());
+    };
+    let mut backends: Vec<NamedBackend> = Vec::new();
+    for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let Some((name, url)) = entry.split_once('=') else {
+            anyhow::bail!("BACKENDS entries must look like name=url (got {:?})", entry);
+        };
+        let name = name.trim().to_lowercase();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            anyhow::bail!("Backend name {:?} in BACKENDS must contain only latin letters, digits, - and _", name);
+        }
+        if name == default_name || backends.iter().any(|backend| backend.name == name) {
+            anyhow::bail!("Backend {:?} is listed twice (the main backend is named by BACKEND_NAME)", name);
+        }
+        let api_key = vars.get(&format!("BACKEND_API_KEY_{}", name.to_uppercase().replace('-', "_")))
+            .filter(|key| !key.is_empty())
+            .or_else(|| default_api_key.map(str::to_string));
+        backends.push(NamedBackend { name, url: url.trim().to_string(), api_key });
+    }
+    Ok(backends)
+}
+
+/// `S3_*`: хранилище настроено, если задан `S3_BUCKET`, остальное тогда обязательно
+fn object_storage_from_env(vars: &Settings) -> Result<Option<crate::object_storage::ObjectStorageConfig>> {
+    let Some(bucket) = vars.get("S3_BUCKET").filter(|bucket| !bucket.is_empty()) else {
+        return Ok(None);
+    };
+    let required = |name: &str| {
+        vars.get(name)
+            .filter(|value| !value.is_empty())
+            .with_context(|| format!("{} is required when S3_BUCKET is set", name))
+    };
+    let url_ttl_secs: u64 = vars.get("S3_URL_TTL_SECS")
+        .map(|secs| secs.parse().context("S3_URL_TTL_SECS must be a number of seconds"))
+        .transpose()?
+        .unwrap_or(24 * 3600);
+    if url_ttl_secs > crate::object_storage::MAX_URL_TTL.as_secs() {
+        anyhow::bail!("S3_URL_TTL_SECS must not exceed {} (7 days)", crate::object_storage::MAX_URL_TTL.as_secs());
+    }
+
+    Ok(Some(crate::object_storage::ObjectStorageConfig {
+        endpoint: required("S3_ENDPOINT")?,
+        bucket,
+        region: vars.get("S3_REGION")
+            .filter(|region| !region.is_empty())
+            .unwrap_or_else(|| "us-east-1".to_string()),
+        access_key_id: required("S3_ACCESS_KEY_ID")?,
+        secret_access_key: required("S3_SECRET_ACCESS_KEY")?,
+        url_ttl: std::time::Duration::from_secs(url_ttl_secs),
+    }))
+}
+
+/// `true`/`false` (также `1`/`0`, `yes`/`no`, `on`/`off`)
+fn parse_flag(value: &str) -> Result<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        other => anyhow::bail!("invalid flag value {:?}", other),
+    }
+}
+
+/// Пары `название=значение` через запятую (`Нур-Султан=Astana,Халык=Halyk`)
+fn parse_dictionary(vars: &Settings, name: &str) -> Result<Vec<(String, String)>> {
+    let Some(value) = vars.get(name) else {
+        return Ok(Vec::new());
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() && !value.trim().is_empty() => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => anyhow::bail!("{} entries must look like name=value (got {:?})", name, entry),
+        })
+        .collect()
+}
+
+/// Курсы валют `USD=505.2,EUR=548.1` (тенге за единицу валюты)
+fn parse_currency_rates(vars: &Settings) -> Result<Vec<(crate::currency::Currency, f64)>> {
+    use crate::currency::Currency;
+
+    parse_dictionary(vars, "CURRENCY_RATES")?
+        .into_iter()
+        .map(|(code, rate)| {
+            let currency = Currency::parse(&code)
+                .filter(|currency| *currency != Currency::Kzt)
+                .with_context(|| format!("CURRENCY_RATES supports USD and EUR (got {:?})", code))?;
+            let rate: f64 = rate.parse()
+                .ok()
+                .filter(|rate: &f64| rate.is_finite() && *rate > 0.0)
+                .with_context(|| format!("CURRENCY_RATES rate for {} must be a positive number (got {:?})", code, rate))?;
+            Ok((currency, rate))
+        })
+        .collect()
+}
+
+/// Список id через запятую (`123,456`)
+fn parse_id_list<T: FromStr>(vars: &Settings, name: &str) -> Result<Vec<T>> {
+    let Some(value) = vars.get(name) else {
+        return Ok(Vec::new());
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse()
+                .map_err(|_| anyhow::anyhow!("{} must be a comma-separated list of numeric ids (got {:?})", name, id))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Окружение `env` поверх файла `config.toml` с текстом `file`
+    fn settings(env: &[(&str, &str)], file: &str) -> Settings {
+        Settings {
+            env: env.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+            file: Settings::parse_file(file, "config.toml").unwrap(),
+        }
+    }
+
+    fn load(env: &[(&str, &str)], file: &str) -> Result<Config> {
+        let config = Config::from_settings(&settings(env, file))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn error(env: &[(&str, &str)], file: &str) -> String {
+        match load(env, file) {
+            Ok(_) => panic!("config must be rejected"),
+            Err(e) => format!("{:#}", e),
+        }
+    }
+
+    #[test]
+    fn environment_overrides_file() {
+        let file = "telegram_bot_token = \"file-token\"\nbackend_url = \"http://file:3000\"\nmax_message_chunks = 5\n";
+        let config = load(&[("BACKEND_URL", "https://env.example.com")], file).unwrap();
+        assert_eq!(config.backend_url, "https://env.example.com");
+        assert_eq!(config.telegram_token, "file-token");
+        assert_eq!(config.max_message_chunks, 5);
+    }
+
+    #[test]
+    fn missing_token_is_named() {
+        assert!(error(&[], "backend_url = \"http://localhost:3000\"").contains("TELEGRAM_BOT_TOKEN"));
+    }
+
+    #[test]
+    fn invalid_url_is_named() {
+        let message = error(&[("TELEGRAM_BOT_TOKEN", "token"), ("BACKEND_URL", "not a url")], "");
+        assert!(message.contains("BACKEND_URL"), "{}", message);
+    }
+
+    #[test]
+    fn zero_limit_is_named() {
+        let message = error(&[("TELEGRAM_BOT_TOKEN", "token")], "max_message_chunks = 0");
+        assert!(message.contains("MAX_MESSAGE_CHUNKS"), "{}", message);
+    }
+}
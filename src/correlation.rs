@@ -0,0 +1,167 @@
+use std::collections::hash_map::RandomState;
+use std::future::{Future, IntoFuture};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use teloxide::types::Update;
+use tracing::Instrument;
+
+/// Заголовок, в котором id корреляции передается бэкенду
+pub const HEADER: &str = "X-Correlation-Id";
+
+/// Заголовок W3C Trace Context (его понимают OpenTelemetry SDK): запросы к бэкенду
+/// в рамках одного обновления попадают в одну трассу
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Обработка текущего обновления: id корреляции, id трассы и время, потраченное на
+/// бэкенд и Telegram (для `/debug`)
+struct Context {
+    id: String,
+    trace_id: String,
+    started: Instant,
+    spent: Mutex<Spent>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Spent {
+    backend: Duration,
+    telegram: Duration,
+}
+
+tokio::task_local! {
+    static CONTEXT: Context;
+}
+
+/// Случайные 64 бита без отдельной зависимости: каждый `RandomState` получает новые ключи SipHash
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Id трассы W3C: 32 шестнадцатеричных символа, не все нули
+fn new_trace_id() -> String {
+    format!("{:016x}{:016x}", random_u64(), random_u64() | 1)
+}
+
+/// Id корреляции для обновления Telegram: по нему связываются логи бота и бэкенда
+pub fn for_update(update: &Update) -> String {
+    format!("tg-{}", update.id)
+}
+
+/// Выполняет `future` с id корреляции: он попадает во все спаны и запросы к бэкенду
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    let trace_id = new_trace_id();
+    let span = tracing::info_span!("update", correlation_id = %id, trace_id = %trace_id);
+    let context = Context { id, trace_id, started: Instant::now(), spent: Mutex::default() };
+    CONTEXT.scope(context, future.instrument(span)).await
+}
+
+/// Id корреляции текущей задачи (`None` вне [`scope`])
+pub fn current() -> Option<String> {
+    CONTEXT.try_with(|context| context.id.clone()).ok()
+}
+
+/// Значение `traceparent` для очередного запроса к бэкенду: трасса обновления и новый спан
+pub fn traceparent() -> Option<String> {
+    CONTEXT
+        .try_with(|context| format!("00-{}-{:016x}-01", context.trace_id, random_u64() | 1))
+        .ok()
+}
+
+/// Учитывает время запроса к бэкенду (вместе с сетью)
+pub fn record_backend(elapsed: Duration) {
+    let _ = CONTEXT.try_with(|context| context.spent.lock().unwrap().backend += elapsed);
+}
+
+/// Учитывает время вызова Telegram API
+pub fn record_telegram(elapsed: Duration) {
+    let _ = CONTEXT.try_with(|context| context.spent.lock().unwrap().telegram += elapsed);
+}
+
+/// Выполняет запрос к бэкенду и учитывает его время
+pub async fn backend<F: IntoFuture>(request: F) -> F::Output {
+    let started = Instant::now();
+    let output = request.await;
+    record_backend(started.elapsed());
+    output
+}
+
+/// Выполняет вызов Telegram API и учитывает его время
+pub async fn telegram<F: IntoFuture>(request: F) -> F::Output {
+    let started = Instant::now();
+    let output = request.await;
+    record_telegram(started.elapsed());
+    output
+}
+
+/// Из чего сложилось время ответа к текущему моменту
+pub struct Breakdown {
+    pub trace_id: String,
+    pub total: Duration,
+    pub backend: Duration,
+    pub telegram: Duration,
+}
+
+/// Время обработки текущего обновления (`None` вне [`scope`])
+pub fn breakdown() -> Option<Breakdown> {
+    CONTEXT
+        .try_with(|context| {
+            let spent = *context.spent.lock().unwrap();
+            Breakdown {
+                trace_id: context.trace_id.clone(),
+                total: context.started.elapsed(),
+                backend: spent.backend,
+                telegram: spent.telegram,
+            }
+        })
+        .ok()
+}
+
+impl Breakdown {
+    /// Подвал ответа для `/debug on`: сам бот, бэкенд (выполнение запроса по `execution_time_ms`
+    /// и остальное - сеть и очередь) и Telegram. Подвал отправляется вместе с ответом, поэтому
+    /// время этой последней отправки в него не входит.
+    pub fn render(&self, execution_time_ms: u64, cached: bool) -> String {
+        let ms = |duration: Duration| duration.as_millis();
+        let bot = self.total.saturating_sub(self.backend + self.telegram);
+        let backend = if cached {
+            "ответ из кэша".to_string()
+        } else {
+            let network = ms(self.backend).saturating_sub(u128::from(execution_time_ms));
+            format!("{} мс (запрос {} мс, сеть {} мс)", ms(self.backend), execution_time_ms, network)
+        };
+        format!(
+            "🛠 <b>Отладка</b>: всего {} мс\n• бот: {} мс\n• бэкенд: {}\n• Telegram: {} мс (до отправки ответа)\n• trace: <code>{}</code>",
+            ms(self.total),
+            ms(bot),
+            backend,
+            ms(self.telegram),
+            self.trace_id
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn requests_in_one_update_share_trace() {
+        assert!(traceparent().is_none());
+        let (first, second, breakdown) = scope("tg-1".to_string(), async {
+            record_backend(Duration::from_millis(30));
+            telegram(tokio::time::sleep(Duration::from_millis(5))).await;
+            (traceparent().unwrap(), traceparent().unwrap(), breakdown().unwrap())
+        })
+        .await;
+
+        let parts: Vec<&str> = first.split('-').collect();
+        assert_eq!((parts[0], parts[1].len(), parts[2].len(), parts[3]), ("00", 32, 16, "01"));
+        assert_eq!(parts[1], breakdown.trace_id);
+        assert!(second.starts_with(&format!("00-{}-", breakdown.trace_id)));
+        assert_ne!(first, second, "each request gets its own span id");
+        assert!(breakdown.telegram >= Duration::from_millis(5));
+        let footer = breakdown.render(20, false);
+        assert!(footer.contains("бэкенд: 30 мс (запрос 20 мс, сеть 10 мс)"), "{}", footer);
+        assert!(footer.contains("(до отправки ответа)"), "{}", footer);
+    }
+}
@@ -0,0 +1,355 @@
+use crate::api_client::QueryResponse;
+use crate::numbers::is_money_column;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Сколько курсы из `FX_API_URL` считаются свежими
+const FX_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Сколько ждать ответа `FX_API_URL`
+const FX_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Валюта, в которой пользователь видит суммы (`/currency`). Бэкенд возвращает суммы в тенге.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    #[default]
+    Kzt,
+    Usd,
+    Eur,
+}
+
+impl Currency {
+    pub const ALL: [Currency; 3] = [Currency::Kzt, Currency::Usd, Currency::Eur];
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "kzt" | "₸" | "тенге" | "тг" => Some(Self::Kzt),
+            "usd" | "$" | "доллар" | "доллары" => Some(Self::Usd),
+            "eur" | "€" | "евро" => Some(Self::Eur),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Kzt => "KZT",
+            Self::Usd => "USD",
+            Self::Eur => "EUR",
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Kzt => "₸",
+            Self::Usd => "$",
+            Self::Eur => "€",
+        }
+    }
+}
+
+/// Курс валюты: сколько тенге стоит единица валюты
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub currency: Currency,
+    pub kzt_per_unit: f64,
+    /// Когда курс получен из `FX_API_URL` (`None` - курс из `CURRENCY_RATES`)
+    pub fetched_at: Option<DateTime<Utc>>,
+}
+
+impl Rate {
+    /// Пометка под ответом: «💱 Суммы пересчитаны в USD по курсу 1 $ = 505,20 ₸ (на 16.10.2026 12:00 UTC)»
+    pub fn note(&self, lang: crate::language::Language) -> String {
+        let source = match self.fetched_at {
+            Some(fetched_at) => format!("на {} UTC", fetched_at.format("%d.%m.%Y %H:%M")),
+            None => "из настроек бота".to_string(),
+        };
+        format!(
+            "💱 <i>Суммы пересчитаны в {} по курсу 1 {} = {} ₸ ({})</i>",
+            self.currency.code(),
+            self.currency.symbol(),
+            crate::numbers::format_number((self.kzt_per_unit * 100.0).round() / 100.0, lang),
+            source
+        )
+    }
+}
+
+/// Ответ `FX_API_URL`: курсы к тенге, `{"rates": {"USD": 0.00198, "EUR": 0.00182}}`
+/// (сколько единиц валюты дают за 1 тенге - как у большинства FX API с `base=KZT`)
+#[derive(Debug, Deserialize)]
+struct FxResponse {
+    rates: HashMap<String, f64>,
+}
+
+/// Курсы из FX API: когда загружены (для срока кэша), когда получены и курсы в тенге за единицу
+type CachedRates = (Instant, DateTime<Utc>, HashMap<Currency, f64>);
+
+/// Курсы валют: статические из `CURRENCY_RATES` или из FX API (`FX_API_URL`), который
+/// запрашивается не чаще раза в час. Статический курс используется, если API недоступен.
+pub struct CurrencyConverter {
+    static_rates: HashMap<Currency, f64>,
+    api: Option<(reqwest::Client, String)>,
+    cached: Mutex<Option<CachedRates>>,
+}
+
+impl CurrencyConverter {
+    /// `static_rates` - тенге за единицу валюты
+    pub fn new(static_rates: &[(Currency, f64)], api_url: Option<String>) -> Result<Self> {
+        let api = match api_url {
+            Some(url) => {
+                let client = reqwest::Client::builder()
+                    .timeout(FX_TIMEOUT)
+                    .build()
+                    .context("Failed to build FX API client")?;
+                Some((client, url))
+            }
+            None => None,
+        };
+        Ok(Self { static_rates: static_rates.iter().copied().collect(), api, cached: Mutex::new(None) })
+    }
+
+    /// Можно ли пересчитывать суммы в `currency`
+    pub fn supports(&self, currency: Currency) -> bool {
+        currency == Currency::Kzt || self.api.is_some() || self.static_rates.contains_key(&currency)
+    }
+
+    /// Курс `currency`; `None` для тенге и если курс неизвестен
+    pub async fn rate(&self, currency: Currency) -> Option<Rate> {
+        if currency == Currency::Kzt {
+            return None;
+        }
+        if let Some((fetched_at, rates)) = self.api_rates().await {
+            if let Some(kzt_per_unit) = rates.get(&currency) {
+                return Some(Rate { currency, kzt_per_unit: *kzt_per_unit, fetched_at: Some(fetched_at) });
+            }
+        }
+        let kzt_per_unit = *self.static_rates.get(&currency)?;
+        Some(Rate { currency, kzt_per_unit, fetched_at: None })
+    }
+
+    /// Курсы из FX API (из кэша, если они свежие); `None`, если API не настроен или недоступен
+    async fn api_rates(&self) -> Option<(DateTime<Utc>, HashMap<Currency, f64>)> {
+        let (client, url) = self.api.as_ref()?;
+        let mut cached = self.cached.lock().await;
+        if let Some((loaded, fetched_at, rates)) = cached.as_ref() {
+            if loaded.elapsed() < FX_CACHE_TTL {
+                return Some((*fetched_at, rates.clone()));
+            }
+        }
+        match fetch_rates(client, url).await {
+            Ok(rates) => {
+                let fetched_at = Utc::now();
+                *cached = Some((Instant::now(), fetched_at, rates.clone()));
+                Some((fetched_at, rates))
+            }
+            Err(e) => {
+                warn!("Failed to fetch exchange rates: {:#}", e);
+                // Устаревший курс лучше, чем никакого
+                cached.as_ref().map(|(_, fetched_at, rates)| (*fetched_at, rates.clone()))
+            }
+        }
+    }
+}
+
+async fn fetch_rates(client: &reqwest::Client, url: &str) -> Result<HashMap<Currency, f64>> {
+    let response: FxResponse = client
+        .get(url)
+        .send()
+        .await
+        .context("FX API request failed")?
+        .error_for_status()
+        .context("FX API returned an error")?
+        .json()
+        .await
+        .context("FX API returned unexpected JSON")?;
+    Ok(response
+        .rates
+        .iter()
+        .filter_map(|(code, per_kzt)| Some((Currency::parse(code)?, *per_kzt)))
+        .filter(|(currency, per_kzt)| *currency != Currency::Kzt && *per_kzt > 0.0)
+        .map(|(currency, per_kzt)| (currency, 1.0 / per_kzt))
+        .collect())
+}
+
+/// Ответ с суммами в валюте курса `rate`: денежные колонки данных (им добавляется код
+/// валюты: `amount` → `amount_usd`), диаграмма по суммам и суммы в тенге в тексте анализа.
+/// Таблица бэкенда заменяется таблицей из пересчитанных данных.
+pub fn convert_response(response: &QueryResponse, rate: Rate, lang: crate::language::Language) -> QueryResponse {
+    let mut converted = response.clone();
+    let convert = |value: f64| (value / rate.kzt_per_unit * 100.0).round() / 100.0;
+    let suffix = format!("_{}", rate.currency.code().to_lowercase());
+
+    let money_columns: Vec<String> = crate::utils::collect_columns(&response.data)
+        .into_iter()
+        .filter(|column| is_money_column(column) && !column.to_lowercase().ends_with(&suffix))
+        .filter(|column| {
+            let mut values = response.data.iter().filter_map(|row| row.get(column)).filter(|value| !value.is_null()).peekable();
+            values.peek().is_some() && values.all(Value::is_number)
+        })
+        .collect();
+    if !money_columns.is_empty() {
+        for row in converted.data.iter_mut().filter_map(Value::as_object_mut) {
+            for column in &money_columns {
+                if let Some(value) = row.remove(column) {
+                    let value = value.as_f64().map_or(Value::Null, |value| serde_json::json!(convert(value)));
+                    row.insert(format!("{}{}", column, suffix), value);
+                }
+            }
+        }
+        if converted.table.is_some() {
+            converted.table = Some(crate::utils::format_data_as_table(&converted.data, lang));
+        }
+    }
+
+    if let Some(chart) = &mut converted.chart_data {
+        let money_chart = chart.title.as_deref().is_some_and(is_money_column)
+            || chart.datasets.iter().any(|dataset| is_money_column(&dataset.label));
+        if money_chart {
+            for dataset in &mut chart.datasets {
+                dataset.data.iter_mut().for_each(|value| *value = convert(*value));
+                dataset.label = format!("{}, {}", dataset.label, rate.currency.symbol());
+            }
+        }
+    }
+
+    if let Some(analysis) = &mut converted.analysis {
+        analysis.headline = convert_text(&analysis.headline, rate);
+        analysis.explanation = convert_text(&analysis.explanation, rate);
+        for insight in &mut analysis.insights {
+            insight.description = convert_text(&insight.description, rate);
+        }
+    }
+    converted
+}
+
+/// Разделители разрядов: пробел, неразрывный и узкие пробелы, запятая (`1 250 000`, `1,250,000`)
+const GROUP_SEPARATORS: [char; 5] = [' ', '\u{a0}', '\u{2009}', '\u{202f}', ','];
+
+/// Число в начале `text` с разделителями разрядов и десятичной точкой или запятой
+/// (`1 250 000,50`, `1,250,000.50`): значение и длина в байтах. После разделителя разрядов
+/// должны идти ровно три цифры, иначе число на нем заканчивается (`2024, 15` - два числа).
+fn parse_amount(text: &str) -> Option<(f64, usize)> {
+    let digits_end = |from: usize| text[from..].find(|c: char| !c.is_ascii_digit()).map_or(text.len(), |end| from + end);
+
+    let mut end = digits_end(0);
+    if end == 0 {
+        return None;
+    }
+    let mut number: String = text[..end].to_string();
+    while let Some(separator) = text[end..].chars().next().filter(|c| GROUP_SEPARATORS.contains(c)) {
+        let group_start = end + separator.len_utf8();
+        let group_end = digits_end(group_start);
+        if group_end - group_start != 3 {
+            break;
+        }
+        number.push_str(&text[group_start..group_end]);
+        end = group_end;
+    }
+    if let Some(point) = text[end..].chars().next().filter(|c| matches!(c, '.' | ',')) {
+        let fraction_end = digits_end(end + point.len_utf8());
+        if fraction_end > end + 1 {
+            number.push('.');
+            number.push_str(&text[end + 1..fraction_end]);
+            end = fraction_end;
+        }
+    }
+    Some((number.parse().ok()?, end))
+}
+
+/// Пересчитывает суммы в тенге в тексте: число, за которым идет `KZT`, `₸` или `тенге`
+/// (`1 234 567,89 ₸` → `2469.14 USD`). Остальные числа не меняются.
+fn convert_text(text: &str, rate: Rate) -> String {
+    const MARKERS: [&str; 7] = [" KZT", "\u{a0}KZT", " ₸", "\u{a0}₸", "₸", " тенге", "\u{a0}тенге"];
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        let (value, length) = parse_amount(&rest[start..]).unwrap_or((f64::NAN, 0));
+        let end = start + length.max(1);
+        let after = &rest[end..];
+        let marker = MARKERS.iter().find(|marker| after.starts_with(*marker));
+        // Число - часть другого (`v1.2`, `abc123`)? Тогда оставляем как есть
+        let glued = rest[..start].chars().next_back().is_some_and(|c| c.is_alphanumeric() || c == '.');
+        match (marker, Some(value).filter(|value| value.is_finite())) {
+            (Some(marker), Some(value)) if !glued => {
+                result.push_str(&rest[..start]);
+                let converted = (value / rate.kzt_per_unit * 100.0).round() / 100.0;
+                result.push_str(&format!("{} {}", converted, rate.currency.code()));
+                rest = &after[marker.len()..];
+            }
+            _ => {
+                result.push_str(&rest[..end]);
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language::Language;
+    use serde_json::json;
+
+    fn usd() -> Rate {
+        Rate { currency: Currency::Usd, kzt_per_unit: 500.0, fetched_at: None }
+    }
+
+    #[test]
+    fn money_columns_charts_and_headlines_are_converted() {
+        let response: QueryResponse = serde_json::from_value(json!({
+            "question": "Оборот по городам",
+            "data": [{"city": "Almaty", "total_amount": 1000000.0, "count": 10}],
+            "table": "Almaty | 1000000",
+            "chart_data": {"chart_type": "bar", "labels": ["Almaty"], "datasets": [{"label": "Объем", "data": [1000000.0]}]},
+            "execution_time_ms": 5,
+            "row_count": 1,
+            "analysis": {
+                "headline": "Оборот 1000000 KZT, 10 транзакций за 2024 год",
+                "insights": [{"title": "Almaty", "description": "Средний чек 5000₸", "significance": "High"}],
+                "explanation": "",
+                "suggested_questions": [],
+            },
+        }))
+        .unwrap();
+
+        let converted = convert_response(&response, usd(), Language::Ru);
+        assert_eq!(converted.data[0], json!({"city": "Almaty", "total_amount_usd": 2000.0, "count": 10}));
+        assert!(converted.table.unwrap().contains("total_amount_usd, $"));
+        let chart = converted.chart_data.unwrap();
+        assert_eq!((chart.datasets[0].label.as_str(), chart.datasets[0].data[0]), ("Объем, $", 2000.0));
+        let analysis = converted.analysis.unwrap();
+        assert_eq!(analysis.headline, "Оборот 2000 USD, 10 транзакций за 2024 год");
+        assert_eq!(analysis.insights[0].description, "Средний чек 10 USD");
+    }
+
+    #[test]
+    fn grouped_amounts_in_text_are_converted_whole() {
+        let rate = usd();
+        assert_eq!(convert_text("Оборот 1 250 000 ₸", rate), "Оборот 2500 USD");
+        assert_eq!(convert_text("Оборот 1\u{a0}250\u{a0}000\u{a0}₸", rate), "Оборот 2500 USD");
+        assert_eq!(convert_text("Оборот 1\u{202f}250\u{2009}000 тенге", rate), "Оборот 2500 USD");
+        assert_eq!(convert_text("Оборот 1,250,000 KZT", rate), "Оборот 2500 USD");
+        assert_eq!(convert_text("Чек 1,250,000.50 KZT", rate), "Чек 2500 USD");
+        assert_eq!(convert_text("Чек 2 500,50 ₸", rate), "Чек 5 USD");
+        assert_eq!(convert_text("Чек 250,5₸", rate), "Чек 0.5 USD");
+        // Числа без валюты и перечисления не трогаем
+        assert_eq!(convert_text("В 2024, 15 городов, 1 250 клиентов", rate), "В 2024, 15 городов, 1 250 клиентов");
+        assert_eq!(convert_text("Итого 12, 25000 ₸", rate), "Итого 12, 50 USD");
+    }
+
+    #[test]
+    fn rate_note_names_source() {
+        assert_eq!(usd().note(Language::Ru), "💱 <i>Суммы пересчитаны в USD по курсу 1 $ = 500 ₸ (из настроек бота)</i>");
+        assert_eq!(Currency::parse("eur"), Some(Currency::Eur));
+        assert_eq!(Currency::parse("rub"), None);
+    }
+}
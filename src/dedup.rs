@@ -0,0 +1,49 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use teloxide::types::ChatId;
+
+/// Запросы, выполняющиеся сейчас в каждом чате. Повторное нажатие на подсказку
+/// или повторная отправка того же вопроса, пока первый не завершен, отклоняются.
+#[derive(Default)]
+pub struct RunningQueries {
+    queries: Mutex<HashSet<(ChatId, String)>>,
+}
+
+impl RunningQueries {
+    /// Отмечает вопрос как выполняющийся. `None`, если такой же вопрос в этом чате
+    /// уже выполняется; иначе запись удаляется, когда гард выходит из области видимости
+    pub fn try_start(self: &Arc<Self>, chat_id: ChatId, question: &str) -> Option<RunningQueryGuard> {
+        let key = (chat_id, normalize(question));
+        if !self.lock().insert(key.clone()) {
+            return None;
+        }
+        Some(RunningQueryGuard {
+            registry: Arc::clone(self),
+            key,
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashSet<(ChatId, String)>> {
+        self.queries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+pub struct RunningQueryGuard {
+    registry: Arc<RunningQueries>,
+    key: (ChatId, String),
+}
+
+impl Drop for RunningQueryGuard {
+    fn drop(&mut self) {
+        self.registry.lock().remove(&self.key);
+    }
+}
+
+/// Вопросы, отличающиеся только регистром и пробелами, считаются одинаковыми
+fn normalize(question: &str) -> String {
+    question
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
@@ -0,0 +1,88 @@
+use crate::prompts::ParamDialog;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use crate::redis_storage::RedisStorage;
+use teloxide::dispatching::dialogue::{Dialogue, ErasedStorage, InMemStorage, Storage};
+use teloxide::types::ChatId;
+use tracing::{info, warn};
+
+/// Состояние разговора с чатом для многошаговых взаимодействий.
+/// Хранится в хранилище диалогов teloxide: в памяти или в Redis (`REDIS_URL`),
+/// чтобы незавершенные диалоги переживали перезапуск и были общими для нескольких экземпляров бота.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub enum ChatState {
+    #[default]
+    Idle,
+    /// Открыт раздел inline-меню (путь из индексов разделов)
+    Menu { path: Vec<usize> },
+    /// Бот спрашивает параметры кнопки меню
+    MenuParams { path: Vec<usize>, dialog: ParamDialog },
+}
+
+impl ChatState {
+    /// Открытый раздел меню
+    pub fn menu_path(&self) -> &[usize] {
+        match self {
+            Self::Idle => &[],
+            Self::Menu { path } | Self::MenuParams { path, .. } => path,
+        }
+    }
+
+    /// То же состояние с другим разделом меню (незаконченный диалог о параметрах сохраняется)
+    pub fn with_menu_path(self, path: Vec<usize>) -> Self {
+        match self {
+            Self::MenuParams { dialog, .. } => Self::MenuParams { path, dialog },
+            _ if path.is_empty() => Self::Idle,
+            _ => Self::Menu { path },
+        }
+    }
+}
+
+pub type ChatStorage = ErasedStorage<ChatState>;
+pub type ChatDialogue = Dialogue<ChatState, ChatStorage>;
+
+/// Хранилище диалогов: Redis, если задан `REDIS_URL`, иначе в памяти процесса
+pub async fn open_storage(redis_url: Option<&str>) -> Result<Arc<ChatStorage>> {
+    match redis_url {
+        Some(url) => {
+            let storage = RedisStorage::open(url)
+                .await
+                .context("Failed to connect to Redis dialogue storage")?;
+            info!("Dialogue state is stored in Redis");
+            Ok(storage.erase())
+        }
+        None => Ok(InMemStorage::<ChatState>::new().erase()),
+    }
+}
+
+/// Текущее состояние чата. Ошибка хранилища не должна ломать ответ - считаем, что диалога нет
+pub async fn load(dialogue: &ChatDialogue) -> ChatState {
+    match dialogue.get().await {
+        Ok(state) => state.unwrap_or_default(),
+        Err(e) => {
+            warn!("Failed to load dialogue state for chat {}: {}", dialogue.chat_id(), e);
+            ChatState::Idle
+        }
+    }
+}
+
+/// Сохраняет состояние чата; `Idle` удаляет запись из хранилища
+pub async fn save(dialogue: &ChatDialogue, state: ChatState) {
+    let result = match state {
+        // Удалять нечего - хранилище вернуло бы ошибку «диалог не найден»
+        ChatState::Idle => match dialogue.get().await {
+            Ok(Some(_)) => dialogue.exit().await,
+            other => other.map(|_| ()),
+        },
+        state => dialogue.update(state).await,
+    };
+    if let Err(e) = result {
+        warn!("Failed to save dialogue state for chat {}: {}", dialogue.chat_id(), e);
+    }
+}
+
+/// Диалог чата в общем хранилище
+pub fn for_chat(storage: &Arc<ChatStorage>, chat_id: ChatId) -> ChatDialogue {
+    Dialogue::new(storage.clone(), chat_id)
+}
@@ -0,0 +1,143 @@
+use crate::api_client::QueryResponse;
+use crate::prompts::{compose, placeholders};
+use crate::suggestions::SuggestionStore;
+use crate::utils::collect_columns;
+use serde_json::Value;
+use teloxide::types::InlineKeyboardButton;
+
+/// Шаблон уточняющего вопроса по умолчанию (`DRILLDOWN_TEMPLATE`)
+pub const DEFAULT_TEMPLATE: &str = "{question} — подробнее по {value}";
+
+/// Переменные шаблона: исходный вопрос, колонка разбивки и значение из строки
+pub const TEMPLATE_VARIABLES: [&str; 3] = ["question", "column", "value"];
+
+/// Кнопки получают первые строки результата (бэкенд уже отсортировал их по убыванию)
+const MAX_DRILLDOWN_ROWS: usize = 6;
+
+/// Сколько кнопок в одном ряду клавиатуры
+const BUTTONS_PER_ROW: usize = 3;
+
+/// Длиннее значения на кнопке обрезаются
+const MAX_LABEL_CHARS: usize = 24;
+
+/// Колонка, по которой разбит агрегат: единственная нечисловая колонка результата, если
+/// остальные колонки числовые и ее значения в строках не повторяются. Для временного ряда
+/// (даты) и «сырых» строк с несколькими текстовыми колонками разбивки нет.
+fn dimension(data: &[Value]) -> Option<String> {
+    if data.len() < 2 {
+        return None;
+    }
+    let columns = collect_columns(data);
+    let is_numeric = |column: &String| {
+        data.iter().filter_map(|row| row.get(column)).filter(|value| !value.is_null()).all(Value::is_number)
+    };
+    let (dimensions, metrics): (Vec<String>, Vec<String>) = columns.into_iter().partition(|column| !is_numeric(column));
+    let [dimension] = &dimensions[..] else {
+        return None;
+    };
+    if metrics.is_empty() {
+        return None;
+    }
+
+    let keys: Vec<&str> = data.iter().filter_map(|row| row.get(dimension)?.as_str()).collect();
+    let distinct = keys.iter().enumerate().all(|(i, key)| !keys[..i].contains(key));
+    let dates = keys.iter().all(|key| chrono::NaiveDate::parse_from_str(key.get(..10).unwrap_or(key), "%Y-%m-%d").is_ok());
+    (keys.len() == data.len() && distinct && !dates).then(|| dimension.clone())
+}
+
+/// Уточняющие вопросы по первым строкам агрегата: значение разбивки и вопрос по шаблону
+pub fn drilldown_questions(template: &str, response: &QueryResponse) -> Vec<(String, String)> {
+    let Some(column) = dimension(&response.data) else {
+        return Vec::new();
+    };
+    response
+        .data
+        .iter()
+        .filter_map(|row| row.get(&column)?.as_str())
+        .filter(|value| !value.trim().is_empty())
+        .take(MAX_DRILLDOWN_ROWS)
+        .map(|value| {
+            let values = [
+                ("question".to_string(), response.question.trim().trim_end_matches(['.', '?', '!']).to_string()),
+                ("column".to_string(), column.clone()),
+                ("value".to_string(), value.to_string()),
+            ];
+            (value.to_string(), compose(template, &values))
+        })
+        .collect()
+}
+
+/// Переменные шаблона, которых нет среди `TEMPLATE_VARIABLES`
+pub fn unknown_variables(template: &str) -> Vec<String> {
+    placeholders(template)
+        .into_iter()
+        .filter(|name| !TEMPLATE_VARIABLES.contains(&name.as_str()))
+        .collect()
+}
+
+/// Ряды кнопок «🔎 Almaty»: вопрос хранится в `store` и выполняется как подсказка (`q:<hash>`)
+pub fn drilldown_rows(template: &str, response: &QueryResponse, store: &SuggestionStore) -> Vec<Vec<InlineKeyboardButton>> {
+    let buttons: Vec<InlineKeyboardButton> = drilldown_questions(template, response)
+        .into_iter()
+        .map(|(value, question)| {
+            let label = if value.chars().count() > MAX_LABEL_CHARS {
+                format!("{}…", value.chars().take(MAX_LABEL_CHARS - 1).collect::<String>())
+            } else {
+                value
+            };
+            InlineKeyboardButton::callback(format!("🔎 {}", label), format!("q:{}", store.insert(&question)))
+        })
+        .collect();
+    buttons.chunks(BUTTONS_PER_ROW).map(<[_]>::to_vec).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn response(data: Value) -> QueryResponse {
+        serde_json::from_value(json!({
+            "question": "Оборот по городам?",
+            "data": data,
+            "execution_time_ms": 5,
+            "row_count": 0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn aggregate_rows_get_drilldown_questions() {
+        let data = json!([
+            {"city": "Almaty", "total_amount": 500.0, "count": 10},
+            {"city": "Astana", "total_amount": 300.0, "count": 7},
+        ]);
+        let questions = drilldown_questions(DEFAULT_TEMPLATE, &response(data));
+        assert_eq!(questions, [
+            ("Almaty".to_string(), "Оборот по городам — подробнее по Almaty".to_string()),
+            ("Astana".to_string(), "Оборот по городам — подробнее по Astana".to_string()),
+        ]);
+
+        let questions = drilldown_questions("Детали {column}={value}", &response(json!([
+            {"city": "Almaty", "total_amount": 500.0},
+            {"city": "Astana", "total_amount": 300.0},
+        ])));
+        assert_eq!(questions[0].1, "Детали city=Almaty");
+    }
+
+    #[test]
+    fn non_aggregates_get_no_buttons() {
+        // Временной ряд
+        assert!(drilldown_questions(DEFAULT_TEMPLATE, &response(json!([
+            {"day": "2024-03-01", "total_amount": 500.0},
+            {"day": "2024-03-02", "total_amount": 300.0},
+        ]))).is_empty());
+        // Строки транзакций с несколькими текстовыми колонками
+        assert!(drilldown_questions(DEFAULT_TEMPLATE, &response(json!([
+            {"city": "Almaty", "merchant": "ТОО 1", "amount": 5.0},
+            {"city": "Almaty", "merchant": "ТОО 2", "amount": 3.0},
+        ]))).is_empty());
+        // Одна строка - разбивки нет
+        assert!(drilldown_questions(DEFAULT_TEMPLATE, &response(json!([{"city": "Almaty", "amount": 5.0}]))).is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Версия HTTP API бэкенда
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    /// Прежний бэкенд: `/api/query`, `/api/chat`, ...
+    V1,
+    /// Новый бэкенд: `/api/v2/query`, `/api/v2/chat`, ...
+    V2,
+}
+
+impl ApiVersion {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+        }
+    }
+
+    /// Префикс путей этой версии
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::V1 => "/api",
+            Self::V2 => "/api/v2",
+        }
+    }
+
+    /// Версия по тому, что сообщает `/api/health`: `2`, `"v2"`, `"2.1.0"`
+    fn from_reported(value: &Value) -> Option<Self> {
+        let major = match value {
+            Value::Number(number) => number.as_u64()?,
+            Value::String(text) => {
+                let text = text.trim().trim_start_matches(['v', 'V']);
+                let digits: String = text.chars().take_while(char::is_ascii_digit).collect();
+                digits.parse().ok()?
+            }
+            _ => return None,
+        };
+        Some(if major >= 2 { Self::V2 } else { Self::V1 })
+    }
+}
+
+/// Как выбирается версия API (`BACKEND_API_VERSION`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionMode {
+    /// По версии, которую бэкенд сообщает в `/api/health`; до первой проверки - v1
+    Auto,
+    Fixed(ApiVersion),
+}
+
+impl VersionMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "v1" | "1" => Ok(Self::Fixed(ApiVersion::V1)),
+            "v2" | "2" => Ok(Self::Fixed(ApiVersion::V2)),
+            other => anyhow::bail!("BACKEND_API_VERSION must be one of auto, v1, v2 (got {:?})", other),
+        }
+    }
+}
+
+/// Эндпоинт бэкенда, путь к которому можно переопределить в `BACKEND_ENDPOINTS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BackendEndpoint {
+    Query,
+    QueryStream,
+    Chat,
+    Upload,
+    ContextClear,
+    Estimate,
+    /// Исправленные варианты вопроса, по которому ничего не нашлось
+    Suggest,
+    /// Данные пользователя; к пути добавляется `/<user_id>`
+    Users,
+    TelegramLink,
+    /// Описание таблиц и колонок для `/schema`
+    Schema,
+    Health,
+}
+
+impl BackendEndpoint {
+    pub const ALL: [Self; 11] = [
+        Self::Query,
+        Self::QueryStream,
+        Self::Chat,
+        Self::Upload,
+        Self::ContextClear,
+        Self::Estimate,
+        Self::Suggest,
+        Self::Users,
+        Self::TelegramLink,
+        Self::Schema,
+        Self::Health,
+    ];
+
+    /// Название в `BACKEND_ENDPOINTS`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Query => "query",
+            Self::QueryStream => "query_stream",
+            Self::Chat => "chat",
+            Self::Upload => "upload",
+            Self::ContextClear => "context_clear",
+            Self::Estimate => "estimate",
+            Self::Suggest => "suggest",
+            Self::Users => "users",
+            Self::TelegramLink => "telegram_link",
+            Self::Schema => "schema",
+            Self::Health => "health",
+        }
+    }
+
+    /// Путь относительно префикса версии
+    fn default_path(self) -> &'static str {
+        match self {
+            Self::Query => "query",
+            Self::QueryStream => "query/stream",
+            Self::Chat => "chat",
+            Self::Upload => "upload",
+            Self::ContextClear => "context/clear",
+            Self::Estimate => "estimate",
+            Self::Suggest => "suggest",
+            Self::Users => "users",
+            Self::TelegramLink => "telegram/link",
+            Self::Schema => "schema",
+            // Через него определяется версия, поэтому он от версии не зависит
+            Self::Health => "/api/health",
+        }
+    }
+}
+
+/// Настройки путей API из переменных окружения
+#[derive(Debug, Clone)]
+pub struct EndpointConfig {
+    pub version: VersionMode,
+    /// Префикс вместо `/api` или `/api/v2` (`BACKEND_API_PREFIX`)
+    pub prefix: Option<String>,
+    /// Пути отдельных эндпоинтов (`BACKEND_ENDPOINTS`): начинающиеся с `/` - от корня,
+    /// остальные - от префикса версии
+    pub overrides: HashMap<BackendEndpoint, String>,
+}
+
+impl Default for EndpointConfig {
+    fn default() -> Self {
+        Self { version: VersionMode::Auto, prefix: None, overrides: HashMap::new() }
+    }
+}
+
+impl EndpointConfig {
+    /// Разбирает `BACKEND_ENDPOINTS`: `query=ask,chat=/legacy/chat`
+    pub fn parse_overrides(value: &str) -> Result<HashMap<BackendEndpoint, String>> {
+        let mut overrides = HashMap::new();
+        for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let Some((name, path)) = entry.split_once('=') else {
+                anyhow::bail!("BACKEND_ENDPOINTS entries must look like name=path (got {:?})", entry);
+            };
+            let name = name.trim();
+            let Some(endpoint) = BackendEndpoint::ALL.into_iter().find(|endpoint| endpoint.name() == name) else {
+                let names: Vec<_> = BackendEndpoint::ALL.iter().map(|endpoint| endpoint.name()).collect();
+                anyhow::bail!("Unknown endpoint {:?} in BACKEND_ENDPOINTS, expected one of {}", name, names.join(", "));
+            };
+            overrides.insert(endpoint, path.trim().to_string());
+        }
+        Ok(overrides)
+    }
+}
+
+/// Пути API бэкенда с учетом версии, которую он сообщил
+pub struct Endpoints {
+    config: EndpointConfig,
+    /// Версия, полученная от `/api/health` в режиме `Auto` (0 - еще неизвестна)
+    negotiated: AtomicU8,
+}
+
+impl Endpoints {
+    pub fn new(config: EndpointConfig) -> Self {
+        Self { config, negotiated: AtomicU8::new(0) }
+    }
+
+    /// Версия API, с которой сейчас работает бот
+    pub fn version(&self) -> ApiVersion {
+        match self.config.version {
+            VersionMode::Fixed(version) => version,
+            VersionMode::Auto => match self.negotiated.load(Ordering::Relaxed) {
+                2 => ApiVersion::V2,
+                _ => ApiVersion::V1,
+            },
+        }
+    }
+
+    /// Запоминает версию из ответа `/api/health` (поле `api_version`, иначе `version`).
+    /// Возвращает новую версию, если она изменилась.
+    pub fn negotiate(&self, health: &Value) -> Option<ApiVersion> {
+        if self.config.version != VersionMode::Auto {
+            return None;
+        }
+        let reported = health.get("api_version")
+            .or_else(|| health.get("version"))
+            .and_then(ApiVersion::from_reported)?;
+        let code = match reported {
+            ApiVersion::V1 => 1,
+            ApiVersion::V2 => 2,
+        };
+        (self.negotiated.swap(code, Ordering::Relaxed) != code).then_some(reported)
+    }
+
+    /// Путь эндпоинта от корня бэкенда, например `/api/v2/query`
+    pub fn path(&self, endpoint: BackendEndpoint) -> String {
+        let path = self.config.overrides
+            .get(&endpoint)
+            .map(String::as_str)
+            .unwrap_or_else(|| endpoint.default_path());
+        if path.starts_with('/') {
+            return path.to_string();
+        }
+        let prefix = self.config.prefix.as_deref().unwrap_or_else(|| self.version().prefix());
+        format!("{}/{}", prefix.trim_end_matches('/'), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn auto_mode_switches_paths_after_health_reports_v2() {
+        let endpoints = Endpoints::new(EndpointConfig::default());
+        assert_eq!(endpoints.path(BackendEndpoint::Query), "/api/query");
+
+        assert_eq!(endpoints.negotiate(&json!({"status": "ok", "api_version": "v2"})), Some(ApiVersion::V2));
+        assert_eq!(endpoints.path(BackendEndpoint::QueryStream), "/api/v2/query/stream");
+        assert_eq!(endpoints.path(BackendEndpoint::Health), "/api/health");
+        // Повторная проверка с той же версией ничего не меняет
+        assert_eq!(endpoints.negotiate(&json!({"version": "2.3.1"})), None);
+
+        assert_eq!(endpoints.negotiate(&json!({"version": 1})), Some(ApiVersion::V1));
+        assert_eq!(endpoints.path(BackendEndpoint::Chat), "/api/chat");
+    }
+
+    #[test]
+    fn overrides_are_relative_to_prefix_unless_absolute() {
+        let config = EndpointConfig {
+            version: VersionMode::Fixed(ApiVersion::V2),
+            prefix: None,
+            overrides: EndpointConfig::parse_overrides("query=ask, health=/healthz").unwrap(),
+        };
+        let endpoints = Endpoints::new(config);
+        assert_eq!(endpoints.path(BackendEndpoint::Query), "/api/v2/ask");
+        assert_eq!(endpoints.path(BackendEndpoint::Health), "/healthz");
+        assert_eq!(endpoints.negotiate(&json!({"version": "1"})), None);
+
+        assert!(EndpointConfig::parse_overrides("search=/find").is_err());
+    }
+}
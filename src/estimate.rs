@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Фразы, после которых запрос может сканировать всю базу
+const HEAVY_KEYWORDS: &[&str] = &[
+    "за все время",
+    "за всё время",
+    "за всю историю",
+    "все транзакции",
+    "всех транзакций",
+    "all time",
+    "all transactions",
+    "барлық уақыт",
+];
+
+/// Сколько ждем подтверждения отложенного запроса
+const PENDING_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Похоже ли, что вопрос запустит полное сканирование базы
+pub fn is_potentially_heavy(text: &str) -> bool {
+    let text = text.to_lowercase();
+    HEAVY_KEYWORDS.iter().any(|keyword| text.contains(keyword))
+}
+
+/// Текст с оценкой запроса и просьбой подтвердить выполнение
+pub fn format_confirmation(estimate: Option<&crate::api_client::EstimateResponse>) -> String {
+    let mut text = String::from("🐢 <b>Запрос может быть тяжелым</b>\n\n");
+    match estimate {
+        Some(estimate) => {
+            text.push_str(&format!("Будет просканировано ~{} строк", estimate.estimated_rows));
+            if let Some(ms) = estimate.estimated_time_ms {
+                text.push_str(&format!(", ориентировочное время ~{:.1} с", ms as f64 / 1000.0));
+            }
+            text.push_str(".\n");
+        }
+        None => text.push_str("Похоже, запрос затрагивает всю историю транзакций, оценить объем заранее не удалось.\n"),
+    }
+    text.push_str("\nВыполнить запрос? Можно уточнить период, например «за последний месяц».");
+    text
+}
+
+struct PendingQuery {
+    user_id: String,
+    question: String,
+    created_at: Instant,
+}
+
+/// Запросы, ожидающие подтверждения пользователя
+#[derive(Default)]
+pub struct PendingQueries {
+    next_id: AtomicU64,
+    queries: Mutex<HashMap<String, PendingQuery>>,
+}
+
+impl PendingQueries {
+    /// Сохраняет вопрос и возвращает короткий id для callback-кнопок
+    pub async fn insert(&self, user_id: &str, question: &str) -> String {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+        let mut queries = self.queries.lock().await;
+        queries.retain(|_, pending| pending.created_at.elapsed() < PENDING_TTL);
+        queries.insert(id.clone(), PendingQuery {
+            user_id: user_id.to_string(),
+            question: question.to_string(),
+            created_at: Instant::now(),
+        });
+        id
+    }
+
+    /// Забирает вопрос; подтвердить может только тот же пользователь
+    pub async fn take(&self, id: &str, user_id: &str) -> Option<String> {
+        let mut queries = self.queries.lock().await;
+        let pending = queries.get(id)?;
+        if pending.user_id != user_id || pending.created_at.elapsed() >= PENDING_TTL {
+            return None;
+        }
+        queries.remove(id).map(|pending| pending.question)
+    }
+}
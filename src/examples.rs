@@ -0,0 +1,189 @@
+use crate::i18n::{tr, Msg};
+use crate::language::Language;
+use crate::onboarding::UserRole;
+use crate::state::BotState;
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode};
+
+/// Тема примеров `/examples`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Category {
+    Volumes,
+    Merchants,
+    Geography,
+    Trends,
+}
+
+impl Category {
+    /// Порядок тем: руководителю сначала объемы и динамика, аналитику - разрезы по мерчантам и географии
+    fn ordered(role: Option<UserRole>) -> [Self; 4] {
+        match role {
+            Some(UserRole::Manager) => [Self::Volumes, Self::Trends, Self::Geography, Self::Merchants],
+            _ => [Self::Merchants, Self::Geography, Self::Volumes, Self::Trends],
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Volumes => "volumes",
+            Self::Merchants => "merchants",
+            Self::Geography => "geo",
+            Self::Trends => "trends",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        [Self::Volumes, Self::Merchants, Self::Geography, Self::Trends]
+            .into_iter()
+            .find(|category| category.as_str() == value)
+    }
+
+    fn label(self, lang: Language) -> &'static str {
+        match (self, lang) {
+            (Self::Volumes, Language::Ru) => "💰 Объемы",
+            (Self::Volumes, Language::En) => "💰 Volumes",
+            (Self::Volumes, Language::Kk) => "💰 Көлемдер",
+            (Self::Merchants, Language::Ru) => "🏪 Мерчанты",
+            (Self::Merchants, Language::En) => "🏪 Merchants",
+            (Self::Merchants, Language::Kk) => "🏪 Мерчанттар",
+            (Self::Geography, Language::Ru) => "🌍 География",
+            (Self::Geography, Language::En) => "🌍 Geography",
+            (Self::Geography, Language::Kk) => "🌍 География",
+            (Self::Trends, Language::Ru) => "📈 Динамика",
+            (Self::Trends, Language::En) => "📈 Trends",
+            (Self::Trends, Language::Kk) => "📈 Динамика",
+        }
+    }
+
+    fn questions(self, lang: Language) -> &'static [&'static str] {
+        match (self, lang) {
+            (Self::Volumes, Language::Ru) => &[
+                "Общий объем и количество транзакций за вчера",
+                "Средний чек за последние 30 дней",
+                "Распределение транзакций по валютам",
+                "Объем транзакций по типам карт за месяц",
+            ],
+            (Self::Volumes, Language::En) => &[
+                "Total volume and number of transactions yesterday",
+                "Average ticket for the last 30 days",
+                "Transactions by currency",
+                "Transaction volume by card type this month",
+            ],
+            (Self::Volumes, Language::Kk) => &[
+                "Кешегі транзакциялардың жалпы көлемі мен саны",
+                "Соңғы 30 күндегі орташа чек",
+                "Транзакциялардың валюталар бойынша бөлінуі",
+                "Айдағы карта түрлері бойынша транзакция көлемі",
+            ],
+            (Self::Merchants, Language::Ru) => &[
+                "Топ-10 мерчантов по объему транзакций за месяц",
+                "Топ-10 категорий MCC по количеству транзакций",
+                "Средний чек по категориям MCC",
+                "Мерчанты с наибольшим ростом оборота за неделю",
+            ],
+            (Self::Merchants, Language::En) => &[
+                "Top 10 merchants by transaction volume this month",
+                "Top 10 MCC categories by number of transactions",
+                "Average ticket by MCC category",
+                "Merchants with the biggest turnover growth this week",
+            ],
+            (Self::Merchants, Language::Kk) => &[
+                "Айдағы транзакция көлемі бойынша топ-10 мерчант",
+                "Транзакция саны бойынша топ-10 MCC санаты",
+                "MCC санаттары бойынша орташа чек",
+                "Аптадағы айналымы ең көп өскен мерчанттар",
+            ],
+            (Self::Geography, Language::Ru) => &[
+                "Топ-10 городов по объему транзакций",
+                "Распределение транзакций по странам",
+                "Средний чек в Astana и Almaty",
+                "Доля зарубежных транзакций за месяц",
+            ],
+            (Self::Geography, Language::En) => &[
+                "Top 10 cities by transaction volume",
+                "Transactions by country",
+                "Average ticket in Astana and Almaty",
+                "Share of foreign transactions this month",
+            ],
+            (Self::Geography, Language::Kk) => &[
+                "Транзакция көлемі бойынша топ-10 қала",
+                "Транзакциялардың елдер бойынша бөлінуі",
+                "Astana мен Almaty-дағы орташа чек",
+                "Айдағы шетелдік транзакциялардың үлесі",
+            ],
+            (Self::Trends, Language::Ru) => &[
+                "Динамика транзакций по дням за последние 7 дней",
+                "Объем транзакций по неделям за последние 30 дней",
+                "Распределение транзакций по часам суток",
+                "Сравнение объема транзакций этого месяца с прошлым",
+            ],
+            (Self::Trends, Language::En) => &[
+                "Daily transactions for the last 7 days",
+                "Weekly transaction volume for the last 30 days",
+                "Transactions by hour of day",
+                "Transaction volume this month compared with last month",
+            ],
+            (Self::Trends, Language::Kk) => &[
+                "Соңғы 7 күндегі транзакциялардың күндік динамикасы",
+                "Соңғы 30 күндегі транзакция көлемі апталар бойынша",
+                "Транзакциялардың тәулік сағаттары бойынша бөлінуі",
+                "Осы айдағы транзакция көлемін өткен аймен салыстыру",
+            ],
+        }
+    }
+}
+
+/// Темы примеров (`ex:<тема>`) в порядке, удобном для роли
+fn categories_keyboard(lang: Language, role: Option<UserRole>) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(Category::ordered(role).chunks(2).map(|row| {
+        row.iter()
+            .map(|category| InlineKeyboardButton::callback(category.label(lang), format!("ex:{}", category.as_str())))
+            .collect::<Vec<_>>()
+    }))
+}
+
+/// Вопросы темы: нажатие сразу выполняет вопрос (`q:<hash>`, как у подсказок), «Назад» - к списку тем
+fn questions_keyboard(state: &BotState, lang: Language, category: Category) -> InlineKeyboardMarkup {
+    let questions = category.questions(lang).iter().map(|question| {
+        vec![InlineKeyboardButton::callback(*question, format!("q:{}", state.suggestions.insert(question)))]
+    });
+    InlineKeyboardMarkup::new(
+        questions.chain([vec![InlineKeyboardButton::callback(crate::menu::BACK_BUTTON, "ex:back")]]),
+    )
+}
+
+/// `/examples` - примеры вопросов по темам на языке интерфейса
+pub async fn send(bot: &Bot, msg: &Message, state: &BotState) -> ResponseResult<()> {
+    let user_id = state.user_key(msg);
+    let lang = state.ui_language(&user_id, msg.from()).await;
+    let role = state.storage.settings(&user_id).await.role;
+
+    bot.send_message(msg.chat.id, tr(lang, Msg::ExamplesTitle))
+        .parse_mode(ParseMode::Html)
+        .reply_markup(categories_keyboard(lang, role))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// Кнопки `/examples`: `<тема>` открывает вопросы темы, `back` возвращает к темам
+pub async fn handle_callback(
+    bot: Bot,
+    msg: Message,
+    user: UserId,
+    action: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let user_id = state.context_scope.key(msg.chat.id, Some(user));
+    let lang = state.ui_language(&user_id, None).await;
+
+    let keyboard = match Category::parse(action) {
+        Some(category) => questions_keyboard(&state, lang, category),
+        None => categories_keyboard(lang, state.storage.settings(&user_id).await.role),
+    };
+    let _ = bot.edit_message_reply_markup(msg.chat.id, msg.id)
+        .reply_markup(keyboard)
+        .await;
+    Ok(())
+}
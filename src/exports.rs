@@ -0,0 +1,189 @@
+use crate::api_client::QueryResponse;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use teloxide::types::{InlineKeyboardButton, ReplyMarkup};
+use tokio::sync::RwLock;
+
+/// Форматы выгрузки результата
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Parquet,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "parquet" => Some(Self::Parquet),
+            "xlsx" => Some(Self::Xlsx),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+            Self::Parquet => "parquet",
+            Self::Xlsx => "xlsx",
+        }
+    }
+
+    /// MIME-тип файла (для загрузки в объектное хранилище)
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv",
+            Self::Json => "application/json",
+            Self::Parquet => "application/vnd.apache.parquet",
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+
+    /// Сериализует данные в файл выбранного формата
+    pub fn render(&self, data: &[Value], csv_delimiter: CsvDelimiter) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Csv => crate::utils::format_as_csv(data, csv_delimiter.byte()),
+            Self::Json => crate::utils::format_as_json(data),
+            Self::Parquet => crate::utils::format_as_parquet(data),
+            Self::Xlsx => crate::utils::format_as_xlsx(data),
+        }
+    }
+}
+
+/// Когда к ответу с данными автоматически прикладывается CSV (`/settings`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvAttachment {
+    /// К каждому ответу с данными
+    Always,
+    /// Только по кнопке «📥 CSV» или просьбе в вопросе
+    #[default]
+    OnDemand,
+    /// Если в результате больше стольких строк
+    AboveRows(usize),
+}
+
+impl CsvAttachment {
+    /// `always`, `demand` или порог строк числом
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "always" | "всегда" => Some(Self::Always),
+            "demand" | "off" | "по запросу" => Some(Self::OnDemand),
+            rows => rows.parse().ok().map(Self::AboveRows),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Always => "всегда".to_string(),
+            Self::OnDemand => "по кнопке «📥 CSV»".to_string(),
+            Self::AboveRows(rows) => format!("если строк больше {}", rows),
+        }
+    }
+
+    /// Нужно ли приложить CSV к результату из `rows` строк
+    pub fn applies_to(&self, rows: usize) -> bool {
+        match self {
+            Self::Always => rows > 0,
+            Self::OnDemand => false,
+            Self::AboveRows(threshold) => rows > *threshold,
+        }
+    }
+}
+
+/// Разделитель колонок CSV (`/settings csvsep`). Excel с русской локалью ожидает `;`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvDelimiter {
+    #[default]
+    Comma,
+    Semicolon,
+}
+
+impl CsvDelimiter {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "," | "comma" | "запятая" => Some(Self::Comma),
+            ";" | "semicolon" | "точка с запятой" => Some(Self::Semicolon),
+            _ => None,
+        }
+    }
+
+    /// Значение для `/settings csvsep` и кнопок настроек
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Comma => "comma",
+            Self::Semicolon => "semicolon",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Comma => "запятая",
+            Self::Semicolon => "точка с запятой",
+        }
+    }
+
+    fn byte(self) -> u8 {
+        match self {
+            Self::Comma => b',',
+            Self::Semicolon => b';',
+        }
+    }
+}
+
+/// Формат файла, о котором пользователь попросил в тексте вопроса («выгрузи в excel»)
+pub fn requested_format(text: &str) -> Option<ExportFormat> {
+    let text = text.to_lowercase();
+    if ["xlsx", "excel", "эксель", "excel-файл"].iter().any(|k| text.contains(k)) {
+        Some(ExportFormat::Xlsx)
+    } else if text.contains("parquet") {
+        Some(ExportFormat::Parquet)
+    } else if text.contains("csv") {
+        Some(ExportFormat::Csv)
+    } else {
+        None
+    }
+}
+
+/// Клавиатура выбора формата выгрузки под ответом с данными
+/// (и кнопка PDF-отчета, если для него есть шрифт)
+pub fn attach_export_buttons(keyboard: Option<ReplyMarkup>, has_data: bool, with_pdf: bool) -> Option<ReplyMarkup> {
+    if !has_data {
+        return keyboard;
+    }
+    let keyboard = crate::utils::append_keyboard_row(keyboard, vec![
+        InlineKeyboardButton::callback("📥 CSV", "export:csv"),
+        InlineKeyboardButton::callback("📥 XLSX", "export:xlsx"),
+        InlineKeyboardButton::callback("📥 Parquet", "export:parquet"),
+        InlineKeyboardButton::callback("📥 JSON", "export:json"),
+    ]);
+    if !with_pdf {
+        return keyboard;
+    }
+    crate::utils::append_keyboard_row(keyboard, vec![
+        InlineKeyboardButton::callback("📄 PDF отчёт", "export:pdf"),
+    ])
+}
+
+/// Последний ответ с данными каждого пользователя (только в памяти)
+#[derive(Default)]
+pub struct LastResults {
+    results: RwLock<HashMap<String, Arc<QueryResponse>>>,
+}
+
+impl LastResults {
+    pub async fn store(&self, user_id: &str, response: &QueryResponse) {
+        self.results.write().await.insert(user_id.to_string(), Arc::new(response.clone()));
+    }
+
+    pub async fn get(&self, user_id: &str) -> Option<Arc<QueryResponse>> {
+        self.results.read().await.get(user_id).cloned()
+    }
+}
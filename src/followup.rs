@@ -0,0 +1,84 @@
+use std::collections::{HashMap, VecDeque};
+use teloxide::types::{ChatId, MessageId};
+use tokio::sync::Mutex;
+
+/// Сколько ответов помним для уточнений
+const MAX_REMEMBERED_ANSWERS: usize = 2000;
+
+#[derive(Default)]
+struct AnswersInner {
+    questions: HashMap<(ChatId, MessageId), String>,
+    order: VecDeque<(ChatId, MessageId)>,
+}
+
+/// Вопросы, на которые отвечают сообщения бота. Ответ (reply) на такое сообщение
+/// считается уточнением: «только за март» превращается в исходный вопрос с уточнением.
+#[derive(Default)]
+pub struct AnsweredQuestions {
+    inner: Mutex<AnswersInner>,
+}
+
+impl AnsweredQuestions {
+    pub async fn insert(&self, chat_id: ChatId, message_id: MessageId, question: &str) {
+        let mut inner = self.inner.lock().await;
+        if inner.questions.insert((chat_id, message_id), question.to_string()).is_none() {
+            inner.order.push_back((chat_id, message_id));
+        }
+        while inner.order.len() > MAX_REMEMBERED_ANSWERS {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.questions.remove(&oldest);
+            }
+        }
+    }
+
+    pub async fn get(&self, chat_id: ChatId, message_id: MessageId) -> Option<String> {
+        self.inner.lock().await.questions.get(&(chat_id, message_id)).cloned()
+    }
+}
+
+#[derive(Default)]
+struct AnswerMessagesInner {
+    messages: HashMap<(ChatId, MessageId), Vec<MessageId>>,
+    order: VecDeque<(ChatId, MessageId)>,
+}
+
+/// Сообщения бота, отправленные в ответ на вопрос (ход запроса, ответ, диаграмма, файлы).
+/// Когда пользователь исправляет вопрос, они удаляются, а вопрос выполняется заново.
+#[derive(Default)]
+pub struct AnswerMessages {
+    inner: Mutex<AnswerMessagesInner>,
+}
+
+impl AnswerMessages {
+    /// Добавляет сообщение `answer` к ответу на вопрос `question`
+    pub async fn add(&self, chat_id: ChatId, question: MessageId, answer: MessageId) {
+        let mut inner = self.inner.lock().await;
+        let key = (chat_id, question);
+        if !inner.messages.contains_key(&key) {
+            inner.order.push_back(key);
+        }
+        inner.messages.entry(key).or_default().push(answer);
+        while inner.order.len() > MAX_REMEMBERED_ANSWERS {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.messages.remove(&oldest);
+            }
+        }
+    }
+
+    /// Забирает сообщения ответа на вопрос; `None`, если бот на этот вопрос не отвечал (или давно забыл ответ)
+    pub async fn take(&self, chat_id: ChatId, question: MessageId) -> Option<Vec<MessageId>> {
+        let mut inner = self.inner.lock().await;
+        let messages = inner.messages.remove(&(chat_id, question))?;
+        inner.order.retain(|key| *key != (chat_id, question));
+        Some(messages)
+    }
+}
+
+/// Исходный вопрос вместе с уточнением
+pub fn refine(question: &str, refinement: &str) -> String {
+    format!(
+        "{}. Уточнение: {}",
+        question.trim().trim_end_matches(['.', '?', '!']),
+        refinement.trim()
+    )
+}
@@ -1,13 +1,31 @@
-use crate::api_client::{ApiClient, QueryRequest};
-use crate::utils::{format_query_response, format_error, format_help, create_suggestions_keyboard};
+use crate::api_client::QueryRequest;
+use crate::query_parser::detect_output_format;
+use crate::i18n::{fill, tr, Msg};
+use crate::language::Language;
+use crate::messenger::Outgoing;
+use crate::progress::Progress;
+use crate::sender::ResponseSender;
+use crate::state::BotState;
+use crate::routing::{force_sql, is_forced_sql, QueryMode};
+use crate::utils::{format_error, format_backend_error, format_help, escape_html};
 use teloxide::prelude::*;
-use teloxide::types::Message;
-use tracing::{info, error};
+use teloxide::types::{Message, MessageId};
+use tracing::{info, error, warn};
 use std::sync::Arc;
 
-pub async fn handle_message(bot: Bot, msg: Message, api_client: Arc<ApiClient>) -> ResponseResult<()> {
-    let user_id = msg.chat.id.to_string();
-    let text = msg.text().unwrap_or_default().trim();
+pub async fn handle_message(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    let lang = state.ui_language(&user_id, msg.from()).await;
+    if let Some(document) = msg.document().cloned() {
+        return crate::uploads::handle_document(bot, msg, state, &document).await;
+    }
+    if msg.photo().is_some() {
+        return crate::uploads::handle_photo(bot, msg).await;
+    }
+    let Some(text) = addressed_text(&msg, &state.bot_username) else {
+        return Ok(());
+    };
+    let text = text.as_str();
 
     if text.is_empty() {
         return Ok(());
@@ -16,72 +34,128 @@ pub async fn handle_message(bot: Bot, msg: Message, api_client: Arc<ApiClient>)
     info!("Received message from user {}: {}", user_id, text);
 
     // Обрабатываем кнопки меню
-    use crate::menu::button_to_query;
+    use crate::menu::{button_to_query, CLEAR_BUTTON, HELP_BUTTON, MENU_BUTTON};
     
     // Проверяем специальные кнопки
     match text {
-        "❓ Помощь" => {
-            return handle_help(bot, msg).await;
+        MENU_BUTTON => {
+            return send_menu(&bot, &msg, &state).await;
         }
-        "🔄 Очистить контекст" => {
-            return handle_clear(bot, msg, api_client).await;
+        HELP_BUTTON => {
+            return handle_help(bot, msg, state).await;
+        }
+        CLEAR_BUTTON => {
+            return handle_clear(bot, msg, state).await;
         }
         _ => {
-            // Проверяем, является ли это кнопкой меню с запросом
-            if let Some(query) = button_to_query(text) {
-                // Это кнопка меню, преобразуем в запрос
-                // Отправляем сообщение "обрабатывается"
-                let processing_msg = bot.send_message(msg.chat.id, "⏳ <b>Обрабатываю запрос...</b>")
-                    .parse_mode(teloxide::types::ParseMode::Html)
-                    .reply_to_message_id(msg.id)
-                    .await?;
-                
-                let _ = bot.send_chat_action(msg.chat.id, teloxide::types::ChatAction::Typing).await;
-                
-                // Определяем формат вывода из запроса
-                let (clean_query, output_type) = detect_output_format(&query);
-                
-                let query_request = QueryRequest {
-                    question: clean_query,
-                    include_analysis: true, // Для кнопок меню всегда включаем анализ
-                    use_cache: true,
-                    include_sql: false,
-                    user_id: Some(user_id.clone()),
-                    output_type,
-                };
-                
-                match api_client.query(query_request).await {
-                    Ok(response) => {
-                        // Удаляем сообщение "обрабатывается"
-                        let _ = bot.delete_message(msg.chat.id, processing_msg.id).await;
-                        // Обрабатываем ответ так же, как обычное сообщение
-                        return process_query_response(bot, msg, response, api_client).await;
-                    }
-                    Err(e) => {
-                        // Удаляем сообщение "обрабатывается" даже при ошибке
-                        let _ = bot.delete_message(msg.chat.id, processing_msg.id).await;
-                        error!("Error processing menu button query: {}", e);
-                        bot.send_message(msg.chat.id, &format_error(&format!("Не удалось обработать запрос: {}", e)))
-                            .parse_mode(teloxide::types::ParseMode::Html)
-                            .await?;
-                        return Ok(());
-                    }
-                }
+            // Готовый запрос с клавиатуры, добавленный по роли пользователя
+            if let Some(query) = crate::onboarding::quick_query(text) {
+                let user_id = state.user_key(&msg);
+                return run_canned_query(bot, msg, state, user_id, query).await;
+            }
+            // Кнопка запроса со старой постоянной клавиатуры
+            if let Some(query) = button_to_query(&state.storage.menu().await, text) {
+                let user_id = state.user_key(&msg);
+                return run_canned_query(bot, msg, state, user_id, &query).await;
             }
         }
     }
 
-    // Отправляем сообщение "обрабатывается"
-    let processing_msg = bot.send_message(msg.chat.id, "⏳ <b>Обрабатываю запрос...</b>")
-        .parse_mode(teloxide::types::ParseMode::Html)
-        .reply_to_message_id(msg.id)
-        .await?;
-    
-    // Отправляем индикатор печати
-    let _ = bot.send_chat_action(msg.chat.id, teloxide::types::ChatAction::Typing).await;
+    // Ответ на вопрос о параметре кнопки меню
+    if crate::prompts::handle_text(&bot, &msg, &state, text).await? {
+        return Ok(());
+    }
+
+    // Короткое вычисление по уже полученному результату считается без бэкенда
+    if answer_from_cached_rows(&bot, &msg, &state, &user_id, text, lang).await? {
+        return Ok(());
+    }
+
+    if reject_if_backend_down(&bot, &msg, &state, lang).await?
+        || reject_if_rate_limited(&bot, &msg, &state, lang).await?
+    {
+        return Ok(());
+    }
+
+    // Ответ на сообщение бота с результатом - уточнение исходного вопроса («только за март»)
+    let refined = match msg.reply_to_message() {
+        Some(reply) => state.answered_questions.get(msg.chat.id, reply.id).await
+            .map(|question| crate::followup::refine(&question, text)),
+        None => None,
+    };
+    if let Some(refined) = &refined {
+        info!("Refining a previous answer for user {}: {}", user_id, refined);
+    }
+    let text = refined.as_deref().unwrap_or(text);
+
+    let text = match state.storage.settings(&user_id).await.query_mode {
+        QueryMode::Chat => {
+            let text = text.to_string();
+            return run_chat(bot, msg, state, user_id, &text).await;
+        }
+        QueryMode::Sql => force_sql(text),
+        QueryMode::Auto => text.to_string(),
+    };
+
+    // Тяжелые запросы (например, «за все время») сначала оцениваем и просим подтверждение
+    if crate::estimate::is_potentially_heavy(&text)
+        && ask_heavy_query_confirmation(&bot, &msg, &state, &user_id, &text).await?
+    {
+        return Ok(());
+    }
+
+    run_question(bot, msg, state, user_id, &text).await
+}
+
+/// Текст сообщения, адресованного боту. В группах бот отвечает только на упоминание
+/// (`@имя_бота ...`) или ответ на свое сообщение; упоминание из вопроса убирается.
+/// `None`, если сообщение обращено не к боту.
+fn addressed_text(msg: &Message, bot_username: &str) -> Option<String> {
+    let text = msg.text()?.trim();
+    if msg.chat.is_private() {
+        return Some(text.to_string());
+    }
+
+    let mention = format!("@{}", bot_username.to_lowercase());
+    let mentioned = !bot_username.is_empty() && text.to_lowercase().contains(&mention);
+    let replied_to_bot = msg.reply_to_message()
+        .and_then(|reply| reply.from())
+        .is_some_and(|author| author.username.as_deref() == Some(bot_username));
+
+    if !mentioned && !replied_to_bot {
+        return None;
+    }
+
+    // Убираем упоминание без учета регистра (username в Telegram регистронезависим)
+    let question = text
+        .split_whitespace()
+        .filter(|word| word.to_lowercase().trim_end_matches([',', ':']) != mention)
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(question)
+}
+
+/// Выполняет произвольный вопрос пользователя (SQL-запрос с откатом на chat API)
+pub async fn run_question<S: crate::messenger::MessageSender>(
+    bot: S,
+    msg: Message,
+    state: Arc<BotState>,
+    user_id: String,
+    text: &str,
+) -> ResponseResult<()> {
+    let lang = state.ui_language(&user_id, msg.from()).await;
+    let Some(_running) = start_unless_running(&bot, &msg, &state, lang, text).await? else {
+        return Ok(());
+    };
+
+    // Сообщение "обрабатывается" редактируется по ходу запроса и становится ответом
+    let progress = Progress::start(&bot, &msg, &state, lang).await?;
+
+    // Просьба ответить на другом языке («ответь на английском»)
+    let (text, requested_language) = crate::language::detect_answer_language(text);
 
     // Определяем формат вывода из запроса
-    let (clean_text, output_type) = detect_output_format(text);
+    let (clean_text, output_type) = detect_output_format(&text);
 
     // Определяем, нужен ли анализ
     let include_analysis = clean_text.to_lowercase().contains("с анализом") 
@@ -95,485 +169,341 @@ pub async fn handle_message(bot: Bot, msg: Message, api_client: Arc<ApiClient>)
         .to_string();
 
     // Пытаемся сначала как SQL-запрос
+    let zone = state.user_zone(&user_id).await;
+    let period = zone.question_period(&question);
     let query_request = QueryRequest {
         question: question.clone(),
         include_analysis,
         use_cache: true,
-        include_sql: false, // Не показываем SQL в Telegram
+        include_sql: true, // SQL показывается по кнопке «🔍 Показать SQL»
         user_id: Some(user_id.clone()),
-        output_type,
+        output_type: preferred_output_type(&state, &user_id, output_type).await,
+        language: answer_language(&state, &user_id, requested_language).await,
+        date_from: period.map(|period| period.from),
+        date_to: period.map(|period| period.to),
+        timezone: Some(zone.name()),
     };
 
-    match api_client.query(query_request).await {
+    let output_type = query_request.output_type;
+    match progress.query(&state, query_request).await {
         Ok(response) => {
-            // Удаляем сообщение "обрабатывается"
-            let _ = bot.delete_message(msg.chat.id, processing_msg.id).await;
-            
-            // Если есть текстовый ответ (обычный вопрос)
-            if let Some(text_response) = &response.text_response {
-                bot.send_message(msg.chat.id, text_response)
-                    .parse_mode(teloxide::types::ParseMode::Html)
-                    .await?;
-                return Ok(());
-            }
-
-            // Отправляем CSV файл, если есть данные
-            if !response.data.is_empty() {
-                use crate::utils::format_as_csv;
-                let csv_content = format_as_csv(&response.data);
-                if !csv_content.is_empty() {
-                    let filename = format!("data_{}.csv", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
-                    // Создаем временный файл
-                    let temp_path = std::env::temp_dir().join(&filename);
-                    std::fs::write(&temp_path, csv_content.as_bytes())?;
-                    bot.send_document(msg.chat.id, teloxide::types::InputFile::file(&temp_path))
-                        .caption("📊 Данные в формате CSV")
-                        .await?;
-                    let _ = std::fs::remove_file(&temp_path);
-                }
-            }
-            
-            // Отправляем диаграмму, если есть данные для неё
-            if let Some(chart_data) = &response.chart_data {
-                use crate::utils::generate_chart_image;
-                // Генерируем изображение синхронно перед await
-                let image_result = generate_chart_image(chart_data, 1000, 700);
-                match image_result {
-                    Ok(image_bytes) => {
-                        let temp_path = std::env::temp_dir().join(format!("chart_{}.png", std::process::id()));
-                        if let Ok(_) = std::fs::write(&temp_path, &image_bytes) {
-                            if let Err(e) = bot.send_photo(msg.chat.id, teloxide::types::InputFile::file(&temp_path))
-                                .caption("📈 Визуализация данных")
-                                .await {
-                                error!("Failed to send chart image: {}", e);
-                            }
-                            let _ = std::fs::remove_file(&temp_path);
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to generate chart image: {}", e);
-                    }
-                }
-            }
-            
-            // Форматируем ответ
-            let formatted = format_query_response(&response);
-            
-            // Создаем клавиатуру с предложениями, если есть анализ
-            // Показываем кнопки с подсказками всегда, если они есть
-            let keyboard = if let Some(analysis) = &response.analysis {
-                if !analysis.suggested_questions.is_empty() {
-                    Some(create_suggestions_keyboard(&analysis.suggested_questions))
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
-            
-            // Если нет анализа, но есть данные - предлагаем стандартные вопросы
-            let keyboard = keyboard.or_else(|| {
-                if !response.data.is_empty() && response.row_count > 0 {
-                    let suggestions = vec![
-                        "📊 Показать больше данных".to_string(),
-                        "📈 С анализом".to_string(),
-                    ];
-                    Some(create_suggestions_keyboard(&suggestions))
-                } else {
-                    None
-                }
-            });
-            
-            // Отправляем ответ (Telegram ограничивает длину сообщения)
-            if formatted.len() > 4096 {
-                // Разбиваем на части с учетом UTF-8 границ
-                let mut chunks = Vec::new();
-                let mut current = String::new();
-                
-                for line in formatted.lines() {
-                    if current.len() + line.len() + 1 > 4000 {
-                        if !current.is_empty() {
-                            chunks.push(current.clone());
-                            current.clear();
-                        }
-                    }
-                    if !current.is_empty() {
-                        current.push('\n');
-                    }
-                    current.push_str(line);
-                }
-                if !current.is_empty() {
-                    chunks.push(current);
-                }
-                
-                // Отправляем все части кроме последней
-                for chunk in chunks.iter().take(chunks.len().saturating_sub(1)) {
-                    bot.send_message(msg.chat.id, chunk)
-                        .parse_mode(teloxide::types::ParseMode::Html)
-                        .await?;
-                }
-                
-                // Последняя часть с клавиатурой
-                let mut last_msg = bot.send_message(msg.chat.id, chunks.last().unwrap_or(&formatted))
-                    .parse_mode(teloxide::types::ParseMode::Html);
-                
-                if let Some(kb) = keyboard {
-                    last_msg = last_msg.reply_markup(kb);
-                }
-                
-                last_msg.await?;
-            } else {
-                let mut message = bot.send_message(msg.chat.id, &formatted)
-                    .parse_mode(teloxide::types::ParseMode::Html);
-                
-                if let Some(kb) = keyboard {
-                    message = message.reply_markup(kb);
-                }
-                
-                message.await?;
-            }
+            // Файл отправляется, только если пользователь попросил о нем в вопросе
+            ResponseSender::new(&bot, &state, msg.chat.id, &user_id)
+                .with_export(crate::exports::requested_format(&text))
+                .with_output_type(output_type)
+                .send(progress, &response)
+                .await?;
         }
         Err(e) => {
-            // Удаляем сообщение "обрабатывается" даже при ошибке
-            let _ = bot.delete_message(msg.chat.id, processing_msg.id).await;
-            
             error!("Error querying backend: {}", e);
             
-            // Если ошибка SQL (обычно означает, что вопрос не про БД), 
-            // попробуем ответить через chat API
-            let error_str = e.to_string();
-            if error_str.contains("syntax error") || 
-               error_str.contains("SQL") || 
-               error_str.contains("database") {
+            // Если SQL не выполнился или бэкенд считает вопрос не связанным с данными,
+            // попробуем ответить через chat API. Явный `sql:` (/sql, режим SQL) не перенаправляем.
+            let suggests_chat = e.downcast_ref::<crate::api_error::ApiError>()
+                .is_some_and(crate::api_error::ApiError::suggests_chat);
+            if suggests_chat && !is_forced_sql(&question) {
                 info!("SQL error detected, trying chat API instead");
                 
                 // Пробуем через chat API
-                match api_client.chat(crate::api_client::ChatRequest {
-                    message: question.clone(),
-                    session_id: None,
-                    user_id: Some(user_id.clone()),
-                }).await {
-                    Ok(chat_response) => {
-                        bot.send_message(msg.chat.id, &chat_response.message)
-                            .parse_mode(teloxide::types::ParseMode::Html)
-                            .await?;
-                        return Ok(());
+                let typing = crate::typing::Typing::start(&bot, msg.chat.id);
+                let reply = ask_chat(&state, &user_id, &question, requested_language).await;
+                drop(typing);
+                match reply {
+                    Ok(reply) => {
+                        return progress.finish_backend_text(&state, &reply).await;
                     }
                     Err(chat_err) => {
                         error!("Chat API also failed: {}", chat_err);
                         // Показываем понятное сообщение
-                        bot.send_message(msg.chat.id, 
-                            "🤔 Похоже, ваш вопрос не связан с базой данных. Я могу помочь с анализом платежных транзакций.\n\nПопробуйте задать вопрос, например:\n• Сколько транзакций было сегодня?\n• Топ 10 городов по объему транзакций")
-                            .parse_mode(teloxide::types::ParseMode::Html)
-                            .await?;
-                        return Ok(());
+                        return progress.fail(
+                            "🤔 Похоже, ваш вопрос не связан с базой данных. Я могу помочь с анализом платежных транзакций.\n\nПопробуйте задать вопрос, например:\n• Сколько транзакций было сегодня?\n• Топ 10 городов по объему транзакций",
+                        ).await;
                     }
                 }
             }
             
             // Для других ошибок показываем стандартное сообщение
-            let error_msg = format_error(&format!("Не удалось обработать запрос. Попробуйте переформулировать вопрос или используйте /help для примеров."));
-            bot.send_message(msg.chat.id, &error_msg)
-                .parse_mode(teloxide::types::ParseMode::Html)
-                .await?;
+            let error_msg = format_backend_error(lang, &e, tr(lang, Msg::QueryFailed));
+            progress.fail(&error_msg).await?;
         }
     }
 
     Ok(())
 }
 
-/// Обрабатывает ответ на запрос (общая функция для переиспользования)
-async fn process_query_response(
+/// Отправляет сообщение в `/api/chat`, продолжая текущую сессию диалога
+async fn ask_chat(
+    state: &BotState,
+    user_id: &str,
+    message: &str,
+    requested_language: Option<crate::language::Language>,
+) -> anyhow::Result<String> {
+    let response = state.api_client.chat(crate::api_client::ChatRequest {
+        message: message.to_string(),
+        session_id: state.chat_sessions.current(user_id).await,
+        user_id: Some(user_id.to_string()),
+        language: answer_language(state, user_id, requested_language).await,
+    }).await?;
+    state.chat_sessions.remember(user_id, response.session_id.as_deref()).await;
+    Ok(response.message)
+}
+
+/// Отвечает через чат-ассистента (`/chat`, режим чата)
+async fn run_chat(
     bot: Bot,
     msg: Message,
-    response: crate::api_client::QueryResponse,
-    _api_client: Arc<ApiClient>,
+    state: Arc<BotState>,
+    user_id: String,
+    text: &str,
 ) -> ResponseResult<()> {
-    // Если есть текстовый ответ (обычный вопрос)
-    if let Some(text_response) = &response.text_response {
-        bot.send_message(msg.chat.id, text_response)
-            .parse_mode(teloxide::types::ParseMode::Html)
-            .await?;
+    let lang = state.ui_language(&user_id, msg.from()).await;
+    let Some(_running) = start_unless_running(&bot, &msg, &state, lang, text).await? else {
         return Ok(());
-    }
-
-    // Отправляем CSV файл, если есть данные
-    if !response.data.is_empty() {
-        use crate::utils::format_as_csv;
-        let csv_content = format_as_csv(&response.data);
-        if !csv_content.is_empty() {
-            let filename = format!("data_{}.csv", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
-            // Создаем временный файл
-            let temp_path = std::env::temp_dir().join(&filename);
-            if let Ok(_) = std::fs::write(&temp_path, csv_content.as_bytes()) {
-                let _ = bot.send_document(msg.chat.id, teloxide::types::InputFile::file(&temp_path))
-                    .caption("📊 Данные в формате CSV")
-                    .await;
-                let _ = std::fs::remove_file(&temp_path);
-            }
+    };
+    let (message, requested_language) = crate::language::detect_answer_language(text);
+    let typing = crate::typing::Typing::start(&bot, msg.chat.id);
+    let reply = ask_chat(&state, &user_id, &message, requested_language).await;
+    drop(typing);
+    match reply {
+        Ok(reply) => send_backend_text(&bot, &msg, &state, &reply).await?,
+        Err(e) => {
+            error!("Chat API failed: {}", e);
+            bot.send_message(msg.chat.id, format_backend_error(lang, &e, tr(lang, Msg::ChatFailed)))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
         }
     }
-    
-    // Отправляем диаграмму, если есть данные для неё
-    if let Some(chart_data) = &response.chart_data {
-        use crate::utils::generate_chart_image;
-        // Генерируем изображение синхронно перед await
-        let image_result = generate_chart_image(chart_data, 1000, 700);
-        match image_result {
-            Ok(image_bytes) => {
-                let temp_path = std::env::temp_dir().join(format!("chart_{}.png", std::process::id()));
-                if let Ok(_) = std::fs::write(&temp_path, &image_bytes) {
-                    if let Err(e) = bot.send_photo(msg.chat.id, teloxide::types::InputFile::file(&temp_path))
-                        .caption("📈 Визуализация данных")
-                        .await {
-                        error!("Failed to send chart image: {}", e);
-                    }
-                    let _ = std::fs::remove_file(&temp_path);
-                }
-            }
-            Err(e) => {
-                error!("Failed to generate chart image: {}", e);
-            }
-        }
+
+    Ok(())
+}
+
+/// Отправляет текстовый ответ бэкенда в ответ на `msg` в разметке `TEXT_FORMAT`
+/// (с переходом на HTML, если ответ длинный или Telegram не принял MarkdownV2)
+async fn send_backend_text(bot: &Bot, msg: &Message, state: &BotState, text: &str) -> ResponseResult<()> {
+    let rich = crate::utils::format_backend_text(text, state.text_format);
+    if !crate::utils::fits_in_message(&rich.text) {
+        let html = crate::utils::format_backend_text_html(text);
+        send_answer_text(bot, msg.chat.id, state, Some(&state.user_key(msg)), &html, None).await?;
+        return Ok(());
     }
-    
-    // Форматируем ответ
-    let formatted = format_query_response(&response);
-    
-    // Создаем клавиатуру с предложениями, если есть анализ
-    // Показываем кнопки с подсказками всегда, если они есть
-    let keyboard = if let Some(analysis) = &response.analysis {
-        if !analysis.suggested_questions.is_empty() {
-            Some(create_suggestions_keyboard(&analysis.suggested_questions))
-        } else {
-            None
-        }
-    } else {
-        None
-    };
-    
-    // Если нет анализа, но есть данные - предлагаем стандартные вопросы
-    let keyboard = keyboard.or_else(|| {
-        if !response.data.is_empty() && response.row_count > 0 {
-            let suggestions = vec![
-                "📊 Показать больше данных".to_string(),
-                "📈 С анализом".to_string(),
-            ];
-            Some(create_suggestions_keyboard(&suggestions))
-        } else {
-            None
-        }
-    });
-    
-    // Отправляем ответ (Telegram ограничивает длину сообщения)
-    if formatted.len() > 4096 {
-        // Разбиваем на части с учетом UTF-8 границ
-        let mut chunks = Vec::new();
-        let mut current = String::new();
-        
-        for line in formatted.lines() {
-            if current.len() + line.len() + 1 > 4000 {
-                if !current.is_empty() {
-                    chunks.push(current.clone());
-                    current.clear();
-                }
-            }
-            if !current.is_empty() {
-                current.push('\n');
-            }
-            current.push_str(line);
-        }
-        if !current.is_empty() {
-            chunks.push(current);
-        }
-        
-        // Отправляем все части кроме последней
-        for chunk in chunks.iter().take(chunks.len().saturating_sub(1)) {
-            bot.send_message(msg.chat.id, chunk)
+
+    let sent = bot.send_message(msg.chat.id, &rich.text)
+        .parse_mode(rich.parse_mode)
+        .reply_to_message_id(msg.id)
+        .await;
+    match sent {
+        Err(e) if matches!(rich.parse_mode, teloxide::types::ParseMode::MarkdownV2) => {
+            warn!("Failed to send answer as MarkdownV2, falling back to HTML: {}", e);
+            bot.send_message(msg.chat.id, crate::utils::format_backend_text_html(text))
                 .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
                 .await?;
         }
-        
-        // Последняя часть с клавиатурой
-        let mut last_msg = bot.send_message(msg.chat.id, chunks.last().unwrap_or(&formatted))
-            .parse_mode(teloxide::types::ParseMode::Html);
-        
-        if let Some(kb) = keyboard {
-            last_msg = last_msg.reply_markup(kb);
-        }
-        
-        last_msg.await?;
-    } else {
-        let mut message = bot.send_message(msg.chat.id, &formatted)
-            .parse_mode(teloxide::types::ParseMode::Html);
-        
-        if let Some(kb) = keyboard {
-            message = message.reply_markup(kb);
+        sent => {
+            sent?;
         }
-        
-        message.await?;
     }
-    
     Ok(())
 }
 
-/// Определяет желаемый формат вывода из текста запроса
-/// Возвращает очищенный текст и тип вывода
-fn detect_output_format(text: &str) -> (String, crate::api_client::OutputType) {
-    let text_lower = text.to_lowercase();
-    
-    // Ключевые слова для таблицы
-    let table_keywords = [
-        "таблица", "table", "таблицу", "таблицей", 
-        "в таблице", "как таблица", "покажи таблицу",
-        "табличный", "табличный формат"
-    ];
-    
-    // Ключевые слова для диаграммы
-    let chart_keywords = [
-        "диаграмма", "chart", "график", "графиком",
-        "диаграмму", "диаграммой", "в диаграмме",
-        "как диаграмма", "покажи диаграмму", "визуализация",
-        "визуализацию", "визуализацией", "визуализировать",
-        "графически", "графический", "plot", "график",
-        "нарисуй", "построй", "visualization"
-    ];
-    
-    // Проверяем наличие ключевых слов
-    let has_table = table_keywords.iter().any(|keyword| text_lower.contains(keyword));
-    let has_chart = chart_keywords.iter().any(|keyword| text_lower.contains(keyword));
-    
-    // Определяем тип вывода
-    let output_type = if has_chart {
-        crate::api_client::OutputType::Chart
-    } else if has_table {
-        crate::api_client::OutputType::Table
-    } else if text_lower.contains("json") {
-        crate::api_client::OutputType::Json
-    } else {
-        crate::api_client::OutputType::Auto
-    };
-    
-    // Убираем ключевые слова из текста запроса
-    let mut clean_text = text.to_string();
-    let text_lower = clean_text.to_lowercase();
-    
-    // Находим все ключевые слова и убираем их
-    let mut to_remove: Vec<(usize, usize)> = Vec::new();
-    
-    // Находим позиции ключевых слов для таблицы
-    for keyword in &table_keywords {
-        let keyword_lower = keyword.to_lowercase();
-        let mut search_pos = 0;
-        while let Some(pos) = text_lower[search_pos..].find(&keyword_lower) {
-            let actual_pos = search_pos + pos;
-            to_remove.push((actual_pos, actual_pos + keyword.len()));
-            search_pos = actual_pos + keyword.len();
-        }
-    }
-    
-    // Находим позиции ключевых слов для диаграммы
-    for keyword in &chart_keywords {
-        let keyword_lower = keyword.to_lowercase();
-        let mut search_pos = 0;
-        while let Some(pos) = text_lower[search_pos..].find(&keyword_lower) {
-            let actual_pos = search_pos + pos;
-            to_remove.push((actual_pos, actual_pos + keyword.len()));
-            search_pos = actual_pos + keyword.len();
+/// Запрашивает оценку тяжелого запроса; если он превышает порог (или оценка недоступна),
+/// откладывает запрос до подтверждения. Возвращает `true`, если запрос отложен.
+async fn ask_heavy_query_confirmation(
+    bot: &Bot,
+    msg: &Message,
+    state: &BotState,
+    user_id: &str,
+    text: &str,
+) -> ResponseResult<bool> {
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    let estimate = match state.api(user_id).await.estimate(text, user_id).await {
+        Ok(estimate) => Some(estimate),
+        Err(e) => {
+            info!("Query estimate is unavailable, asking for confirmation anyway: {}", e);
+            None
         }
-    }
-    
-    // Сортируем позиции по убыванию, чтобы удалять с конца
-    to_remove.sort_by(|a, b| b.0.cmp(&a.0));
-    
-    // Удаляем ключевые слова с конца к началу
-    for (start, end) in to_remove {
-        if end <= clean_text.len() {
-            // Безопасное удаление с учетом UTF-8
-            let mut chars: Vec<char> = clean_text.chars().collect();
-            let start_char = clean_text.chars().take(start).count();
-            let end_char = clean_text.chars().take(end).count();
-            if end_char <= chars.len() {
-                chars.drain(start_char..end_char);
-                clean_text = chars.into_iter().collect();
-            }
+    };
+
+    if let Some(estimate) = &estimate {
+        if estimate.estimated_rows < state.estimate_confirm_rows {
+            return Ok(false);
         }
     }
-    
-    // Очищаем лишние пробелы и запятые
-    clean_text = clean_text
-        .split_whitespace()
-        .filter(|s| !s.is_empty())
-        .collect::<Vec<_>>()
-        .join(" ")
-        .trim()
-        .trim_matches(',')
-        .trim()
-        .to_string();
-    
-    (clean_text, output_type)
-}
 
-pub async fn handle_start(bot: Bot, msg: Message) -> ResponseResult<()> {
-    use crate::menu::create_main_menu;
-    
-    let welcome = r#"👋 <b>Добро пожаловать в Payment Analytics Bot!</b>
+    let id = state.pending_queries.insert(user_id, text).await;
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback("▶️ Выполнить", format!("heavy:run:{}", id)),
+        InlineKeyboardButton::callback("Отмена", format!("heavy:cancel:{}", id)),
+    ]]);
 
-🤖 Я умный помощник для анализа платежных транзакций.
+    bot.send_message(msg.chat.id, crate::estimate::format_confirmation(estimate.as_ref()))
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(keyboard)
+        .reply_to_message_id(msg.id)
+        .await?;
 
-Просто задавайте вопросы на естественном языке, и я сгенерирую SQL-запросы и предоставлю детальную аналитику!
+    Ok(true)
+}
 
-✨ <b>Что я умею:</b>
-• Анализ транзакций в реальном времени
-• Генерация SQL-запросов из обычных вопросов
-• Детальная аналитика с инсайтами и рекомендациями
-• Экспорт данных в CSV
-• Генерация диаграмм
-• Поддержка русского, английского и казахского языков
-• Контекстная память ваших запросов
+/// Обрабатывает кнопки подтверждения тяжелого запроса (`heavy:run:<id>` / `heavy:cancel:<id>`)
+pub async fn handle_heavy_query_callback(
+    bot: Bot,
+    msg: Message,
+    user_id: String,
+    action: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let (command, id) = action.split_once(':').unwrap_or((action, ""));
+    let Some(question) = state.pending_queries.take(id, &user_id).await else {
+        bot.edit_message_text(msg.chat.id, msg.id, "⌛ Запрос устарел, отправьте его заново")
+            .await?;
+        return Ok(());
+    };
 
-🔍 <b>ВАЖНО: Для SQL запросов к базе данных ОБЯЗАТЕЛЬНО используйте префикс:</b>
-• <code>sql:</code> - например: <code>sql: Показать транзакции за сегодня</code>
+    if command != "run" {
+        bot.edit_message_text(msg.chat.id, msg.id, "Запрос отменен").await?;
+        return Ok(());
+    }
 
-⚠️ <b>Без префикса</b> бот может неправильно определить тип запроса и ответить как в чате.
+    let _ = bot.edit_message_reply_markup(msg.chat.id, msg.id).await;
+    let lang = state.ui_language(&user_id, None).await;
+    if reject_if_backend_down(&bot, &msg, &state, lang).await?
+        || reject_if_rate_limited(&bot, &msg, &state, lang).await?
+    {
+        return Ok(());
+    }
+    run_question(bot, msg, state, user_id, &question).await
+}
 
-⚠️ <b>Важно о данных:</b> Все данные в базе на латинице (Astana, Almaty, Halyk Bank). Бот автоматически преобразует кириллицу.
+/// Запоминает ответ: журнал запросов и данные для повторной выгрузки
+pub async fn remember_response(state: &BotState, user_id: &str, response: &crate::api_client::QueryResponse) {
+    crate::audit::record(&state.storage, user_id, response).await;
+    if !response.data.is_empty() {
+        state.last_results.store(user_id, response).await;
+    }
+}
 
-💡 Используйте кнопки меню для быстрого доступа к популярным запросам или просто напишите свой вопрос!"#;
+/// Отправляет данные последнего ответа в выбранном формате (кнопки `export:<format>`)
+pub async fn handle_export_callback(
+    bot: Bot,
+    msg: Message,
+    user_id: String,
+    format: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    use crate::exports::ExportFormat;
 
-    bot.send_message(msg.chat.id, welcome)
-        .parse_mode(teloxide::types::ParseMode::Html)
-        .reply_markup(create_main_menu())
-        .reply_to_message_id(msg.id)
-        .await?;
+    let Some(response) = state.last_results.get(&user_id).await else {
+        bot.send_message(msg.chat.id, "⌛ Данные для выгрузки устарели, повторите запрос")
+            .await?;
+        return Ok(());
+    };
+    if format == "pdf" {
+        let lang = state.ui_language(&user_id, None).await;
+        let settings = state.storage.settings(&user_id).await;
+        return send_pdf_report(&bot, msg.chat.id, &state, &response, lang, &settings).await;
+    }
+    let Some(format) = ExportFormat::parse(format) else {
+        return Ok(());
+    };
 
+    let settings = state.storage.settings(&user_id).await;
+    send_export(&bot, msg.chat.id, &state, format, &response.data, &settings, None).await?;
     Ok(())
 }
 
-pub async fn handle_help(bot: Bot, msg: Message) -> ResponseResult<()> {
-    let help_text = format_help();
-    
-    bot.send_message(msg.chat.id, &help_text)
-        .parse_mode(teloxide::types::ParseMode::Html)
-        .reply_to_message_id(msg.id)
-        .await?;
+/// `/export csv|xlsx|json|parquet|pdf` - полные данные последнего ответа файлом
+/// (в сообщении показывается только часть строк); без формата - кнопки выбора
+pub async fn handle_export(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    use crate::exports::ExportFormat;
+
+    let user_id = state.user_key(&msg);
+    let Some(response) = state.last_results.get(&user_id).await else {
+        bot.send_message(msg.chat.id, "📥 Выгружать пока нечего: сначала задайте вопрос к данным")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+    let summary = format!("«{}», строк: {}", escape_html(&response.question), response.data.len());
+
+    let arg = arg.trim().to_lowercase();
+    if arg == "pdf" && state.pdf_font.is_some() {
+        let lang = state.ui_language(&user_id, msg.from()).await;
+        let settings = state.storage.settings(&user_id).await;
+        return send_pdf_report(&bot, msg.chat.id, &state, &response, lang, &settings).await;
+    }
+    let Some(format) = ExportFormat::parse(&arg) else {
+        let keyboard = crate::exports::attach_export_buttons(None, true, state.pdf_font.is_some());
+        let mut request = bot.send_message(
+            msg.chat.id,
+            format!("📥 Последний результат: {}
+
+Выберите формат или укажите его в команде: <code>/export xlsx</code>", summary),
+        )
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id);
+        if let Some(keyboard) = keyboard {
+            request = request.reply_markup(keyboard);
+        }
+        request.await?;
+        return Ok(());
+    };
 
+    let settings = state.storage.settings(&user_id).await;
+    let caption = Caption {
+        text: crate::utils::fit_caption(&format!("📥 {}: {}", format.extension().to_uppercase(), summary)),
+        keyboard: None,
+    };
+    send_export(&bot, msg.chat.id, &state, format, &response.data, &settings, Some(caption)).await?;
     Ok(())
 }
 
-pub async fn handle_clear(bot: Bot, msg: Message, api_client: Arc<ApiClient>) -> ResponseResult<()> {
-    let user_id = msg.chat.id.to_string();
-    
-    match api_client.clear_context(&user_id).await {
-        Ok(_) => {
-            bot.send_message(msg.chat.id, "✅ Контекст запросов очищен!")
-                .reply_to_message_id(msg.id)
+/// Собирает и отправляет PDF-отчет: вывод, выводы анализа, диаграмма и таблица в одном файле
+async fn send_pdf_report(
+    bot: &Bot,
+    chat_id: ChatId,
+    state: &BotState,
+    response: &Arc<crate::api_client::QueryResponse>,
+    lang: Language,
+    settings: &crate::storage::UserSettings,
+) -> ResponseResult<()> {
+    let Some(font) = state.pdf_font.clone() else {
+        return Ok(());
+    };
+    let _ = bot.send_chat_action(chat_id, teloxide::types::ChatAction::UploadDocument).await;
+
+    let zone = crate::timezone::UserZone::new(settings.time_zone, state.time_zone);
+    let chart = match &response.chart_data {
+        Some(chart_data) => match state.chart_renderer.render(chart_data, 1000, 700, lang, settings.chart_theme, zone).await {
+            Ok(png) => Some(png),
+            Err(e) => {
+                error!("Failed to render chart for PDF report: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let report = {
+        let response = response.clone();
+        tokio::task::spawn_blocking(move || crate::pdf::render_report(&font, &response, chart.as_deref(), zone))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|report| report)
+    };
+    match report {
+        Ok(bytes) => {
+            let filename = format!("report_{}.pdf", zone.file_stamp());
+            bot.send_document(chat_id, teloxide::types::InputFile::memory(bytes).file_name(filename))
+                .caption(format!("📄 {}", truncate_caption(&response.question)))
                 .await?;
         }
         Err(e) => {
-            error!("Error clearing context: {}", e);
-            bot.send_message(msg.chat.id, &format!("❌ Ошибка при очистке контекста: {}", e))
-                .reply_to_message_id(msg.id)
+            error!("Failed to build PDF report: {}", e);
+            bot.send_message(chat_id, format_error("Не удалось сформировать PDF-отчёт"))
+                .parse_mode(teloxide::types::ParseMode::Html)
                 .await?;
         }
     }
@@ -581,21 +511,730 @@ pub async fn handle_clear(bot: Bot, msg: Message, api_client: Arc<ApiClient>) ->
     Ok(())
 }
 
-pub async fn handle_status(bot: Bot, msg: Message, api_client: Arc<ApiClient>) -> ResponseResult<()> {
-    match api_client.health_check().await {
-        Ok(true) => {
-            bot.send_message(msg.chat.id, "✅ Бэкенд работает нормально!")
-                .reply_to_message_id(msg.id)
-                .await?;
+/// Подпись к документу ограничена 1024 символами
+fn truncate_caption(text: &str) -> String {
+    if text.chars().count() <= 1000 {
+        return text.to_string();
+    }
+    text.chars().take(999).chain(std::iter::once('…')).collect()
+}
+
+/// Текст ответа, отправляемый подписью к диаграмме, картинке таблицы или файлу выгрузки
+/// вместо отдельного сообщения
+pub struct Caption {
+    /// HTML, обрезанный до `TELEGRAM_CAPTION_LIMIT`
+    pub text: String,
+    pub keyboard: Option<teloxide::types::InlineKeyboardMarkup>,
+}
+
+impl Caption {
+    /// Подпись из отформатированного ответа; `None`, если к ответу нужна обычная клавиатура,
+    /// которую к фото или документу не прикрепить
+    pub fn for_answer(formatted: &str, keyboard: Option<teloxide::types::ReplyMarkup>) -> Option<Self> {
+        let keyboard = match keyboard {
+            None => None,
+            Some(teloxide::types::ReplyMarkup::InlineKeyboard(markup)) => Some(markup),
+            Some(_) => return None,
+        };
+        Some(Self { text: crate::utils::fit_caption(formatted), keyboard })
+    }
+
+    /// Текст подписи и оформление (HTML с клавиатурой ответа)
+    fn into_parts(self) -> (String, Outgoing) {
+        let mut options = Outgoing::html();
+        if let Some(keyboard) = self.keyboard {
+            options = options.keyboard(keyboard);
         }
-        Ok(false) => {
-            bot.send_message(msg.chat.id, "⚠️ Бэкенд недоступен")
+        (self.text, options)
+    }
+}
+
+/// Больше этого Telegram не принимает документы от ботов
+const TELEGRAM_DOCUMENT_LIMIT: usize = 50 * 1024 * 1024;
+
+/// Отправляет данные документом в выбранном формате, подписав его текстом ответа, если он передан.
+/// Файл больше лимита Telegram загружается в объектное хранилище, и вместо документа приходит ссылка.
+/// Возвращает отправленное сообщение или `None`, если файл не удалось сформировать или отправить.
+pub async fn send_export<S: crate::messenger::MessageSender>(
+    bot: &S,
+    chat_id: ChatId,
+    state: &BotState,
+    format: crate::exports::ExportFormat,
+    data: &[serde_json::Value],
+    settings: &crate::storage::UserSettings,
+    caption: Option<Caption>,
+) -> ResponseResult<Option<MessageId>> {
+    match format.render(data, settings.csv_delimiter) {
+        Ok(bytes) => {
+            // Время в имени файла - по часам пользователя
+            let zone = crate::timezone::UserZone::new(settings.time_zone, state.time_zone);
+            let filename = format!("data_{}.{}", zone.file_stamp(), format.extension());
+            if bytes.len() > TELEGRAM_DOCUMENT_LIMIT {
+                return send_export_link(bot, chat_id, state, format, filename, bytes, caption).await;
+            }
+            let document = teloxide::types::InputFile::memory(bytes).file_name(filename);
+            let (text, options) = match caption {
+                Some(caption) => caption.into_parts(),
+                None => (format!("📥 Данные в формате {}", format.extension().to_uppercase()), Outgoing::default()),
+            };
+            Ok(Some(bot.send_document(chat_id, document, text, options).await?))
+        }
+        Err(e) => {
+            error!("Failed to export data as {:?}: {}", format, e);
+            bot.send_message(chat_id, format_error("Не удалось сформировать файл"), Outgoing::html()).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Выгрузка больше лимита Telegram: ссылка на файл в объектном хранилище вместо документа
+async fn send_export_link<S: crate::messenger::MessageSender>(
+    bot: &S,
+    chat_id: ChatId,
+    state: &BotState,
+    format: crate::exports::ExportFormat,
+    filename: String,
+    bytes: Vec<u8>,
+    caption: Option<Caption>,
+) -> ResponseResult<Option<MessageId>> {
+    let size_mb = bytes.len() as f64 / (1024.0 * 1024.0);
+    let Some(storage) = &state.object_storage else {
+        warn!("Export of {:.1} MB exceeds the Telegram limit and object storage is not configured", size_mb);
+        bot.send_message(
+            chat_id,
+            format_error(&format!("Файл получился слишком большим для Telegram ({:.0} МБ при лимите 50 МБ). Уточните запрос, чтобы строк было меньше", size_mb)),
+            Outgoing::html(),
+        )
+            .await?;
+        return Ok(None);
+    };
+
+    let url = match storage.upload(&filename, format.content_type(), bytes).await {
+        Ok(url) => url,
+        Err(e) => {
+            error!("Failed to upload {:.1} MB export to object storage: {:#}", size_mb, e);
+            bot.send_message(chat_id, format_error("Не удалось загрузить большой файл, попробуйте позже"), Outgoing::html())
+                .await?;
+            return Ok(None);
+        }
+    };
+
+    let hours = storage.url_ttl().as_secs().div_ceil(3600);
+    let link = format!(
+        "📥 <a href=\"{}\">Скачать {}</a> ({:.1} МБ — больше лимита Telegram, поэтому ссылкой; действует {} ч)",
+        escape_html(url.as_str()),
+        format.extension().to_uppercase(),
+        size_mb,
+        hours
+    );
+    let (text, options) = match caption {
+        Some(caption) => {
+            let (text, options) = caption.into_parts();
+            (format!("{}\n\n{}", text, link), options)
+        }
+        None => (link, Outgoing::html()),
+    };
+    Ok(Some(bot.send_message(chat_id, text, options.without_preview()).await?))
+}
+
+/// Ответ длиннее порога пользователя (`/settings long`, в символах)
+pub fn exceeds_long_answer_limit(formatted: &str, limit: Option<usize>) -> bool {
+    limit.is_some_and(|limit| formatted.chars().count() > limit)
+}
+
+/// Отправляет отформатированный ответ: одним сообщением, частями или кратким сообщением
+/// с полным ответом в файле - если ответ длиннее порога пользователя (`/settings long`),
+/// а без порога - если частей больше `MAX_MESSAGE_CHUNKS`. `user_id` - чьи настройки учитывать.
+/// Возвращает сообщение, к которому прикреплена клавиатура.
+pub async fn send_answer_text<S: crate::messenger::MessageSender>(
+    bot: &S,
+    chat_id: ChatId,
+    state: &BotState,
+    user_id: Option<&str>,
+    formatted: &str,
+    keyboard: Option<teloxide::types::ReplyMarkup>,
+) -> ResponseResult<MessageId> {
+    use crate::utils::{split_message, TELEGRAM_MESSAGE_LIMIT};
+
+    let settings = match user_id {
+        Some(user_id) => state.storage.settings(user_id).await,
+        None => crate::storage::UserSettings::default(),
+    };
+    let chunks = split_message(formatted, TELEGRAM_MESSAGE_LIMIT);
+    let as_file = match settings.long_answer_chars {
+        Some(limit) => exceeds_long_answer_limit(formatted, Some(limit)),
+        None => chunks.len() > state.max_message_chunks,
+    };
+
+    if as_file {
+        let summary = format!(
+            "{}\n\n📄 <i>Ответ слишком длинный ({} символов) — полная версия в файле ниже</i>",
+            split_message(formatted, 1000).swap_remove(0),
+            formatted.chars().count()
+        );
+        let sent = bot.send_message(chat_id, summary, Outgoing { reply_markup: keyboard, ..Outgoing::html() }).await?;
+
+        let format = settings.long_answer_file;
+        let zone = crate::timezone::UserZone::new(settings.time_zone, state.time_zone);
+        let filename = format!("answer_{}.{}", zone.file_stamp(), format.as_str());
+        bot.send_document(
+            chat_id,
+            teloxide::types::InputFile::memory(format.render(formatted).into_bytes()).file_name(filename),
+            "📄 Полный ответ".to_string(),
+            Outgoing::default(),
+        )
+            .await?;
+        return Ok(sent);
+    }
+
+    // Отправляем все части кроме последней
+    for chunk in chunks.iter().take(chunks.len().saturating_sub(1)) {
+        bot.send_message(chat_id, chunk.clone(), Outgoing::html()).await?;
+    }
+
+    // Последняя часть с клавиатурой
+    let last = chunks.last().map(String::as_str).unwrap_or(formatted);
+    bot.send_message(chat_id, last.to_string(), Outgoing { reply_markup: keyboard, ..Outgoing::html() }).await
+}
+
+/// Отправляет первую страницу большого результата с кнопками навигации, сортировки и «Топ N»
+pub async fn send_result_pages<S: crate::messenger::MessageSender>(
+    bot: &S,
+    chat_id: ChatId,
+    state: &BotState,
+    response: &crate::api_client::QueryResponse,
+    lang: Language,
+) -> ResponseResult<()> {
+    use crate::paging::{is_paginated, render_page, result_keyboard, View};
+
+    if !is_paginated(response) {
+        return Ok(());
+    }
+
+    let sent = bot
+        .send_message(
+            chat_id,
+            render_page(&response.data, View::default(), 0, lang),
+            Outgoing::html().keyboard(result_keyboard(&response.data, View::default(), 0)),
+        )
+        .await?;
+    state.result_pages.insert_table(chat_id, sent, response.data.clone(), View::default()).await;
+
+    Ok(())
+}
+
+/// Перелистывает результат в том же сообщении (кнопки `page:<n>`)
+pub async fn handle_page_callback(
+    bot: Bot,
+    msg: Message,
+    page: &str,
+    lang: Language,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    use crate::paging::{apply_view, page_count, render_page, result_keyboard};
+
+    // Кнопка с номером текущей страницы ничего не делает
+    let Ok(page) = page.parse::<usize>() else {
+        return Ok(());
+    };
+    let Some((rows, view)) = state.result_pages.view(msg.chat.id, msg.id).await else {
+        bot.send_message(msg.chat.id, "⌛ Результат устарел, повторите запрос")
+            .await?;
+        return Ok(());
+    };
+
+    let page = page.min(page_count(&apply_view(&rows, view)) - 1);
+    bot.edit_message_text(msg.chat.id, msg.id, render_page(&rows, view, page, lang))
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(result_keyboard(&rows, view, page))
+        .await?;
+
+    Ok(())
+}
+
+/// Сортирует таблицу или оставляет в ней «Топ N» (кнопки `view:sort:<колонка>`, `view:top:<n>`)
+/// по сохраненным строкам, без запроса к бэкенду; показ начинается с первой страницы
+pub async fn handle_view_callback(
+    bot: Bot,
+    msg: Message,
+    action: &str,
+    lang: Language,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    use crate::paging::{render_page, result_keyboard};
+
+    let Some((rows, view)) = state.result_pages.view(msg.chat.id, msg.id).await else {
+        bot.send_message(msg.chat.id, "⌛ Результат устарел, повторите запрос")
+            .await?;
+        return Ok(());
+    };
+    let Some(view) = view.apply(action) else {
+        return Ok(());
+    };
+
+    // Короткая таблица - часть текста ответа: отсортированная отправляется отдельным сообщением,
+    // дальше сортировка меняет уже его
+    if !state.result_pages.is_table(msg.chat.id, msg.id).await {
+        let sent = bot
+            .send_message(msg.chat.id, render_page(&rows, view, 0, lang))
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_markup(result_keyboard(&rows, view, 0))
+            .reply_to_message_id(msg.id)
+            .await?;
+        state.result_pages.insert_table(msg.chat.id, sent.id, rows.to_vec(), view).await;
+        return Ok(());
+    }
+
+    state.result_pages.set_view(msg.chat.id, msg.id, view).await;
+    bot.edit_message_text(msg.chat.id, msg.id, render_page(&rows, view, 0, lang))
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(result_keyboard(&rows, view, 0))
+        .await?;
+
+    Ok(())
+}
+
+/// Кнопки быстрых вычислений (`frame:menu` под ответом, `frame:<n>` в сообщении с действиями):
+/// сумма, среднее и количество по сохраненным строкам ответа, без запроса к бэкенду
+pub async fn handle_frame_callback(
+    bot: Bot,
+    msg: Message,
+    action: &str,
+    lang: Language,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    use crate::utils::frame::quick_actions;
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    let Some(rows) = state.result_pages.get(msg.chat.id, msg.id).await else {
+        bot.send_message(msg.chat.id, "⌛ Результат устарел, повторите запрос")
+            .await?;
+        return Ok(());
+    };
+    let actions = quick_actions(&rows);
+
+    if action == "menu" {
+        let buttons: Vec<InlineKeyboardButton> = actions.iter()
+            .enumerate()
+            .map(|(i, query)| InlineKeyboardButton::callback(query.label(), format!("frame:{}", i)))
+            .collect();
+        let sent = bot.send_message(
+            msg.chat.id,
+            "🧮 <b>Что посчитать?</b>\nСчитается по строкам этого ответа, без запроса к бэкенду. \
+             Можно написать и свое, например <code>сумма по колонке amount где city = Almaty</code>",
+        )
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_markup(InlineKeyboardMarkup::new(buttons.chunks(2).map(<[_]>::to_vec).collect::<Vec<_>>()))
+            .reply_to_message_id(msg.id)
+            .await?;
+        // Действия считаются по тем же строкам, даже если придет новый ответ
+        state.result_pages.insert(msg.chat.id, sent.id, rows.to_vec()).await;
+        return Ok(());
+    }
+
+    let Some(query) = action.parse::<usize>().ok().and_then(|i| actions.get(i)) else {
+        return Ok(());
+    };
+    bot.send_message(msg.chat.id, query.render(&rows, lang))
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// Отвечает на короткое вычисление по уже полученному результату («сумма по колонке amount»,
+/// «количество по city где amount > 1000») без запроса к бэкенду. Строки берутся из ответа,
+/// на который отвечает сообщение, иначе из последнего результата пользователя.
+/// `false`, если сообщение - не такое вычисление.
+async fn answer_from_cached_rows(
+    bot: &Bot,
+    msg: &Message,
+    state: &BotState,
+    user_id: &str,
+    text: &str,
+    lang: Language,
+) -> ResponseResult<bool> {
+    let replied_rows = match msg.reply_to_message() {
+        Some(reply) => state.result_pages.get(msg.chat.id, reply.id).await,
+        None => None,
+    };
+    let rows = match replied_rows {
+        Some(rows) => rows,
+        None => match state.last_results.get(user_id).await {
+            Some(response) => Arc::new(response.data.clone()),
+            None => return Ok(false),
+        },
+    };
+    let Some(query) = crate::utils::frame::Query::parse(text, &crate::utils::collect_columns(&rows)) else {
+        return Ok(false);
+    };
+
+    info!("Answering {:?} from cached rows for user {}", text, user_id);
+    bot.send_message(msg.chat.id, query.render(&rows, lang))
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(true)
+}
+
+/// Показывает строку результата целиком в ответ на сообщение с кнопками «🔎 1…5» (`row:<n>`)
+pub async fn handle_row_callback(bot: Bot, msg: Message, row: &str, state: Arc<BotState>) -> ResponseResult<()> {
+    let Ok(row) = row.parse::<usize>() else {
+        return Ok(());
+    };
+    let Some(text) = state.result_pages.get(msg.chat.id, msg.id).await
+        .and_then(|rows| crate::paging::render_row(&rows, row))
+    else {
+        bot.send_message(msg.chat.id, "⌛ Результат устарел, повторите запрос")
+            .await?;
+        return Ok(());
+    };
+
+    send_answer_text(&bot, msg.chat.id, &state, None, &text, None).await?;
+    Ok(())
+}
+
+/// Рисует и отправляет диаграмму с кнопками переключения типа в теме и поясе из `settings`;
+/// с подписью `caption` кнопки ответа идут под кнопками типа. Возвращает отправленное сообщение.
+pub async fn send_chart<S: crate::messenger::MessageSender>(
+    bot: &S,
+    chat_id: ChatId,
+    state: &BotState,
+    chart_data: &crate::api_client::ChartData,
+    lang: Language,
+    settings: &crate::storage::UserSettings,
+    caption: Option<Caption>,
+) -> Option<MessageId> {
+    use crate::charts::chart_options_keyboard;
+
+    let zone = crate::timezone::UserZone::new(settings.time_zone, state.time_zone);
+    let image_bytes = match state.chart_renderer.render(chart_data, 1000, 700, lang, settings.chart_theme, zone).await {
+        Ok(image_bytes) => image_bytes,
+        Err(e) => {
+            error!("Failed to generate chart image: {}", e);
+            return None;
+        }
+    };
+
+    let photo = teloxide::types::InputFile::memory(image_bytes).file_name("chart.png");
+    let (text, rows) = match caption {
+        Some(caption) => (caption.text, caption.keyboard.map(|keyboard| keyboard.inline_keyboard).unwrap_or_default()),
+        None => ("📈 Визуализация данных".to_string(), Vec::new()),
+    };
+    let mut keyboard = chart_options_keyboard(chart_data);
+    keyboard.inline_keyboard.extend(rows);
+    match bot.send_photo(chat_id, photo, text, Outgoing::html().keyboard(keyboard)).await {
+        Ok(sent) => {
+            state.charts.insert(chat_id, sent, chart_data.clone()).await;
+            Some(sent)
+        }
+        Err(e) => {
+            error!("Failed to send chart image: {}", e);
+            None
+        }
+    }
+}
+
+/// Рисует и отправляет таблицу результата картинкой (для широких таблиц, см. `table_image`).
+/// Возвращает `None`, если картинку отправить не удалось.
+pub async fn send_table_image<S: crate::messenger::MessageSender>(
+    bot: &S,
+    chat_id: ChatId,
+    state: &BotState,
+    data: &[serde_json::Value],
+    lang: Language,
+    caption: Option<Caption>,
+) -> Option<MessageId> {
+    let image_bytes = match state.chart_renderer.render_table(data, lang).await {
+        Ok(image_bytes) => image_bytes,
+        Err(e) => {
+            error!("Failed to render table image: {}", e);
+            return None;
+        }
+    };
+
+    let photo = teloxide::types::InputFile::memory(image_bytes).file_name("table.png");
+    let (text, options) = match caption {
+        Some(caption) => caption.into_parts(),
+        None => ("📋 Результаты".to_string(), Outgoing::default()),
+    };
+    match bot.send_photo(chat_id, photo, text, options).await {
+        Ok(sent) => Some(sent),
+        Err(e) => {
+            error!("Failed to send table image: {}", e);
+            None
+        }
+    }
+}
+
+/// Перерисовывает отправленную диаграмму из кэша другим типом или с другой шкалой
+/// (кнопки `chart:<type>` и `chart:log`), не запрашивая данные у бэкенда заново
+pub async fn handle_chart_options_callback(
+    bot: Bot,
+    msg: Message,
+    option: &str,
+    lang: Language,
+    settings: &crate::storage::UserSettings,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    use crate::charts::{chart_options_keyboard, LOG_SCALE_OPTION};
+    use teloxide::types::{InputFile, InputMedia, InputMediaPhoto};
+
+    let Some(mut chart_data) = state.charts.get(msg.chat.id, msg.id).await else {
+        bot.send_message(msg.chat.id, "⌛ Данные диаграммы устарели, повторите запрос")
+            .await?;
+        return Ok(());
+    };
+    if option == LOG_SCALE_OPTION {
+        chart_data.log_scale = !chart_data.log_scale;
+    } else if chart_data.chart_type.eq_ignore_ascii_case(option) {
+        return Ok(());
+    } else {
+        chart_data.chart_type = option.to_string();
+    }
+
+    let zone = crate::timezone::UserZone::new(settings.time_zone, state.time_zone);
+    let image_bytes = match state.chart_renderer.render(&chart_data, 1000, 700, lang, settings.chart_theme, zone).await {
+        Ok(image_bytes) => image_bytes,
+        Err(e) => {
+            error!("Failed to re-render chart with option {}: {}", option, e);
+            return Ok(());
+        }
+    };
+
+    // Подпись и кнопки ответа (если ответ пришел подписью к диаграмме) остаются прежними
+    let mut photo = InputMediaPhoto::new(InputFile::memory(image_bytes).file_name("chart.png"))
+        .caption(msg.caption().unwrap_or("📈 Визуализация данных"));
+    if let Some(entities) = msg.caption_entities() {
+        photo = photo.caption_entities(entities.to_vec());
+    }
+    let mut keyboard = chart_options_keyboard(&chart_data);
+    if let Some(markup) = msg.reply_markup() {
+        keyboard.inline_keyboard.extend(markup.inline_keyboard.iter().skip(1).cloned());
+    }
+    bot.edit_message_media(msg.chat.id, msg.id, InputMedia::Photo(photo))
+        .reply_markup(keyboard)
+        .await?;
+    state.charts.insert(msg.chat.id, msg.id, chart_data).await;
+
+    Ok(())
+}
+
+/// Язык ответа: явная просьба в вопросе важнее сохраненной настройки
+pub async fn answer_language(
+    state: &BotState,
+    user_id: &str,
+    requested: Option<crate::language::Language>,
+) -> Option<String> {
+    let language = match requested {
+        Some(language) => Some(language),
+        None => {
+            // Без отдельной настройки /answerlang отвечаем на языке интерфейса
+            let settings = state.storage.settings(user_id).await;
+            settings.answer_language.or(settings.interface_language)
+        }
+    };
+    language.map(|language| language.code().to_string())
+}
+
+/// Формат вывода: просьба в вопросе («диаграммой», «таблицу») важнее настройки `/settings output`
+pub async fn preferred_output_type(
+    state: &BotState,
+    user_id: &str,
+    requested: crate::api_client::OutputType,
+) -> crate::api_client::OutputType {
+    match requested {
+        crate::api_client::OutputType::Auto => state.storage.settings(user_id).await.output_type,
+        requested => requested,
+    }
+}
+
+/// Пока мониторинг считает бэкенд недоступным, сразу отвечаем пользователю,
+/// не отправляя запрос и не пытаясь повторить его через chat API
+pub async fn reject_if_backend_down(bot: &Bot, msg: &Message, state: &BotState, lang: Language) -> ResponseResult<bool> {
+    if state.monitor.is_available() {
+        return Ok(false);
+    }
+
+    bot.send_message(msg.chat.id, tr(lang, Msg::BackendDown))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(true)
+}
+
+/// Отвечает пользователю, превысившему лимит запросов; `true`, если запрос нужно пропустить
+pub async fn reject_if_rate_limited(bot: &Bot, msg: &Message, state: &BotState, lang: Language) -> ResponseResult<bool> {
+    let Err(retry_after) = state.rate_limiter.check(msg.chat.id).await else {
+        return Ok(false);
+    };
+
+    info!("Rate limit exceeded in chat {}", msg.chat.id);
+    bot.send_message(
+        msg.chat.id,
+        fill(lang, Msg::RateLimited, &[("seconds", &retry_after.as_secs().max(1).to_string())]),
+    )
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(true)
+}
+
+/// Отмечает вопрос как выполняющийся в чате. Если такой же вопрос уже выполняется
+/// (двойное нажатие на подсказку, повторная отправка), сообщает об этом и возвращает `None`.
+/// Гард нужно держать до отправки ответа.
+pub async fn start_unless_running<S: crate::messenger::MessageSender>(
+    bot: &S,
+    msg: &Message,
+    state: &BotState,
+    lang: Language,
+    question: &str,
+) -> ResponseResult<Option<crate::dedup::RunningQueryGuard>> {
+    if let Some(guard) = state.running_queries.try_start(msg.chat.id, question) {
+        return Ok(Some(guard));
+    }
+
+    info!("Duplicate query in chat {} while the first one is running", msg.chat.id);
+    bot.send_message(msg.chat.id, tr(lang, Msg::AlreadyRunning).to_string(), Outgoing::default().reply_to(msg.id))
+        .await?;
+    Ok(None)
+}
+
+/// Выполняет заранее заданный запрос (кнопки меню, deep link) с анализом
+pub async fn run_canned_query(
+    bot: Bot,
+    msg: Message,
+    state: Arc<BotState>,
+    user_id: String,
+    query: &str,
+) -> ResponseResult<()> {
+    let lang = state.ui_language(&user_id, msg.from()).await;
+
+    if reject_if_backend_down(&bot, &msg, &state, lang).await?
+        || reject_if_rate_limited(&bot, &msg, &state, lang).await?
+    {
+        return Ok(());
+    }
+    let Some(_running) = start_unless_running(&bot, &msg, &state, lang, query).await? else {
+        return Ok(());
+    };
+
+    // Сообщение "обрабатывается" редактируется по ходу запроса и становится ответом
+    let progress = Progress::start(&bot, &msg, &state, lang).await?;
+    
+    // Определяем формат вывода из запроса
+    let (clean_query, output_type) = detect_output_format(query);
+    let zone = state.user_zone(&user_id).await;
+    let period = zone.question_period(&clean_query);
+    
+    let query_request = QueryRequest {
+        question: clean_query,
+        include_analysis: true, // Для кнопок меню всегда включаем анализ
+        use_cache: true,
+        include_sql: true,
+        user_id: Some(user_id.clone()),
+        output_type: preferred_output_type(&state, &user_id, output_type).await,
+        language: answer_language(&state, &user_id, None).await,
+        date_from: period.map(|period| period.from),
+        date_to: period.map(|period| period.to),
+        timezone: Some(zone.name()),
+    };
+    
+    let output_type = query_request.output_type;
+    match progress.query(&state, query_request).await {
+        Ok(response) => {
+            // Обрабатываем ответ так же, как обычное сообщение
+            ResponseSender::new(&bot, &state, msg.chat.id, &user_id)
+                .with_output_type(output_type)
+                .send(progress, &response)
+                .await
+        }
+        Err(e) => {
+            error!("Error processing menu button query: {}", e);
+            progress.fail(&format_backend_error(lang, &e, tr(lang, Msg::QueryFailed))).await
+        }
+    }
+}
+
+pub async fn handle_start(bot: Bot, msg: Message, state: Arc<BotState>, payload: &str) -> ResponseResult<()> {
+    // Deep link из веб-интерфейса: /start link_<nonce>
+    let payload = payload.split_whitespace().next().unwrap_or("");
+    if let Some(nonce) = payload.strip_prefix("link_") {
+        return handle_account_link(&bot, &msg, &state, nonce).await;
+    }
+
+    // Кнопка «Открыть в боте» из inline-режима: /start q_<id>
+    if let Some(question) = payload.strip_prefix("q_").and_then(crate::inline::headline_question) {
+        let user_id = state.user_key(&msg);
+        return run_canned_query(bot, msg, state, user_id, question).await;
+    }
+
+    // Первый /start - знакомство (язык, роль, формат ответов), дальше - короткая справка под роль
+    let user_id = state.user_key(&msg);
+    match state.storage.settings(&user_id).await.role {
+        Some(role) => {
+            let lang = state.ui_language(&user_id, msg.from()).await;
+            crate::onboarding::send_ready(&bot, msg.chat.id, &state, lang, role).await
+        }
+        None => crate::onboarding::start(&bot, &msg, &state).await,
+    }
+}
+
+/// Привязывает Telegram-пользователя к аккаунту бэкенда по nonce из deep link
+async fn handle_account_link(
+    bot: &Bot,
+    msg: &Message,
+    state: &BotState,
+    nonce: &str,
+) -> ResponseResult<()> {
+    // Аккаунт и его токен принадлежат отправителю, а не чату (см. `handle_login`)
+    let user_id = match crate::auth::personal_key(msg) {
+        Some(user_id) if msg.chat.is_private() => user_id,
+        _ => {
+            bot.send_message(msg.chat.id, "🔒 Аккаунт можно привязать только в личном чате с ботом")
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let request = crate::api_client::LinkRequest {
+        nonce: nonce.to_string(),
+        user_id: user_id.clone(),
+        telegram_username: msg.from().and_then(|user| user.username.clone()),
+    };
+
+    match state.api_client.link_account(request).await {
+        Ok(link) => {
+            info!("User {} linked to backend account {}", user_id, link.account);
+
+            if let (Some(token), Some(credentials)) = (&link.token, &state.credentials) {
+                if let Err(e) = credentials.store_token(&user_id, token).await {
+                    error!("Error storing token for user {}: {}", user_id, e);
+                }
+            }
+
+            let account = link.account.clone();
+            if let Err(e) = state.storage
+                .update_user(&user_id, |user| user.linked_account = Some(account))
+                .await
+            {
+                error!("Error saving linked account for user {}: {}", user_id, e);
+            }
+
+            bot.send_message(
+                msg.chat.id,
+                format!("🔗 Аккаунт <b>{}</b> успешно привязан!", escape_html(&link.account)),
+            )
+                .parse_mode(teloxide::types::ParseMode::Html)
                 .reply_to_message_id(msg.id)
                 .await?;
         }
         Err(e) => {
-            error!("Error checking backend status: {}", e);
-            bot.send_message(msg.chat.id, &format!("❌ Ошибка при проверке статуса: {}", e))
+            error!("Error linking account for user {}: {}", user_id, e);
+            bot.send_message(
+                msg.chat.id,
+                format_error("Не удалось привязать аккаунт. Ссылка недействительна или устарела — сгенерируйте новую в веб-интерфейсе."),
+            )
+                .parse_mode(teloxide::types::ParseMode::Html)
                 .reply_to_message_id(msg.id)
                 .await?;
         }
@@ -604,3 +1243,2257 @@ pub async fn handle_status(bot: Bot, msg: Message, api_client: Arc<ApiClient>) -
     Ok(())
 }
 
+pub async fn handle_help(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    let lang = state.ui_language(&state.user_key(&msg), msg.from()).await;
+    let help_text = format_help(lang);
+    
+    bot.send_message(msg.chat.id, &help_text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn handle_clear(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    state.chat_sessions.reset(&user_id).await;
+    
+    match state.api(&user_id).await.clear_context(&user_id).await {
+        Ok(_) => {
+            bot.send_message(msg.chat.id, "✅ Контекст запросов очищен!")
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+        Err(e) => {
+            error!("Error clearing context: {}", e);
+            bot.send_message(msg.chat.id, format!("❌ Ошибка при очистке контекста: {}", e))
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `/status` - проверка бэкенда прямо сейчас, доступность и задержки по фоновым проверкам
+/// и график задержек, если проверок набралось достаточно
+pub async fn handle_status(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    let mut text = match state.api_client.health_check().await {
+        Ok(true) => {
+            let version = state.api_client.api_version().as_str();
+            format!("✅ Бэкенд работает нормально! (API {})", version)
+        }
+        Ok(false) => "⚠️ Бэкенд недоступен".to_string(),
+        Err(e) => {
+            error!("Error checking backend status: {}", e);
+            format!("❌ Ошибка при проверке статуса: {}", escape_html(&e.to_string()))
+        }
+    };
+    let health = state.monitor.snapshot().await;
+    text.push_str("\n\n");
+    text.push_str(&health.render(state.time_zone));
+
+    // Подписи графика задержек - в поясе бота, как и текст сводки
+    let zone = crate::timezone::UserZone::new(None, state.time_zone);
+    let chart = match health.latency_chart(state.time_zone) {
+        Some(chart) => match state.chart_renderer.render(&chart, 800, 300, Language::Ru, None, zone).await {
+            Ok(image) => Some(image),
+            Err(e) => {
+                warn!("Failed to render latency chart: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    match chart {
+        Some(image) => {
+            bot.send_photo(msg.chat.id, teloxide::types::InputFile::memory(image).file_name("status.png"))
+                .caption(text)
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+        None => {
+            bot.send_message(msg.chat.id, text)
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Простейший запрос для замера времени выполнения запроса на бэкенде
+const PING_QUESTION: &str = "sql: SELECT 1";
+
+pub async fn handle_ping(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    use std::time::Instant;
+
+    let user_id = state.user_key(&msg);
+
+    // Задержка Telegram API
+    let started = Instant::now();
+    let telegram = bot.get_me().await.map(|_| started.elapsed());
+
+    // Задержка /api/health
+    let api = state.api(&user_id).await;
+    let started = Instant::now();
+    let health = api.health_check().await.map(|ok| (ok, started.elapsed()));
+
+    // Время тривиального запроса (без кэша и анализа)
+    let started = Instant::now();
+    let query = api.query(QueryRequest {
+        question: PING_QUESTION.to_string(),
+        include_analysis: false,
+        use_cache: false,
+        include_sql: false,
+        user_id: Some(user_id),
+        output_type: crate::api_client::OutputType::Json,
+        language: None,
+        date_from: None,
+        date_to: None,
+        timezone: None,
+    }).await.map(|response| (response.execution_time_ms, started.elapsed()));
+
+    let mut text = String::from("🏓 <b>Понг!</b>\n\n");
+    match telegram {
+        Ok(elapsed) => text.push_str(&format!("• Telegram API: {} мс\n", elapsed.as_millis())),
+        Err(e) => text.push_str(&format!("• Telegram API: ❌ {}\n", escape_html(&e.to_string()))),
+    }
+    match health {
+        Ok((true, elapsed)) => text.push_str(&format!("• Бэкенд /api/health: {} мс\n", elapsed.as_millis())),
+        Ok((false, elapsed)) => text.push_str(&format!("• Бэкенд /api/health: ⚠️ недоступен ({} мс)\n", elapsed.as_millis())),
+        Err(e) => text.push_str(&format!("• Бэкенд /api/health: ❌ {}\n", escape_html(&e.to_string()))),
+    }
+    match query {
+        Ok((backend_ms, elapsed)) => text.push_str(&format!(
+            "• Тестовый запрос: {} мс (на бэкенде {} мс)\n",
+            elapsed.as_millis(),
+            backend_ms
+        )),
+        Err(e) => {
+            error!("Ping query failed: {}", e);
+            text.push_str("• Тестовый запрос: ❌ ошибка\n");
+        }
+    }
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn handle_answer_language(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    use crate::language::Language;
+
+    let user_id = state.user_key(&msg);
+    let arg = arg.split_whitespace().next().unwrap_or("");
+
+    let reply = if arg.is_empty() {
+        let current = state.storage.settings(&user_id).await.answer_language
+            .map(|language| language.name())
+            .unwrap_or("как в вопросе");
+        format!(
+            "🌐 Язык ответов: <b>{}</b>\n\nИзменить: <code>/answerlang ru|en|kk</code>, сбросить: <code>/answerlang auto</code>\nТакже можно написать в вопросе «ответь на английском».",
+            current
+        )
+    } else {
+        let language = if arg.eq_ignore_ascii_case("auto") {
+            None
+        } else {
+            match Language::parse(arg) {
+                Some(language) => Some(language),
+                None => {
+                    bot.send_message(msg.chat.id, "⚠️ Неизвестный язык. Доступно: ru, en, kk, auto")
+                        .reply_to_message_id(msg.id)
+                        .await?;
+                    return Ok(());
+                }
+            }
+        };
+
+        if let Err(e) = state.storage
+            .update_user(&user_id, |user| user.settings.answer_language = language)
+            .await
+        {
+            error!("Error saving answer language for user {}: {}", user_id, e);
+            bot.send_message(msg.chat.id, format_error("Не удалось сохранить настройку"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+
+        match language {
+            Some(language) => format!("✅ Теперь ответы будут на языке: <b>{}</b>", language.name()),
+            None => "✅ Язык ответов сброшен".to_string(),
+        }
+    };
+
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Команда `/currency [KZT|USD|EUR]` - в какой валюте показывать суммы в ответах
+pub async fn handle_currency(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    use crate::currency::Currency;
+
+    let user_id = state.user_key(&msg);
+    let arg = arg.split_whitespace().next().unwrap_or("");
+    let available = Currency::ALL
+        .into_iter()
+        .filter(|currency| state.currency.supports(*currency))
+        .map(Currency::code)
+        .collect::<Vec<_>>()
+        .join("|");
+
+    let reply = if arg.is_empty() {
+        let current = state.storage.settings(&user_id).await.currency;
+        format!(
+            "💱 Валюта сумм: <b>{}</b>\n\nИзменить: <code>/currency {}</code>",
+            current.code(),
+            available
+        )
+    } else {
+        let Some(currency) = Currency::parse(arg).filter(|currency| state.currency.supports(*currency)) else {
+            bot.send_message(msg.chat.id, format!("⚠️ Неизвестная валюта. Доступно: {}", available.replace('|', ", ")))
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        };
+
+        if let Err(e) = state.storage
+            .update_user(&user_id, |user| user.settings.currency = currency)
+            .await
+        {
+            error!("Error saving currency for user {}: {}", user_id, e);
+            bot.send_message(msg.chat.id, format_error("Не удалось сохранить настройку"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+
+        match currency {
+            Currency::Kzt => "✅ Суммы снова показываются в тенге".to_string(),
+            currency => format!(
+                "✅ Суммы в ответах будут пересчитаны в <b>{}</b> ({})",
+                currency.code(),
+                currency.symbol()
+            ),
+        }
+    };
+
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Команда `/timezone [Asia/Almaty|auto]` - часовой пояс, в котором показывается время в данных
+/// и считаются «сегодня» и «вчера» в вопросах
+pub async fn handle_timezone(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    let arg = arg.split_whitespace().next().unwrap_or("");
+
+    let reply = if arg.is_empty() {
+        let current = match state.storage.settings(&user_id).await.time_zone {
+            Some(tz) => tz.name().to_string(),
+            None => format!("как у бота (UTC{})", state.time_zone),
+        };
+        format!(
+            "🕐 Часовой пояс: <b>{}</b>\n\nИзменить: <code>/timezone Asia/Almaty</code>, сбросить: <code>/timezone auto</code>",
+            escape_html(&current)
+        )
+    } else {
+        let time_zone = if arg.eq_ignore_ascii_case("auto") {
+            None
+        } else {
+            match crate::timezone::parse(arg) {
+                Some(tz) => Some(tz),
+                None => {
+                    bot.send_message(
+                        msg.chat.id,
+                        "⚠️ Неизвестный часовой пояс. Укажите его как в базе IANA, например Asia/Almaty, Asia/Aqtobe или Europe/Moscow",
+                    )
+                        .reply_to_message_id(msg.id)
+                        .await?;
+                    return Ok(());
+                }
+            }
+        };
+
+        if let Err(e) = state.storage
+            .update_user(&user_id, |user| user.settings.time_zone = time_zone)
+            .await
+        {
+            error!("Error saving time zone for user {}: {}", user_id, e);
+            bot.send_message(msg.chat.id, format_error("Не удалось сохранить настройку"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+
+        match time_zone {
+            Some(tz) => format!("✅ Время в ответах и периоды в вопросах — по поясу <b>{}</b>", tz.name()),
+            None => format!("✅ Часовой пояс сброшен, используется пояс бота (UTC{})", state.time_zone),
+        }
+    };
+
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Команда администратора `/env [имя]` - через какой бэкенд идут его запросы (например, `prod` или `sandbox`)
+pub async fn handle_env(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    if reject_if_not_admin(&bot, &msg, &state).await? {
+        return Ok(());
+    }
+
+    let user_id = state.user_key(&msg);
+    let names = state.backends.names()
+        .map(|name| format!("<code>{}</code>", name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let arg = arg.trim().to_lowercase();
+
+    let reply = if arg.is_empty() {
+        let selected = state.storage.settings(&user_id).await.backend;
+        let (current, _) = state.backends.resolve(selected.as_deref());
+        format!(
+            "🧭 Запросы идут в бэкенд <b>{}</b>\n\nДоступно: {}\nПереключить: <code>/env имя</code>",
+            escape_html(current),
+            names
+        )
+    } else if state.backends.get(&arg).is_none() {
+        format!("⚠️ Бэкенд <code>{}</code> не настроен. Доступно: {}", escape_html(&arg), names)
+    } else {
+        // Основной бэкенд не запоминаем, чтобы пользователь следовал за BACKEND_NAME
+        let backend = (arg != state.backends.default_name()).then(|| arg.clone());
+        if let Err(e) = state.storage.update_user(&user_id, |user| user.settings.backend = backend).await {
+            error!("Error saving backend for user {}: {}", user_id, e);
+            bot.send_message(msg.chat.id, format_error("Не удалось сохранить настройку"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+        // Сессия чат-ассистента принадлежит прежнему бэкенду
+        state.chat_sessions.reset(&user_id).await;
+        info!("User {} switched to backend {}", user_id, arg);
+        format!("✅ Теперь запросы идут в бэкенд <b>{}</b>", escape_html(&arg))
+    };
+
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Команда администратора `/debug on|off` - подвал под ответами: время бота, бэкенда и Telegram и id трассы
+pub async fn handle_debug(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    if reject_if_not_admin(&bot, &msg, &state).await? {
+        return Ok(());
+    }
+
+    let user_id = state.user_key(&msg);
+    let reply = match arg.trim().to_lowercase().as_str() {
+        "" => {
+            let enabled = state.storage.settings(&user_id).await.debug;
+            format!(
+                "🛠 Отладочный подвал: <b>{}</b>\n\nВключить: <code>/debug on</code>, выключить: <code>/debug off</code>",
+                if enabled { "включен" } else { "выключен" }
+            )
+        }
+        arg @ ("on" | "off") => {
+            let enabled = arg == "on";
+            if let Err(e) = state.storage.update_user(&user_id, |user| user.settings.debug = enabled).await {
+                error!("Error saving debug setting for user {}: {}", user_id, e);
+                bot.send_message(msg.chat.id, format_error("Не удалось сохранить настройку"))
+                    .parse_mode(teloxide::types::ParseMode::Html)
+                    .reply_to_message_id(msg.id)
+                    .await?;
+                return Ok(());
+            }
+            if enabled {
+                "✅ Под ответами будет разбивка времени: бот, бэкенд (запрос и сеть), Telegram — и id трассы".to_string()
+            } else {
+                "✅ Отладочный подвал выключен".to_string()
+            }
+        }
+        _ => "⚠️ Используйте <code>/debug on</code> или <code>/debug off</code>".to_string(),
+    };
+
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// `/language` - выбор языка интерфейса кнопками
+pub async fn handle_language(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    let lang = state.ui_language(&state.user_key(&msg), msg.from()).await;
+    let keyboard = InlineKeyboardMarkup::new(vec![
+        [Language::Ru, Language::En, Language::Kk]
+            .into_iter()
+            .map(|language| {
+                let label = if language == lang {
+                    format!("✅ {}", language.native_name())
+                } else {
+                    language.native_name().to_string()
+                };
+                InlineKeyboardButton::callback(label, format!("lang:{}", language.code()))
+            })
+            .collect::<Vec<_>>(),
+    ]);
+
+    bot.send_message(msg.chat.id, tr(lang, Msg::LanguagePrompt))
+        .reply_markup(keyboard)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Кнопка выбора языка (`lang:<код>`)
+pub async fn handle_language_callback(
+    bot: Bot,
+    msg: Message,
+    user_id: String,
+    code: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let Some(language) = Language::parse(code) else {
+        return Ok(());
+    };
+
+    if let Err(e) = state.storage
+        .update_user(&user_id, |user| user.settings.interface_language = Some(language))
+        .await
+    {
+        error!("Error saving interface language for user {}: {}", user_id, e);
+        bot.send_message(msg.chat.id, format_error(tr(language, Msg::SettingSaveFailed)))
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .await?;
+        return Ok(());
+    }
+
+    bot.edit_message_text(msg.chat.id, msg.id, tr(language, Msg::LanguageChanged))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn handle_login(bot: Bot, msg: Message, state: Arc<BotState>, token: &str) -> ResponseResult<()> {
+    let Some(credentials) = &state.credentials else {
+        bot.send_message(msg.chat.id, "⚠️ Вход по токену не настроен на этом боте")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+
+    let token = token.split_whitespace().next().unwrap_or("").to_string();
+
+    // Токен дает права его владельца: в группе он достался бы всем участникам
+    let user_id = match crate::auth::personal_key(&msg) {
+        Some(user_id) if msg.chat.is_private() => user_id,
+        _ => {
+            if !token.is_empty() {
+                let _ = bot.delete_message(msg.chat.id, msg.id).await;
+            }
+            bot.send_message(msg.chat.id, "🔒 Токен можно привязать только в личном чате с ботом")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if token.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "🔑 <b>Привязка токена бэкенда</b>\n\nОтправьте персональный API-токен командой:\n<code>/login ваш_токен</code>\n\nТокен хранится в зашифрованном виде и используется только для ваших запросов. Отвязать токен: /logout",
+        )
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    // Удаляем сообщение с токеном, чтобы он не остался в истории чата
+    let _ = bot.delete_message(msg.chat.id, msg.id).await;
+
+    match credentials.store_token(&user_id, &token).await {
+        Ok(_) => {
+            info!("User {} linked a backend token", user_id);
+            bot.send_message(msg.chat.id, "✅ Токен сохранен. Ваши запросы теперь выполняются с вашими правами доступа.")
+                .await?;
+        }
+        Err(e) => {
+            error!("Error storing token for user {}: {}", user_id, e);
+            bot.send_message(msg.chat.id, format_error("Не удалось сохранить токен"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Сколько записей попадает в стенограмму по умолчанию
+const DEFAULT_TRANSCRIPT_ENTRIES: usize = 20;
+
+pub async fn handle_transcript(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    use crate::audit::{render_transcript_html, MAX_AUDIT_ENTRIES};
+
+    let user_id = state.user_key(&msg);
+    // Необязательный аргумент - количество записей: /transcript 50
+    let limit = arg
+        .split_whitespace()
+        .next()
+        .and_then(|arg| arg.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_TRANSCRIPT_ENTRIES)
+        .clamp(1, MAX_AUDIT_ENTRIES);
+
+    let entries = state.storage.history(&user_id, limit).await;
+
+    if entries.is_empty() {
+        bot.send_message(msg.chat.id, tr(state.ui_language(&user_id, msg.from()).await, Msg::HistoryEmpty))
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let zone = state.user_zone(&user_id).await;
+    let html = render_transcript_html(&entries, zone);
+    let filename = format!("transcript_{}.html", zone.file_stamp());
+
+    bot.send_document(
+        msg.chat.id,
+        teloxide::types::InputFile::memory(html.into_bytes()).file_name(filename),
+    )
+        .caption(format!("🗂 История запросов (последние {})", entries.len()))
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Сколько вопросов `/history` показывает по умолчанию и максимум
+const DEFAULT_HISTORY_ENTRIES: usize = 10;
+const MAX_HISTORY_ENTRIES: usize = 20;
+
+/// `/history [N]` - последние вопросы с кнопками повтора и редактирования
+pub async fn handle_history(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    let user_id = state.user_key(&msg);
+    let limit = arg
+        .split_whitespace()
+        .next()
+        .and_then(|arg| arg.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_HISTORY_ENTRIES)
+        .clamp(1, MAX_HISTORY_ENTRIES);
+
+    let history = state.storage.history(&user_id, crate::audit::MAX_AUDIT_ENTRIES).await;
+
+    // Свежие вопросы первыми, повторы одного вопроса показываем один раз
+    let mut seen = std::collections::HashSet::new();
+    let entries: Vec<_> = history
+        .iter()
+        .rev()
+        .filter(|entry| seen.insert(entry.question.trim().to_lowercase()))
+        .take(limit)
+        .collect();
+
+    if entries.is_empty() {
+        bot.send_message(msg.chat.id, tr(state.ui_language(&user_id, msg.from()).await, Msg::HistoryEmpty))
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let zone = state.user_zone(&user_id).await;
+    let mut text = String::from("🕘 <b>Последние запросы</b>\n");
+    let mut keyboard = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.iter().enumerate() {
+        let number = i + 1;
+        text.push_str(&format!(
+            "\n{}. {}\n<i>{} · строк: {}</i>\n",
+            number,
+            escape_html(&entry.question),
+            zone.local(entry.timestamp).format("%d.%m %H:%M"),
+            entry.row_count
+        ));
+
+        // Полный текст вопроса не помещается в callback-данные, кнопки несут его хеш
+        let hash = state.suggestions.insert(&entry.question);
+        keyboard.push(vec![
+            InlineKeyboardButton::callback(format!("🔁 {}", number), format!("q:{}", hash)),
+            InlineKeyboardButton::callback(format!("✏️ {}", number), format!("hist:edit:{}", hash)),
+        ]);
+    }
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Кнопка «✏️» в `/history`: присылает вопрос для правки, ответ на сообщение уходит как новый запрос
+pub async fn handle_history_edit_callback(
+    bot: Bot,
+    msg: Message,
+    hash: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    use teloxide::types::ForceReply;
+
+    let Some(question) = state.suggestions.get(hash) else {
+        bot.send_message(msg.chat.id, "⌛ История устарела. Откройте /history заново.")
+            .await?;
+        return Ok(());
+    };
+
+    // Placeholder поля ввода ограничен 64 символами
+    let placeholder: String = question.chars().take(64).collect();
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "✏️ Скопируйте вопрос, исправьте и отправьте ответом на это сообщение:\n\n<code>{}</code>",
+            escape_html(&question)
+        ),
+    )
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(ForceReply::new().input_field_placeholder(Some(placeholder)))
+        .await?;
+
+    Ok(())
+}
+
+/// Сколько запросов пользователь может сохранить и ограничение длины названия
+const MAX_SAVED_QUERIES: usize = 20;
+const MAX_SAVED_NAME_CHARS: usize = 40;
+
+/// `/save название | вопрос` - сохраняет запрос; без вопроса сохраняется последний заданный
+pub async fn handle_save(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    let (name, query) = match arg.split_once('|') {
+        Some((name, query)) => (name.trim(), Some(query.trim().to_string())),
+        None => (arg.trim(), None),
+    };
+
+    if name.is_empty() || query.as_deref() == Some("") {
+        bot.send_message(
+            msg.chat.id,
+            "⭐ Укажите название и вопрос:\n<code>/save Объем за неделю | объем транзакций за последние 7 дней</code>\n\nИли только название, чтобы сохранить последний заданный вопрос.",
+        )
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+    if name.chars().count() > MAX_SAVED_NAME_CHARS {
+        bot.send_message(msg.chat.id, format!("⭐ Название длиннее {} символов. Сократите его.", MAX_SAVED_NAME_CHARS))
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let query = match query {
+        Some(query) => query,
+        None => match state.storage.history(&user_id, 1).await.pop() {
+            Some(entry) => entry.question,
+            None => {
+                bot.send_message(msg.chat.id, "⭐ Вы еще не задавали вопросов. Укажите вопрос после «|».")
+                    .reply_to_message_id(msg.id)
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+
+    let saved = state.storage.saved_queries(&user_id).await;
+    if saved.len() >= MAX_SAVED_QUERIES && !saved.iter().any(|saved| saved.name == name) {
+        bot.send_message(
+            msg.chat.id,
+            format!("⭐ Сохранено максимум запросов ({}). Удалите ненужные в /saved.", MAX_SAVED_QUERIES),
+        )
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let text = match state.storage.save_query(&user_id, name, &query).await {
+        Ok(replaced) => {
+            info!("User {} saved query {:?}", user_id, name);
+            format!(
+                "⭐ Запрос «{}» {}:\n<code>{}</code>\n\nВыполнить его: /saved",
+                escape_html(name),
+                if replaced { "обновлен" } else { "сохранен" },
+                escape_html(&query)
+            )
+        }
+        Err(e) => {
+            error!("Error saving query for user {}: {:#}", user_id, e);
+            format_error("Не удалось сохранить запрос")
+        }
+    };
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Список сохраненных запросов с кнопками выполнения (`saved:run:<id>`) и удаления (`saved:del:<id>`)
+fn saved_queries_message(saved: &[crate::storage::SavedQuery]) -> (String, teloxide::types::InlineKeyboardMarkup) {
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    if saved.is_empty() {
+        return (
+            "⭐ Сохраненных запросов нет. Сохраните запрос командой <code>/save название | вопрос</code>".to_string(),
+            InlineKeyboardMarkup::default(),
+        );
+    }
+
+    let mut text = String::from("⭐ <b>Сохраненные запросы</b>\n");
+    let mut keyboard = Vec::with_capacity(saved.len());
+    for query in saved {
+        text.push_str(&format!("\n<b>{}</b>\n<code>{}</code>\n", escape_html(&query.name), escape_html(&query.query)));
+        keyboard.push(vec![
+            InlineKeyboardButton::callback(format!("▶️ {}", query.name), format!("saved:run:{}", query.id)),
+            InlineKeyboardButton::callback("🗑", format!("saved:del:{}", query.id)),
+        ]);
+    }
+    (text, InlineKeyboardMarkup::new(keyboard))
+}
+
+/// `/saved` - сохраненные запросы пользователя
+pub async fn handle_saved(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    let (text, keyboard) = saved_queries_message(&state.storage.saved_queries(&user_id).await);
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(keyboard)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// Кнопки под списком `/saved`: `run:<id>` выполняет запрос, `del:<id>` удаляет его
+pub async fn handle_saved_callback(
+    bot: Bot,
+    msg: Message,
+    user_id: String,
+    action: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let Some((action, id)) = action.split_once(':') else {
+        return Ok(());
+    };
+    let Ok(id) = id.parse::<i64>() else {
+        return Ok(());
+    };
+    // Запросы ищутся среди сохраненных нажавшим: чужие кнопки в группе ничего не делают
+    let saved = state.storage.saved_queries(&user_id).await;
+    let Some(query) = saved.iter().find(|query| query.id == id) else {
+        bot.send_message(msg.chat.id, "⭐ Этого запроса уже нет среди ваших сохраненных. Откройте /saved заново.")
+            .await?;
+        return Ok(());
+    };
+
+    match action {
+        "run" => {
+            let query = query.query.clone();
+            run_canned_query(bot, msg, state, user_id, &query).await
+        }
+        "del" => {
+            if let Err(e) = state.storage.remove_saved_query(&user_id, id).await {
+                error!("Error removing saved query for user {}: {:#}", user_id, e);
+                bot.send_message(msg.chat.id, "❌ Не удалось удалить запрос").await?;
+                return Ok(());
+            }
+            let (text, keyboard) = saved_queries_message(&state.storage.saved_queries(&user_id).await);
+            bot.edit_message_text(msg.chat.id, msg.id, text)
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_markup(keyboard)
+                .await?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+pub async fn handle_forgetme(bot: Bot, msg: Message) -> ResponseResult<()> {
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback("🗑 Да, удалить всё", "forget:confirm"),
+        InlineKeyboardButton::callback("Отмена", "forget:cancel"),
+    ]]);
+
+    bot.send_message(
+        msg.chat.id,
+        "⚠️ <b>Удаление ваших данных</b>\n\nБудут безвозвратно удалены:\n• история запросов\n• настройки и привязанный токен\n• сохраненные запросы, шаблоны и расписания\n• контекст и данные на бэкенде\n\nВ группе удаляются только ваши данные: общий контекст и история группы остаются.\n\nПродолжить?",
+    )
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(keyboard)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Обрабатывает подтверждение /forgetme (кнопки `forget:confirm` / `forget:cancel`):
+/// удаляет данные под ключами `user_ids` нажавшего кнопку
+pub async fn handle_forget_callback(
+    bot: Bot,
+    msg: Message,
+    user_ids: Vec<String>,
+    action: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    if action != "confirm" {
+        bot.edit_message_text(msg.chat.id, msg.id, "Удаление данных отменено")
+            .await?;
+        return Ok(());
+    }
+
+    let mut failures = Vec::new();
+
+    for user_id in &user_ids {
+        if let Err(e) = state.storage.remove_user(user_id).await {
+            error!("Error removing stored data for user {}: {}", user_id, e);
+            if !failures.contains(&"данные бота") {
+                failures.push("данные бота");
+            }
+        }
+        // Данные удаляются на всех бэкендах: пользователь мог работать и с тестовым через /env
+        for api in state.backends.clients() {
+            if let Err(e) = api.clear_context(user_id).await {
+                error!("Error clearing context for user {}: {}", user_id, e);
+                if !failures.contains(&"контекст на бэкенде") {
+                    failures.push("контекст на бэкенде");
+                }
+            }
+            if let Err(e) = api.delete_user_data(user_id).await {
+                error!("Error deleting backend data for user {}: {}", user_id, e);
+                if !failures.contains(&"данные на бэкенде") {
+                    failures.push("данные на бэкенде");
+                }
+            }
+        }
+    }
+
+    let text = if failures.is_empty() {
+        info!("All data of user {} has been deleted", user_ids.join(", "));
+        "✅ Все ваши данные удалены".to_string()
+    } else {
+        format!(
+            "⚠️ Данные удалены частично. Не удалось удалить: {}. Попробуйте позже.",
+            failures.join(", ")
+        )
+    };
+
+    bot.edit_message_text(msg.chat.id, msg.id, text).await?;
+
+    Ok(())
+}
+
+pub async fn handle_logout(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    let Some(credentials) = &state.credentials else {
+        bot.send_message(msg.chat.id, "⚠️ Вход по токену не настроен на этом боте")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+    let Some(user_id) = crate::auth::personal_key(&msg) else {
+        return Ok(());
+    };
+
+    match credentials.remove_token(&user_id).await {
+        Ok(_) => {
+            bot.send_message(msg.chat.id, "✅ Токен отвязан")
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+        Err(e) => {
+            error!("Error removing token for user {}: {}", user_id, e);
+            bot.send_message(msg.chat.id, format_error("Не удалось отвязать токен"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Команды администратора `/allow <id>` и `/deny <id>`.
+/// Положительный id - пользователь, отрицательный - группа.
+pub async fn handle_allow(
+    bot: Bot,
+    msg: Message,
+    state: Arc<BotState>,
+    arg: &str,
+    allow: bool,
+) -> ResponseResult<()> {
+    use crate::acl::AclEntry;
+
+    let is_admin = msg.from().is_some_and(|user| state.acl.is_admin(user.id));
+    if !is_admin {
+        bot.send_message(msg.chat.id, "⛔ Команда доступна только администраторам")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let command = if allow { "/allow" } else { "/deny" };
+    let Some(entry) = arg
+        .split_whitespace()
+        .next()
+        .and_then(AclEntry::parse)
+    else {
+        bot.send_message(
+            msg.chat.id,
+            format!("Укажите id пользователя или группы: <code>{} 123456789</code>", command),
+        )
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+
+    let result = if allow {
+        state.acl.allow(entry).await
+    } else {
+        state.acl.deny(entry).await
+    };
+
+    let text = match result {
+        Ok(true) if allow => format!("✅ Доступ открыт: {}", entry),
+        Ok(false) if allow => format!("ℹ️ Доступ уже был открыт: {}", entry),
+        Ok(true) => format!("🚫 Доступ закрыт: {}", entry),
+        Ok(false) if state.acl.is_static(entry) => format!(
+            "ℹ️ {} задан в ALLOWED_USER_IDS / ALLOWED_CHAT_IDS, удалите его из конфигурации",
+            entry
+        ),
+        Ok(false) => format!("ℹ️ Нет в списке доступа: {}", entry),
+        Err(e) => {
+            error!("Failed to update allowlist: {}", e);
+            format_error("Не удалось сохранить список доступа")
+        }
+    };
+    info!("Admin {:?} ran {} for {:?}", msg.from().map(|user| user.id), command, entry);
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Команда администратора `/cache stats` - эффективность кэша ответов
+pub async fn handle_cache(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    let is_admin = msg.from().is_some_and(|user| state.acl.is_admin(user.id));
+    if !is_admin {
+        bot.send_message(msg.chat.id, "⛔ Команда доступна только администраторам")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let subcommand = arg.split_whitespace().next();
+    if subcommand != Some("stats") {
+        bot.send_message(msg.chat.id, "Использование: <code>/cache stats</code>")
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let cache = state.api_client.cache();
+    let text = if cache.is_enabled() {
+        let stats = cache.stats().await;
+        let total = stats.hits + stats.misses;
+        let hit_rate = if total > 0 {
+            stats.hits as f64 * 100.0 / total as f64
+        } else {
+            0.0
+        };
+        format!(
+            "🗄 <b>Кэш ответов</b>\n\n\
+             Записей: {}\n\
+             Попаданий: {}\n\
+             Промахов: {}\n\
+             Доля попаданий: {:.1}%\n\
+             Время жизни: {} с",
+            stats.entries, stats.hits, stats.misses, hit_rate, stats.ttl_secs
+        )
+    } else {
+        "🗄 Кэш ответов выключен (RESPONSE_CACHE_TTL_SECS=0)".to_string()
+    };
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Команда администратора `/stats` - активность пользователей и работа бэкенда
+pub async fn handle_stats(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    if reject_if_not_admin(&bot, &msg, &state).await? {
+        return Ok(());
+    }
+
+    let usage = match state.storage.usage_stats(chrono::Utc::now()).await {
+        Ok(usage) => usage,
+        Err(e) => {
+            error!("Error collecting usage statistics: {:#}", e);
+            bot.send_message(msg.chat.id, format_error("Не удалось собрать статистику"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let mut request = bot.send_message(msg.chat.id, crate::stats::render(&usage, &crate::metrics::METRICS.summary()))
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id);
+    if !usage.daily.is_empty() {
+        request = request.reply_markup(InlineKeyboardMarkup::new(vec![vec![
+            InlineKeyboardButton::callback("📈 Запросы по дням", "stats:chart"),
+        ]]));
+    }
+    request.await?;
+
+    Ok(())
+}
+
+/// Кнопка «📈 Запросы по дням» под `/stats`: график отрисовывается по текущим данным
+pub async fn handle_stats_chart_callback(
+    bot: Bot,
+    msg: Message,
+    user: UserId,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    if !state.acl.is_admin(user) {
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now();
+    let chart = match state.storage.usage_stats(now).await {
+        Ok(usage) => crate::stats::chart(&usage, now.date_naive()),
+        Err(e) => {
+            error!("Error collecting usage statistics: {:#}", e);
+            None
+        }
+    };
+    let Some(chart) = chart else {
+        bot.send_message(msg.chat.id, "📈 Нет запросов для графика").await?;
+        return Ok(());
+    };
+
+    // Сводка `/stats` только на русском
+    let zone = state.user_zone(&state.context_scope.key(msg.chat.id, Some(user))).await;
+    match state.chart_renderer.render(&chart, 1000, 700, Language::Ru, None, zone).await {
+        Ok(image) => {
+            bot.send_photo(msg.chat.id, teloxide::types::InputFile::memory(image).file_name("stats.png"))
+                .await?;
+        }
+        Err(e) => {
+            error!("Error rendering statistics chart: {:#}", e);
+            bot.send_message(msg.chat.id, "❌ Не удалось построить график").await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Сообщает, что команда только для администраторов; `true`, если пользователь не администратор
+async fn reject_if_not_admin(bot: &Bot, msg: &Message, state: &BotState) -> ResponseResult<bool> {
+    if msg.from().is_some_and(|user| state.acl.is_admin(user.id)) {
+        return Ok(false);
+    }
+    bot.send_message(msg.chat.id, "⛔ Команда доступна только администраторам")
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(true)
+}
+
+/// Отправляет inline-меню с разделами верхнего уровня
+pub async fn send_menu(bot: &Bot, msg: &Message, state: &BotState) -> ResponseResult<()> {
+    let menu = state.storage.menu().await;
+    let dialogue = state.dialogue(msg.chat.id);
+    let chat_state = crate::dialogue::load(&dialogue).await;
+    crate::dialogue::save(&dialogue, chat_state.with_menu_path(Vec::new())).await;
+    bot.send_message(msg.chat.id, crate::menu::title(&menu, &[]))
+        .reply_markup(crate::menu::level_keyboard(&menu, false))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// Навигация по inline-меню: `open:<i>` открывает раздел, `back` возвращает на уровень выше,
+/// `run:<i>` выполняет запрос кнопки (или начинает диалог о ее параметрах).
+/// Открытый раздел хранится в состоянии диалога чата.
+pub async fn handle_menu_callback(
+    bot: Bot,
+    msg: Message,
+    user: UserId,
+    action: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let menu = state.storage.menu().await;
+    let dialogue = state.dialogue(msg.chat.id);
+    let chat_state = crate::dialogue::load(&dialogue).await;
+    let mut path = chat_state.menu_path().to_vec();
+    // Меню изменили (/menu_remove) - сохраненный путь больше не ведет в раздел
+    if crate::menu::level(&menu, &path).is_none() {
+        path.clear();
+    }
+
+    let (command, index) = action.split_once(':').unwrap_or((action, ""));
+    let index: Option<usize> = index.parse().ok();
+    let items = crate::menu::level(&menu, &path).unwrap_or(&menu);
+    match (command, index.and_then(|index| items.get(index).map(|item| (index, item)))) {
+        ("run", Some((_, item))) => {
+            if let Some(query) = item.query.clone() {
+                // Шаблон с параметрами (`Топ {N} городов за {период}`) сначала заполняется в диалоге
+                if crate::prompts::start(&bot, &msg, &state, user, path, item).await? {
+                    return Ok(());
+                }
+                let user_id = state.context_scope.key(msg.chat.id, Some(user));
+                return run_canned_query(bot, msg, state, user_id, &query).await;
+            }
+        }
+        ("open", Some((index, item))) if item.is_section() => path.push(index),
+        ("back", _) => {
+            path.pop();
+        }
+        // Кнопка из устаревшего меню: показываем верхний уровень заново
+        _ => path.clear(),
+    }
+
+    let items = crate::menu::level(&menu, &path).unwrap_or(&menu);
+    let _ = bot.edit_message_text(msg.chat.id, msg.id, crate::menu::title(&menu, &path))
+        .reply_markup(crate::menu::level_keyboard(items, !path.is_empty()))
+        .await;
+    crate::dialogue::save(&dialogue, chat_state.with_menu_path(path)).await;
+    Ok(())
+}
+
+/// `/menu_add [раздел >] <надпись> | <запрос>` - добавляет кнопку в меню (раздел создается,
+/// если его нет) или меняет запрос существующей кнопки
+pub async fn handle_menu_add(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    use crate::menu::AddOutcome;
+
+    if reject_if_not_admin(&bot, &msg, &state).await? {
+        return Ok(());
+    }
+
+    let parsed = arg.split_once('|')
+        .map(|(path, query)| (crate::menu::parse_path(path), query.trim().to_string()))
+        .filter(|(path, query)| !path.is_empty() && !query.is_empty());
+    let Some((path, query)) = parsed else {
+        bot.send_message(
+            msg.chat.id,
+            "Укажите раздел, надпись и запрос:\n<code>/menu_add 🏦 Банки и карты > 🏦 По банкам | sql: Топ-10 банков по объему транзакций</code>\n\nБез раздела кнопка попадет на верхний уровень меню.",
+        )
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+    if path.iter().any(|label| crate::menu::is_reserved(label) || label.starts_with('/')) {
+        bot.send_message(msg.chat.id, "❌ Эту надпись нельзя использовать в меню")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let label = escape_html(&path.join(" › "));
+    let result = state.storage.update_menu(|menu| crate::menu::add(menu, &path, &query)).await;
+    let text = match result {
+        Ok(AddOutcome::Added) => format!("✅ Кнопка «{}» добавлена в меню", label),
+        Ok(AddOutcome::Updated) => format!("✅ Запрос кнопки «{}» изменен", label),
+        Ok(AddOutcome::LevelFull) => format!(
+            "❌ В разделе уже {} пунктов. Удалите лишние через /menu_remove",
+            crate::menu::MAX_ITEMS_PER_LEVEL
+        ),
+        Ok(AddOutcome::Conflict) => format!("❌ «{}»: надпись уже занята разделом или кнопкой. Список: /menu_list", label),
+        Err(e) => {
+            error!("Failed to update menu: {}", e);
+            format_error("Не удалось сохранить меню")
+        }
+    };
+    bot.send_message(msg.chat.id, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// `/menu_remove [раздел >] <надпись>` - убирает кнопку или раздел целиком
+pub async fn handle_menu_remove(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    if reject_if_not_admin(&bot, &msg, &state).await? {
+        return Ok(());
+    }
+
+    let path = crate::menu::parse_path(arg);
+    if path.is_empty() {
+        bot.send_message(msg.chat.id, "Укажите путь к кнопке или разделу: <code>/menu_remove 🌍 Валюты и география > 💰 По валютам</code>")
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let label = escape_html(&path.join(" › "));
+    let result = state.storage.update_menu(|menu| crate::menu::remove(menu, &path)).await;
+    let text = match result {
+        Ok(true) => format!("🗑 «{}» удалено из меню", label),
+        Ok(false) => format!("В меню нет «{}». Список: /menu_list", label),
+        Err(e) => {
+            error!("Failed to update menu: {}", e);
+            format_error("Не удалось сохранить меню")
+        }
+    };
+    bot.send_message(msg.chat.id, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// `/menu_list` - разделы и кнопки меню с запросами
+pub async fn handle_menu_list(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    if reject_if_not_admin(&bot, &msg, &state).await? {
+        return Ok(());
+    }
+
+    let menu = state.storage.menu().await;
+    let mut text = String::from("📋 <b>Главное меню</b>\n\n");
+    if menu.is_empty() {
+        text.push_str("Меню пустое.\n");
+    }
+    crate::menu::describe(&menu, 0, &mut text);
+    text.push_str("\nДобавить: <code>/menu_add раздел > надпись | запрос</code>\nУдалить: <code>/menu_remove раздел > надпись</code>");
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// `/sql <вопрос>` - вопрос к данным без перехода в чат
+pub async fn handle_sql_command(bot: Bot, msg: Message, state: Arc<BotState>, question: &str) -> ResponseResult<()> {
+    let question = question.trim();
+    if question.is_empty() {
+        bot.send_message(msg.chat.id, "Укажите вопрос: <code>/sql Топ 10 городов по объему</code>")
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+    let user_id = state.user_key(&msg);
+    let lang = state.ui_language(&user_id, msg.from()).await;
+    if reject_if_backend_down(&bot, &msg, &state, lang).await?
+        || reject_if_rate_limited(&bot, &msg, &state, lang).await?
+    {
+        return Ok(());
+    }
+
+    let question = force_sql(question);
+    run_question(bot, msg, state, user_id, &question).await
+}
+
+/// `/chat <сообщение>` - вопрос чат-ассистенту без SQL
+pub async fn handle_chat_command(bot: Bot, msg: Message, state: Arc<BotState>, message: &str) -> ResponseResult<()> {
+    let message = message.trim();
+    if message.is_empty() {
+        bot.send_message(msg.chat.id, "Укажите сообщение: <code>/chat Что такое MCC-код?</code>")
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+    let user_id = state.user_key(&msg);
+    let lang = state.ui_language(&user_id, msg.from()).await;
+    if reject_if_backend_down(&bot, &msg, &state, lang).await?
+        || reject_if_rate_limited(&bot, &msg, &state, lang).await?
+    {
+        return Ok(());
+    }
+
+    let message = message.to_string();
+    run_chat(bot, msg, state, user_id, &message).await
+}
+
+/// `/mode [auto|sql|chat]` - куда по умолчанию отправляются сообщения
+pub async fn handle_mode(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    let arg = arg.split_whitespace().next().unwrap_or("");
+
+    let reply = if arg.is_empty() {
+        let current = state.storage.settings(&user_id).await.query_mode;
+        format!(
+            "🔀 Режим запросов: <b>{}</b>\n\nИзменить: <code>/mode auto|sql|chat</code>\nРазово: <code>/sql вопрос</code> или <code>/chat вопрос</code>",
+            current.name()
+        )
+    } else {
+        let Some(mode) = QueryMode::parse(arg) else {
+            bot.send_message(msg.chat.id, "⚠️ Неизвестный режим. Доступно: auto, sql, chat")
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        };
+
+        if let Err(e) = state.storage
+            .update_user(&user_id, |user| user.settings.query_mode = mode)
+            .await
+        {
+            error!("Error saving query mode for user {}: {}", user_id, e);
+            bot.send_message(msg.chat.id, format_error("Не удалось сохранить настройку"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+        format!("✅ Режим запросов: <b>{}</b>", mode.name())
+    };
+
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Пороги строк, предлагаемые кнопками `/settings`
+const CSV_ROW_THRESHOLDS: [usize; 2] = [20, 100];
+
+/// Пороги длинного ответа (символов) на кнопках `/settings`
+const LONG_ANSWER_THRESHOLDS: [usize; 2] = [4000, 10000];
+
+/// Ниже этого порога `/settings long` почти каждый ответ приходил бы файлом
+const MIN_LONG_ANSWER_CHARS: usize = 500;
+
+/// Изменение настройки: `/settings <ключ> <значение>` или кнопка `settings:<ключ>:<значение>`
+enum SettingChange {
+    Csv(crate::exports::CsvAttachment),
+    CsvDelimiter(crate::exports::CsvDelimiter),
+    ShowSql(bool),
+    ChartTheme(crate::utils::ChartTheme),
+    OutputType(crate::api_client::OutputType),
+    Role(crate::onboarding::UserRole),
+    LongAnswer(Option<usize>),
+    LongAnswerFile(crate::utils::AnswerFile),
+    InsightLevel(crate::utils::InsightLevel),
+}
+
+impl SettingChange {
+    fn parse(key: &str, value: &str) -> Option<Self> {
+        match (key, value) {
+            ("csv", value) => crate::exports::CsvAttachment::parse(value).map(Self::Csv),
+            ("csvsep", value) => crate::exports::CsvDelimiter::parse(value).map(Self::CsvDelimiter),
+            ("sql", "on") => Some(Self::ShowSql(true)),
+            ("sql", "off") => Some(Self::ShowSql(false)),
+            ("theme", value) => crate::utils::ChartTheme::parse(value).map(Self::ChartTheme),
+            ("output", value) => crate::api_client::OutputType::parse(value).map(Self::OutputType),
+            ("role", value) => crate::onboarding::UserRole::parse(value).map(Self::Role),
+            ("long", "auto") => Some(Self::LongAnswer(None)),
+            ("long", value) => value
+                .parse::<usize>()
+                .ok()
+                .filter(|chars| *chars >= MIN_LONG_ANSWER_CHARS)
+                .map(|chars| Self::LongAnswer(Some(chars))),
+            ("longfile", value) => crate::utils::AnswerFile::parse(value).map(Self::LongAnswerFile),
+            ("insights", value) => crate::utils::InsightLevel::parse(value).map(Self::InsightLevel),
+            _ => None,
+        }
+    }
+
+    fn apply(self, settings: &mut crate::storage::UserSettings) {
+        match self {
+            Self::Csv(csv_attachment) => settings.csv_attachment = csv_attachment,
+            Self::CsvDelimiter(csv_delimiter) => settings.csv_delimiter = csv_delimiter,
+            Self::ShowSql(show_sql) => settings.show_sql = show_sql,
+            Self::ChartTheme(theme) => settings.chart_theme = Some(theme),
+            Self::OutputType(output_type) => settings.output_type = output_type,
+            Self::Role(role) => settings.role = Some(role),
+            Self::LongAnswer(chars) => settings.long_answer_chars = chars,
+            Self::LongAnswerFile(format) => settings.long_answer_file = format,
+            Self::InsightLevel(level) => settings.insight_level = level,
+        }
+    }
+}
+
+/// Текст и кнопки `/settings`
+fn settings_overview(
+    settings: &crate::storage::UserSettings,
+    default_theme: crate::utils::ChartTheme,
+) -> (String, teloxide::types::InlineKeyboardMarkup) {
+    use crate::exports::{CsvAttachment, CsvDelimiter};
+    use crate::api_client::OutputType;
+    use crate::utils::{AnswerFile, ChartTheme, InsightLevel};
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    let theme = settings.chart_theme.unwrap_or(default_theme);
+    let text = format!(
+        "⚙️ <b>Настройки</b>\n\n🔀 Режим запросов: <b>{}</b> (<code>/mode</code>)\n🌐 Язык ответов: <b>{}</b> (<code>/answerlang</code>)\n🧾 Формат ответа: <b>{}</b>\n📥 CSV к ответу с данными: <b>{}</b>\n📑 Разделитель CSV: <b>{}</b>\n🔍 SQL запроса: <b>{}</b>\n🎨 Тема диаграмм: <b>{}</b>\n💡 Выводы анализа: <b>{}</b>\n📄 Длинные ответы: <b>{}</b>\n👤 Роль: <b>{}</b> (<code>/settings role analyst|manager</code>)\n\nСвой порог строк: <code>/settings csv 500</code>, символов: <code>/settings long 6000</code>",
+        settings.query_mode.name(),
+        settings.answer_language.map(|language| language.name()).unwrap_or("как в вопросе"),
+        settings.output_type.name(),
+        settings.csv_attachment.name(),
+        settings.csv_delimiter.name(),
+        if settings.show_sql { "под каждым ответом" } else { "по кнопке" },
+        theme.name(),
+        settings.insight_level.name(),
+        match settings.long_answer_chars {
+            Some(chars) => format!("файлом {} длиннее {} символов", settings.long_answer_file.as_str().to_uppercase(), chars),
+            None => format!("файлом {}, если не помещаются в сообщения", settings.long_answer_file.as_str().to_uppercase()),
+        },
+        settings.role.map(|role| role.name()).unwrap_or("не выбрана"),
+    );
+
+    let checked = |label: String, selected: bool| if selected { format!("✅ {}", label) } else { label };
+    let options = [CsvAttachment::Always, CsvAttachment::OnDemand]
+        .into_iter()
+        .chain(CSV_ROW_THRESHOLDS.map(CsvAttachment::AboveRows));
+    let csv_button = |option: CsvAttachment| {
+        let (label, value) = match option {
+            CsvAttachment::Always => ("CSV всегда".to_string(), "always".to_string()),
+            CsvAttachment::OnDemand => ("CSV по кнопке".to_string(), "demand".to_string()),
+            CsvAttachment::AboveRows(rows) => (format!("CSV > {} строк", rows), rows.to_string()),
+        };
+        InlineKeyboardButton::callback(checked(label, option == settings.csv_attachment), format!("settings:csv:{}", value))
+    };
+    let csv_buttons: Vec<_> = options.map(csv_button).collect();
+    let delimiter_buttons = [(CsvDelimiter::Comma, "CSV через «,»"), (CsvDelimiter::Semicolon, "CSV через «;» (Excel)")]
+        .map(|(delimiter, label)| {
+            InlineKeyboardButton::callback(
+                checked(label.to_string(), delimiter == settings.csv_delimiter),
+                format!("settings:csvsep:{}", delimiter.as_str()),
+            )
+        })
+        .to_vec();
+    let sql_buttons = vec![
+        InlineKeyboardButton::callback(checked("SQL всегда".to_string(), settings.show_sql), "settings:sql:on"),
+        InlineKeyboardButton::callback(checked("SQL по кнопке".to_string(), !settings.show_sql), "settings:sql:off"),
+    ];
+    let theme_buttons = [(ChartTheme::Light, "☀️ Светлые диаграммы"), (ChartTheme::Dark, "🌙 Темные диаграммы")]
+        .map(|(option, label)| {
+            InlineKeyboardButton::callback(
+                checked(label.to_string(), option == theme),
+                format!("settings:theme:{}", option.as_str()),
+            )
+        })
+        .to_vec();
+    let insight_buttons = [(InsightLevel::Low, "Все выводы"), (InsightLevel::High, "Только важные")]
+        .map(|(option, label)| {
+            InlineKeyboardButton::callback(
+                checked(label.to_string(), option == settings.insight_level),
+                format!("settings:insights:{}", option.as_str()),
+            )
+        })
+        .to_vec();
+    let long_buttons = [None].into_iter()
+        .chain(LONG_ANSWER_THRESHOLDS.map(Some))
+        .map(|option| {
+            let (label, value) = match option {
+                None => ("Файл: авто".to_string(), "auto".to_string()),
+                Some(chars) => (format!("Файл > {} симв.", chars), chars.to_string()),
+            };
+            InlineKeyboardButton::callback(checked(label, option == settings.long_answer_chars), format!("settings:long:{}", value))
+        })
+        .collect();
+    let long_file_buttons = [AnswerFile::Html, AnswerFile::Txt]
+        .map(|option| {
+            InlineKeyboardButton::callback(
+                checked(format!("Файл {}", option.as_str().to_uppercase()), option == settings.long_answer_file),
+                format!("settings:longfile:{}", option.as_str()),
+            )
+        })
+        .to_vec();
+    let output_buttons = [
+        (OutputType::Auto, "Авто"),
+        (OutputType::Table, "Таблица"),
+        (OutputType::Chart, "Диаграмма"),
+        (OutputType::Json, "JSON"),
+    ]
+        .map(|(option, label)| {
+            InlineKeyboardButton::callback(
+                checked(label.to_string(), option == settings.output_type),
+                format!("settings:output:{}", option.as_str()),
+            )
+        })
+        .to_vec();
+    let keyboard = InlineKeyboardMarkup::new(
+        [output_buttons].into_iter()
+            .chain(csv_buttons.chunks(2).map(<[_]>::to_vec))
+            .chain([delimiter_buttons, sql_buttons, theme_buttons, insight_buttons, long_buttons, long_file_buttons]),
+    );
+
+    (text, keyboard)
+}
+
+/// `/settings [output auto|table|chart|json | csv always|demand|<строк> | csvsep comma|semicolon | sql on|off | theme light|dark | insights all|medium|high | long auto|<символов> | longfile html|txt | role analyst|manager]` - настройки пользователя
+pub async fn handle_settings(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    let mut args = arg.split_whitespace();
+    if let Some(key) = args.next() {
+        let Some(change) = SettingChange::parse(&key.to_lowercase(), &args.next().unwrap_or("").to_lowercase()) else {
+            bot.send_message(msg.chat.id, "⚠️ Использование: <code>/settings</code>, <code>/settings output auto|table|chart|json</code>, <code>/settings csv always|demand|&lt;строк&gt;</code>, <code>/settings csvsep comma|semicolon</code>, <code>/settings sql on|off</code>, <code>/settings theme light|dark</code>, <code>/settings insights all|medium|high</code>, <code>/settings long auto|&lt;символов от 500&gt;</code>, <code>/settings longfile html|txt</code> или <code>/settings role analyst|manager</code>")
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        };
+        if let Err(e) = state.storage.update_user(&user_id, |user| change.apply(&mut user.settings)).await {
+            error!("Error saving settings for user {}: {}", user_id, e);
+            bot.send_message(msg.chat.id, format_error("Не удалось сохранить настройку"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    }
+
+    let (text, keyboard) = settings_overview(&state.storage.settings(&user_id).await, state.chart_renderer.default_theme());
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(keyboard)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Кнопки `/settings` (`settings:<ключ>:<значение>`)
+pub async fn handle_settings_callback(
+    bot: Bot,
+    msg: Message,
+    user_id: String,
+    action: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let Some(change) = action.split_once(':').and_then(|(key, value)| SettingChange::parse(key, value)) else {
+        return Ok(());
+    };
+
+    if let Err(e) = state.storage.update_user(&user_id, |user| change.apply(&mut user.settings)).await {
+        error!("Error saving settings for user {}: {}", user_id, e);
+        bot.send_message(msg.chat.id, format_error("Не удалось сохранить настройку"))
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .await?;
+        return Ok(());
+    }
+
+    let (text, keyboard) = settings_overview(&state.storage.settings(&user_id).await, state.chart_renderer.default_theme());
+    bot.edit_message_text(msg.chat.id, msg.id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(keyboard)
+        .await?;
+
+    Ok(())
+}
+
+/// Кнопка «🔍 Показать SQL» (`showsql:<hash>`): SQL ответа блоком кода
+pub async fn handle_show_sql_callback(bot: Bot, msg: Message, hash: &str, state: Arc<BotState>) -> ResponseResult<()> {
+    let Some(sql) = state.sql_queries.get(hash) else {
+        bot.send_message(msg.chat.id, "⌛ SQL этого ответа уже не сохранен, повторите запрос")
+            .await?;
+        return Ok(());
+    };
+
+    send_answer_text(&bot, msg.chat.id, &state, None, &crate::utils::format_sql(&sql), None).await?;
+    Ok(())
+}
+
+/// `/schedule [когда: вопрос | list | delete <id>]` - регулярные отчеты в чат
+pub async fn handle_schedule(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    use crate::scheduler::{parse_schedule, ScheduledReport, MAX_SCHEDULES_PER_CHAT};
+
+    let arg = arg.trim();
+    if arg.is_empty() || arg == "list" {
+        return send_schedule_list(&bot, msg.chat.id, &state).await;
+    }
+
+    if let Some(id) = arg.strip_prefix("delete").or_else(|| arg.strip_prefix("удалить")) {
+        let reply = match id.trim().trim_start_matches('#').parse::<u64>() {
+            Ok(id) => match state.storage.remove_schedule(msg.chat.id.0, id).await {
+                Ok(true) => format!("🗑 Отчет #{} удален", id),
+                Ok(false) => format!("⚠️ Отчета #{} нет в этом чате", id),
+                Err(e) => {
+                    error!("Error removing schedule {}: {}", id, e);
+                    format_error("Не удалось удалить отчет")
+                }
+            },
+            Err(_) => "⚠️ Укажите номер отчета: <code>/schedule delete 3</code>".to_string(),
+        };
+        bot.send_message(msg.chat.id, reply)
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let Some((frequency, time, question)) = parse_schedule(arg) else {
+        bot.send_message(
+            msg.chat.id,
+            "⚠️ Не удалось разобрать расписание. Примеры:\n\
+             <code>/schedule каждый день в 9:00: объем транзакций за вчера</code>\n\
+             <code>/schedule каждый понедельник в 10:00: топ городов за неделю</code>",
+        )
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+        return Ok(());
+    };
+
+    let existing = state.storage.schedules().await
+        .iter()
+        .filter(|report| report.chat_id == msg.chat.id.0)
+        .count();
+    if existing >= MAX_SCHEDULES_PER_CHAT {
+        bot.send_message(
+            msg.chat.id,
+            format!("⚠️ В чате уже {} отчетов. Удалите ненужные: /schedule", existing),
+        )
+        .reply_to_message_id(msg.id)
+        .await?;
+        return Ok(());
+    }
+
+    let report = ScheduledReport {
+        id: 0,
+        chat_id: msg.chat.id.0,
+        user_id: state.user_key(&msg),
+        question,
+        frequency,
+        time,
+        created_at: chrono::Utc::now(),
+        last_run: None,
+    };
+    let reply = match state.storage.add_schedule(report.clone()).await {
+        Ok(id) => format!(
+            "🗓 Отчет #{} создан: {}\n<i>{}</i>\n\nСписок отчетов — /schedule",
+            id,
+            report.describe(),
+            escape_html(&report.question)
+        ),
+        Err(e) => {
+            error!("Error saving schedule for chat {}: {}", msg.chat.id, e);
+            format_error("Не удалось сохранить отчет")
+        }
+    };
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Список отчетов чата с кнопками удаления
+async fn send_schedule_list(bot: &Bot, chat_id: ChatId, state: &BotState) -> ResponseResult<()> {
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    let reports: Vec<_> = state.storage.schedules().await
+        .into_iter()
+        .filter(|report| report.chat_id == chat_id.0)
+        .collect();
+
+    if reports.is_empty() {
+        bot.send_message(
+            chat_id,
+            "🗓 Регулярных отчетов пока нет.\n\nСоздать: <code>/schedule каждый день в 9:00: объем транзакций за вчера</code>",
+        )
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .await?;
+        return Ok(());
+    }
+
+    let mut text = String::from("🗓 <b>Регулярные отчеты</b>\n");
+    for report in &reports {
+        text.push_str(&format!(
+            "\n#{} — {}\n<i>{}</i>\n",
+            report.id,
+            report.describe(),
+            escape_html(&report.question)
+        ));
+    }
+    let keyboard = InlineKeyboardMarkup::new(reports.iter().map(|report| {
+        vec![InlineKeyboardButton::callback(
+            format!("🗑 Удалить #{}", report.id),
+            format!("sched:del:{}", report.id),
+        )]
+    }));
+
+    bot.send_message(chat_id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(keyboard)
+        .await?;
+
+    Ok(())
+}
+
+/// Кнопка «Удалить» в списке отчетов
+pub async fn handle_schedule_delete_callback(
+    bot: Bot,
+    msg: Message,
+    id: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let Ok(id) = id.parse::<u64>() else {
+        return Ok(());
+    };
+
+    match state.storage.remove_schedule(msg.chat.id.0, id).await {
+        Ok(_) => {
+            let _ = bot.delete_message(msg.chat.id, msg.id).await;
+            send_schedule_list(&bot, msg.chat.id, &state).await
+        }
+        Err(e) => {
+            error!("Error removing schedule {}: {}", id, e);
+            bot.send_message(msg.chat.id, format_error("Не удалось удалить отчет"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+/// Пример для подсказок `/alert`
+const ALERT_EXAMPLE: &str = "<code>/alert \"объем транзакций за час\" &gt; 1000000 every 15m</code>";
+
+/// `/alert "вопрос" > порог [every 15m]` - оповещение, когда значение ответа выходит за порог
+pub async fn handle_alert(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    use crate::watcher::{parse_alert, Alert, MAX_ALERTS_PER_CHAT};
+
+    let Some((question, comparison, threshold, interval_mins)) = parse_alert(arg) else {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "🚨 Не удалось разобрать оповещение. Укажите вопрос, условие и порог, например:\n{}\n\n\
+                 Бот выполняет вопрос с заданным интервалом (по умолчанию каждые {} мин) и сообщает, \
+                 когда первое число в ответе удовлетворяет условию. Условия: &gt;, &gt;=, &lt;, &lt;=, =, !=",
+                ALERT_EXAMPLE,
+                crate::watcher::DEFAULT_INTERVAL_MINS
+            ),
+        )
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+
+    let existing = state.storage.alerts().await
+        .iter()
+        .filter(|alert| alert.chat_id == msg.chat.id.0)
+        .count();
+    if existing >= MAX_ALERTS_PER_CHAT {
+        bot.send_message(
+            msg.chat.id,
+            format!("⚠️ В чате уже {} оповещений. Удалите ненужные: /alerts", existing),
+        )
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let alert = Alert {
+        id: 0,
+        chat_id: msg.chat.id.0,
+        user_id: state.user_key(&msg),
+        question,
+        comparison,
+        threshold,
+        interval_mins,
+        created_at: chrono::Utc::now(),
+        last_checked: None,
+        last_value: None,
+        triggered: false,
+    };
+    let reply = match state.storage.add_alert(alert.clone()).await {
+        Ok(id) => format!(
+            "🚨 Оповещение #{} создано: {}, {}\n<i>{}</i>\n\nСписок оповещений — /alerts",
+            id,
+            escape_html(&alert.condition()),
+            alert.describe_interval(),
+            escape_html(&alert.question)
+        ),
+        Err(e) => {
+            error!("Error saving alert for chat {}: {}", msg.chat.id, e);
+            format_error("Не удалось сохранить оповещение")
+        }
+    };
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// `/alerts` - оповещения чата; `/alerts delete <id>` - удалить оповещение
+pub async fn handle_alerts(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    let arg = arg.trim();
+    let Some(id) = arg.strip_prefix("delete").or_else(|| arg.strip_prefix("удалить")) else {
+        let zone = state.user_zone(&state.user_key(&msg)).await;
+        return send_alert_list(&bot, msg.chat.id, &state, zone).await;
+    };
+
+    let reply = match id.trim().trim_start_matches('#').parse::<u64>() {
+        Ok(id) => match state.storage.remove_alert(msg.chat.id.0, id).await {
+            Ok(true) => format!("🗑 Оповещение #{} удалено", id),
+            Ok(false) => format!("⚠️ Оповещения #{} нет в этом чате", id),
+            Err(e) => {
+                error!("Error removing alert {}: {}", id, e);
+                format_error("Не удалось удалить оповещение")
+            }
+        },
+        Err(_) => "⚠️ Укажите номер оповещения: <code>/alerts delete 3</code>".to_string(),
+    };
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Список оповещений чата с последними значениями и кнопками удаления; время проверки - в поясе `zone`
+async fn send_alert_list(
+    bot: &Bot,
+    chat_id: ChatId,
+    state: &BotState,
+    zone: crate::timezone::UserZone,
+) -> ResponseResult<()> {
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    let alerts: Vec<_> = state.storage.alerts().await
+        .into_iter()
+        .filter(|alert| alert.chat_id == chat_id.0)
+        .collect();
+
+    if alerts.is_empty() {
+        bot.send_message(chat_id, format!("🚨 Оповещений пока нет.\n\nСоздать: {}", ALERT_EXAMPLE))
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .await?;
+        return Ok(());
+    }
+
+    let mut text = String::from("🚨 <b>Оповещения</b>\n");
+    for alert in &alerts {
+        let status = match (alert.last_checked, alert.last_value) {
+            (None, _) => "еще не проверялось".to_string(),
+            (Some(_), None) => "в ответе нет числа".to_string(),
+            (Some(checked), Some(value)) => format!(
+                "{}: {}{}",
+                zone.local(checked).format("%d.%m %H:%M"),
+                crate::utils::format_value(value),
+                if alert.triggered { " 🔴" } else { "" }
+            ),
+        };
+        text.push_str(&format!(
+            "\n#{} — {}, {}\n<i>{}</i>\nПоследняя проверка: {}\n",
+            alert.id,
+            escape_html(&alert.condition()),
+            alert.describe_interval(),
+            escape_html(&alert.question),
+            status
+        ));
+    }
+    let keyboard = InlineKeyboardMarkup::new(alerts.iter().map(|alert| {
+        vec![InlineKeyboardButton::callback(
+            format!("🗑 Удалить #{}", alert.id),
+            format!("alert:del:{}", alert.id),
+        )]
+    }));
+
+    bot.send_message(chat_id, text)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_markup(keyboard)
+        .await?;
+
+    Ok(())
+}
+
+/// `/subscribe <рассылка>` и `/unsubscribe <рассылка>` - уведомления, которые присылает бэкенд.
+/// Без аргумента показывает рассылки, на которые подписан чат.
+pub async fn handle_subscribe(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str, subscribe: bool) -> ResponseResult<()> {
+    use crate::notify::Topic;
+
+    let command = if subscribe { "/subscribe" } else { "/unsubscribe" };
+    let Some(topic) = Topic::parse(arg) else {
+        let current = state.storage.subscriptions(msg.chat.id.0).await;
+        let mut text = String::from("🔔 <b>Уведомления</b>\n");
+        for topic in Topic::ALL {
+            let mark = if current.iter().any(|name| name == topic.as_str()) { "✅" } else { "▫️" };
+            text.push_str(&format!("\n{} <code>{}</code> — {}", mark, topic.as_str(), topic.description()));
+        }
+        text.push_str(&format!(
+            "\n\nПодписаться: <code>/subscribe anomalies</code>\nОтписаться: <code>/unsubscribe anomalies</code>{}",
+            if arg.trim().is_empty() { String::new() } else { format!("\n\n⚠️ Неизвестная рассылка для {}", command) }
+        ));
+        bot.send_message(msg.chat.id, text)
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    };
+
+    let result = if subscribe {
+        state.storage.subscribe(msg.chat.id.0, topic.as_str()).await
+    } else {
+        state.storage.unsubscribe(msg.chat.id.0, topic.as_str()).await
+    };
+    let reply = match (result, subscribe) {
+        (Ok(true), true) => {
+            info!("Chat {} subscribed to {}", msg.chat.id, topic.as_str());
+            format!("🔔 Чат подписан: {}. Отписаться — <code>/unsubscribe {}</code>", topic.description(), topic.as_str())
+        }
+        (Ok(false), true) => format!("🔔 Чат уже подписан: {}", topic.description()),
+        (Ok(true), false) => {
+            info!("Chat {} unsubscribed from {}", msg.chat.id, topic.as_str());
+            format!("🔕 Чат отписан: {}", topic.description())
+        }
+        (Ok(false), false) => format!("🔕 Чат не был подписан: {}", topic.description()),
+        (Err(e), _) => {
+            error!("Error updating subscription of chat {}: {}", msg.chat.id, e);
+            format_error("Не удалось изменить подписку")
+        }
+    };
+    bot.send_message(msg.chat.id, reply)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+
+    Ok(())
+}
+
+/// Кнопка «Удалить» в списке оповещений
+pub async fn handle_alert_delete_callback(
+    bot: Bot,
+    msg: Message,
+    id: &str,
+    zone: crate::timezone::UserZone,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let Ok(id) = id.parse::<u64>() else {
+        return Ok(());
+    };
+
+    match state.storage.remove_alert(msg.chat.id.0, id).await {
+        Ok(_) => {
+            let _ = bot.delete_message(msg.chat.id, msg.id).await;
+            send_alert_list(&bot, msg.chat.id, &state, zone).await
+        }
+        Err(e) => {
+            error!("Error removing alert {}: {}", id, e);
+            bot.send_message(msg.chat.id, format_error("Не удалось удалить оповещение"))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::testing::FakeBackend;
+    use crate::api_client::QueryResponse;
+    use crate::messenger::testing::{RecordingSender, Sent};
+    use serde_json::{json, Value};
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Бэкенд на `MockServer`, бот - `RecordingSender`
+    async fn setup(name: &str) -> (MockServer, Arc<BotState>, RecordingSender) {
+        let backend = MockServer::start().await;
+        let state = Arc::new(BotState::for_tests(&format!("handlers_{}", name), &backend.uri()).await);
+        (backend, state, RecordingSender::default())
+    }
+
+    fn message(text: &str) -> Message {
+        serde_json::from_value(json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": {"id": 42, "type": "private", "first_name": "Test"},
+            "from": {"id": 42, "is_bot": false, "first_name": "Test"},
+            "text": text,
+        }))
+        .unwrap()
+    }
+
+    async fn ask(state: &Arc<BotState>, sender: &RecordingSender, text: &str) -> Vec<Sent> {
+        let msg = message(text);
+        let user_id = state.user_key(&msg);
+        run_question(sender.clone(), msg, state.clone(), user_id, text).await.unwrap();
+        sender.take()
+    }
+
+    /// Последнее, что бот отправил или записал в сообщение о ходе запроса
+    fn answer(sent: &[Sent]) -> &Sent {
+        sent.iter().rev().find(|sent| sent.method != "delete_message").unwrap()
+    }
+
+    fn query_response(question: &str, data: Value) -> Value {
+        let row_count = data.as_array().map_or(0, Vec::len);
+        json!({
+            "question": question,
+            "sql": "SELECT city, SUM(amount) FROM transactions GROUP BY city",
+            "data": data,
+            "execution_time_ms": 12,
+            "row_count": row_count,
+        })
+    }
+
+    #[tokio::test]
+    async fn data_answer_replaces_progress_message() {
+        let (backend, state, sender) = setup("data").await;
+        Mock::given(method("POST"))
+            .and(path("/api/query"))
+            .and(body_partial_json(json!({"question": "Топ городов по объему", "user_id": "42"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(query_response(
+                "Топ городов по объему",
+                json!([{"city": "Almaty", "amount": 1500}, {"city": "Astana", "amount": 900}]),
+            )))
+            .expect(1)
+            .mount(&backend)
+            .await;
+
+        let sent = ask(&state, &sender, "Топ городов по объему").await;
+
+        assert_eq!(sent[0].method, "send_message");
+        assert!(sent[0].text.contains("Обрабатываю запрос"));
+        let answer = answer(&sent);
+        assert_eq!((answer.method, answer.message_id), ("edit_message", sent[0].message_id));
+        assert!(answer.text.contains("Найдено результатов:</b> 2"));
+        assert!(answer.buttons.iter().any(|button| button == "🔍 Показать SQL"));
+    }
+
+    #[tokio::test]
+    async fn off_topic_question_is_answered_by_chat() {
+        let (backend, state, sender) = setup("chat").await;
+        Mock::given(method("POST"))
+            .and(path("/api/query"))
+            .respond_with(ResponseTemplate::new(422).set_body_json(json!({
+                "error": {"code": "not_data", "message": "Not a data question", "category": "off_topic"}
+            })))
+            .mount(&backend)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(json!({"message": "Кто ты?"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "message": "Я помогаю анализировать платежные транзакции",
+                "session_id": "s1",
+                "response_time_ms": 40,
+            })))
+            .expect(1)
+            .mount(&backend)
+            .await;
+
+        let sent = ask(&state, &sender, "Кто ты?").await;
+
+        let answer = answer(&sent);
+        assert_eq!(answer.message_id, sent[0].message_id);
+        assert!(answer.text.contains("Я помогаю анализировать платежные транзакции"));
+    }
+
+    #[tokio::test]
+    async fn backend_failure_is_reported_in_progress_message() {
+        let (backend, state, sender) = setup("failure").await;
+        Mock::given(method("POST"))
+            .and(path("/api/query"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("internal error"))
+            .mount(&backend)
+            .await;
+
+        let sent = ask(&state, &sender, "Объем транзакций за вчера").await;
+
+        let answer = answer(&sent);
+        assert_eq!((answer.method, answer.message_id), ("edit_message", sent[0].message_id));
+        assert!(answer.text.contains(tr(Language::Ru, Msg::QueryFailed)));
+    }
+
+    #[tokio::test]
+    async fn empty_result_offers_corrected_questions() {
+        let (backend, state, sender) = setup("empty").await;
+        Mock::given(method("POST"))
+            .and(path("/api/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(query_response("Оборот в Almati", json!([]))))
+            .mount(&backend)
+            .await;
+        // Бэкенд без `/api/suggest`: варианты исправляет сам бот
+        Mock::given(method("POST"))
+            .and(path("/api/suggest"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&backend)
+            .await;
+
+        let sent = ask(&state, &sender, "Оборот в Almati").await;
+
+        let answer = answer(&sent);
+        assert!(answer.text.contains(tr(Language::Ru, Msg::DidYouMean)));
+        assert!(answer.buttons.iter().any(|button| button == "Оборот в Almaty"));
+    }
+
+    #[tokio::test]
+    async fn duplicate_question_is_not_sent_twice() {
+        let (backend, state, sender) = setup("duplicate").await;
+        let msg = message("Объем за неделю");
+        let _running = state.running_queries.try_start(msg.chat.id, "Объем за неделю").unwrap();
+
+        let sent = ask(&state, &sender, "Объем за неделю").await;
+
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].text, tr(Language::Ru, Msg::AlreadyRunning));
+        assert!(backend.received_requests().await.unwrap().is_empty());
+    }
+
+    /// Бот с бэкендом, который отвечает заготовленным `response`
+    async fn ask_fake(name: &str, question: &str, response: Value) -> (Vec<Sent>, Arc<FakeBackend>) {
+        let response: QueryResponse = serde_json::from_value(response).unwrap();
+        let backend = Arc::new(FakeBackend::new([response]));
+        let state = Arc::new(BotState::with_backend(&format!("handlers_{}", name), backend.clone()).await);
+        let sent = ask(&state, &RecordingSender::default(), question).await;
+        (sent, backend)
+    }
+
+    #[tokio::test]
+    async fn huge_table_is_paged_in_separate_message() {
+        let rows: Vec<Value> = (0..500).map(|i| json!({"merchant": format!("Merchant {}", i), "amount": i})).collect();
+        let (sent, backend) = ask_fake("huge", "Все мерчанты", query_response("Все мерчанты", json!(rows))).await;
+
+        assert_eq!(backend.questions(), ["Все мерчанты"]);
+        let pages = sent.iter().find(|sent| sent.buttons.iter().any(|button| button == "1/50")).unwrap();
+        assert_eq!(pages.method, "send_message");
+        assert!(pages.text.contains("Merchant 0") && !pages.text.contains("Merchant 10"));
+    }
+
+    #[tokio::test]
+    async fn rows_with_missing_fields_are_answered() {
+        // Только обязательные поля ответа; в строках не хватает колонок и есть null
+        let response = json!({
+            "question": "Оборот по городам",
+            "data": [{"city": "Almaty", "amount": 100}, {"city": null}, {"amount": 5}],
+            "execution_time_ms": 3,
+            "row_count": 3,
+        });
+        let (sent, _) = ask_fake("missing", "Оборот по городам", response).await;
+
+        let answer = answer(&sent);
+        assert_eq!((answer.method, answer.message_id), ("edit_message", sent[0].message_id));
+        assert!(answer.text.contains("Найдено результатов:</b> 3"));
+        // Без SQL в ответе нет и кнопки для него
+        assert!(!answer.buttons.iter().any(|button| button == "🔍 Показать SQL"));
+    }
+
+    #[tokio::test]
+    async fn chart_only_answer_is_sent_as_photo() {
+        let mut response = query_response("Динамика за неделю", json!([]));
+        response["chart_data"] = json!({
+            "chart_type": "line",
+            "labels": ["Пн", "Вт", "Ср"],
+            "datasets": [{"label": "Объем", "data": [10.0, 15.0, 12.0]}],
+        });
+        let (sent, _) = ask_fake("chart", "Динамика за неделю", response).await;
+
+        let photo = sent.iter().find(|sent| sent.method == "send_photo").unwrap();
+        assert!(photo.text.contains("Время выполнения"));
+        // Ответ ушел подписью к диаграмме, сообщение о ходе запроса удалено
+        assert!(sent.iter().any(|deleted| deleted.method == "delete_message" && deleted.message_id == sent[0].message_id));
+        assert!(!sent.iter().any(|sent| sent.method == "send_document"));
+    }
+
+    #[tokio::test]
+    async fn analysis_answer_can_be_shortened() {
+        let mut response = query_response("Оборот по городам", json!([{"city": "Almaty", "amount": 100}]));
+        response["analysis"] = json!({
+            "headline": "Лидирует Almaty",
+            "insights": [{"title": "Almaty", "description": "Весь оборот", "significance": "High"}],
+            "explanation": "Других городов в выборке нет",
+            "suggested_questions": [],
+        });
+        let (sent, _) = ask_fake("brief", "Оборот по городам", response).await;
+
+        let answer = answer(&sent);
+        assert_eq!(answer.method, "edit_message");
+        assert!(answer.text.contains("Других городов в выборке нет"));
+        assert!(answer.buttons.iter().any(|button| button == "📝 Кратко"));
+    }
+
+    #[tokio::test]
+    async fn many_insights_are_collapsed_behind_button() {
+        let insights: Vec<Value> = (0..7)
+            .map(|i| json!({"title": format!("Вывод {}", i), "description": "", "significance": if i == 6 { "High" } else { "Low" }}))
+            .collect();
+        let mut response = query_response("Оборот по городам", json!([{"city": "Almaty", "amount": 100}]));
+        response["analysis"] = json!({"headline": "Итог", "insights": insights, "explanation": "", "suggested_questions": []});
+        let (sent, _) = ask_fake("insights", "Оборот по городам", response).await;
+
+        let answer = answer(&sent);
+        // Важный вывод - первым, после пяти выводов остальные свернуты
+        assert!(answer.text.find("Вывод 6").unwrap() < answer.text.find("Вывод 0").unwrap());
+        assert!(!answer.text.contains("Вывод 5") && answer.text.contains("… и еще выводов: 2"));
+        assert!(answer.buttons.iter().any(|button| button == "📋 Показать все выводы"));
+    }
+
+    #[tokio::test]
+    async fn answer_above_user_threshold_is_sent_as_file() {
+        let mut response = query_response("Опиши динамику", json!([]));
+        response["text_response"] = json!("Объем растет. ".repeat(60));
+        let response: QueryResponse = serde_json::from_value(response).unwrap();
+        let state = Arc::new(BotState::with_backend("handlers_long", Arc::new(FakeBackend::new([response]))).await);
+        state.storage
+            .update_user("42", |user| {
+                SettingChange::parse("long", "500").unwrap().apply(&mut user.settings);
+                SettingChange::parse("longfile", "txt").unwrap().apply(&mut user.settings);
+            })
+            .await
+            .unwrap();
+
+        // Ответ поместился бы в одно сообщение, но длиннее порога пользователя
+        let sent = ask(&state, &RecordingSender::default(), "Опиши динамику").await;
+
+        let summary = sent.iter().find(|sent| sent.text.contains("Ответ слишком длинный")).unwrap();
+        assert_eq!(summary.method, "send_message");
+        assert_eq!(answer(&sent).method, "send_document");
+        assert!(SettingChange::parse("long", "100").is_none());
+    }
+}
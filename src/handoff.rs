@@ -0,0 +1,101 @@
+use crate::api_client::QueryResponse;
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use sha2::Sha256;
+use crate::utils::append_keyboard_row;
+use teloxide::types::{InlineKeyboardButton, ReplyMarkup};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Генерирует подписанные ссылки для перехода из чата в веб-интерфейс.
+/// Дашборд проверяет подпись тем же секретом `HANDOFF_SECRET`.
+pub struct HandoffSigner {
+    dashboard_url: String,
+    secret: String,
+}
+
+impl HandoffSigner {
+    pub fn new(dashboard_url: String, secret: String) -> Self {
+        Self {
+            dashboard_url,
+            secret,
+        }
+    }
+
+    /// Ссылка на дашборд с текущим вопросом и контекстом пользователя.
+    /// Подписываются поля `user_id`, `query_id`, `question`, `ts` (см. `payload`).
+    pub fn url(&self, user_id: &str, query_id: Option<&str>, question: &str) -> Option<Url> {
+        let ts = chrono::Utc::now().timestamp().to_string();
+        let query_id = query_id.unwrap_or("");
+        let signature = self.sign(&payload(&[user_id, query_id, question, &ts]));
+
+        let url = format!("{}/handoff", self.dashboard_url.trim_end_matches('/'));
+        match Url::parse_with_params(
+            &url,
+            &[
+                ("user_id", user_id),
+                ("query_id", query_id),
+                ("question", question),
+                ("ts", &ts),
+                ("sig", &signature),
+            ],
+        ) {
+            Ok(url) => Some(url),
+            Err(e) => {
+                tracing::warn!("Failed to build handoff URL: {}", e);
+                None
+            }
+        }
+    }
+
+    fn sign(&self, payload: &str) -> String {
+        let mut mac = HmacSha256::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(payload.as_bytes());
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Подписываемая строка: поля подряд, перед каждым - его длина в байтах (`2:42` + `2:q1` + ...).
+/// Разделитель вроде `|` может встретиться в вопросе, а с длинами часть одного поля нельзя
+/// перенести в соседнее, сохранив подпись.
+fn payload(fields: &[&str]) -> String {
+    fields.iter().map(|field| format!("{}:{}", field.len(), field)).collect()
+}
+
+/// Добавляет под ответом кнопку «Продолжить в веб-интерфейсе», если дашборд настроен
+pub fn attach_handoff_button(
+    signer: Option<&HandoffSigner>,
+    user_id: &str,
+    response: &QueryResponse,
+    keyboard: Option<ReplyMarkup>,
+) -> Option<ReplyMarkup> {
+    let Some(url) = signer.and_then(|s| s.url(user_id, response.query_id.as_deref(), &response.question)) else {
+        return keyboard;
+    };
+    let button = InlineKeyboardButton::url("🌐 Продолжить в веб-интерфейсе", url);
+    append_keyboard_row(keyboard, vec![button])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_cannot_be_shifted_between_each_other() {
+        assert_eq!(payload(&["42", "", "Топ|5", "1700000000"]), "2:420:8:Топ|510:1700000000");
+        assert_ne!(payload(&["42|q1", "", "вопрос"]), payload(&["42", "q1|", "вопрос"]));
+        assert_ne!(payload(&["4", "2:"]), payload(&["4", "", "2"]));
+
+        let signer = HandoffSigner::new("https://dash.example.com/".to_string(), "secret".to_string());
+        let url = signer.url("42", Some("q1"), "Топ 5 городов").unwrap();
+        assert!(url.as_str().starts_with("https://dash.example.com/handoff?user_id=42&query_id=q1&question="), "{}", url);
+        let ts = url.query_pairs().find(|(name, _)| name == "ts").unwrap().1.to_string();
+        let sig = url.query_pairs().find(|(name, _)| name == "sig").unwrap().1.to_string();
+        assert_eq!(sig, signer.sign(&payload(&["42", "q1", "Топ 5 городов", &ts])));
+    }
+}
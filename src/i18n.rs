@@ -0,0 +1,413 @@
+use crate::language::Language;
+
+/// Сообщения интерфейса, переведенные на все поддерживаемые языки.
+/// Подстановки записываются как `{name}` и заменяются через [`fill`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msg {
+    /// Приветствие и первый шаг знакомства (выбор языка)
+    Welcome,
+    /// Второй шаг знакомства: роль
+    OnboardingRole,
+    /// Третий шаг знакомства: формат ответов
+    OnboardingOutput,
+    /// Знакомство закончено (и повторный `/start`): подсказки для аналитика
+    ReadyAnalyst,
+    /// То же для руководителя
+    ReadyManager,
+    /// Подпись над кнопками с примерами вопросов
+    TryExamples,
+    /// Заголовок `/examples`
+    ExamplesTitle,
+    /// Запрос ничего не нашел, ниже - исправленные варианты вопроса
+    DidYouMean,
+    RoleAnalyst,
+    RoleManager,
+    OutputAuto,
+    OutputTable,
+    OutputChart,
+    Help,
+    Processing,
+    StageQueued,
+    StageSql,
+    StageQuery,
+    StageChart,
+    /// Бэкенд присылает ответ по частям (потоковый режим)
+    StageAnswer,
+    /// Пометка ответа на исправленный (отредактированный) вопрос, без разметки
+    AnswerUpdated,
+    BackendDown,
+    /// `{seconds}` - через сколько можно повторить запрос
+    RateLimited,
+    AlreadyRunning,
+    QueryFailed,
+    ChatFailed,
+    Timeout,
+    /// Бэкенд перегружен: запрос не удался и после повтора
+    BackendBusy,
+    HistoryEmpty,
+    LanguagePrompt,
+    LanguageChanged,
+    SettingSaveFailed,
+}
+
+/// Текст сообщения на языке интерфейса
+pub fn tr(lang: Language, msg: Msg) -> &'static str {
+    match lang {
+        Language::Ru => ru(msg),
+        Language::En => en(msg),
+        Language::Kk => kk(msg),
+    }
+}
+
+/// Текст сообщения с подстановками: `fill(lang, Msg::RateLimited, &[("seconds", "5")])`
+pub fn fill(lang: Language, msg: Msg, args: &[(&str, &str)]) -> String {
+    args.iter().fold(tr(lang, msg).to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+fn ru(msg: Msg) -> &'static str {
+    match msg {
+        Msg::Welcome => r#"👋 <b>Добро пожаловать в Payment Analytics Bot!</b>
+
+Я отвечаю на вопросы о платежных транзакциях: строю SQL, таблицы и диаграммы.
+
+Настроим бота за три шага.
+<b>1/3.</b> 🌐 На каком языке общаться?"#,
+        Msg::OnboardingRole => "<b>2/3.</b> 👤 Кто вы? От этого зависят кнопки и примеры вопросов.",
+        Msg::OnboardingOutput => "<b>3/3.</b> 🧾 В каком виде показывать результаты? Всегда можно попросить другой формат в вопросе или сменить его в /settings.",
+        Msg::ReadyAnalyst => r#"✅ <b>Готово!</b>
+
+Пишите вопросы к данным с префиксом <code>sql:</code> или командой /sql — к ответу прилагаются SQL, таблица и выгрузка (/export). Готовые запросы — в «📋 Меню».
+
+Данные в базе на латинице (Astana, Halyk Bank), кириллица преобразуется автоматически. Все команды — /help."#,
+        Msg::ReadyManager => r#"✅ <b>Готово!</b>
+
+Задавайте вопросы обычными словами, например «сколько транзакций было вчера». Кнопки внизу показывают сводку за сегодня и динамику за неделю, регулярный отчет можно заказать через /schedule.
+
+Все команды — /help."#,
+        Msg::TryExamples => "💡 Попробуйте один из вопросов (еще примеры — /examples):",
+        Msg::DidYouMean => "🔍 <b>Ничего не найдено — возможно, вы имели в виду:</b>",
+        Msg::ExamplesTitle => "💡 <b>Примеры вопросов</b>\n\nВыберите тему — вопрос выполнится сразу по нажатию.",
+        Msg::RoleAnalyst => "📊 Аналитик",
+        Msg::RoleManager => "💼 Руководитель",
+        Msg::OutputAuto => "🤖 На выбор бота",
+        Msg::OutputTable => "📋 Таблица",
+        Msg::OutputChart => "📈 Диаграмма",
+        Msg::Help => r#"📖 <b>Справка по использованию бота</b>
+
+🤖 <b>Основные команды:</b>
+/start - Начать работу с ботом
+/help - Показать эту справку
+/clear - Очистить контекст запросов
+/status - Проверить статус бэкенда
+/ping - Замерить задержки Telegram и бэкенда
+/sql - Вопрос к данным: <code>/sql Топ 10 городов</code>
+/compare - Сравнить два периода: <code>/compare оборот по городам | прошлый месяц | этот месяц</code>
+/chat - Вопрос ассистенту без SQL
+/mode - Режим по умолчанию (auto, sql, chat)
+/schedule - Регулярные отчеты (<code>/schedule каждый день в 9:00: объем за вчера</code>)
+/alert - Оповещение о пороге (<code>/alert "объем за час" &gt; 1000000 every 15m</code>)
+/alerts - Оповещения чата
+/subscribe - Уведомления об аномалиях (<code>/subscribe anomalies</code>)
+/language - Язык интерфейса
+/answerlang - Язык ответов (ru, en, kk)
+/currency - Валюта сумм (KZT, USD, EUR)
+/timezone - Часовой пояс (Asia/Almaty)
+/menu - Показать главное меню
+/examples - Примеры вопросов по темам, выполняются нажатием
+/schema - Какие таблицы и поля есть в данных (<code>/schema город</code> — поиск)
+/login - Привязать персональный токен бэкенда
+/logout - Отвязать токен
+/history - Последние запросы с кнопками повтора
+/transcript - Выгрузить историю запросов (HTML)
+/export - Выгрузить последний результат целиком (csv, xlsx, json, parquet)
+/save - Сохранить запрос под названием
+/saved - Сохраненные запросы
+/template - Шаблоны запросов с переменными
+/t - Выполнить шаблон (<code>/t top_cities n=5 period=неделя</code>)
+/forgetme - Удалить все мои данные
+
+💡 <b>Как использовать:</b>
+Просто задавайте вопросы на естественном языке, и бот автоматически сгенерирует SQL-запросы и предоставит аналитику!
+
+🔍 <b>ОБЯЗАТЕЛЬНО: Для SQL запросов к базе данных используйте префикс:</b>
+• <b>sql:</b> - например: <code>sql: Показать транзакции за сегодня</code>
+• или команда <code>/sql</code>, или постоянный режим <code>/mode sql</code>
+
+⚠️ <b>Без префикса</b> бот может неправильно определить тип запроса и ответить как в обычном чате, а не выполнить SQL запрос к базе данных.
+
+📊 <b>Примеры вопросов (с префиксом sql:):</b>
+• <code>sql:</code> Сколько транзакций было сегодня?
+• <code>sql:</code> Топ 10 городов по объему транзакций
+• <code>sql:</code> Средний чек для карт Halyk Bank
+• <code>sql:</code> Объем транзакций по категориям за месяц
+• <code>sql:</code> Распределение транзакций по валютам
+
+📋 <b>Указание формата вывода:</b>
+Вы можете явно указать желаемый формат вывода в запросе:
+• <b>Таблица:</b> добавьте слова "таблица", "table", "таблицу" в запрос
+  Пример: "Покажи топ категорий таблица"
+• <b>Диаграмма:</b> добавьте слова "диаграмма", "chart", "график", "визуализация" в запрос
+  Пример: "Распределение по валютам диаграмма"
+• <b>Автоматически:</b> если не указано, бот сам выберет подходящий формат
+
+✨ <b>Особенности:</b>
+• Автоматическая генерация SQL из вопросов
+• Детальная аналитика с инсайтами
+• Экспорт данных в CSV
+• Генерация диаграмм
+• Поддержка русского, английского и казахского языков
+• Контекстная память ваших запросов
+
+Используйте конкретные вопросы для лучших результатов. Бот понимает естественный язык и автоматически оптимизирует запросы к базе данных."#,
+        Msg::Processing => "⏳ <b>Обрабатываю запрос...</b>",
+        Msg::StageQueued => "⏳ <b>Жду завершения предыдущего запроса…</b>",
+        Msg::StageSql => "⏳ <b>Генерирую SQL…</b>",
+        Msg::StageQuery => "⏳ <b>Выполняю запрос…</b>",
+        Msg::StageChart => "⏳ <b>Строю график…</b>",
+        Msg::StageAnswer => "⏳ <b>Формирую ответ…</b>",
+        Msg::AnswerUpdated => "Обновлено по исправленному вопросу",
+        Msg::BackendDown => "⚠️ Бэкенд временно недоступен, мы уже знаем о проблеме. Попробуйте позже — /status покажет текущее состояние.",
+        Msg::RateLimited => "⏳ Слишком много запросов, подождите {seconds} секунд",
+        Msg::AlreadyRunning => "⏳ Этот запрос уже выполняется, дождитесь ответа",
+        Msg::QueryFailed => "Не удалось обработать запрос. Попробуйте переформулировать вопрос или используйте /help для примеров.",
+        Msg::ChatFailed => "Не удалось получить ответ. Попробуйте позже.",
+        Msg::Timeout => "⏱ <b>Запрос превысил время ожидания.</b>\nБэкенд не успел ответить — попробуйте сузить период или упростить вопрос.",
+        Msg::BackendBusy => "⏳ <b>Бэкенд сейчас перегружен.</b>\nЗапрос не удалось выполнить и со второй попытки — повторите его через минуту.",
+        Msg::HistoryEmpty => "📭 История запросов пуста",
+        Msg::LanguagePrompt => "🌐 Выберите язык интерфейса:",
+        Msg::LanguageChanged => "✅ Язык интерфейса: русский. Ответы бэкенда тоже будут на русском (изменить отдельно — /answerlang).",
+        Msg::SettingSaveFailed => "Не удалось сохранить настройку",
+    }
+}
+
+fn en(msg: Msg) -> &'static str {
+    match msg {
+        Msg::Welcome => r#"👋 <b>Welcome to Payment Analytics Bot!</b>
+
+I answer questions about payment transactions with SQL, tables and charts.
+
+Let's set the bot up in three steps.
+<b>1/3.</b> 🌐 Which language do you prefer?"#,
+        Msg::OnboardingRole => "<b>2/3.</b> 👤 What is your role? It decides which buttons and examples you see.",
+        Msg::OnboardingOutput => "<b>3/3.</b> 🧾 How should results be shown? You can always ask for another format in the question or change it in /settings.",
+        Msg::ReadyAnalyst => r#"✅ <b>All set!</b>
+
+Ask the data with the <code>sql:</code> prefix or the /sql command — answers come with the SQL, a table and a full export (/export). Ready-made queries are under «📋 Меню».
+
+Values in the database are in Latin script (Astana, Halyk Bank); Cyrillic is converted automatically. All commands — /help."#,
+        Msg::ReadyManager => r#"✅ <b>All set!</b>
+
+Ask in plain words, for example "how many transactions were there yesterday". The buttons below show today's summary and the weekly trend; order a regular report with /schedule.
+
+All commands — /help."#,
+        Msg::TryExamples => "💡 Try one of these questions (more in /examples):",
+        Msg::DidYouMean => "🔍 <b>Nothing found — did you mean:</b>",
+        Msg::ExamplesTitle => "💡 <b>Sample questions</b>\n\nPick a topic — a question runs as soon as you tap it.",
+        Msg::RoleAnalyst => "📊 Analyst",
+        Msg::RoleManager => "💼 Manager",
+        Msg::OutputAuto => "🤖 Bot decides",
+        Msg::OutputTable => "📋 Table",
+        Msg::OutputChart => "📈 Chart",
+        Msg::Help => r#"📖 <b>Bot help</b>
+
+🤖 <b>Commands:</b>
+/start - Start working with the bot
+/help - Show this help
+/clear - Clear the query context
+/status - Check the backend status
+/ping - Measure Telegram and backend latency
+/sql - Ask the data: <code>/sql Top 10 cities</code>
+/compare - Compare two periods: <code>/compare volume by city | last month | this month</code>
+/chat - Ask the assistant without SQL
+/mode - Default mode (auto, sql, chat)
+/schedule - Recurring reports (<code>/schedule daily 9:00: volume for yesterday</code>)
+/alert - Threshold alert (<code>/alert "volume for the last hour" &gt; 1000000 every 15m</code>)
+/alerts - Alerts of this chat
+/subscribe - Anomaly notifications (<code>/subscribe anomalies</code>)
+/language - Interface language
+/answerlang - Answer language (ru, en, kk)
+/currency - Currency of amounts (KZT, USD, EUR)
+/timezone - Time zone (Asia/Almaty)
+/menu - Show the main menu
+/examples - Sample questions by topic, run with a tap
+/schema - Tables and fields in the data (<code>/schema city</code> to search)
+/login - Link a personal backend token
+/logout - Unlink the token
+/history - Recent questions with re-run buttons
+/transcript - Export the query history (HTML)
+/export - Download the full last result (csv, xlsx, json, parquet)
+/save - Save a query under a name
+/saved - Saved queries
+/template - Query templates with variables
+/t - Run a template (<code>/t top_cities n=5 period=week</code>)
+/forgetme - Delete all my data
+
+💡 <b>How to use:</b>
+Ask questions in plain language, and the bot will generate SQL queries and provide analytics!
+
+🔍 <b>REQUIRED: use a prefix for database queries:</b>
+• <b>sql:</b> - for example: <code>sql: Show today's transactions</code>
+• or the <code>/sql</code> command, or the permanent <code>/mode sql</code>
+
+⚠️ <b>Without the prefix</b> the bot may answer as in a regular chat instead of querying the database.
+
+📊 <b>Example questions (with the sql: prefix):</b>
+• <code>sql:</code> How many transactions were there today?
+• <code>sql:</code> Top 10 cities by transaction volume
+• <code>sql:</code> Average ticket for Halyk Bank cards
+• <code>sql:</code> Transaction volume by category this month
+• <code>sql:</code> Transaction distribution by currency
+
+📋 <b>Output format:</b>
+You can ask for a specific format in the question:
+• <b>Table:</b> add "table" to the question
+  Example: "Top categories table"
+• <b>Chart:</b> add "chart" or "visualization" to the question
+  Example: "Distribution by currency chart"
+• <b>Automatic:</b> otherwise the bot picks a suitable format
+
+✨ <b>Features:</b>
+• Automatic SQL generation
+• Detailed analytics with insights
+• Data export to CSV
+• Charts
+• Russian, English and Kazakh support
+• Memory of your previous questions
+
+Specific questions give the best results. The bot understands natural language and optimises database queries automatically."#,
+        Msg::Processing => "⏳ <b>Processing your request...</b>",
+        Msg::StageQueued => "⏳ <b>Waiting for the previous request…</b>",
+        Msg::StageSql => "⏳ <b>Generating SQL…</b>",
+        Msg::StageQuery => "⏳ <b>Running the query…</b>",
+        Msg::StageChart => "⏳ <b>Drawing the chart…</b>",
+        Msg::StageAnswer => "⏳ <b>Writing the answer…</b>",
+        Msg::AnswerUpdated => "Updated for the edited question",
+        Msg::BackendDown => "⚠️ The backend is temporarily unavailable, we are aware of the problem. Please try again later — /status shows the current state.",
+        Msg::RateLimited => "⏳ Too many requests, please wait {seconds} seconds",
+        Msg::AlreadyRunning => "⏳ This request is already running, please wait for the answer",
+        Msg::QueryFailed => "Could not process the request. Try rephrasing the question or see /help for examples.",
+        Msg::ChatFailed => "Could not get an answer. Please try again later.",
+        Msg::Timeout => "⏱ <b>The request timed out.</b>\nThe backend did not answer in time — try a shorter period or a simpler question.",
+        Msg::BackendBusy => "⏳ <b>The backend is overloaded right now.</b>\nThe request failed even on the second attempt — please try again in a minute.",
+        Msg::HistoryEmpty => "📭 Query history is empty",
+        Msg::LanguagePrompt => "🌐 Choose the interface language:",
+        Msg::LanguageChanged => "✅ Interface language: English. Backend answers will be in English too (change separately with /answerlang).",
+        Msg::SettingSaveFailed => "Could not save the setting",
+    }
+}
+
+fn kk(msg: Msg) -> &'static str {
+    match msg {
+        Msg::Welcome => r#"👋 <b>Payment Analytics Bot-қа қош келдіңіз!</b>
+
+Мен төлем транзакциялары туралы сұрақтарға жауап беремін: SQL, кестелер мен диаграммалар құрамын.
+
+Ботты үш қадамда баптайық.
+<b>1/3.</b> 🌐 Қай тілде сөйлесеміз?"#,
+        Msg::OnboardingRole => "<b>2/3.</b> 👤 Сіз кімсіз? Батырмалар мен сұрақ мысалдары осыған байланысты.",
+        Msg::OnboardingOutput => "<b>3/3.</b> 🧾 Нәтижелерді қандай түрде көрсету керек? Басқа форматты сұрақта сұрауға немесе /settings арқылы өзгертуге болады.",
+        Msg::ReadyAnalyst => r#"✅ <b>Дайын!</b>
+
+Деректерге сұрақты <code>sql:</code> префиксімен немесе /sql командасымен қойыңыз — жауапқа SQL, кесте және толық жүктеу (/export) қоса беріледі. Дайын сұраулар — «📋 Меню» ішінде.
+
+Дерекқордағы мәндер латын әрпімен (Astana, Halyk Bank), кирилл автоматты түрде түрлендіріледі. Барлық командалар — /help."#,
+        Msg::ReadyManager => r#"✅ <b>Дайын!</b>
+
+Сұрақтарды қарапайым сөздермен қойыңыз, мысалы «кеше қанша транзакция болды». Төмендегі батырмалар бүгінгі жиынтық пен апталық динамиканы көрсетеді, тұрақты есепті /schedule арқылы тапсыруға болады.
+
+Барлық командалар — /help."#,
+        Msg::TryExamples => "💡 Мына сұрақтардың бірін көріңіз (басқа мысалдар — /examples):",
+        Msg::DidYouMean => "🔍 <b>Ештеңе табылмады — мүмкін, сіз мынаны айтқыңыз келген шығар:</b>",
+        Msg::ExamplesTitle => "💡 <b>Сұрақ мысалдары</b>\n\nТақырыпты таңдаңыз — сұрақ басқан бойда орындалады.",
+        Msg::RoleAnalyst => "📊 Талдаушы",
+        Msg::RoleManager => "💼 Басшы",
+        Msg::OutputAuto => "🤖 Бот таңдайды",
+        Msg::OutputTable => "📋 Кесте",
+        Msg::OutputChart => "📈 Диаграмма",
+        Msg::Help => r#"📖 <b>Бот бойынша анықтама</b>
+
+🤖 <b>Командалар:</b>
+/start - Ботпен жұмысты бастау
+/help - Осы анықтаманы көрсету
+/clear - Сұраулар контекстін тазарту
+/status - Бэкенд күйін тексеру
+/ping - Telegram мен бэкендтің кідірісін өлшеу
+/sql - Деректерге сұрақ: <code>/sql Топ 10 қала</code>
+/compare - Екі кезеңді салыстыру: <code>/compare қалалар бойынша айналым | прошлый месяц | этот месяц</code>
+/chat - Көмекшіге SQL-сыз сұрақ
+/mode - Әдепкі режим (auto, sql, chat)
+/schedule - Тұрақты есептер (<code>/schedule каждый день в 9:00: кешегі көлем</code>)
+/alert - Шек туралы хабарлама (<code>/alert "соңғы сағаттағы көлем" &gt; 1000000 every 15m</code>)
+/alerts - Чаттың хабарламалары
+/subscribe - Аномалиялар туралы хабарламалар (<code>/subscribe anomalies</code>)
+/language - Интерфейс тілі
+/answerlang - Жауап тілі (ru, en, kk)
+/currency - Сомалар валютасы (KZT, USD, EUR)
+/timezone - Уақыт белдеуі (Asia/Almaty)
+/menu - Басты мәзір
+/examples - Тақырыптар бойынша сұрақ мысалдары, басу арқылы орындалады
+/schema - Деректердегі кестелер мен өрістер (<code>/schema қала</code> — іздеу)
+/login - Жеке бэкенд токенін байланыстыру
+/logout - Токенді ажырату
+/history - Қайталау батырмалары бар соңғы сұрақтар
+/transcript - Сұраулар тарихын жүктеу (HTML)
+/export - Соңғы нәтижені толық жүктеу (csv, xlsx, json, parquet)
+/save - Сұрауды атаумен сақтау
+/saved - Сақталған сұраулар
+/template - Айнымалылары бар сұрау үлгілері
+/t - Үлгіні орындау (<code>/t top_cities n=5 period=апта</code>)
+/forgetme - Менің барлық деректерімді жою
+
+💡 <b>Қалай қолдану керек:</b>
+Сұрақтарыңызды қарапайым тілмен қойыңыз, бот SQL-сұраулар құрып, талдау береді!
+
+🔍 <b>МІНДЕТТІ: дерекқорға сұрау үшін префиксті қолданыңыз:</b>
+• <b>sql:</b> - мысалы: <code>sql: Бүгінгі транзакцияларды көрсет</code>
+• немесе <code>/sql</code> командасы, не тұрақты <code>/mode sql</code> режимі
+
+⚠️ <b>Префикссіз</b> бот дерекқорға сұрау орындаудың орнына әдеттегі чаттағыдай жауап беруі мүмкін.
+
+📊 <b>Сұрақ мысалдары (sql: префиксімен):</b>
+• <code>sql:</code> Бүгін қанша транзакция болды?
+• <code>sql:</code> Транзакция көлемі бойынша топ 10 қала
+• <code>sql:</code> Halyk Bank карталары бойынша орташа чек
+• <code>sql:</code> Айдағы санаттар бойынша транзакция көлемі
+• <code>sql:</code> Транзакциялардың валюталар бойынша бөлінуі
+
+📋 <b>Нәтиже форматы:</b>
+Сұрақта қажетті форматты көрсетуге болады:
+• <b>Кесте:</b> сұраққа "кесте", "table", "таблица" сөзін қосыңыз
+• <b>Диаграмма:</b> "диаграмма", "chart", "график" сөзін қосыңыз
+• <b>Автоматты:</b> көрсетілмесе, бот өзі қолайлы форматты таңдайды
+
+✨ <b>Ерекшеліктері:</b>
+• Сұрақтардан SQL автоматты түрде құрылады
+• Түйіндері бар толық талдау
+• Деректерді CSV-ге экспорттау
+• Диаграммалар
+• Орыс, ағылшын және қазақ тілдерін қолдау
+• Сұрақтарыңыздың контекстін есте сақтау
+
+Ең жақсы нәтиже үшін нақты сұрақтар қойыңыз."#,
+        Msg::Processing => "⏳ <b>Сұрау өңделуде...</b>",
+        Msg::StageQueued => "⏳ <b>Алдыңғы сұраудың аяқталуын күтіп жатырмын…</b>",
+        Msg::StageSql => "⏳ <b>SQL құрастырып жатырмын…</b>",
+        Msg::StageQuery => "⏳ <b>Сұрауды орындап жатырмын…</b>",
+        Msg::StageChart => "⏳ <b>График салып жатырмын…</b>",
+        Msg::StageAnswer => "⏳ <b>Жауап құрастырып жатырмын…</b>",
+        Msg::AnswerUpdated => "Түзетілген сұрақ бойынша жаңартылды",
+        Msg::BackendDown => "⚠️ Бэкенд уақытша қолжетімсіз, мәселе туралы білеміз. Кейінірек қайталап көріңіз — /status ағымдағы күйді көрсетеді.",
+        Msg::RateLimited => "⏳ Сұраулар тым көп, {seconds} секунд күтіңіз",
+        Msg::AlreadyRunning => "⏳ Бұл сұрау орындалып жатыр, жауапты күтіңіз",
+        Msg::QueryFailed => "Сұрауды өңдеу мүмкін болмады. Сұрақты басқаша қойып көріңіз немесе мысалдар үшін /help.",
+        Msg::ChatFailed => "Жауап алу мүмкін болмады. Кейінірек қайталап көріңіз.",
+        Msg::Timeout => "⏱ <b>Сұраудың күту уақыты өтіп кетті.</b>\nБэкенд уақытында жауап бермеді — кезеңді қысқартып немесе сұрақты жеңілдетіп көріңіз.",
+        Msg::BackendBusy => "⏳ <b>Бэкенд қазір шамадан тыс жүктелген.</b>\nСұрауды екінші әрекетте де орындау мүмкін болмады — бір минуттан кейін қайталаңыз.",
+        Msg::HistoryEmpty => "📭 Сұраулар тарихы бос",
+        Msg::LanguagePrompt => "🌐 Интерфейс тілін таңдаңыз:",
+        Msg::LanguageChanged => "✅ Интерфейс тілі: қазақша. Бэкенд жауаптары да қазақша болады (бөлек өзгерту — /answerlang).",
+        Msg::SettingSaveFailed => "Баптауды сақтау мүмкін болмады",
+    }
+}
@@ -0,0 +1,236 @@
+use crate::api_client::{BackendClient, OutputType, QueryRequest, QueryResponse};
+use crate::state::BotState;
+use crate::utils::escape_html;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use std::sync::Arc;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, InlineQuery, InlineQueryResult,
+    InlineQueryResultArticle, InputMessageContent, InputMessageContentText, ParseMode,
+};
+use tokio::sync::RwLock;
+
+/// Как часто пересчитываются заголовочные ответы
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// С какой длины inline-запрос отправляется бэкенду как вопрос
+/// (Telegram присылает запрос на каждое нажатие клавиши)
+const MIN_FREE_TEXT_LEN: usize = 5;
+
+/// Telegram ждет ответ на inline-запрос около 10 секунд
+const INLINE_QUERY_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// Заранее вычисляемые ответы для inline-режима: (id, заголовок, вопрос к бэкенду).
+/// id используется в deep link `/start q_<id>`, поэтому только `[a-z_]`.
+pub const HEADLINE_QUERIES: &[(&str, &str, &str)] = &[
+    ("volume_today", "💰 Объем транзакций за сегодня", "sql: Общий объем транзакций за сегодня"),
+    ("count_today", "📅 Количество транзакций за сегодня", "sql: Сколько транзакций было сегодня?"),
+    ("failure_rate", "⚠️ Доля неуспешных транзакций", "sql: Процент неуспешных транзакций за сегодня"),
+];
+
+/// Возвращает вопрос по id заголовочного ответа
+pub fn headline_question(id: &str) -> Option<&'static str> {
+    HEADLINE_QUERIES
+        .iter()
+        .find(|(headline_id, _, _)| *headline_id == id)
+        .map(|(_, _, question)| *question)
+}
+
+#[derive(Debug, Clone)]
+struct Headline {
+    id: &'static str,
+    title: &'static str,
+    summary: String,
+    updated_at: DateTime<Utc>,
+}
+
+/// Кэш заголовочных ответов, обновляемый в фоне
+#[derive(Default)]
+pub struct HeadlineCache {
+    headlines: RwLock<Vec<Headline>>,
+}
+
+impl HeadlineCache {
+    /// Запускает фоновое обновление кэша
+    pub fn spawn_refresh(self: &Arc<Self>, api_client: Arc<dyn BackendClient>) {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                cache.refresh(api_client.as_ref()).await;
+            }
+        });
+    }
+
+    async fn refresh(&self, api_client: &dyn BackendClient) {
+        let mut fresh = Vec::new();
+        for (id, title, question) in HEADLINE_QUERIES {
+            let request = QueryRequest {
+                question: question.to_string(),
+                include_analysis: true,
+                // Заголовки пересчитываются по расписанию, локальный кэш им не нужен
+                use_cache: false,
+                include_sql: false,
+                user_id: None,
+                output_type: OutputType::Auto,
+                language: None,
+                // «За сегодня» в заголовках бэкенд считает сам: у фонового обновления нет часового пояса пользователя
+                date_from: None,
+                date_to: None,
+                timezone: None,
+            };
+            match api_client.query(request).await {
+                Ok(response) => {
+                    fresh.push(Headline {
+                        id,
+                        title,
+                        summary: summarize(&response),
+                        updated_at: Utc::now(),
+                    });
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to refresh inline headline {}: {}", id, e);
+                    // Оставляем предыдущее значение, если оно было
+                    if let Some(old) = self.headlines.read().await.iter().find(|h| h.id == *id) {
+                        fresh.push(old.clone());
+                    }
+                }
+            }
+        }
+        *self.headlines.write().await = fresh;
+    }
+}
+
+/// Краткое содержание ответа для карточки: заголовок анализа, текстовый ответ или ключевые числа
+fn summarize(response: &QueryResponse) -> String {
+    if let Some(analysis) = &response.analysis {
+        return analysis.headline.clone();
+    }
+    if let Some(text) = &response.text_response {
+        return crate::utils::truncate_on_line(text, 500);
+    }
+    crate::audit::key_numbers(response).join(", ")
+}
+
+/// Отвечает на inline-запрос: кэшированные заголовки сразу,
+/// а произвольный вопрос (`@bot топ городов`) - ответом бэкенда
+pub async fn handle_inline_query(bot: Bot, query: InlineQuery, state: Arc<BotState>) -> ResponseResult<()> {
+    let filter = query.query.trim().to_lowercase();
+    let headlines = state.headlines.headlines.read().await.clone();
+    // Карточку отправляют в чужие чаты: время подписано поясом отправителя
+    let user_id = state.context_scope.key(ChatId(query.from.id.0 as i64), Some(query.from.id));
+    let zone = state.user_zone(&user_id).await;
+
+    let mut results: Vec<InlineQueryResult> = headlines
+        .iter()
+        .filter(|h| filter.is_empty() || h.title.to_lowercase().contains(&filter))
+        .map(|h| {
+            let text = format!(
+                "<b>{}</b>\n{}\n\n<i>обновлено {}</i>",
+                h.title,
+                escape_html(&h.summary),
+                zone.stamp(h.updated_at, "%H:%M")
+            );
+            let article = InlineQueryResultArticle::new(
+                h.id,
+                h.title,
+                InputMessageContent::Text(InputMessageContentText::new(text).parse_mode(ParseMode::Html)),
+            )
+            .description(h.summary.clone());
+
+            match open_in_bot_url(&state.bot_username, h.id) {
+                Some(url) => article.reply_markup(InlineKeyboardMarkup::new(vec![vec![
+                    InlineKeyboardButton::url("🤖 Открыть в боте", url),
+                ]])),
+                None => article,
+            }
+        })
+        .map(InlineQueryResult::Article)
+        .collect();
+
+    let question = query.query.trim();
+    let is_free_text = question.chars().count() >= MIN_FREE_TEXT_LEN && results.is_empty();
+    if is_free_text {
+        if let Some(article) = free_text_answer(&state, &query, question).await {
+            results.push(InlineQueryResult::Article(article));
+        }
+    }
+
+    // Ответы на произвольные вопросы зависят от пользователя (токен, язык) и быстро устаревают
+    let mut answer = bot.answer_inline_query(query.id, results)
+        .cache_time(if is_free_text { 30 } else { 60 })
+        .is_personal(is_free_text);
+    if headlines.is_empty() && !is_free_text {
+        answer = answer
+            .switch_pm_text("Данные еще загружаются — открыть бота")
+            .switch_pm_parameter("inline");
+    }
+    answer.await?;
+
+    Ok(())
+}
+
+/// Карточка с ответом бэкенда на произвольный вопрос; `None`, если ответа нет или он не успел
+async fn free_text_answer(
+    state: &BotState,
+    query: &InlineQuery,
+    question: &str,
+) -> Option<InlineQueryResultArticle> {
+    // В inline-режиме нет чата, лимит считаем по личному чату пользователя
+    let user_chat = ChatId(query.from.id.0 as i64);
+    if state.rate_limiter.check(user_chat).await.is_err() {
+        return None;
+    }
+
+    let user_id = state.context_scope.key(user_chat, Some(query.from.id));
+    let zone = state.user_zone(&user_id).await;
+    let period = zone.question_period(question);
+    let request = QueryRequest {
+        question: question.to_string(),
+        include_analysis: true,
+        use_cache: true,
+        include_sql: false,
+        user_id: Some(user_id.clone()),
+        output_type: OutputType::Auto,
+        language: crate::handlers::answer_language(state, &user_id, None).await,
+        date_from: period.map(|period| period.from),
+        date_to: period.map(|period| period.to),
+        timezone: Some(zone.name()),
+    };
+
+    let api = state.api(&user_id).await;
+    let response = match tokio::time::timeout(INLINE_QUERY_TIMEOUT, api.query(request)).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            tracing::warn!("Inline query failed for user {}: {}", user_id, e);
+            return None;
+        }
+        Err(_) => {
+            tracing::warn!("Inline query timed out for user {}", user_id);
+            return None;
+        }
+    };
+
+    let summary = summarize(&response);
+    if summary.is_empty() {
+        return None;
+    }
+    let text = format!("<b>{}</b>\n{}", escape_html(question), escape_html(&summary));
+
+    Some(
+        InlineQueryResultArticle::new(
+            "answer",
+            question,
+            InputMessageContent::Text(InputMessageContentText::new(text).parse_mode(ParseMode::Html)),
+        )
+        .description(summary),
+    )
+}
+
+/// Deep link, запускающий полный запрос в личном чате с ботом
+fn open_in_bot_url(bot_username: &str, headline_id: &str) -> Option<Url> {
+    Url::parse(&format!("https://t.me/{}?start=q_{}", bot_username, headline_id)).ok()
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+/// Поддерживаемые языки
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Ru,
+    En,
+    Kk,
+}
+
+/// Фразы с просьбой ответить на определенном языке
+const ANSWER_LANGUAGE_PHRASES: &[(&str, Language)] = &[
+    ("ответь на английском", Language::En),
+    ("ответь по-английски", Language::En),
+    ("на английском", Language::En),
+    ("answer in english", Language::En),
+    ("in english", Language::En),
+    ("ответь на казахском", Language::Kk),
+    ("на казахском", Language::Kk),
+    ("қазақша жауап бер", Language::Kk),
+    ("қазақша", Language::Kk),
+    ("in kazakh", Language::Kk),
+    ("ответь на русском", Language::Ru),
+    ("на русском", Language::Ru),
+    ("in russian", Language::Ru),
+];
+
+impl Language {
+    /// Код языка для бэкенда (ISO 639-1)
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Ru => "ru",
+            Self::En => "en",
+            Self::Kk => "kk",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "ru" | "рус" | "русский" => Some(Self::Ru),
+            "en" | "eng" | "english" | "английский" => Some(Self::En),
+            "kk" | "kz" | "қаз" | "казахский" | "қазақша" => Some(Self::Kk),
+            _ => None,
+        }
+    }
+
+    /// Язык из `language_code` Telegram (`en`, `en-US`, `kk`)
+    pub fn from_telegram(code: &str) -> Option<Self> {
+        match code.split('-').next()?.to_lowercase().as_str() {
+            "ru" => Some(Self::Ru),
+            "en" => Some(Self::En),
+            "kk" => Some(Self::Kk),
+            _ => None,
+        }
+    }
+
+    /// Название на самом языке (для выбора языка интерфейса)
+    pub fn native_name(&self) -> &'static str {
+        match self {
+            Self::Ru => "🇷🇺 Русский",
+            Self::En => "🇬🇧 English",
+            Self::Kk => "🇰🇿 Қазақша",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ru => "русский",
+            Self::En => "английский",
+            Self::Kk => "казахский",
+        }
+    }
+}
+
+/// Где в `text` стоит фраза `phrase` (в нижнем регистре) без учета регистра: границы в байтах `text`.
+/// Смещения считаются по символам самого `text`: в нижнем регистре некоторые символы (`İ`, `K`
+/// знака Кельвина) меняют длину, и позиции в `text.to_lowercase()` с ними не совпадают.
+fn find_ignore_case(text: &str, phrase: &str) -> Option<std::ops::Range<usize>> {
+    text.char_indices().find_map(|(start, _)| {
+        let mut expected = phrase.chars();
+        for (offset, c) in text[start..].char_indices() {
+            for lower in c.to_lowercase() {
+                if expected.next() != Some(lower) {
+                    return None;
+                }
+            }
+            if expected.as_str().is_empty() {
+                return Some(start..start + offset + c.len_utf8());
+            }
+        }
+        None
+    })
+}
+
+/// Ищет в вопросе просьбу ответить на определенном языке («ответь на английском»).
+/// Возвращает вопрос без этой фразы и найденный язык.
+pub fn detect_answer_language(text: &str) -> (String, Option<Language>) {
+    for (phrase, language) in ANSWER_LANGUAGE_PHRASES {
+        if let Some(found) = find_ignore_case(text, phrase) {
+            let mut clean = String::with_capacity(text.len());
+            clean.push_str(&text[..found.start]);
+            clean.push_str(&text[found.end..]);
+            let clean = clean
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .trim_matches(|c: char| c == ',' || c.is_whitespace())
+                .to_string();
+            return (clean, Some(*language));
+        }
+    }
+    (text.to_string(), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answer_language_phrase_is_cut_out() {
+        assert_eq!(
+            detect_answer_language("Топ 5 городов, Ответь на английском"),
+            ("Топ 5 городов".to_string(), Some(Language::En))
+        );
+        assert_eq!(detect_answer_language("Топ 5 городов"), ("Топ 5 городов".to_string(), None));
+    }
+
+    #[test]
+    fn letters_changing_length_in_lowercase_do_not_shift_the_phrase() {
+        // «İ» в нижнем регистре длиннее, «K» (знак Кельвина) - короче: общая длина та же,
+        // а позиции после них сдвинуты
+        let text = "İİ\u{212A} оборот ОТВЕТЬ НА АНГЛИЙСКОМ за март";
+        assert_eq!(text.len(), text.to_lowercase().len());
+        assert_eq!(
+            detect_answer_language(text),
+            ("İİ\u{212A} оборот за март".to_string(), Some(Language::En))
+        );
+        assert_eq!(
+            detect_answer_language("İstanbul на английском"),
+            ("İstanbul".to_string(), Some(Language::En))
+        );
+    }
+}
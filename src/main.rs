@@ -1,9 +1,67 @@
+mod acl;
 mod bot;
+mod brief;
+mod commands;
+mod compare;
 mod config;
+mod correlation;
+mod currency;
+mod dedup;
+mod dialogue;
+mod drilldown;
+mod endpoints;
 mod handlers;
 mod api_client;
+mod api_error;
 mod utils;
 mod menu;
+mod messenger;
+mod audit;
+mod auth;
+mod backends;
+mod charts;
+mod estimate;
+mod examples;
+mod exports;
+mod followup;
+mod handoff;
+mod i18n;
+mod inline;
+mod language;
+mod metrics;
+mod monitor;
+mod notify;
+mod numbers;
+mod object_storage;
+mod onboarding;
+mod paging;
+mod pdf;
+mod progress;
+mod prompts;
+mod query_parser;
+mod queue;
+mod rate_limit;
+mod redis_storage;
+mod response_cache;
+mod retention;
+mod routing;
+mod scheduler;
+mod schema;
+mod sender;
+mod sessions;
+mod shutdown;
+mod spelling;
+mod state;
+mod stats;
+mod storage;
+mod suggestions;
+mod table_image;
+mod templates;
+mod timezone;
+mod transliterate;
+mod typing;
+mod uploads;
+mod watcher;
 
 use anyhow::Result;
 use config::Config;
@@ -12,14 +70,20 @@ use tracing::info;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // .env читаем до настройки логов, чтобы из него применялись RUST_LOG и LOG_FORMAT
+    dotenvy::dotenv().ok();
+
     // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env());
+    match std::env::var("LOG_FORMAT").as_deref() {
+        // Одна JSON-строка на событие, с полями текущего спана (в том числе correlation_id)
+        Ok("json") => subscriber.json().with_current_span(true).with_span_list(false).init(),
+        _ => subscriber.init(),
+    }
 
     // Load configuration
-    dotenvy::dotenv().ok();
-    let config = Config::from_env()?;
+    let config = Config::load()?;
     
     info!("Starting Telegram bot...");
     info!("Backend URL: {}", config.backend_url);
@@ -1,47 +1,243 @@
-use teloxide::types::{KeyboardButton, ReplyMarkup};
-
-/// Создает главное меню с кнопками
-pub fn create_main_menu() -> ReplyMarkup {
-    let mut keyboard: Vec<Vec<KeyboardButton>> = Vec::new();
-    
-    // Первая строка - популярные запросы
-    keyboard.push(vec![
-        KeyboardButton::new("📊 Топ категорий"),
-        KeyboardButton::new("💰 По валютам"),
-    ]);
-    
-    // Вторая строка
-    keyboard.push(vec![
-        KeyboardButton::new("📈 Динамика (7 дней)"),
-        KeyboardButton::new("🌍 По странам"),
-    ]);
-    
-    // Третья строка
-    keyboard.push(vec![
-        KeyboardButton::new("💳 По типам транзакций"),
-        KeyboardButton::new("📅 За сегодня"),
-    ]);
-    
-    // Четвертая строка - служебные
-    keyboard.push(vec![
-        KeyboardButton::new("❓ Помощь"),
-        KeyboardButton::new("🔄 Очистить контекст"),
-    ]);
-    
-    // Создаем клавиатуру через метод keyboard
-    // В teloxide 0.12 метод keyboard возвращает builder, который можно настроить
-    ReplyMarkup::keyboard(keyboard)
-}
-
-/// Преобразует текст кнопки в SQL-запрос
-pub fn button_to_query(button_text: &str) -> Option<String> {
-    match button_text {
-        "📊 Топ категорий" => Some("sql: Топ-10 категорий MCC по количеству транзакций".to_string()),
-        "💰 По валютам" => Some("sql: Распределение транзакций по валютам".to_string()),
-        "📈 Динамика (7 дней)" => Some("sql: Показать динамику транзакций по дням за последние 7 дней".to_string()),
-        "🌍 По странам" => Some("sql: Распределение транзакций по странам".to_string()),
-        "💳 По типам транзакций" => Some("sql: Распределение транзакций по типам".to_string()),
-        "📅 За сегодня" => Some("sql: Статистика транзакций за сегодня".to_string()),
-        _ => None,
+use crate::onboarding::UserRole;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, KeyboardButton, ReplyMarkup};
+
+/// Кнопки постоянной клавиатуры. Сами запросы живут в inline-меню, которое открывает `MENU_BUTTON`
+pub const MENU_BUTTON: &str = "📋 Меню";
+pub const HELP_BUTTON: &str = "❓ Помощь";
+pub const CLEAR_BUTTON: &str = "🔄 Очистить контекст";
+pub const BACK_BUTTON: &str = "⬅️ Назад";
+
+/// Сколько кнопок помещается в строку inline-меню
+const BUTTONS_PER_ROW: usize = 2;
+
+/// Пункт меню: раздел с вложенными пунктами или кнопка с запросом
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MenuItem {
+    pub label: String,
+    /// Запрос, который выполняет кнопка (`None` у разделов)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<MenuItem>,
+}
+
+impl MenuItem {
+    pub fn button(label: &str, query: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            query: Some(query.to_string()),
+            items: Vec::new(),
+        }
+    }
+
+    pub fn section(label: &str, items: Vec<MenuItem>) -> Self {
+        Self {
+            label: label.to_string(),
+            query: None,
+            items,
+        }
+    }
+
+    pub fn is_section(&self) -> bool {
+        self.query.is_none()
+    }
+}
+
+/// Меню по умолчанию, пока администраторы не изменили его через /menu_add и /menu_remove
+pub fn default_menu() -> Vec<MenuItem> {
+    vec![
+        MenuItem::section("📊 Категории и типы", vec![
+            MenuItem::button("📊 Топ категорий", "sql: Топ-10 категорий MCC по количеству транзакций"),
+            MenuItem::button("💳 По типам транзакций", "sql: Распределение транзакций по типам"),
+            MenuItem::button("🧾 Средний чек по категориям", "sql: Средний чек по категориям MCC"),
+        ]),
+        MenuItem::section("🌍 Валюты и география", vec![
+            MenuItem::button("💰 По валютам", "sql: Распределение транзакций по валютам"),
+            MenuItem::button("🌍 По странам", "sql: Распределение транзакций по странам"),
+            MenuItem::button("🏙 Топ городов", "sql: Топ-{N} городов по объему транзакций за {период}"),
+        ]),
+        MenuItem::section("📈 Динамика", vec![
+            MenuItem::button("📅 За сегодня", "sql: Статистика транзакций за сегодня"),
+            MenuItem::button("📈 Динамика (7 дней)", "sql: Показать динамику транзакций по дням за последние 7 дней"),
+            MenuItem::button("📆 По неделям (30 дней)", "sql: Динамика объема транзакций по неделям за последние 30 дней"),
+        ]),
+        MenuItem::section("🏦 Банки и карты", vec![
+            MenuItem::button("🏦 Топ банков", "sql: Топ-10 банков-эмитентов по объему транзакций"),
+            MenuItem::button("💳 Типы карт", "sql: Распределение транзакций по типам карт"),
+        ]),
+    ]
+}
+
+/// Постоянная клавиатура: кнопка меню, готовые запросы для роли пользователя и служебные кнопки
+pub fn create_main_keyboard(role: Option<UserRole>) -> ReplyMarkup {
+    let quick: Vec<_> = role
+        .map(UserRole::quick_buttons)
+        .unwrap_or_default()
+        .iter()
+        .map(|(label, _)| KeyboardButton::new(*label))
+        .collect();
+    let rows = [vec![KeyboardButton::new(MENU_BUTTON)], quick]
+        .into_iter()
+        .chain([vec![KeyboardButton::new(HELP_BUTTON), KeyboardButton::new(CLEAR_BUTTON)]])
+        .filter(|row| !row.is_empty())
+        .collect::<Vec<_>>();
+    ReplyMarkup::keyboard(rows)
+}
+
+/// Пункты уровня меню по пути из индексов (`None`, если меню изменилось и пути больше нет)
+pub fn level<'a>(menu: &'a [MenuItem], path: &[usize]) -> Option<&'a [MenuItem]> {
+    path.iter().try_fold(menu, |items, &index| {
+        items.get(index).filter(|item| item.is_section()).map(|item| item.items.as_slice())
+    })
+}
+
+/// Заголовок сообщения меню: путь по разделам
+pub fn title(menu: &[MenuItem], path: &[usize]) -> String {
+    let mut title = String::from("📋 Главное меню");
+    let mut items = menu;
+    for &index in path {
+        let Some(item) = items.get(index) else {
+            break;
+        };
+        title.push_str(" › ");
+        title.push_str(&item.label);
+        items = &item.items;
+    }
+    title
+}
+
+/// Inline-клавиатура уровня: разделы открываются (`menu:open:<i>`), кнопки выполняют запрос
+/// (`menu:run:<i>`); на вложенных уровнях добавляется «⬅️ Назад» (`menu:back`)
+pub fn level_keyboard(items: &[MenuItem], nested: bool) -> InlineKeyboardMarkup {
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = items
+        .iter()
+        .enumerate()
+        .collect::<Vec<_>>()
+        .chunks(BUTTONS_PER_ROW)
+        .map(|row| {
+            row.iter()
+                .map(|(index, item)| {
+                    let action = if item.is_section() { "open" } else { "run" };
+                    InlineKeyboardButton::callback(item.label.clone(), format!("menu:{}:{}", action, index))
+                })
+                .collect()
+        })
+        .collect();
+    if nested {
+        rows.push(vec![InlineKeyboardButton::callback(BACK_BUTTON, "menu:back")]);
     }
+    InlineKeyboardMarkup::new(rows)
+}
+
+/// Запрос кнопки по надписи на любом уровне меню
+/// (нужен для старых постоянных клавиатур, где кнопки запросов были в одном списке)
+pub fn button_to_query(menu: &[MenuItem], button_text: &str) -> Option<String> {
+    menu.iter().find_map(|item| match &item.query {
+        Some(query) if item.label == button_text => Some(query.clone()),
+        Some(_) => None,
+        None => button_to_query(&item.items, button_text),
+    })
+}
+
+/// Больше пунктов на одном уровне меню неудобно листать
+pub const MAX_ITEMS_PER_LEVEL: usize = 20;
+
+/// Результат добавления пункта через /menu_add
+#[derive(Debug, PartialEq)]
+pub enum AddOutcome {
+    Added,
+    /// Кнопка уже была, изменен ее запрос
+    Updated,
+    /// На уровне уже `MAX_ITEMS_PER_LEVEL` пунктов
+    LevelFull,
+    /// Надпись из пути занята кнопкой, а нужен раздел (или наоборот)
+    Conflict,
+}
+
+/// Путь к пункту меню: `Раздел > Подраздел > Надпись`
+pub fn parse_path(path: &str) -> Vec<String> {
+    path.split('>')
+        .map(|segment| segment.trim().to_string())
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// Добавляет кнопку с запросом по пути (недостающие разделы создаются) или меняет запрос существующей
+pub fn add(items: &mut Vec<MenuItem>, path: &[String], query: &str) -> AddOutcome {
+    let Some((label, rest)) = path.split_first() else {
+        return AddOutcome::Conflict;
+    };
+    let existing = items.iter().position(|item| &item.label == label);
+
+    if rest.is_empty() {
+        return match existing {
+            Some(index) if items[index].is_section() => AddOutcome::Conflict,
+            Some(index) => {
+                items[index].query = Some(query.to_string());
+                AddOutcome::Updated
+            }
+            None if items.len() >= MAX_ITEMS_PER_LEVEL => AddOutcome::LevelFull,
+            None => {
+                items.push(MenuItem::button(label, query));
+                AddOutcome::Added
+            }
+        };
+    }
+
+    let index = match existing {
+        Some(index) if !items[index].is_section() => return AddOutcome::Conflict,
+        Some(index) => index,
+        None if items.len() >= MAX_ITEMS_PER_LEVEL => return AddOutcome::LevelFull,
+        None => {
+            items.push(MenuItem::section(label, Vec::new()));
+            items.len() - 1
+        }
+    };
+    let outcome = add(&mut items[index].items, rest, query);
+    // Раздел, созданный для кнопки, которую не удалось добавить, не нужен
+    items.retain(|item| !(item.is_section() && item.items.is_empty()));
+    outcome
+}
+
+/// Удаляет пункт (раздел - вместе с содержимым); опустевшие разделы тоже удаляются
+pub fn remove(items: &mut Vec<MenuItem>, path: &[String]) -> bool {
+    let Some((label, rest)) = path.split_first() else {
+        return false;
+    };
+    let Some(index) = items.iter().position(|item| &item.label == label) else {
+        return false;
+    };
+
+    if rest.is_empty() {
+        items.remove(index);
+        return true;
+    }
+    let removed = remove(&mut items[index].items, rest);
+    items.retain(|item| !(item.is_section() && item.items.is_empty()));
+    removed
+}
+
+/// Дерево меню для /menu_list (HTML)
+pub fn describe(items: &[MenuItem], depth: usize, out: &mut String) {
+    let indent = "    ".repeat(depth);
+    for item in items {
+        match &item.query {
+            Some(query) => out.push_str(&format!(
+                "{}• {} — <code>{}</code>\n",
+                indent,
+                crate::utils::escape_html(&item.label),
+                crate::utils::escape_html(query)
+            )),
+            None => {
+                out.push_str(&format!("{}📁 <b>{}</b>\n", indent, crate::utils::escape_html(&item.label)));
+                describe(&item.items, depth + 1, out);
+            }
+        }
+    }
+}
+
+/// Надписи служебных кнопок нельзя занять пунктом меню
+pub fn is_reserved(label: &str) -> bool {
+    [MENU_BUTTON, HELP_BUTTON, CLEAR_BUTTON, BACK_BUTTON].contains(&label) || crate::onboarding::is_quick_button(label)
 }
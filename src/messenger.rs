@@ -0,0 +1,225 @@
+use crate::correlation::telegram;
+use std::future::Future;
+use teloxide::prelude::*;
+use teloxide::requests::HasPayload;
+use teloxide::types::{ChatAction, InlineKeyboardMarkup, InputFile, MessageId, ParseMode, ReplyMarkup};
+
+/// Оформление исходящего сообщения: разметка, клавиатура и сообщение, на которое оно отвечает
+#[derive(Debug, Clone, Default)]
+pub struct Outgoing {
+    pub parse_mode: Option<ParseMode>,
+    pub reply_markup: Option<ReplyMarkup>,
+    pub reply_to: Option<MessageId>,
+    pub disable_web_page_preview: bool,
+}
+
+impl Outgoing {
+    /// Текст в HTML - так бот отправляет почти все сообщения
+    pub fn html() -> Self {
+        Self { parse_mode: Some(ParseMode::Html), ..Self::default() }
+    }
+
+    pub fn parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.parse_mode = Some(parse_mode);
+        self
+    }
+
+    pub fn keyboard(mut self, keyboard: impl Into<ReplyMarkup>) -> Self {
+        self.reply_markup = Some(keyboard.into());
+        self
+    }
+
+    pub fn reply_to(mut self, message_id: MessageId) -> Self {
+        self.reply_to = Some(message_id);
+        self
+    }
+
+    pub fn without_preview(mut self) -> Self {
+        self.disable_web_page_preview = true;
+        self
+    }
+
+    /// Клавиатура для правки сообщения: Telegram позволяет прикрепить при правке только inline-клавиатуру
+    fn inline_keyboard(&self) -> Option<InlineKeyboardMarkup> {
+        match &self.reply_markup {
+            Some(ReplyMarkup::InlineKeyboard(markup)) => Some(markup.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Отправка сообщений в чат. Путь от вопроса до ответа (сообщение о ходе запроса, ответ,
+/// диаграмма, выгрузка) работает через этот трейт, а не напрямую с `Bot`, поэтому его можно
+/// проверить без Bot API: в тестах вместо бота подставляется `RecordingSender`.
+pub trait MessageSender: Clone + Send + Sync + 'static {
+    fn send_message(
+        &self,
+        chat_id: ChatId,
+        text: String,
+        options: Outgoing,
+    ) -> impl Future<Output = ResponseResult<MessageId>> + Send;
+
+    fn send_photo(
+        &self,
+        chat_id: ChatId,
+        photo: InputFile,
+        caption: String,
+        options: Outgoing,
+    ) -> impl Future<Output = ResponseResult<MessageId>> + Send;
+
+    fn send_document(
+        &self,
+        chat_id: ChatId,
+        document: InputFile,
+        caption: String,
+        options: Outgoing,
+    ) -> impl Future<Output = ResponseResult<MessageId>> + Send;
+
+    /// Заменяет текст сообщения; из клавиатуры `options` прикрепляется только inline-клавиатура
+    fn edit_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        text: String,
+        options: Outgoing,
+    ) -> impl Future<Output = ResponseResult<()>> + Send;
+
+    fn delete_message(&self, chat_id: ChatId, message_id: MessageId) -> impl Future<Output = ResponseResult<()>> + Send;
+
+    /// Индикатор «печатает...»
+    fn send_typing(&self, chat_id: ChatId) -> impl Future<Output = ResponseResult<()>> + Send;
+}
+
+/// Время каждого вызова учитывается в подвале `/debug`
+impl MessageSender for Bot {
+    async fn send_message(&self, chat_id: ChatId, text: String, options: Outgoing) -> ResponseResult<MessageId> {
+        let mut request = Requester::send_message(self, chat_id, text);
+        let payload = request.payload_mut();
+        payload.parse_mode = options.parse_mode;
+        payload.reply_markup = options.reply_markup;
+        payload.reply_to_message_id = options.reply_to;
+        payload.disable_web_page_preview = options.disable_web_page_preview.then_some(true);
+        Ok(telegram(request).await?.id)
+    }
+
+    async fn send_photo(&self, chat_id: ChatId, photo: InputFile, caption: String, options: Outgoing) -> ResponseResult<MessageId> {
+        let mut request = Requester::send_photo(self, chat_id, photo);
+        let payload = request.payload_mut();
+        payload.caption = Some(caption);
+        payload.parse_mode = options.parse_mode;
+        payload.reply_markup = options.reply_markup;
+        payload.reply_to_message_id = options.reply_to;
+        Ok(telegram(request).await?.id)
+    }
+
+    async fn send_document(
+        &self,
+        chat_id: ChatId,
+        document: InputFile,
+        caption: String,
+        options: Outgoing,
+    ) -> ResponseResult<MessageId> {
+        let mut request = Requester::send_document(self, chat_id, document);
+        let payload = request.payload_mut();
+        payload.caption = Some(caption);
+        payload.parse_mode = options.parse_mode;
+        payload.reply_markup = options.reply_markup;
+        payload.reply_to_message_id = options.reply_to;
+        Ok(telegram(request).await?.id)
+    }
+
+    async fn edit_message(&self, chat_id: ChatId, message_id: MessageId, text: String, options: Outgoing) -> ResponseResult<()> {
+        let mut request = Requester::edit_message_text(self, chat_id, message_id, text);
+        let payload = request.payload_mut();
+        payload.reply_markup = options.inline_keyboard();
+        payload.parse_mode = options.parse_mode;
+        payload.disable_web_page_preview = options.disable_web_page_preview.then_some(true);
+        telegram(request).await?;
+        Ok(())
+    }
+
+    async fn delete_message(&self, chat_id: ChatId, message_id: MessageId) -> ResponseResult<()> {
+        telegram(Requester::delete_message(self, chat_id, message_id)).await?;
+        Ok(())
+    }
+
+    async fn send_typing(&self, chat_id: ChatId) -> ResponseResult<()> {
+        telegram(self.send_chat_action(chat_id, ChatAction::Typing)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Что отправил обработчик через `RecordingSender`
+    #[derive(Debug, Clone)]
+    pub struct Sent {
+        /// Имя метода `MessageSender` (`send_message`, `edit_message`, ...)
+        pub method: &'static str,
+        pub message_id: MessageId,
+        /// Текст или подпись
+        pub text: String,
+        /// Надписи кнопок inline-клавиатуры по порядку
+        pub buttons: Vec<String>,
+    }
+
+    /// Подставной `MessageSender`: ничего не отправляет, а запоминает вызовы.
+    /// Отправленные сообщения получают id по порядку, начиная со 100.
+    #[derive(Clone, Default)]
+    pub struct RecordingSender {
+        sent: Arc<Mutex<Vec<Sent>>>,
+        last_id: Arc<AtomicI32>,
+    }
+
+    impl RecordingSender {
+        /// Записанные вызовы; журнал очищается
+        pub fn take(&self) -> Vec<Sent> {
+            std::mem::take(&mut *self.sent.lock().unwrap())
+        }
+
+        fn record(&self, method: &'static str, message_id: Option<MessageId>, text: String, options: &Outgoing) -> MessageId {
+            let message_id = message_id.unwrap_or_else(|| MessageId(100 + self.last_id.fetch_add(1, Ordering::SeqCst)));
+            let buttons = match &options.reply_markup {
+                Some(ReplyMarkup::InlineKeyboard(markup)) => {
+                    markup.inline_keyboard.iter().flatten().map(|button| button.text.clone()).collect()
+                }
+                _ => Vec::new(),
+            };
+            self.sent.lock().unwrap().push(Sent { method, message_id, text, buttons });
+            message_id
+        }
+    }
+
+    impl MessageSender for RecordingSender {
+        async fn send_message(&self, _: ChatId, text: String, options: Outgoing) -> ResponseResult<MessageId> {
+            Ok(self.record("send_message", None, text, &options))
+        }
+
+        async fn send_photo(&self, _: ChatId, _: InputFile, caption: String, options: Outgoing) -> ResponseResult<MessageId> {
+            Ok(self.record("send_photo", None, caption, &options))
+        }
+
+        async fn send_document(&self, _: ChatId, _: InputFile, caption: String, options: Outgoing) -> ResponseResult<MessageId> {
+            Ok(self.record("send_document", None, caption, &options))
+        }
+
+        async fn edit_message(&self, _: ChatId, message_id: MessageId, text: String, options: Outgoing) -> ResponseResult<()> {
+            let options = Outgoing { reply_markup: options.inline_keyboard().map(Into::into), ..options };
+            self.record("edit_message", Some(message_id), text, &options);
+            Ok(())
+        }
+
+        async fn delete_message(&self, _: ChatId, message_id: MessageId) -> ResponseResult<()> {
+            self.record("delete_message", Some(message_id), String::new(), &Outgoing::default());
+            Ok(())
+        }
+
+        async fn send_typing(&self, _: ChatId) -> ResponseResult<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Границы корзин гистограммы задержек бэкенда, в секундах
+const LATENCY_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// Границы корзин гистограммы времени отрисовки диаграмм, в секундах
+const RENDER_BUCKETS: [f64; 8] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// Счетчики бота в формате Prometheus. Один экземпляр на процесс ([`METRICS`]),
+/// чтобы их можно было обновлять из любого места без передачи состояния.
+pub struct Metrics {
+    updates: [AtomicU64; UpdateKind::COUNT],
+    query: BackendStats,
+    chat: BackendStats,
+    upload: BackendStats,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    chart_renders: Histogram<{ RENDER_BUCKETS.len() }>,
+    chart_render_errors: AtomicU64,
+}
+
+pub static METRICS: Metrics = Metrics {
+    updates: [const { AtomicU64::new(0) }; UpdateKind::COUNT],
+    query: BackendStats::new(),
+    chat: BackendStats::new(),
+    upload: BackendStats::new(),
+    cache_hits: AtomicU64::new(0),
+    cache_misses: AtomicU64::new(0),
+    chart_renders: Histogram::new(&RENDER_BUCKETS),
+    chart_render_errors: AtomicU64::new(0),
+};
+
+/// Тип входящего обновления Telegram
+#[derive(Debug, Clone, Copy)]
+pub enum UpdateKind {
+    Message,
+    /// Пользователь исправил свое сообщение
+    EditedMessage,
+    Command,
+    Callback,
+    Inline,
+}
+
+impl UpdateKind {
+    const COUNT: usize = 5;
+    const ALL: [Self; Self::COUNT] = [Self::Message, Self::EditedMessage, Self::Command, Self::Callback, Self::Inline];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Message => "message",
+            Self::EditedMessage => "edited_message",
+            Self::Command => "command",
+            Self::Callback => "callback",
+            Self::Inline => "inline",
+        }
+    }
+}
+
+/// Эндпоинт бэкенда, задержки которого измеряются отдельно
+#[derive(Debug, Clone, Copy)]
+pub enum Endpoint {
+    Query,
+    Chat,
+    Upload,
+}
+
+impl Endpoint {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Query => "query",
+            Self::Chat => "chat",
+            Self::Upload => "upload",
+        }
+    }
+}
+
+/// Гистограмма длительностей с фиксированными границами корзин
+struct Histogram<const N: usize> {
+    bounds: &'static [f64; N],
+    /// Счетчики по корзинам `bounds` (не накопительные)
+    buckets: [AtomicU64; N],
+    /// Значения больше последней границы (`+Inf`)
+    overflow: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl<const N: usize> Histogram<N> {
+    const fn new(bounds: &'static [f64; N]) -> Self {
+        Self {
+            bounds,
+            buckets: [const { AtomicU64::new(0) }; N],
+            overflow: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn count(&self) -> u64 {
+        self.buckets.iter().map(|bucket| bucket.load(Ordering::Relaxed)).sum::<u64>()
+            + self.overflow.load(Ordering::Relaxed)
+    }
+
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        match self.bounds.iter().position(|&bound| seconds <= bound) {
+            Some(bucket) => self.buckets[bucket].fetch_add(1, Ordering::Relaxed),
+            None => self.overflow.fetch_add(1, Ordering::Relaxed),
+        };
+        self.sum_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Строки `_bucket`, `_sum` и `_count`; `labels` - уже отформатированные метки (`endpoint="query"`)
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, cumulative);
+        }
+        cumulative += self.overflow.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, cumulative);
+        let labels = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+        let _ = writeln!(
+            out,
+            "{}_sum{} {}",
+            name,
+            labels,
+            self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(out, "{}_count{} {}", name, labels, cumulative);
+    }
+}
+
+struct BackendStats {
+    errors: AtomicU64,
+    latency: Histogram<{ LATENCY_BUCKETS.len() }>,
+}
+
+impl BackendStats {
+    const fn new() -> Self {
+        Self {
+            errors: AtomicU64::new(0),
+            latency: Histogram::new(&LATENCY_BUCKETS),
+        }
+    }
+
+    fn observe(&self, elapsed: Duration, success: bool) {
+        self.latency.observe(elapsed);
+        if !success {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Итоги работы с бэкендом с запуска бота (для `/stats`)
+pub struct Summary {
+    pub backend_requests: u64,
+    pub backend_errors: u64,
+    /// Средняя задержка запросов к бэкенду (`None`, если запросов еще не было)
+    pub backend_latency: Option<Duration>,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+}
+
+impl Metrics {
+    pub fn record_update(&self, kind: UpdateKind) {
+        self.updates[kind as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Запрос к бэкенду: задержка и успешность
+    pub fn record_backend(&self, endpoint: Endpoint, elapsed: Duration, success: bool) {
+        self.backend(endpoint).observe(elapsed, success);
+    }
+
+    pub fn record_cache(&self, hit: bool) {
+        let counter = if hit { &self.cache_hits } else { &self.cache_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Отрисовка диаграммы: время учитывается и для неудачных попыток
+    pub fn record_chart_render(&self, elapsed: Duration, success: bool) {
+        self.chart_renders.observe(elapsed);
+        if !success {
+            self.chart_render_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Итоги по всем эндпоинтам бэкенда
+    pub fn summary(&self) -> Summary {
+        let endpoints = [Endpoint::Query, Endpoint::Chat, Endpoint::Upload].map(|endpoint| self.backend(endpoint));
+        let backend_requests: u64 = endpoints.iter().map(|stats| stats.latency.count()).sum();
+        let latency_micros: u64 = endpoints.iter().map(|stats| stats.latency.sum_micros.load(Ordering::Relaxed)).sum();
+        Summary {
+            backend_requests,
+            backend_errors: endpoints.iter().map(|stats| stats.errors.load(Ordering::Relaxed)).sum(),
+            backend_latency: (backend_requests > 0).then(|| Duration::from_micros(latency_micros / backend_requests)),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn backend(&self, endpoint: Endpoint) -> &BackendStats {
+        match endpoint {
+            Endpoint::Query => &self.query,
+            Endpoint::Chat => &self.chat,
+            Endpoint::Upload => &self.upload,
+        }
+    }
+
+    /// Текстовый формат экспозиции Prometheus
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP bot_updates_total Telegram updates handled by the bot\n");
+        out.push_str("# TYPE bot_updates_total counter\n");
+        for kind in UpdateKind::ALL {
+            let _ = writeln!(
+                out,
+                "bot_updates_total{{kind=\"{}\"}} {}",
+                kind.label(),
+                self.updates[kind as usize].load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# HELP bot_backend_errors_total Failed backend requests\n");
+        out.push_str("# TYPE bot_backend_errors_total counter\n");
+        for endpoint in [Endpoint::Query, Endpoint::Chat, Endpoint::Upload] {
+            let _ = writeln!(
+                out,
+                "bot_backend_errors_total{{endpoint=\"{}\"}} {}",
+                endpoint.label(),
+                self.backend(endpoint).errors.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# HELP bot_backend_request_duration_seconds Backend request latency\n");
+        out.push_str("# TYPE bot_backend_request_duration_seconds histogram\n");
+        for endpoint in [Endpoint::Query, Endpoint::Chat, Endpoint::Upload] {
+            self.backend(endpoint).latency.render(
+                &mut out,
+                "bot_backend_request_duration_seconds",
+                &format!("endpoint=\"{}\"", endpoint.label()),
+            );
+        }
+
+        out.push_str("# HELP bot_chart_render_duration_seconds Chart image render time\n");
+        out.push_str("# TYPE bot_chart_render_duration_seconds histogram\n");
+        self.chart_renders.render(&mut out, "bot_chart_render_duration_seconds", "");
+
+        let counters = [
+            ("bot_response_cache_hits_total", "Answers served from the bot response cache", &self.cache_hits),
+            ("bot_response_cache_misses_total", "Cacheable questions sent to the backend", &self.cache_misses),
+            ("bot_chart_render_errors_total", "Chart images that failed to render", &self.chart_render_errors),
+        ];
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+        }
+
+        out
+    }
+}
+
+/// Запускает HTTP-сервер с `GET /metrics` на указанном порту
+pub fn spawn_server(port: u16) {
+    use axum::{routing::get, Router};
+
+    let app = Router::new().route(
+        "/metrics",
+        get(|| async {
+            (
+                [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+                METRICS.render(),
+            )
+        }),
+    );
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+
+    tokio::spawn(async move {
+        info!("Serving Prometheus metrics on http://{}/metrics", address);
+        if let Err(e) = axum::Server::bind(&address).serve(app.into_make_service()).await {
+            error!("Metrics server failed: {}", e);
+        }
+    });
+}
@@ -0,0 +1,223 @@
+use crate::api_client::{BackendClient, ChartData, ChartDataset};
+use chrono::{DateTime, FixedOffset, Utc};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use teloxide::prelude::*;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Сколько неудачных проверок подряд считается падением (защита от дребезга)
+const FAILURES_BEFORE_DOWN: u32 = 2;
+
+/// Сколько последних проверок хранится для `/status` (при интервале по умолчанию 30 с - час)
+const HISTORY_LEN: usize = 120;
+
+/// Результат одной проверки `/api/health`
+#[derive(Debug, Clone, Copy)]
+pub struct Probe {
+    pub at: DateTime<Utc>,
+    /// Время ответа; `None`, если бэкенд не ответил или ответил ошибкой
+    pub latency: Option<Duration>,
+}
+
+struct ProbeState {
+    consecutive_failures: u32,
+    down_since: Option<DateTime<Utc>>,
+    /// С какого момента бэкенд доступен (с запуска бота или с последнего восстановления)
+    up_since: DateTime<Utc>,
+    history: VecDeque<Probe>,
+}
+
+/// Доступность и задержки бэкенда по последним проверкам
+#[derive(Debug, Clone)]
+pub struct HealthSnapshot {
+    pub available: bool,
+    /// Когда бэкенд стал доступен или недоступен
+    pub since: DateTime<Utc>,
+    pub history: Vec<Probe>,
+}
+
+impl HealthSnapshot {
+    /// Доля успешных проверок, %
+    pub fn uptime_pct(&self) -> Option<f64> {
+        if self.history.is_empty() {
+            return None;
+        }
+        let successful = self.history.iter().filter(|probe| probe.latency.is_some()).count();
+        Some(successful as f64 * 100.0 / self.history.len() as f64)
+    }
+
+    fn latencies_ms(&self) -> impl Iterator<Item = f64> + '_ {
+        self.history.iter().filter_map(|probe| probe.latency).map(|latency| latency.as_secs_f64() * 1000.0)
+    }
+
+    /// Последняя, средняя и максимальная задержка успешных проверок, мс
+    pub fn latency_ms(&self) -> Option<(f64, f64, f64)> {
+        let last = self.latencies_ms().last()?;
+        let count = self.latencies_ms().count() as f64;
+        let average = self.latencies_ms().sum::<f64>() / count;
+        let max = self.latencies_ms().fold(0.0, f64::max);
+        Some((last, average, max))
+    }
+
+    /// Текст для `/status` (HTML)
+    pub fn render(&self, time_zone: FixedOffset) -> String {
+        let since = self.since.with_timezone(&time_zone).format("%d.%m %H:%M");
+        let elapsed = Utc::now() - self.since;
+        let duration = format_duration(elapsed.to_std().unwrap_or_default());
+        let mut text = if self.available {
+            format!("🟢 По фоновым проверкам доступен {} (с {})\n", duration, since)
+        } else {
+            format!("🔴 По фоновым проверкам недоступен {} (с {})\n", duration, since)
+        };
+        let (Some(first), Some(uptime)) = (self.history.first(), self.uptime_pct()) else {
+            text.push_str("Фоновых проверок еще не было\n");
+            return text;
+        };
+        let window = format_duration((Utc::now() - first.at).to_std().unwrap_or_default());
+        text.push_str(&format!(
+            "Доступность за {}: <b>{:.1}%</b> ({} проверок)\n",
+            window,
+            uptime,
+            self.history.len()
+        ));
+        if let Some((last, average, max)) = self.latency_ms() {
+            text.push_str(&format!("Задержка: {:.0} мс (средняя {:.0}, максимум {:.0})\n", last, average, max));
+        }
+        text
+    }
+
+    /// Линейный график задержек; неудачные проверки отмечены нулем. `None`, если точек меньше двух.
+    pub fn latency_chart(&self, time_zone: FixedOffset) -> Option<ChartData> {
+        if self.history.len() < 2 {
+            return None;
+        }
+        Some(ChartData {
+            log_scale: false,
+            chart_type: "line".to_string(),
+            labels: self.history.iter()
+                .map(|probe| probe.at.with_timezone(&time_zone).format("%H:%M").to_string())
+                .collect(),
+            datasets: vec![ChartDataset {
+                label: "Задержка, мс".to_string(),
+                data: self.history.iter()
+                    .map(|probe| probe.latency.map_or(0.0, |latency| latency.as_secs_f64() * 1000.0))
+                    .collect(),
+                background_color: None,
+            }],
+            title: Some("Задержка /api/health, мс (0 - бэкенд не ответил)".to_string()),
+        })
+    }
+}
+
+/// `3 ч 5 мин`, `12 мин`, `40 с`
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{} с", secs),
+        60..=3599 => format!("{} мин", secs / 60),
+        3600..=86399 => format!("{} ч {} мин", secs / 3600, secs % 3600 / 60),
+        _ => format!("{} д {} ч", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+/// Периодически проверяет бэкенд и хранит его текущую доступность и историю проверок
+pub struct BackendMonitor {
+    available: AtomicBool,
+    probe: Mutex<ProbeState>,
+}
+
+impl Default for BackendMonitor {
+    fn default() -> Self {
+        // До первой проверки считаем бэкенд доступным
+        Self {
+            available: AtomicBool::new(true),
+            probe: Mutex::new(ProbeState {
+                consecutive_failures: 0,
+                down_since: None,
+                up_since: Utc::now(),
+                history: VecDeque::with_capacity(HISTORY_LEN),
+            }),
+        }
+    }
+}
+
+impl BackendMonitor {
+    pub fn is_available(&self) -> bool {
+        self.available.load(Ordering::Relaxed)
+    }
+
+    /// Текущее состояние и история проверок для `/status`
+    pub async fn snapshot(&self) -> HealthSnapshot {
+        let probe = self.probe.lock().await;
+        HealthSnapshot {
+            available: self.is_available(),
+            since: probe.down_since.unwrap_or(probe.up_since),
+            history: probe.history.iter().copied().collect(),
+        }
+    }
+
+    /// Запускает фоновые проверки `/api/health` с уведомлениями в чат администратора
+    pub fn spawn(
+        self: &Arc<Self>,
+        bot: Bot,
+        api_client: Arc<dyn BackendClient>,
+        admin_chat: Option<ChatId>,
+        interval: Duration,
+    ) {
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let started = Instant::now();
+                let latency = matches!(api_client.health_check().await, Ok(true)).then(|| started.elapsed());
+                if let Some(text) = monitor.record_probe(latency).await {
+                    if let Some(chat_id) = admin_chat {
+                        if let Err(e) = bot.send_message(chat_id, &text).await {
+                            warn!("Failed to notify admin chat: {}", e);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Учитывает результат проверки (`None` - неудачная); возвращает текст уведомления при смене состояния
+    async fn record_probe(&self, latency: Option<Duration>) -> Option<String> {
+        let mut probe = self.probe.lock().await;
+        if probe.history.len() == HISTORY_LEN {
+            probe.history.pop_front();
+        }
+        probe.history.push_back(Probe { at: Utc::now(), latency });
+
+        if latency.is_some() {
+            probe.consecutive_failures = 0;
+            let down_since = probe.down_since.take()?;
+            probe.up_since = Utc::now();
+            self.available.store(true, Ordering::Relaxed);
+            let downtime = Utc::now() - down_since;
+            info!("Backend recovered after {} s", downtime.num_seconds());
+            return Some(format!(
+                "✅ Бэкенд снова доступен (простой {} мин {} с)",
+                downtime.num_minutes(),
+                downtime.num_seconds() % 60
+            ));
+        }
+
+        probe.consecutive_failures += 1;
+        if probe.consecutive_failures < FAILURES_BEFORE_DOWN || probe.down_since.is_some() {
+            return None;
+        }
+
+        probe.down_since = Some(Utc::now());
+        self.available.store(false, Ordering::Relaxed);
+        warn!("Backend is down after {} failed health checks", probe.consecutive_failures);
+        Some(format!(
+            "🚨 Бэкенд недоступен с {} UTC. Запросы пользователей временно не выполняются.",
+            Utc::now().format("%H:%M:%S")
+        ))
+    }
+}
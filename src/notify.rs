@@ -0,0 +1,224 @@
+use crate::api_client::ChartData;
+use crate::language::Language;
+use crate::state::BotState;
+use crate::timezone::UserZone;
+use crate::utils::{escape_html, format_value};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::{ApiError, RequestError};
+use tracing::{error, info, warn};
+
+/// Пауза между отправками в разные чаты, чтобы не упереться в ограничение Telegram (~30 сообщений в секунду)
+const SEND_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Рассылки, на которые можно подписать чат (`/subscribe`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topic {
+    /// Аномалии, найденные бэкендом (`POST /api/anomalies`)
+    Anomalies,
+}
+
+impl Topic {
+    pub const ALL: [Self; 1] = [Self::Anomalies];
+
+    /// Название в командах и хранилище
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Anomalies => "anomalies",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "anomalies" | "anomaly" | "аномалии" => Some(Self::Anomalies),
+            _ => None,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Anomalies => "аномалии в транзакциях",
+        }
+    }
+}
+
+/// Событие об аномалии, которое бэкенд присылает в `POST /api/anomalies`
+#[derive(Debug, Deserialize)]
+pub struct AnomalyEvent {
+    /// Мерчант (или другой объект), у которого найдена аномалия
+    #[serde(default)]
+    pub merchant: Option<String>,
+    /// Метрика, например «объем транзакций за час»
+    pub metric: String,
+    #[serde(default)]
+    pub value: Option<f64>,
+    /// Ожидаемое значение метрики
+    #[serde(default)]
+    pub expected: Option<f64>,
+    /// Отклонение от ожидаемого в процентах; если не задано, считается по `value` и `expected`
+    #[serde(default)]
+    pub deviation_pct: Option<f64>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub detected_at: Option<DateTime<Utc>>,
+    /// Диаграмма метрики в формате ответов `/api/query`
+    #[serde(default)]
+    pub chart_data: Option<ChartData>,
+}
+
+impl AnomalyEvent {
+    fn deviation_pct(&self) -> Option<f64> {
+        self.deviation_pct.or_else(|| match (self.value, self.expected) {
+            (Some(value), Some(expected)) if expected != 0.0 => Some((value - expected) * 100.0 / expected.abs()),
+            _ => None,
+        })
+    }
+
+    /// Текст уведомления (HTML); время обнаружения - в поясе `zone`
+    pub fn render(&self, zone: UserZone) -> String {
+        let mut text = String::from("⚠️ <b>Аномалия</b>");
+        if let Some(merchant) = &self.merchant {
+            text.push_str(&format!(": {}", escape_html(merchant)));
+        }
+        text.push_str(&format!("\n\nМетрика: {}\n", escape_html(&self.metric)));
+        match (self.value, self.expected) {
+            (Some(value), Some(expected)) => text.push_str(&format!(
+                "Значение: <b>{}</b> (ожидалось {})\n",
+                format_value(value),
+                format_value(expected)
+            )),
+            (Some(value), None) => text.push_str(&format!("Значение: <b>{}</b>\n", format_value(value))),
+            _ => {}
+        }
+        if let Some(deviation) = self.deviation_pct() {
+            let arrow = if deviation >= 0.0 { "📈" } else { "📉" };
+            text.push_str(&format!("Отклонение: {} {:+.1}%\n", arrow, deviation));
+        }
+        if let Some(description) = self.description.as_deref().filter(|description| !description.is_empty()) {
+            text.push_str(&format!("\n{}\n", escape_html(description)));
+        }
+        if let Some(detected_at) = self.detected_at {
+            text.push_str(&format!("\n<i>Обнаружено {}</i>", zone.stamp(detected_at, "%d.%m.%Y %H:%M")));
+        }
+        text
+    }
+}
+
+struct Endpoint {
+    bot: Bot,
+    state: Arc<BotState>,
+    secret: String,
+}
+
+/// Запускает HTTP-сервер, принимающий события бэкенда (`POST /api/anomalies`).
+/// Запросы без заголовка `Authorization: Bearer <secret>` отклоняются.
+pub fn spawn_server(port: u16, secret: String, bot: Bot, state: Arc<BotState>) {
+    let endpoint = Arc::new(Endpoint { bot, state, secret });
+    let app = Router::new()
+        .route("/api/anomalies", post(receive_anomaly))
+        .with_state(endpoint);
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+
+    tokio::spawn(async move {
+        info!("Accepting backend notifications on http://{}/api/anomalies", address);
+        if let Err(e) = axum::Server::bind(&address).serve(app.into_make_service()).await {
+            error!("Notification server failed: {}", e);
+        }
+    });
+}
+
+async fn receive_anomaly(
+    State(endpoint): State<Arc<Endpoint>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !token.is_some_and(|token| constant_time_eq(token.as_bytes(), endpoint.secret.as_bytes())) {
+        warn!("Rejected anomaly notification with invalid credentials");
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "invalid token" })));
+    }
+
+    let event: AnomalyEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))),
+    };
+    if event.metric.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "metric is required" })));
+    }
+
+    let chats = endpoint.state.storage.subscribers(Topic::Anomalies.as_str()).await;
+    info!("Received anomaly notification for {:?}, delivering to {} chats", event.metric, chats.len());
+    let subscribers = chats.len();
+    // Отвечаем сразу: рассылка по многим чатам может занять время
+    tokio::spawn(async move {
+        deliver(&endpoint.bot, &endpoint.state, &event, chats).await;
+    });
+    (StatusCode::ACCEPTED, Json(json!({ "subscribers": subscribers })))
+}
+
+/// Отправляет событие во все подписанные чаты; чаты, где бот заблокирован или удален, отписываются
+async fn deliver(bot: &Bot, state: &BotState, event: &AnomalyEvent, chats: Vec<i64>) {
+    // Одна диаграмма на все чаты - подпись в поясе бота
+    let bot_zone = UserZone::new(None, state.time_zone);
+    let chart = match &event.chart_data {
+        Some(chart_data) => match state.chart_renderer.render(chart_data, 1000, 700, Language::Ru, None, bot_zone).await {
+            Ok(image) => Some(image),
+            Err(e) => {
+                warn!("Failed to render anomaly chart: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    for chat in chats {
+        let chat_id = ChatId(chat);
+        // Подписка - по чату: в личном чате это пояс пользователя, в группе - пояс группы или бота
+        let text = event.render(state.user_zone(&chat.to_string()).await);
+        let mut result = bot.send_message(chat_id, text)
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .await
+            .map(|_| ());
+        if let (Ok(()), Some(image)) = (&result, &chart) {
+            result = bot.send_photo(chat_id, teloxide::types::InputFile::memory(image.clone()).file_name("anomaly.png"))
+                .await
+                .map(|_| ());
+        }
+
+        match result {
+            Ok(()) => {}
+            Err(RequestError::Api(
+                ApiError::BotBlocked
+                | ApiError::ChatNotFound
+                | ApiError::BotKicked
+                | ApiError::BotKickedFromSupergroup
+                | ApiError::UserDeactivated,
+            )) => {
+                info!("Chat {} is no longer reachable, unsubscribing from anomalies", chat);
+                if let Err(e) = state.storage.unsubscribe(chat, Topic::Anomalies.as_str()).await {
+                    error!("Failed to unsubscribe chat {}: {}", chat, e);
+                }
+            }
+            Err(e) => error!("Failed to deliver anomaly notification to chat {}: {}", chat, e),
+        }
+        tokio::time::sleep(SEND_INTERVAL).await;
+    }
+}
+
+/// Сравнение секрета, время которого не зависит от позиции первого отличия
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,179 @@
+use crate::language::Language;
+
+/// Неразрывный пробел: разделитель разрядов и отступ перед знаком валюты в русском и казахском
+const NBSP: char = '\u{a0}';
+
+/// Знак валюты, в которой бэкенд возвращает суммы транзакций (тенге)
+const DATA_CURRENCY_SYMBOL: &str = "₸";
+
+/// Разделители разрядов и дробной части для языка
+fn separators(lang: Language) -> (char, char) {
+    match lang {
+        Language::Ru | Language::Kk => (NBSP, ','),
+        Language::En => (',', '.'),
+    }
+}
+
+/// Число с `decimals` знаками после запятой и разрядами по правилам языка:
+/// `1 234 567,89` (ru, kk), `1,234,567.89` (en)
+fn format_fixed(value: f64, decimals: usize, lang: Language) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let (group, decimal) = separators(lang);
+    let digits = format!("{:.*}", decimals, value.abs());
+    let (integer, fraction) = match digits.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (digits.as_str(), None),
+    };
+
+    let mut result = String::with_capacity(digits.len() + integer.len() / 3 + 1);
+    // `-0,00` после округления показываем без минуса
+    if value < 0.0 && digits.bytes().any(|digit| matches!(digit, b'1'..=b'9')) {
+        result.push('-');
+    }
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            result.push(group);
+        }
+        result.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        result.push(decimal);
+        result.push_str(fraction);
+    }
+    result
+}
+
+/// Число для таблиц и текста: целые без дробной части, остальные - с двумя знаками
+pub fn format_number(value: f64, lang: Language) -> String {
+    let decimals = if value.fract() == 0.0 { 0 } else { 2 };
+    format_fixed(value, decimals, lang)
+}
+
+/// Изменение со знаком: `+1 234`, `-56,50`
+pub fn format_change(value: f64, lang: Language) -> String {
+    let number = format_number(value, lang);
+    if value > 0.0 { format!("+{}", number) } else { number }
+}
+
+/// Изменение в процентах с одним знаком после запятой: `+12,5%`, `-3%`
+pub fn format_percent_change(percent: f64, lang: Language) -> String {
+    let rounded = (percent * 10.0).round() / 10.0;
+    let decimals = if rounded.fract() == 0.0 { 0 } else { 1 };
+    let number = format_fixed(rounded, decimals, lang);
+    if rounded > 0.0 { format!("+{}%", number) } else { format!("{}%", number) }
+}
+
+/// Короткая запись больших чисел для подписей осей и заголовков: `1,2 млн`, `350 тыс.`, `1.2M`.
+/// Числа меньше 10 000 записываются полностью.
+pub fn format_compact(value: f64, lang: Language) -> String {
+    const SCALES: [(f64, [&str; 3]); 4] = [
+        // ru, kk, en
+        (1e12, ["трлн", "трлн", "T"]),
+        (1e9, ["млрд", "млрд", "B"]),
+        (1e6, ["млн", "млн", "M"]),
+        (1e3, ["тыс.", "мың", "K"]),
+    ];
+
+    let Some((scale, names)) = SCALES.iter().find(|(scale, _)| value.abs() >= *scale && value.abs() >= 1e4) else {
+        return format_number(value, lang);
+    };
+    let scaled = value / scale;
+    let decimals = if scaled.abs() < 100.0 && (scaled * 10.0).round() % 10.0 != 0.0 { 1 } else { 0 };
+    let number = format_fixed(scaled, decimals, lang);
+    match lang {
+        Language::Ru => format!("{}{}{}", number, NBSP, names[0]),
+        Language::Kk => format!("{}{}{}", number, NBSP, names[1]),
+        Language::En => format!("{}{}", number, names[2]),
+    }
+}
+
+/// Колонка с денежными суммами (по названию): `amount`, `total_sum`, `объем`, `сумма_kzt`.
+/// Колонки с количеством и долями (`count`, `кол-во`, `pct`) суммами не считаются.
+pub fn is_money_column(name: &str) -> bool {
+    const MONEY: [&str; 14] = [
+        "amount", "sum", "total", "revenue", "volume", "price", "kzt", "сумм", "объем", "объём", "выручк", "оборот",
+        "стоимост", "тенге",
+    ];
+    const NOT_MONEY: [&str; 9] = ["count", "cnt", "qty", "кол", "число", "pct", "percent", "доля", "%"];
+
+    let name = name.to_lowercase();
+    MONEY.iter().any(|word| name.contains(word)) && !NOT_MONEY.iter().any(|word| name.contains(word))
+}
+
+/// Заголовок колонки таблицы: у числовой колонки с суммами указывается валюта (`сумма, ₸`).
+/// Колонки, пересчитанные в другую валюту (`amount_usd`, см. `currency`), получают ее знак.
+pub fn column_header(column: &str, numeric: bool) -> String {
+    if !numeric || !is_money_column(column) {
+        return column.to_string();
+    }
+    let lower = column.to_lowercase();
+    let symbol = crate::currency::Currency::ALL
+        .iter()
+        .find(|currency| lower.ends_with(&format!("_{}", currency.code().to_lowercase())))
+        .map_or(DATA_CURRENCY_SYMBOL, |currency| currency.symbol());
+    format!("{}, {}", column, symbol)
+}
+
+/// Переписывает «сырые» числа в тексте бэкенда по правилам языка: `1234567.89 KZT` → `1 234 567,89 ₸`.
+/// Трогает только большие числа (от 10 000 или от 1000 с дробной частью), чтобы не менять
+/// годы, даты, время и идентификаторы.
+pub fn localize_text(text: &str, lang: Language) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let starts_number = chars[i].is_ascii_digit() && (i == 0 || !is_number_neighbour(&chars, i - 1, true));
+        if !starts_number {
+            result.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        let integer_digits = i - start;
+        let has_fraction = i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit();
+        if has_fraction {
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+        let number: String = chars[start..i].iter().collect();
+
+        let standalone = i == chars.len() || !is_number_neighbour(&chars, i, false);
+        let large = integer_digits >= 5 || (has_fraction && integer_digits >= 4);
+        match number.parse::<f64>() {
+            Ok(value) if standalone && large => result.push_str(&format_number(value, lang)),
+            _ => result.push_str(&number),
+        }
+
+        // Код валюты после числа заменяем знаком: `500 KZT` → `500 ₸`
+        let rest: String = chars[i..chars.len().min(i + 5)].iter().collect();
+        if standalone && rest.starts_with(" KZT") && !rest[4..].starts_with(char::is_alphanumeric) {
+            result.push(NBSP);
+            result.push_str(DATA_CURRENCY_SYMBOL);
+            i += 4;
+        }
+    }
+    result
+}
+
+/// Символ рядом с цифрами, из-за которого они - часть даты, времени, версии или слова
+fn is_number_neighbour(chars: &[char], index: usize, before: bool) -> bool {
+    let c = chars[index];
+    if c.is_alphanumeric() || c == '_' {
+        return true;
+    }
+    if !matches!(c, '-' | ':' | '/' | '.' | ',') {
+        return false;
+    }
+    // `2024-03-01`, `10:30`, `1.2.3`: разделитель, за которым (или перед которым) снова цифра
+    let neighbour = if before { index.checked_sub(1) } else { Some(index + 1) };
+    neighbour.and_then(|j| chars.get(j)).is_some_and(|c| c.is_ascii_digit())
+}
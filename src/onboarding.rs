@@ -0,0 +1,249 @@
+use crate::api_client::OutputType;
+use crate::i18n::{tr, Msg};
+use crate::language::Language;
+use crate::state::BotState;
+use crate::utils::format_error;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode};
+use tracing::{error, info};
+
+/// Роль, которую пользователь выбрал при знакомстве: от нее зависят кнопки клавиатуры и примеры вопросов
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+    /// Пишет запросы сам, ему нужны SQL и выгрузки
+    Analyst,
+    /// Смотрит сводки и динамику
+    Manager,
+}
+
+/// Кнопки постоянной клавиатуры руководителя: (надпись, запрос)
+const MANAGER_BUTTONS: &[(&str, &str)] = &[
+    ("📅 Сводка за сегодня", "sql: Статистика транзакций за сегодня"),
+    ("📈 Динамика за неделю", "sql: Показать динамику транзакций по дням за последние 7 дней"),
+];
+
+impl UserRole {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "analyst" | "аналитик" => Some(Self::Analyst),
+            "manager" | "руководитель" => Some(Self::Manager),
+            _ => None,
+        }
+    }
+
+    /// Значение для `/settings role` и кнопок знакомства
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Analyst => "analyst",
+            Self::Manager => "manager",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Analyst => "аналитик",
+            Self::Manager => "руководитель",
+        }
+    }
+
+    fn label(self, lang: Language) -> &'static str {
+        match self {
+            Self::Analyst => tr(lang, Msg::RoleAnalyst),
+            Self::Manager => tr(lang, Msg::RoleManager),
+        }
+    }
+
+    /// Дополнительные кнопки постоянной клавиатуры с готовыми запросами
+    pub fn quick_buttons(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Analyst => &[],
+            Self::Manager => MANAGER_BUTTONS,
+        }
+    }
+
+    /// Вопросы, которые предлагаются сразу после знакомства
+    fn examples(self, lang: Language) -> &'static [&'static str] {
+        match (self, lang) {
+            (Self::Analyst, Language::Ru) => &[
+                "Средний чек по категориям MCC за последний месяц",
+                "Топ-10 банков-эмитентов по объему транзакций",
+                "Распределение транзакций по типам карт и валютам",
+            ],
+            (Self::Analyst, Language::En) => &[
+                "Average ticket by MCC category for the last month",
+                "Top 10 issuing banks by transaction volume",
+                "Transactions by card type and currency",
+            ],
+            (Self::Analyst, Language::Kk) => &[
+                "Соңғы айдағы MCC санаттары бойынша орташа чек",
+                "Транзакция көлемі бойынша топ-10 эмитент банк",
+                "Транзакциялардың карта түрлері мен валюталар бойынша бөлінуі",
+            ],
+            (Self::Manager, Language::Ru) => &[
+                "Сколько транзакций было вчера и на какую сумму?",
+                "Динамика объема транзакций по неделям за последние 30 дней",
+                "Топ-5 городов по объему транзакций за месяц",
+            ],
+            (Self::Manager, Language::En) => &[
+                "How many transactions were there yesterday and for what amount?",
+                "Weekly transaction volume for the last 30 days",
+                "Top 5 cities by transaction volume this month",
+            ],
+            (Self::Manager, Language::Kk) => &[
+                "Кеше қанша транзакция болды және қандай сомаға?",
+                "Соңғы 30 күндегі транзакция көлемінің апталық динамикасы",
+                "Айдағы транзакция көлемі бойынша топ-5 қала",
+            ],
+        }
+    }
+}
+
+/// Запрос кнопки постоянной клавиатуры, добавленной по роли
+pub fn quick_query(text: &str) -> Option<&'static str> {
+    [UserRole::Analyst, UserRole::Manager]
+        .iter()
+        .flat_map(|role| role.quick_buttons())
+        .find(|(label, _)| *label == text)
+        .map(|(_, query)| *query)
+}
+
+/// Надписи кнопок ролей: их нельзя занять пунктом меню
+pub fn is_quick_button(label: &str) -> bool {
+    quick_query(label).is_some()
+}
+
+/// Первый шаг знакомства: приветствие и выбор языка (`onb:lang:<код>`)
+pub async fn start(bot: &Bot, msg: &Message, state: &BotState) -> ResponseResult<()> {
+    let lang = state.ui_language(&state.user_key(msg), msg.from()).await;
+    let keyboard = InlineKeyboardMarkup::new(vec![
+        [Language::Ru, Language::En, Language::Kk]
+            .into_iter()
+            .map(|language| InlineKeyboardButton::callback(language.native_name(), format!("onb:lang:{}", language.code())))
+            .collect::<Vec<_>>(),
+    ]);
+
+    bot.send_message(msg.chat.id, tr(lang, Msg::Welcome))
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// Короткая справка под роль с постоянной клавиатурой и кнопки с примерами вопросов
+pub async fn send_ready(bot: &Bot, chat_id: ChatId, state: &BotState, lang: Language, role: UserRole) -> ResponseResult<()> {
+    let text = match role {
+        UserRole::Analyst => tr(lang, Msg::ReadyAnalyst),
+        UserRole::Manager => tr(lang, Msg::ReadyManager),
+    };
+    bot.send_message(chat_id, text)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(crate::menu::create_main_keyboard(Some(role)))
+        .await?;
+
+    // Кнопки несут хеш вопроса (`q:<hash>`): полный текст не помещается в callback-данные
+    let examples = InlineKeyboardMarkup::new(role.examples(lang).iter().map(|question| {
+        vec![InlineKeyboardButton::callback(*question, format!("q:{}", state.suggestions.insert(question)))]
+    }));
+    bot.send_message(chat_id, tr(lang, Msg::TryExamples))
+        .reply_markup(examples)
+        .await?;
+    Ok(())
+}
+
+/// Кнопки знакомства: `lang:<код>` → роль, `role:<роль>` → формат ответов, `out:<формат>` → готово.
+/// Каждый ответ сразу сохраняется в настройках нажавшего.
+pub async fn handle_callback(
+    bot: Bot,
+    msg: Message,
+    user: UserId,
+    action: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let Some((step, value)) = action.split_once(':') else {
+        return Ok(());
+    };
+    let user_id = state.context_scope.key(msg.chat.id, Some(user));
+
+    match step {
+        "lang" => {
+            let Some(language) = Language::parse(value) else {
+                return Ok(());
+            };
+            if !save(&bot, &msg, &state, &user_id, language, |settings| settings.interface_language = Some(language)).await? {
+                return Ok(());
+            }
+            let keyboard = InlineKeyboardMarkup::new(vec![
+                [UserRole::Analyst, UserRole::Manager]
+                    .into_iter()
+                    .map(|role| InlineKeyboardButton::callback(role.label(language), format!("onb:role:{}", role.as_str())))
+                    .collect::<Vec<_>>(),
+            ]);
+            bot.edit_message_text(msg.chat.id, msg.id, tr(language, Msg::OnboardingRole))
+                .parse_mode(ParseMode::Html)
+                .reply_markup(keyboard)
+                .await?;
+        }
+        "role" => {
+            let Some(role) = UserRole::parse(value) else {
+                return Ok(());
+            };
+            let lang = state.ui_language(&user_id, None).await;
+            if !save(&bot, &msg, &state, &user_id, lang, |settings| settings.role = Some(role)).await? {
+                return Ok(());
+            }
+            let keyboard = InlineKeyboardMarkup::new(vec![
+                [
+                    (OutputType::Auto, Msg::OutputAuto),
+                    (OutputType::Table, Msg::OutputTable),
+                    (OutputType::Chart, Msg::OutputChart),
+                ]
+                    .into_iter()
+                    .map(|(output, label)| InlineKeyboardButton::callback(tr(lang, label), format!("onb:out:{}", output.as_str())))
+                    .collect::<Vec<_>>(),
+            ]);
+            bot.edit_message_text(msg.chat.id, msg.id, tr(lang, Msg::OnboardingOutput))
+                .parse_mode(ParseMode::Html)
+                .reply_markup(keyboard)
+                .await?;
+        }
+        "out" => {
+            let Some(output_type) = OutputType::parse(value) else {
+                return Ok(());
+            };
+            let lang = state.ui_language(&user_id, None).await;
+            if !save(&bot, &msg, &state, &user_id, lang, |settings| settings.output_type = output_type).await? {
+                return Ok(());
+            }
+            let role = state.storage.settings(&user_id).await.role.unwrap_or(UserRole::Analyst);
+            info!("User {} finished onboarding as {}", user_id, role.as_str());
+            // Постоянную клавиатуру нельзя прикрепить правкой, поэтому вопросы знакомства заменяются новыми сообщениями
+            let _ = bot.delete_message(msg.chat.id, msg.id).await;
+            send_ready(&bot, msg.chat.id, &state, lang, role).await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Сохраняет ответ знакомства; при ошибке сообщает о ней и возвращает `false`
+async fn save(
+    bot: &Bot,
+    msg: &Message,
+    state: &BotState,
+    user_id: &str,
+    lang: Language,
+    apply: impl FnOnce(&mut crate::storage::UserSettings),
+) -> ResponseResult<bool> {
+    if let Err(e) = state.storage.update_user(user_id, |user| apply(&mut user.settings)).await {
+        error!("Error saving onboarding answer for user {}: {}", user_id, e);
+        bot.send_message(msg.chat.id, format_error(tr(lang, Msg::SettingSaveFailed)))
+            .parse_mode(ParseMode::Html)
+            .await?;
+        return Ok(false);
+    }
+    Ok(true)
+}
@@ -0,0 +1,371 @@
+use crate::api_client::QueryResponse;
+use crate::language::Language;
+use crate::utils::{collect_columns, escape_html, format_data_as_table};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use teloxide::types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup, MessageId};
+use tokio::sync::Mutex;
+
+/// Сколько строк показывается на одной странице
+pub const PAGE_SIZE: usize = 10;
+
+/// Сколько постраничных результатов помним для навигации
+const MAX_CACHED_RESULTS: usize = 200;
+
+/// Для скольких первых строк ответа есть кнопки «🔎 1…5»
+const MAX_DETAIL_ROWS: usize = 5;
+
+/// По скольким колонкам таблицы можно сортировать кнопками (сначала числовые)
+const MAX_SORT_COLUMNS: usize = 4;
+
+/// Длиннее названия колонок на кнопках сортировки обрезаются
+const MAX_SORT_LABEL_CHARS: usize = 14;
+
+/// Кнопки «Топ N» под таблицей (предлагаются, только если строк больше)
+const TOP_LIMITS: [usize; 3] = [5, 10, 50];
+
+/// Результат листается, если строк больше, чем помещается на страницу
+pub fn is_paginated(response: &QueryResponse) -> bool {
+    response.data.len() > PAGE_SIZE
+}
+
+pub fn page_count(rows: &[Value]) -> usize {
+    rows.len().div_ceil(PAGE_SIZE).max(1)
+}
+
+/// Сортировка по колонке с номером `column` (в порядке `collect_columns`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub column: usize,
+    pub descending: bool,
+}
+
+/// Как показывается результат в сообщении с таблицей: сортировка и «Топ N».
+/// Меняется кнопками под таблицей без запроса к бэкенду.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct View {
+    pub sort: Option<Sort>,
+    /// Сколько первых строк показывать (`None` - все)
+    pub top: Option<usize>,
+}
+
+impl View {
+    /// Вид после нажатия `view:<action>`: `sort:<колонка>` сортирует по убыванию, повторное
+    /// нажатие - по возрастанию; `top:<n>` оставляет первые n строк, `top:0` - все строки
+    pub fn apply(self, action: &str) -> Option<Self> {
+        let (kind, value) = action.split_once(':')?;
+        let value: usize = value.parse().ok()?;
+        match kind {
+            "sort" => {
+                let descending = !matches!(self.sort, Some(sort) if sort.column == value && sort.descending);
+                Some(Self { sort: Some(Sort { column: value, descending }), ..self })
+            }
+            "top" => Some(Self { top: (value > 0).then_some(value), ..self }),
+            _ => None,
+        }
+    }
+}
+
+/// Строки в виде `view`: отсортированные (пустые значения всегда в конце) и ограниченные «Топ N»
+pub fn apply_view(rows: &[Value], view: View) -> Vec<Value> {
+    let mut rows = rows.to_vec();
+    if let Some(sort) = view.sort {
+        if let Some(column) = collect_columns(&rows).get(sort.column) {
+            let value = |row: &Value| row.get(column).filter(|value| !value.is_null()).cloned();
+            rows.sort_by(|a, b| match (value(a), value(b)) {
+                (Some(a), Some(b)) if sort.descending => compare_values(&a, &b).reverse(),
+                (Some(a), Some(b)) => compare_values(&a, &b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            });
+        }
+    }
+    if let Some(top) = view.top {
+        rows.truncate(top);
+    }
+    rows
+}
+
+/// Числа сравниваются как числа, строки - без учета регистра, числа идут раньше строк
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            a.as_f64().partial_cmp(&b.as_f64()).unwrap_or(Ordering::Equal)
+        }
+        (Value::Number(_), _) => Ordering::Less,
+        (_, Value::Number(_)) => Ordering::Greater,
+        (Value::String(a), Value::String(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+        _ => a.to_string().cmp(&b.to_string()),
+    }
+}
+
+/// Текст страницы `page` (с нуля) с таблицей строк в виде `view`
+pub fn render_page(rows: &[Value], view: View, page: usize, lang: Language) -> String {
+    let columns = collect_columns(rows);
+    let rows = apply_view(rows, view);
+    let start = page * PAGE_SIZE;
+    let end = (start + PAGE_SIZE).min(rows.len());
+    let mut text = format!("📋 <b>Результаты</b> — строки {}–{} из {}", start + 1, end, rows.len());
+    let sort = view.sort.and_then(|sort| Some((columns.get(sort.column)?, sort.descending)));
+    match (sort, view.top) {
+        (Some((column, descending)), top) => {
+            text.push_str(&format!("\n<i>По {} {}", escape_html(column), sort_arrow(descending)));
+            if let Some(top) = top {
+                text.push_str(&format!(", первые {}", top));
+            }
+            text.push_str("</i>");
+        }
+        (None, Some(top)) => text.push_str(&format!("\n<i>Первые {}</i>", top)),
+        (None, None) => {}
+    }
+    text.push_str("\n\n");
+    text.push_str(&format_data_as_table(&rows[start.min(end)..end], lang));
+    text
+}
+
+fn sort_arrow(descending: bool) -> &'static str {
+    if descending { "⬇️" } else { "⬆️" }
+}
+
+/// Кнопки таблицы: ⬅️/➡️ с номером страницы, сортировка и «Топ N»
+pub fn result_keyboard(rows: &[Value], view: View, page: usize) -> InlineKeyboardMarkup {
+    let pages = page_count(&apply_view(rows, view));
+    let mut keyboard = vec![page_row(page, pages)];
+    keyboard.extend(view_rows(rows, view));
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+/// Колонка числовая, если все ее непустые значения - числа
+fn is_numeric_column(rows: &[Value], column: &str) -> bool {
+    let mut values = rows.iter().filter_map(|row| row.get(column)).filter(|value| !value.is_null()).peekable();
+    values.peek().is_some() && values.all(Value::is_number)
+}
+
+/// Колонки для кнопок сортировки с их номерами: сначала числовые (суммы, количества),
+/// потом остальные, в каждой группе - в порядке `collect_columns`
+fn sort_columns(rows: &[Value]) -> Vec<(usize, String)> {
+    let mut columns: Vec<(usize, String)> = collect_columns(rows).into_iter().enumerate().collect();
+    columns.sort_by_key(|(_, column)| !is_numeric_column(rows, column));
+    columns.truncate(MAX_SORT_COLUMNS);
+    columns
+}
+
+/// Ряды кнопок сортировки и «Топ N» для таблицы из нескольких строк
+pub fn view_rows(rows: &[Value], view: View) -> Vec<Vec<InlineKeyboardButton>> {
+    let mut keyboard = Vec::new();
+    let sort_row: Vec<InlineKeyboardButton> = sort_columns(rows)
+        .into_iter()
+        .map(|(i, column)| {
+            let arrow = match view.sort {
+                Some(sort) if sort.column == i => sort_arrow(sort.descending),
+                _ => "↕️",
+            };
+            let name = if column.chars().count() > MAX_SORT_LABEL_CHARS {
+                format!("{}…", column.chars().take(MAX_SORT_LABEL_CHARS - 1).collect::<String>())
+            } else {
+                column
+            };
+            InlineKeyboardButton::callback(format!("{} {}", arrow, name), format!("view:sort:{}", i))
+        })
+        .collect();
+    if rows.len() > 1 {
+        keyboard.push(sort_row);
+    }
+
+    let limits: Vec<usize> = TOP_LIMITS.into_iter().filter(|limit| *limit < rows.len()).collect();
+    if !limits.is_empty() {
+        let mark = |selected: bool, label: String| if selected { format!("✅ {}", label) } else { label };
+        let mut top_row: Vec<InlineKeyboardButton> = limits
+            .iter()
+            .map(|limit| {
+                let label = mark(view.top == Some(*limit), format!("Топ {}", limit));
+                InlineKeyboardButton::callback(label, format!("view:top:{}", limit))
+            })
+            .collect();
+        top_row.push(InlineKeyboardButton::callback(mark(view.top.is_none(), "Все".to_string()), "view:top:0"));
+        keyboard.push(top_row);
+    }
+    keyboard
+}
+
+/// Кнопки ⬅️/➡️ и номер текущей страницы
+fn page_row(page: usize, pages: usize) -> Vec<InlineKeyboardButton> {
+    let mut row = Vec::new();
+    if page > 0 {
+        row.push(InlineKeyboardButton::callback("⬅️", format!("page:{}", page - 1)));
+    }
+    row.push(InlineKeyboardButton::callback(
+        format!("{}/{}", page + 1, pages),
+        "page:current",
+    ));
+    if page + 1 < pages {
+        row.push(InlineKeyboardButton::callback("➡️", format!("page:{}", page + 1)));
+    }
+    row
+}
+
+/// Кнопки «🔎 1…5» для первых строк таблицы: строка целиком, без обрезанных значений.
+/// Для результата из одной колонки (одно число, список названий) кнопок нет.
+pub fn row_buttons(rows: &[Value]) -> Vec<InlineKeyboardButton> {
+    if collect_columns(rows).len() < 2 {
+        return Vec::new();
+    }
+    (1..=rows.len().min(MAX_DETAIL_ROWS))
+        .map(|number| InlineKeyboardButton::callback(format!("🔎 {}", number), format!("row:{}", number - 1)))
+        .collect()
+}
+
+/// Строка `index` (с нуля) целиком: каждое поле с полным значением, как его вернул бэкенд (HTML)
+pub fn render_row(rows: &[Value], index: usize) -> Option<String> {
+    let row = rows.get(index)?.as_object()?;
+    let mut text = format!("🔎 <b>Строка {} из {}</b>\n", index + 1, rows.len());
+    for column in collect_columns(rows) {
+        let value = match row.get(&column) {
+            None | Some(Value::Null) => "—".to_string(),
+            Some(Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+        };
+        text.push_str(&format!("\n<b>{}</b>: <code>{}</code>", escape_html(&column), escape_html(&value)));
+    }
+    Some(text)
+}
+
+/// Строки результата и вид таблицы в сообщении
+type CachedResult = (Arc<Vec<Value>>, View);
+
+struct Entry {
+    rows: Arc<Vec<Value>>,
+    view: View,
+    /// Сообщение - таблица с кнопками (ее текст меняется при сортировке), а не ответ
+    table: bool,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    results: HashMap<(ChatId, MessageId), Entry>,
+    order: VecDeque<(ChatId, MessageId)>,
+}
+
+/// Полные результаты по сообщению: для постраничного вывода с сортировкой и строк по кнопкам «🔎 1…5» под ответом
+#[derive(Default)]
+pub struct ResultPages {
+    inner: Mutex<CacheInner>,
+}
+
+impl ResultPages {
+    /// Строки ответа: таблица в нем - часть текста и при сортировке не меняется
+    pub async fn insert(&self, chat_id: ChatId, message_id: MessageId, rows: Vec<Value>) {
+        self.put(chat_id, message_id, rows, View::default(), false).await;
+    }
+
+    /// Строки сообщения-таблицы в виде `view`: сортировка и «Топ N» меняют его текст
+    pub async fn insert_table(&self, chat_id: ChatId, message_id: MessageId, rows: Vec<Value>, view: View) {
+        self.put(chat_id, message_id, rows, view, true).await;
+    }
+
+    async fn put(&self, chat_id: ChatId, message_id: MessageId, rows: Vec<Value>, view: View, table: bool) {
+        let mut inner = self.inner.lock().await;
+        let entry = Entry { rows: Arc::new(rows), view, table };
+        if inner.results.insert((chat_id, message_id), entry).is_none() {
+            inner.order.push_back((chat_id, message_id));
+        }
+        while inner.order.len() > MAX_CACHED_RESULTS {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.results.remove(&oldest);
+            }
+        }
+    }
+
+    /// Строки в том порядке, в котором их вернул бэкенд
+    pub async fn get(&self, chat_id: ChatId, message_id: MessageId) -> Option<Arc<Vec<Value>>> {
+        self.view(chat_id, message_id).await.map(|(rows, _)| rows)
+    }
+
+    /// Строки и текущий вид таблицы в сообщении
+    pub async fn view(&self, chat_id: ChatId, message_id: MessageId) -> Option<CachedResult> {
+        let inner = self.inner.lock().await;
+        inner.results.get(&(chat_id, message_id)).map(|entry| (entry.rows.clone(), entry.view))
+    }
+
+    /// Сообщение - таблица с кнопками, а не ответ с таблицей в тексте
+    pub async fn is_table(&self, chat_id: ChatId, message_id: MessageId) -> bool {
+        let inner = self.inner.lock().await;
+        inner.results.get(&(chat_id, message_id)).is_some_and(|entry| entry.table)
+    }
+
+    pub async fn set_view(&self, chat_id: ChatId, message_id: MessageId, view: View) {
+        if let Some(entry) = self.inner.lock().await.results.get_mut(&(chat_id, message_id)) {
+            entry.view = view;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn row_is_shown_in_full() {
+        let rows = vec![
+            json!({"merchant": "ТОО «Очень длинное название мерчанта»", "amount": 1234.5, "mcc": null}),
+            json!({"merchant": "ТОО 2", "amount": 10}),
+        ];
+        assert_eq!(row_buttons(&rows).len(), 2);
+        assert!(row_buttons(&[json!({"count": 5})]).is_empty());
+
+        let text = render_row(&rows, 0).unwrap();
+        assert!(text.starts_with("🔎 <b>Строка 1 из 2</b>"));
+        assert!(text.contains("<b>merchant</b>: <code>ТОО «Очень длинное название мерчанта»</code>"));
+        assert!(text.contains("<b>amount</b>: <code>1234.5</code>"));
+        assert!(text.contains("<b>mcc</b>: <code>—</code>"));
+        assert!(render_row(&rows, 2).is_none());
+    }
+
+    #[test]
+    fn view_sorts_and_limits_rows() {
+        let rows = vec![
+            json!({"city": "astana", "amount": 50}),
+            json!({"city": "Almaty", "amount": null}),
+            json!({"city": "Shymkent", "amount": 200.5}),
+        ];
+        let cities = |view: View| -> Vec<String> {
+            apply_view(&rows, view).iter().map(|row| row["city"].as_str().unwrap().to_string()).collect()
+        };
+
+        // Колонки по алфавиту: amount, city
+        let by_amount = View::default().apply("sort:0").unwrap();
+        assert_eq!(cities(by_amount), ["Shymkent", "astana", "Almaty"]);
+        let ascending = by_amount.apply("sort:0").unwrap();
+        assert_eq!(cities(ascending), ["astana", "Shymkent", "Almaty"]);
+        let by_city = ascending.apply("sort:1").unwrap();
+        assert_eq!(cities(by_city), ["Shymkent", "astana", "Almaty"]);
+        assert_eq!(cities(by_city.apply("top:2").unwrap()), ["Shymkent", "astana"]);
+        assert_eq!(by_city.apply("top:2").unwrap().apply("top:0").unwrap(), by_city);
+        assert!(View::default().apply("sort:x").is_none());
+
+        let page = render_page(&rows, by_amount.apply("top:2").unwrap(), 0, Language::Ru);
+        assert!(page.starts_with("📋 <b>Результаты</b> — строки 1–2 из 2\n<i>По amount ⬇️, первые 2</i>"), "{}", page);
+    }
+
+    #[test]
+    fn sort_buttons_prefer_numeric_columns() {
+        let rows = vec![
+            json!({"a_city": "Almaty", "b_mcc": "5411", "c_merchant": "ТОО 1", "d_name": "x", "e_day": "пн", "z_amount": 10}),
+            json!({"a_city": "Astana", "b_mcc": "5812", "c_merchant": "ТОО 2", "d_name": "y", "e_day": "вт", "z_amount": 20.5}),
+        ];
+        let keyboard = view_rows(&rows, View::default());
+        let sort: Vec<&str> = keyboard[0].iter().map(|button| button.text.as_str()).collect();
+        assert_eq!(sort, ["↕️ z_amount", "↕️ a_city", "↕️ b_mcc", "↕️ c_merchant"]);
+        // Номер в кнопке - номер колонки в `collect_columns`, а не место на клавиатуре
+        assert!(matches!(
+            &keyboard[0][0].kind,
+            teloxide::types::InlineKeyboardButtonKind::CallbackData(data) if data == "view:sort:5"
+        ));
+        // Двух строк мало для «Топ N»
+        assert_eq!(keyboard.len(), 1);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::api_client::QueryResponse;
+use crate::timezone::UserZone;
+use anyhow::{Context, Result};
+use printpdf::{
+    Color, ColorBits, ColorSpace, Image, ImageTransform, ImageXObject, IndirectFontRef, Line, Mm,
+    PdfDocument, PdfDocumentReference, PdfLayerReference, Point, Px, Rgb,
+};
+use serde_json::Value;
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 15.0;
+const CONTENT_WIDTH: f32 = PAGE_WIDTH - 2.0 * MARGIN;
+/// Сколько строк результата попадает в таблицу отчета (полные данные - в CSV/XLSX)
+const MAX_TABLE_ROWS: usize = 50;
+const MAX_TABLE_COLUMNS: usize = 6;
+/// Средняя ширина символа DejaVu Sans в долях кегля, для переноса строк
+const AVERAGE_CHAR_WIDTH: f32 = 0.55;
+const PT_TO_MM: f32 = 0.3528;
+
+/// Собирает PDF-отчет: вопрос, вывод и выводы анализа, диаграмму и таблицу.
+/// Встроенные шрифты PDF не знают кириллицы, поэтому нужен TTF-шрифт (`PDF_FONT_PATH`).
+/// Время формирования печатается в поясе `zone` с его названием.
+pub fn render_report(font: &[u8], response: &QueryResponse, chart_png: Option<&[u8]>, zone: UserZone) -> Result<Vec<u8>> {
+    let (doc, page, layer) = PdfDocument::new(&response.question, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "content");
+    let font = doc.add_external_font(font)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .context("Failed to load PDF font")?;
+    let layer = doc.get_page(page).get_layer(layer);
+    let mut writer = ReportWriter { doc: &doc, layer, font, y: PAGE_HEIGHT - MARGIN };
+
+    writer.paragraph(&response.question, 16.0);
+    writer.gray_line(
+        &format!(
+            "{} · строк: {} · {} мс",
+            zone.stamp(chrono::Utc::now(), "%d.%m.%Y %H:%M"),
+            response.row_count,
+            response.execution_time_ms
+        ),
+        9.0,
+    );
+    writer.gap(4.0);
+
+    if let Some(analysis) = &response.analysis {
+        writer.paragraph(&analysis.headline, 13.0);
+        for insight in &analysis.insights {
+            writer.paragraph(&format!("• {}: {}", insight.title, insight.description), 10.0);
+        }
+        if !analysis.explanation.is_empty() {
+            writer.gap(2.0);
+            writer.paragraph(&analysis.explanation, 10.0);
+        }
+        writer.gap(4.0);
+    }
+
+    if let Some(png) = chart_png {
+        writer.image(png)?;
+        writer.gap(4.0);
+    }
+
+    writer.table(&response.data);
+
+    doc.save_to_bytes()
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .context("Failed to serialize PDF report")
+}
+
+/// Пишет содержимое сверху вниз, открывая новые страницы по мере заполнения
+struct ReportWriter<'a> {
+    doc: &'a PdfDocumentReference,
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    /// Текущая позиция от нижнего края страницы, мм
+    y: f32,
+}
+
+impl ReportWriter<'_> {
+    fn ensure_space(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            let (page, layer) = self.doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "content");
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    fn text_line(&mut self, text: &str, size: f32, x: f32) {
+        let height = size * PT_TO_MM * 1.4;
+        self.ensure_space(height);
+        self.y -= height;
+        self.layer.use_text(text, size, Mm(x), Mm(self.y), &self.font);
+    }
+
+    /// Текст с переносом по словам на ширину страницы
+    fn paragraph(&mut self, text: &str, size: f32) {
+        for line in wrap(text, max_chars(CONTENT_WIDTH, size)) {
+            self.text_line(&line, size, MARGIN);
+        }
+    }
+
+    fn gray_line(&mut self, text: &str, size: f32) {
+        self.layer.set_fill_color(gray(0.45));
+        self.text_line(text, size, MARGIN);
+        self.layer.set_fill_color(gray(0.0));
+    }
+
+    /// Диаграмма (PNG) на всю ширину страницы
+    fn image(&mut self, png: &[u8]) -> Result<()> {
+        let image = image::load_from_memory_with_format(png, image::ImageFormat::Png)
+            .context("Failed to decode chart image")?
+            .to_rgb8();
+        let (width, height) = image.dimensions();
+        let scale = CONTENT_WIDTH / width as f32;
+        let image_height = height as f32 * scale;
+        self.ensure_space(image_height);
+        self.y -= image_height;
+
+        // При 25.4 dpi один пиксель занимает 1 мм, дальше масштабируем до ширины страницы
+        Image::from(ImageXObject {
+            width: Px(width as usize),
+            height: Px(height as usize),
+            color_space: ColorSpace::Rgb,
+            bits_per_component: ColorBits::Bit8,
+            interpolate: true,
+            image_data: image.into_raw(),
+            image_filter: None,
+            smask: None,
+            clipping_bbox: None,
+        })
+        .add_to_layer(self.layer.clone(), ImageTransform {
+            translate_x: Some(Mm(MARGIN)),
+            translate_y: Some(Mm(self.y)),
+            scale_x: Some(scale),
+            scale_y: Some(scale),
+            dpi: Some(25.4),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    /// Таблица первых `MAX_TABLE_ROWS` строк. Шрифт пропорциональный, поэтому каждая
+    /// колонка выводится со своей позиции, а длинные значения обрезаются.
+    fn table(&mut self, data: &[Value]) {
+        let Some(first) = data.first().and_then(Value::as_object) else {
+            return;
+        };
+        let columns: Vec<&String> = first.keys().take(MAX_TABLE_COLUMNS).collect();
+        let column_width = CONTENT_WIDTH / columns.len() as f32;
+        let size = 9.0;
+        let cell_chars = max_chars(column_width - 2.0, size);
+
+        let header: Vec<String> = columns.iter().map(|column| column.to_string()).collect();
+        self.table_row(&header, column_width, cell_chars, size);
+        self.rule();
+        for row in data.iter().take(MAX_TABLE_ROWS) {
+            let cells: Vec<String> = columns.iter().map(|column| cell_text(row.get(column.as_str()))).collect();
+            self.table_row(&cells, column_width, cell_chars, size);
+        }
+
+        if data.len() > MAX_TABLE_ROWS || first.len() > MAX_TABLE_COLUMNS {
+            self.gap(2.0);
+            self.gray_line(
+                &format!(
+                    "Показаны первые {} из {} строк и {} из {} колонок. Полные данные — в выгрузке CSV/XLSX.",
+                    data.len().min(MAX_TABLE_ROWS),
+                    data.len(),
+                    columns.len(),
+                    first.len()
+                ),
+                8.0,
+            );
+        }
+    }
+
+    fn table_row(&mut self, cells: &[String], column_width: f32, cell_chars: usize, size: f32) {
+        let height = size * PT_TO_MM * 1.5;
+        self.ensure_space(height);
+        self.y -= height;
+        for (i, cell) in cells.iter().enumerate() {
+            let x = MARGIN + i as f32 * column_width;
+            self.layer.use_text(truncate(cell, cell_chars), size, Mm(x), Mm(self.y), &self.font);
+        }
+    }
+
+    fn rule(&mut self) {
+        self.y -= 1.0;
+        self.layer.set_outline_color(gray(0.6));
+        self.layer.set_outline_thickness(0.5);
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(self.y)), false),
+                (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(self.y)), false),
+            ],
+            is_closed: false,
+        });
+    }
+}
+
+fn gray(level: f32) -> Color {
+    Color::Rgb(Rgb::new(level, level, level, None))
+}
+
+/// Сколько символов кегля `size` помещается в `width` мм
+fn max_chars(width: f32, size: f32) -> usize {
+    ((width / (size * PT_TO_MM * AVERAGE_CHAR_WIDTH)) as usize).max(1)
+}
+
+fn wrap(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+fn cell_text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => "—".to_string(),
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Number(number)) => match number.as_i64() {
+            Some(int) => int.to_string(),
+            None => format!("{:.2}", number.as_f64().unwrap_or(0.0)),
+        },
+        Some(other) => other.to_string(),
+    }
+}
@@ -0,0 +1,278 @@
+use crate::api_client::{QueryRequest, QueryResponse};
+use crate::i18n::{tr, Msg};
+use crate::language::Language;
+use crate::messenger::{MessageSender, Outgoing};
+use crate::shutdown::InFlightGuard;
+use crate::state::BotState;
+use crate::typing::Typing;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::{MessageId, ParseMode, ReplyMarkup};
+use tokio::sync::{mpsc, OwnedMutexGuard};
+use tokio::time::Instant;
+use tracing::warn;
+
+/// Через сколько секунд ожидания бэкенда «генерирую SQL» сменяется на «выполняю запрос»
+const SQL_STAGE_DURATION: Duration = Duration::from_secs(3);
+/// Как часто сообщение обновляется частями потокового ответа: Telegram ограничивает
+/// частоту редактирования сообщений в одном чате
+const STREAM_EDIT_INTERVAL: Duration = Duration::from_secs(2);
+/// Сколько последних символов потокового ответа видно в сообщении о ходе запроса
+const STREAM_PREVIEW_CHARS: usize = 3000;
+
+/// Этап обработки, показываемый в сообщении о ходе запроса
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    /// Ждет, пока завершится предыдущий запрос из этого чата
+    Queued,
+    GeneratingSql,
+    RunningQuery,
+    DrawingChart,
+    /// Бэкенд присылает ответ по частям
+    Answering,
+}
+
+impl Stage {
+    fn message(self) -> Msg {
+        match self {
+            Self::Queued => Msg::StageQueued,
+            Self::GeneratingSql => Msg::StageSql,
+            Self::RunningQuery => Msg::StageQuery,
+            Self::DrawingChart => Msg::StageChart,
+            Self::Answering => Msg::StageAnswer,
+        }
+    }
+}
+
+/// Сообщение «⏳ Обрабатываю запрос...», которое редактируется по ходу обработки
+/// и в конце превращается в ответ (вместо удаления и отправки нового сообщения)
+pub struct Progress<S: MessageSender = Bot> {
+    bot: S,
+    chat_id: ChatId,
+    message_id: MessageId,
+    /// Вопрос, на который отвечает сообщение
+    question: MessageId,
+    /// Вопрос исправлен после ответа: новый ответ помечается «обновлено»
+    edited: bool,
+    lang: Language,
+    /// Чьи настройки учитываются при отправке ответа
+    user_id: String,
+    /// Очередь запросов чата к бэкенду
+    queue: Arc<tokio::sync::Mutex<()>>,
+    _in_flight: InFlightGuard,
+}
+
+impl<S: MessageSender> Progress<S> {
+    /// Отправляет сообщение о начале обработки в ответ на `msg`
+    pub async fn start(bot: &S, msg: &Message, state: &BotState, lang: Language) -> ResponseResult<Self> {
+        let sent = bot
+            .send_message(msg.chat.id, tr(lang, Msg::Processing).to_string(), Outgoing::html().reply_to(msg.id))
+            .await?;
+        state.answer_messages.add(msg.chat.id, msg.id, sent).await;
+
+        Ok(Self {
+            bot: bot.clone(),
+            chat_id: msg.chat.id,
+            message_id: sent,
+            question: msg.id,
+            edited: msg.edit_date().is_some(),
+            lang,
+            user_id: state.user_key(msg),
+            queue: state.chat_queues.chat(msg.chat.id),
+            _in_flight: state.in_flight.track(msg.chat.id, sent),
+        })
+    }
+
+    /// Сообщение, которое станет ответом (если ответ поместится в одно сообщение)
+    pub fn message_id(&self) -> MessageId {
+        self.message_id
+    }
+
+    /// Вопрос, на который отвечает сообщение
+    pub fn question(&self) -> MessageId {
+        self.question
+    }
+
+    /// Помечает HTML-текст ответа на исправленный вопрос
+    pub fn mark_updated(&self, html: String) -> String {
+        if !self.edited {
+            return html;
+        }
+        format!("✏️ <i>{}</i>\n\n{}", tr(self.lang, Msg::AnswerUpdated), html)
+    }
+
+    /// Показывает этап обработки. Ошибки редактирования не важны для ответа и игнорируются
+    pub async fn stage(&self, stage: Stage) {
+        let _ = self.bot
+            .edit_message(self.chat_id, self.message_id, tr(self.lang, stage.message()).to_string(), Outgoing::html())
+            .await;
+    }
+
+    /// Очередь запросов чата: если в чате уже выполняется другой запрос, дожидается его
+    async fn wait_turn(&self) -> OwnedMutexGuard<()> {
+        match self.queue.clone().try_lock_owned() {
+            Ok(turn) => turn,
+            Err(_) => {
+                self.stage(Stage::Queued).await;
+                self.queue.clone().lock_owned().await
+            }
+        }
+    }
+
+    /// Ждет запрос к бэкенду, переключая этапы «генерирую SQL» → «выполняю запрос».
+    /// Если в чате уже выполняется другой запрос, сначала дожидается его.
+    pub async fn run<F: Future>(&self, request: F) -> F::Output {
+        let _turn = self.wait_turn().await;
+        let _typing = Typing::start(&self.bot, self.chat_id);
+        self.stage(Stage::GeneratingSql).await;
+        tokio::pin!(request);
+        tokio::select! {
+            output = &mut request => return output,
+            _ = tokio::time::sleep(SQL_STAGE_DURATION) => {}
+        }
+        self.stage(Stage::RunningQuery).await;
+        request.await
+    }
+
+    /// Выполняет вопрос. Если бэкенд присылает ответ по частям (`BACKEND_STREAMING`),
+    /// сообщение показывает формирующийся ответ вместо этапов обработки.
+    pub async fn query(&self, state: &BotState, request: QueryRequest) -> anyhow::Result<QueryResponse> {
+        let api = match request.user_id.as_deref() {
+            Some(user_id) => state.api(user_id).await,
+            None => state.api_client.clone(),
+        };
+        if !api.is_streaming() {
+            return self.run(api.query(request)).await;
+        }
+        let (tokens, received) = mpsc::unbounded_channel();
+        self.run_streaming(api.query_stream(request, tokens), received).await
+    }
+
+    /// Как `run`, но по мере поступления частей ответа из `tokens` показывает их в сообщении,
+    /// редактируя его не чаще `STREAM_EDIT_INTERVAL`
+    async fn run_streaming<F: Future>(&self, request: F, mut tokens: mpsc::UnboundedReceiver<String>) -> F::Output {
+        let _turn = self.wait_turn().await;
+        let _typing = Typing::start(&self.bot, self.chat_id);
+        self.stage(Stage::GeneratingSql).await;
+        tokio::pin!(request);
+
+        let mut text = String::new();
+        let mut shown = 0;
+        let mut last_edit = Instant::now();
+        // Без новых частей ответа этап сменяется, как в `run`, а дальше сообщение не трогаем
+        let mut next_edit = Some(last_edit + SQL_STAGE_DURATION);
+        loop {
+            tokio::select! {
+                output = &mut request => return output,
+                Some(token) = tokens.recv() => {
+                    text.push_str(&token);
+                    let due = last_edit + STREAM_EDIT_INTERVAL;
+                    next_edit = Some(next_edit.map_or(due, |at| at.min(due)));
+                }
+                _ = tokio::time::sleep_until(next_edit.unwrap_or(last_edit)), if next_edit.is_some() => {
+                    if text.len() > shown {
+                        self.show_partial(&text).await;
+                        shown = text.len();
+                    } else if shown == 0 {
+                        self.stage(Stage::RunningQuery).await;
+                    }
+                    last_edit = Instant::now();
+                    next_edit = None;
+                }
+            }
+        }
+    }
+
+    /// Показывает конец формирующегося ответа. Ошибки редактирования игнорируются
+    async fn show_partial(&self, text: &str) {
+        let skip = text.chars().count().saturating_sub(STREAM_PREVIEW_CHARS);
+        let tail: String = text.chars().skip(skip).collect();
+        let ellipsis = if skip > 0 { "…" } else { "" };
+        let preview = format!(
+            "{}\n\n{}{} ▌",
+            tr(self.lang, Stage::Answering.message()),
+            ellipsis,
+            crate::utils::escape_html(tail.trim_start())
+        );
+        if !crate::utils::fits_in_message(&preview) {
+            return;
+        }
+        let _ = self.bot.edit_message(self.chat_id, self.message_id, preview, Outgoing::html()).await;
+    }
+
+    /// Превращает сообщение в ответ. Ответ, не помещающийся в одно сообщение или длиннее
+    /// порога `/settings long`, отправляется как обычно (частями или файлом), а сообщение о ходе удаляется.
+    /// Возвращает сообщение ответа, к которому прикреплена клавиатура.
+    pub async fn finish(self, state: &BotState, formatted: &str, keyboard: Option<ReplyMarkup>) -> ResponseResult<MessageId> {
+        let limit = state.storage.settings(&self.user_id).await.long_answer_chars;
+        let fits = crate::utils::fits_in_message(formatted)
+            && !crate::handlers::exceeds_long_answer_limit(formatted, limit);
+        let inline_keyboard = match &keyboard {
+            None => Some(None),
+            Some(ReplyMarkup::InlineKeyboard(markup)) => Some(Some(markup.clone())),
+            // Обычную клавиатуру к редактируемому сообщению не прикрепить
+            Some(_) => None,
+        };
+
+        if let (true, Some(inline_keyboard)) = (fits, inline_keyboard) {
+            let mut options = Outgoing::html();
+            if let Some(markup) = inline_keyboard {
+                options = options.keyboard(markup);
+            }
+            if self.bot.edit_message(self.chat_id, self.message_id, formatted.to_string(), options).await.is_ok() {
+                return Ok(self.message_id);
+            }
+        }
+
+        let _ = self.bot.delete_message(self.chat_id, self.message_id).await;
+        crate::handlers::send_answer_text(&self.bot, self.chat_id, state, Some(&self.user_id), formatted, keyboard).await
+    }
+
+    /// Удаляет сообщение о ходе запроса, когда ответ отправлен подписью к диаграмме или файлу
+    pub async fn dismiss(self) {
+        let _ = self.bot.delete_message(self.chat_id, self.message_id).await;
+    }
+
+    /// Превращает сообщение в текстовый ответ бэкенда в разметке `TEXT_FORMAT`.
+    /// Если ответ в MarkdownV2 не помещается в одно сообщение или Telegram его не принял,
+    /// ответ отправляется в HTML.
+    pub async fn finish_backend_text(self, state: &BotState, text: &str) -> ResponseResult<()> {
+        let rich = crate::utils::format_backend_text(text, state.text_format);
+        if !matches!(rich.parse_mode, ParseMode::MarkdownV2) {
+            let html = self.mark_updated(rich.text);
+            self.finish(state, &html, None).await?;
+            return Ok(());
+        }
+
+        // В пометке нет символов, которые нужно экранировать в MarkdownV2
+        let markdown = if self.edited {
+            format!("✏️ _{}_\n\n{}", tr(self.lang, Msg::AnswerUpdated), rich.text)
+        } else {
+            rich.text
+        };
+        if crate::utils::fits_in_message(&markdown) {
+            let edited = self.bot
+                .edit_message(self.chat_id, self.message_id, markdown.clone(), Outgoing::default().parse_mode(ParseMode::MarkdownV2))
+                .await;
+            match edited {
+                Ok(_) => return Ok(()),
+                Err(e) => warn!("Failed to send answer as MarkdownV2, falling back to HTML: {}", e),
+            }
+        }
+        let html = self.mark_updated(crate::utils::format_backend_text_html(text));
+        self.finish(state, &html, None).await?;
+        Ok(())
+    }
+
+    /// Превращает сообщение в сообщение об ошибке (HTML)
+    pub async fn fail(self, text: &str) -> ResponseResult<()> {
+        let edited = self.bot.edit_message(self.chat_id, self.message_id, text.to_string(), Outgoing::html()).await;
+        if edited.is_err() {
+            let _ = self.bot.delete_message(self.chat_id, self.message_id).await;
+            self.bot.send_message(self.chat_id, text.to_string(), Outgoing::html()).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use crate::dialogue::{self, ChatDialogue, ChatState};
+use crate::handlers;
+use crate::menu::MenuItem;
+use crate::state::BotState;
+use crate::utils::escape_html;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId, ParseMode};
+use tracing::info;
+
+/// Сколько ждать ответа на вопрос о параметре, прежде чем забыть диалог
+const DIALOG_TTL: Duration = Duration::minutes(10);
+/// Ограничение длины значения, введенного текстом
+const MAX_VALUE_CHARS: usize = 100;
+
+/// Варианты значений для известных параметров; остальные параметры вводятся текстом
+fn choices(name: &str) -> &'static [&'static str] {
+    match name.to_lowercase().as_str() {
+        "n" | "число" | "количество" | "топ" => &["5", "10", "20", "50"],
+        "период" | "period" => &[
+            "сегодня",
+            "вчера",
+            "последние 7 дней",
+            "последние 30 дней",
+            "текущий месяц",
+            "прошлый месяц",
+            "текущий год",
+        ],
+        _ => &[],
+    }
+}
+
+/// Имена параметров `{...}` в шаблоне запроса, без повторов, в порядке появления
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let Some(end) = after.find(['{', '}']) else {
+            break;
+        };
+        if after[end..].starts_with('}') {
+            let name = after[..end].trim();
+            if !name.is_empty() && !names.iter().any(|known| known == name) {
+                names.push(name.to_string());
+            }
+            rest = &after[end + 1..];
+        } else {
+            rest = &after[end..];
+        }
+    }
+    names
+}
+
+/// Подставляет значения параметров в шаблон
+pub fn compose(template: &str, values: &[(String, String)]) -> String {
+    values.iter().fold(template.to_string(), |query, (name, value)| {
+        query.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+/// Диалог заполнения параметров кнопки меню или шаблона (часть состояния чата, см. `dialogue::ChatState`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParamDialog {
+    /// Кто нажал кнопку: в группах отвечать на вопросы может только он
+    user: UserId,
+    /// Ключ пользователя для контекста на бэкенде
+    user_key: String,
+    label: String,
+    template: String,
+    values: Vec<(String, String)>,
+    /// Параметры, которые еще нужно спросить (первый - текущий вопрос)
+    pending: Vec<String>,
+    /// Сообщение с текущим вопросом
+    prompt: Option<MessageId>,
+    updated: DateTime<Utc>,
+}
+
+impl ParamDialog {
+    fn current(&self) -> Option<&str> {
+        self.pending.first().map(String::as_str)
+    }
+
+    /// Записывает ответ на текущий вопрос
+    fn answer(&mut self, value: String) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let name = self.pending.remove(0);
+        self.values.push((name, value));
+        self.updated = Utc::now();
+    }
+
+    fn is_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn is_expired(&self) -> bool {
+        Utc::now() - self.updated >= DIALOG_TTL
+    }
+
+    fn query(&self) -> String {
+        compose(&self.template, &self.values)
+    }
+
+    /// Текст вопроса о текущем параметре и варианты ответа
+    fn question(&self) -> (String, InlineKeyboardMarkup) {
+        let name = self.current().unwrap_or_default();
+        let options = choices(name);
+        let mut text = format!("🧩 <b>{}</b>\n", escape_html(&self.label));
+        for (filled, value) in &self.values {
+            text.push_str(&format!("{}: {}\n", escape_html(filled), escape_html(value)));
+        }
+        if options.is_empty() {
+            text.push_str(&format!("\nНапишите значение «{}» сообщением", escape_html(name)));
+        } else {
+            text.push_str(&format!("\nВыберите «{}» или напишите свое значение", escape_html(name)));
+        }
+
+        let mut rows: Vec<Vec<InlineKeyboardButton>> = options
+            .chunks(3)
+            .enumerate()
+            .map(|(row, values)| {
+                values.iter()
+                    .enumerate()
+                    .map(|(i, value)| InlineKeyboardButton::callback(*value, format!("param:{}", row * 3 + i)))
+                    .collect()
+            })
+            .collect();
+        rows.push(vec![InlineKeyboardButton::callback("✖️ Отмена", "param:cancel")]);
+        (text, InlineKeyboardMarkup::new(rows))
+    }
+}
+
+/// Незавершенный диалог о параметрах в чате, если его начал пользователь `user` и он не устарел.
+/// Вместе с диалогом возвращается открытый раздел меню, чтобы вернуть его после диалога.
+async fn pending_dialog(dialogue: &ChatDialogue, user: UserId) -> Option<(Vec<usize>, ParamDialog)> {
+    match dialogue::load(dialogue).await {
+        ChatState::MenuParams { path, dialog } if dialog.is_expired() => {
+            dialogue::save(dialogue, ChatState::Idle.with_menu_path(path)).await;
+            None
+        }
+        ChatState::MenuParams { path, dialog } if dialog.user == user => Some((path, dialog)),
+        _ => None,
+    }
+}
+
+/// Кнопка меню с параметрами: вместо запроса начинается диалог с вопросами о параметрах.
+/// `false`, если параметров в шаблоне нет и запрос можно выполнять сразу.
+pub async fn start(
+    bot: &Bot,
+    msg: &Message,
+    state: &BotState,
+    user: UserId,
+    path: Vec<usize>,
+    button: &MenuItem,
+) -> ResponseResult<bool> {
+    let template = button.query.as_deref().unwrap_or_default();
+    start_template(bot, msg, state, user, path, &button.label, template).await
+}
+
+/// Диалог о параметрах шаблона запроса (кнопка меню или `/template`); `false`, если параметров нет
+pub async fn start_template(
+    bot: &Bot,
+    msg: &Message,
+    state: &BotState,
+    user: UserId,
+    path: Vec<usize>,
+    label: &str,
+    template: &str,
+) -> ResponseResult<bool> {
+    let pending = placeholders(template);
+    if pending.is_empty() {
+        return Ok(false);
+    }
+
+    info!("Starting parameter dialog in chat {}: {}", msg.chat.id, template);
+    let dialog = ParamDialog {
+        user,
+        user_key: state.context_scope.key(msg.chat.id, Some(user)),
+        label: label.to_string(),
+        template: template.to_string(),
+        values: Vec::new(),
+        pending,
+        prompt: None,
+        updated: Utc::now(),
+    };
+    ask(bot, msg.chat.id, state, path, dialog).await?;
+    Ok(true)
+}
+
+/// Отправляет вопрос о следующем параметре и сохраняет диалог
+async fn ask(bot: &Bot, chat_id: ChatId, state: &BotState, path: Vec<usize>, mut dialog: ParamDialog) -> ResponseResult<()> {
+    let (text, keyboard) = dialog.question();
+    let sent = bot.send_message(chat_id, text)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard)
+        .await?;
+    dialog.prompt = Some(sent.id);
+    dialogue::save(&state.dialogue(chat_id), ChatState::MenuParams { path, dialog }).await;
+    Ok(())
+}
+
+/// Выбор варианта (`param:<i>`) или отмена (`param:cancel`) под вопросом о параметре
+pub async fn handle_callback(
+    bot: Bot,
+    msg: Message,
+    user: UserId,
+    action: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let dialogue = state.dialogue(msg.chat.id);
+    // Диалог устарел или его начал другой участник группы
+    let Some((path, mut dialog)) = pending_dialog(&dialogue, user).await else {
+        return Ok(());
+    };
+    // Нажатие под старым вопросом, когда диалог уже ушел дальше
+    if dialog.prompt != Some(msg.id) {
+        let _ = bot.edit_message_reply_markup(msg.chat.id, msg.id).await;
+        return Ok(());
+    }
+
+    if action == "cancel" {
+        dialogue::save(&dialogue, ChatState::Idle.with_menu_path(path)).await;
+        bot.edit_message_text(msg.chat.id, msg.id, "Запрос отменен").await?;
+        return Ok(());
+    }
+    let value = action.parse::<usize>().ok()
+        .and_then(|index| choices(dialog.current().unwrap_or_default()).get(index));
+    let Some(value) = value else {
+        return Ok(());
+    };
+    dialog.answer(value.to_string());
+    let _ = bot.edit_message_reply_markup(msg.chat.id, msg.id).await;
+    proceed(bot, msg, state, path, dialog).await
+}
+
+/// Текстовый ответ на вопрос о параметре. `false`, если в чате нет диалога этого пользователя
+/// и сообщение нужно обработать как обычный вопрос.
+pub async fn handle_text(bot: &Bot, msg: &Message, state: &Arc<BotState>, text: &str) -> ResponseResult<bool> {
+    let Some(user) = msg.from().map(|user| user.id) else {
+        return Ok(false);
+    };
+    let Some((path, mut dialog)) = pending_dialog(&state.dialogue(msg.chat.id), user).await else {
+        return Ok(false);
+    };
+
+    let value: String = text.trim().chars().take(MAX_VALUE_CHARS).collect();
+    dialog.answer(value);
+    if let Some(prompt) = dialog.prompt {
+        let _ = bot.edit_message_reply_markup(msg.chat.id, prompt).await;
+    }
+    proceed(bot.clone(), msg.clone(), state.clone(), path, dialog).await?;
+    Ok(true)
+}
+
+/// Спрашивает следующий параметр или выполняет собранный запрос
+async fn proceed(bot: Bot, msg: Message, state: Arc<BotState>, path: Vec<usize>, dialog: ParamDialog) -> ResponseResult<()> {
+    if !dialog.is_complete() {
+        return ask(&bot, msg.chat.id, &state, path, dialog).await;
+    }
+    dialogue::save(&state.dialogue(msg.chat.id), ChatState::Idle.with_menu_path(path)).await;
+    let query = dialog.query();
+    info!("Parameter dialog completed in chat {}: {}", msg.chat.id, query);
+    handlers::run_canned_query(bot, msg, state, dialog.user_key, &query).await
+}
@@ -0,0 +1,738 @@
+use crate::api_client::OutputType;
+use chrono::{Datelike, Duration, Months, NaiveDate};
+
+/// Ключевые слова формата вывода на одном языке. Фразы из нескольких слов
+/// сравниваются со словами вопроса целиком, без учета регистра.
+struct Keywords {
+    chart: &'static [&'static str],
+    table: &'static [&'static str],
+    json: &'static [&'static str],
+    /// Слова перед ключевым словом, которые убираются вместе с ним: «в виде таблицы», «покажи график»
+    before: &'static [&'static str],
+    /// Слова после ключевого слова, которые убираются вместе с ним: «кесте түрінде», «json format»
+    after: &'static [&'static str],
+}
+
+const RU: Keywords = Keywords {
+    chart: &[
+        "диаграмма", "диаграммы", "диаграмме", "диаграмму", "диаграммой", "диаграммка",
+        "график", "графика", "графике", "графиком", "графически", "графический", "графическом",
+        "визуализация", "визуализации", "визуализацию", "визуализацией", "визуализируй", "визуализировать",
+        "нарисуй",
+    ],
+    table: &[
+        "таблица", "таблицы", "таблице", "таблицу", "таблицей", "табличка", "табличкой",
+        "табличный", "табличном", "табличный формат", "табличном формате",
+    ],
+    json: &["json", "джсон"],
+    before: &["в", "виде", "как", "покажи", "построй", "выведи", "сделай", "формате"],
+    after: &["формате"],
+};
+
+const EN: Keywords = Keywords {
+    chart: &["chart", "charts", "graph", "plot", "visualization", "visualize", "draw"],
+    table: &["table", "tabular", "tabular format"],
+    json: &["json"],
+    before: &["as", "a", "in", "show", "build", "make"],
+    after: &["format", "form"],
+};
+
+const KK: Keywords = Keywords {
+    chart: &["диаграмма", "диаграммамен", "диаграммада", "графикпен", "графикте", "график"],
+    table: &["кесте", "кестемен", "кестеде", "кестені"],
+    json: &["json"],
+    before: &["көрсет"],
+    after: &["түрінде", "ретінде", "форматында", "көрсет"],
+};
+
+/// Словари всех поддерживаемых языков: вопрос может быть на любом из них
+const KEYWORDS: [&Keywords; 3] = [&RU, &EN, &KK];
+
+/// Союзы между двумя ключевыми словами убираются вместе с ними: «график и таблица»
+const CONNECTORS: &[&str] = &["и", "или", "and", "or", "және", "немесе"];
+
+/// Слово вопроса: позиция в байтах и текст в нижнем регистре
+struct Word {
+    start: usize,
+    end: usize,
+    lower: String,
+}
+
+/// Слова - непрерывные последовательности букв и цифр
+fn words(text: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (start, c.is_alphanumeric()) {
+            (None, true) => start = Some(i),
+            (Some(begin), false) => {
+                words.push(Word { start: begin, end: i, lower: text[begin..i].to_lowercase() });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Совпадает ли фраза со словами, начиная с `at`; возвращает число слов фразы
+fn phrase_at(words: &[Word], at: usize, phrase: &str) -> Option<usize> {
+    let mut count = 0;
+    for part in phrase.split_whitespace() {
+        if words.get(at + count)?.lower != part {
+            return None;
+        }
+        count += 1;
+    }
+    Some(count)
+}
+
+/// Самая длинная из фраз, начинающаяся с `at`
+fn longest_match<'a>(words: &[Word], at: usize, phrases: impl Iterator<Item = &'a &'static str>) -> Option<usize> {
+    phrases.filter_map(|phrase| phrase_at(words, at, phrase)).max()
+}
+
+/// Найденное ключевое слово: формат и слова `[start, end)`, которые нужно убрать из вопроса
+struct Found {
+    output_type: OutputType,
+    start: usize,
+    end: usize,
+}
+
+/// Определяет желаемый формат вывода по словам в вопросе («графиком», «в виде таблицы», «as a chart»)
+/// и возвращает вопрос без них. Слова сравниваются целиком, так что «график» не находится
+/// внутри «фотографика». Если просят и диаграмму, и таблицу, побеждает диаграмма.
+pub fn detect_output_format(text: &str) -> (String, OutputType) {
+    let words = words(text);
+    let mut found: Vec<Found> = Vec::new();
+
+    let mut i = 0;
+    while i < words.len() {
+        let matched = [OutputType::Chart, OutputType::Table, OutputType::Json]
+            .into_iter()
+            .filter_map(|output_type| {
+                let phrases = KEYWORDS.iter().flat_map(|keywords| match output_type {
+                    OutputType::Chart => keywords.chart,
+                    OutputType::Table => keywords.table,
+                    _ => keywords.json,
+                });
+                longest_match(&words, i, phrases).map(|len| (output_type, len))
+            })
+            .max_by_key(|(_, len)| *len);
+        let Some((output_type, len)) = matched else {
+            i += 1;
+            continue;
+        };
+
+        // Захватываем служебные слова вокруг, но не уже найденные раньше
+        let floor = found.last().map_or(0, |previous| previous.end);
+        let mut start = i;
+        while start > floor
+            && KEYWORDS.iter().any(|keywords| longest_match(&words, start - 1, keywords.before.iter()) == Some(1))
+        {
+            start -= 1;
+        }
+        let mut end = i + len;
+        while let Some(extra) = KEYWORDS.iter().filter_map(|keywords| longest_match(&words, end, keywords.after.iter())).max() {
+            end += extra;
+        }
+        found.push(Found { output_type, start, end });
+        i = end;
+    }
+
+    let Some(output_type) = [OutputType::Chart, OutputType::Table, OutputType::Json]
+        .into_iter()
+        .find(|output_type| found.iter().any(|found| found.output_type == *output_type))
+    else {
+        return (text.to_string(), OutputType::Auto);
+    };
+
+    // Вырезаем найденные слова вместе с союзами между ними
+    let mut removed: Vec<(usize, usize)> = found.iter().map(|found| (found.start, found.end)).collect();
+    for pair in found.windows(2) {
+        let gap = pair[0].end;
+        if pair[1].start == gap + 1 && CONNECTORS.contains(&words[gap].lower.as_str()) {
+            removed.push((gap, gap + 1));
+        }
+    }
+    removed.sort_unstable();
+
+    let mut clean = String::with_capacity(text.len());
+    let mut position = 0;
+    for (start, end) in removed {
+        clean.push_str(&text[position..words[start].start]);
+        position = words[end - 1].end;
+        // «продажи графиком?» → «продажи?», без пробела перед знаком
+        if text[position..].starts_with(|c: char| !c.is_whitespace()) {
+            clean.truncate(clean.trim_end().len());
+        }
+    }
+    clean.push_str(&text[position..]);
+
+    let clean = clean
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | ':' | '-' | '—'))
+        .to_string();
+    // Вопрос из одного ключевого слова («диаграмма») отправляем как есть
+    if clean.is_empty() {
+        return (text.to_string(), output_type);
+    }
+    (clean, output_type)
+}
+
+/// Период дат из вопроса, обе границы включительно
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl DateRange {
+    /// Период между двумя датами в любом порядке: «с 15 по 1 марта» - то же, что «с 1 по 15 марта»
+    fn new(a: NaiveDate, b: NaiveDate) -> Self {
+        Self { from: a.min(b), to: a.max(b) }
+    }
+
+    fn day(date: NaiveDate) -> Self {
+        Self { from: date, to: date }
+    }
+
+    fn month(year: i32, month: u32) -> Option<Self> {
+        let from = NaiveDate::from_ymd_opt(year, month, 1)?;
+        Some(Self { from, to: from.checked_add_months(Months::new(1))?.pred_opt()? })
+    }
+
+    fn year(year: i32) -> Option<Self> {
+        Some(Self { from: NaiveDate::from_ymd_opt(year, 1, 1)?, to: NaiveDate::from_ymd_opt(year, 12, 31)? })
+    }
+}
+
+const TODAY: &[&str] = &["сегодня", "сегодняшний", "сегодняшние", "today", "бүгін", "бүгінгі"];
+const YESTERDAY: &[&str] = &["вчера", "вчерашний", "вчерашние", "yesterday", "кеше", "кешегі"];
+const DAY_BEFORE_YESTERDAY: &[&str] = &["позавчера", "day before yesterday", "алдыңғы күні"];
+/// Скользящий период до сегодняшнего дня: «последние 7 дней», «за неделю», «past month», «соңғы 3 ай»
+const ROLLING: &[&str] = &["последние", "последний", "последнюю", "последних", "за", "past", "соңғы"];
+/// Текущий календарный период: «на этой неделе», «this month», «осы жылы»
+const CURRENT: &[&str] = &[
+    "этот", "эта", "эту", "этой", "этом", "этого", "текущий", "текущая", "текущую", "текущей", "текущем", "текущего",
+    "this", "current", "осы", "ағымдағы",
+];
+/// Предыдущий календарный период: «на прошлой неделе», «last month», «өткен жылы»
+const PREVIOUS: &[&str] = &[
+    "прошлый", "прошлая", "прошлую", "прошлой", "прошлом", "прошлого", "предыдущий", "предыдущая", "предыдущую",
+    "предыдущей", "предыдущем", "предыдущего", "last", "previous", "өткен",
+];
+/// Слова между границами периода: «с 1 по 15 марта», «from March 1 to 15», «1 — 15 марта»
+const RANGE_SEPARATORS: &[&str] = &["по", "до", "и", "-", "to", "till", "until", "through", "and"];
+/// Слова перед годом: «в 2024», «за 2023», «in 2024»
+const YEAR_PREFIXES: &[&str] = &["в", "во", "за", "in", "for", "during"];
+const YEAR_WORDS: &[&str] = &["год", "года", "году", "г", "year", "жыл", "жылы", "жылғы"];
+
+const RU_MONTHS: [&str; 12] = [
+    "январ", "феврал", "март", "апрел", "ма", "июн", "июл", "август", "сентябр", "октябр", "ноябр", "декабр",
+];
+const EN_MONTHS: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november",
+    "december",
+];
+const KK_MONTHS: [&str; 12] = [
+    "қаңтар", "ақпан", "наурыз", "сәуір", "мамыр", "маусым", "шілде", "тамыз", "қыркүйек", "қазан", "қараша",
+    "желтоқсан",
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Unit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Unit {
+    fn parse(word: &str) -> Option<Self> {
+        match word {
+            "день" | "дня" | "дней" | "дни" | "сутки" | "суток" | "day" | "days" | "күн" | "күнде" | "күнгі" => {
+                Some(Self::Day)
+            }
+            "неделя" | "недели" | "неделю" | "недель" | "неделе" | "week" | "weeks" | "апта" | "аптада" | "аптадағы" => {
+                Some(Self::Week)
+            }
+            "месяц" | "месяца" | "месяцев" | "месяце" | "month" | "months" | "ай" | "айда" | "айдағы" => Some(Self::Month),
+            "год" | "года" | "году" | "лет" | "year" | "years" | "жыл" | "жылы" | "жылда" | "жылғы" => Some(Self::Year),
+            _ => None,
+        }
+    }
+
+    /// `count` последних единиц, включая сегодняшний день: 7 дней - это сегодня и 6 дней до него
+    fn rolling(self, today: NaiveDate, count: u32) -> Option<DateRange> {
+        let from = match self {
+            Self::Day => today - Duration::days(i64::from(count) - 1),
+            Self::Week => today - Duration::days(7 * i64::from(count) - 1),
+            Self::Month => today.checked_sub_months(Months::new(count))?.succ_opt()?,
+            Self::Year => today.checked_sub_months(Months::new(count.checked_mul(12)?))?.succ_opt()?,
+        };
+        Some(DateRange::new(from, today))
+    }
+
+    /// Календарный период, в который попадает `today`, до сегодняшнего дня включительно
+    fn current(self, today: NaiveDate) -> Option<DateRange> {
+        let from = match self {
+            Self::Day => today,
+            Self::Week => today - Duration::days(i64::from(today.weekday().num_days_from_monday())),
+            Self::Month => today.with_day(1)?,
+            Self::Year => NaiveDate::from_ymd_opt(today.year(), 1, 1)?,
+        };
+        Some(DateRange::new(from, today))
+    }
+
+    /// Предыдущий календарный период целиком (неделя - с понедельника по воскресенье)
+    fn previous(self, today: NaiveDate) -> Option<DateRange> {
+        let current = self.current(today)?.from;
+        match self {
+            Self::Day => Some(DateRange::day(today.pred_opt()?)),
+            Self::Week => Some(DateRange::new(current - Duration::days(7), current - Duration::days(1))),
+            Self::Month => {
+                let from = current.checked_sub_months(Months::new(1))?;
+                DateRange::month(from.year(), from.month())
+            }
+            Self::Year => DateRange::year(today.year() - 1),
+        }
+    }
+}
+
+/// Слова для разбора дат: как `words`, но `01.03.2024`, `2024-03-01` и `1-15` остаются одним словом,
+/// а тире между словами становится отдельным словом («1 — 15 марта»)
+fn date_tokens(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let between_digits = i > 0
+            && chars[i - 1].is_ascii_digit()
+            && chars.get(i + 1).is_some_and(|next| next.is_ascii_digit());
+        if c.is_alphanumeric() || (matches!(c, '.' | '/' | '-') && between_digits) {
+            current.extend(c.to_lowercase());
+            continue;
+        }
+        if !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+        if matches!(c, '-' | '–' | '—') {
+            tokens.push("-".to_string());
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Совпадает ли одна из фраз со словами, начиная с `at`
+fn phrase_in(tokens: &[String], at: usize, phrases: &[&str]) -> bool {
+    phrases.iter().any(|phrase| {
+        phrase.split_whitespace().enumerate().all(|(offset, part)| tokens.get(at + offset).is_some_and(|token| token == part))
+    })
+}
+
+/// Целое число; у английских порядковых числительных суффикс отбрасывается: `1st`, `15th`
+fn number(word: &str) -> Option<u32> {
+    let digits = ["st", "nd", "rd", "th"].iter().find_map(|suffix| word.strip_suffix(suffix)).unwrap_or(word);
+    if digits.is_empty() || digits.len() > 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn day_number(word: &str) -> Option<u32> {
+    number(word).filter(|day| (1..=31).contains(day))
+}
+
+/// Номер месяца по названию на любом языке: «марта», «наурызда», «March», «mar»
+fn month_number(word: &str) -> Option<u32> {
+    let index = RU_MONTHS.iter().position(|stem| match *stem {
+        // «ма» - слишком короткая основа, у мая формы перечислены целиком
+        "ма" => matches!(word, "май" | "мая" | "мае" | "маю"),
+        stem => word.starts_with(stem),
+    });
+    let index = index
+        .or_else(|| EN_MONTHS.iter().position(|name| *name == word || (word.len() == 3 && name.starts_with(word))))
+        .or_else(|| (word == "sept").then_some(8))
+        .or_else(|| KK_MONTHS.iter().position(|name| word.starts_with(name)))?;
+    Some(index as u32 + 1)
+}
+
+/// Год из четырех цифр и следующее за ним слово «год», если оно есть; возвращает год и позицию после него
+fn year_at(tokens: &[String], at: usize) -> Option<(i32, usize)> {
+    let word = tokens.get(at)?;
+    if word.len() != 4 {
+        return None;
+    }
+    let year = number(word).filter(|year| (1900..=2100).contains(year))? as i32;
+    let next = if tokens.get(at + 1).is_some_and(|word| YEAR_WORDS.contains(&word.as_str())) { at + 2 } else { at + 1 };
+    Some((year, next))
+}
+
+/// Год для месяца без года: текущий, а для месяцев, которые в этом году еще не наступили, - прошлый
+fn infer_year(month: u32, today: NaiveDate) -> i32 {
+    if month > today.month() {
+        today.year() - 1
+    } else {
+        today.year()
+    }
+}
+
+/// Месяц и необязательный год после него; возвращает месяц, год (если указан) и позицию после них
+fn month_at(tokens: &[String], at: usize) -> Option<(u32, Option<i32>, usize)> {
+    let month = month_number(tokens.get(at)?)?;
+    Some(match year_at(tokens, at + 1) {
+        Some((year, next)) => (month, Some(year), next),
+        None => (month, None, at + 1),
+    })
+}
+
+/// Позиция после разделителя границ периода, если он стоит в `at`
+fn after_separator(tokens: &[String], at: usize) -> Option<usize> {
+    tokens.get(at).filter(|word| RANGE_SEPARATORS.contains(&word.as_str())).map(|_| at + 1)
+}
+
+/// Дата цифрами: `01.03.2024`, `1/3/24`, `01.03` (текущий год), `2024-03-01`
+fn numeric_date(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let parts: Vec<&str> = word.split(['.', '/', '-']).collect();
+    if parts.iter().any(|part| part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+    match parts.as_slice() {
+        [year, month, day] if year.len() == 4 && word.contains('-') => {
+            NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+        }
+        [day, month, year] if day.len() <= 2 && month.len() <= 2 && matches!(year.len(), 2 | 4) && !word.contains('-') => {
+            let year: i32 = year.parse().ok()?;
+            let year = if year < 100 { 2000 + year } else { year };
+            NaiveDate::from_ymd_opt(year, month.parse().ok()?, day.parse().ok()?)
+        }
+        // `1.5` - скорее дробное число, поэтому у короткой даты месяц из двух цифр
+        [day, month] if day.len() <= 2 && month.len() == 2 && word.contains('.') => {
+            NaiveDate::from_ymd_opt(today.year(), month.parse().ok()?, day.parse().ok()?)
+        }
+        _ => None,
+    }
+}
+
+/// `01.03.2024`, `01.03-15.03`, `01.03.2024 по 15.03.2024`
+fn numeric_range(tokens: &[String], at: usize, today: NaiveDate) -> Option<DateRange> {
+    let word = &tokens[at];
+    if let Some((from, to)) = word.split_once('-') {
+        if let (Some(from), Some(to)) = (numeric_date(from, today), numeric_date(to, today)) {
+            return Some(DateRange::new(from, to));
+        }
+    }
+    let from = numeric_date(word, today)?;
+    let next = after_separator(tokens, at + 1).unwrap_or(at + 1);
+    let to = tokens.get(next).and_then(|word| numeric_date(word, today)).unwrap_or(from);
+    Some(DateRange::new(from, to))
+}
+
+/// День перед месяцем: «15 марта», «1-15 марта», «с 1 по 15 марта», «1 наурыздан 15 сәуірге дейін»
+fn day_then_month(tokens: &[String], at: usize, today: NaiveDate) -> Option<DateRange> {
+    let date = |year: Option<i32>, month, day| NaiveDate::from_ymd_opt(year.unwrap_or(infer_year(month, today)), month, day);
+    let word = &tokens[at];
+
+    if let Some((first, last)) = word.split_once('-') {
+        let (first, last) = (day_number(first)?, day_number(last)?);
+        let (month, year, _) = month_at(tokens, at + 1)?;
+        return Some(DateRange::new(date(year, month, first)?, date(year, month, last)?));
+    }
+
+    let first = day_number(word)?;
+    if let Some((month, year, next)) = month_at(tokens, at + 1) {
+        let from = date(year, month, first)?;
+        let next = after_separator(tokens, next).unwrap_or(next);
+        let to = tokens.get(next).and_then(|word| day_number(word)).and_then(|last| {
+            let (month, second_year, _) = month_at(tokens, next + 1)?;
+            date(second_year.or(year), month, last)
+        });
+        return Some(to.map_or(DateRange::day(from), |to| DateRange::new(from, to)));
+    }
+
+    let next = after_separator(tokens, at + 1)?;
+    let last = day_number(tokens.get(next)?)?;
+    let (month, year, _) = month_at(tokens, next + 1)?;
+    Some(DateRange::new(date(year, month, first)?, date(year, month, last)?))
+}
+
+/// Месяц перед днем, как пишут по-английски: «March 1», «March 1 to 15», «March 1 - April 15, 2024»
+fn month_then_day(tokens: &[String], at: usize, today: NaiveDate) -> Option<DateRange> {
+    let month = month_number(&tokens[at])?;
+    let first = day_number(tokens.get(at + 1)?)?;
+    let (year, next) = match year_at(tokens, at + 2) {
+        Some((year, next)) => (Some(year), next),
+        None => (None, at + 2),
+    };
+    let date = |year: Option<i32>, month, day| NaiveDate::from_ymd_opt(year.unwrap_or(infer_year(month, today)), month, day);
+    let from = date(year, month, first)?;
+
+    let to = after_separator(tokens, next).and_then(|next| {
+        let (last_month, last, after) = match tokens.get(next).and_then(|word| month_number(word)) {
+            Some(last_month) => (last_month, day_number(tokens.get(next + 1)?)?, next + 2),
+            None => (month, day_number(tokens.get(next)?)?, next + 1),
+        };
+        let last_year = year_at(tokens, after).map(|(year, _)| year).or(year);
+        date(last_year, last_month, last)
+    });
+    Some(to.map_or(DateRange::day(from), |to| DateRange::new(from, to)))
+}
+
+/// Месяц целиком: «за март», «в марте 2024 года», «наурызда»
+fn whole_month(tokens: &[String], at: usize, today: NaiveDate) -> Option<DateRange> {
+    let (month, year, _) = month_at(tokens, at)?;
+    // Английское «may» без года - чаще глагол, чем месяц
+    if tokens[at] == "may" && year.is_none() {
+        return None;
+    }
+    DateRange::month(year.unwrap_or(infer_year(month, today)), month)
+}
+
+/// Год целиком: «2024 год», «в 2024», «for 2023»
+fn whole_year(tokens: &[String], at: usize) -> Option<DateRange> {
+    if let Some((year, next)) = year_at(tokens, at) {
+        if next > at + 1 {
+            return DateRange::year(year);
+        }
+    }
+    if YEAR_PREFIXES.contains(&tokens[at].as_str()) {
+        let (year, _) = year_at(tokens, at + 1)?;
+        return DateRange::year(year);
+    }
+    None
+}
+
+/// «последние 7 дней», «за неделю», «last 30 days», «соңғы 3 ай»
+fn rolling(tokens: &[String], at: usize, today: NaiveDate) -> Option<DateRange> {
+    let word = tokens[at].as_str();
+    // «last week» - прошлая календарная неделя, а «last 2 weeks» - скользящий период
+    let needs_count = word == "last";
+    if !ROLLING.contains(&word) && !needs_count {
+        return None;
+    }
+    let (count, unit_at) = match tokens.get(at + 1).and_then(|word| number(word)) {
+        Some(count) => (count, at + 2),
+        None if needs_count => return None,
+        None => (1, at + 1),
+    };
+    if !(1..1000).contains(&count) {
+        return None;
+    }
+    Unit::parse(tokens.get(unit_at)?)?.rolling(today, count)
+}
+
+/// «на этой неделе», «в прошлом месяце», «last year», «өткен айда»
+fn calendar(tokens: &[String], at: usize, today: NaiveDate) -> Option<DateRange> {
+    let word = tokens[at].as_str();
+    let unit = Unit::parse(tokens.get(at + 1)?)?;
+    if CURRENT.contains(&word) {
+        unit.current(today)
+    } else if PREVIOUS.contains(&word) {
+        unit.previous(today)
+    } else {
+        None
+    }
+}
+
+fn relative_day(tokens: &[String], at: usize, today: NaiveDate) -> Option<DateRange> {
+    let days_ago = if phrase_in(tokens, at, DAY_BEFORE_YESTERDAY) {
+        2
+    } else if phrase_in(tokens, at, YESTERDAY) {
+        1
+    } else if phrase_in(tokens, at, TODAY) {
+        0
+    } else {
+        return None;
+    };
+    Some(DateRange::day(today - Duration::days(days_ago)))
+}
+
+/// Находит в вопросе период на русском, английском или казахском: «за вчера», «на прошлой неделе»,
+/// «с 1 по 15 марта», «last 30 days», «01.03.2024-15.03.2024». Относительные даты считаются от `today`,
+/// месяц без года - ближайший прошедший. Если периодов несколько, берется первый.
+pub fn detect_date_range(text: &str, today: NaiveDate) -> Option<DateRange> {
+    let tokens = date_tokens(text);
+    (0..tokens.len()).find_map(|at| {
+        numeric_range(&tokens, at, today)
+            .or_else(|| day_then_month(&tokens, at, today))
+            .or_else(|| month_then_day(&tokens, at, today))
+            .or_else(|| whole_month(&tokens, at, today))
+            .or_else(|| whole_year(&tokens, at))
+            .or_else(|| relative_day(&tokens, at, today))
+            .or_else(|| rolling(&tokens, at, today))
+            .or_else(|| calendar(&tokens, at, today))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> (String, OutputType) {
+        detect_output_format(text)
+    }
+
+    #[test]
+    fn question_without_keywords_is_unchanged() {
+        assert_eq!(parse("Сколько транзакций за вчера?"), ("Сколько транзакций за вчера?".to_string(), OutputType::Auto));
+    }
+
+    #[test]
+    fn keywords_inside_other_words_are_ignored() {
+        for text in ["топ мерчантов категории фотографика", "поля jsonb в логах", "выручка табличного процессора"] {
+            assert_eq!(parse(text), (text.to_string(), OutputType::Auto), "{}", text);
+        }
+    }
+
+    #[test]
+    fn chart_keyword_is_removed() {
+        assert_eq!(
+            parse("покажи продажи по городам графиком"),
+            ("покажи продажи по городам".to_string(), OutputType::Chart)
+        );
+        assert_eq!(parse("Диаграмма продаж по дням"), ("продаж по дням".to_string(), OutputType::Chart));
+    }
+
+    #[test]
+    fn lead_in_words_are_removed_with_keyword() {
+        assert_eq!(parse("продажи по дням в виде таблицы"), ("продажи по дням".to_string(), OutputType::Table));
+        assert_eq!(parse("покажи таблицу продаж за март"), ("продаж за март".to_string(), OutputType::Table));
+        assert_eq!(parse("построй график оборота"), ("оборота".to_string(), OutputType::Chart));
+    }
+
+    #[test]
+    fn lead_in_words_stay_without_keyword() {
+        assert_eq!(parse("покажи продажи в Алматы"), ("покажи продажи в Алматы".to_string(), OutputType::Auto));
+    }
+
+    #[test]
+    fn multibyte_text_is_cut_on_word_boundaries() {
+        assert_eq!(parse("объём по городам, таблицей"), ("объём по городам".to_string(), OutputType::Table));
+        assert_eq!(parse("объём за неделю ГРАФИКОМ"), ("объём за неделю".to_string(), OutputType::Chart));
+    }
+
+    #[test]
+    fn chart_wins_over_table() {
+        assert_eq!(parse("график и таблица продаж"), ("продаж".to_string(), OutputType::Chart));
+        assert_eq!(parse("продажи таблицей или графиком"), ("продажи".to_string(), OutputType::Chart));
+    }
+
+    #[test]
+    fn json_is_detected_and_removed() {
+        assert_eq!(parse("транзакции за сегодня в json"), ("транзакции за сегодня".to_string(), OutputType::Json));
+        assert_eq!(parse("transactions in JSON format"), ("transactions".to_string(), OutputType::Json));
+    }
+
+    #[test]
+    fn english_keywords() {
+        assert_eq!(parse("sales by city as a chart"), ("sales by city".to_string(), OutputType::Chart));
+        assert_eq!(parse("refunds by day in a table"), ("refunds by day".to_string(), OutputType::Table));
+    }
+
+    #[test]
+    fn kazakh_keywords() {
+        assert_eq!(
+            parse("қалалар бойынша сату кесте түрінде"),
+            ("қалалар бойынша сату".to_string(), OutputType::Table)
+        );
+        assert_eq!(parse("айлар бойынша айналым графикпен"), ("айлар бойынша айналым".to_string(), OutputType::Chart));
+    }
+
+    #[test]
+    fn keyword_only_question_is_kept() {
+        assert_eq!(parse("диаграмма"), ("диаграмма".to_string(), OutputType::Chart));
+    }
+
+    #[test]
+    fn punctuation_around_removed_keyword_is_trimmed() {
+        assert_eq!(parse("продажи по дням — график"), ("продажи по дням".to_string(), OutputType::Chart));
+        assert_eq!(parse("график: продажи по дням"), ("продажи по дням".to_string(), OutputType::Chart));
+        assert_eq!(parse("продажи за март графиком?"), ("продажи за март?".to_string(), OutputType::Chart));
+    }
+
+    /// Пятница, 15 марта 2024 года
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 15).unwrap()
+    }
+
+    fn dates(text: &str) -> Option<(String, String)> {
+        detect_date_range(text, today()).map(|range| (range.from.to_string(), range.to.to_string()))
+    }
+
+    fn range(from: &str, to: &str) -> Option<(String, String)> {
+        Some((from.to_string(), to.to_string()))
+    }
+
+    #[test]
+    fn question_without_dates_has_no_range() {
+        for text in ["топ 10 мерчантов по обороту", "sales may drop in Almaty", "айналым бойынша қалалар", "рост на 1.5%"] {
+            assert_eq!(dates(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn relative_days() {
+        assert_eq!(dates("Сколько транзакций за вчера?"), range("2024-03-14", "2024-03-14"));
+        assert_eq!(dates("выручка сегодня"), range("2024-03-15", "2024-03-15"));
+        assert_eq!(dates("refunds the day before yesterday"), range("2024-03-13", "2024-03-13"));
+        assert_eq!(dates("кешегі транзакциялар"), range("2024-03-14", "2024-03-14"));
+    }
+
+    #[test]
+    fn rolling_periods_end_today() {
+        assert_eq!(dates("продажи за последние 7 дней"), range("2024-03-09", "2024-03-15"));
+        assert_eq!(dates("оборот за неделю"), range("2024-03-09", "2024-03-15"));
+        assert_eq!(dates("sales for the last 30 days"), range("2024-02-15", "2024-03-15"));
+        assert_eq!(dates("соңғы 3 ай бойынша айналым"), range("2023-12-16", "2024-03-15"));
+    }
+
+    #[test]
+    fn calendar_periods() {
+        assert_eq!(dates("продажи на прошлой неделе"), range("2024-03-04", "2024-03-10"));
+        assert_eq!(dates("sales last week"), range("2024-03-04", "2024-03-10"));
+        assert_eq!(dates("оборот в этом месяце"), range("2024-03-01", "2024-03-15"));
+        assert_eq!(dates("revenue last month"), range("2024-02-01", "2024-02-29"));
+        assert_eq!(dates("өткен жылы"), range("2023-01-01", "2023-12-31"));
+    }
+
+    #[test]
+    fn day_ranges_within_month() {
+        assert_eq!(dates("транзакции с 1 по 15 марта"), range("2024-03-01", "2024-03-15"));
+        assert_eq!(dates("транзакции 1-15 марта 2023 года"), range("2023-03-01", "2023-03-15"));
+        assert_eq!(dates("с 1 — 5 марта"), range("2024-03-01", "2024-03-05"));
+        assert_eq!(dates("с 20 февраля по 3 марта"), range("2024-02-20", "2024-03-03"));
+        assert_eq!(dates("from March 1 to 10"), range("2024-03-01", "2024-03-10"));
+        assert_eq!(dates("between Feb 20th and March 3rd, 2024"), range("2024-02-20", "2024-03-03"));
+        assert_eq!(dates("1 наурыздан 10 наурызға дейін"), range("2024-03-01", "2024-03-10"));
+    }
+
+    #[test]
+    fn single_days() {
+        assert_eq!(dates("продажи 8 марта"), range("2024-03-08", "2024-03-08"));
+        assert_eq!(dates("sales on March 8"), range("2024-03-08", "2024-03-08"));
+    }
+
+    #[test]
+    fn whole_months_and_years() {
+        assert_eq!(dates("продажи за февраль"), range("2024-02-01", "2024-02-29"));
+        // Декабрь еще не наступил, значит прошлогодний
+        assert_eq!(dates("оборот в декабре"), range("2023-12-01", "2023-12-31"));
+        assert_eq!(dates("sales in May 2023"), range("2023-05-01", "2023-05-31"));
+        assert_eq!(dates("итоги за 2023 год"), range("2023-01-01", "2023-12-31"));
+        assert_eq!(dates("revenue in 2022"), range("2022-01-01", "2022-12-31"));
+    }
+
+    #[test]
+    fn numeric_dates() {
+        assert_eq!(dates("транзакции 01.03.2024"), range("2024-03-01", "2024-03-01"));
+        assert_eq!(dates("с 01.03.2024 по 10.03.2024"), range("2024-03-01", "2024-03-10"));
+        assert_eq!(dates("за 01.02-15.02"), range("2024-02-01", "2024-02-15"));
+        assert_eq!(dates("since 2024-02-01 until 2024-02-10"), range("2024-02-01", "2024-02-10"));
+    }
+}
@@ -0,0 +1,21 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use teloxide::types::ChatId;
+
+/// Очередь запросов к бэкенду внутри чата: запросы одного чата выполняются по одному,
+/// поэтому серия сообщений из одного чата не занимает все места глобального лимита
+/// (`MAX_CONCURRENT_BACKEND_REQUESTS`) и не задерживает другие чаты.
+#[derive(Default)]
+pub struct ChatQueues {
+    chats: Mutex<HashMap<ChatId, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl ChatQueues {
+    /// Очередь чата. Очередь занята, пока держится ее блокировка
+    pub fn chat(&self, chat_id: ChatId) -> Arc<tokio::sync::Mutex<()>> {
+        let mut chats = self.chats.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // Очереди, которые никто не держит и не ждет, больше не нужны
+        chats.retain(|_, queue| Arc::strong_count(queue) > 1);
+        chats.entry(chat_id).or_default().clone()
+    }
+}
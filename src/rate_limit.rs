@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Ограничение частоты запросов к бэкенду (token bucket на чат)
+pub struct RateLimiter {
+    /// Сколько запросов можно сделать подряд
+    capacity: f64,
+    /// Сколько запросов восстанавливается в секунду
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<ChatId, Bucket>>,
+}
+
+impl RateLimiter {
+    /// `per_minute == 0` отключает ограничение
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        Self {
+            capacity: burst.max(1) as f64,
+            refill_per_sec: per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Списывает один запрос; при превышении лимита возвращает время до следующей попытки
+    pub async fn check(&self, chat_id: ChatId) -> Result<(), Duration> {
+        if self.refill_per_sec <= 0.0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().await;
+        // Полные корзины ничего не ограничивают, их можно не хранить
+        buckets.retain(|_, bucket| {
+            bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * self.refill_per_sec
+                < self.capacity
+        });
+
+        let bucket = buckets.entry(chat_id).or_insert(Bucket {
+            tokens: self.capacity,
+            updated_at: now,
+        });
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.refill_per_sec))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn burst_is_allowed_then_limited_per_chat() {
+        let limiter = RateLimiter::new(6, 2);
+        let chat = ChatId(42);
+        assert!(limiter.check(chat).await.is_ok());
+        assert!(limiter.check(chat).await.is_ok());
+        // 6 запросов в минуту - очередной через ~10 секунд
+        let wait = limiter.check(chat).await.unwrap_err();
+        assert!(wait > Duration::from_secs(9) && wait <= Duration::from_secs(10), "{:?}", wait);
+        // У другого чата своя корзина
+        assert!(limiter.check(ChatId(7)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn tokens_refill_over_time() {
+        // 1000 запросов в секунду: корзина из одного запроса восстанавливается за миллисекунду
+        let limiter = RateLimiter::new(60_000, 1);
+        let chat = ChatId(42);
+        assert!(limiter.check(chat).await.is_ok());
+        assert!(limiter.check(chat).await.is_err());
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert!(limiter.check(chat).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn zero_rate_disables_limit() {
+        let limiter = RateLimiter::new(0, 1);
+        for _ in 0..100 {
+            assert!(limiter.check(ChatId(42)).await.is_ok());
+        }
+    }
+}
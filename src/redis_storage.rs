@@ -0,0 +1,199 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use teloxide::dispatching::dialogue::Storage;
+use teloxide::types::ChatId;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+/// Префикс ключей состояний диалогов
+const KEY_PREFIX: &str = "textquery:dialogue:";
+/// Незавершенный диалог хранится неделю с последнего изменения
+const DIALOGUE_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+const DEFAULT_PORT: u16 = 6379;
+
+/// Тип будущих значений в методах `Storage` (как `futures::future::BoxFuture`)
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Хранилище диалогов teloxide в Redis. Нужны только GET/SET/DEL, поэтому вместо
+/// клиентской библиотеки используется минимальная реализация протокола RESP
+/// с одним соединением, которое переоткрывается после сетевой ошибки.
+pub struct RedisStorage {
+    address: String,
+    /// `AUTH`: пользователь (ACL Redis 6+) и пароль из URL
+    auth: Option<(Option<String>, String)>,
+    database: Option<u32>,
+    connection: Mutex<Option<Connection>>,
+}
+
+impl RedisStorage {
+    /// Подключается по URL вида `redis://[[user]:password@]host[:port][/db]` и проверяет соединение
+    pub async fn open(url: &str) -> io::Result<Arc<Self>> {
+        let url = reqwest::Url::parse(url)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid REDIS_URL: {}", e)))?;
+        if url.scheme() != "redis" {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "REDIS_URL must start with redis://"));
+        }
+        let host = url.host_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "REDIS_URL has no host"))?;
+        let auth = url.password().map(|password| {
+            let user = Some(url.username()).filter(|user| !user.is_empty()).map(str::to_string);
+            (user, password.to_string())
+        });
+        let database = match url.path().trim_start_matches('/') {
+            "" => None,
+            db => Some(db.parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "REDIS_URL database must be a number")
+            })?),
+        };
+
+        let storage = Arc::new(Self {
+            address: format!("{}:{}", host, url.port().unwrap_or(DEFAULT_PORT)),
+            auth,
+            database,
+            connection: Mutex::new(None),
+        });
+        storage.execute(&[b"PING"]).await?;
+        Ok(storage)
+    }
+
+    /// Выполняет команду; после сетевой ошибки один раз повторяет ее на новом соединении
+    async fn execute(&self, args: &[&[u8]]) -> io::Result<Reply> {
+        let mut connection = self.connection.lock().await;
+        for attempt in 0..2 {
+            if connection.is_none() {
+                *connection = Some(self.connect().await?);
+            }
+            let Some(open) = connection.as_mut() else {
+                continue;
+            };
+            match open.command(args).await {
+                Err(e) if e.kind() != io::ErrorKind::Other && attempt == 0 => {
+                    tracing::warn!("Redis connection lost, reconnecting: {}", e);
+                    *connection = None;
+                }
+                result => return result,
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotConnected, "Redis is unavailable"))
+    }
+
+    async fn connect(&self) -> io::Result<Connection> {
+        let stream = TcpStream::connect(&self.address).await?;
+        let mut connection = Connection {
+            stream: BufReader::new(stream),
+        };
+        match &self.auth {
+            Some((Some(user), password)) => {
+                connection.command(&[b"AUTH", user.as_bytes(), password.as_bytes()]).await?;
+            }
+            Some((None, password)) => {
+                connection.command(&[b"AUTH", password.as_bytes()]).await?;
+            }
+            None => {}
+        }
+        if let Some(database) = self.database {
+            connection.command(&[b"SELECT", database.to_string().as_bytes()]).await?;
+        }
+        Ok(connection)
+    }
+}
+
+fn key(chat_id: ChatId) -> String {
+    format!("{}{}", KEY_PREFIX, chat_id.0)
+}
+
+impl<D> Storage<D> for RedisStorage
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = io::Error;
+
+    fn remove_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            match self.execute(&[b"DEL", key(chat_id).as_bytes()]).await? {
+                Reply::Integer(0) => Err(io::Error::new(io::ErrorKind::NotFound, "dialogue not found")),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn update_dialogue(self: Arc<Self>, chat_id: ChatId, dialogue: D) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let value = serde_json::to_vec(&dialogue).map_err(io::Error::other)?;
+            let ttl = DIALOGUE_TTL_SECS.to_string();
+            self.execute(&[b"SET", key(chat_id).as_bytes(), &value, b"EX", ttl.as_bytes()]).await?;
+            Ok(())
+        })
+    }
+
+    fn get_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            match self.execute(&[b"GET", key(chat_id).as_bytes()]).await? {
+                Reply::Bulk(Some(value)) => serde_json::from_slice(&value).map(Some).map_err(io::Error::other),
+                _ => Ok(None),
+            }
+        })
+    }
+}
+
+enum Reply {
+    Status,
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+}
+
+struct Connection {
+    stream: BufReader<TcpStream>,
+}
+
+impl Connection {
+    /// Отправляет команду (массив bulk-строк RESP) и читает ответ.
+    /// Ответ-ошибка Redis возвращается как `io::ErrorKind::Other`, чтобы не путать его с обрывом связи.
+    async fn command(&mut self, args: &[&[u8]]) -> io::Result<Reply> {
+        let mut request = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            request.extend_from_slice(arg);
+            request.extend_from_slice(b"\r\n");
+        }
+        self.stream.get_mut().write_all(&request).await?;
+
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Redis closed the connection"));
+        }
+        let line = line.trim_end();
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("unexpected Redis reply: {}", line));
+        let (kind, rest) = match line.as_bytes().first() {
+            Some(kind) if kind.is_ascii() => (*kind, &line[1..]),
+            _ => return Err(invalid()),
+        };
+        match kind {
+            b'+' => Ok(Reply::Status),
+            b'-' => Err(io::Error::other(format!("Redis error: {}", rest))),
+            b':' => rest.parse().map(Reply::Integer).map_err(|_| invalid()),
+            b'$' => {
+                let len: i64 = rest.parse().map_err(|_| invalid())?;
+                if len < 0 {
+                    return Ok(Reply::Bulk(None));
+                }
+                let mut value = vec![0; len as usize + 2];
+                self.stream.read_exact(&mut value).await?;
+                value.truncate(len as usize);
+                Ok(Reply::Bulk(Some(value)))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
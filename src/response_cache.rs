@@ -0,0 +1,271 @@
+use crate::api_client::{QueryRequest, QueryResponse};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::RwLock;
+
+/// Сколько ответов держим в кэше одновременно
+const MAX_CACHED_RESPONSES: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedResponse {
+    stored_at: DateTime<Utc>,
+    response: QueryResponse,
+}
+
+/// Счетчики для `/cache stats`
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub ttl_secs: u64,
+}
+
+/// Кэш ответов `/api/query` на стороне бота: повторные вопросы (например, кнопки меню)
+/// в пределах TTL не доходят до бэкенда и LLM. Сохраняется на диск, чтобы переживать перезапуск.
+pub struct ResponseCache {
+    path: Option<PathBuf>,
+    ttl_secs: u64,
+    entries: RwLock<HashMap<String, CachedResponse>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    /// Загружает кэш с диска (если задан путь); `ttl_secs == 0` отключает кэш
+    pub fn open(path: Option<PathBuf>, ttl_secs: u64) -> Result<Self> {
+        let mut entries: HashMap<String, CachedResponse> = match &path {
+            Some(path) if ttl_secs > 0 && path.exists() => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read response cache {}", path.display()))?;
+                // Битый кэш не повод не запускаться - начинаем с пустого
+                serde_json::from_str(&content).unwrap_or_else(|e| {
+                    tracing::warn!("Ignoring unreadable response cache {}: {}", path.display(), e);
+                    HashMap::new()
+                })
+            }
+            _ => HashMap::new(),
+        };
+        let now = Utc::now();
+        entries.retain(|_, entry| !Self::expired(entry, ttl_secs, now));
+
+        Ok(Self {
+            path,
+            ttl_secs,
+            entries: RwLock::new(entries),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ttl_secs > 0
+    }
+
+    /// Ключ кэша: нормализованный вопрос, формат вывода и параметры, влияющие на ответ.
+    /// `scope` - пользователь с персональным токеном (его данные не должны попадать другим).
+    pub fn key(request: &QueryRequest, scope: Option<&str>) -> String {
+        let question = request
+            .question
+            .to_lowercase()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let question = question.trim_end_matches(['?', '.', '!']).trim_end();
+        // Период входит в ключ: «за вчера» завтра - уже другой день
+        let period = match (request.date_from, request.date_to) {
+            (Some(from), Some(to)) => format!("{}..{}", from, to),
+            _ => String::new(),
+        };
+        format!(
+            "{}|{:?}|{}|{}|{}|{}",
+            question,
+            request.output_type,
+            request.include_analysis,
+            request.language.as_deref().unwrap_or(""),
+            period,
+            scope.unwrap_or("")
+        )
+    }
+
+    pub async fn get(&self, key: &str) -> Option<QueryResponse> {
+        let entries = self.entries.read().await;
+        match entries.get(key).filter(|entry| !Self::expired(entry, self.ttl_secs, Utc::now())) {
+            Some(entry) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.response.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Сохраняет ответ (ошибки записи на диск только логируются)
+    pub async fn insert(&self, key: String, response: &QueryResponse) {
+        let mut entries = self.entries.write().await;
+        let now = Utc::now();
+        entries.retain(|_, entry| !Self::expired(entry, self.ttl_secs, now));
+        if entries.len() >= MAX_CACHED_RESPONSES {
+            // Вытесняем самый старый ответ
+            if let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.stored_at)
+                .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, CachedResponse {
+            stored_at: now,
+            response: response.clone(),
+        });
+
+        if let Err(e) = self.save(&entries).await {
+            tracing::warn!("Failed to persist response cache: {}", e);
+        }
+    }
+
+    /// Удаляет просроченные ответы и переписывает файл кэша, в котором могли остаться
+    /// ответы прошлых запусков; возвращает, сколько ответов удалено из памяти
+    pub async fn purge_expired(&self) -> Result<usize> {
+        let mut entries = self.entries.write().await;
+        let before = entries.len();
+        let now = Utc::now();
+        entries.retain(|_, entry| !Self::expired(entry, self.ttl_secs, now));
+        if self.path.as_ref().is_some_and(|path| path.exists()) {
+            self.save(&entries).await?;
+        }
+        Ok(before - entries.len())
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let now = Utc::now();
+        let entries = self.entries.read().await;
+        CacheStats {
+            entries: entries.values().filter(|entry| !Self::expired(entry, self.ttl_secs, now)).count(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ttl_secs: self.ttl_secs,
+        }
+    }
+
+    fn expired(entry: &CachedResponse, ttl_secs: u64, now: DateTime<Utc>) -> bool {
+        (now - entry.stored_at).num_seconds() >= ttl_secs as i64
+    }
+
+    async fn save(&self, entries: &HashMap<String, CachedResponse>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let content = serde_json::to_string(entries).context("Failed to serialize response cache")?;
+        // Пишем во временный файл и переименовываем, чтобы не оставить битый файл при сбое
+        let temp_path = path.with_extension("tmp");
+        tokio::fs::write(&temp_path, content)
+            .await
+            .with_context(|| format!("Failed to write response cache {}", temp_path.display()))?;
+        tokio::fs::rename(&temp_path, path)
+            .await
+            .with_context(|| format!("Failed to replace response cache {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(question: &str) -> QueryRequest {
+        QueryRequest {
+            question: question.to_string(),
+            include_analysis: true,
+            use_cache: true,
+            user_id: Some("42".to_string()),
+            ..QueryRequest::default()
+        }
+    }
+
+    fn response(question: &str) -> QueryResponse {
+        serde_json::from_value(json!({
+            "question": question,
+            "data": [{"total_amount": 100}],
+            "execution_time_ms": 5,
+            "row_count": 1,
+        }))
+        .unwrap()
+    }
+
+    fn cache_path(name: &str) -> PathBuf {
+        crate::utils::test_dir(&format!("cache_{}", name)).join("response_cache.json")
+    }
+
+    #[test]
+    fn key_ignores_case_spacing_and_punctuation_but_not_scope_or_period() {
+        let key = ResponseCache::key(&request("Топ 5  городов?"), None);
+        assert_eq!(key, ResponseCache::key(&request("топ 5 городов"), None));
+        assert_ne!(key, ResponseCache::key(&request("топ 5 городов"), Some("42")));
+
+        let mut yesterday = request("Оборот за вчера");
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        yesterday.date_from = Some(day);
+        yesterday.date_to = Some(day);
+        let mut next_day = request("Оборот за вчера");
+        next_day.date_from = day.succ_opt();
+        next_day.date_to = day.succ_opt();
+        assert_ne!(ResponseCache::key(&yesterday, None), ResponseCache::key(&next_day, None));
+    }
+
+    #[tokio::test]
+    async fn responses_are_counted_and_survive_restart() {
+        let path = cache_path("restart");
+        let cache = ResponseCache::open(Some(path.clone()), 600).unwrap();
+        assert!(cache.get("оборот").await.is_none());
+        cache.insert("оборот".to_string(), &response("Оборот")).await;
+        assert_eq!(cache.get("оборот").await.unwrap().question, "Оборот");
+        let stats = cache.stats().await;
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+
+        let reopened = ResponseCache::open(Some(path), 600).unwrap();
+        assert_eq!(reopened.get("оборот").await.unwrap().question, "Оборот");
+    }
+
+    #[tokio::test]
+    async fn expired_and_unreadable_files_start_empty() {
+        let path = cache_path("expired");
+        let stale = CachedResponse { stored_at: Utc::now() - chrono::Duration::hours(1), response: response("Оборот") };
+        std::fs::write(&path, serde_json::to_string(&HashMap::from([("оборот", stale)])).unwrap()).unwrap();
+        let cache = ResponseCache::open(Some(path.clone()), 600).unwrap();
+        assert_eq!(cache.stats().await.entries, 0);
+        assert!(cache.get("оборот").await.is_none());
+
+        std::fs::write(&path, "{not json").unwrap();
+        assert_eq!(ResponseCache::open(Some(path), 600).unwrap().stats().await.entries, 0);
+        assert!(!ResponseCache::open(None, 0).unwrap().is_enabled());
+    }
+
+    #[tokio::test]
+    async fn expired_responses_are_purged_from_disk() {
+        let path = cache_path("purge");
+        let stale = CachedResponse { stored_at: Utc::now() - chrono::Duration::hours(1), response: response("Оборот") };
+        std::fs::write(&path, serde_json::to_string(&HashMap::from([("старый", stale.clone())])).unwrap()).unwrap();
+        let cache = ResponseCache::open(Some(path.clone()), 600).unwrap();
+        cache.insert("свежий".to_string(), &response("Топ городов")).await;
+        cache.entries.write().await.insert("просроченный".to_string(), stale);
+
+        assert_eq!(cache.purge_expired().await.unwrap(), 1);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("свежий"));
+        assert!(!content.contains("старый") && !content.contains("просроченный"), "{}", content);
+
+        // Выключенный кэш стирает ответы, сохраненные, пока он был включен
+        std::fs::write(&path, serde_json::to_string(&HashMap::from([("старый", CachedResponse { stored_at: Utc::now(), response: response("Оборот") })])).unwrap()).unwrap();
+        let disabled = ResponseCache::open(Some(path.clone()), 0).unwrap();
+        assert_eq!(disabled.purge_expired().await.unwrap(), 0);
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("старый"));
+    }
+}
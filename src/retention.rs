@@ -0,0 +1,41 @@
+use crate::api_client::BackendClient;
+use crate::storage::Storage;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Как часто запускается очистка
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Запускает фоновую задачу, удаляющую историю старше `retention_days` дней (`0` - хранить
+/// бессрочно) и просроченные ответы из кэшей бэкендов `clients`
+pub fn spawn_purge_task(storage: Arc<Storage>, clients: Vec<Arc<dyn BackendClient>>, retention_days: u32) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            purge(&storage, &clients, retention_days).await;
+        }
+    });
+}
+
+async fn purge(storage: &Storage, clients: &[Arc<dyn BackendClient>], retention_days: u32) {
+    if retention_days > 0 {
+        let cutoff = Utc::now() - chrono::Duration::days(i64::from(retention_days));
+        match storage.purge_history_before(cutoff).await {
+            Ok(0) => {}
+            Ok(removed) => info!("Retention: removed {} history entries older than {} days", removed, retention_days),
+            Err(e) => error!("Retention: failed to purge history: {}", e),
+        }
+    }
+
+    // В кэше лежат ответы с платежными данными: на диске они не должны переживать TTL
+    for client in clients {
+        match client.cache().purge_expired().await {
+            Ok(0) => {}
+            Ok(removed) => info!("Retention: removed {} expired cached responses", removed),
+            Err(e) => error!("Retention: failed to purge response cache: {}", e),
+        }
+    }
+}
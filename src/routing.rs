@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+/// Какой эндпоинт бэкенда обрабатывает обычные сообщения пользователя
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryMode {
+    /// `/api/query`, при ошибке SQL - `/api/chat`
+    #[default]
+    Auto,
+    /// Только `/api/query`
+    Sql,
+    /// Только `/api/chat`
+    Chat,
+}
+
+impl QueryMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "auto" => Some(Self::Auto),
+            "sql" => Some(Self::Sql),
+            "chat" => Some(Self::Chat),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Auto => "авто (SQL, при неудаче — чат)",
+            Self::Sql => "только SQL",
+            Self::Chat => "только чат",
+        }
+    }
+}
+
+/// Вопрос с префиксом `sql:` бэкенд всегда обрабатывает как запрос к данным
+pub fn force_sql(question: &str) -> String {
+    if is_forced_sql(question) {
+        question.to_string()
+    } else {
+        format!("sql: {}", question)
+    }
+}
+
+pub fn is_forced_sql(question: &str) -> bool {
+    question.trim_start().to_lowercase().starts_with("sql:")
+}
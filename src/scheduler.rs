@@ -0,0 +1,270 @@
+use crate::api_client::{OutputType, QueryRequest};
+use crate::state::BotState;
+use crate::utils::{format_backend_error, format_query_response};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use teloxide::prelude::*;
+use tracing::{error, info};
+
+/// Как часто проверяется, не пора ли отправить отчеты
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Сколько регулярных отчетов может быть в одном чате
+pub const MAX_SCHEDULES_PER_CHAT: usize = 10;
+
+/// Периодичность отчета
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Frequency {
+    Daily,
+    Weekly(Weekday),
+}
+
+/// Сохраненный вопрос, который бот выполняет по расписанию и присылает в чат
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledReport {
+    pub id: u64,
+    pub chat_id: i64,
+    /// Ключ контекста пользователя, создавшего отчет (см. `CONTEXT_SCOPE`)
+    pub user_id: String,
+    pub question: String,
+    pub frequency: Frequency,
+    /// Время запуска в часовом поясе `SCHEDULE_UTC_OFFSET_HOURS`
+    pub time: NaiveTime,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+impl ScheduledReport {
+    /// Ближайший прошедший момент запуска по расписанию (`None`, если сегодня не день отчета)
+    fn due_at(&self, now: DateTime<Utc>, offset: FixedOffset) -> Option<DateTime<Utc>> {
+        let local_now = now.with_timezone(&offset);
+        if let Frequency::Weekly(weekday) = self.frequency {
+            if local_now.weekday() != weekday {
+                return None;
+            }
+        }
+        let scheduled = local_now
+            .date_naive()
+            .and_time(self.time)
+            .and_local_timezone(offset)
+            .single()?
+            .with_timezone(&Utc);
+        (scheduled <= now).then_some(scheduled)
+    }
+
+    /// Описание расписания для списка отчетов
+    pub fn describe(&self) -> String {
+        let when = match self.frequency {
+            Frequency::Daily => "каждый день".to_string(),
+            Frequency::Weekly(weekday) => format!("по {}", weekday_name(weekday)),
+        };
+        format!("{} в {}", when, self.time.format("%H:%M"))
+    }
+}
+
+/// Разбирает «каждый день в 9:00: объем транзакций за вчера».
+/// Возвращает периодичность, время и вопрос.
+pub fn parse_schedule(text: &str) -> Option<(Frequency, NaiveTime, String)> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let time_idx = words.iter().position(|word| parse_time(word).is_some())?;
+    let time = parse_time(words[time_idx])?;
+
+    let when = words[..time_idx].join(" ").to_lowercase();
+    let frequency = match WEEKDAY_WORDS.iter().find(|(word, _)| when.contains(word)) {
+        Some((_, weekday)) => Frequency::Weekly(*weekday),
+        None if when.contains("недел") || when.contains("weekly") => Frequency::Weekly(Weekday::Mon),
+        None if when.contains("день") || when.contains("ежедневно") || when.contains("daily") => Frequency::Daily,
+        None => return None,
+    };
+
+    let question = words[time_idx + 1..].join(" ");
+    let question = question.trim_start_matches([':', '-', '—']).trim().to_string();
+    if question.is_empty() {
+        return None;
+    }
+    Some((frequency, time, question))
+}
+
+/// `9:00`, `09:30:` (двоеточие после времени отделяет вопрос)
+fn parse_time(word: &str) -> Option<NaiveTime> {
+    let word = word.trim_end_matches([':', ',']);
+    let (hours, minutes) = word.split_once(':')?;
+    NaiveTime::from_hms_opt(hours.parse().ok()?, minutes.parse().ok()?, 0)
+        .filter(|_| minutes.len() == 2)
+}
+
+const WEEKDAY_WORDS: &[(&str, Weekday)] = &[
+    ("понедельник", Weekday::Mon),
+    ("вторник", Weekday::Tue),
+    ("сред", Weekday::Wed),
+    ("четверг", Weekday::Thu),
+    ("пятниц", Weekday::Fri),
+    ("суббот", Weekday::Sat),
+    ("воскресень", Weekday::Sun),
+    ("monday", Weekday::Mon),
+    ("tuesday", Weekday::Tue),
+    ("wednesday", Weekday::Wed),
+    ("thursday", Weekday::Thu),
+    ("friday", Weekday::Fri),
+    ("saturday", Weekday::Sat),
+    ("sunday", Weekday::Sun),
+];
+
+fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "понедельникам",
+        Weekday::Tue => "вторникам",
+        Weekday::Wed => "средам",
+        Weekday::Thu => "четвергам",
+        Weekday::Fri => "пятницам",
+        Weekday::Sat => "субботам",
+        Weekday::Sun => "воскресеньям",
+    }
+}
+
+/// Запускает фоновую проверку расписания
+pub fn spawn(bot: Bot, state: Arc<BotState>, utc_offset_hours: i32) {
+    let Some(offset) = FixedOffset::east_opt(utc_offset_hours * 3600) else {
+        error!("Invalid SCHEDULE_UTC_OFFSET_HOURS {}, scheduled reports are disabled", utc_offset_hours);
+        return;
+    };
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            run_due_reports(&bot, &state, offset).await;
+        }
+    });
+}
+
+async fn run_due_reports(bot: &Bot, state: &Arc<BotState>, offset: FixedOffset) {
+    let now = Utc::now();
+    for report in state.storage.schedules().await {
+        let Some(due_at) = report.due_at(now, offset) else {
+            continue;
+        };
+        // Отчет, созданный после сегодняшнего времени запуска, впервые придет в следующий раз
+        let already_ran = report.last_run.is_some_and(|last_run| last_run >= due_at);
+        if already_ran || report.created_at > due_at {
+            continue;
+        }
+        // Не досылаем пропущенные за время простоя бота отчеты спустя часы
+        if now - due_at > Duration::hours(1) {
+            let _ = state.storage.mark_schedule_run(report.id, now).await;
+            continue;
+        }
+
+        if let Err(e) = state.storage.mark_schedule_run(report.id, now).await {
+            error!("Failed to mark scheduled report {} as run: {}", report.id, e);
+            continue;
+        }
+        info!("Running scheduled report {} for chat {}", report.id, report.chat_id);
+        let correlation_id = format!("schedule-{}-{}", report.id, now.timestamp());
+        if let Err(e) = crate::correlation::scope(correlation_id, deliver(bot, state, &report)).await {
+            error!("Failed to deliver scheduled report {}: {}", report.id, e);
+        }
+    }
+}
+
+/// Выполняет вопрос отчета и отправляет в чат текст, диаграмму и CSV
+async fn deliver(bot: &Bot, state: &BotState, report: &ScheduledReport) -> ResponseResult<()> {
+    let chat_id = ChatId(report.chat_id);
+    // Период считается в момент запуска: «за вчера» в ежедневном отчете - каждый раз новый день
+    let zone = state.user_zone(&report.user_id).await;
+    let period = zone.question_period(&report.question);
+    let request = QueryRequest {
+        question: report.question.clone(),
+        include_analysis: true,
+        use_cache: false,
+        include_sql: false,
+        user_id: Some(report.user_id.clone()),
+        output_type: OutputType::Auto,
+        language: crate::handlers::answer_language(state, &report.user_id, None).await,
+        date_from: period.map(|period| period.from),
+        date_to: period.map(|period| period.to),
+        timezone: Some(zone.name()),
+    };
+
+    let header = format!(
+        "🗓 <b>Отчет #{}</b> ({})\n<i>{}</i>",
+        report.id,
+        report.describe(),
+        crate::utils::escape_html(&report.question)
+    );
+
+    match state.api(&report.user_id).await.query(request).await {
+        Ok(response) => {
+            let lang = state.ui_language(&report.user_id, None).await;
+            let insight_level = state.storage.settings(&report.user_id).await.insight_level;
+            let formatted = format!("{}\n\n{}", header, format_query_response(&response, insight_level, lang));
+            crate::handlers::send_answer_text(bot, chat_id, state, Some(&report.user_id), &formatted, None).await?;
+            if let Some(chart_data) = &response.chart_data {
+                let settings = state.storage.settings(&report.user_id).await;
+                crate::handlers::send_chart(bot, chat_id, state, chart_data, lang, &settings, None).await;
+            }
+            if !response.data.is_empty() {
+                let settings = state.storage.settings(&report.user_id).await;
+                crate::handlers::send_export(bot, chat_id, state, crate::exports::ExportFormat::Csv, &response.data, &settings, None).await?;
+            }
+        }
+        Err(e) => {
+            error!("Scheduled report {} query failed: {}", report.id, e);
+            let lang = state.ui_language(&report.user_id, None).await;
+            bot.send_message(chat_id, format!("{}\n\n{}", header, format_backend_error(lang, &e, "Не удалось выполнить запрос отчета")))
+                .parse_mode(teloxide::types::ParseMode::Html)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use chrono::Timelike;
+
+    #[tokio::test]
+    async fn forgotten_user_reports_are_not_delivered() {
+        let path = crate::utils::test_dir("scheduler_forget").join("bot_data.db");
+        let storage = Storage::open(&path).unwrap();
+        let offset = FixedOffset::east_opt(5 * 3600).unwrap();
+        // Оба отчета должны прийти в текущую минуту
+        let now = Utc::now();
+        let local = now.with_timezone(&offset);
+        let time = NaiveTime::from_hms_opt(local.hour(), local.minute(), 0).unwrap();
+        for user_id in ["42", "7"] {
+            let report = ScheduledReport {
+                id: 0,
+                chat_id: user_id.parse().unwrap(),
+                user_id: user_id.to_string(),
+                question: format!("Оборот {}", user_id),
+                frequency: Frequency::Daily,
+                time,
+                created_at: now - Duration::hours(2),
+                last_run: None,
+            };
+            storage.add_schedule(report).await.unwrap();
+        }
+
+        // Что удаляет из хранилища /forgetme
+        storage.remove_user("42").await.unwrap();
+        drop(storage);
+
+        // Планировщик берет отчеты из хранилища: после перезапуска остается только отчет другого пользователя
+        let storage = Storage::open(&path).unwrap();
+        let due: Vec<_> = storage
+            .schedules()
+            .await
+            .into_iter()
+            .filter(|report| report.due_at(now, offset).is_some())
+            .map(|report| report.question)
+            .collect();
+        assert_eq!(due, ["Оборот 7"]);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::api_client::SchemaTable;
+use crate::api_error::ApiError;
+use crate::state::BotState;
+use crate::utils::{escape_html, format_error};
+use reqwest::StatusCode;
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode};
+use tracing::error;
+
+/// Сколько таблиц и колонок показывается на одной странице
+const TABLES_PER_PAGE: usize = 8;
+const COLUMNS_PER_PAGE: usize = 10;
+
+/// Длиннее описание колонки обрезается, чтобы страница поместилась в сообщение
+const MAX_DESCRIPTION_CHARS: usize = 200;
+
+/// Больше совпадений поиска `/schema <текст>` не показывается
+const MAX_SEARCH_RESULTS: usize = 20;
+
+/// Страница списка таблиц (`schema:t:<страница>`)
+fn tables_page(tables: &[SchemaTable], page: usize) -> (String, InlineKeyboardMarkup) {
+    let pages = tables.len().div_ceil(TABLES_PER_PAGE).max(1);
+    let page = page.min(pages - 1);
+    let start = page * TABLES_PER_PAGE;
+
+    let mut text = String::from("🗂 <b>Данные, о которых можно спрашивать</b>\n\nВыберите таблицу, чтобы посмотреть ее поля.");
+    if pages > 1 {
+        text.push_str(&format!(" Страница {}/{}.", page + 1, pages));
+    }
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = tables
+        .iter()
+        .enumerate()
+        .skip(start)
+        .take(TABLES_PER_PAGE)
+        .map(|(index, table)| {
+            let label = match &table.description {
+                Some(description) => format!("{} — {}", table.name, description),
+                None => table.name.clone(),
+            };
+            vec![InlineKeyboardButton::callback(truncate(&label, 60), format!("schema:o:{}:0", index))]
+        })
+        .collect();
+    rows.extend(pager(page, pages, |page| format!("schema:t:{}", page)));
+    (text, InlineKeyboardMarkup::new(rows))
+}
+
+/// Страница колонок таблицы (`schema:o:<таблица>:<страница>`)
+fn table_page(tables: &[SchemaTable], index: usize, page: usize) -> (String, InlineKeyboardMarkup) {
+    let table = &tables[index];
+    let pages = table.columns.len().div_ceil(COLUMNS_PER_PAGE).max(1);
+    let page = page.min(pages - 1);
+
+    let mut text = format!("🗂 <b>{}</b>", escape_html(&table.name));
+    if let Some(description) = &table.description {
+        text.push_str(&format!("\n{}", escape_html(description)));
+    }
+    if table.columns.is_empty() {
+        text.push_str("\n\nБэкенд не сообщил поля этой таблицы.");
+    } else {
+        text.push_str(&format!("\n\n<b>Поля</b> ({}):\n", table.columns.len()));
+        for column in table.columns.iter().skip(page * COLUMNS_PER_PAGE).take(COLUMNS_PER_PAGE) {
+            text.push_str(&describe_column(column));
+            text.push('\n');
+        }
+    }
+
+    let mut rows = pager(page, pages, |page| format!("schema:o:{}:{}", index, page));
+    if tables.len() > 1 {
+        rows.push(vec![InlineKeyboardButton::callback(crate::menu::BACK_BUTTON, "schema:t:0")]);
+    }
+    (text, InlineKeyboardMarkup::new(rows))
+}
+
+/// `• <code>amount</code> <i>numeric</i> — Сумма транзакции`
+fn describe_column(column: &crate::api_client::SchemaColumn) -> String {
+    let mut line = format!("• <code>{}</code>", escape_html(&column.name));
+    if let Some(data_type) = &column.data_type {
+        line.push_str(&format!(" <i>{}</i>", escape_html(data_type)));
+    }
+    if let Some(description) = &column.description {
+        line.push_str(&format!(" — {}", escape_html(&truncate(description, MAX_DESCRIPTION_CHARS))));
+    }
+    line
+}
+
+/// Кнопки ⬅️/➡️ со счетчиком страниц; одна страница - без кнопок
+fn pager(page: usize, pages: usize, callback: impl Fn(usize) -> String) -> Vec<Vec<InlineKeyboardButton>> {
+    if pages <= 1 {
+        return Vec::new();
+    }
+    let mut row = Vec::new();
+    if page > 0 {
+        row.push(InlineKeyboardButton::callback("⬅️", callback(page - 1)));
+    }
+    row.push(InlineKeyboardButton::callback(format!("{}/{}", page + 1, pages), callback(page)));
+    if page + 1 < pages {
+        row.push(InlineKeyboardButton::callback("➡️", callback(page + 1)));
+    }
+    vec![row]
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Результат поиска `/schema <текст>`
+#[derive(Debug, PartialEq)]
+enum Found {
+    /// Индекс таблицы с таким названием или описанием
+    Table(usize),
+    /// Подходящие поля всех таблиц (или сообщение, что ничего нет), HTML
+    Columns(String),
+}
+
+/// Поиск `/schema <текст>`: таблица с таким названием или описанием, иначе подходящие поля всех таблиц
+fn search(tables: &[SchemaTable], needle: &str) -> Found {
+    let needle = needle.to_lowercase();
+    let matches = |text: &str| text.to_lowercase().contains(&needle);
+
+    if let Some(index) = tables.iter().position(|table| {
+        matches(&table.name) || table.description.as_deref().is_some_and(matches)
+    }) {
+        return Found::Table(index);
+    }
+
+    let found: Vec<String> = tables
+        .iter()
+        .flat_map(|table| {
+            table.columns
+                .iter()
+                .filter(|column| matches(&column.name) || column.description.as_deref().is_some_and(matches))
+                .map(move |column| format!("{} <i>({})</i>", describe_column(column), escape_html(&table.name)))
+        })
+        .take(MAX_SEARCH_RESULTS)
+        .collect();
+    if found.is_empty() {
+        return Found::Columns(format!(
+            "🔍 Ничего не найдено по «{}». Список таблиц — /schema",
+            escape_html(&needle)
+        ));
+    }
+    Found::Columns(format!("🔍 <b>Поля по запросу «{}»</b>\n\n{}", escape_html(&needle), found.join("\n")))
+}
+
+/// `/schema [текст]` - таблицы и поля, о которых можно спрашивать; с текстом - поиск по ним
+pub async fn handle_schema(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    let Some(tables) = fetch(&bot, msg.chat.id, &state, &user_id).await? else {
+        return Ok(());
+    };
+
+    if tables.is_empty() {
+        bot.send_message(msg.chat.id, "🗂 Бэкенд не сообщил ни одной таблицы")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let arg = arg.trim();
+    let (text, keyboard) = if !arg.is_empty() {
+        match search(&tables, arg) {
+            Found::Table(index) => table_page(&tables, index, 0),
+            Found::Columns(text) => (text, InlineKeyboardMarkup::default()),
+        }
+    } else if tables.len() == 1 {
+        // Единственную таблицу незачем выбирать
+        table_page(&tables, 0, 0)
+    } else {
+        tables_page(&tables, 0)
+    };
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// Кнопки `/schema`: `t:<страница>` - список таблиц, `o:<таблица>:<страница>` - поля таблицы
+pub async fn handle_callback(
+    bot: Bot,
+    msg: Message,
+    user: UserId,
+    action: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let user_id = state.context_scope.key(msg.chat.id, Some(user));
+    let Some(tables) = fetch(&bot, msg.chat.id, &state, &user_id).await? else {
+        return Ok(());
+    };
+    if tables.is_empty() {
+        return Ok(());
+    }
+
+    let mut parts = action.split(':');
+    let (kind, first, second) = (parts.next(), parts.next(), parts.next());
+    let number = |value: Option<&str>| value.and_then(|value| value.parse::<usize>().ok()).unwrap_or(0);
+    let (text, keyboard) = match kind {
+        // Схема могла измениться с тех пор, как отправлены кнопки: неизвестная таблица - к списку
+        Some("o") if number(first) < tables.len() => table_page(&tables, number(first), number(second)),
+        _ => tables_page(&tables, number(first)),
+    };
+
+    // Нажатие на счетчик страниц ничего не меняет, и Telegram отклоняет такую правку - это не ошибка
+    let _ = bot.edit_message_text(msg.chat.id, msg.id, text)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard)
+        .await;
+    Ok(())
+}
+
+/// Описание схемы от бэкенда пользователя; при ошибке сообщает о ней в чат и возвращает `None`
+async fn fetch(bot: &Bot, chat_id: ChatId, state: &BotState, user_id: &str) -> ResponseResult<Option<Arc<Vec<SchemaTable>>>> {
+    match state.api(user_id).await.schema(user_id).await {
+        Ok(tables) => Ok(Some(tables)),
+        Err(e) => {
+            error!("Error fetching schema for user {}: {}", user_id, e);
+            let unsupported = matches!(
+                e.downcast_ref::<ApiError>(),
+                Some(ApiError::Other { status: StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED, .. })
+            );
+            let text = if unsupported {
+                "Бэкенд не умеет описывать схему данных. Спросите в чате: «какие поля есть у транзакции?»"
+            } else {
+                "Не удалось получить описание данных. Попробуйте позже."
+            };
+            bot.send_message(chat_id, format_error(text))
+                .parse_mode(ParseMode::Html)
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::SchemaColumn;
+
+    fn column(name: &str, description: &str) -> SchemaColumn {
+        SchemaColumn { name: name.to_string(), data_type: Some("text".to_string()), description: Some(description.to_string()) }
+    }
+
+    #[test]
+    fn search_prefers_tables_then_columns() {
+        let tables = vec![
+            SchemaTable {
+                name: "transactions".to_string(),
+                description: Some("Платежные транзакции".to_string()),
+                columns: vec![column("merchant_city", "Город мерчанта")],
+            },
+            SchemaTable { name: "banks".to_string(), description: None, columns: vec![column("bank_name", "Название банка")] },
+        ];
+
+        assert_eq!(search(&tables, "Транзакц"), Found::Table(0));
+        let Found::Columns(found) = search(&tables, "город") else {
+            panic!("expected matching columns");
+        };
+        assert!(found.contains("merchant_city") && found.contains("transactions"));
+        assert!(matches!(search(&tables, "валюта"), Found::Columns(text) if text.starts_with("🔍 Ничего не найдено")));
+    }
+
+    #[test]
+    fn columns_are_paginated() {
+        let columns = (0..25).map(|i| column(&format!("col_{}", i), "поле")).collect();
+        let tables = vec![SchemaTable { name: "wide".to_string(), description: None, columns }];
+
+        let (text, _) = table_page(&tables, 0, 2);
+        assert!(text.contains("col_20") && !text.contains("col_19"));
+        // Страница за пределами - последняя
+        let (text, _) = table_page(&tables, 0, 9);
+        assert!(text.contains("col_24"));
+    }
+}
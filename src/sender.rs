@@ -0,0 +1,650 @@
+use crate::api_client::{OutputType, QueryResponse};
+use crate::brief::{brief_answer, expand_button, toggle_button, Versions};
+use crate::currency::convert_response;
+use crate::exports::ExportFormat;
+use crate::handlers::{remember_response, send_chart, send_export, send_result_pages, send_table_image, Caption};
+use crate::handoff::attach_handoff_button;
+use crate::i18n::{tr, Msg};
+use crate::language::Language;
+use crate::messenger::MessageSender;
+use crate::progress::{Progress, Stage};
+use crate::state::BotState;
+use crate::storage::UserSettings;
+use crate::timezone::convert_timestamps;
+use crate::utils::{
+    append_keyboard_row, create_suggestions_keyboard, escape_html, fits_in_message, format_query_response_with, format_sql,
+    DataPlacement, InsightOptions,
+};
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, ReplyMarkup};
+
+/// JSON длиннее стольких символов отправляется файлом, а не блоком кода в ответе
+const MAX_INLINE_JSON_CHARS: usize = 2000;
+
+/// Больше исправленных вариантов вопроса под пустым результатом не предлагается
+const MAX_CORRECTIONS: usize = 3;
+
+/// Отправляет ответ бэкенда на запрос к данным: выгрузку, диаграмму, текст с кнопками
+/// и постраничный просмотр. Общий для вопросов, кнопок меню и подсказок, так что новый
+/// вид вывода достаточно подключить здесь.
+pub struct ResponseSender<'a, S: MessageSender = Bot> {
+    bot: &'a S,
+    state: &'a BotState,
+    chat_id: ChatId,
+    user_id: &'a str,
+    export: Option<ExportFormat>,
+    output_type: OutputType,
+}
+
+impl<'a, S: MessageSender> ResponseSender<'a, S> {
+    pub fn new(bot: &'a S, state: &'a BotState, chat_id: ChatId, user_id: &'a str) -> Self {
+        Self { bot, state, chat_id, user_id, export: None, output_type: OutputType::Auto }
+    }
+
+    /// Формат, в котором запрошен ответ: для JSON данные показываются JSON, а не таблицей
+    pub fn with_output_type(mut self, output_type: OutputType) -> Self {
+        self.output_type = output_type;
+        self
+    }
+
+    /// Дополнительно отправить данные файлом (просьба «выгрузи в excel» в вопросе)
+    pub fn with_export(mut self, export: Option<ExportFormat>) -> Self {
+        self.export = export;
+        self
+    }
+
+    /// Запоминает ответ и превращает в него сообщение о ходе запроса. Если вместе с ответом
+    /// отправляются диаграмма, картинка таблицы или файл, текст ответа становится подписью
+    /// к последнему из них, а сообщение о ходе запроса удаляется.
+    pub async fn send(&self, progress: Progress<S>, response: &QueryResponse) -> ResponseResult<()> {
+        remember_response(self.state, self.user_id, response).await;
+
+        // Текстовый ответ (обычный вопрос) отправляется без данных и кнопок
+        if let Some(text_response) = &response.text_response {
+            self.state.answered_questions
+                .insert(self.chat_id, progress.message_id(), &response.question)
+                .await;
+            return progress.finish_backend_text(self.state, text_response).await;
+        }
+
+        let settings = self.state.storage.settings(self.user_id).await;
+        let lang = self.state.ui_language(self.user_id, None).await;
+        // Суммы в валюте пользователя (`/currency`); запомненный ответ для `/export` остается в тенге
+        let rate = self.state.currency.rate(settings.currency).await;
+        let converted = rate.map(|rate| convert_response(response, rate, lang));
+        let response = converted.as_ref().unwrap_or(response);
+        // Время из данных (бэкенд отдает его в UTC) - в поясе пользователя, если он его выбрал
+        let localized = settings.time_zone.and_then(|tz| convert_timestamps(response, tz, lang));
+        let response = localized.as_ref().unwrap_or(response);
+        // Администратор с `/debug on` видит, из чего сложилось время ответа
+        let debug = settings.debug
+            .then(crate::correlation::breakdown)
+            .flatten()
+            .map(|breakdown| breakdown.render(response.execution_time_ms, response.cached));
+        let notes: Vec<String> = rate.map(|rate| rate.note(lang)).into_iter()
+            .chain(settings.time_zone.filter(|_| localized.is_some()).map(crate::timezone::note))
+            .chain(debug)
+            .collect();
+        let note = Some(notes.join("\n")).filter(|note| !note.is_empty());
+        if self.output_type == OutputType::Json && !response.data.is_empty() {
+            return self.send_json(progress, response, &settings, lang, note.as_deref()).await;
+        }
+
+        // Пустой результат часто из-за опечатки в названии: предлагаем исправленные варианты вопроса
+        let corrections = if response.data.is_empty() {
+            self.corrections(&response.question).await
+        } else {
+            Vec::new()
+        };
+
+        // Широкую таблицу на телефоне моноширинным текстом не прочитать: она отправляется
+        // картинкой, а все строки - в CSV
+        let wide_table = response.row_count > 1 && crate::table_image::is_wide(&response.data);
+
+        // Формат, о котором попросили в вопросе, иначе CSV по настройке пользователя
+        let export = match self.export {
+            Some(format) => Some(format),
+            None => (wide_table || settings.csv_attachment.applies_to(response.data.len()))
+                .then_some(ExportFormat::Csv),
+        }
+        .filter(|_| !response.data.is_empty());
+
+        // Подпись достается последнему из отправляемых вложений
+        let keyboard = self.keyboard(response, &settings, &corrections);
+        let placement = if wide_table { DataPlacement::Image } else { DataPlacement::Text };
+        // Текст ответа со свернутыми выводами анализа и, если часть выводов свернута, со всеми выводами
+        let answer_texts = |placement: DataPlacement| {
+            let text = |expanded| {
+                let formatted = self.format(response, &settings, placement, expanded, lang, &corrections);
+                progress.mark_updated(with_note(formatted, note.as_deref()))
+            };
+            let formatted = text(false);
+            let all_insights = Some(text(true))
+                .filter(|all_insights| *all_insights != formatted && fits_in_message(all_insights));
+            (formatted, all_insights)
+        };
+        let (formatted, all_insights) = answer_texts(placement);
+        // Ответ с анализом можно свернуть до заголовка и главного вывода; длинный ответ
+        // не поместился бы обратно в сообщение при переключении на полную версию
+        let brief = brief_answer(response, lang)
+            .filter(|_| fits_in_message(&formatted))
+            .map(|brief| progress.mark_updated(brief));
+        let version_buttons: Vec<_> = brief.iter().map(|_| toggle_button(false))
+            .chain(all_insights.iter().map(|_| expand_button()))
+            .collect();
+        let keyboard = if version_buttons.is_empty() {
+            keyboard
+        } else {
+            append_keyboard_row(keyboard, version_buttons)
+        };
+        // В таблице длинные значения обрезаны: «🔎 1…5» показывают строку целиком
+        let row_buttons = crate::paging::row_buttons(&response.data);
+        let keyboard = if row_buttons.is_empty() { keyboard } else { append_keyboard_row(keyboard, row_buttons.clone()) };
+        // Короткую таблицу в тексте ответа можно отсортировать и сократить до «Топ N»;
+        // длинная листается с этими кнопками в отдельном сообщении, широкая - на картинке
+        let view_rows = if wide_table || crate::paging::is_paginated(response) {
+            Vec::new()
+        } else {
+            crate::paging::view_rows(&response.data, crate::paging::View::default())
+        };
+        let with_view = !view_rows.is_empty();
+        let keyboard = view_rows.into_iter().fold(keyboard, append_keyboard_row);
+        // Сумму, среднее и количество по колонкам «🧮 Посчитать» считает сам бот
+        let with_frame = !crate::utils::frame::quick_actions(&response.data).is_empty();
+        let keyboard = if with_frame {
+            append_keyboard_row(keyboard, vec![InlineKeyboardButton::callback("🧮 Посчитать", "frame:menu")])
+        } else {
+            keyboard
+        };
+        // Строки ответа нужны кнопкам «🔎», сортировки и «🧮» после отправки
+        let with_rows = !row_buttons.is_empty() || with_view || with_frame;
+        let mut caption = Caption::for_answer(&formatted, keyboard.clone());
+        let table_caption = if wide_table { caption.take() } else { None };
+        let chart_caption = if response.chart_data.is_some() { caption.take() } else { None };
+        let export_caption = if export.is_some() { caption.take() } else { None };
+
+        let mut captioned = None;
+        let mut attachments = Vec::new();
+        if let Some(format) = export {
+            let with_caption = export_caption.is_some();
+            let sent = send_export(self.bot, self.chat_id, self.state, format, &response.data, &settings, export_caption).await?;
+            attachments.extend(sent);
+            captioned = sent.filter(|_| with_caption);
+        }
+
+        if let Some(chart_data) = &response.chart_data {
+            progress.stage(Stage::DrawingChart).await;
+            let with_caption = chart_caption.is_some();
+            let sent = send_chart(self.bot, self.chat_id, self.state, chart_data, lang, &settings, chart_caption).await;
+            attachments.extend(sent);
+            captioned = captioned.or(sent.filter(|_| with_caption));
+        }
+
+        let mut table_sent = false;
+        if wide_table {
+            let with_caption = table_caption.is_some();
+            let sent = send_table_image(self.bot, self.chat_id, self.state, &response.data, lang, table_caption).await;
+            attachments.extend(sent);
+            table_sent = sent.is_some();
+            captioned = captioned.or(sent.filter(|_| with_caption));
+        }
+
+        // Вложения заменяются вместе с ответом, если вопрос исправят
+        for message_id in attachments {
+            self.state.answer_messages.add(self.chat_id, progress.question(), message_id).await;
+        }
+
+        match captioned {
+            Some(message_id) => {
+                self.state.answered_questions.insert(self.chat_id, message_id, &response.question).await;
+                progress.dismiss().await;
+                if with_rows {
+                    self.state.result_pages.insert(self.chat_id, message_id, response.data.clone()).await;
+                }
+                if brief.is_some() || all_insights.is_some() {
+                    let versions = Versions { full: formatted, brief, all_insights };
+                    self.state.answer_versions.insert(self.chat_id, message_id, versions).await;
+                }
+            }
+            None => {
+                // Подпись не ушла (вложение не отправилось): ответ - отдельным сообщением,
+                // с таблицей текстом, если ее картинка не отправилась
+                self.state.answered_questions
+                    .insert(self.chat_id, progress.message_id(), &response.question)
+                    .await;
+                let placement = if table_sent { DataPlacement::Image } else { DataPlacement::Text };
+                let (formatted, all_insights) = answer_texts(placement);
+                let message_id = progress.finish(self.state, &formatted, keyboard).await?;
+                if with_rows {
+                    self.state.result_pages.insert(self.chat_id, message_id, response.data.clone()).await;
+                }
+                if brief.is_some() || all_insights.is_some() {
+                    let versions = Versions { full: formatted, brief, all_insights };
+                    self.state.answer_versions.insert(self.chat_id, message_id, versions).await;
+                }
+            }
+        }
+        if table_sent {
+            return Ok(());
+        }
+        send_result_pages(self.bot, self.chat_id, self.state, response, lang).await
+    }
+
+    /// Ответ в JSON (`OutputType::Json`): данные с отступами блоком кода в тексте ответа,
+    /// а если они длинные - файлом `.json` с ответом в подписи. Диаграмма и таблица не отправляются.
+    async fn send_json(
+        &self,
+        progress: Progress<S>,
+        response: &QueryResponse,
+        settings: &UserSettings,
+        lang: Language,
+        note: Option<&str>,
+    ) -> ResponseResult<()> {
+        let keyboard = self.keyboard(response, settings, &[]);
+        let json = serde_json::to_string_pretty(&response.data).unwrap_or_default();
+        if json.chars().count() <= MAX_INLINE_JSON_CHARS {
+            let formatted = progress.mark_updated(with_note(self.format(response, settings, DataPlacement::Json(Some(&json)), true, lang, &[]), note));
+            if fits_in_message(&formatted) {
+                self.state.answered_questions
+                    .insert(self.chat_id, progress.message_id(), &response.question)
+                    .await;
+                progress.finish(self.state, &formatted, keyboard).await?;
+                return Ok(());
+            }
+        }
+
+        let formatted = progress.mark_updated(with_note(self.format(response, settings, DataPlacement::Json(None), true, lang, &[]), note));
+        let caption = Caption::for_answer(&formatted, keyboard.clone());
+        let with_caption = caption.is_some();
+        let sent = send_export(self.bot, self.chat_id, self.state, ExportFormat::Json, &response.data, settings, caption).await?;
+        if let Some(message_id) = sent {
+            self.state.answer_messages.add(self.chat_id, progress.question(), message_id).await;
+        }
+        match sent.filter(|_| with_caption) {
+            Some(message_id) => {
+                self.state.answered_questions.insert(self.chat_id, message_id, &response.question).await;
+                progress.dismiss().await;
+                Ok(())
+            }
+            None => {
+                self.state.answered_questions
+                    .insert(self.chat_id, progress.message_id(), &response.question)
+                    .await;
+                progress.finish(self.state, &formatted, keyboard).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Исправленные варианты вопроса без результата: от бэкенда (`/api/suggest`), а если он
+    /// их не дал - по известным названиям городов и банков
+    async fn corrections(&self, question: &str) -> Vec<String> {
+        let suggested = match self.state.api(self.user_id).await.suggest(question, self.user_id).await {
+            Ok(suggestions) => suggestions,
+            Err(e) => {
+                tracing::debug!("Backend suggestions unavailable: {}", e);
+                Vec::new()
+            }
+        };
+        let mut corrections = if suggested.is_empty() { crate::spelling::corrections(question) } else { suggested };
+        corrections.retain(|correction| !correction.trim().is_empty() && correction.trim() != question.trim());
+        corrections.truncate(MAX_CORRECTIONS);
+        corrections
+    }
+
+    /// Текст ответа с выводами анализа по настройке пользователя (`expanded` - все, иначе
+    /// после `MAX_COLLAPSED_INSIGHTS` свернуты); SQL добавляется, если он включен в настройках
+    fn format(
+        &self,
+        response: &QueryResponse,
+        settings: &UserSettings,
+        placement: DataPlacement,
+        expanded: bool,
+        lang: Language,
+        corrections: &[String],
+    ) -> String {
+        let insights = InsightOptions { min_level: settings.insight_level, expanded };
+        let mut formatted = format_query_response_with(response, placement, insights, lang);
+        if !corrections.is_empty() {
+            formatted.push_str("\n\n");
+            formatted.push_str(tr(lang, Msg::DidYouMean));
+            for correction in corrections {
+                formatted.push_str(&format!("\n• <i>{}</i>", escape_html(correction)));
+            }
+        }
+        if !response.sql.is_empty() && settings.show_sql {
+            formatted.push_str("\n\n");
+            formatted.push_str(&format_sql(&response.sql));
+        }
+        formatted
+    }
+
+    /// Исправленные варианты вопроса, подсказки бэкенда (или стандартные вопросы, если есть данные),
+    /// выгрузка и переход в веб-интерфейс
+    fn keyboard(&self, response: &QueryResponse, settings: &UserSettings, corrections: &[String]) -> Option<ReplyMarkup> {
+        let suggestions = Some(corrections.to_vec())
+            .filter(|questions| !questions.is_empty())
+            .or_else(|| response.analysis.as_ref().map(|analysis| analysis.suggested_questions.clone()))
+            .filter(|questions| !questions.is_empty())
+            .or_else(|| {
+                (!response.data.is_empty() && response.row_count > 0).then(|| vec![
+                    "📊 Показать больше данных".to_string(),
+                    "📈 С анализом".to_string(),
+                ])
+            });
+        let keyboard = suggestions.map(|questions| create_suggestions_keyboard(&questions, &self.state.suggestions));
+        let keyboard = crate::exports::attach_export_buttons(
+            keyboard,
+            !response.data.is_empty(),
+            self.state.pdf_font.is_some(),
+        );
+        let keyboard = attach_handoff_button(self.state.handoff.as_ref(), self.user_id, response, keyboard);
+        // Кнопки «🔎» под агрегатом по городам, мерчантам и т. п. задают уточняющий вопрос по строке
+        let drilldown = self.state.drilldown_template.as_deref()
+            .map(|template| crate::drilldown::drilldown_rows(template, response, &self.state.suggestions))
+            .unwrap_or_default();
+        let keyboard = drilldown.into_iter().fold(keyboard, append_keyboard_row);
+        if response.sql.is_empty() || settings.show_sql {
+            return keyboard;
+        }
+        append_keyboard_row(keyboard, vec![InlineKeyboardButton::callback(
+            "🔍 Показать SQL",
+            format!("showsql:{}", self.state.sql_queries.insert(&response.sql)),
+        )])
+    }
+}
+
+/// Текст ответа с пометками о пересчете сумм в другую валюту и времени в поясе пользователя
+fn with_note(mut formatted: String, note: Option<&str>) -> String {
+    if let Some(note) = note {
+        formatted.push_str("\n\n");
+        formatted.push_str(note);
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Path, State};
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    const USER_ID: &str = "42";
+
+    /// Запросы к Bot API, которые получил тестовый сервер: метод и тело
+    type Calls = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// Поддельный Bot API: запоминает вызовы и отвечает так, будто они выполнены
+    async fn telegram_api(
+        State(calls): State<Calls>,
+        Path((_, method)): Path<(String, String)>,
+        body: axum::body::Bytes,
+    ) -> Json<Value> {
+        // teloxide пишет методы с заглавной буквы (`SendMessage`), Bot API к регистру не чувствителен
+        let method = method[..1].to_lowercase() + &method[1..];
+        let result = match method.as_str() {
+            "sendMessage" | "editMessageText" | "sendPhoto" | "sendDocument" => {
+                let mut calls = calls.lock().unwrap();
+                calls.push((method, String::from_utf8_lossy(&body).into_owned()));
+                json!({
+                    "message_id": 100 + calls.len(),
+                    "date": 0,
+                    "chat": {"id": 42, "type": "private", "first_name": "Test"},
+                    "text": "ok",
+                })
+            }
+            _ => {
+                calls.lock().unwrap().push((method, String::from_utf8_lossy(&body).into_owned()));
+                json!(true)
+            }
+        };
+        Json(json!({"ok": true, "result": result}))
+    }
+
+    struct Harness {
+        bot: Bot,
+        state: BotState,
+        calls: Calls,
+        msg: Message,
+    }
+
+    impl Harness {
+        async fn new(name: &str) -> Self {
+            let calls = Calls::default();
+            let app = Router::new()
+                .route("/:token/:method", post(telegram_api))
+                .with_state(calls.clone());
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+
+            let bot = Bot::new("123:TEST").set_api_url(format!("http://{}", addr).parse().unwrap());
+            // Бэкенд недоступен: ответы в этих тестах уже получены
+            let state = BotState::for_tests(&format!("sender_{}", name), "http://127.0.0.1:9").await;
+            let msg = serde_json::from_value(json!({
+                "message_id": 1,
+                "date": 0,
+                "chat": {"id": 42, "type": "private", "first_name": "Test"},
+                "from": {"id": 42, "is_bot": false, "first_name": "Test"},
+                "text": "вопрос",
+            }))
+            .unwrap();
+
+            Self { bot, state, calls, msg }
+        }
+
+        async fn send(&self, response: Value, export: Option<ExportFormat>) -> Vec<(String, String)> {
+            let response: QueryResponse = serde_json::from_value(response).unwrap();
+            let progress = Progress::start(&self.bot, &self.msg, &self.state, crate::language::Language::Ru)
+                .await
+                .unwrap();
+            ResponseSender::new(&self.bot, &self.state, self.msg.chat.id, USER_ID)
+                .with_export(export)
+                .send(progress, &response)
+                .await
+                .unwrap();
+
+            // Сообщение о ходе запроса, отправленное до ответа, не интересно
+            self.calls.lock().unwrap().drain(..).skip(1).collect()
+        }
+    }
+
+    fn methods(calls: &[(String, String)]) -> Vec<&str> {
+        calls.iter().map(|(method, _)| method.as_str()).collect()
+    }
+
+    fn response(extra: Value) -> Value {
+        let mut response = json!({
+            "question": "вопрос",
+            "data": [],
+            "execution_time_ms": 5,
+            "row_count": 0,
+        });
+        response.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        response
+    }
+
+    fn rows(count: usize) -> Value {
+        (0..count).map(|i| json!({"city": format!("Город {}", i), "amount": i * 100})).collect()
+    }
+
+    #[tokio::test]
+    async fn text_answer_replaces_progress_message() {
+        let harness = Harness::new("text").await;
+        let calls = harness.send(response(json!({"text_response": "Итого: 5 < 10"})), Some(ExportFormat::Csv)).await;
+
+        assert_eq!(methods(&calls), ["editMessageText"]);
+        assert!(calls[0].1.contains("Итого: 5 &lt; 10"), "{}", calls[0].1);
+        assert!(!calls[0].1.contains("reply_markup"));
+    }
+
+    #[tokio::test]
+    async fn data_answer_gets_suggestions_and_export_buttons() {
+        let harness = Harness::new("data").await;
+        let calls = harness.send(response(json!({"data": rows(3), "row_count": 3})), None).await;
+
+        assert_eq!(methods(&calls), ["editMessageText"]);
+        let body = &calls[0].1;
+        assert!(body.contains("Показать больше данных"), "{}", body);
+        assert!(body.contains("export:csv"), "{}", body);
+        assert!(body.contains("🔎 Город 0"), "drill-down buttons are missing: {}", body);
+        assert!(body.contains("row:2") && !body.contains("row:3"), "row buttons are missing: {}", body);
+        assert!(body.contains("frame:menu"), "{}", body);
+        // Короткая таблица сортируется кнопками под ответом, числовая колонка - первой
+        assert!(body.contains("↕️ amount") && body.contains("view:sort:0"), "sort buttons are missing: {}", body);
+        // Ответом стало сообщение о ходе запроса - первое отправленное
+        let answer = teloxide::types::MessageId(101);
+        assert_eq!(harness.state.result_pages.get(harness.msg.chat.id, answer).await.unwrap().len(), 3);
+        assert!(harness.state.last_results.get(USER_ID).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn backend_suggestions_replace_default_ones() {
+        let harness = Harness::new("suggestions").await;
+        let calls = harness.send(response(json!({
+            "data": rows(2),
+            "row_count": 2,
+            "analysis": {
+                "headline": "Рост",
+                "insights": [],
+                "explanation": "",
+                "suggested_questions": ["Сравни с прошлой неделей"],
+            },
+        })), None).await;
+
+        let body = &calls.last().unwrap().1;
+        assert!(body.contains("Сравни с прошлой неделей"), "{}", body);
+        assert!(!body.contains("Показать больше данных"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn requested_export_carries_answer_as_caption() {
+        let harness = Harness::new("export").await;
+        let calls = harness.send(response(json!({
+            "data": rows(2),
+            "row_count": 2,
+            "table": "Город 0 | 0",
+        })), Some(ExportFormat::Csv)).await;
+
+        assert_eq!(methods(&calls), ["sendDocument", "deleteMessage"]);
+        assert!(calls[0].1.contains("Результаты (2)"), "answer is not in the caption");
+        assert!(calls[0].1.contains("export:csv"), "answer buttons are missing");
+    }
+
+    #[tokio::test]
+    async fn long_caption_is_truncated() {
+        let harness = Harness::new("long_caption").await;
+        let calls = harness.send(response(json!({
+            "data": rows(2),
+            "row_count": 2,
+            "table": "Город 0 | 0",
+            "analysis": {
+                "headline": "Рост ".repeat(400),
+                "insights": [],
+                "explanation": "",
+                "suggested_questions": [],
+            },
+        })), Some(ExportFormat::Csv)).await;
+
+        assert_eq!(methods(&calls), ["sendDocument", "deleteMessage"]);
+        assert!(calls[0].1.contains('…'), "caption is not truncated");
+        assert!(!calls[0].1.contains("Результаты (2)"), "caption is not truncated");
+    }
+
+    #[tokio::test]
+    async fn csv_is_attached_by_user_setting() {
+        let harness = Harness::new("csv_setting").await;
+        harness.state.storage
+            .update_user(USER_ID, |user| user.settings.csv_attachment = crate::exports::CsvAttachment::AboveRows(2))
+            .await
+            .unwrap();
+
+        let calls = harness.send(response(json!({"data": rows(2), "row_count": 2})), None).await;
+        assert_eq!(methods(&calls), ["editMessageText"]);
+
+        let calls = harness.send(response(json!({"data": rows(3), "row_count": 3})), None).await;
+        assert_eq!(methods(&calls), ["sendDocument", "deleteMessage"]);
+    }
+
+    #[tokio::test]
+    async fn amounts_are_converted_to_user_currency() {
+        let harness = Harness::new("currency").await;
+        harness.state.storage
+            .update_user(USER_ID, |user| user.settings.currency = crate::currency::Currency::Usd)
+            .await
+            .unwrap();
+
+        let calls = harness.send(response(json!({"data": rows(2), "row_count": 2, "table": "Город 0 | 0"})), None).await;
+        let body = &calls[0].1;
+        assert!(body.contains("amount_usd, $"), "{}", body);
+        assert!(body.contains("Суммы пересчитаны в USD"), "conversion note is missing: {}", body);
+        // Для `/export` запоминается ответ в тенге
+        let remembered = harness.state.last_results.get(USER_ID).await.unwrap();
+        assert!(remembered.data[0].get("amount").is_some());
+    }
+
+    #[tokio::test]
+    async fn wide_table_is_sent_as_image_with_csv() {
+        let harness = Harness::new("wide_table").await;
+        let data: Value = (0..12)
+            .map(|i| json!({"city": "Алматы", "merchant": format!("ТОО {}", i), "amount": i, "count": 1, "status": "ok"}))
+            .collect();
+        let calls = harness.send(response(json!({"data": data, "row_count": 12})), None).await;
+
+        // Ответ - подписью к картинке таблицы, CSV - с обычной подписью
+        assert_eq!(methods(&calls), ["sendDocument", "sendPhoto", "deleteMessage"]);
+        assert!(!calls[0].1.contains("export:csv"), "answer buttons are attached to the CSV");
+        assert!(calls[1].1.contains("на картинке"), "answer is not in the table caption");
+    }
+
+    #[tokio::test]
+    async fn sql_is_behind_button_unless_enabled() {
+        let harness = Harness::new("sql").await;
+        let answer = || response(json!({"data": rows(1), "row_count": 1, "sql": "SELECT city FROM t WHERE amount > 0"}));
+
+        let calls = harness.send(answer(), None).await;
+        assert!(calls[0].1.contains("showsql:"), "{}", calls[0].1);
+        assert!(!calls[0].1.contains("SELECT city"), "{}", calls[0].1);
+
+        harness.state.storage
+            .update_user(USER_ID, |user| user.settings.show_sql = true)
+            .await
+            .unwrap();
+        let calls = harness.send(answer(), None).await;
+        assert!(calls[0].1.contains("SELECT city FROM t WHERE amount &gt; 0"), "{}", calls[0].1);
+        assert!(!calls[0].1.contains("showsql:"), "{}", calls[0].1);
+    }
+
+    #[tokio::test]
+    async fn chart_carries_answer_as_caption() {
+        let harness = Harness::new("chart").await;
+        let calls = harness.send(response(json!({
+            "data": rows(2),
+            "row_count": 2,
+            "chart_data": {
+                "chart_type": "bar",
+                "labels": ["Алматы", "Астана"],
+                "datasets": [{"label": "Объем", "data": [10.0, 20.0]}],
+            },
+        })), None).await;
+
+        // Этап «рисую диаграмму», затем диаграмма с ответом в подписи
+        assert_eq!(methods(&calls), ["editMessageText", "sendPhoto", "deleteMessage"]);
+        assert!(calls[1].1.contains("chart:"), "chart type buttons are missing");
+        assert!(calls[1].1.contains("Показать больше данных"), "answer buttons are missing");
+    }
+
+    #[tokio::test]
+    async fn long_answer_is_sent_in_parts() {
+        let harness = Harness::new("long").await;
+        let text = "строка ответа\n".repeat(500);
+        let calls = harness.send(response(json!({"text_response": text})), None).await;
+
+        assert_eq!(methods(&calls)[0], "deleteMessage");
+        assert!(calls[1..].iter().all(|(method, _)| method == "sendMessage"));
+        assert!(calls.len() > 2);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::storage::Storage;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Диалог с `/api/chat`, который бэкенд продолжает по `session_id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSession {
+    pub id: String,
+    pub last_used: DateTime<Utc>,
+}
+
+/// Хранит `session_id` диалога для каждого ключа контекста (см. `CONTEXT_SCOPE`).
+/// Сессии, которыми давно не пользовались, считаются завершенными.
+pub struct ChatSessions {
+    storage: Arc<Storage>,
+    ttl: Duration,
+}
+
+impl ChatSessions {
+    pub fn new(storage: Arc<Storage>, ttl_mins: u32) -> Self {
+        Self {
+            storage,
+            ttl: Duration::minutes(i64::from(ttl_mins)),
+        }
+    }
+
+    /// Текущая сессия, если она еще не устарела
+    pub async fn current(&self, key: &str) -> Option<String> {
+        let session = self.storage.user(key).await?.chat_session?;
+        (Utc::now() - session.last_used < self.ttl).then_some(session.id)
+    }
+
+    /// Запоминает сессию из ответа бэкенда (ошибки только логируются)
+    pub async fn remember(&self, key: &str, session_id: Option<&str>) {
+        let Some(session_id) = session_id else {
+            return;
+        };
+        let session = ChatSession {
+            id: session_id.to_string(),
+            last_used: Utc::now(),
+        };
+        if let Err(e) = self.storage.update_user(key, |user| user.chat_session = Some(session)).await {
+            tracing::error!("Failed to store chat session for {}: {}", key, e);
+        }
+    }
+
+    /// Забывает сессию (например, по `/clear`)
+    pub async fn reset(&self, key: &str) {
+        let has_session = self.storage.user(key).await.is_some_and(|user| user.chat_session.is_some());
+        if !has_session {
+            return;
+        }
+        if let Err(e) = self.storage.update_user(key, |user| user.chat_session = None).await {
+            tracing::error!("Failed to reset chat session for {}: {}", key, e);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use teloxide::dispatching::ShutdownToken;
+use teloxide::prelude::*;
+use teloxide::types::MessageId;
+use tracing::{info, warn};
+
+/// Сообщения «Обрабатываю запрос...», ответ на которые еще не отправлен.
+/// При остановке бот дожидается этих запросов, а оставшиеся по истечении срока удаляет.
+#[derive(Default)]
+pub struct InFlight {
+    messages: Mutex<HashMap<u64, (ChatId, MessageId)>>,
+    next_id: AtomicU64,
+}
+
+impl InFlight {
+    /// Регистрирует сообщение об обработке; запись удаляется, когда гард выходит из области видимости
+    pub fn track(self: &Arc<Self>, chat_id: ChatId, message_id: MessageId) -> InFlightGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.lock().insert(id, (chat_id, message_id));
+        InFlightGuard {
+            registry: Arc::clone(self),
+            id,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Удаляет из чатов сообщения об обработке, на которые ответ так и не пришел
+    async fn delete_dangling(&self, bot: &Bot) -> usize {
+        let messages: Vec<_> = self.lock().drain().map(|(_, message)| message).collect();
+        for (chat_id, message_id) in &messages {
+            let _ = bot.delete_message(*chat_id, *message_id).await;
+        }
+        messages.len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, (ChatId, MessageId)>> {
+        self.messages.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+pub struct InFlightGuard {
+    registry: Arc<InFlight>,
+    id: u64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.registry.lock().remove(&self.id);
+    }
+}
+
+/// Ждет SIGINT/SIGTERM, останавливает прием обновлений и дает обработчикам `deadline`
+/// на завершение. Если не успели - удаляет зависшие сообщения и завершает процесс.
+pub fn spawn_signal_handler(bot: Bot, token: ShutdownToken, in_flight: Arc<InFlight>, deadline: Duration) {
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        let started = Instant::now();
+        info!(
+            "Shutdown requested: no longer accepting updates, waiting up to {}s for {} in-flight queries",
+            deadline.as_secs(),
+            in_flight.len()
+        );
+        if token.shutdown().is_err() {
+            warn!("Dispatcher is not running, exiting immediately");
+            std::process::exit(0);
+        }
+
+        tokio::time::sleep(deadline).await;
+        let abandoned = in_flight.delete_dangling(&bot).await;
+        warn!(
+            "Shutdown deadline of {}s exceeded after {:.1}s: abandoned {} in-flight queries",
+            deadline.as_secs(),
+            started.elapsed().as_secs_f64(),
+            abandoned
+        );
+        std::process::exit(1);
+    });
+}
+
+/// Вызывается после остановки диспетчера: все обработчики завершились вовремя
+pub async fn finish(bot: &Bot, in_flight: &InFlight) {
+    let dangling = in_flight.delete_dangling(bot).await;
+    info!("Shutdown complete: all handlers finished, {} dangling processing messages removed", dangling);
+}
+
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("Failed to listen for SIGTERM: {}", e),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
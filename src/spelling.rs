@@ -0,0 +1,97 @@
+use crate::utils::{edit_distance, words};
+
+/// Названия городов и банков, как они записаны в базе (на латинице). Слова вопроса,
+/// похожие на них с точностью до опечатки, исправляются, если запрос ничего не нашел.
+const KNOWN_NAMES: &[&str] = &[
+    // Города
+    "Almaty", "Astana", "Shymkent", "Karaganda", "Aktobe", "Taraz", "Pavlodar", "Oskemen", "Semey", "Atyrau",
+    "Kostanay", "Kyzylorda", "Oral", "Petropavl", "Aktau", "Temirtau", "Turkistan", "Taldykorgan", "Ekibastuz",
+    "Kokshetau", "Zhezkazgan", "Balkhash", "Konaev",
+    // Банки (без слова Bank)
+    "Halyk", "Kaspi", "ForteBank", "Jusan", "CenterCredit", "Eurasian", "Freedom", "Bereke", "Otbasy", "Nurbank",
+    "Altyn", "Shinhan", "Citibank",
+];
+
+/// Слова короче не исправляются: у коротких слов слишком много случайных «соседей»
+const MIN_WORD_CHARS: usize = 4;
+
+/// Больше вариантов исправления не предлагается
+const MAX_VARIANTS: usize = 3;
+
+/// Сколько опечаток допускается в слове такой длины
+fn max_distance(chars: usize) -> usize {
+    if chars <= 5 { 1 } else { 2 }
+}
+
+/// Ближайшие известные названия для слова; пусто, если слово известно или ни на что не похоже
+fn candidates(word: &str) -> Vec<&'static str> {
+    let chars = word.chars().count();
+    let lower = word.to_lowercase();
+    if chars < MIN_WORD_CHARS || KNOWN_NAMES.iter().any(|name| name.to_lowercase() == lower) {
+        return Vec::new();
+    }
+
+    // Первую букву опечатка почти никогда не задевает, а без этого условия похожими оказываются обычные слова
+    let first = lower.chars().next();
+    let scored: Vec<(usize, &'static str)> = KNOWN_NAMES
+        .iter()
+        .map(|name| (name.to_lowercase(), *name))
+        .filter(|(known, _)| known.chars().next() == first)
+        .map(|(known, name)| (edit_distance(&lower, &known), name))
+        .filter(|(distance, _)| *distance <= max_distance(chars))
+        .collect();
+    let Some(best) = scored.iter().map(|(distance, _)| *distance).min() else {
+        return Vec::new();
+    };
+    scored.into_iter().filter(|(distance, _)| *distance == best).map(|(_, name)| name).collect()
+}
+
+/// Варианты вопроса с исправленными названиями городов и банков («Almati» → «Almaty»).
+/// Первый вариант исправляет все слова ближайшими названиями; если для слова есть несколько
+/// одинаково близких названий, следующие варианты берут другие из них.
+pub fn corrections(question: &str) -> Vec<String> {
+    let fixes: Vec<(usize, &str, Vec<&str>)> = words(question)
+        .into_iter()
+        .map(|(start, word)| (start, word, candidates(word)))
+        .filter(|(_, _, candidates)| !candidates.is_empty())
+        .collect();
+    if fixes.is_empty() {
+        return Vec::new();
+    }
+
+    let widest = fixes.iter().map(|(_, _, candidates)| candidates.len()).max().unwrap_or(1);
+    (0..widest.min(MAX_VARIANTS))
+        .map(|variant| {
+            let mut corrected = String::with_capacity(question.len());
+            let mut position = 0;
+            for (start, word, candidates) in &fixes {
+                corrected.push_str(&question[position..*start]);
+                corrected.push_str(candidates.get(variant).unwrap_or(&candidates[0]));
+                position = start + word.len();
+            }
+            corrected.push_str(&question[position..]);
+            corrected
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typos_in_known_names_are_corrected() {
+        assert_eq!(corrections("Топ мерчантов в Almati за май"), vec!["Топ мерчантов в Almaty за май"]);
+        assert_eq!(
+            corrections("sql: Halik vs Kaspy по городу Shymkend"),
+            vec!["sql: Halyk vs Kaspi по городу Shymkent"]
+        );
+    }
+
+    #[test]
+    fn correct_and_unrelated_words_are_left_alone() {
+        assert!(corrections("Оборот в Almaty и astana").is_empty());
+        assert!(corrections("Top merchants by volume this month").is_empty());
+        assert!(corrections("Топ 10 городов").is_empty());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::acl::AccessControl;
+use crate::api_client::BackendClient;
+use crate::auth::Credentials;
+use crate::backends::Backends;
+use crate::brief::AnswerVersions;
+use crate::charts::{ChartCache, ChartRenderer};
+use crate::config::{ContextScope, TextFormat};
+use crate::currency::CurrencyConverter;
+use crate::dedup::RunningQueries;
+use crate::dialogue::{ChatDialogue, ChatStorage};
+use crate::estimate::PendingQueries;
+use crate::exports::LastResults;
+use crate::followup::{AnswerMessages, AnsweredQuestions};
+use crate::handoff::HandoffSigner;
+use crate::inline::HeadlineCache;
+use crate::monitor::BackendMonitor;
+use crate::object_storage::ObjectStorage;
+use crate::paging::ResultPages;
+use crate::queue::ChatQueues;
+use crate::rate_limit::RateLimiter;
+use crate::sessions::ChatSessions;
+use crate::shutdown::InFlight;
+use crate::storage::Storage;
+use crate::suggestions::SuggestionStore;
+use std::sync::Arc;
+use crate::language::Language;
+use crate::timezone::UserZone;
+use chrono::FixedOffset;
+use teloxide::types::{Message, User};
+
+/// Общее состояние бота, передаваемое во все обработчики
+pub struct BotState {
+    pub acl: AccessControl,
+    /// Основной бэкенд (фоновые проверки, inline-заголовки, пользователи без `/env`)
+    pub api_client: Arc<dyn BackendClient>,
+    /// Все настроенные бэкенды, включая основной
+    pub backends: Backends,
+    pub storage: Arc<Storage>,
+    pub chat_sessions: ChatSessions,
+    /// `None`, если не задан `TOKEN_ENCRYPTION_KEY` (вход по токену отключен)
+    pub credentials: Option<Arc<Credentials>>,
+    /// `None`, если не заданы `WEB_DASHBOARD_URL` и `HANDOFF_SECRET`
+    pub handoff: Option<HandoffSigner>,
+    /// Заранее вычисленные ответы для inline-режима
+    pub headlines: Arc<HeadlineCache>,
+    /// Текущая доступность бэкенда по фоновым проверкам
+    pub monitor: Arc<BackendMonitor>,
+    pub context_scope: ContextScope,
+    /// Тяжелые запросы, ожидающие подтверждения
+    pub pending_queries: PendingQueries,
+    pub rate_limiter: RateLimiter,
+    pub estimate_confirm_rows: u64,
+    /// Больше стольких сообщений ответ отправляется файлом
+    pub max_message_chunks: usize,
+    /// Максимальный размер загружаемого файла для сравнения, байт
+    pub max_upload_bytes: u32,
+    /// Разметка текстовых ответов бэкенда
+    pub text_format: TextFormat,
+    /// Данные последнего ответа для выгрузки в другом формате
+    pub last_results: LastResults,
+    /// Отправленные диаграммы для переключения типа
+    pub charts: ChartCache,
+    pub chart_renderer: ChartRenderer,
+    /// Шрифт PDF-отчетов (`None`, если `PDF_FONT_PATH` не прочитан - отчеты отключены)
+    pub pdf_font: Option<Arc<Vec<u8>>>,
+    pub result_pages: ResultPages,
+    /// Полные и краткие тексты ответов с анализом для кнопок «Кратко / Подробно»
+    pub answer_versions: AnswerVersions,
+    /// Полные тексты длинных подсказок для кнопок `q:<hash>`
+    pub suggestions: SuggestionStore,
+    /// SQL ответов для кнопок «🔍 Показать SQL» (`showsql:<hash>`)
+    pub sql_queries: SuggestionStore,
+    /// Вопросы отправленных ответов, чтобы уточнять их ответом на сообщение
+    pub answered_questions: AnsweredQuestions,
+    /// Сообщения ответов на вопросы, чтобы заменить их, когда вопрос исправят
+    pub answer_messages: AnswerMessages,
+    /// Состояния многошаговых диалогов по чатам (раздел меню, вопросы о параметрах)
+    pub dialogues: Arc<ChatStorage>,
+    /// Запросы, которые нужно дождаться при остановке бота
+    pub in_flight: Arc<InFlight>,
+    /// Выполняющиеся вопросы по чатам, чтобы не запускать дубликаты
+    pub running_queries: Arc<RunningQueries>,
+    /// Очереди запросов к бэкенду по чатам
+    pub chat_queues: ChatQueues,
+    /// Username бота (без @), нужен для deep link
+    pub bot_username: String,
+    /// Часовой пояс, от которого считаются «сегодня» и «вчера» в вопросах
+    pub time_zone: FixedOffset,
+    /// Хранилище для выгрузок больше лимита Telegram (`None` - такие выгрузки не отправляются)
+    pub object_storage: Option<ObjectStorage>,
+    /// Шаблон уточняющего вопроса по строке агрегата (`DRILLDOWN_TEMPLATE`; `None` - без кнопок)
+    pub drilldown_template: Option<String>,
+    /// Курсы для пересчета сумм в валюту пользователя (`/currency`)
+    pub currency: CurrencyConverter,
+}
+
+impl BotState {
+    /// Ключ пользователя для контекста на бэкенде и данных в хранилище
+    pub fn user_key(&self, msg: &Message) -> String {
+        self.context_scope.key(msg.chat.id, msg.from().map(|user| user.id))
+    }
+
+    /// Диалог чата: текущее состояние многошагового взаимодействия
+    pub fn dialogue(&self, chat_id: teloxide::types::ChatId) -> ChatDialogue {
+        crate::dialogue::for_chat(&self.dialogues, chat_id)
+    }
+
+    /// Часовой пояс пользователя (`/timezone`), иначе пояс бота `time_zone`. От него считаются
+    /// «сегодня» и «вчера» в вопросах пользователя.
+    pub async fn user_zone(&self, user_id: &str) -> UserZone {
+        UserZone::new(self.storage.settings(user_id).await.time_zone, self.time_zone)
+    }
+
+    /// Бэкенд, через который идут запросы пользователя: выбранный через `/env`, иначе основной
+    pub async fn api(&self, user_id: &str) -> Arc<dyn BackendClient> {
+        if !self.backends.is_multiple() {
+            return self.api_client.clone();
+        }
+        let selected = self.storage.settings(user_id).await.backend;
+        self.backends.resolve(selected.as_deref()).1.clone()
+    }
+
+    /// Язык интерфейса: выбранный через /language, иначе язык Telegram, иначе русский
+    pub async fn ui_language(&self, user_id: &str, user: Option<&User>) -> Language {
+        if let Some(language) = self.storage.settings(user_id).await.interface_language {
+            return language;
+        }
+        user.and_then(|user| user.language_code.as_deref())
+            .and_then(Language::from_telegram)
+            .unwrap_or(Language::Ru)
+    }
+}
+
+#[cfg(test)]
+impl BotState {
+    /// Состояние для тестов обработчиков с HTTP-бэкендом по адресу `backend_url`
+    pub async fn for_tests(name: &str, backend_url: &str) -> Self {
+        let api_client = crate::api_client::ApiClient::new(
+            backend_url.to_string(),
+            None,
+            &crate::api_client::HttpOptions {
+                timeout: std::time::Duration::from_secs(5),
+                root_certs: Vec::new(),
+                accept_invalid_certs: false,
+            },
+            None,
+            crate::response_cache::ResponseCache::open(None, 0).unwrap(),
+            1,
+            false,
+        ).unwrap();
+        Self::with_backend(name, Arc::new(api_client)).await
+    }
+
+    /// Состояние для тестов обработчиков: бэкенд `api_client` (например, `FakeBackend`),
+    /// хранилище и список доступа во временной папке `name`, остальное - по умолчанию
+    pub async fn with_backend(name: &str, api_client: Arc<dyn BackendClient>) -> Self {
+        let dir = crate::utils::test_dir(name);
+        let storage = Arc::new(Storage::open(dir.join("bot_data.db")).unwrap());
+        Self {
+            acl: AccessControl::open(dir.join("allowlist.json"), &[], &[], &[]).unwrap(),
+            api_client: api_client.clone(),
+            backends: Backends::new("prod".to_string(), api_client),
+            chat_sessions: ChatSessions::new(storage.clone(), 30),
+            storage,
+            credentials: None,
+            handoff: None,
+            headlines: Default::default(),
+            monitor: Default::default(),
+            context_scope: ContextScope::Chat,
+            pending_queries: Default::default(),
+            rate_limiter: RateLimiter::new(0, 0),
+            estimate_confirm_rows: 1_000_000,
+            max_message_chunks: 3,
+            max_upload_bytes: 10 * 1_048_576,
+            text_format: TextFormat::Auto,
+            last_results: Default::default(),
+            charts: Default::default(),
+            chart_renderer: ChartRenderer::new(1, crate::utils::ChartTheme::Light, "test_bot"),
+            pdf_font: None,
+            result_pages: Default::default(),
+            answer_versions: Default::default(),
+            suggestions: Default::default(),
+            sql_queries: Default::default(),
+            answered_questions: Default::default(),
+            answer_messages: Default::default(),
+            in_flight: Default::default(),
+            dialogues: crate::dialogue::open_storage(None).await.unwrap(),
+            running_queries: Default::default(),
+            chat_queues: Default::default(),
+            bot_username: "test_bot".to_string(),
+            time_zone: FixedOffset::east_opt(5 * 3600).unwrap(),
+            object_storage: None,
+            drilldown_template: Some(crate::drilldown::DEFAULT_TEMPLATE.to_string()),
+            currency: CurrencyConverter::new(&[(crate::currency::Currency::Usd, 500.0)], None).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::api_client::{ChartData, ChartDataset};
+use crate::metrics::Summary;
+use crate::utils::escape_html;
+use chrono::{Duration, NaiveDate};
+
+/// За сколько дней строится график запросов по дням
+pub const CHART_DAYS: i64 = 14;
+/// Сколько популярных вопросов показывает `/stats`
+pub const POPULAR_QUESTIONS: usize = 5;
+/// Длинные вопросы в списке популярных обрезаются
+const MAX_QUESTION_CHARS: usize = 80;
+
+/// Активность за период по журналу запросов
+#[derive(Debug, Default)]
+pub struct Activity {
+    pub active_users: u64,
+    pub queries: u64,
+    /// Среднее время выполнения запросов на бэкенде, мс (`None`, если запросов не было)
+    pub avg_execution_ms: Option<f64>,
+}
+
+/// Статистика использования по журналу запросов (см. `Storage::usage_stats`)
+#[derive(Debug, Default)]
+pub struct UsageStats {
+    /// Последние 24 часа
+    pub day: Activity,
+    /// Последние 7 дней
+    pub week: Activity,
+    /// Самые частые вопросы за 7 дней и сколько раз их задавали
+    pub popular: Vec<(String, u64)>,
+    /// Запросы по дням (UTC) за последние `CHART_DAYS` дней; дни без запросов пропущены
+    pub daily: Vec<(NaiveDate, u64)>,
+}
+
+/// HTML-сводка `/stats`: активность по журналу и работа бэкенда с запуска бота
+pub fn render(usage: &UsageStats, summary: &Summary) -> String {
+    let mut text = String::from("📊 <b>Статистика использования</b>\n");
+    for (title, activity) in [("За 24 часа", &usage.day), ("За 7 дней", &usage.week)] {
+        text.push_str(&format!(
+            "\n<b>{}</b>\nАктивных пользователей: {}\nЗапросов: {}\n",
+            title, activity.active_users, activity.queries
+        ));
+        if let Some(avg) = activity.avg_execution_ms {
+            text.push_str(&format!("Среднее время выполнения: {:.0} мс\n", avg));
+        }
+    }
+
+    if !usage.popular.is_empty() {
+        text.push_str("\n<b>Популярные вопросы (7 дней)</b>\n");
+        for (i, (question, count)) in usage.popular.iter().enumerate() {
+            let shown: String = question.chars().take(MAX_QUESTION_CHARS).collect();
+            let ellipsis = if shown.len() < question.len() { "…" } else { "" };
+            text.push_str(&format!("{}. {}{} — {}\n", i + 1, escape_html(&shown), ellipsis, count));
+        }
+    }
+
+    text.push_str(&format!("\n<b>С запуска бота</b>\nЗапросов к бэкенду: {}\n", summary.backend_requests));
+    if summary.backend_requests > 0 {
+        text.push_str(&format!(
+            "Ошибок: {} ({:.1}%)\n",
+            summary.backend_errors,
+            percent(summary.backend_errors, summary.backend_requests)
+        ));
+    }
+    if let Some(latency) = summary.backend_latency {
+        text.push_str(&format!("Средняя задержка бэкенда: {:.2} с\n", latency.as_secs_f64()));
+    }
+    let cache_total = summary.cache_hits + summary.cache_misses;
+    if cache_total > 0 {
+        text.push_str(&format!(
+            "Кэш ответов: {} попаданий из {} ({:.1}%)\n",
+            summary.cache_hits,
+            cache_total,
+            percent(summary.cache_hits, cache_total)
+        ));
+    }
+    text
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    part as f64 * 100.0 / total as f64
+}
+
+/// Линейный график запросов по дням, заканчивающийся днем `today` (`None`, если запросов не было)
+pub fn chart(usage: &UsageStats, today: NaiveDate) -> Option<ChartData> {
+    if usage.daily.is_empty() {
+        return None;
+    }
+    let days: Vec<NaiveDate> = (0..CHART_DAYS).rev().map(|ago| today - Duration::days(ago)).collect();
+    let data = days
+        .iter()
+        .map(|day| {
+            usage.daily.iter()
+                .find(|(date, _)| date == day)
+                .map_or(0.0, |(_, count)| *count as f64)
+        })
+        .collect();
+
+    Some(ChartData {
+        log_scale: false,
+        chart_type: "line".to_string(),
+        labels: days.iter().map(|day| day.format("%d.%m").to_string()).collect(),
+        datasets: vec![ChartDataset {
+            label: "Запросов".to_string(),
+            data,
+            background_color: None,
+        }],
+        title: Some(format!("Запросы по дням за {} дней (UTC)", CHART_DAYS)),
+    })
+}
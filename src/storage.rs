@@ -0,0 +1,1054 @@
+use crate::api_client::OutputType;
+use crate::audit::AuditEntry;
+use crate::exports::{CsvAttachment, CsvDelimiter};
+use crate::language::Language;
+use crate::menu::MenuItem;
+use crate::routing::QueryMode;
+use crate::scheduler::ScheduledReport;
+use crate::sessions::ChatSession;
+use crate::stats::{Activity, UsageStats, CHART_DAYS, POPULAR_QUESTIONS};
+use crate::watcher::Alert;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use tracing::{error, info};
+
+/// Пользовательские настройки
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct UserSettings {
+    /// Язык ответов бэкенда (`None` - по умолчанию бэкенда)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer_language: Option<Language>,
+    /// Язык интерфейса (`/language`; `None` - по языку Telegram)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface_language: Option<Language>,
+    /// Куда по умолчанию отправляются сообщения (`/mode`)
+    #[serde(default)]
+    pub query_mode: QueryMode,
+    /// Когда прикладывать CSV к ответу с данными (`/settings`)
+    #[serde(default)]
+    pub csv_attachment: CsvAttachment,
+    /// Разделитель колонок в CSV (`/settings csvsep`)
+    #[serde(default)]
+    pub csv_delimiter: CsvDelimiter,
+    /// Показывать SQL под каждым ответом, а не по кнопке (`/settings sql on`)
+    #[serde(default)]
+    pub show_sql: bool,
+    /// Подвал с разбивкой времени ответа и id трассы (`/debug on`, только для администраторов)
+    #[serde(default)]
+    pub debug: bool,
+    /// Формат ответа, если в вопросе не попросили другой (`/settings output`)
+    #[serde(default)]
+    pub output_type: OutputType,
+    /// Тема диаграмм (`/settings theme`; `None` - `CHART_THEME`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chart_theme: Option<crate::utils::ChartTheme>,
+    /// Бэкенд, выбранный администратором через `/env` (`None` - основной)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Роль из знакомства при первом `/start` (`None` - знакомство не пройдено)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<crate::onboarding::UserRole>,
+    /// Ответ длиннее стольких символов приходит файлом с кратким сообщением (`/settings long`;
+    /// `None` - если не помещается в `MAX_MESSAGE_CHUNKS` сообщений)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub long_answer_chars: Option<usize>,
+    /// Формат файла с длинным ответом (`/settings longfile`)
+    #[serde(default)]
+    pub long_answer_file: crate::utils::AnswerFile,
+    /// Выводы анализа менее важные не показываются (`/settings insights`)
+    #[serde(default)]
+    pub insight_level: crate::utils::InsightLevel,
+    /// Валюта, в которую пересчитываются суммы в ответах (`/currency`)
+    #[serde(default)]
+    pub currency: crate::currency::Currency,
+    /// Часовой пояс пользователя (`/timezone`); `None` - пояс бота
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<chrono_tz::Tz>,
+}
+
+/// Профиль пользователя, который бот хранит у себя
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct UserRecord {
+    /// Персональный токен бэкенда (зашифрован, см. `auth::Credentials`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_token: Option<String>,
+    /// Аккаунт бэкенда, привязанный через deep link из веб-интерфейса
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linked_account: Option<String>,
+    #[serde(default)]
+    pub settings: UserSettings,
+    /// Текущий диалог с `/api/chat` (см. `sessions`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat_session: Option<ChatSession>,
+}
+
+/// Запрос, сохраненный пользователем под своим названием (`/save`)
+#[derive(Debug, Clone)]
+pub struct SavedQuery {
+    pub id: i64,
+    pub name: String,
+    pub query: String,
+}
+
+/// Шаблон запроса с переменными `{...}` (`/template`)
+#[derive(Debug, Clone)]
+pub struct QueryTemplate {
+    pub id: i64,
+    pub name: String,
+    pub template: String,
+}
+
+/// Миграции схемы по порядку; номер последней примененной хранится в `PRAGMA user_version`.
+/// Выпущенные миграции не меняются - изменение схемы добавляется новой записью в конец.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE users (
+        user_id TEXT PRIMARY KEY,
+        api_token TEXT,
+        linked_account TEXT,
+        interface_language TEXT,
+        answer_language TEXT,
+        preferences TEXT NOT NULL DEFAULT '{}',
+        chat_session TEXT,
+        updated_at TEXT NOT NULL
+    );
+    CREATE TABLE history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id TEXT NOT NULL,
+        timestamp TEXT NOT NULL,
+        question TEXT NOT NULL,
+        headline TEXT,
+        key_numbers TEXT NOT NULL DEFAULT '[]',
+        row_count INTEGER NOT NULL,
+        execution_time_ms INTEGER NOT NULL
+    );
+    CREATE INDEX history_user ON history (user_id, id);
+    CREATE INDEX history_timestamp ON history (timestamp);
+    CREATE TABLE saved_queries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        query TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        UNIQUE (user_id, name)
+    );
+    CREATE TABLE schedules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id INTEGER NOT NULL,
+        user_id TEXT NOT NULL,
+        question TEXT NOT NULL,
+        frequency TEXT NOT NULL,
+        time TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        last_run TEXT
+    );
+    CREATE TABLE bot_settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    "CREATE TABLE alerts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id INTEGER NOT NULL,
+        user_id TEXT NOT NULL,
+        question TEXT NOT NULL,
+        comparison TEXT NOT NULL,
+        threshold REAL NOT NULL,
+        interval_mins INTEGER NOT NULL,
+        created_at TEXT NOT NULL,
+        last_checked TEXT,
+        last_value REAL,
+        triggered INTEGER NOT NULL DEFAULT 0
+    );",
+    "CREATE TABLE subscriptions (
+        chat_id INTEGER NOT NULL,
+        topic TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        PRIMARY KEY (chat_id, topic)
+    );",
+    "CREATE TABLE templates (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        template TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        UNIQUE (user_id, name)
+    );",
+];
+
+/// Таблицы с данными пользователя по колонке `user_id`: их очищает `/forgetme`.
+/// Новая таблица с данными пользователя добавляется сюда или в `CHAT_TABLES`.
+const USER_TABLES: [&str; 6] = ["users", "history", "saved_queries", "templates", "schedules", "alerts"];
+
+/// Таблицы с данными чата по колонке `chat_id` (без `user_id`)
+const CHAT_TABLES: [&str; 1] = ["subscriptions"];
+
+/// Ключ меню в `bot_settings`
+const MENU_KEY: &str = "menu";
+
+/// Хранилище бота во встроенной базе SQLite.
+/// Запросы к базе выполняются в пуле блокирующих задач tokio, чтобы не останавливать обработку обновлений.
+pub struct Storage {
+    connection: Arc<Mutex<Connection>>,
+    /// Изменения «прочитать - изменить - записать» (`update_user`, `update_menu`) выполняются по одному
+    writes: tokio::sync::Mutex<()>,
+}
+
+impl Storage {
+    /// Открывает базу, создавая ее и применяя недостающие миграции.
+    /// Данные из JSON-файла прежних версий бота переносятся в новую базу.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let mut path = path.into();
+        let legacy_path = if path.extension().is_some_and(|extension| extension == "json") {
+            let legacy_path = path.clone();
+            path.set_extension("db");
+            info!("STORAGE_PATH points to a JSON file, using SQLite database {} instead", path.display());
+            legacy_path
+        } else {
+            path.with_extension("json")
+        };
+
+        let mut connection = Connection::open(&path)
+            .with_context(|| format!("Failed to open storage database {}", path.display()))?;
+        connection
+            .pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))
+            .context("Failed to enable WAL journal")?;
+        connection.busy_timeout(std::time::Duration::from_secs(5))?;
+
+        let version = migrate(&mut connection)
+            .with_context(|| format!("Failed to migrate storage database {}", path.display()))?;
+        if version == 0 && legacy_path.exists() {
+            import_legacy(&mut connection, &legacy_path)
+                .with_context(|| format!("Failed to import storage file {}", legacy_path.display()))?;
+            info!("Imported legacy storage {} into {}", legacy_path.display(), path.display());
+        }
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            writes: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Выполняет запрос к базе в блокирующей задаче
+    async fn call<T, F>(&self, query: F) -> Result<T>
+    where
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap_or_else(PoisonError::into_inner);
+            query(&mut connection)
+        })
+            .await
+            .context("Storage task panicked")?
+    }
+
+    pub async fn user(&self, user_id: &str) -> Option<UserRecord> {
+        let key = user_id.to_string();
+        self.call(move |connection| read_user(connection, &key))
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load user {}: {:#}", user_id, e);
+                None
+            })
+    }
+
+    /// Настройки пользователя (по умолчанию, если записи нет)
+    pub async fn settings(&self, user_id: &str) -> UserSettings {
+        self.user(user_id).await.map(|user| user.settings).unwrap_or_default()
+    }
+
+    /// Изменяет профиль пользователя (создает его, если записи нет)
+    pub async fn update_user<F>(&self, user_id: &str, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut UserRecord),
+    {
+        let _write = self.writes.lock().await;
+        let key = user_id.to_string();
+        let mut record = self.call(move |connection| read_user(connection, &key)).await?.unwrap_or_default();
+        update(&mut record);
+        let key = user_id.to_string();
+        self.call(move |connection| write_user(connection, &key, &record)).await
+    }
+
+    /// Последние `limit` записей журнала пользователя, от старых к новым
+    pub async fn history(&self, user_id: &str, limit: usize) -> Vec<AuditEntry> {
+        let key = user_id.to_string();
+        self.call(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT timestamp, question, headline, key_numbers, row_count, execution_time_ms
+                 FROM history WHERE user_id = ?1 ORDER BY id DESC LIMIT ?2",
+            )?;
+            let mut entries = statement
+                .query_map(params![key, limit as i64], read_history_entry)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            entries.reverse();
+            Ok(entries)
+        })
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load history of user {}: {:#}", user_id, e);
+                Vec::new()
+            })
+    }
+
+    /// Добавляет запись в журнал пользователя, оставляя не больше `keep` последних
+    pub async fn add_history(&self, user_id: &str, entry: AuditEntry, keep: usize) -> Result<()> {
+        let key = user_id.to_string();
+        self.call(move |connection| {
+            let transaction = connection.transaction()?;
+            insert_history(&transaction, &key, &entry)?;
+            transaction.execute(
+                "DELETE FROM history WHERE user_id = ?1 AND id NOT IN
+                 (SELECT id FROM history WHERE user_id = ?1 ORDER BY id DESC LIMIT ?2)",
+                params![key, keep as i64],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+            .await
+    }
+
+    /// Удаляет записи журнала старше `cutoff`, возвращает количество удаленных
+    pub async fn purge_history_before(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        self.call(move |connection| {
+            Ok(connection.execute("DELETE FROM history WHERE timestamp < ?1", [cutoff])?)
+        })
+            .await
+    }
+
+    /// Полностью удаляет все данные пользователя
+    pub async fn remove_user(&self, user_id: &str) -> Result<()> {
+        let key = user_id.to_string();
+        self.call(move |connection| {
+            let transaction = connection.transaction()?;
+            for table in USER_TABLES {
+                transaction.execute(&format!("DELETE FROM {} WHERE user_id = ?1", table), [&key])?;
+            }
+            // Подписки хранятся по чату: ключ пользователя в личном чате - id этого чата
+            if let Ok(chat_id) = key.parse::<i64>() {
+                for table in CHAT_TABLES {
+                    transaction.execute(&format!("DELETE FROM {} WHERE chat_id = ?1", table), [chat_id])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+            .await
+    }
+
+    /// Сохраненные запросы пользователя в порядке добавления
+    pub async fn saved_queries(&self, user_id: &str) -> Vec<SavedQuery> {
+        let key = user_id.to_string();
+        self.call(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT id, name, query FROM saved_queries WHERE user_id = ?1 ORDER BY id",
+            )?;
+            let queries = statement
+                .query_map([key], |row| {
+                    Ok(SavedQuery {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        query: row.get(2)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(queries)
+        })
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load saved queries of user {}: {:#}", user_id, e);
+                Vec::new()
+            })
+    }
+
+    /// Сохраняет запрос под названием; `true`, если запрос с таким названием уже был и заменен
+    pub async fn save_query(&self, user_id: &str, name: &str, query: &str) -> Result<bool> {
+        let (key, name, query) = (user_id.to_string(), name.to_string(), query.to_string());
+        self.call(move |connection| {
+            let updated = connection.execute(
+                "UPDATE saved_queries SET query = ?3 WHERE user_id = ?1 AND name = ?2",
+                params![key, name, query],
+            )?;
+            if updated == 0 {
+                connection.execute(
+                    "INSERT INTO saved_queries (user_id, name, query, created_at) VALUES (?1, ?2, ?3, ?4)",
+                    params![key, name, query, Utc::now()],
+                )?;
+            }
+            Ok(updated > 0)
+        })
+            .await
+    }
+
+    /// Удаляет сохраненный запрос пользователя; `false`, если такого запроса нет
+    pub async fn remove_saved_query(&self, user_id: &str, id: i64) -> Result<bool> {
+        let key = user_id.to_string();
+        self.call(move |connection| {
+            Ok(connection.execute("DELETE FROM saved_queries WHERE id = ?1 AND user_id = ?2", params![id, key])? > 0)
+        })
+            .await
+    }
+
+    /// Шаблоны запросов пользователя в порядке добавления
+    pub async fn templates(&self, user_id: &str) -> Vec<QueryTemplate> {
+        let key = user_id.to_string();
+        self.call(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT id, name, template FROM templates WHERE user_id = ?1 ORDER BY id",
+            )?;
+            let templates = statement
+                .query_map([key], |row| {
+                    Ok(QueryTemplate {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        template: row.get(2)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(templates)
+        })
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load templates of user {}: {:#}", user_id, e);
+                Vec::new()
+            })
+    }
+
+    /// Сохраняет шаблон под названием; `true`, если шаблон с таким названием уже был и заменен
+    pub async fn save_template(&self, user_id: &str, name: &str, template: &str) -> Result<bool> {
+        let (key, name, template) = (user_id.to_string(), name.to_string(), template.to_string());
+        self.call(move |connection| {
+            let updated = connection.execute(
+                "UPDATE templates SET template = ?3 WHERE user_id = ?1 AND name = ?2",
+                params![key, name, template],
+            )?;
+            if updated == 0 {
+                connection.execute(
+                    "INSERT INTO templates (user_id, name, template, created_at) VALUES (?1, ?2, ?3, ?4)",
+                    params![key, name, template, Utc::now()],
+                )?;
+            }
+            Ok(updated > 0)
+        })
+            .await
+    }
+
+    /// Удаляет шаблон пользователя; `false`, если такого шаблона нет
+    pub async fn remove_template(&self, user_id: &str, id: i64) -> Result<bool> {
+        let key = user_id.to_string();
+        self.call(move |connection| {
+            Ok(connection.execute("DELETE FROM templates WHERE id = ?1 AND user_id = ?2", params![id, key])? > 0)
+        })
+            .await
+    }
+
+    pub async fn schedules(&self) -> Vec<ScheduledReport> {
+        self.call(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT id, chat_id, user_id, question, frequency, time, created_at, last_run
+                 FROM schedules ORDER BY id",
+            )?;
+            let reports = statement
+                .query_map([], |row| {
+                    Ok(ScheduledReport {
+                        id: row.get(0)?,
+                        chat_id: row.get(1)?,
+                        user_id: row.get(2)?,
+                        question: row.get(3)?,
+                        frequency: json_column(row, 4)?,
+                        time: row.get(5)?,
+                        created_at: row.get(6)?,
+                        last_run: row.get(7)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(reports)
+        })
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load schedules: {:#}", e);
+                Vec::new()
+            })
+    }
+
+    /// Сохраняет новый отчет и возвращает его id
+    pub async fn add_schedule(&self, report: ScheduledReport) -> Result<u64> {
+        self.call(move |connection| insert_schedule(connection, &report, None)).await
+    }
+
+    /// Удаляет отчет чата; `false`, если такого отчета нет
+    pub async fn remove_schedule(&self, chat_id: i64, id: u64) -> Result<bool> {
+        self.call(move |connection| {
+            Ok(connection.execute("DELETE FROM schedules WHERE id = ?1 AND chat_id = ?2", params![id, chat_id])? > 0)
+        })
+            .await
+    }
+
+    /// Отмечает время последнего запуска отчета
+    pub async fn mark_schedule_run(&self, id: u64, at: DateTime<Utc>) -> Result<()> {
+        self.call(move |connection| {
+            connection.execute("UPDATE schedules SET last_run = ?2 WHERE id = ?1", params![id, at])?;
+            Ok(())
+        })
+            .await
+    }
+
+    pub async fn alerts(&self) -> Vec<Alert> {
+        self.call(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT id, chat_id, user_id, question, comparison, threshold, interval_mins,
+                        created_at, last_checked, last_value, triggered
+                 FROM alerts ORDER BY id",
+            )?;
+            let alerts = statement
+                .query_map([], |row| {
+                    Ok(Alert {
+                        id: row.get(0)?,
+                        chat_id: row.get(1)?,
+                        user_id: row.get(2)?,
+                        question: row.get(3)?,
+                        comparison: json_column(row, 4)?,
+                        threshold: row.get(5)?,
+                        interval_mins: row.get(6)?,
+                        created_at: row.get(7)?,
+                        last_checked: row.get(8)?,
+                        last_value: row.get(9)?,
+                        triggered: row.get(10)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(alerts)
+        })
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load alerts: {:#}", e);
+                Vec::new()
+            })
+    }
+
+    /// Сохраняет новое оповещение и возвращает его id
+    pub async fn add_alert(&self, alert: Alert) -> Result<u64> {
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT INTO alerts (chat_id, user_id, question, comparison, threshold, interval_mins, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    alert.chat_id,
+                    alert.user_id,
+                    alert.question,
+                    serde_json::to_string(&alert.comparison)?,
+                    alert.threshold,
+                    alert.interval_mins,
+                    alert.created_at,
+                ],
+            )?;
+            Ok(connection.last_insert_rowid() as u64)
+        })
+            .await
+    }
+
+    /// Удаляет оповещение чата; `false`, если такого оповещения нет
+    pub async fn remove_alert(&self, chat_id: i64, id: u64) -> Result<bool> {
+        self.call(move |connection| {
+            Ok(connection.execute("DELETE FROM alerts WHERE id = ?1 AND chat_id = ?2", params![id, chat_id])? > 0)
+        })
+            .await
+    }
+
+    /// Сохраняет результат проверки оповещения
+    pub async fn record_alert_check(&self, id: u64, at: DateTime<Utc>, value: Option<f64>, triggered: bool) -> Result<()> {
+        self.call(move |connection| {
+            connection.execute(
+                "UPDATE alerts SET last_checked = ?2, last_value = ?3, triggered = ?4 WHERE id = ?1",
+                params![id, at, value, triggered],
+            )?;
+            Ok(())
+        })
+            .await
+    }
+
+    /// Чаты, подписанные на рассылку `topic` (см. `notify::Topic`)
+    pub async fn subscribers(&self, topic: &str) -> Vec<i64> {
+        let topic = topic.to_string();
+        self.call(move |connection| {
+            let mut statement = connection.prepare("SELECT chat_id FROM subscriptions WHERE topic = ?1")?;
+            let chats = statement
+                .query_map([&topic], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(chats)
+        })
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load subscribers: {:#}", e);
+                Vec::new()
+            })
+    }
+
+    /// Рассылки, на которые подписан чат
+    pub async fn subscriptions(&self, chat_id: i64) -> Vec<String> {
+        self.call(move |connection| {
+            let mut statement = connection.prepare("SELECT topic FROM subscriptions WHERE chat_id = ?1 ORDER BY topic")?;
+            let topics = statement
+                .query_map([chat_id], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(topics)
+        })
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load subscriptions of chat {}: {:#}", chat_id, e);
+                Vec::new()
+            })
+    }
+
+    /// Подписывает чат на рассылку; `false`, если чат уже подписан
+    pub async fn subscribe(&self, chat_id: i64, topic: &str) -> Result<bool> {
+        let topic = topic.to_string();
+        self.call(move |connection| {
+            Ok(connection.execute(
+                "INSERT OR IGNORE INTO subscriptions (chat_id, topic, created_at) VALUES (?1, ?2, ?3)",
+                params![chat_id, topic, Utc::now()],
+            )? > 0)
+        })
+            .await
+    }
+
+    /// Отписывает чат от рассылки; `false`, если чат не был подписан
+    pub async fn unsubscribe(&self, chat_id: i64, topic: &str) -> Result<bool> {
+        let topic = topic.to_string();
+        self.call(move |connection| {
+            Ok(connection.execute("DELETE FROM subscriptions WHERE chat_id = ?1 AND topic = ?2", params![chat_id, topic])? > 0)
+        })
+            .await
+    }
+
+    /// Статистика использования по журналу запросов на момент `now` (для `/stats`)
+    pub async fn usage_stats(&self, now: DateTime<Utc>) -> Result<UsageStats> {
+        self.call(move |connection| {
+            let activity = |since: DateTime<Utc>| {
+                connection.query_row(
+                    "SELECT COUNT(DISTINCT user_id), COUNT(*), AVG(execution_time_ms) FROM history WHERE timestamp >= ?1",
+                    [since],
+                    |row| {
+                        Ok(Activity {
+                            active_users: row.get(0)?,
+                            queries: row.get(1)?,
+                            avg_execution_ms: row.get(2)?,
+                        })
+                    },
+                )
+            };
+            let week_start = now - Duration::days(7);
+            let day = activity(now - Duration::days(1))?;
+            let week = activity(week_start)?;
+
+            // Один и тот же вопрос с разным регистром и пробелами по краям считается одним
+            let popular = connection
+                .prepare(
+                    "SELECT MIN(question), COUNT(*) AS count FROM history WHERE timestamp >= ?1
+                     GROUP BY lower(trim(question)) ORDER BY count DESC, MAX(id) DESC LIMIT ?2",
+                )?
+                .query_map(params![week_start, POPULAR_QUESTIONS as i64], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            // Время хранится как `YYYY-MM-DD HH:MM:SS+00:00`, первые 10 символов - дата в UTC
+            let chart_start = (now.date_naive() - Duration::days(CHART_DAYS - 1)).and_time(NaiveTime::MIN).and_utc();
+            let daily = connection
+                .prepare(
+                    "SELECT substr(timestamp, 1, 10) AS day, COUNT(*) FROM history WHERE timestamp >= ?1
+                     GROUP BY day ORDER BY day",
+                )?
+                .query_map([chart_start], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(UsageStats { day, week, popular, daily })
+        })
+            .await
+    }
+
+    /// Разделы и кнопки главного меню
+    pub async fn menu(&self) -> Vec<MenuItem> {
+        self.call(read_menu)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load menu: {:#}", e);
+                None
+            })
+            .unwrap_or_else(crate::menu::default_menu)
+    }
+
+    /// Изменяет главное меню и сохраняет его
+    pub async fn update_menu<F, T>(&self, update: F) -> Result<T>
+    where
+        F: FnOnce(&mut Vec<MenuItem>) -> T,
+    {
+        let _write = self.writes.lock().await;
+        let mut menu = self.call(read_menu).await?.unwrap_or_else(crate::menu::default_menu);
+        let result = update(&mut menu);
+        self.call(move |connection| write_setting(connection, MENU_KEY, &menu)).await?;
+        Ok(result)
+    }
+}
+
+/// Применяет недостающие миграции; возвращает версию схемы до миграции
+fn migrate(connection: &mut Connection) -> Result<usize> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+        info!("Applied storage migration {}", index + 1);
+    }
+    Ok(version)
+}
+
+/// JSON-значение из текстовой колонки (`NULL` читается как `null`)
+fn json_column<T: DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<T> {
+    let text: Option<String> = row.get(index)?;
+    serde_json::from_str(text.as_deref().unwrap_or("null"))
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+}
+
+fn read_user(connection: &Connection, user_id: &str) -> Result<Option<UserRecord>> {
+    let record = connection
+        .query_row(
+            "SELECT api_token, linked_account, interface_language, answer_language, preferences, chat_session
+             FROM users WHERE user_id = ?1",
+            [user_id],
+            |row| {
+                // Языки хранятся отдельными колонками, остальные настройки - JSON в `preferences`
+                let mut settings: UserSettings = json_column(row, 4)?;
+                settings.interface_language = row.get::<_, Option<String>>(2)?.as_deref().and_then(Language::parse);
+                settings.answer_language = row.get::<_, Option<String>>(3)?.as_deref().and_then(Language::parse);
+                Ok(UserRecord {
+                    api_token: row.get(0)?,
+                    linked_account: row.get(1)?,
+                    settings,
+                    chat_session: json_column(row, 5)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(record)
+}
+
+fn write_user(connection: &Connection, user_id: &str, record: &UserRecord) -> Result<()> {
+    let preferences = UserSettings {
+        interface_language: None,
+        answer_language: None,
+        ..record.settings.clone()
+    };
+    let chat_session = record.chat_session.as_ref().map(serde_json::to_string).transpose()?;
+    connection.execute(
+        "INSERT INTO users (user_id, api_token, linked_account, interface_language, answer_language,
+                            preferences, chat_session, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT (user_id) DO UPDATE SET
+            api_token = excluded.api_token,
+            linked_account = excluded.linked_account,
+            interface_language = excluded.interface_language,
+            answer_language = excluded.answer_language,
+            preferences = excluded.preferences,
+            chat_session = excluded.chat_session,
+            updated_at = excluded.updated_at",
+        params![
+            user_id,
+            record.api_token,
+            record.linked_account,
+            record.settings.interface_language.map(|language| language.code()),
+            record.settings.answer_language.map(|language| language.code()),
+            serde_json::to_string(&preferences)?,
+            chat_session,
+            Utc::now(),
+        ],
+    )?;
+    Ok(())
+}
+
+fn read_history_entry(row: &Row) -> rusqlite::Result<AuditEntry> {
+    Ok(AuditEntry {
+        timestamp: row.get(0)?,
+        question: row.get(1)?,
+        headline: row.get(2)?,
+        key_numbers: json_column(row, 3)?,
+        row_count: row.get(4)?,
+        execution_time_ms: row.get(5)?,
+    })
+}
+
+fn insert_history(connection: &Connection, user_id: &str, entry: &AuditEntry) -> Result<()> {
+    connection.execute(
+        "INSERT INTO history (user_id, timestamp, question, headline, key_numbers, row_count, execution_time_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            user_id,
+            entry.timestamp,
+            entry.question,
+            entry.headline,
+            serde_json::to_string(&entry.key_numbers)?,
+            entry.row_count,
+            entry.execution_time_ms,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Добавляет отчет; `id` задается только при переносе старых данных, чтобы номера отчетов не изменились
+fn insert_schedule(connection: &Connection, report: &ScheduledReport, id: Option<u64>) -> Result<u64> {
+    connection.execute(
+        "INSERT INTO schedules (id, chat_id, user_id, question, frequency, time, created_at, last_run)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            id,
+            report.chat_id,
+            report.user_id,
+            report.question,
+            serde_json::to_string(&report.frequency)?,
+            report.time,
+            report.created_at,
+            report.last_run,
+        ],
+    )?;
+    Ok(connection.last_insert_rowid() as u64)
+}
+
+fn read_menu(connection: &mut Connection) -> Result<Option<Vec<MenuItem>>> {
+    let menu = connection
+        .query_row("SELECT value FROM bot_settings WHERE key = ?1", [MENU_KEY], |row| json_column(row, 0))
+        .optional()?;
+    Ok(menu)
+}
+
+fn write_setting<T: Serialize>(connection: &Connection, key: &str, value: &T) -> Result<()> {
+    connection.execute(
+        "INSERT INTO bot_settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        params![key, serde_json::to_string(value)?],
+    )?;
+    Ok(())
+}
+
+/// Пользователь в JSON-файле прежних версий: журнал хранился вместе с профилем
+#[derive(Deserialize)]
+struct LegacyUser {
+    #[serde(flatten)]
+    record: UserRecord,
+    #[serde(default)]
+    history: Vec<AuditEntry>,
+}
+
+/// Содержимое JSON-файла прежних версий бота
+#[derive(Deserialize)]
+struct LegacyData {
+    #[serde(default)]
+    users: HashMap<String, LegacyUser>,
+    #[serde(default)]
+    schedules: Vec<ScheduledReport>,
+    #[serde(default)]
+    menu: Option<Vec<MenuItem>>,
+}
+
+/// Переносит данные из JSON-файла в только что созданную базу одной транзакцией
+fn import_legacy(connection: &mut Connection, path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path)?;
+    let data: LegacyData = serde_json::from_str(&content)?;
+
+    let transaction = connection.transaction()?;
+    for (user_id, user) in &data.users {
+        write_user(&transaction, user_id, &user.record)?;
+        for entry in &user.history {
+            insert_history(&transaction, user_id, entry)?;
+        }
+    }
+    for report in &data.schedules {
+        insert_schedule(&transaction, report, Some(report.id))?;
+    }
+    if let Some(menu) = &data.menu {
+        write_setting(&transaction, MENU_KEY, menu)?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_dir;
+
+    fn open(name: &str) -> Storage {
+        Storage::open(test_dir(&format!("storage_{}", name)).join("bot_data.db")).unwrap()
+    }
+
+    fn user_version(storage: &Storage) -> usize {
+        let connection = storage.connection.lock().unwrap();
+        connection.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn legacy_json_is_imported_once() {
+        let dir = test_dir("storage_legacy");
+        let record = UserRecord {
+            linked_account: Some("analyst@example.com".to_string()),
+            settings: UserSettings { show_sql: true, ..UserSettings::default() },
+            ..UserRecord::default()
+        };
+        let entry = AuditEntry {
+            timestamp: Utc::now(),
+            question: "Оборот за март".to_string(),
+            headline: Some("Рост".to_string()),
+            key_numbers: vec!["Оборот: 100".to_string()],
+            row_count: 1,
+            execution_time_ms: 5,
+        };
+        let report = crate::scheduler::ScheduledReport {
+            id: 3,
+            chat_id: 42,
+            user_id: "42".to_string(),
+            question: "Оборот".to_string(),
+            frequency: crate::scheduler::Frequency::Daily,
+            time: chrono::NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            created_at: Utc::now(),
+            last_run: None,
+        };
+        let mut user = serde_json::to_value(&record).unwrap();
+        user["history"] = serde_json::json!([entry]);
+        let legacy = serde_json::json!({"users": {"42": user}, "schedules": [report]});
+        std::fs::write(dir.join("bot_data.json"), legacy.to_string()).unwrap();
+
+        // STORAGE_PATH прежних версий указывал на JSON-файл
+        let storage = Storage::open(dir.join("bot_data.json")).unwrap();
+        assert!(dir.join("bot_data.db").exists());
+        assert_eq!(user_version(&storage), MIGRATIONS.len());
+        let user = storage.user("42").await.unwrap();
+        assert_eq!(user.linked_account.as_deref(), Some("analyst@example.com"));
+        assert!(user.settings.show_sql);
+        let history = storage.history("42", 10).await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].question, "Оборот за март");
+        assert_eq!(history[0].key_numbers, ["Оборот: 100"]);
+        let schedules = storage.schedules().await;
+        assert_eq!((schedules.len(), schedules[0].id), (1, 3));
+        drop(storage);
+
+        // Файл остается на месте, но в уже созданную базу повторно не переносится
+        let storage = Storage::open(dir.join("bot_data.db")).unwrap();
+        assert_eq!(storage.history("42", 10).await.len(), 1);
+        assert_eq!(storage.schedules().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn old_database_is_migrated_in_place() {
+        let dir = test_dir("storage_migrate");
+        let path = dir.join("bot_data.db");
+        {
+            // База первой версии: только первая миграция и данные в ней
+            let connection = Connection::open(&path).unwrap();
+            connection.execute_batch(MIGRATIONS[0]).unwrap();
+            connection.pragma_update(None, "user_version", 1).unwrap();
+            connection
+                .execute(
+                    "INSERT INTO saved_queries (user_id, name, query, created_at) VALUES ('42', 'оборот', 'Оборот за день', ?1)",
+                    [Utc::now().to_rfc3339()],
+                )
+                .unwrap();
+        }
+        // JSON рядом с базой, созданной раньше, не импортируется
+        std::fs::write(dir.join("bot_data.json"), r#"{"users": {"7": {}}}"#).unwrap();
+
+        let storage = Storage::open(&path).unwrap();
+        assert_eq!(user_version(&storage), MIGRATIONS.len());
+        let saved = storage.saved_queries("42").await;
+        assert_eq!(saved.iter().map(|query| query.name.as_str()).collect::<Vec<_>>(), ["оборот"]);
+        assert!(storage.user("7").await.is_none());
+    }
+
+    async fn fill(storage: &Storage, user_id: &str) {
+        let chat_id: i64 = user_id.parse().unwrap();
+        let entry = AuditEntry {
+            timestamp: Utc::now(),
+            question: "Оборот".to_string(),
+            headline: None,
+            key_numbers: Vec::new(),
+            row_count: 1,
+            execution_time_ms: 5,
+        };
+        storage.update_user(user_id, |user| user.settings.show_sql = true).await.unwrap();
+        storage.add_history(user_id, entry, 10).await.unwrap();
+        storage.save_query(user_id, "оборот", "Оборот за день").await.unwrap();
+        storage.save_template(user_id, "город", "Оборот в {city}").await.unwrap();
+        storage.add_schedule(crate::scheduler::ScheduledReport {
+            id: 0,
+            chat_id,
+            user_id: user_id.to_string(),
+            question: "Оборот".to_string(),
+            frequency: crate::scheduler::Frequency::Daily,
+            time: chrono::NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            created_at: Utc::now(),
+            last_run: None,
+        }).await.unwrap();
+        storage.add_alert(crate::watcher::Alert {
+            id: 0,
+            chat_id,
+            user_id: user_id.to_string(),
+            question: "Оборот".to_string(),
+            comparison: crate::watcher::Comparison::Above,
+            threshold: 1.0,
+            interval_mins: 60,
+            created_at: Utc::now(),
+            last_checked: None,
+            last_value: None,
+            triggered: false,
+        }).await.unwrap();
+        storage.subscribe(chat_id, "anomalies").await.unwrap();
+    }
+
+    /// Строки таблицы, относящиеся к пользователю `key` (по `user_id` или по `chat_id` личного чата)
+    fn rows_of(connection: &Connection, table: &str, key: &str) -> i64 {
+        let columns: Vec<String> = connection
+            .prepare(&format!("PRAGMA table_info({})", table))
+            .unwrap()
+            .query_map([], |row| row.get(1))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        let column = if columns.iter().any(|column| column == "user_id") { "user_id" } else { "chat_id" };
+        assert!(columns.iter().any(|name| name == column), "table {} is keyed neither by user nor by chat", table);
+        connection
+            .query_row(&format!("SELECT COUNT(*) FROM {} WHERE CAST({} AS TEXT) = ?1", table, column), [key], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn forgetting_user_empties_every_user_table() {
+        let storage = open("forget");
+        fill(&storage, "42").await;
+        fill(&storage, "7").await;
+
+        storage.remove_user("42").await.unwrap();
+
+        let connection = storage.connection.lock().unwrap();
+        let tables: Vec<String> = connection
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != 'bot_settings'")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        for table in &tables {
+            assert!(
+                USER_TABLES.contains(&table.as_str()) || CHAT_TABLES.contains(&table.as_str()),
+                "table {} is not cleared by remove_user",
+                table
+            );
+            assert_eq!(rows_of(&connection, table, "42"), 0, "{} still has rows of the user", table);
+            assert_eq!(rows_of(&connection, table, "7"), 1, "{} lost rows of another user", table);
+        }
+    }
+}
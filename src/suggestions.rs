@@ -0,0 +1,49 @@
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Сколько длинных вопросов помним для кнопок-подсказок
+const MAX_STORED_QUESTIONS: usize = 5000;
+
+/// Длина хеша в callback-данных (`q:<hash>`)
+const HASH_LEN: usize = 16;
+
+#[derive(Default)]
+struct StoreInner {
+    questions: HashMap<String, String>,
+    order: VecDeque<String>,
+}
+
+/// Хранит полные тексты подсказок, не помещающихся в 64 байта callback-данных.
+/// Кнопка несет короткий хеш, по которому `handle_callback` восстанавливает вопрос.
+#[derive(Default)]
+pub struct SuggestionStore {
+    inner: Mutex<StoreInner>,
+}
+
+impl SuggestionStore {
+    /// Сохраняет вопрос и возвращает его хеш (для одинаковых вопросов хеш совпадает)
+    pub fn insert(&self, question: &str) -> String {
+        let hash: String = Sha256::digest(question.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()[..HASH_LEN]
+            .to_string();
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if inner.questions.insert(hash.clone(), question.to_string()).is_none() {
+            inner.order.push_back(hash.clone());
+        }
+        while inner.order.len() > MAX_STORED_QUESTIONS {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.questions.remove(&oldest);
+            }
+        }
+        hash
+    }
+
+    pub fn get(&self, hash: &str) -> Option<String> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.questions.get(hash).cloned()
+    }
+}
@@ -0,0 +1,172 @@
+use crate::language::Language;
+use serde_json::Value;
+
+/// Таблицы шире стольких колонок на телефоне моноширинным текстом не прочитать
+const MAX_TEXT_COLUMNS: usize = 4;
+/// ...или шире стольких символов в строке
+const MAX_TEXT_WIDTH: usize = 60;
+/// Сколько строк помещается на картинку; остальные - только в CSV
+const MAX_IMAGE_ROWS: usize = 40;
+/// Сколько колонок помещается на картинку
+const MAX_IMAGE_COLUMNS: usize = 15;
+/// Длинные значения обрезаются до стольких символов
+const MAX_CELL_CHARS: usize = 28;
+
+const FONT_SIZE: f64 = 18.0;
+const CELL_PADDING: u32 = 10;
+const ROW_HEIGHT: u32 = 34;
+const MARGIN: u32 = 12;
+
+/// Нужно ли отправить результат картинкой: колонок слишком много или строка слишком широкая
+pub fn is_wide(data: &[Value]) -> bool {
+    let columns = crate::utils::collect_columns(data);
+    if columns.len() > MAX_TEXT_COLUMNS {
+        return true;
+    }
+    let width: usize = columns.iter()
+        .map(|column| {
+            data.iter()
+                .map(|row| cell_text(row.get(column), Language::En).chars().count())
+                .chain([column.chars().count()])
+                .max()
+                .unwrap_or(0)
+                .min(MAX_CELL_CHARS)
+        })
+        .sum();
+    width > MAX_TEXT_WIDTH
+}
+
+/// Значение ячейки: `null` - пустая строка, числа по правилам языка, вложенные значения в JSON
+fn cell_text(value: Option<&Value>, lang: Language) -> String {
+    let text = match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Number(number)) => match number.as_f64() {
+            Some(value) => crate::numbers::format_number(value, lang),
+            None => number.to_string(),
+        },
+        Some(value) => value.to_string(),
+    };
+    if text.chars().count() > MAX_CELL_CHARS {
+        text.chars().take(MAX_CELL_CHARS - 1).chain(std::iter::once('…')).collect()
+    } else {
+        text
+    }
+}
+
+/// Рисует таблицу (первые `MAX_IMAGE_ROWS` строк) в PNG: шапка, чередующаяся заливка строк,
+/// числа выровнены по правому краю и записаны по правилам языка `lang`
+pub fn render(data: &[Value], lang: Language) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    use plotters::prelude::*;
+    use plotters::style::FontStyle;
+
+    let mut columns = crate::utils::collect_columns(data);
+    columns.truncate(MAX_IMAGE_COLUMNS);
+    if columns.is_empty() {
+        return Err("table has no columns".into());
+    }
+    let rows: Vec<&Value> = data.iter().filter(|row| row.is_object()).take(MAX_IMAGE_ROWS).collect();
+    let hidden = data.len().saturating_sub(rows.len());
+
+    let font = FontDesc::new(FontFamily::SansSerif, FONT_SIZE, FontStyle::Normal);
+    let bold = FontDesc::new(FontFamily::SansSerif, FONT_SIZE, FontStyle::Bold);
+    let numeric: Vec<bool> = columns.iter()
+        .map(|column| rows.iter().all(|row| matches!(row.get(column), Some(Value::Number(_)) | None | Some(Value::Null))))
+        .collect();
+    let headers: Vec<String> = columns.iter()
+        .zip(&numeric)
+        .map(|(column, numeric)| crate::numbers::column_header(column, *numeric))
+        .collect();
+    let cells: Vec<Vec<String>> = rows.iter()
+        .map(|row| columns.iter().map(|column| cell_text(row.get(column), lang)).collect())
+        .collect();
+
+    let mut widths = Vec::with_capacity(columns.len());
+    for (i, header) in headers.iter().enumerate() {
+        let mut width = bold.box_size(header)?.0;
+        for row in &cells {
+            width = width.max(font.box_size(&row[i])?.0);
+        }
+        widths.push(width + 2 * CELL_PADDING);
+    }
+    let footer = (hidden > 0).then(|| format!("… и еще {} строк(и) — все строки в CSV", hidden));
+    let footer_width = match &footer {
+        Some(footer) => font.box_size(footer)?.0 + 2 * CELL_PADDING,
+        None => 0,
+    };
+    let table_width: u32 = widths.iter().sum();
+    let width = table_width.max(footer_width) + 2 * MARGIN;
+    let table_height = ROW_HEIGHT * (cells.len() as u32 + 1);
+    let height = table_height + 2 * MARGIN + if footer.is_some() { ROW_HEIGHT } else { 0 };
+
+    let mut pixels = vec![0u8; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, (width, height)).into_drawing_area();
+        root.fill(&WHITE)?;
+
+        let header_fill = RGBColor(0xE8, 0xEC, 0xF2);
+        let stripe_fill = RGBColor(0xF7, 0xF8, 0xFA);
+        let grid = RGBColor(0xD0, 0xD5, 0xDD);
+        let left = MARGIN as i32;
+        let right = (MARGIN + table_width) as i32;
+        let top = MARGIN as i32;
+
+        for line in 0..=cells.len() {
+            let y = top + (line as u32 * ROW_HEIGHT) as i32;
+            let fill = if line == 0 {
+                Some(header_fill)
+            } else if line % 2 == 0 {
+                Some(stripe_fill)
+            } else {
+                None
+            };
+            if let Some(fill) = fill {
+                root.draw(&Rectangle::new([(left, y), (right, y + ROW_HEIGHT as i32)], fill.filled()))?;
+            }
+        }
+
+        let mut x = left;
+        for (i, header) in headers.iter().enumerate() {
+            let cell_width = widths[i] as i32;
+            let texts = std::iter::once((header.as_str(), &bold))
+                .chain(cells.iter().map(|row| (row[i].as_str(), &font)));
+            for (line, (text, font)) in texts.enumerate() {
+                if text.is_empty() {
+                    continue;
+                }
+                let (text_width, text_height) = font.box_size(text)?;
+                let text_x = if numeric[i] && line > 0 {
+                    x + cell_width - CELL_PADDING as i32 - text_width as i32
+                } else {
+                    x + CELL_PADDING as i32
+                };
+                let text_y = top + (line as u32 * ROW_HEIGHT) as i32 + (ROW_HEIGHT as i32 - text_height as i32) / 2;
+                root.draw_text(text, &font.color(&BLACK), (text_x, text_y))?;
+            }
+            x += cell_width;
+        }
+
+        // Сетка: горизонтальные линии между строками и вертикальные между колонками
+        let bottom = top + table_height as i32;
+        for line in 0..=cells.len() + 1 {
+            let y = top + (line as u32 * ROW_HEIGHT) as i32;
+            root.draw(&PathElement::new(vec![(left, y), (right, y)], grid))?;
+        }
+        let mut x = left;
+        for width in std::iter::once(0).chain(widths.iter().copied()) {
+            x += width as i32;
+            root.draw(&PathElement::new(vec![(x, top), (x, bottom)], grid))?;
+        }
+
+        if let Some(footer) = &footer {
+            root.draw_text(
+                footer,
+                &font.color(&RGBColor(0x60, 0x66, 0x70)),
+                (left + CELL_PADDING as i32, bottom + (ROW_HEIGHT as i32 - FONT_SIZE as i32) / 2),
+            )?;
+        }
+        root.present()?;
+    }
+
+    crate::utils::encode_png(pixels, width, height)
+}
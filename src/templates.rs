@@ -0,0 +1,382 @@
+use crate::dialogue;
+use crate::handlers;
+use crate::prompts::{compose, placeholders};
+use crate::state::BotState;
+use crate::storage::QueryTemplate;
+use crate::utils::{escape_html, format_error};
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode};
+use tracing::{error, info};
+
+/// Сколько шаблонов может сохранить пользователь и ограничение длины названия
+const MAX_TEMPLATES: usize = 20;
+const MAX_NAME_CHARS: usize = 32;
+/// Первое слово `/template`, удаляющее шаблон: `/template del top_cities`
+const DELETE_WORDS: [&str; 4] = ["del", "delete", "remove", "удалить"];
+
+/// Значения переменных не подходят к шаблону
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SubstitutionError {
+    /// Переменные шаблона, для которых не передано значение
+    missing: Vec<String>,
+    /// Переданные переменные, которых в шаблоне нет (скорее всего, опечатка)
+    unknown: Vec<String>,
+}
+
+/// Название шаблона и переменной: буквы, цифры и `_`, чтобы их было удобно набирать после `/t`
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Разбирает значения переменных `имя=значение` из аргументов `/t`. Значение продолжается до следующего
+/// `имя=`, так что пробелы в нем допустимы (`period=последние 7 дней`); кавычки вокруг значения убираются.
+/// Ошибка - слово перед первой переменной, которое не относится ни к одному значению.
+fn parse_values(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut values: Vec<(String, String)> = Vec::new();
+    for word in text.split_whitespace() {
+        match word.split_once('=').filter(|(name, _)| is_valid_name(name)) {
+            Some((name, value)) => {
+                // Повтор переменной заменяет прежнее значение
+                values.retain(|(known, _)| known != name);
+                values.push((name.to_string(), value.to_string()));
+            }
+            None => match values.last_mut() {
+                Some((_, value)) if value.is_empty() => value.push_str(word),
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(word);
+                }
+                None => return Err(word.to_string()),
+            },
+        }
+    }
+    for (_, value) in &mut values {
+        let unquoted = value.trim_matches(|c| matches!(c, '"' | '\'' | '«' | '»'));
+        *value = unquoted.to_string();
+    }
+    Ok(values)
+}
+
+/// Подставляет значения в шаблон. Каждой переменной шаблона нужно непустое значение,
+/// а значения для переменных, которых в шаблоне нет, считаются ошибкой.
+fn substitute(template: &str, values: &[(String, String)]) -> Result<String, SubstitutionError> {
+    let names = placeholders(template);
+    let error = SubstitutionError {
+        missing: names.iter()
+            .filter(|name| !values.iter().any(|(known, value)| known == *name && !value.is_empty()))
+            .cloned()
+            .collect(),
+        unknown: values.iter()
+            .filter(|(name, _)| !names.contains(name))
+            .map(|(name, _)| name.clone())
+            .collect(),
+    };
+    if error != SubstitutionError::default() {
+        return Err(error);
+    }
+    Ok(compose(template, values))
+}
+
+/// Пример вызова шаблона: `/t top_cities n=… period=…`
+fn usage(template: &QueryTemplate) -> String {
+    std::iter::once(format!("/t {}", template.name))
+        .chain(placeholders(&template.template).iter().map(|name| format!("{}=…", name)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Список шаблонов с примерами вызова и кнопками заполнения (`tpl:run:<id>`) и удаления (`tpl:del:<id>`)
+fn templates_message(templates: &[QueryTemplate]) -> (String, InlineKeyboardMarkup) {
+    if templates.is_empty() {
+        return (
+            "🧩 Шаблонов нет. Создайте шаблон с переменными в фигурных скобках:\n\
+             <code>/template top_cities топ {n} городов за {period}</code>\n\n\
+             И выполняйте его с разными значениями:\n<code>/t top_cities n=5 period=неделя</code>"
+                .to_string(),
+            InlineKeyboardMarkup::default(),
+        );
+    }
+
+    let mut text = String::from("🧩 <b>Шаблоны запросов</b>\n");
+    let mut keyboard = Vec::with_capacity(templates.len());
+    for template in templates {
+        text.push_str(&format!(
+            "\n<b>{}</b>\n<code>{}</code>\n<code>{}</code>\n",
+            escape_html(&template.name),
+            escape_html(&template.template),
+            escape_html(&usage(template))
+        ));
+        keyboard.push(vec![
+            InlineKeyboardButton::callback(format!("▶️ {}", template.name), format!("tpl:run:{}", template.id)),
+            InlineKeyboardButton::callback("🗑", format!("tpl:del:{}", template.id)),
+        ]);
+    }
+    text.push_str("\n▶️ спросит значения переменных по одной");
+    (text, InlineKeyboardMarkup::new(keyboard))
+}
+
+async fn reply(bot: &Bot, msg: &Message, text: impl Into<String>) -> ResponseResult<()> {
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+/// `/template` - список шаблонов, `/template название текст с {переменными}` - сохранить,
+/// `/template del название` - удалить
+pub async fn handle_template(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    let arg = arg.trim();
+    if arg.is_empty() || arg.eq_ignore_ascii_case("list") {
+        let (text, keyboard) = templates_message(&state.storage.templates(&user_id).await);
+        bot.send_message(msg.chat.id, text)
+            .parse_mode(ParseMode::Html)
+            .reply_markup(keyboard)
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let (name, text) = arg.split_once(char::is_whitespace).unwrap_or((arg, ""));
+    let name = name.to_lowercase();
+    // «top_cities = топ {n} городов» и «top_cities | ...» тоже понимаем
+    let text = text.trim().trim_start_matches(['=', '|', ':']).trim();
+
+    if DELETE_WORDS.contains(&name.as_str()) {
+        let name = text.to_lowercase();
+        let templates = state.storage.templates(&user_id).await;
+        let Some(template) = templates.iter().find(|template| template.name == name) else {
+            return reply(&bot, &msg, format!("🧩 Шаблона «{}» нет. Ваши шаблоны: /template", escape_html(&name))).await;
+        };
+        let text = match state.storage.remove_template(&user_id, template.id).await {
+            Ok(_) => {
+                info!("User {} removed template {:?}", user_id, name);
+                format!("🧩 Шаблон «{}» удален", escape_html(&name))
+            }
+            Err(e) => {
+                error!("Error removing template for user {}: {:#}", user_id, e);
+                format_error("Не удалось удалить шаблон")
+            }
+        };
+        return reply(&bot, &msg, text).await;
+    }
+
+    if text.is_empty() {
+        return reply(
+            &bot,
+            &msg,
+            "🧩 Укажите название и текст шаблона с переменными в фигурных скобках:\n\
+             <code>/template top_cities топ {n} городов за {period}</code>",
+        )
+            .await;
+    }
+    if !is_valid_name(&name) || name.chars().count() > MAX_NAME_CHARS {
+        return reply(
+            &bot,
+            &msg,
+            format!(
+                "🧩 Название шаблона - одно слово до {} символов из букв, цифр и «_», например <code>top_cities</code>",
+                MAX_NAME_CHARS
+            ),
+        )
+            .await;
+    }
+    let variables = placeholders(text);
+    if variables.is_empty() {
+        return reply(
+            &bot,
+            &msg,
+            "🧩 В шаблоне нет переменных в фигурных скобках, например <code>{period}</code>. \
+             Запрос без переменных можно сохранить командой /save",
+        )
+            .await;
+    }
+    if let Some(variable) = variables.iter().find(|variable| !is_valid_name(variable)) {
+        return reply(
+            &bot,
+            &msg,
+            format!("🧩 Имя переменной «{}» должно быть одним словом из букв, цифр и «_»", escape_html(variable)),
+        )
+            .await;
+    }
+
+    let templates = state.storage.templates(&user_id).await;
+    if templates.len() >= MAX_TEMPLATES && !templates.iter().any(|template| template.name == name) {
+        return reply(
+            &bot,
+            &msg,
+            format!("🧩 Сохранено максимум шаблонов ({}). Удалите ненужные в /template.", MAX_TEMPLATES),
+        )
+            .await;
+    }
+
+    let text = match state.storage.save_template(&user_id, &name, text).await {
+        Ok(replaced) => {
+            info!("User {} saved template {:?}", user_id, name);
+            let template = QueryTemplate { id: 0, name, template: text.to_string() };
+            format!(
+                "🧩 Шаблон «{}» {}. Выполнить его:\n<code>{}</code>",
+                escape_html(&template.name),
+                if replaced { "обновлен" } else { "сохранен" },
+                escape_html(&usage(&template))
+            )
+        }
+        Err(e) => {
+            error!("Error saving template for user {}: {:#}", user_id, e);
+            format_error("Не удалось сохранить шаблон")
+        }
+    };
+    reply(&bot, &msg, text).await
+}
+
+/// `/t название имя=значение ...` - выполняет шаблон с подставленными значениями.
+/// Без значений бот спрашивает переменные по одной, как после кнопки ▶️.
+pub async fn handle_run(bot: Bot, msg: Message, state: Arc<BotState>, arg: &str) -> ResponseResult<()> {
+    let user_id = state.user_key(&msg);
+    let arg = arg.trim();
+    if arg.is_empty() {
+        return handle_template(bot, msg, state, "").await;
+    }
+    let (name, values) = arg.split_once(char::is_whitespace).unwrap_or((arg, ""));
+    let name = name.to_lowercase();
+
+    let templates = state.storage.templates(&user_id).await;
+    let Some(template) = templates.into_iter().find(|template| template.name == name) else {
+        return reply(&bot, &msg, format!("🧩 Шаблона «{}» нет. Ваши шаблоны: /template", escape_html(&name))).await;
+    };
+
+    let values = match parse_values(values) {
+        Ok(values) => values,
+        Err(word) => {
+            return reply(
+                &bot,
+                &msg,
+                format!(
+                    "🧩 Не понял «{}»: значения указываются как <code>имя=значение</code>\n\n<code>{}</code>",
+                    escape_html(&word),
+                    escape_html(&usage(&template))
+                ),
+            )
+                .await;
+        }
+    };
+    if values.is_empty() {
+        if let Some(user) = msg.from().map(|user| user.id) {
+            let path = dialogue::load(&state.dialogue(msg.chat.id)).await.menu_path().to_vec();
+            if crate::prompts::start_template(&bot, &msg, &state, user, path, &template.name, &template.template).await? {
+                return Ok(());
+            }
+        }
+    }
+
+    match substitute(&template.template, &values) {
+        Ok(query) => {
+            info!("User {} runs template {:?}: {}", user_id, template.name, query);
+            handlers::run_canned_query(bot, msg, state, user_id, &query).await
+        }
+        Err(error) => {
+            let mut text = String::from("🧩 Шаблон не заполнен\n");
+            if !error.missing.is_empty() {
+                text.push_str(&format!("\nНе хватает значений: {}", escape_html(&error.missing.join(", "))));
+            }
+            if !error.unknown.is_empty() {
+                text.push_str(&format!("\nВ шаблоне нет переменных: {}", escape_html(&error.unknown.join(", "))));
+            }
+            text.push_str(&format!("\n\n<code>{}</code>", escape_html(&usage(&template))));
+            reply(&bot, &msg, text).await
+        }
+    }
+}
+
+/// Кнопки под списком `/template`: `run:<id>` спрашивает значения переменных, `del:<id>` удаляет шаблон
+pub async fn handle_callback(
+    bot: Bot,
+    msg: Message,
+    user: UserId,
+    action: &str,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let Some((action, id)) = action.split_once(':') else {
+        return Ok(());
+    };
+    let Ok(id) = id.parse::<i64>() else {
+        return Ok(());
+    };
+    // Шаблоны ищутся среди сохраненных нажавшим: чужие кнопки в группе ничего не делают
+    let user_id = state.context_scope.key(msg.chat.id, Some(user));
+    let templates = state.storage.templates(&user_id).await;
+    let Some(template) = templates.iter().find(|template| template.id == id) else {
+        bot.send_message(msg.chat.id, "🧩 Этого шаблона уже нет среди ваших. Откройте /template заново.")
+            .await?;
+        return Ok(());
+    };
+
+    match action {
+        "run" => {
+            let path = dialogue::load(&state.dialogue(msg.chat.id)).await.menu_path().to_vec();
+            if !crate::prompts::start_template(&bot, &msg, &state, user, path, &template.name, &template.template).await? {
+                let query = template.template.clone();
+                return handlers::run_canned_query(bot, msg, state, user_id, &query).await;
+            }
+            Ok(())
+        }
+        "del" => {
+            if let Err(e) = state.storage.remove_template(&user_id, id).await {
+                error!("Error removing template for user {}: {:#}", user_id, e);
+                bot.send_message(msg.chat.id, "❌ Не удалось удалить шаблон").await?;
+                return Ok(());
+            }
+            let (text, keyboard) = templates_message(&state.storage.templates(&user_id).await);
+            bot.edit_message_text(msg.chat.id, msg.id, text)
+                .parse_mode(ParseMode::Html)
+                .reply_markup(keyboard)
+                .await?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn values_keep_spaces_until_next_variable() {
+        assert_eq!(
+            parse_values("n=5 period=«последние 7 дней» n=10").unwrap(),
+            values(&[("period", "последние 7 дней"), ("n", "10")])
+        );
+        assert_eq!(parse_values("").unwrap(), []);
+        assert_eq!(parse_values("пять n=5").unwrap_err(), "пять");
+    }
+
+    #[test]
+    fn placeholders_are_substituted() {
+        let template = "топ {n} городов за {period}, {n} строк";
+        let query = substitute(template, &values(&[("period", "неделю"), ("n", "5")])).unwrap();
+        assert_eq!(query, "топ 5 городов за неделю, 5 строк");
+    }
+
+    #[test]
+    fn missing_and_unknown_variables_are_reported() {
+        let template = "топ {n} городов за {period}";
+        assert_eq!(
+            substitute(template, &values(&[("n", "5")])).unwrap_err(),
+            SubstitutionError { missing: vec!["period".to_string()], unknown: Vec::new() }
+        );
+        // Пустое значение не подставляется
+        assert_eq!(
+            substitute(template, &values(&[("n", "5"), ("period", ""), ("perod", "неделя")])).unwrap_err(),
+            SubstitutionError { missing: vec!["period".to_string()], unknown: vec!["perod".to_string()] }
+        );
+        let template = QueryTemplate { id: 1, name: "top_cities".to_string(), template: template.to_string() };
+        assert_eq!(usage(&template), "/t top_cities n=… period=…");
+    }
+}
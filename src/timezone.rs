@@ -0,0 +1,187 @@
+use crate::api_client::QueryResponse;
+use crate::language::Language;
+use crate::query_parser::{detect_date_range, DateRange};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
+
+/// Формат времени в данных после перевода в часовой пояс пользователя
+const LOCAL_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Часовой пояс по имени IANA (`Asia/Almaty`) без учета регистра
+pub fn parse(name: &str) -> Option<Tz> {
+    let name = name.trim();
+    name.parse::<Tz>().ok().or_else(|| {
+        chrono_tz::TZ_VARIANTS
+            .iter()
+            .copied()
+            .find(|tz| tz.name().eq_ignore_ascii_case(name))
+    })
+}
+
+/// Часовой пояс, в котором пользователь видит время: выбранный через `/timezone`
+/// или пояс бота (`SCHEDULE_UTC_OFFSET_HOURS`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserZone {
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl UserZone {
+    pub fn new(user_zone: Option<Tz>, bot_zone: FixedOffset) -> Self {
+        user_zone.map_or(Self::Fixed(bot_zone), Self::Named)
+    }
+
+    /// Время `at` на часах пользователя
+    pub fn local(&self, at: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Self::Named(tz) => at.with_timezone(tz).naive_local(),
+            Self::Fixed(offset) => at.with_timezone(offset).naive_local(),
+        }
+    }
+
+    /// Сегодняшняя дата пользователя
+    pub fn today(&self) -> NaiveDate {
+        self.local(Utc::now()).date()
+    }
+
+    /// Период из вопроса, относительные даты («за сегодня», «вчера») считаются от `today`
+    pub fn question_period(&self, question: &str) -> Option<DateRange> {
+        detect_date_range(question, self.today())
+    }
+
+    /// Отметка времени для имени файла: `20240301_143000`
+    pub fn file_stamp(&self) -> String {
+        self.local(Utc::now()).format("%Y%m%d_%H%M%S").to_string()
+    }
+
+    /// Время `at` на часах пользователя с именем пояса: `16.10.2026 17:30 Asia/Almaty`.
+    /// Для файлов, диаграмм и сообщений, которые могут прочитать в другом поясе.
+    pub fn stamp(&self, at: DateTime<Utc>, format: &str) -> String {
+        format!("{} {}", self.local(at).format(format), self.name())
+    }
+
+    /// Имя пояса для бэкенда и пользователя: `Asia/Almaty` или смещение `+05:00`
+    pub fn name(&self) -> String {
+        match self {
+            Self::Named(tz) => tz.name().to_string(),
+            Self::Fixed(offset) => offset.to_string(),
+        }
+    }
+}
+
+/// Момент времени из значения бэкенда, если в нем указан пояс: RFC 3339 (`2024-03-01T14:30:00Z`)
+/// или `2024-03-01 14:30:00+00:00`. Время без пояса не переводится: бэкенд получает пояс
+/// пользователя в `timezone` и такое время может быть уже местным. Даты без времени тоже не переводятся.
+fn parse_instant(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .or_else(|| DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f%#z").ok())
+        .map(|at| at.with_timezone(&Utc))
+}
+
+fn convert_value(value: &mut Value, tz: Tz) -> bool {
+    let Some(at) = value.as_str().and_then(parse_instant) else {
+        return false;
+    };
+    *value = Value::String(at.with_timezone(&tz).format(LOCAL_FORMAT).to_string());
+    true
+}
+
+/// Ответ со временем в поясе `tz`: значения и подписи диаграммы с датой, временем и поясом.
+/// Таблица бэкенда заменяется таблицей из переведенных данных. `None`, если времени в ответе нет.
+pub fn convert_timestamps(response: &QueryResponse, tz: Tz, lang: Language) -> Option<QueryResponse> {
+    let mut converted = response.clone();
+    let mut data_changed = false;
+    for row in converted.data.iter_mut().filter_map(Value::as_object_mut) {
+        for value in row.values_mut() {
+            data_changed |= convert_value(value, tz);
+        }
+    }
+    if data_changed && converted.table.is_some() {
+        converted.table = Some(crate::utils::format_data_as_table(&converted.data, lang));
+    }
+
+    let mut labels_changed = false;
+    if let Some(chart) = &mut converted.chart_data {
+        for label in &mut chart.labels {
+            if let Some(at) = parse_instant(label) {
+                *label = at.with_timezone(&tz).format(LOCAL_FORMAT).to_string();
+                labels_changed = true;
+            }
+        }
+    }
+    (data_changed || labels_changed).then_some(converted)
+}
+
+/// Пометка под ответом, время в котором переведено в пояс пользователя
+pub fn note(tz: Tz) -> String {
+    format!("🕐 <i>Время указано в поясе {}</i>", tz.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    #[test]
+    fn datetimes_are_converted_and_dates_kept() {
+        let response: QueryResponse = serde_json::from_value(json!({
+            "question": "Последние транзакции",
+            "data": [
+                {"created_at": "2024-03-01T20:30:00Z", "day": "2024-03-01", "amount": 5.0},
+                {"created_at": "2024-03-01 18:00:00+03:00", "day": "2024-03-01", "amount": 3.0},
+            ],
+            "execution_time_ms": 5,
+            "row_count": 2,
+        }))
+        .unwrap();
+
+        let almaty = parse("asia/almaty").unwrap();
+        let converted = convert_timestamps(&response, almaty, Language::Ru).unwrap();
+        // С 1 марта 2024 года Алматы живет по UTC+5
+        assert_eq!(converted.data[0]["created_at"], "2024-03-02 01:30:00");
+        assert_eq!(converted.data[1]["created_at"], "2024-03-01 20:00:00");
+        assert_eq!(converted.data[0]["day"], "2024-03-01");
+
+        let dates_only: QueryResponse = serde_json::from_value(json!({
+            "question": "По дням",
+            "data": [{"day": "2024-03-01", "amount": 5.0}],
+            "execution_time_ms": 5,
+            "row_count": 1,
+        }))
+        .unwrap();
+        assert!(convert_timestamps(&dates_only, almaty, Language::Ru).is_none());
+    }
+
+    #[test]
+    fn naive_datetimes_are_not_shifted_again() {
+        // Время без пояса бэкенд мог уже перевести по `timezone` запроса
+        let response: QueryResponse = serde_json::from_value(json!({
+            "question": "Последние транзакции",
+            "data": [
+                {"created_at": "2024-03-01 18:00:00", "amount": 3.0},
+                {"created_at": "2024-03-01T18:00:00", "amount": 4.0},
+            ],
+            "chart_data": {"chart_type": "line", "labels": ["2024-03-01 18:00"], "datasets": []},
+            "execution_time_ms": 5,
+            "row_count": 2,
+        }))
+        .unwrap();
+        assert!(convert_timestamps(&response, parse("Asia/Almaty").unwrap(), Language::Ru).is_none());
+    }
+
+    #[test]
+    fn zone_names() {
+        assert_eq!(parse("Europe/Moscow").map(|tz| tz.name()), Some("Europe/Moscow"));
+        assert_eq!(parse("Mars/Olympus"), None);
+        let bot_zone = UserZone::new(None, FixedOffset::east_opt(5 * 3600).unwrap());
+        assert_eq!(bot_zone.name(), "+05:00");
+        let at = Utc.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap();
+        assert_eq!(bot_zone.local(at).to_string(), "2024-03-02 01:00:00");
+        assert_eq!(bot_zone.stamp(at, "%d.%m %H:%M"), "02.03 01:00 +05:00");
+        let moscow = UserZone::new(parse("Europe/Moscow"), FixedOffset::east_opt(5 * 3600).unwrap());
+        assert_eq!(moscow.stamp(at, "%d.%m %H:%M"), "01.03 23:00 Europe/Moscow");
+    }
+}
@@ -0,0 +1,259 @@
+use crate::utils::words;
+
+/// Название из словаря: основа в нижнем регистре (без падежного окончания) и запись в базе
+struct Entry {
+    stem: String,
+    latin: String,
+    /// Основа совпадает с обычным словом («семей», «орал», «банки»): заменяется, только если слово -
+    /// имя собственное (с заглавной не в начале предложения или в кавычках)
+    proper_only: bool,
+}
+
+/// Встроенный словарь: города и банки, как они записаны в базе (на латинице)
+const BUILTIN: &[(&str, &str, bool)] = &[
+    // Города
+    ("алматы", "Almaty", false),
+    ("алма-ат", "Almaty", false),
+    ("астан", "Astana", false),
+    ("шымкент", "Shymkent", false),
+    ("чимкент", "Shymkent", false),
+    ("караганд", "Karaganda", false),
+    ("актобе", "Aktobe", false),
+    ("актюбинск", "Aktobe", false),
+    ("тараз", "Taraz", false),
+    ("павлодар", "Pavlodar", false),
+    ("усть-каменогорск", "Oskemen", false),
+    ("оскемен", "Oskemen", false),
+    ("өскемен", "Oskemen", false),
+    ("семе", "Semey", true),
+    ("атырау", "Atyrau", false),
+    ("костана", "Kostanay", false),
+    ("қостанай", "Kostanay", false),
+    ("кызылорд", "Kyzylorda", false),
+    ("қызылорда", "Kyzylorda", false),
+    ("уральск", "Oral", false),
+    ("орал", "Oral", true),
+    ("петропавловск", "Petropavl", false),
+    ("петропавл", "Petropavl", false),
+    ("актау", "Aktau", false),
+    ("ақтау", "Aktau", false),
+    ("темиртау", "Temirtau", false),
+    ("туркестан", "Turkistan", false),
+    ("түркістан", "Turkistan", false),
+    ("талдыкорган", "Taldykorgan", false),
+    ("талдықорған", "Taldykorgan", false),
+    ("экибастуз", "Ekibastuz", false),
+    ("кокшетау", "Kokshetau", false),
+    ("көкшетау", "Kokshetau", false),
+    ("жезказган", "Zhezkazgan", false),
+    ("балхаш", "Balkhash", false),
+    ("конаев", "Konaev", false),
+    // Банки
+    ("халык", "Halyk", false),
+    ("халық", "Halyk", false),
+    ("каспи", "Kaspi", false),
+    ("фортебанк", "ForteBank", false),
+    ("форте", "Forte", true),
+    ("жусан", "Jusan", false),
+    ("центркредит", "CenterCredit", false),
+    ("евразийск", "Eurasian", false),
+    ("фридом", "Freedom", false),
+    ("береке", "Bereke", false),
+    ("отбасы", "Otbasy", false),
+    ("нурбанк", "Nurbank", false),
+    ("алтын", "Altyn", true),
+    ("шинхан", "Shinhan", false),
+    ("ситибанк", "Citibank", false),
+    // «Халык Банк», «Банк ЦентрКредит»
+    ("банк", "Bank", true),
+];
+
+/// Самое длинное падежное окончание, которое может стоять после основы («Астаной», «Алматыда»)
+const MAX_ENDING_CHARS: usize = 4;
+
+/// Переводит названия городов и банков в вопросе на латиницу, как они записаны в базе:
+/// сначала по словарю (встроенному и `TRANSLIT_DICTIONARY`), а имена собственные, которых
+/// в словаре нет (слово с заглавной буквы не в начале предложения или в кавычках), - по общим правилам.
+/// Остальной текст вопроса не меняется.
+pub struct Transliterator {
+    entries: Vec<Entry>,
+}
+
+impl Transliterator {
+    /// `extra` - пары «название кириллицей → запись в базе»; они важнее встроенного словаря
+    pub fn new(extra: &[(String, String)]) -> Self {
+        let mut configured: Vec<Entry> = extra
+            .iter()
+            .map(|(name, latin)| Entry { stem: name.trim().to_lowercase(), latin: latin.trim().to_string(), proper_only: false })
+            .filter(|entry| !entry.stem.is_empty() && !entry.latin.is_empty())
+            .collect();
+        let mut builtin: Vec<Entry> = BUILTIN
+            .iter()
+            .map(|(stem, latin, proper_only)| Entry { stem: stem.to_string(), latin: latin.to_string(), proper_only: *proper_only })
+            .collect();
+        // Внутри каждого словаря длинная основа проверяется раньше короткой («фортебанк» раньше «форте»)
+        for entries in [&mut configured, &mut builtin] {
+            entries.sort_by_key(|entry| std::cmp::Reverse(entry.stem.chars().count()));
+        }
+        configured.extend(builtin);
+        Self { entries: configured }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        if !text.chars().any(is_cyrillic) {
+            return text.to_string();
+        }
+
+        let mut result = String::with_capacity(text.len());
+        let mut position = 0;
+        // Начало текста - начало предложения
+        let mut sentence_start = true;
+        let mut in_quotes = false;
+        for (start, word) in words(text) {
+            let gap = &text[position..start];
+            for c in gap.chars() {
+                match c {
+                    '.' | '!' | '?' | ':' | '\n' => sentence_start = true,
+                    '«' | '“' => in_quotes = true,
+                    '»' | '”' => in_quotes = false,
+                    '"' => in_quotes = !in_quotes,
+                    _ => {}
+                }
+            }
+            result.push_str(gap);
+
+            let capitalized = word.chars().next().is_some_and(char::is_uppercase);
+            let proper_name = (capitalized && !sentence_start) || in_quotes;
+            let replacement = match self.lookup(word, proper_name) {
+                Some(latin) => Some(latin.to_string()),
+                None if proper_name && word.chars().any(is_cyrillic) => Some(transliterate_word(word)),
+                None => None,
+            };
+            result.push_str(replacement.as_deref().unwrap_or(word));
+            sentence_start = false;
+            position = start + word.len();
+        }
+        result.push_str(&text[position..]);
+        result
+    }
+
+    /// Запись в базе для слова из словаря: слово - основа с окончанием не длиннее `MAX_ENDING_CHARS`
+    fn lookup(&self, word: &str, proper_name: bool) -> Option<&str> {
+        let lower = word.to_lowercase();
+        self.entries
+            .iter()
+            .filter(|entry| proper_name || !entry.proper_only)
+            .find(|entry| {
+                lower.strip_prefix(entry.stem.as_str()).is_some_and(|ending| {
+                    ending.chars().count() <= MAX_ENDING_CHARS && ending.chars().all(is_cyrillic)
+                })
+            })
+            .map(|entry| entry.latin.as_str())
+    }
+}
+
+fn is_cyrillic(c: char) -> bool {
+    matches!(c, '\u{0400}'..='\u{04FF}')
+}
+
+/// Общие правила: русская и казахская кириллица латиницей (близко к официальной латинице
+/// названий в Казахстане: «Жанаозен» → «Zhanaozen», «Щучинск» → «Shchuchinsk»)
+fn transliterate_word(word: &str) -> String {
+    let mut result = String::with_capacity(word.len() * 2);
+    for c in word.chars() {
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        let latin = match lower {
+            'а' | 'ә' => "a",
+            'б' => "b",
+            'в' => "v",
+            'г' | 'ғ' => "g",
+            'д' => "d",
+            'е' | 'э' => "e",
+            'ё' => "yo",
+            'ж' => "zh",
+            'з' => "z",
+            'и' | 'і' => "i",
+            'й' => "y",
+            'к' | 'қ' => "k",
+            'л' => "l",
+            'м' => "m",
+            'н' | 'ң' => "n",
+            'о' | 'ө' => "o",
+            'п' => "p",
+            'р' => "r",
+            'с' => "s",
+            'т' => "t",
+            'у' | 'ұ' | 'ү' => "u",
+            'ф' => "f",
+            'х' | 'һ' => "kh",
+            'ц' => "ts",
+            'ч' => "ch",
+            'ш' => "sh",
+            'щ' => "shch",
+            'ъ' | 'ь' => "",
+            'ы' => "y",
+            'ю' => "yu",
+            'я' => "ya",
+            _ => {
+                result.push(c);
+                continue;
+            }
+        };
+        if c.is_uppercase() {
+            let mut chars = latin.chars();
+            if let Some(first) = chars.next() {
+                result.extend(first.to_uppercase());
+                result.push_str(chars.as_str());
+            }
+        } else {
+            result.push_str(latin);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(text: &str) -> String {
+        Transliterator::new(&[]).apply(text)
+    }
+
+    #[test]
+    fn cities_are_replaced_in_any_case_form() {
+        assert_eq!(apply("Топ мерчантов в Алматы за май"), "Топ мерчантов в Almaty за май");
+        assert_eq!(apply("sql: Сравни Астану и Шымкент"), "sql: Сравни Astana и Shymkent");
+        assert_eq!(apply("оборот в караганде и костанае"), "оборот в Karaganda и Kostanay");
+        assert_eq!(apply("Транзакции в Усть-Каменогорске"), "Транзакции в Oskemen");
+        assert_eq!(apply("Алматыда қанша транзакция болды?"), "Almaty қанша транзакция болды?");
+    }
+
+    #[test]
+    fn banks_are_replaced() {
+        assert_eq!(apply("Средний чек по картам Халык Банка"), "Средний чек по картам Halyk Bank");
+        assert_eq!(apply("Сравни Каспи и Jusan"), "Сравни Kaspi и Jusan");
+        assert_eq!(apply("доля банка ЦентрКредит"), "доля банка CenterCredit");
+    }
+
+    #[test]
+    fn ordinary_words_are_kept() {
+        // «семей» и «орал» - обычные слова, пока не написаны с заглавной
+        assert_eq!(apply("Сколько семей платили картой"), "Сколько семей платили картой");
+        assert_eq!(apply("Сколько транзакций в Семее"), "Сколько транзакций в Semey");
+        assert_eq!(apply("Банки с наибольшим оборотом"), "Банки с наибольшим оборотом");
+        assert_eq!(apply("Top cities by volume"), "Top cities by volume");
+    }
+
+    #[test]
+    fn unknown_proper_names_use_general_rules() {
+        assert_eq!(apply("Транзакции в Жанаозене"), "Транзакции в Zhanaozene");
+        assert_eq!(apply("Покажи мерчанта «Щедрый двор»"), "Покажи мерчанта «Shchedryy dvor»");
+    }
+
+    #[test]
+    fn configured_names_take_precedence() {
+        let transliterator = Transliterator::new(&[("Нур-Султан".to_string(), "Astana".to_string())]);
+        assert_eq!(transliterator.apply("Объем в Нур-Султане"), "Объем в Astana");
+    }
+}
@@ -0,0 +1,41 @@
+use crate::messenger::MessageSender;
+use std::time::Duration;
+use teloxide::types::ChatId;
+use tokio_util::sync::CancellationToken;
+
+/// Telegram показывает «печатает...» около 5 секунд, поэтому повторяем чуть чаще
+const TYPING_INTERVAL: Duration = Duration::from_secs(4);
+
+/// Индикатор «печатает...», который держится, пока бэкенд готовит ответ.
+/// Фоновая задача повторяет действие каждые `TYPING_INTERVAL` и останавливается,
+/// когда индикатор выходит из области видимости.
+pub struct Typing {
+    token: CancellationToken,
+}
+
+impl Typing {
+    pub fn start<S: MessageSender>(bot: &S, chat_id: ChatId) -> Self {
+        let token = CancellationToken::new();
+        let cancelled = token.clone();
+        let bot = bot.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TYPING_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = cancelled.cancelled() => break,
+                    _ = interval.tick() => {
+                        // Ошибки не важны: индикатор только косметика
+                        let _ = bot.send_typing(chat_id).await;
+                    }
+                }
+            }
+        });
+        Self { token }
+    }
+}
+
+impl Drop for Typing {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
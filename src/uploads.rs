@@ -0,0 +1,123 @@
+use crate::api_client::UploadRequest;
+use crate::handlers;
+use crate::progress::Progress;
+use crate::sender::ResponseSender;
+use crate::state::BotState;
+use crate::utils::{escape_html, format_backend_error};
+use std::sync::Arc;
+use teloxide::net::Download;
+use teloxide::prelude::*;
+use teloxide::types::{Document, Message, ParseMode};
+use tracing::{error, info};
+
+/// Расширения файлов, которые бэкенд умеет сравнивать с данными транзакций
+const SUPPORTED_EXTENSIONS: [&str; 2] = [".csv", ".xlsx"];
+
+/// Документ от пользователя: CSV/XLSX отправляется в `/api/upload` для сравнения
+/// с данными транзакций, подпись к файлу становится вопросом.
+/// В группах файл обрабатывается, только если в подписи упомянут бот.
+pub async fn handle_document(bot: Bot, msg: Message, state: Arc<BotState>, document: &Document) -> ResponseResult<()> {
+    let caption = msg.caption().unwrap_or("").trim();
+    let mention = format!("@{}", state.bot_username.to_lowercase());
+    if !msg.chat.is_private() && !caption.to_lowercase().contains(&mention) {
+        return Ok(());
+    }
+    let question = caption
+        .split_whitespace()
+        .filter(|word| word.to_lowercase() != mention)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let user_id = state.user_key(&msg);
+    let lang = state.ui_language(&user_id, msg.from()).await;
+    let file_name = document.file_name.clone().unwrap_or_default();
+
+    if !is_supported(&file_name) {
+        bot.send_message(msg.chat.id, "📎 Для сравнения с данными отправьте файл CSV или XLSX.")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+    if document.file.size > state.max_upload_bytes {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "📎 Файл слишком большой: {:.1} МБ. Максимум — {} МБ.",
+                document.file.size as f64 / 1_048_576.0,
+                state.max_upload_bytes / 1_048_576
+            ),
+        )
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    if handlers::reject_if_backend_down(&bot, &msg, &state, lang).await?
+        || handlers::reject_if_rate_limited(&bot, &msg, &state, lang).await?
+    {
+        return Ok(());
+    }
+    let Some(_running) = handlers::start_unless_running(&bot, &msg, &state, lang, &document.file.unique_id).await? else {
+        return Ok(());
+    };
+
+    info!("Received upload from user {}: {} ({} bytes)", user_id, file_name, document.file.size);
+    let progress = Progress::start(&bot, &msg, &state, lang).await?;
+
+    let content = match download(&bot, document).await {
+        Ok(content) => content,
+        Err(e) => {
+            error!("Failed to download uploaded file: {:#}", e);
+            return progress.fail("❌ Не удалось скачать файл из Telegram. Попробуйте отправить его еще раз.").await;
+        }
+    };
+
+    let request = UploadRequest {
+        question: if question.is_empty() { format!("Сравни файл {} с данными транзакций", file_name) } else { question },
+        file_name: file_name.clone(),
+        content,
+        user_id: Some(user_id.clone()),
+        language: handlers::answer_language(&state, &user_id, None).await,
+    };
+
+    let api = state.api(&user_id).await;
+    match progress.run(api.upload(request)).await {
+        Ok(response) => {
+            ResponseSender::new(&bot, &state, msg.chat.id, &user_id)
+                .send(progress, &response)
+                .await
+        }
+        Err(e) => {
+            error!("Error processing upload {}: {}", file_name, e);
+            let fallback = format!("Не удалось обработать файл <code>{}</code>.", escape_html(&file_name));
+            progress.fail(&format_backend_error(lang, &e, &fallback)).await
+        }
+    }
+}
+
+/// Фото вместо файла: подсказываем, что таблицы нужно отправлять документом
+pub async fn handle_photo(bot: Bot, msg: Message) -> ResponseResult<()> {
+    if !msg.chat.is_private() {
+        return Ok(());
+    }
+    bot.send_message(
+        msg.chat.id,
+        "📎 Изображения я не анализирую. Чтобы сравнить свои данные с транзакциями, отправьте файл <b>CSV</b> или <b>XLSX</b> документом.",
+    )
+        .parse_mode(ParseMode::Html)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+fn is_supported(file_name: &str) -> bool {
+    let file_name = file_name.to_lowercase();
+    SUPPORTED_EXTENSIONS.iter().any(|extension| file_name.ends_with(extension))
+}
+
+async fn download(bot: &Bot, document: &Document) -> anyhow::Result<Vec<u8>> {
+    let file = bot.get_file(&document.file.id).await?;
+    let mut content = Vec::with_capacity(file.size as usize);
+    bot.download_file(&file.path, &mut content).await?;
+    Ok(content)
+}
@@ -1,68 +1,344 @@
 use serde_json::Value;
 use crate::api_client::ChartData;
+use crate::i18n::{tr, Msg};
+use crate::language::Language;
+use crate::suggestions::SuggestionStore;
 
-/// Форматирует данные в CSV
-pub fn format_as_csv(data: &[Value]) -> String {
-    if data.is_empty() {
-        return String::new();
+/// Сумма, среднее и количество по строкам уже полученного результата
+pub mod frame;
+
+/// Форматирует данные в CSV (UTF-8 с BOM, чтобы Excel правильно открыл кириллицу).
+/// Колонки собираются по всем строкам; `null` - пустая ячейка, вложенные массивы
+/// и объекты записываются как JSON.
+pub fn format_as_csv(data: &[Value], delimiter: u8) -> anyhow::Result<Vec<u8>> {
+    const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+    let columns = collect_columns(data);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(UTF8_BOM.to_vec());
+    if !columns.is_empty() {
+        writer.write_record(&columns)?;
+    }
+    for row in data.iter().filter_map(Value::as_object) {
+        writer.write_record(columns.iter().map(|column| match row.get(column) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(text)) => text.clone(),
+            Some(value) => value.to_string(),
+        }))?;
     }
+    writer.into_inner().map_err(|e| anyhow::anyhow!("Failed to write CSV: {}", e.error()))
+}
 
-    let mut result = String::new();
-    
-    if let Some(first_obj) = data[0].as_object() {
-        let keys: Vec<String> = first_obj.keys().map(|k| k.clone()).collect();
-        
-        // Заголовок
-        result.push_str(&keys.join(","));
-        result.push_str("\n");
-        
-        // Данные
-        for row in data {
-            if let Some(obj) = row.as_object() {
-                let values: Vec<String> = keys.iter()
-                    .map(|key| {
-                        let value = obj.get(key)
-                            .and_then(|v| {
-                                if v.is_number() {
-                                    Some(format!("{}", v.as_f64().unwrap_or(0.0)))
-                                } else {
-                                    v.as_str().map(|s| format!("\"{}\"", s.replace("\"", "\"\"")))
-                                }
-                            })
-                            .unwrap_or_else(|| "".to_string());
-                        value
-                    })
-                    .collect();
-                result.push_str(&values.join(","));
-                result.push_str("\n");
+/// Форматирует данные в JSON: массив строк-объектов с отступами, значения - как их вернул бэкенд
+pub fn format_as_json(data: &[Value]) -> anyhow::Result<Vec<u8>> {
+    Ok(serde_json::to_vec_pretty(data)?)
+}
+
+/// Собирает все колонки результата в порядке первого появления
+pub fn collect_columns(data: &[Value]) -> Vec<String> {
+    let mut columns: Vec<String> = Vec::new();
+    for row in data {
+        if let Some(obj) = row.as_object() {
+            for key in obj.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
             }
         }
     }
+    columns
+}
 
-    result
+/// Форматирует данные в Parquet (для загрузки в pandas/Spark).
+/// Тип колонки выводится из значений: целые, дробные, логические или строки.
+pub fn format_as_parquet(data: &[Value]) -> anyhow::Result<Vec<u8>> {
+    use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    let columns = collect_columns(data);
+    let mut fields = Vec::with_capacity(columns.len());
+    let mut arrays: Vec<ArrayRef> = Vec::with_capacity(columns.len());
+
+    for column in &columns {
+        let values: Vec<Option<&Value>> = data.iter()
+            .map(|row| row.get(column).filter(|v| !v.is_null()))
+            .collect();
+        let present = || values.iter().flatten();
+
+        let (data_type, array): (DataType, ArrayRef) = if present().all(|v| v.is_i64()) {
+            (DataType::Int64, Arc::new(Int64Array::from(
+                values.iter().map(|v| v.and_then(Value::as_i64)).collect::<Vec<_>>(),
+            )))
+        } else if present().all(|v| v.is_number()) {
+            (DataType::Float64, Arc::new(Float64Array::from(
+                values.iter().map(|v| v.and_then(Value::as_f64)).collect::<Vec<_>>(),
+            )))
+        } else if present().all(|v| v.is_boolean()) {
+            (DataType::Boolean, Arc::new(BooleanArray::from(
+                values.iter().map(|v| v.and_then(Value::as_bool)).collect::<Vec<_>>(),
+            )))
+        } else {
+            (DataType::Utf8, Arc::new(StringArray::from(
+                values.iter()
+                    .map(|v| v.map(|v| match v {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    }))
+                    .collect::<Vec<_>>(),
+            )))
+        };
+
+        fields.push(Field::new(column, data_type, true));
+        arrays.push(array);
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), arrays)?;
+
+    let mut buffer = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut buffer, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    Ok(buffer)
+}
+
+/// Форматирует данные в XLSX: числа и логические значения сохраняются
+/// как есть, чтобы Excel не зависел от локали при разборе CSV
+pub fn format_as_xlsx(data: &[Value]) -> anyhow::Result<Vec<u8>> {
+    use rust_xlsxwriter::{Format, Workbook};
+
+    let columns = collect_columns(data);
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    let header_format = Format::new().set_bold();
+
+    for (col, column) in columns.iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, column, &header_format)?;
+    }
+
+    for (idx, row) in data.iter().enumerate() {
+        let row_num = idx as u32 + 1;
+        for (col, column) in columns.iter().enumerate() {
+            let col = col as u16;
+            match row.get(column) {
+                Some(Value::Number(n)) => {
+                    worksheet.write_number(row_num, col, n.as_f64().unwrap_or(0.0))?;
+                }
+                Some(Value::Bool(b)) => {
+                    worksheet.write_boolean(row_num, col, *b)?;
+                }
+                Some(Value::String(s)) => {
+                    worksheet.write_string(row_num, col, s)?;
+                }
+                Some(Value::Null) | None => {}
+                Some(other) => {
+                    worksheet.write_string(row_num, col, other.to_string())?;
+                }
+            }
+        }
+    }
+    worksheet.autofit();
+
+    Ok(workbook.save_to_buffer()?)
+}
+
+/// Добавляет ряд кнопок к inline-клавиатуре ответа (или создает новую)
+pub fn append_keyboard_row(
+    keyboard: Option<teloxide::types::ReplyMarkup>,
+    row: Vec<teloxide::types::InlineKeyboardButton>,
+) -> Option<teloxide::types::ReplyMarkup> {
+    use teloxide::types::{InlineKeyboardMarkup, ReplyMarkup};
+
+    match keyboard {
+        Some(ReplyMarkup::InlineKeyboard(markup)) => Some(ReplyMarkup::InlineKeyboard(markup.append_row(row))),
+        Some(other) => Some(other),
+        None => Some(ReplyMarkup::InlineKeyboard(InlineKeyboardMarkup::new(vec![row]))),
+    }
+}
+
+/// Тема оформления диаграмм (`CHART_THEME`, `/settings theme`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChartTheme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl ChartTheme {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "light" | "светлая" => Some(Self::Light),
+            "dark" | "темная" | "тёмная" => Some(Self::Dark),
+            _ => None,
+        }
+    }
+
+    /// Значение для `/settings theme` и кнопок настроек
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Dark => "dark",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Light => "светлая",
+            Self::Dark => "темная",
+        }
+    }
+
+    fn colors(self) -> ThemeColors {
+        use plotters::style::RGBColor;
+
+        match self {
+            Self::Light => ThemeColors {
+                background: RGBColor(0xFF, 0xFF, 0xFF),
+                text: RGBColor(0x1F, 0x29, 0x37),
+                muted: RGBColor(0x6B, 0x72, 0x80),
+                axis: RGBColor(0x9C, 0xA3, 0xAF),
+                grid: RGBColor(0xE5, 0xE7, 0xEB),
+                grid_light: RGBColor(0xF3, 0xF4, 0xF6),
+            },
+            Self::Dark => ThemeColors {
+                background: RGBColor(0x1E, 0x22, 0x2A),
+                text: RGBColor(0xE5, 0xE7, 0xEB),
+                muted: RGBColor(0x9C, 0xA3, 0xAF),
+                axis: RGBColor(0x6B, 0x72, 0x80),
+                grid: RGBColor(0x37, 0x3D, 0x48),
+                grid_light: RGBColor(0x2A, 0x2F, 0x38),
+            },
+        }
+    }
+}
+
+/// Цвета темы, кроме цветов серий (они общие, см. `BRAND_PALETTE`)
+struct ThemeColors {
+    background: plotters::style::RGBColor,
+    /// Заголовок и легенда
+    text: plotters::style::RGBColor,
+    /// Подписи осей и водяной знак
+    muted: plotters::style::RGBColor,
+    axis: plotters::style::RGBColor,
+    /// Основные линии сетки
+    grid: plotters::style::RGBColor,
+    /// Промежуточные линии сетки
+    grid_light: plotters::style::RGBColor,
+}
+
+/// Фирменная палитра столбцов, линий и секторов; читается и на светлом, и на темном фоне
+const BRAND_PALETTE: [plotters::style::RGBColor; 8] = [
+    plotters::style::RGBColor(0x2F, 0x80, 0xED),
+    plotters::style::RGBColor(0x27, 0xAE, 0x60),
+    plotters::style::RGBColor(0xF2, 0x99, 0x4A),
+    plotters::style::RGBColor(0x9B, 0x51, 0xE0),
+    plotters::style::RGBColor(0xEB, 0x57, 0x57),
+    plotters::style::RGBColor(0x2D, 0xB7, 0xB5),
+    plotters::style::RGBColor(0xF2, 0xC9, 0x4C),
+    plotters::style::RGBColor(0x56, 0xCC, 0xF2),
+];
+
+/// Шрифт подписей с кириллицей и казахскими буквами; встроен в бинарник (см. `charts::register_fonts`)
+pub const CHART_FONT: &str = "DejaVu Sans";
+
+fn series_color(i: usize) -> plotters::style::RGBColor {
+    BRAND_PALETTE[i % BRAND_PALETTE.len()]
+}
+
+/// Ось значений диаграммы: линейная от нуля или логарифмическая. На логарифмической
+/// рисуется `log10` значения по целым декадам, а подписи переводятся обратно в значения.
+struct ValueAxis {
+    log: bool,
+    range: std::ops::Range<f64>,
+}
+
+impl ValueAxis {
+    fn new(values: &[f64], log: bool) -> Self {
+        let max = values.iter().fold(0f64, |a, &b| a.max(b));
+        if !log {
+            return Self { log, range: 0.0..max };
+        }
+        // Нули и отрицательные значения на логарифмической шкале не показать: они остаются у основания
+        let min = values.iter().copied().filter(|value| *value > 0.0).fold(f64::INFINITY, f64::min);
+        if !min.is_finite() {
+            return Self { log, range: 0.0..1.0 };
+        }
+        let start = min.log10().floor();
+        let end = max.log10().ceil().max(start + 1.0);
+        Self { log, range: start..end }
+    }
+
+    /// Начало столбцов
+    fn base(&self) -> f64 {
+        self.range.start
+    }
+
+    /// Координата значения на оси
+    fn position(&self, value: f64) -> f64 {
+        match self.log {
+            true if value > 0.0 => value.log10(),
+            true => self.base(),
+            false => value,
+        }
+    }
+
+    /// Сколько подписей просить у plotters: на логарифмической шкале - по одной на декаду
+    fn label_count(&self) -> usize {
+        if self.log {
+            (self.range.end - self.range.start) as usize + 1
+        } else {
+            10
+        }
+    }
+
+    fn label(&self, position: f64, lang: Language) -> String {
+        if !self.log {
+            return crate::numbers::format_compact(position, lang);
+        }
+        if (position - position.round()).abs() > 0.01 {
+            return String::new();
+        }
+        crate::numbers::format_compact(10f64.powf(position.round()), lang)
+    }
 }
 
 /// Генерирует изображение диаграммы из данных
-/// Возвращает PNG изображение в виде байтов
+/// Возвращает PNG изображение в виде байтов. Подписи осей записываются по правилам языка `lang`,
+/// в правом нижнем углу - подпись `footer` (имя бота и время построения).
 pub fn generate_chart_image(
     chart_data: &ChartData,
     width: u32,
     height: u32,
+    lang: Language,
+    theme: ChartTheme,
+    footer: &str,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
     use plotters::prelude::*;
+    use plotters::style::text_anchor::{HPos, Pos, VPos};
     
-    // Создаем временный файл для plotters
-    let temp_path = std::env::temp_dir().join(format!("chart_{}.png", std::process::id()));
+    let colors = theme.colors();
+    // Рисуем в RGB-буфер в памяти: без временных файлов, общих для параллельных чатов
+    let mut pixels = vec![0u8; (width * height * 3) as usize];
     
-    {
-        // Используем файл для создания изображения
-        let root = BitMapBackend::new(&temp_path, (width, height))
+    'draw: {
+        let root = BitMapBackend::with_buffer(&mut pixels, (width, height))
             .into_drawing_area();
-        root.fill(&WHITE)?;
+        root.fill(&colors.background)?;
+        if !footer.is_empty() {
+            let style = (CHART_FONT, 14).into_font()
+                .color(&colors.muted)
+                .pos(Pos::new(HPos::Right, VPos::Bottom));
+            root.draw(&Text::new(footer, (width as i32 - 12, height as i32 - 8), style))?;
+        }
         
-        let root = root.margin(50, 20, 20, 50);
+        let root = root.margin(50, 30, 20, 50);
         
-        let max_val = chart_data.datasets[0].data.iter().fold(0f64, |a, &b| a.max(b));
+        let axis = ValueAxis::new(&chart_data.datasets[0].data, chart_data.log_scale);
         let label_count = chart_data.labels.len();
         
         if label_count == 0 {
@@ -72,31 +348,46 @@ pub fn generate_chart_image(
         // Определяем тип диаграммы
         let chart_type = chart_data.chart_type.to_lowercase();
         
+        // Круговая диаграмма рисуется без осей
+        if chart_type == "pie" || chart_type == "donut" {
+            draw_pie_chart(&root, chart_data, chart_type == "donut", &colors)?;
+            break 'draw;
+        }
+        
+        // Несколько наборов данных (например, два периода `/compare`) - столбцы рядом по категориям
+        let is_bar = !matches!(chart_type.as_str(), "line" | "trend");
+        if is_bar && chart_data.datasets.len() > 1 {
+            draw_grouped_bar_chart(&root, chart_data, lang, &colors)?;
+            break 'draw;
+        }
+
+        // Длинные подписи (города, мерчанты) не помещаются на оси X - кладем столбцы набок
+        let has_long_labels = chart_data.labels.iter()
+            .any(|label| label.chars().count() > HORIZONTAL_BAR_LABEL_LEN);
+        if chart_type == "horizontal_bar" || (is_bar && has_long_labels) {
+            draw_horizontal_bar_chart(&root, chart_data, lang, &colors)?;
+            break 'draw;
+        }
+        
         // Улучшенная визуализация с поддержкой разных типов
         let mut chart = ChartBuilder::on(&root)
             .caption(
-                &chart_data.title.clone().unwrap_or_else(|| "Данные".to_string()),
-                ("sans-serif", 24).into_font()
+                chart_data.title.clone().unwrap_or_else(|| "Данные".to_string()),
+                (CHART_FONT, 24).into_font().color(&colors.text)
             )
             .x_label_area_size(60)
             .y_label_area_size(80)
-            .build_cartesian_2d(0..label_count as i32, 0f64..max_val)?;
+            .build_cartesian_2d(0..label_count as i32, axis.range.clone())?;
         
         // Настраиваем сетку и подписи
         chart.configure_mesh()
+            .bold_line_style(colors.grid)
+            .light_line_style(colors.grid_light)
+            .axis_style(colors.axis)
+            .label_style((CHART_FONT, 15).into_font().color(&colors.muted))
             .x_labels(label_count.min(20)) // Ограничиваем количество меток на оси X
-            .y_label_formatter(&|y| {
-                // Форматируем большие числа
-                if *y >= 1_000_000_000.0 {
-                    format!("{:.1}B", y / 1_000_000_000.0)
-                } else if *y >= 1_000_000.0 {
-                    format!("{:.1}M", y / 1_000_000.0)
-                } else if *y >= 1_000.0 {
-                    format!("{:.1}K", y / 1_000.0)
-                } else {
-                    format!("{:.0}", y)
-                }
-            })
+            .y_labels(axis.label_count())
+            .y_label_formatter(&|y| axis.label(*y, lang))
             .x_label_formatter(&|x| {
                 // Обрезаем длинные метки
                 if let Some(label) = chart_data.labels.get(*x as usize) {
@@ -117,59 +408,380 @@ pub fn generate_chart_image(
                 // Линейный график
                 let points: Vec<(i32, f64)> = chart_data.datasets[0].data.iter()
                     .enumerate()
-                    .map(|(i, &val)| (i as i32, val))
+                    .map(|(i, &val)| (i as i32, axis.position(val)))
                     .collect();
                 
                 chart.draw_series(LineSeries::new(
                     points.iter().map(|&(x, y)| (x, y)),
-                    RED.stroke_width(2),
+                    series_color(0).stroke_width(3),
                 ))?;
                 
                 // Добавляем точки
                 chart.draw_series(
                     points.iter().map(|&(x, y)| {
-                        Circle::new((x, y), 3, RED.filled())
+                        Circle::new((x, y), 4, series_color(0).filled())
                     })
                 )?;
             }
-            "pie" => {
-                // Круговая диаграмма - используем bar chart как fallback
-                // (plotters не поддерживает pie напрямую, можно добавить позже)
-                for (i, value) in chart_data.datasets[0].data.iter().enumerate() {
-                    let x = i as i32;
-                    let y_val = *value;
-                    let color = Palette99::pick(i);
-                    
-                    chart.draw_series(std::iter::once(
-                        Rectangle::new([(x, 0.0), (x + 1, y_val)], color.filled())
-                    ))?;
-                }
-            }
             _ => {
                 // Bar chart (по умолчанию)
                 for (i, value) in chart_data.datasets[0].data.iter().enumerate() {
                     let x = i as i32;
                     let y_val = *value;
-                    let color = Palette99::pick(i);
+                    let color = series_color(i);
                     
                     // Рисуем столбец
                     chart.draw_series(std::iter::once(
-                        Rectangle::new([(x, 0.0), (x + 1, y_val)], color.filled())
+                        Rectangle::new([(x, axis.base()), (x + 1, axis.position(y_val))], color.filled())
                     ))?;
                 }
             }
         }
     }
     
-    // Читаем файл в буфер
-    let buffer = std::fs::read(&temp_path)?;
-    // Удаляем временный файл
-    let _ = std::fs::remove_file(&temp_path);
-    
-    Ok(buffer)
+    encode_png(pixels, width, height)
 }
 
-pub fn format_query_response(response: &crate::api_client::QueryResponse) -> String {
+/// Подписи длиннее этого числа символов переводят столбчатую диаграмму в горизонтальную
+const HORIZONTAL_BAR_LABEL_LEN: usize = 10;
+
+/// Рисует горизонтальную столбчатую диаграмму с полными подписями на оси Y
+fn draw_horizontal_bar_chart(
+    root: &plotters::drawing::DrawingArea<plotters::prelude::BitMapBackend<'_>, plotters::coord::Shift>,
+    chart_data: &ChartData,
+    lang: Language,
+    colors: &ThemeColors,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use plotters::prelude::*;
+
+    let values = &chart_data.datasets[0].data;
+    let label_count = chart_data.labels.len();
+    let axis = ValueAxis::new(values, chart_data.log_scale);
+    // Первая категория сверху
+    let row = |i: usize| (label_count - 1 - i) as f64;
+    let label_at = |y: f64| -> String {
+        let i = label_count as f64 - 1.0 - y.round();
+        if (y - y.round()).abs() > 0.01 || i < 0.0 {
+            return String::new();
+        }
+        chart_data.labels.get(i as usize)
+            .map(|label| {
+                if label.chars().count() > 40 {
+                    label.chars().take(38).collect::<String>() + ".."
+                } else {
+                    label.clone()
+                }
+            })
+            .unwrap_or_default()
+    };
+    let longest_label = chart_data.labels.iter()
+        .map(|label| label.chars().count().min(40))
+        .max()
+        .unwrap_or(0) as u32;
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(
+            chart_data.title.clone().unwrap_or_else(|| "Данные".to_string()),
+            (CHART_FONT, 24).into_font().color(&colors.text)
+        )
+        .x_label_area_size(40)
+        .y_label_area_size((longest_label * 9 + 20).min(360))
+        .build_cartesian_2d(axis.range.clone(), -0.5f64..label_count as f64 - 0.5)?;
+
+    chart.configure_mesh()
+        .disable_y_mesh()
+        .bold_line_style(colors.grid)
+        .light_line_style(colors.grid_light)
+        .axis_style(colors.axis)
+        .label_style((CHART_FONT, 15).into_font().color(&colors.muted))
+        .y_labels(label_count.min(30))
+        .y_label_formatter(&|y| label_at(*y))
+        .x_labels(axis.label_count())
+        .x_label_formatter(&|x| axis.label(*x, lang))
+        .draw()?;
+
+    for (i, value) in values.iter().enumerate().take(label_count) {
+        let y = row(i);
+        chart.draw_series(std::iter::once(
+            Rectangle::new([(axis.base(), y - 0.4), (axis.position(*value), y + 0.4)], series_color(i).filled())
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Рисует столбцы всех наборов данных рядом по каждой категории с легендой наборов
+fn draw_grouped_bar_chart(
+    root: &plotters::drawing::DrawingArea<plotters::prelude::BitMapBackend<'_>, plotters::coord::Shift>,
+    chart_data: &ChartData,
+    lang: Language,
+    colors: &ThemeColors,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use plotters::prelude::*;
+
+    let label_count = chart_data.labels.len();
+    let series = chart_data.datasets.len();
+    // Категория занимает по единице на каждый набор и единицу на промежуток до следующей
+    let group_width = series as i32 + 1;
+    let all_values: Vec<f64> = chart_data.datasets.iter().flat_map(|dataset| dataset.data.iter().copied()).collect();
+    let axis = ValueAxis::new(&all_values, chart_data.log_scale);
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(
+            chart_data.title.clone().unwrap_or_else(|| "Данные".to_string()),
+            (CHART_FONT, 24).into_font().color(&colors.text)
+        )
+        .x_label_area_size(60)
+        .y_label_area_size(80)
+        .build_cartesian_2d(0..label_count as i32 * group_width, axis.range.clone())?;
+
+    chart.configure_mesh()
+        .disable_x_mesh()
+        .bold_line_style(colors.grid)
+        .light_line_style(colors.grid_light)
+        .axis_style(colors.axis)
+        .label_style((CHART_FONT, 15).into_font().color(&colors.muted))
+        .x_labels(label_count * group_width as usize + 1)
+        .y_labels(axis.label_count())
+        .y_label_formatter(&|y| axis.label(*y, lang))
+        .x_label_formatter(&|x| {
+            // Подпись - под серединой группы столбцов
+            if x % group_width != (series as i32) / 2 {
+                return String::new();
+            }
+            match chart_data.labels.get((x / group_width) as usize) {
+                Some(label) if label.chars().count() > 10 => label.chars().take(8).collect::<String>() + "..",
+                Some(label) => label.clone(),
+                None => String::new(),
+            }
+        })
+        .draw()?;
+
+    for (j, dataset) in chart_data.datasets.iter().enumerate() {
+        let color = series_color(j);
+        chart
+            .draw_series(dataset.data.iter().take(label_count).enumerate().map(|(i, value)| {
+                let x = i as i32 * group_width + j as i32;
+                Rectangle::new([(x, axis.base()), (x + 1, axis.position(*value))], color.filled())
+            }))?
+            .label(dataset.label.clone())
+            .legend(move |(x, y)| Rectangle::new([(x, y - 6), (x + 12, y + 6)], color.filled()));
+    }
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(colors.background)
+        .border_style(colors.axis)
+        .label_font((CHART_FONT, 15).into_font().color(&colors.text))
+        .draw()?;
+
+    Ok(())
+}
+
+/// Рисует круговую (или кольцевую) диаграмму с легендой «подпись — доля»
+fn draw_pie_chart(
+    root: &plotters::drawing::DrawingArea<plotters::prelude::BitMapBackend<'_>, plotters::coord::Shift>,
+    chart_data: &ChartData,
+    donut: bool,
+    colors: &ThemeColors,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use plotters::prelude::*;
+
+    // Отрицательные и нулевые значения в долях не участвуют
+    let slices: Vec<(&str, f64)> = chart_data.labels.iter()
+        .zip(&chart_data.datasets[0].data)
+        .filter(|(_, value)| **value > 0.0)
+        .map(|(label, value)| (label.as_str(), *value))
+        .collect();
+    let total: f64 = slices.iter().map(|(_, value)| value).sum();
+    let slice_colors: Vec<RGBColor> = (0..slices.len()).map(series_color).collect();
+
+    let title = chart_data.title.clone().unwrap_or_else(|| "Данные".to_string());
+    let root = root.titled(&title, (CHART_FONT, 24).into_font().color(&colors.text))?;
+    let (root_width, _) = root.dim_in_pixel();
+    let (pie_area, legend_area) = root.split_horizontally(root_width * 3 / 5);
+
+    if total > 0.0 {
+        let (pie_width, pie_height) = pie_area.dim_in_pixel();
+        // Pie принимает координаты холста, а не области
+        let (left, top) = pie_area.get_base_pixel();
+        let center = (left + pie_width as i32 / 2, top + pie_height as i32 / 2);
+        let radius = pie_width.min(pie_height) as f64 / 2.0 * 0.9;
+        let sizes: Vec<f64> = slices.iter().map(|(_, value)| *value).collect();
+        // Подписи выводятся в легенде, на самих секторах они налезают друг на друга
+        let no_labels = vec![""; slices.len()];
+
+        let mut pie = Pie::new(&center, &radius, &sizes, &slice_colors, &no_labels);
+        pie.start_angle(-90.0);
+        if donut {
+            pie.donut_hole(radius * 0.5);
+        }
+        pie_area.draw(&pie)?;
+    }
+
+    // Легенда: цвет, подпись и доля; не поместившиеся строки сворачиваем
+    let row_height = 28;
+    let (_, legend_height) = legend_area.dim_in_pixel();
+    let max_rows = (legend_height as usize / row_height as usize).max(1);
+    let shown = if slices.len() > max_rows { max_rows - 1 } else { slices.len() };
+
+    for (i, ((label, value), color)) in slices.iter().zip(&slice_colors).take(shown).enumerate() {
+        let y = i as i32 * row_height + 10;
+        let label = if label.chars().count() > 24 {
+            label.chars().take(22).collect::<String>() + ".."
+        } else {
+            label.to_string()
+        };
+        legend_area.draw(&Rectangle::new([(10, y), (28, y + 18)], color.filled()))?;
+        legend_area.draw(&Text::new(
+            format!("{} — {:.1}%", label, value / total * 100.0),
+            (38, y + 2),
+            (CHART_FONT, 18).into_font().color(&colors.text),
+        ))?;
+    }
+    if shown < slices.len() {
+        let y = shown as i32 * row_height + 10;
+        legend_area.draw(&Text::new(
+            format!("... и еще {}", slices.len() - shown),
+            (38, y + 2),
+            (CHART_FONT, 18).into_font().color(&colors.text),
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Кодирует RGB-буфер в PNG
+pub fn encode_png(
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    use image::{ImageBuffer, ImageOutputFormat, Rgb};
+
+    let image: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_raw(width, height, pixels)
+        .ok_or("chart buffer size does not match image dimensions")?;
+    let mut png = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageOutputFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+/// Где в ответе на запрос к данным показываются строки результата
+#[derive(Debug, Clone, Copy)]
+pub enum DataPlacement<'a> {
+    /// Таблицей в тексте ответа
+    Text,
+    /// Отдельной картинкой (см. `table_image`), все строки - в CSV
+    Image,
+    /// JSON с отступами: блоком кода в тексте ответа (`Some`) или отдельным файлом `.json` (`None`)
+    Json(Option<&'a str>),
+}
+
+/// Больше стольких выводов анализа сворачиваются за кнопку «Показать все выводы»
+pub const MAX_COLLAPSED_INSIGHTS: usize = 5;
+
+/// Важность вывода анализа; менее важные выводы можно скрыть (`/settings insights`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InsightLevel {
+    #[default]
+    Low,
+    Medium,
+    High,
+}
+
+impl InsightLevel {
+    /// Уровень по `significance` бэкенда; неизвестное значение - наименее важный
+    pub fn of(significance: &str) -> Self {
+        match significance {
+            "High" => Self::High,
+            "Medium" => Self::Medium,
+            _ => Self::Low,
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "all" | "low" | "все" => Some(Self::Low),
+            "medium" | "средние" => Some(Self::Medium),
+            "high" | "important" | "важные" => Some(Self::High),
+            _ => None,
+        }
+    }
+
+    /// Значение для `/settings insights` и кнопок настроек
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "all",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Low => "все",
+            Self::Medium => "важные и средние",
+            Self::High => "только важные",
+        }
+    }
+
+    fn emoji(self) -> &'static str {
+        match self {
+            Self::High => "🔴",
+            Self::Medium => "🟡",
+            Self::Low => "🟢",
+        }
+    }
+}
+
+/// Какие выводы анализа показываются в ответе
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InsightOptions {
+    /// Менее важные выводы пропускаются
+    pub min_level: InsightLevel,
+    /// Все выводы сразу; иначе после `MAX_COLLAPSED_INSIGHTS` остальные свернуты
+    pub expanded: bool,
+}
+
+/// Выводы не ниже `min_level`, самые важные первыми; выводы одной важности - в порядке бэкенда
+pub fn visible_insights(insights: &[crate::api_client::Insight], min_level: InsightLevel) -> Vec<&crate::api_client::Insight> {
+    let mut visible: Vec<_> = insights
+        .iter()
+        .filter(|insight| InsightLevel::of(&insight.significance) >= min_level)
+        .collect();
+    visible.sort_by_key(|insight| std::cmp::Reverse(InsightLevel::of(&insight.significance)));
+    visible
+}
+
+/// Вывод анализа: значок важности, заголовок и описание (HTML)
+pub fn format_insight(insight: &crate::api_client::Insight, lang: Language) -> String {
+    use crate::numbers::localize_text;
+
+    format!(
+        "{} <b>{}</b>\n{}",
+        InsightLevel::of(&insight.significance).emoji(),
+        escape_html(&localize_text(&insight.title, lang)),
+        escape_html(&localize_text(&insight.description, lang))
+    )
+}
+
+/// Ответ на запрос к данным (HTML) со всеми выводами анализа не ниже `min_level`.
+/// Числа в выводах анализа записываются по правилам языка `lang`.
+pub fn format_query_response(response: &crate::api_client::QueryResponse, min_level: InsightLevel, lang: Language) -> String {
+    let insights = InsightOptions { min_level, expanded: true };
+    format_query_response_with(response, DataPlacement::Text, insights, lang)
+}
+
+/// Как `format_query_response`, но строки результата показываются так, как указано в `placement`,
+/// а выводы анализа - как указано в `insights`
+pub fn format_query_response_with(
+    response: &crate::api_client::QueryResponse,
+    placement: DataPlacement,
+    insights: InsightOptions,
+    lang: Language,
+) -> String {
+    use crate::numbers::localize_text;
+
     let mut result = String::new();
 
     // Если есть текстовый ответ (обычный вопрос)
@@ -180,21 +792,26 @@ pub fn format_query_response(response: &crate::api_client::QueryResponse) -> Str
 
     // Если есть анализ, показываем его
     if let Some(analysis) = &response.analysis {
-        result.push_str(&format!("📊 <b>{}</b>\n\n", escape_html(&analysis.headline)));
+        result.push_str(&format!("📊 <b>{}</b>\n\n", escape_html(&localize_text(&analysis.headline, lang))));
         
-        if !analysis.insights.is_empty() {
+        let visible = visible_insights(&analysis.insights, insights.min_level);
+        if !visible.is_empty() {
             result.push_str("💡 <b>Основные выводы:</b>\n");
-            for insight in &analysis.insights {
-                let emoji = match insight.significance.as_str() {
-                    "High" => "🔴",
-                    "Medium" => "🟡",
-                    _ => "🟢",
-                };
-                result.push_str(&format!("{} <b>{}</b>\n{}\n\n", emoji, escape_html(&insight.title), escape_html(&insight.description)));
+            let shown = if insights.expanded { visible.len() } else { MAX_COLLAPSED_INSIGHTS };
+            for insight in visible.iter().take(shown) {
+                result.push_str(&format_insight(insight, lang));
+                result.push_str("\n\n");
+            }
+            if visible.len() > shown {
+                result.push_str(&format!("<i>… и еще выводов: {}</i>\n\n", visible.len() - shown));
             }
         }
+        let hidden = analysis.insights.len() - visible.len();
+        if hidden > 0 {
+            result.push_str(&format!("<i>Менее важных выводов скрыто: {} (<code>/settings insights all</code>)</i>\n\n", hidden));
+        }
 
-        result.push_str(&format!("📝 <b>Объяснение:</b>\n{}\n\n", escape_html(&analysis.explanation)));
+        result.push_str(&format!("📝 <b>Объяснение:</b>\n{}\n\n", escape_html(&localize_text(&analysis.explanation, lang))));
 
         if !analysis.suggested_questions.is_empty() {
             result.push_str("💭 <b>Рекомендуемые вопросы:</b>\n");
@@ -202,27 +819,44 @@ pub fn format_query_response(response: &crate::api_client::QueryResponse) -> Str
             for (idx, question) in analysis.suggested_questions.iter().enumerate() {
                 result.push_str(&format!("{}. {}\n", idx + 1, escape_html(question)));
             }
-            result.push_str("\n");
+            result.push('\n');
         }
     }
 
     // Показываем данные только если есть таблица (не для одиночных агрегаций)
     // Для одиночных значений (COUNT, SUM, AVG) показываем только текстовое описание из анализа
-    if let Some(table) = &response.table {
+    if let DataPlacement::Image = placement {
+        result.push_str(&format!(
+            "📋 <b>Результаты ({})</b>: таблица — на картинке, все строки — в CSV\n",
+            response.row_count
+        ));
+    } else if let DataPlacement::Json(json) = placement {
+        match json {
+            Some(json) => result.push_str(&format!(
+                "📋 <b>Результаты ({})</b>:\n<pre><code class=\"language-json\">{}</code></pre>\n",
+                response.row_count,
+                escape_html(json)
+            )),
+            None => result.push_str(&format!("📋 <b>Результаты ({})</b>: все строки — в JSON-файле\n", response.row_count)),
+        }
+    } else if let Some(table) = &response.table {
         if !table.is_empty() {
             result.push_str(&format!("📋 <b>Результаты ({})</b>:\n\n", response.row_count));
             
             // Если данных немного, показываем таблицу
             if response.row_count <= 10 {
                 result.push_str(table);
+            } else if crate::paging::is_paginated(response) {
+                // Все строки листаются в отдельном сообщении с кнопками навигации
+                result.push_str("📄 Все строки — постранично в сообщении ниже\n");
             } else {
                 // Если много данных, показываем первые 5 строк
                 let lines: Vec<&str> = table.lines().collect();
-                let first_lines = lines.iter().take(10).map(|s| *s).collect::<Vec<_>>().join("\n");
+                let first_lines = lines.iter().take(10).copied().collect::<Vec<_>>().join("\n");
                 result.push_str(&first_lines);
                 result.push_str(&format!("\n... и еще {} строк(и)\n", response.row_count - 5));
             }
-            result.push_str("\n");
+            result.push('\n');
         }
     } else if !response.data.is_empty() && response.row_count > 1 {
         // Если нет таблицы, но есть данные (множественные строки), показываем краткую информацию
@@ -239,112 +873,439 @@ pub fn format_query_response(response: &crate::api_client::QueryResponse) -> Str
     result
 }
 
-fn format_data_as_table(data: &[Value]) -> String {
-    if data.is_empty() {
+/// Колонка моноширинной таблицы шире стольких символов обрезается
+const MAX_TABLE_COLUMN_WIDTH: usize = 24;
+/// Разделитель колонок моноширинной таблицы
+const TABLE_COLUMN_SEPARATOR: &str = " | ";
+
+/// Форматирует строки данных в моноширинную таблицу (HTML `<pre>`).
+/// Колонки собираются по всем строкам и выравниваются по ширине символов на экране
+/// (кириллица, эмодзи); числа выровнены по правому краю и записаны по правилам языка `lang`,
+/// у колонок с суммами в заголовке указана валюта, даты приводятся к виду `31.12.2024`.
+pub fn format_data_as_table(data: &[Value], lang: Language) -> String {
+    let columns = collect_columns(data);
+    if columns.is_empty() {
         return String::new();
     }
+    let rows: Vec<Vec<String>> = data.iter()
+        .filter_map(Value::as_object)
+        .map(|row| columns.iter().map(|column| format_table_cell(row.get(column), lang)).collect())
+        .collect();
+    let numeric: Vec<bool> = columns.iter()
+        .map(|column| {
+            let mut values = data.iter().filter_map(|row| row.get(column)).filter(|value| !value.is_null()).peekable();
+            values.peek().is_some() && values.all(Value::is_number)
+        })
+        .collect();
+    let headers: Vec<String> = columns.iter()
+        .zip(&numeric)
+        .map(|(column, numeric)| crate::numbers::column_header(column, *numeric))
+        .collect();
+    format_table(&headers, &rows, &numeric)
+}
+
+/// Моноширинная таблица (HTML `<pre>`) из готовых ячеек: колонки выравниваются по ширине
+/// символов на экране, колонки `numeric` - по правому краю
+pub fn format_table(headers: &[String], rows: &[Vec<String>], numeric: &[bool]) -> String {
+    let widths: Vec<usize> = headers.iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| display_width(&row[i]))
+                .chain([display_width(column)])
+                .max()
+                .unwrap_or(0)
+                .min(MAX_TABLE_COLUMN_WIDTH)
+        })
+        .collect();
+
+    let format_line = |cells: &[String]| {
+        cells.iter()
+            .enumerate()
+            .map(|(i, cell)| pad_cell(&truncate_to_width(cell, widths[i]), widths[i], numeric[i]))
+            .collect::<Vec<_>>()
+            .join(TABLE_COLUMN_SEPARATOR)
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = vec![format_line(headers)];
+    lines.push(widths.iter().map(|width| "-".repeat(*width)).collect::<Vec<_>>().join("-+-"));
+    lines.extend(rows.iter().map(|row| format_line(row)));
+    format!("<pre>{}</pre>\n", escape_html(&lines.join("\n")))
+}
+
+/// Значение ячейки таблицы: числа по правилам языка, даты `дд.мм.гггг`, пустые значения - `—`
+fn format_table_cell(value: Option<&Value>, lang: Language) -> String {
+    match value {
+        None | Some(Value::Null) => "—".to_string(),
+        Some(Value::Bool(true)) => "да".to_string(),
+        Some(Value::Bool(false)) => "нет".to_string(),
+        Some(Value::Number(number)) => match number.as_f64() {
+            Some(value) => crate::numbers::format_number(value, lang),
+            None => number.to_string(),
+        },
+        Some(Value::String(text)) => format_table_date(text).unwrap_or_else(|| text.clone()),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Дата или время из ответа бэкенда (`2024-12-31`, `2024-12-31T10:00:00Z`, `2024-12-31 10:00:00`)
+/// в виде `31.12.2024` или `31.12.2024 10:00`; `None`, если строка не похожа на дату
+fn format_table_date(text: &str) -> Option<String> {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
 
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Some(date.format("%d.%m.%Y").to_string());
+    }
+    let date_time = DateTime::parse_from_rfc3339(text)
+        .map(|date_time| date_time.naive_local())
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f"))
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f"))
+        .ok()?;
+    if date_time.time() == NaiveTime::MIN {
+        Some(date_time.format("%d.%m.%Y").to_string())
+    } else {
+        Some(date_time.format("%d.%m.%Y %H:%M").to_string())
+    }
+}
+
+/// Ширина текста на экране в моноширинном шрифте (широкие символы занимают две клетки)
+fn display_width(text: &str) -> usize {
+    unicode_width::UnicodeWidthStr::width(text)
+}
+
+/// Обрезает текст до `width` клеток, заменяя конец на `…`
+fn truncate_to_width(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
     let mut result = String::new();
-    
-    // Получаем все ключи из первой строки
-    if let Some(first_obj) = data[0].as_object() {
-        let keys: Vec<&String> = first_obj.keys().collect();
-        
-        // Формируем заголовок
-        result.push_str("```\n");
-        for key in &keys {
-            result.push_str(&format!("{:20} | ", key));
-        }
-        result.push_str("\n");
-        result.push_str(&"-".repeat(keys.len() * 23));
-        result.push_str("\n");
-
-        // Формируем строки данных
-        for row in data {
-            if let Some(obj) = row.as_object() {
-                for key in &keys {
-                    let value = obj.get(&**key)
-                        .and_then(|v| {
-                            if v.is_number() {
-                                Some(format!("{:.2}", v.as_f64().unwrap_or(0.0)))
-                            } else {
-                                v.as_str().map(|s| s.to_string())
-                            }
-                        })
-                        .unwrap_or_else(|| "N/A".to_string());
-                    
-                    // Обрезаем длинные значения (с учетом UTF-8)
-                    let display_value = if value.len() > 18 {
-                        // Безопасное обрезание UTF-8
-                        let mut chars: Vec<char> = value.chars().take(15).collect();
-                        chars.push('…');
-                        chars.into_iter().collect::<String>()
-                    } else {
-                        value
-                    };
-                    
-                    result.push_str(&format!("{:20} | ", display_value));
-                }
-                result.push_str("\n");
+    let mut used = 0;
+    for c in text.chars() {
+        let char_width = unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+        if used + char_width + 1 > width {
+            break;
+        }
+        result.push(c);
+        used += char_width;
+    }
+    result.push('…');
+    result
+}
+
+/// Дополняет ячейку пробелами до `width` клеток (числа - слева)
+fn pad_cell(text: &str, width: usize, align_right: bool) -> String {
+    let padding = " ".repeat(width.saturating_sub(display_width(text)));
+    if align_right {
+        format!("{}{}", padding, text)
+    } else {
+        format!("{}{}", text, padding)
+    }
+}
+
+/// Лимит Telegram на длину сообщения (в UTF-16, как считает Telegram)
+pub const TELEGRAM_MESSAGE_LIMIT: usize = 4096;
+/// Ограничение Telegram на длину подписи к фото или документу
+pub const TELEGRAM_CAPTION_LIMIT: usize = 1024;
+
+/// Длина текста так, как ее считает Telegram
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Помещается ли текст в одно сообщение. Теги и `&...;` тоже считаются, так что оценка с запасом
+pub fn fits_in_message(text: &str) -> bool {
+    utf16_len(text) <= TELEGRAM_MESSAGE_LIMIT
+}
+
+/// Помещается ли текст в подпись к фото или документу
+pub fn fits_in_caption(text: &str) -> bool {
+    utf16_len(text) <= TELEGRAM_CAPTION_LIMIT
+}
+
+/// Обрезает HTML-ответ до длины подписи, не разрывая теги; обрезанный текст заканчивается «…»
+pub fn fit_caption(text: &str) -> String {
+    if fits_in_caption(text) {
+        return text.to_string();
+    }
+    let mut caption = split_message(text, TELEGRAM_CAPTION_LIMIT - 1).swap_remove(0);
+    caption.push('…');
+    caption
+}
+
+/// Открытый тег: имя и сам тег целиком (`<a href="...">`), чтобы открыть его заново
+#[derive(Clone)]
+struct OpenTag<'a> {
+    name: &'a str,
+    tag: &'a str,
+}
+
+/// Место, где можно разрезать сообщение
+struct Break<'a> {
+    /// Позиция в байтах в текущей части
+    pos: usize,
+    /// Длина части до этого места в UTF-16
+    len: usize,
+    kind: BreakKind,
+    open: Vec<OpenTag<'a>>,
+}
+
+/// Чем лучше место для разреза, тем больше значение
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum BreakKind {
+    Char,
+    Space,
+    Line,
+}
+
+/// Следующий неделимый фрагмент Telegram-HTML: тег, `&...;` или один символ
+fn next_html_token(text: &str) -> Option<&str> {
+    let c = text.chars().next()?;
+    let len = match c {
+        '<' => text.find('>').map(|end| end + 1),
+        '&' => text
+            .char_indices()
+            .take(12)
+            .find(|&(_, c)| c == ';' || c.is_whitespace())
+            .filter(|&(_, c)| c == ';')
+            .map(|(end, _)| end + 1),
+        _ => None,
+    };
+    Some(&text[..len.unwrap_or(c.len_utf8())])
+}
+
+fn is_tag(token: &str) -> bool {
+    token.starts_with('<') && token.len() > 1
+}
+
+/// Имя тега (`b`, `a`, `pre`) без `<`, `/` и атрибутов
+fn tag_name(tag: &str) -> &str {
+    let tag = tag.trim_start_matches('<').trim_start_matches('/');
+    &tag[..tag.find(|c: char| c.is_whitespace() || c == '>' || c == '/').unwrap_or(tag.len())]
+}
+
+fn closing_tags(open: &[OpenTag]) -> String {
+    open.iter().rev().map(|tag| format!("</{}>", tag.name)).collect()
+}
+
+/// Разбивает сообщение в Telegram-HTML на части не длиннее `max_len` (в UTF-16).
+///
+/// Режет по переводам строк, если их нет - по пробелам, и только в крайнем случае
+/// посреди слова, но никогда внутри тега или `&...;`. Теги, открытые на месте разреза
+/// (`<b>`, `<pre><code class="...">`), закрываются в конце части и открываются заново в следующей.
+pub fn split_message(text: &str, max_len: usize) -> Vec<String> {
+    if utf16_len(text) <= max_len {
+        return vec![text.to_string()];
+    }
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    // Начало содержимого текущей части (после заново открытых тегов)
+    let mut content_start = 0;
+    let mut open: Vec<OpenTag> = Vec::new();
+    let mut breaks: Vec<Break> = Vec::new();
+    let mut rest = text;
+
+    while let Some(token) = next_html_token(rest) {
+        rest = &rest[token.len()..];
+        current.push_str(token);
+        current_len += utf16_len(token);
+
+        let kind = if let Some(tag) = token.strip_prefix("</") {
+            let name = tag_name(tag);
+            if let Some(index) = open.iter().rposition(|tag| tag.name.eq_ignore_ascii_case(name)) {
+                open.truncate(index);
+            }
+            BreakKind::Char
+        } else if is_tag(token) {
+            open.push(OpenTag { name: tag_name(token), tag: token });
+            BreakKind::Char
+        } else if token == "\n" {
+            BreakKind::Line
+        } else if token.chars().all(char::is_whitespace) {
+            BreakKind::Space
+        } else {
+            BreakKind::Char
+        };
+        breaks.push(Break { pos: current.len(), len: current_len, kind, open: open.clone() });
+
+        while current_len + utf16_len(&closing_tags(&open)) > max_len {
+            let fits = |b: &&Break| b.pos > content_start && b.len + utf16_len(&closing_tags(&b.open)) <= max_len;
+            let Some(best) = breaks.iter().filter(fits).map(|b| b.kind).max() else {
+                // Один неделимый фрагмент длиннее лимита: отправляем как есть
+                break;
+            };
+            let Some(index) = breaks.iter().rposition(|b| b.kind == best && fits(&b)) else {
+                break;
+            };
+            let cut = breaks.remove(index);
+
+            let mut chunk = current[..cut.pos].to_string();
+            if cut.kind == BreakKind::Line {
+                chunk.pop();
             }
+            chunk.push_str(&closing_tags(&cut.open));
+            chunks.push(chunk);
+
+            let reopened: String = cut.open.iter().map(|tag| tag.tag).collect();
+            let reopened_len = utf16_len(&reopened);
+            current = format!("{}{}", reopened, &current[cut.pos..]);
+            current_len = current_len - cut.len + reopened_len;
+            content_start = reopened.len();
+            breaks = breaks
+                .into_iter()
+                .skip(index)
+                .map(|b| Break {
+                    pos: b.pos - cut.pos + reopened.len(),
+                    len: b.len - cut.len + reopened_len,
+                    ..b
+                })
+                .collect();
         }
-        
-        result.push_str("```\n");
     }
 
+    // Хвост из одних заново открытых и сразу закрытых тегов не отправляем
+    if current.len() > content_start && !is_only_tags(&current[content_start..]) {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn is_only_tags(text: &str) -> bool {
+    let mut rest = text;
+    while let Some(token) = next_html_token(rest) {
+        if !is_tag(token) && !token.trim().is_empty() {
+            return false;
+        }
+        rest = &rest[token.len()..];
+    }
+    true
+}
+
+/// Обрезает текст до `max_len` байт по границе строки
+pub fn truncate_on_line(text: &str, max_len: usize) -> String {
+    let mut result = String::new();
+    for line in text.lines() {
+        if !result.is_empty() && result.len() + line.len() + 1 > max_len {
+            break;
+        }
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        result.push_str(line);
+    }
     result
 }
 
-pub fn format_error(error: &str) -> String {
-    format!("❌ <b>Ошибка:</b>\n{}", escape_html(error))
+/// Оборачивает ответ в Telegram-HTML в самостоятельный HTML-документ
+pub fn answer_as_html_document(formatted: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"ru\">\n<head>\n<meta charset=\"utf-8\">\n<title>Payment Analytics — ответ</title>\n</head>\n<body style=\"font-family: sans-serif; white-space: pre-wrap; margin: 2em;\">\n{}\n</body>\n</html>\n",
+        formatted
+    )
+}
+
+/// Ответ в Telegram-HTML простым текстом для файла `.txt`: без тегов, с раскрытыми `&...;`
+pub fn answer_as_text_document(formatted: &str) -> String {
+    let mut text = String::with_capacity(formatted.len());
+    let mut rest = formatted;
+    while let Some(token) = next_html_token(rest) {
+        match token {
+            "&lt;" => text.push('<'),
+            "&gt;" => text.push('>'),
+            "&amp;" => text.push('&'),
+            "&quot;" => text.push('"'),
+            "&#39;" => text.push('\''),
+            token if is_tag(token) => {}
+            token => text.push_str(token),
+        }
+        rest = &rest[token.len()..];
+    }
+    text.push('\n');
+    text
 }
 
-pub fn format_help() -> String {
-    r#"📖 <b>Справка по использованию бота</b>
+/// Формат файла, которым отправляется слишком длинный ответ (`/settings longfile`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerFile {
+    /// Сохраняет оформление ответа, открывается в браузере
+    #[default]
+    Html,
+    /// Простой текст без разметки
+    Txt,
+}
 
-🤖 <b>Основные команды:</b>
-/start - Начать работу с ботом
-/help - Показать эту справку
-/clear - Очистить контекст запросов
-/status - Проверить статус бэкенда
-/menu - Показать главное меню
+impl AnswerFile {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "html" => Some(Self::Html),
+            "txt" | "text" | "текст" => Some(Self::Txt),
+            _ => None,
+        }
+    }
 
-💡 <b>Как использовать:</b>
-Просто задавайте вопросы на естественном языке, и бот автоматически сгенерирует SQL-запросы и предоставит аналитику!
+    /// Значение для `/settings longfile`, кнопок настроек и расширение файла
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Txt => "txt",
+        }
+    }
 
-🔍 <b>ОБЯЗАТЕЛЬНО: Для SQL запросов к базе данных используйте префикс:</b>
-• <b>sql:</b> - например: <code>sql: Показать транзакции за сегодня</code>
+    /// Содержимое файла с ответом в Telegram-HTML
+    pub fn render(self, formatted: &str) -> String {
+        match self {
+            Self::Html => answer_as_html_document(formatted),
+            Self::Txt => answer_as_text_document(formatted),
+        }
+    }
+}
 
-⚠️ <b>Без префикса</b> бот может неправильно определить тип запроса и ответить как в обычном чате, а не выполнить SQL запрос к базе данных.
+/// SQL запроса блоком кода
+pub fn format_sql(sql: &str) -> String {
+    format!("🔍 <b>SQL</b>\n<pre><code class=\"language-sql\">{}</code></pre>", escape_html(sql.trim()))
+}
 
-📊 <b>Примеры вопросов (с префиксом sql:):</b>
-• <code>sql:</code> Сколько транзакций было сегодня?
-• <code>sql:</code> Топ 10 городов по объему транзакций
-• <code>sql:</code> Средний чек для карт Halyk Bank
-• <code>sql:</code> Объем транзакций по категориям за месяц
-• <code>sql:</code> Распределение транзакций по валютам
+/// Число для уведомлений: целые без дробной части, остальные - с двумя знаками
+pub fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
 
-📋 <b>Указание формата вывода:</b>
-Вы можете явно указать желаемый формат вывода в запросе:
-• <b>Таблица:</b> добавьте слова "таблица", "table", "таблицу" в запрос
-  Пример: "Покажи топ категорий таблица"
-• <b>Диаграмма:</b> добавьте слова "диаграмма", "chart", "график", "визуализация" в запрос
-  Пример: "Распределение по валютам диаграмма"
-• <b>Автоматически:</b> если не указано, бот сам выберет подходящий формат
+pub fn format_error(error: &str) -> String {
+    format!("❌ <b>Ошибка:</b>\n{}", escape_html(error))
+}
 
-✨ <b>Особенности:</b>
-• Автоматическая генерация SQL из вопросов
-• Детальная аналитика с инсайтами
-• Экспорт данных в CSV
-• Генерация диаграмм
-• Поддержка русского, английского и казахского языков
-• Контекстная память ваших запросов
+/// Сообщение об ошибке бэкенда: превышение времени ожидания и перегрузку отличаем от прочих ошибок,
+/// к ошибке в самом вопросе добавляется подсказка бэкенда
+pub fn format_backend_error(lang: Language, error: &anyhow::Error, fallback: &str) -> String {
+    use crate::api_error::ApiError;
 
-Используйте конкретные вопросы для лучших результатов. Бот понимает естественный язык и автоматически оптимизирует запросы к базе данных."#
-        .to_string()
+    if crate::api_client::is_timeout(error) {
+        return tr(lang, Msg::Timeout).to_string();
+    }
+    match error.downcast_ref::<ApiError>() {
+        Some(ApiError::Transient { .. }) => tr(lang, Msg::BackendBusy).to_string(),
+        Some(ApiError::Invalid { message, .. }) if !message.trim().is_empty() => {
+            format!("{}\n\n💡 {}", format_error(fallback), escape_html(message.trim()))
+        }
+        _ => format_error(fallback),
+    }
 }
 
-pub fn create_suggestions_keyboard(questions: &[String]) -> teloxide::types::ReplyMarkup {
+pub fn format_help(lang: Language) -> String {
+    tr(lang, Msg::Help).to_string()
+}
+
+/// Клавиатура с предложенными вопросами. Вопросы, не помещающиеся в callback-данные,
+/// сохраняются в `store` и передаются коротким хешем (`q:<hash>`).
+pub fn create_suggestions_keyboard(questions: &[String], store: &SuggestionStore) -> teloxide::types::ReplyMarkup {
     use teloxide::types::InlineKeyboardButton;
     
     let mut keyboard: Vec<Vec<InlineKeyboardButton>> = Vec::new();
@@ -360,35 +1321,10 @@ pub fn create_suggestions_keyboard(questions: &[String]) -> teloxide::types::Rep
             question.to_string()
         };
         
-        // Создаем callback данные, ограничивая их до 64 байт (лимит Telegram)
         // Telegram ограничивает callback_data до 64 байт
-        let max_callback_len = 64;
-        let prefix = "query:";
-        let max_question_len = max_callback_len - prefix.len();
-        
-        // Обрезаем вопрос до максимальной длины (с учетом UTF-8)
-        let truncated_question = if question.as_bytes().len() > max_question_len {
-            // Безопасно обрезаем по байтам, но не разрываем UTF-8 символы
-            let bytes = question.as_bytes();
-            let mut len = max_question_len;
-            while len > 0 && !std::str::from_utf8(&bytes[..len]).is_ok() {
-                len -= 1;
-            }
-            std::str::from_utf8(&bytes[..len]).unwrap_or("").to_string()
-        } else {
-            question.to_string()
-        };
-        
-        let callback_data = format!("{}{}", prefix, truncated_question);
-        
-        // Финальная проверка - если все еще слишком длинный, обрезаем еще больше
-        let callback_data = if callback_data.as_bytes().len() > max_callback_len {
-            let bytes = callback_data.as_bytes();
-            let mut len = max_callback_len;
-            while len > 0 && !std::str::from_utf8(&bytes[..len]).is_ok() {
-                len -= 1;
-            }
-            std::str::from_utf8(&bytes[..len]).unwrap_or("").to_string()
+        let callback_data = format!("query:{}", question);
+        let callback_data = if callback_data.len() > 64 {
+            format!("q:{}", store.insert(question))
         } else {
             callback_data
         };
@@ -399,8 +1335,518 @@ pub fn create_suggestions_keyboard(questions: &[String]) -> teloxide::types::Rep
     teloxide::types::ReplyMarkup::InlineKeyboard(teloxide::types::InlineKeyboardMarkup::new(keyboard))
 }
 
-fn escape_html(text: &str) -> String {
+/// Расстояние Левенштейна
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Слова текста с их позициями (байтовыми); дефис внутри слова - часть слова («Усть-Каменогорск»)
+pub fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        let in_word = c.is_alphanumeric() || (c == '-' && start.is_some());
+        match (start, in_word) {
+            (None, true) => start = Some(index),
+            (Some(begin), false) => {
+                words.push((begin, text[begin..index].trim_end_matches('-')));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Пустой временный каталог для теста `name` (свой у каждого процесса)
+#[cfg(test)]
+pub fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("bot_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn escape_html(text: &str) -> String {
     text.replace("&", "&amp;")
         .replace("<", "&lt;")
         .replace(">", "&gt;")
 }
+
+/// Текст, готовый к отправке, вместе с режимом разбора Telegram
+#[derive(Debug, Clone)]
+pub struct RichText {
+    pub text: String,
+    pub parse_mode: teloxide::types::ParseMode,
+}
+
+impl RichText {
+    fn html(text: String) -> Self {
+        Self { text, parse_mode: teloxide::types::ParseMode::Html }
+    }
+}
+
+/// Готовит текстовый ответ бэкенда к отправке: бэкенд отвечает то HTML, то Markdown,
+/// то простым текстом, и в режиме HTML Markdown-ответы отображались со звездочками,
+/// а символы `<` и `&` ломали разбор сообщения.
+pub fn format_backend_text(text: &str, format: crate::config::TextFormat) -> RichText {
+    if looks_like_html(text) {
+        return RichText::html(text.to_string());
+    }
+
+    let use_markdown = match format {
+        crate::config::TextFormat::Html => false,
+        crate::config::TextFormat::MarkdownV2 => true,
+        crate::config::TextFormat::Auto => looks_like_markdown(text),
+    };
+    let spans = parse_markdown(text);
+    if use_markdown {
+        RichText {
+            text: render_markdown_v2(&spans),
+            parse_mode: teloxide::types::ParseMode::MarkdownV2,
+        }
+    } else {
+        RichText::html(render_html(&spans))
+    }
+}
+
+/// Тот же ответ в HTML (запасной вариант, если MarkdownV2 не подошел)
+pub fn format_backend_text_html(text: &str) -> String {
+    format_backend_text(text, crate::config::TextFormat::Html).text
+}
+
+fn looks_like_html(text: &str) -> bool {
+    let lower = text.to_lowercase();
+    ["<b>", "<i>", "<u>", "<code>", "<pre>", "<a href=", "<br"]
+        .iter()
+        .any(|tag| lower.contains(tag))
+}
+
+fn looks_like_markdown(text: &str) -> bool {
+    text.contains("**")
+        || text.contains("```")
+        || text.contains("__")
+        || text.matches('`').count() >= 2
+        || (text.contains("](") && text.contains('['))
+        || text.lines().any(|line| {
+            let line = line.trim_start();
+            line.starts_with("# ") || line.starts_with("## ") || line.starts_with("### ")
+        })
+}
+
+/// Фрагмент текста в простом подмножестве Markdown
+#[derive(Debug, Clone, PartialEq)]
+enum Span {
+    Text(String),
+    Bold(String),
+    Italic(String),
+    Code(String),
+    Pre { language: Option<String>, code: String },
+    Link { text: String, url: String },
+}
+
+/// Разбирает заголовки, списки, блоки ``` и inline-разметку (`**`, `__`, `*`, `_`, `` ` ``, ссылки).
+/// Вложенная разметка не поддерживается: ее содержимое остается простым текстом.
+fn parse_markdown(text: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut code_block: Option<(Option<String>, Vec<&str>)> = None;
+
+    for line in text.lines() {
+        if let Some(fence) = line.trim_start().strip_prefix("```") {
+            match code_block.take() {
+                Some((language, lines)) => {
+                    if !spans.is_empty() {
+                        spans.push(Span::Text("\n".to_string()));
+                    }
+                    spans.push(Span::Pre { language, code: lines.join("\n") });
+                }
+                None => {
+                    let language = Some(fence.trim().to_string()).filter(|lang| !lang.is_empty());
+                    code_block = Some((language, Vec::new()));
+                }
+            }
+            continue;
+        }
+        if let Some((_, lines)) = &mut code_block {
+            lines.push(line);
+            continue;
+        }
+
+        if !spans.is_empty() {
+            spans.push(Span::Text("\n".to_string()));
+        }
+        let trimmed = line.trim_start();
+        let heading = trimmed.trim_start_matches('#');
+        if heading.len() < trimmed.len() && heading.starts_with(' ') {
+            spans.push(Span::Bold(heading.trim().to_string()));
+        } else if let Some(item) = trimmed.strip_prefix("* ").or_else(|| trimmed.strip_prefix("- ")) {
+            spans.push(Span::Text("• ".to_string()));
+            parse_inline(item, &mut spans);
+        } else {
+            parse_inline(line, &mut spans);
+        }
+    }
+
+    // Незакрытый блок кода
+    if let Some((language, lines)) = code_block {
+        if !spans.is_empty() {
+            spans.push(Span::Text("\n".to_string()));
+        }
+        spans.push(Span::Pre { language, code: lines.join("\n") });
+    }
+    spans
+}
+
+fn parse_inline(line: &str, spans: &mut Vec<Span>) {
+    let mut text = String::new();
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        let previous = text.chars().last();
+        if let Some((span, consumed)) = parse_inline_entity(rest, previous) {
+            if !text.is_empty() {
+                spans.push(Span::Text(std::mem::take(&mut text)));
+            }
+            spans.push(span);
+            rest = &rest[consumed..];
+        } else {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if !text.is_empty() {
+        spans.push(Span::Text(text));
+    }
+}
+
+/// Разметка в начале `rest`: фрагмент и число прочитанных байт
+fn parse_inline_entity(rest: &str, previous: Option<char>) -> Option<(Span, usize)> {
+    if let Some(inner) = rest.strip_prefix('`') {
+        let end = inner.find('`')?;
+        return Some((Span::Code(inner[..end].to_string()), end + 2));
+    }
+    for marker in ["**", "__"] {
+        if let Some(inner) = rest.strip_prefix(marker) {
+            let end = inner.find(marker).filter(|&end| end > 0)?;
+            return Some((Span::Bold(inner[..end].to_string()), end + 4));
+        }
+    }
+    for marker in ['*', '_'] {
+        if let Some(inner) = rest.strip_prefix(marker) {
+            // snake_case и «2 * 3» - не курсив
+            if previous.is_some_and(char::is_alphanumeric) || inner.starts_with(char::is_whitespace) {
+                return None;
+            }
+            let end = inner.find(marker).filter(|&end| end > 0)?;
+            if inner[..end].ends_with(char::is_whitespace) {
+                return None;
+            }
+            return Some((Span::Italic(inner[..end].to_string()), end + 2));
+        }
+    }
+    if let Some(inner) = rest.strip_prefix('[') {
+        let text_end = inner.find("](")?;
+        let url_start = text_end + 2;
+        // Скобки внутри адреса (`.../Foo_(bar)`) должны быть парными
+        let mut depth = 0usize;
+        let url_len = inner[url_start..].find(|c| {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => return true,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            false
+        })?;
+        let span = Span::Link {
+            text: inner[..text_end].to_string(),
+            url: inner[url_start..url_start + url_len].to_string(),
+        };
+        return Some((span, 1 + url_start + url_len + 1));
+    }
+    None
+}
+
+fn render_html(spans: &[Span]) -> String {
+    let mut out = String::new();
+    for span in spans {
+        match span {
+            Span::Text(text) => out.push_str(&escape_html(text)),
+            Span::Bold(text) => out.push_str(&format!("<b>{}</b>", escape_html(text))),
+            Span::Italic(text) => out.push_str(&format!("<i>{}</i>", escape_html(text))),
+            Span::Code(code) => out.push_str(&format!("<code>{}</code>", escape_html(code))),
+            Span::Pre { language: Some(language), code } => out.push_str(&format!(
+                "<pre><code class=\"language-{}\">{}</code></pre>",
+                escape_html(language),
+                escape_html(code)
+            )),
+            Span::Pre { language: None, code } => out.push_str(&format!("<pre>{}</pre>", escape_html(code))),
+            Span::Link { text, url } => out.push_str(&format!(
+                "<a href=\"{}\">{}</a>",
+                escape_html(url).replace('"', "&quot;"),
+                escape_html(text)
+            )),
+        }
+    }
+    out
+}
+
+fn render_markdown_v2(spans: &[Span]) -> String {
+    let mut out = String::new();
+    for span in spans {
+        match span {
+            Span::Text(text) => out.push_str(&escape_markdown_v2(text)),
+            Span::Bold(text) => out.push_str(&format!("*{}*", escape_markdown_v2(text))),
+            Span::Italic(text) => out.push_str(&format!("_{}_", escape_markdown_v2(text))),
+            Span::Code(code) => out.push_str(&format!("`{}`", escape_markdown_v2_code(code))),
+            Span::Pre { language, code } => out.push_str(&format!(
+                "```{}\n{}\n```",
+                language.as_deref().unwrap_or(""),
+                escape_markdown_v2_code(code)
+            )),
+            Span::Link { text, url } => out.push_str(&format!(
+                "[{}]({})",
+                escape_markdown_v2(text),
+                url.replace('\\', "\\\\").replace(')', "\\)")
+            )),
+        }
+    }
+    out
+}
+
+/// Экранирует все символы, которые MarkdownV2 считает разметкой
+pub fn escape_markdown_v2(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Внутри `code` и ```pre``` экранируются только `` ` `` и `\`
+fn escape_markdown_v2_code(code: &str) -> String {
+    code.replace('\\', "\\\\").replace('`', "\\`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Строки таблицы (ru) без обертки `<pre>`; неразрывные пробелы заменены обычными
+    fn table_lines(data: &[Value]) -> Vec<String> {
+        let table = format_data_as_table(data, Language::Ru).replace('\u{a0}', " ");
+        let inner = table.trim_end().strip_prefix("<pre>").and_then(|t| t.strip_suffix("</pre>")).unwrap();
+        inner.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn insights_are_sorted_filtered_and_collapsed() {
+        let significance = ["Low", "High", "Medium", "High", "Low", "Medium", "Low"];
+        let insights: Vec<Value> = significance
+            .iter()
+            .enumerate()
+            .map(|(i, significance)| json!({"title": format!("Вывод {}", i), "description": "", "significance": significance}))
+            .collect();
+        let response: crate::api_client::QueryResponse = serde_json::from_value(json!({
+            "question": "Динамика",
+            "data": [],
+            "execution_time_ms": 1,
+            "row_count": 0,
+            "analysis": {"headline": "Итог", "insights": insights, "explanation": "", "suggested_questions": []},
+        }))
+        .unwrap();
+        let titles = |text: &str| {
+            text.match_indices("Вывод ").map(|(at, _)| text[at..].chars().nth(6).unwrap()).collect::<String>()
+        };
+
+        let collapsed = InsightOptions { min_level: InsightLevel::Low, expanded: false };
+        let text = format_query_response_with(&response, DataPlacement::Text, collapsed, Language::Ru);
+        assert_eq!(titles(&text), "13250");
+        assert!(text.contains("… и еще выводов: 2"));
+
+        let text = format_query_response(&response, InsightLevel::High, Language::Ru);
+        assert_eq!(titles(&text), "13");
+        assert!(text.contains("Менее важных выводов скрыто: 5"));
+    }
+
+    #[test]
+    fn table_aligns_cyrillic_and_right_aligns_numbers() {
+        let data = [
+            json!({"город": "Алматы", "сумма": 1234567.891, "кол": 5}),
+            json!({"город": "Шымкент", "сумма": 12.5, "кол": 12000}),
+        ];
+
+        assert_eq!(table_lines(&data), [
+            "город   |    кол |     сумма, ₸",
+            "--------+--------+-------------",
+            "Алматы  |      5 | 1 234 567,89",
+            "Шымкент | 12 000 |        12,50",
+        ]);
+    }
+
+    #[test]
+    fn table_handles_mixed_types_and_missing_keys() {
+        let data = [
+            json!({"день": "2024-03-01", "активен": true, "теги": ["a"]}),
+            json!({"день": "2024-03-02T10:30:00Z", "активен": null, "сумма": 1000}),
+        ];
+
+        assert_eq!(table_lines(&data), [
+            "активен | день             | теги  | сумма, ₸",
+            "--------+------------------+-------+---------",
+            "да      | 01.03.2024       | [\"a\"] |        —",
+            "—       | 02.03.2024 10:30 | —     |    1 000",
+        ]);
+    }
+
+    #[test]
+    fn table_pads_wide_characters_and_truncates_long_values() {
+        let data = [json!({"emoji": "🔥🔥", "name": "<b>".repeat(20)})];
+        let lines = table_lines(&data);
+
+        assert_eq!(lines[0], "emoji | name");
+        assert!(lines[2].starts_with("🔥🔥  | &lt;b&gt;"), "{}", lines[2]);
+        assert!(lines[2].ends_with('…'), "{}", lines[2]);
+        let value = lines[2].split(" | ").nth(1).unwrap().replace("&lt;", "<").replace("&gt;", ">");
+        assert_eq!(display_width(&value), MAX_TABLE_COLUMN_WIDTH);
+    }
+
+    #[test]
+    fn table_numbers_follow_the_language() {
+        assert_eq!(format_table_cell(Some(&json!(-1234.5)), Language::Ru), "-1\u{a0}234,50");
+        assert_eq!(format_table_cell(Some(&json!(1234567)), Language::En), "1,234,567");
+        assert_eq!(format_table_cell(Some(&json!(2.0)), Language::Ru), "2");
+        assert_eq!(format_table_cell(Some(&json!("2024-02-30")), Language::Ru), "2024-02-30");
+    }
+
+    /// Проверяет, что каждая часть укладывается в лимит и все теги в ней закрыты
+    fn assert_valid_chunks(chunks: &[String], max_len: usize) {
+        for chunk in chunks {
+            assert!(utf16_len(chunk) <= max_len, "chunk is too long: {:?}", chunk);
+            let mut open = Vec::new();
+            let mut rest = chunk.as_str();
+            while let Some(token) = next_html_token(rest) {
+                if let Some(tag) = token.strip_prefix("</") {
+                    assert_eq!(open.pop(), Some(tag_name(tag)), "unbalanced tags in {:?}", chunk);
+                } else if is_tag(token) {
+                    open.push(tag_name(token));
+                }
+                rest = &rest[token.len()..];
+            }
+            assert!(open.is_empty(), "unclosed tags in {:?}", chunk);
+        }
+    }
+
+    fn strip_tags(text: &str) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(token) = next_html_token(rest) {
+            if !is_tag(token) {
+                out.push_str(token);
+            }
+            rest = &rest[token.len()..];
+        }
+        out
+    }
+
+    #[test]
+    fn text_document_drops_markup() {
+        assert_eq!(
+            answer_as_text_document("📊 <b>Итого</b>\n<pre>Almaty &amp; Astana &lt;10&gt;</pre>"),
+            "📊 Итого\nAlmaty & Astana <10>\n"
+        );
+    }
+
+    #[test]
+    fn short_message_is_not_split() {
+        assert_eq!(split_message("<b>Итого</b>: 42", 100), vec!["<b>Итого</b>: 42"]);
+    }
+
+    #[test]
+    fn splits_on_line_boundaries() {
+        let text = "первая строка\nвторая строка\nтретья строка";
+        let chunks = split_message(text, 30);
+        assert_eq!(chunks, vec!["первая строка\nвторая строка", "третья строка"]);
+    }
+
+    #[test]
+    fn prefers_spaces_over_cutting_words() {
+        let chunks = split_message("один два три четыре", 10);
+        assert_valid_chunks(&chunks, 10);
+        assert_eq!(chunks.concat(), "один два три четыре");
+        assert!(chunks[..chunks.len() - 1].iter().all(|chunk| chunk.ends_with(' ')), "{:?}", chunks);
+    }
+
+    #[test]
+    fn closes_and_reopens_bold_across_chunks() {
+        let text = format!("<b>{}</b>", "жирный текст ".repeat(20));
+        let chunks = split_message(&text, 60);
+        assert!(chunks.len() > 1);
+        assert_valid_chunks(&chunks, 60);
+        assert!(chunks.iter().all(|chunk| chunk.starts_with("<b>") && chunk.ends_with("</b>")));
+        assert_eq!(strip_tags(&chunks.concat()), strip_tags(&text));
+    }
+
+    #[test]
+    fn reopens_code_block_with_attributes() {
+        let code = (1..=30).map(|i| format!("SELECT {} FROM t;", i)).collect::<Vec<_>>().join("\n");
+        let text = format!("Запрос:\n<pre><code class=\"language-sql\">{}</code></pre>", code);
+        let chunks = split_message(&text, 200);
+        assert!(chunks.len() > 1);
+        assert_valid_chunks(&chunks, 200);
+        for chunk in &chunks[1..] {
+            assert!(chunk.starts_with("<pre><code class=\"language-sql\">"), "{:?}", chunk);
+        }
+    }
+
+    #[test]
+    fn keeps_nested_tags_in_order() {
+        let text = format!("<b>Итоги <i>{}</i> конец</b>", "курсив ".repeat(30));
+        let chunks = split_message(&text, 50);
+        assert_valid_chunks(&chunks, 50);
+        assert!(chunks[1].starts_with("<b><i>"));
+        assert!(chunks[0].ends_with("</i></b>"));
+        assert_eq!(strip_tags(&chunks.concat()), strip_tags(&text));
+    }
+
+    #[test]
+    fn never_splits_entities_or_tags() {
+        let text = format!("<a href=\"https://example.com/report\">{}</a>", "&amp;&lt;".repeat(40));
+        let chunks = split_message(&text, 64);
+        assert_valid_chunks(&chunks, 64);
+        for chunk in &chunks {
+            let content = strip_tags(chunk);
+            assert!(content.split_inclusive(';').all(|entity| entity == "&amp;" || entity == "&lt;"), "{:?}", chunk);
+        }
+    }
+
+    #[test]
+    fn cuts_multibyte_text_on_char_boundaries() {
+        // Ни пробелов, ни переводов строк: режется посреди слова, но не посреди символа
+        let text = "😀Ақша€".repeat(50);
+        let chunks = split_message(&text, 25);
+        assert_valid_chunks(&chunks, 25);
+        assert_eq!(chunks.concat(), text);
+    }
+
+    #[test]
+    fn counts_length_in_utf16_units() {
+        // Эмодзи занимает две единицы UTF-16, поэтому 10 эмодзи не помещаются в 10
+        let chunks = split_message(&"😀".repeat(10), 10);
+        assert_eq!(chunks, vec!["😀".repeat(5), "😀".repeat(5)]);
+    }
+}
@@ -0,0 +1,435 @@
+use super::{collect_columns, escape_html, format_table};
+use crate::language::Language;
+use crate::numbers::format_number;
+use serde_json::Value;
+
+/// Больше групп в ответе не показывается
+const MAX_GROUPS: usize = 30;
+
+/// Для скольких колонок-показателей предлагаются быстрые действия
+const MAX_QUICK_COLUMNS: usize = 2;
+
+/// Слова, которые могут стоять в запросе между агрегатом и колонками
+const FILLER_WORDS: &[&str] = &[
+    "по", "колонке", "колонки", "колонка", "столбцу", "столбца", "полю", "поля", "в", "разрезе", "для", "всех",
+    "строк", "значений", "by", "of", "column", "per", "for", "rows", "all",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Avg,
+    Count,
+}
+
+impl Aggregate {
+    /// Агрегат по первому слову запроса
+    fn parse(word: &str) -> Option<Self> {
+        match word {
+            "сумма" | "сумму" | "итого" | "sum" | "total" => Some(Self::Sum),
+            "среднее" | "средний" | "средняя" | "avg" | "average" | "mean" => Some(Self::Avg),
+            "количество" | "кол-во" | "число" | "count" => Some(Self::Count),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Sum => "Сумма",
+            Self::Avg => "Среднее",
+            Self::Count => "Количество строк",
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Sum => "Σ",
+            Self::Avg => "⌀",
+            Self::Count => "#",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Ne => "≠",
+            Self::Gt => ">",
+            Self::Ge => "≥",
+            Self::Lt => "<",
+            Self::Le => "≤",
+        }
+    }
+}
+
+/// Фильтр строк: `city = Almaty`, `amount > 1000`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    column: String,
+    op: Op,
+    value: String,
+}
+
+impl Condition {
+    /// `колонка оператор значение`; колонка должна быть среди `columns`
+    fn parse(text: &str, columns: &[String]) -> Option<Self> {
+        const OPS: [(&str, Op); 8] = [
+            (">=", Op::Ge), ("<=", Op::Le), ("!=", Op::Ne), ("<>", Op::Ne), ("=", Op::Eq), (">", Op::Gt), ("<", Op::Lt), ("≠", Op::Ne),
+        ];
+        let (position, symbol, op) = OPS
+            .iter()
+            .filter_map(|(symbol, op)| text.find(symbol).map(|position| (position, *symbol, *op)))
+            .min_by_key(|(position, symbol, _)| (*position, std::cmp::Reverse(symbol.len())))?;
+        let column = find_column(text[..position].trim(), columns)?;
+        let value = text[position + symbol.len()..].trim().trim_matches(|c| matches!(c, '"' | '\'' | '«' | '»'));
+        if value.is_empty() {
+            return None;
+        }
+        Some(Self { column, op, value: value.to_string() })
+    }
+
+    fn matches(&self, row: &Value) -> bool {
+        let Some(cell) = row.get(&self.column).filter(|cell| !cell.is_null()) else {
+            return self.op == Op::Ne;
+        };
+        let ordering = match (cell.as_f64(), self.value.replace(',', ".").parse::<f64>()) {
+            (Some(cell), Ok(value)) => cell.partial_cmp(&value),
+            _ => {
+                let cell = match cell {
+                    Value::String(text) => text.to_lowercase(),
+                    other => other.to_string(),
+                };
+                Some(cell.cmp(&self.value.to_lowercase()))
+            }
+        };
+        let Some(ordering) = ordering else {
+            return false;
+        };
+        match self.op {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+        }
+    }
+}
+
+/// Вычисление над строками результата: агрегат колонки `column`, по группам `group_by`,
+/// только по строкам, подходящим под `filter`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub aggregate: Aggregate,
+    pub column: Option<String>,
+    pub group_by: Option<String>,
+    pub filter: Option<Condition>,
+}
+
+impl Query {
+    /// Разбирает короткое уточнение: «сумма по колонке amount», «среднее amount по city»,
+    /// «количество по city где amount > 1000». Каждое слово, кроме служебных, должно быть
+    /// названием колонки из `columns` - иначе это обычный вопрос и он уходит бэкенду.
+    pub fn parse(text: &str, columns: &[String]) -> Option<Self> {
+        let text = text.trim().trim_end_matches(['?', '.', '!']).to_lowercase();
+        let (head, filter) = match text.split_once(" где ").or_else(|| text.split_once(" where ")) {
+            Some((head, filter)) => (head.to_string(), Some(Condition::parse(filter, columns)?)),
+            None => (text, None),
+        };
+
+        let mut tokens = head.split_whitespace().map(|token| token.trim_matches(|c: char| !c.is_alphanumeric() && c != '_' && c != '-'));
+        let aggregate = Aggregate::parse(tokens.next()?)?;
+        let mut mentioned = Vec::new();
+        for token in tokens.filter(|token| !token.is_empty()) {
+            if FILLER_WORDS.contains(&token) {
+                continue;
+            }
+            mentioned.push(find_column(token, columns)?);
+        }
+
+        let mut mentioned = mentioned.into_iter();
+        let (column, group_by) = match aggregate {
+            Aggregate::Count => (None, mentioned.next()),
+            Aggregate::Sum | Aggregate::Avg => (Some(mentioned.next()?), mentioned.next()),
+        };
+        if mentioned.next().is_some() {
+            return None;
+        }
+        Some(Self { aggregate, column, group_by, filter })
+    }
+
+    /// Подпись кнопки: «Σ amount», «⌀ amount по city», «# строк»
+    pub fn label(&self) -> String {
+        let mut label = self.aggregate.symbol().to_string();
+        match &self.column {
+            Some(column) => label.push_str(&format!(" {}", column)),
+            None => label.push_str(" строк"),
+        }
+        if let Some(group_by) = &self.group_by {
+            label.push_str(&format!(" по {}", group_by));
+        }
+        label
+    }
+
+    /// Заголовок результата: «Сумма amount по city (где city = Almaty)»
+    fn title(&self) -> String {
+        let mut title = self.aggregate.name().to_string();
+        if let Some(column) = &self.column {
+            title.push_str(&format!(" {}", column));
+        }
+        if let Some(group_by) = &self.group_by {
+            title.push_str(&format!(" по {}", group_by));
+        }
+        if let Some(filter) = &self.filter {
+            title.push_str(&format!(" (где {} {} {})", filter.column, filter.op.as_str(), filter.value));
+        }
+        title
+    }
+
+    /// Значения по группам в порядке убывания (без группировки - одна группа «Итого»).
+    /// Ошибка - текст для пользователя, если колонка не числовая.
+    pub fn run(&self, rows: &[Value]) -> Result<Vec<(String, f64)>, String> {
+        let rows: Vec<&Value> = rows.iter().filter(|row| self.filter.as_ref().is_none_or(|filter| filter.matches(row))).collect();
+
+        let mut groups: Vec<(String, Vec<&Value>)> = Vec::new();
+        for row in rows {
+            let key = match &self.group_by {
+                Some(group_by) => match row.get(group_by) {
+                    Some(Value::String(text)) => text.clone(),
+                    None | Some(Value::Null) => "—".to_string(),
+                    Some(value) => value.to_string(),
+                },
+                None => "Итого".to_string(),
+            };
+            match groups.iter_mut().find(|(known, _)| *known == key) {
+                Some((_, members)) => members.push(row),
+                None => groups.push((key, vec![row])),
+            }
+        }
+        if groups.is_empty() && self.group_by.is_none() {
+            groups.push(("Итого".to_string(), Vec::new()));
+        }
+
+        let mut result = Vec::with_capacity(groups.len());
+        for (key, members) in groups {
+            let values: Vec<f64> = match &self.column {
+                Some(column) => {
+                    let cells: Vec<&Value> = members.iter().filter_map(|row| row.get(column)).filter(|cell| !cell.is_null()).collect();
+                    if cells.iter().any(|cell| !cell.is_number()) {
+                        return Err(format!("Колонка {} не числовая", column));
+                    }
+                    cells.iter().filter_map(|cell| cell.as_f64()).collect()
+                }
+                None => Vec::new(),
+            };
+            let value = match self.aggregate {
+                Aggregate::Sum => values.iter().sum(),
+                Aggregate::Avg if values.is_empty() => continue,
+                Aggregate::Avg => values.iter().sum::<f64>() / values.len() as f64,
+                Aggregate::Count => members.len() as f64,
+            };
+            result.push((key, value));
+        }
+        if self.group_by.is_some() {
+            result.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        }
+        Ok(result)
+    }
+
+    /// Результат вычисления для ответа в чат (HTML)
+    pub fn render(&self, rows: &[Value], lang: Language) -> String {
+        let mut text = format!("🧮 <b>{}</b>\n", escape_html(&self.title()));
+        let groups = match self.run(rows) {
+            Ok(groups) => groups,
+            Err(error) => return format!("{}\n⚠️ {}", text, escape_html(&error)),
+        };
+        match &self.group_by {
+            None => {
+                let value = groups.first().map_or_else(|| "—".to_string(), |(_, value)| format_number(*value, lang));
+                text.push_str(&format!("\n<b>{}</b>\n", value));
+            }
+            Some(group_by) => {
+                let headers = [group_by.clone(), self.aggregate.name().to_lowercase()];
+                let cells: Vec<Vec<String>> = groups
+                    .iter()
+                    .take(MAX_GROUPS)
+                    .map(|(key, value)| vec![key.clone(), format_number(*value, lang)])
+                    .collect();
+                text.push('\n');
+                text.push_str(&format_table(&headers, &cells, &[false, true]));
+                if groups.len() > MAX_GROUPS {
+                    text.push_str(&format!("<i>… и еще групп: {}</i>\n", groups.len() - MAX_GROUPS));
+                }
+            }
+        }
+        text.push_str("\n<i>Посчитано по уже полученным строкам, без запроса к бэкенду</i>");
+        text
+    }
+}
+
+/// Колонка, название которой совпадает со словом без учета регистра
+fn find_column(word: &str, columns: &[String]) -> Option<String> {
+    columns.iter().find(|column| column.to_lowercase() == word.to_lowercase()).cloned()
+}
+
+/// Части названий колонок-показателей: их сумма и среднее имеют смысл
+const METRIC_NAME_PARTS: &[&str] = &[
+    "amount", "sum", "total", "count", "cnt", "qty", "quantity", "volume", "avg", "average", "price", "revenue",
+    "turnover", "balance", "fee", "share", "percent", "pct", "сумм", "оборот", "количеств", "средн", "доля",
+];
+
+/// Части названий колонок-ключей: идентификаторы, коды и календарные поля
+const KEY_NAME_PARTS: &[&str] = &[
+    "mcc", "code", "year", "month", "week", "day", "quarter", "hour", "date", "phone", "код", "год", "месяц", "день",
+];
+
+/// Колонка-идентификатор по названию: `id`, `*_id`, `id_*`
+fn is_id_name(column: &str) -> bool {
+    column == "id" || column.ends_with("_id") || column.starts_with("id_")
+}
+
+/// Числовая колонка-показатель, а не ключ: по названию (`account_id` - ключ, хотя в нем есть
+/// `count`), а для названий без подсказки - по значениям (все разные целые - это идентификаторы,
+/// целые 1900-2100 - годы)
+fn is_metric(column: &str, cells: &[&Value]) -> bool {
+    let name = column.to_lowercase();
+    if is_id_name(&name) {
+        return false;
+    }
+    if METRIC_NAME_PARTS.iter().any(|part| name.contains(part)) {
+        return true;
+    }
+    if KEY_NAME_PARTS.iter().any(|part| name.contains(part)) {
+        return false;
+    }
+    let integers: Vec<i64> = cells.iter().filter_map(|cell| cell.as_i64()).collect();
+    if integers.len() < cells.len() {
+        return true;
+    }
+    let mut distinct = integers.clone();
+    distinct.sort_unstable();
+    distinct.dedup();
+    let unique_ids = integers.len() > 2 && distinct.len() == integers.len();
+    let years = integers.iter().all(|value| (1900..=2100).contains(value));
+    !unique_ids && !years
+}
+
+/// Быстрые действия для результата: сумма и среднее первых колонок-показателей (не ключей:
+/// id, MCC, годы), сумма по первой текстовой колонке и количество строк. Пусто, если считать нечего.
+pub fn quick_actions(rows: &[Value]) -> Vec<Query> {
+    if rows.len() < 2 {
+        return Vec::new();
+    }
+    let mut numeric = Vec::new();
+    let mut text = Vec::new();
+    for column in collect_columns(rows) {
+        let cells: Vec<&Value> = rows.iter().filter_map(|row| row.get(&column)).filter(|cell| !cell.is_null()).collect();
+        if cells.is_empty() || !cells.iter().all(|cell| cell.is_number()) {
+            text.push(column);
+        } else if is_metric(&column, &cells) {
+            numeric.push(column);
+        }
+    }
+    if numeric.is_empty() {
+        return Vec::new();
+    }
+
+    let query = |aggregate, column: Option<&String>, group_by: Option<&String>| Query {
+        aggregate,
+        column: column.cloned(),
+        group_by: group_by.cloned(),
+        filter: None,
+    };
+    let mut actions = Vec::new();
+    for column in numeric.iter().take(MAX_QUICK_COLUMNS) {
+        actions.push(query(Aggregate::Sum, Some(column), None));
+        actions.push(query(Aggregate::Avg, Some(column), None));
+    }
+    if let Some(key) = text.first() {
+        actions.push(query(Aggregate::Sum, numeric.first(), Some(key)));
+        actions.push(query(Aggregate::Count, None, Some(key)));
+    } else {
+        actions.push(query(Aggregate::Count, None, None));
+    }
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rows() -> Vec<Value> {
+        vec![
+            json!({"city": "Almaty", "total_amount": 100.0, "count": 2}),
+            json!({"city": "Astana", "total_amount": 50.0, "count": 1}),
+            json!({"city": "Almaty", "total_amount": 300.0, "count": 3}),
+        ]
+    }
+
+    fn columns() -> Vec<String> {
+        collect_columns(&rows())
+    }
+
+    #[test]
+    fn follow_ups_with_column_names_are_parsed() {
+        let query = Query::parse("Сумма по колонке total_amount", &columns()).unwrap();
+        assert_eq!((query.aggregate, query.column.as_deref(), query.group_by), (Aggregate::Sum, Some("total_amount"), None));
+
+        let query = Query::parse("среднее total_amount по city где count >= 2", &columns()).unwrap();
+        assert_eq!(query.group_by.as_deref(), Some("city"));
+        assert_eq!(query.run(&rows()).unwrap(), [("Almaty".to_string(), 200.0)]);
+
+        let query = Query::parse("количество по city", &columns()).unwrap();
+        assert_eq!(query.run(&rows()).unwrap(), [("Almaty".to_string(), 2.0), ("Astana".to_string(), 1.0)]);
+
+        // Обычные вопросы уходят бэкенду
+        assert!(Query::parse("сумма оборота по городам", &columns()).is_none());
+        assert!(Query::parse("Топ 10 городов по total_amount", &columns()).is_none());
+        assert!(Query::parse("сумма по колонке total_amount где", &columns()).is_none());
+    }
+
+    #[test]
+    fn aggregates_are_computed_over_filtered_rows() {
+        let sum = Query::parse("сумма total_amount где city = almaty", &columns()).unwrap();
+        assert_eq!(sum.run(&rows()).unwrap(), [("Итого".to_string(), 400.0)]);
+        let text = sum.render(&rows(), Language::Ru);
+        assert!(text.starts_with("🧮 <b>Сумма total_amount (где city = almaty)</b>"), "{}", text);
+        assert!(text.contains("<b>400</b>"), "{}", text);
+
+        let not_numeric = Query::parse("сумма city", &columns()).unwrap();
+        assert_eq!(not_numeric.run(&rows()).unwrap_err(), "Колонка city не числовая");
+    }
+
+    #[test]
+    fn quick_actions_cover_numeric_and_key_columns() {
+        let labels: Vec<String> = quick_actions(&rows()).iter().map(Query::label).collect();
+        assert_eq!(labels, ["Σ count", "⌀ count", "Σ total_amount", "⌀ total_amount", "Σ count по city", "# строк по city"]);
+        assert!(quick_actions(&[json!({"city": "Almaty"}), json!({"city": "Astana"})]).is_empty());
+    }
+
+    #[test]
+    fn quick_actions_skip_ids_codes_and_years() {
+        let rows = vec![
+            json!({"id": 1, "account_id": 7, "mcc": 5411, "year": 2023, "ref": 90017, "score": 4, "amount": 100.5}),
+            json!({"id": 2, "account_id": 7, "mcc": 5812, "year": 2024, "ref": 90342, "score": 4, "amount": 20.0}),
+            json!({"id": 3, "account_id": 8, "mcc": 5411, "year": 2024, "ref": 91005, "score": 5, "amount": 7.0}),
+        ];
+        let labels: Vec<String> = quick_actions(&rows).iter().map(Query::label).collect();
+        assert_eq!(labels, ["Σ amount", "⌀ amount", "Σ score", "⌀ score", "# строк"]);
+
+        let only_keys = [json!({"id": 1, "year": 2023, "name": "a"}), json!({"id": 2, "year": 2024, "name": "b"})];
+        assert!(quick_actions(&only_keys).is_empty());
+    }
+}
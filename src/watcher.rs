@@ -0,0 +1,361 @@
+use crate::api_client::{OutputType, QueryRequest, QueryResponse};
+use crate::messenger::{MessageSender, Outgoing};
+use crate::state::BotState;
+use crate::utils::{escape_html, format_value};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use teloxide::prelude::*;
+use tracing::{error, info, warn};
+
+/// Как часто проверяется, не пора ли выполнить запросы оповещений
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Сколько оповещений может быть в одном чате
+pub const MAX_ALERTS_PER_CHAT: usize = 10;
+/// Интервал проверки по умолчанию и допустимые границы, в минутах
+pub const DEFAULT_INTERVAL_MINS: u32 = 15;
+const MIN_INTERVAL_MINS: u32 = 5;
+const MAX_INTERVAL_MINS: u32 = 24 * 60;
+
+/// Условие срабатывания оповещения
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Above,
+    AboveOrEqual,
+    Below,
+    BelowOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    /// Операторы в порядке разбора: двухсимвольные раньше односимвольных
+    const OPERATORS: [(&'static str, Self); 9] = [
+        (">=", Self::AboveOrEqual),
+        ("<=", Self::BelowOrEqual),
+        ("!=", Self::NotEqual),
+        ("==", Self::Equal),
+        ("≥", Self::AboveOrEqual),
+        ("≤", Self::BelowOrEqual),
+        (">", Self::Above),
+        ("<", Self::Below),
+        ("=", Self::Equal),
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Above => ">",
+            Self::AboveOrEqual => "≥",
+            Self::Below => "<",
+            Self::BelowOrEqual => "≤",
+            Self::Equal => "=",
+            Self::NotEqual => "≠",
+        }
+    }
+
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Above => value > threshold,
+            Self::AboveOrEqual => value >= threshold,
+            Self::Below => value < threshold,
+            Self::BelowOrEqual => value <= threshold,
+            Self::Equal => value == threshold,
+            Self::NotEqual => value != threshold,
+        }
+    }
+}
+
+/// Вопрос, который бот периодически выполняет и сообщает в чат, когда его значение выходит за порог
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub id: u64,
+    pub chat_id: i64,
+    /// Ключ контекста пользователя, создавшего оповещение (см. `CONTEXT_SCOPE`)
+    pub user_id: String,
+    pub question: String,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    pub interval_mins: u32,
+    pub created_at: DateTime<Utc>,
+    pub last_checked: Option<DateTime<Utc>>,
+    /// Первое число ответа при последней проверке
+    pub last_value: Option<f64>,
+    /// Условие выполнялось при последней проверке: повторно о нем не сообщаем, пока значение не вернется
+    pub triggered: bool,
+}
+
+impl Alert {
+    /// Условие для списка оповещений и уведомлений: `> 1000000`
+    pub fn condition(&self) -> String {
+        format!("{} {}", self.comparison.symbol(), format_value(self.threshold))
+    }
+
+    /// Периодичность для списка оповещений
+    pub fn describe_interval(&self) -> String {
+        if self.interval_mins.is_multiple_of(60) {
+            format!("каждые {} ч", self.interval_mins / 60)
+        } else {
+            format!("каждые {} мин", self.interval_mins)
+        }
+    }
+
+    fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.last_checked
+            .is_none_or(|checked| now - checked >= Duration::minutes(i64::from(self.interval_mins)))
+    }
+}
+
+/// Разбирает `"объем транзакций за час" > 1000000 every 15m`.
+/// Кавычки вокруг вопроса необязательны; интервал - `every 15m`, `каждые 2 ч` и т.п.
+/// Возвращает вопрос, условие, порог и интервал в минутах.
+pub fn parse_alert(text: &str) -> Option<(String, Comparison, f64, u32)> {
+    let text = text.trim();
+    let (question, rest) = match text.chars().next()? {
+        quote @ ('"' | '«' | '“') => {
+            let closing = match quote {
+                '«' => '»',
+                '“' => '”',
+                _ => '"',
+            };
+            let inner = &text[quote.len_utf8()..];
+            let end = inner.find(closing)?;
+            (inner[..end].trim(), &inner[end + closing.len_utf8()..])
+        }
+        _ => {
+            let position = Comparison::OPERATORS.iter()
+                .filter_map(|(operator, _)| text.find(operator))
+                .min()?;
+            (text[..position].trim(), &text[position..])
+        }
+    };
+    if question.is_empty() {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    let (comparison, rest) = Comparison::OPERATORS.iter()
+        .find_map(|(operator, comparison)| rest.strip_prefix(operator).map(|rest| (*comparison, rest)))?;
+
+    let mut words = rest.split_whitespace();
+    let threshold = parse_number(words.next()?)?;
+    let interval = match words.next() {
+        None => DEFAULT_INTERVAL_MINS,
+        Some(word) if ["every", "каждые", "каждый", "каждую"].contains(&word.to_lowercase().as_str()) => {
+            parse_interval(&words.collect::<Vec<_>>().join(""))?
+        }
+        Some(_) => return None,
+    };
+    Some((question.to_string(), comparison, threshold, interval))
+}
+
+/// `1000000`, `1_000_000`, `1.5`, `1,5`, `2.5k`, `3m`, `1млн`
+fn parse_number(word: &str) -> Option<f64> {
+    let word = word.to_lowercase().replace('_', "").replace(',', ".");
+    let (digits, multiplier) = [("млрд", 1e9), ("млн", 1e6), ("тыс", 1e3), ("b", 1e9), ("m", 1e6), ("k", 1e3)]
+        .iter()
+        .find_map(|(suffix, multiplier)| word.strip_suffix(suffix).map(|digits| (digits, *multiplier)))
+        .unwrap_or((word.as_str(), 1.0));
+    digits.parse::<f64>().ok().filter(|value| value.is_finite()).map(|value| value * multiplier)
+}
+
+/// `15m`, `15мин`, `2h`, `2ч`, `1d`; без единицы - минуты. Интервал ограничивается от 5 минут до суток.
+fn parse_interval(text: &str) -> Option<u32> {
+    let text = text.to_lowercase();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let amount: u32 = text[..split].parse().ok().filter(|amount| *amount > 0)?;
+    let minutes = match &text[split..] {
+        "" | "m" | "min" | "мин" | "минут" | "минуты" => amount,
+        "h" | "ч" | "час" | "часа" | "часов" => amount.checked_mul(60)?,
+        "d" | "д" | "день" | "дня" | "дней" => amount.checked_mul(24 * 60)?,
+        _ => return None,
+    };
+    Some(minutes.clamp(MIN_INTERVAL_MINS, MAX_INTERVAL_MINS))
+}
+
+/// Первое число в первой строке ответа (числа в виде строк тоже учитываются)
+fn first_number(response: &QueryResponse) -> Option<f64> {
+    let row = response.data.first()?.as_object()?;
+    row.values().find_map(|value| match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse::<f64>().ok(),
+        _ => None,
+    })
+}
+
+/// Запускает фоновую проверку оповещений
+pub fn spawn(bot: Bot, state: Arc<BotState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            check_due_alerts(&bot, &state).await;
+        }
+    });
+}
+
+async fn check_due_alerts<S: MessageSender>(bot: &S, state: &Arc<BotState>) {
+    let now = Utc::now();
+    let due: Vec<Alert> = state.storage.alerts().await
+        .into_iter()
+        .filter(|alert| alert.is_due(now))
+        .collect();
+    // Пока бэкенд недоступен, проверки откладываются до следующего тика
+    if due.is_empty() || !state.monitor.is_available() {
+        return;
+    }
+
+    for alert in due {
+        let correlation_id = format!("alert-{}-{}", alert.id, now.timestamp());
+        if let Err(e) = crate::correlation::scope(correlation_id, check(bot, state, &alert)).await {
+            error!("Failed to check alert {}: {}", alert.id, e);
+        }
+    }
+}
+
+/// Выполняет вопрос оповещения, сохраняет результат проверки и сообщает в чат о срабатывании
+async fn check<S: MessageSender>(bot: &S, state: &BotState, alert: &Alert) -> ResponseResult<()> {
+    let zone = state.user_zone(&alert.user_id).await;
+    let period = zone.question_period(&alert.question);
+    let request = QueryRequest {
+        question: alert.question.clone(),
+        include_analysis: false,
+        use_cache: false,
+        include_sql: false,
+        user_id: Some(alert.user_id.clone()),
+        output_type: OutputType::Auto,
+        language: None,
+        date_from: period.map(|period| period.from),
+        date_to: period.map(|period| period.to),
+        timezone: Some(zone.name()),
+    };
+    let checked_at = Utc::now();
+
+    let value = match state.api(&alert.user_id).await.query(request).await {
+        Ok(response) => first_number(&response),
+        Err(e) => {
+            // Ошибку не сообщаем в чат: повторим в следующий интервал
+            warn!("Alert {} query failed: {}", alert.id, e);
+            if let Err(e) = state.storage.record_alert_check(alert.id, checked_at, alert.last_value, alert.triggered).await {
+                error!("Failed to record check of alert {}: {}", alert.id, e);
+            }
+            return Ok(());
+        }
+    };
+    let Some(value) = value else {
+        warn!("Alert {} answer has no numeric value", alert.id);
+        if let Err(e) = state.storage.record_alert_check(alert.id, checked_at, None, false).await {
+            error!("Failed to record check of alert {}: {}", alert.id, e);
+        }
+        return Ok(());
+    };
+
+    let triggered = alert.comparison.holds(value, alert.threshold);
+    if let Err(e) = state.storage.record_alert_check(alert.id, checked_at, Some(value), triggered).await {
+        error!("Failed to record check of alert {}: {}", alert.id, e);
+        return Ok(());
+    }
+    if !triggered || alert.triggered {
+        return Ok(());
+    }
+
+    info!("Alert {} triggered in chat {}: {} {}", alert.id, alert.chat_id, value, alert.condition());
+    bot.send_message(
+        ChatId(alert.chat_id),
+        format!(
+            "🚨 <b>Оповещение #{}</b>\n<i>{}</i>\n\nЗначение: <b>{}</b> (условие {})\n\nСписок оповещений — /alerts",
+            alert.id,
+            escape_html(&alert.question),
+            format_value(value),
+            escape_html(&alert.condition())
+        ),
+        Outgoing::html(),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::testing::FakeBackend;
+    use crate::messenger::testing::RecordingSender;
+    use serde_json::json;
+
+    fn answer(value: f64) -> QueryResponse {
+        serde_json::from_value(json!({
+            "question": "объем за час",
+            "data": [{"total_amount": value}],
+            "execution_time_ms": 5,
+            "row_count": 1,
+        }))
+        .unwrap()
+    }
+
+    /// Состояние с бэкендом, отвечающим `values` по очереди, и оповещением «объем за час > 100»
+    async fn watched(name: &str, values: &[f64]) -> (Arc<FakeBackend>, Arc<BotState>, u64) {
+        let backend = Arc::new(FakeBackend::new(values.iter().copied().map(answer)));
+        let state = Arc::new(BotState::with_backend(&format!("watcher_{}", name), backend.clone()).await);
+        let id = state.storage.add_alert(Alert {
+            id: 0,
+            chat_id: 42,
+            user_id: "42".to_string(),
+            question: "объем за час".to_string(),
+            comparison: Comparison::Above,
+            threshold: 100.0,
+            interval_mins: DEFAULT_INTERVAL_MINS,
+            created_at: Utc::now(),
+            last_checked: None,
+            last_value: None,
+            triggered: false,
+        })
+        .await
+        .unwrap();
+        (backend, state, id)
+    }
+
+    async fn stored(state: &BotState, id: u64) -> Alert {
+        state.storage.alerts().await.into_iter().find(|alert| alert.id == id).unwrap()
+    }
+
+    #[tokio::test]
+    async fn alert_fires_once_while_condition_holds() {
+        let (backend, state, id) = watched("trigger", &[150.0, 200.0, 50.0]).await;
+        let sender = RecordingSender::default();
+
+        check_due_alerts(&sender, &state).await;
+        let sent = sender.take();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].text.contains(&format!("Оповещение #{}", id)), "{}", sent[0].text);
+        assert!(sent[0].text.contains("150"), "{}", sent[0].text);
+        let alert = stored(&state, id).await;
+        assert_eq!((alert.last_value, alert.triggered), (Some(150.0), true));
+
+        // Значение все еще выше порога - повторно не сообщаем
+        check(&sender, &state, &alert).await.unwrap();
+        assert!(sender.take().is_empty());
+        // Значение вернулось - оповещение снова готово сработать
+        check(&sender, &state, &stored(&state, id).await).await.unwrap();
+        assert!(sender.take().is_empty());
+        assert!(!stored(&state, id).await.triggered);
+        assert_eq!(backend.questions().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn value_within_threshold_is_recorded_silently() {
+        let (backend, state, id) = watched("quiet", &[50.0]).await;
+        let sender = RecordingSender::default();
+
+        check_due_alerts(&sender, &state).await;
+        assert!(sender.take().is_empty());
+        let alert = stored(&state, id).await;
+        assert_eq!((alert.last_value, alert.triggered), (Some(50.0), false));
+        assert!(alert.last_checked.is_some());
+
+        // До следующего интервала вопрос не выполняется
+        check_due_alerts(&sender, &state).await;
+        assert_eq!(backend.questions(), ["объем за час"]);
+    }
+}